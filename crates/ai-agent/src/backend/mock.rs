@@ -0,0 +1,198 @@
+use super::LlmBackend;
+use crate::{Error, Result};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How the mock backend builds its answers.
+#[derive(Debug, Clone)]
+pub enum MockResponses {
+	/// Answer with the user message itself.
+	Echo,
+	/// Answer with the scripted responses, in order, starting over once exhausted.
+	Scripted(Vec<String>),
+}
+
+/// Offline backend with scripted answers, fixed latency and failure injection.
+#[derive(Debug)]
+pub struct MockBackend {
+	responses: MockResponses,
+	latency: Duration,
+	/// Fail every n-th call (1-based) when set.
+	fail_every: Option<usize>,
+	calls: AtomicUsize,
+}
+
+impl MockBackend {
+	pub fn echo() -> Self {
+		Self::new(MockResponses::Echo)
+	}
+
+	pub fn scripted(responses: impl IntoIterator<Item = impl Into<String>>) -> Self {
+		Self::new(MockResponses::Scripted(responses.into_iter().map(Into::into).collect()))
+	}
+
+	pub fn new(responses: MockResponses) -> Self {
+		Self { responses, latency: Duration::ZERO, fail_every: None, calls: AtomicUsize::new(0) }
+	}
+
+	pub fn with_latency(mut self, latency: Duration) -> Self {
+		self.latency = latency;
+		self
+	}
+
+	pub fn with_failure_every(mut self, n: usize) -> Self {
+		self.fail_every = (n > 0).then_some(n);
+		self
+	}
+
+	/// Build the backend from `MOCK_RESPONSES` (`|`-separated, echo when unset),
+	/// `MOCK_LATENCY_MS` and `MOCK_FAIL_EVERY`.
+	pub fn from_env() -> Result<Self> {
+		let mut backend = match std::env::var("MOCK_RESPONSES") {
+			Ok(responses) => Self::scripted(responses.split('|')),
+			Err(_) => Self::echo(),
+		};
+
+		if let Ok(latency_ms) = std::env::var("MOCK_LATENCY_MS") {
+			let latency_ms: u64 = latency_ms
+				.parse()
+				.map_err(|_| format!("Invalid MOCK_LATENCY_MS: {latency_ms}"))?;
+			backend = backend.with_latency(Duration::from_millis(latency_ms));
+		}
+
+		if let Ok(fail_every) = std::env::var("MOCK_FAIL_EVERY") {
+			let fail_every: usize = fail_every
+				.parse()
+				.map_err(|_| format!("Invalid MOCK_FAIL_EVERY: {fail_every}"))?;
+			backend = backend.with_failure_every(fail_every);
+		}
+
+		Ok(backend)
+	}
+
+	/// Number of calls made so far, failed ones included.
+	pub fn calls(&self) -> usize {
+		self.calls.load(Ordering::SeqCst)
+	}
+}
+
+#[async_trait]
+impl LlmBackend for MockBackend {
+	fn name(&self) -> &str {
+		"mock"
+	}
+
+	async fn complete(&self, message: &str) -> Result<String> {
+		let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+
+		if !self.latency.is_zero() {
+			tokio::time::sleep(self.latency).await;
+		}
+
+		if self.fail_every.is_some_and(|n| call.is_multiple_of(n)) {
+			return Err(Error::Custom(format!("Mock backend failure injected on call {call}")));
+		}
+
+		match &self.responses {
+			MockResponses::Echo => Ok(message.to_string()),
+			MockResponses::Scripted(responses) if responses.is_empty() => Ok(String::new()),
+			MockResponses::Scripted(responses) => {
+				Ok(responses[(call - 1) % responses.len()].clone())
+			},
+		}
+	}
+
+	/// Stream the answer word by word.
+	async fn complete_stream(&self, message: &str, tokens: mpsc::Sender<String>) -> Result<()> {
+		let answer = self.complete(message).await?;
+		for token in answer.split_inclusive(' ') {
+			tokens.send(token.to_string()).await.map_err(|_| "Token stream closed")?;
+		}
+		Ok(())
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::chat::{ContextMessage, Role};
+
+	#[tokio::test]
+	async fn test_mock_echo() -> Result<()> {
+		let backend = MockBackend::echo();
+
+		assert_eq!(backend.complete("hello").await?, "hello");
+		assert_eq!(backend.calls(), 1);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_mock_scripted_cycles() -> Result<()> {
+		let backend = MockBackend::scripted(["one", "two"]);
+
+		assert_eq!(backend.complete("a").await?, "one");
+		assert_eq!(backend.complete("b").await?, "two");
+		assert_eq!(backend.complete("c").await?, "one");
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_mock_failure_injection() -> Result<()> {
+		let backend = MockBackend::echo().with_failure_every(2);
+
+		assert!(backend.complete("a").await.is_ok());
+		assert!(backend.complete("b").await.is_err());
+		assert!(backend.complete("c").await.is_ok());
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_mock_stream_words() -> Result<()> {
+		let backend = MockBackend::echo();
+		let (sender, mut receiver) = mpsc::channel(8);
+
+		backend.complete_stream("hello streaming world", sender).await?;
+
+		let mut tokens = Vec::new();
+		while let Some(token) = receiver.recv().await {
+			tokens.push(token);
+		}
+		assert_eq!(tokens, ["hello ", "streaming ", "world"]);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_mock_answers_the_transcript_of_the_context() -> Result<()> {
+		let backend = MockBackend::echo();
+		let context = [
+			ContextMessage { role: Role::User, content: "Who are you?".to_string() },
+			ContextMessage { role: Role::Assistant, content: "An echo.".to_string() },
+		];
+
+		assert_eq!(
+			backend.complete_with_context(&context, "Really?").await?,
+			"user: Who are you?\nassistant: An echo.\nuser: Really?"
+		);
+		assert_eq!(backend.complete_with_context(&[], "Really?").await?, "Really?");
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_mock_latency() -> Result<()> {
+		let backend = MockBackend::echo().with_latency(Duration::from_millis(50));
+
+		let start = std::time::Instant::now();
+		backend.complete("a").await?;
+		assert!(start.elapsed() >= Duration::from_millis(50));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,87 @@
+// region:    --- Modules
+
+mod mock;
+mod openai;
+mod shadow;
+mod warm_pool;
+
+// -- Flatten
+pub use mock::*;
+pub use openai::*;
+pub use shadow::*;
+pub use warm_pool::*;
+
+use crate::{chat::ContextMessage, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+// endregion: --- Modules
+
+/// Environment variable selecting the backend used to answer agent requests.
+pub const BACKEND_ENV: &str = "AGENT_BACKEND";
+
+/// A language model able to answer a user message.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+	/// Short name of the backend, used in logs.
+	fn name(&self) -> &str;
+
+	/// Model answering the requests, e.g. `gpt-4o-mini`, reported in the provenance of the
+	/// responses.
+	fn model(&self) -> &str {
+		self.name()
+	}
+
+	/// Answer the given user message.
+	async fn complete(&self, message: &str) -> Result<String>;
+
+	/// Answer the given user message after the prior messages of the conversation, oldest first.
+	/// Backends without a chat history answer the whole transcript as a single message.
+	async fn complete_with_context(
+		&self,
+		context: &[ContextMessage],
+		message: &str,
+	) -> Result<String> {
+		if context.is_empty() {
+			return self.complete(message).await;
+		}
+
+		let mut transcript = String::new();
+		for prior in context {
+			transcript.push_str(&format!("{}: {}\n", prior.role, prior.content));
+		}
+		transcript.push_str(&format!("user: {message}"));
+		self.complete(&transcript).await
+	}
+
+	/// Answer the given user message token by token. Sending waits while the consumer is behind,
+	/// which pauses the generation, and fails once the consumer went away.
+	async fn complete_stream(&self, message: &str, tokens: mpsc::Sender<String>) -> Result<()> {
+		let answer = self.complete(message).await?;
+		tokens.send(answer).await.map_err(|_| "Token stream closed")?;
+		Ok(())
+	}
+}
+
+/// Build the backend selected by `AGENT_BACKEND`, a [`new_backend`] profile (`openai` by default).
+pub fn new_backend_from_env() -> Result<Arc<dyn LlmBackend>> {
+	let backend = std::env::var(BACKEND_ENV).unwrap_or_else(|_| "openai".to_string());
+
+	new_backend(&backend)
+}
+
+/// Build a backend from a profile: `mock`, `openai`, or `openai:<model>` to pick the model.
+pub fn new_backend(profile: &str) -> Result<Arc<dyn LlmBackend>> {
+	match profile.split_once(':') {
+		None if profile == "openai" => Ok(Arc::new(OpenAiBackend::new()?)),
+		None if profile == "mock" => Ok(Arc::new(MockBackend::from_env()?)),
+		Some(("openai", model)) if !model.is_empty() => {
+			Ok(Arc::new(OpenAiBackend::new()?.with_model(model)))
+		},
+		_ => Err(format!(
+			"Unknown backend '{profile}', expected 'openai', 'openai:<model>' or 'mock'."
+		)
+		.into()),
+	}
+}
@@ -0,0 +1,60 @@
+use super::LlmBackend;
+use crate::model::ModelManager;
+use crate::oa_client::{new_oa_client, OaClient};
+use crate::tools::{new_ai_tools, AiTools, ToolCacheStats};
+use crate::{chat::ContextMessage, conv, gpts, Result};
+use async_trait::async_trait;
+use rpc_router::resources_builder;
+
+/// Backend answering through the OpenAI chat API, with the agent tools enabled.
+#[derive(Clone)]
+pub struct OpenAiBackend {
+	oa_client: OaClient,
+	ai_tools: AiTools,
+	model: String,
+}
+
+impl OpenAiBackend {
+	pub fn new() -> Result<Self> {
+		let oa_client = new_oa_client()?;
+		let mm = ModelManager::default();
+		let ai_tools = new_ai_tools(Some(resources_builder![mm]))?;
+
+		Ok(Self { oa_client, ai_tools, model: gpts::MODEL.to_string() })
+	}
+
+	pub fn with_model(mut self, model: impl Into<String>) -> Self {
+		self.model = model.into();
+		self
+	}
+
+	/// Hits and misses of the cache of the tool results.
+	pub fn tool_cache_stats(&self) -> ToolCacheStats {
+		self.ai_tools.cache().stats()
+	}
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiBackend {
+	fn name(&self) -> &str {
+		"openai"
+	}
+
+	fn model(&self) -> &str {
+		&self.model
+	}
+
+	async fn complete(&self, message: &str) -> Result<String> {
+		conv::send_user_msg(self.oa_client.clone(), self.ai_tools.clone(), &self.model, message)
+			.await
+	}
+
+	async fn complete_with_context(
+		&self,
+		context: &[ContextMessage],
+		message: &str,
+	) -> Result<String> {
+		let (oa_client, ai_tools) = (self.oa_client.clone(), self.ai_tools.clone());
+		conv::send_conversation(oa_client, ai_tools, &self.model, context, message).await
+	}
+}
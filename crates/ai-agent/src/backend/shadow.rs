@@ -0,0 +1,190 @@
+use super::LlmBackend;
+use crate::{chat::ContextMessage, Result};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, oneshot};
+
+/// Answers with the live backend and mirrors a share of the requests to a shadow backend, e.g. a
+/// new prompt or model under evaluation. Shadow answers are only logged next to the live ones,
+/// they are never returned and never delay the live answer.
+pub struct ShadowBackend {
+	live: Arc<dyn LlmBackend>,
+	shadow: Arc<dyn LlmBackend>,
+	/// Share of the requests mirrored, in percent.
+	percent: u8,
+	requests: AtomicUsize,
+	mirrored: AtomicUsize,
+}
+
+impl ShadowBackend {
+	pub fn new(live: Arc<dyn LlmBackend>, shadow: Arc<dyn LlmBackend>, percent: u8) -> Self {
+		Self {
+			live,
+			shadow,
+			percent: percent.min(100),
+			requests: AtomicUsize::new(0),
+			mirrored: AtomicUsize::new(0),
+		}
+	}
+
+	/// Number of requests mirrored to the shadow backend so far.
+	pub fn mirrored(&self) -> usize {
+		self.mirrored.load(Ordering::SeqCst)
+	}
+
+	/// Spread the mirrored requests evenly: request `n` is mirrored when it crosses the next
+	/// `100 / percent` boundary.
+	fn should_mirror(&self, request: usize) -> bool {
+		let percent = usize::from(self.percent);
+		request * percent / 100 != (request - 1) * percent / 100
+	}
+
+	fn mirror(&self, request: usize, message: &str) -> oneshot::Sender<String> {
+		let (live_sender, live_receiver) = oneshot::channel::<String>();
+		let shadow = self.shadow.clone();
+		let message = message.to_string();
+		self.mirrored.fetch_add(1, Ordering::SeqCst);
+
+		tokio::spawn(async move {
+			let start = Instant::now();
+			let result = shadow.complete(&message).await;
+			let elapsed = start.elapsed();
+
+			match (result, live_receiver.await) {
+				(Ok(answer), Ok(live)) => {
+					tracing::info!(
+						"Shadow {} answered request {request} in {elapsed:?}, {} the live answer",
+						shadow.name(),
+						if answer == live { "matching" } else { "differing from" },
+					);
+					tracing::debug!("Shadow answer to request {request}: {answer}");
+				},
+				(Ok(_), Err(_)) => tracing::info!(
+					"Shadow {} answered request {request} in {elapsed:?}, the live backend failed",
+					shadow.name(),
+				),
+				(Err(e), _) => {
+					tracing::warn!("Shadow {} failed request {request}: {e}", shadow.name())
+				},
+			}
+		});
+
+		live_sender
+	}
+}
+
+#[async_trait]
+impl LlmBackend for ShadowBackend {
+	fn name(&self) -> &str {
+		self.live.name()
+	}
+
+	fn model(&self) -> &str {
+		self.live.model()
+	}
+
+	async fn complete(&self, message: &str) -> Result<String> {
+		let request = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+		let live_sender = self.should_mirror(request).then(|| self.mirror(request, message));
+
+		let result = self.live.complete(message).await;
+		if let (Some(live_sender), Ok(answer)) = (live_sender, &result) {
+			let _ = live_sender.send(answer.clone());
+		}
+
+		result
+	}
+
+	/// Conversations are answered by the live backend only, the shadow compares single messages.
+	async fn complete_with_context(
+		&self,
+		context: &[ContextMessage],
+		message: &str,
+	) -> Result<String> {
+		if context.is_empty() {
+			return self.complete(message).await;
+		}
+		self.live.complete_with_context(context, message).await
+	}
+
+	async fn complete_stream(&self, message: &str, tokens: mpsc::Sender<String>) -> Result<()> {
+		let request = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+		let live_sender = self.should_mirror(request).then(|| self.mirror(request, message));
+
+		// Relay the live tokens to keep the whole answer for the comparison.
+		let (relay, mut relayed) = mpsc::channel::<String>(1);
+		let forward = async move {
+			let mut answer = String::new();
+			while let Some(token) = relayed.recv().await {
+				answer.push_str(&token);
+				tokens.send(token).await.map_err(|_| "Token stream closed")?;
+			}
+			Ok::<_, crate::Error>(answer)
+		};
+		let (result, answer) = tokio::join!(self.live.complete_stream(message, relay), forward);
+		result?;
+		let answer = answer?;
+
+		if let Some(live_sender) = live_sender {
+			let _ = live_sender.send(answer);
+		}
+		Ok(())
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::backend::MockBackend;
+	use std::time::Duration;
+
+	#[tokio::test]
+	async fn test_shadow_mirrors_share_of_requests() -> Result<()> {
+		let shadow = Arc::new(MockBackend::scripted(["shadow"]));
+		let backend = ShadowBackend::new(Arc::new(MockBackend::echo()), shadow.clone(), 25);
+
+		for i in 0..8 {
+			assert_eq!(backend.complete(&format!("request {i}")).await?, format!("request {i}"));
+		}
+		tokio::time::sleep(Duration::from_millis(10)).await;
+
+		assert_eq!(backend.mirrored(), 2);
+		assert_eq!(shadow.calls(), 2);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_shadow_failures_never_reach_the_requester() -> Result<()> {
+		let shadow = Arc::new(MockBackend::echo().with_failure_every(1));
+		let backend = ShadowBackend::new(Arc::new(MockBackend::scripted(["live"])), shadow, 100);
+
+		assert_eq!(backend.complete("a").await?, "live");
+		assert_eq!(backend.complete("b").await?, "live");
+		assert_eq!(backend.mirrored(), 2);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_shadow_relays_live_stream() -> Result<()> {
+		let shadow = Arc::new(MockBackend::scripted(["shadow"]));
+		let backend = ShadowBackend::new(Arc::new(MockBackend::echo()), shadow, 100);
+		let (sender, mut receiver) = mpsc::channel(8);
+
+		backend.complete_stream("live answer", sender).await?;
+
+		assert_eq!(receiver.recv().await.as_deref(), Some("live "));
+		assert_eq!(receiver.recv().await.as_deref(), Some("answer"));
+		assert_eq!(receiver.recv().await, None);
+		assert_eq!(backend.mirrored(), 1);
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,112 @@
+use super::LlmBackend;
+use crate::Result;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Builds the backend answering the requests of an agent.
+pub type BackendFactory = Box<dyn Fn(&str) -> Result<Arc<dyn LlmBackend>> + Send + Sync>;
+
+/// Whether the backend of an agent was still resident when it was requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolHit {
+	Warm,
+	Cold,
+}
+
+/// Keeps the backends of the most recently requested agents resident, along with their sessions
+/// and model contexts, so their first tokens do not wait for a cold start. Once `capacity` agents
+/// are warm, the least recently requested one is evicted.
+pub struct WarmPool {
+	factory: BackendFactory,
+	capacity: usize,
+	/// Least recently requested first.
+	backends: Mutex<VecDeque<(String, Arc<dyn LlmBackend>)>>,
+	warm_hits: AtomicU64,
+	cold_hits: AtomicU64,
+}
+
+impl WarmPool {
+	/// Create a pool keeping at most `capacity` agents warm, `0` building a backend per request.
+	pub fn new(capacity: usize, factory: BackendFactory) -> Self {
+		Self {
+			factory,
+			capacity,
+			backends: Mutex::new(VecDeque::with_capacity(capacity)),
+			warm_hits: AtomicU64::new(0),
+			cold_hits: AtomicU64::new(0),
+		}
+	}
+
+	/// The backend of the given agent, built when it is not warm.
+	pub fn get(&self, agent_name: &str) -> Result<(Arc<dyn LlmBackend>, PoolHit)> {
+		let mut backends = self.backends.lock().expect("Warm pool not to be poisoned.");
+
+		if let Some(index) = backends.iter().position(|(name, _)| name == agent_name) {
+			let entry = backends.remove(index).expect("Index to be in the pool.");
+			let backend = entry.1.clone();
+			backends.push_back(entry);
+			self.warm_hits.fetch_add(1, Ordering::SeqCst);
+			return Ok((backend, PoolHit::Warm));
+		}
+
+		let backend = (self.factory)(agent_name)?;
+		self.cold_hits.fetch_add(1, Ordering::SeqCst);
+		backends.push_back((agent_name.to_string(), backend.clone()));
+		while backends.len() > self.capacity {
+			if let Some((evicted, _)) = backends.pop_front() {
+				tracing::debug!("Evicted {evicted} from the warm pool");
+			}
+		}
+
+		Ok((backend, PoolHit::Cold))
+	}
+
+	/// Number of requests served by a resident backend so far.
+	pub fn warm_hits(&self) -> u64 {
+		self.warm_hits.load(Ordering::SeqCst)
+	}
+
+	/// Number of requests that had to build their backend so far.
+	pub fn cold_hits(&self) -> u64 {
+		self.cold_hits.load(Ordering::SeqCst)
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::backend::MockBackend;
+	use std::sync::atomic::AtomicUsize;
+
+	#[test]
+	fn test_warm_pool_evicts_least_recently_requested() -> Result<()> {
+		let built = Arc::new(AtomicUsize::new(0));
+		let counter = built.clone();
+		let pool = WarmPool::new(
+			2,
+			Box::new(move |_| {
+				counter.fetch_add(1, Ordering::SeqCst);
+				Ok(Arc::new(MockBackend::echo()))
+			}),
+		);
+
+		let hits = ["a", "b", "a", "c", "a", "b"]
+			.iter()
+			.map(|name| pool.get(name).map(|(_, hit)| hit))
+			.collect::<crate::Result<Vec<_>>>()?;
+
+		use PoolHit::*;
+		assert_eq!(hits, [Cold, Cold, Warm, Cold, Warm, Cold]);
+		assert_eq!(built.load(Ordering::SeqCst), 4);
+		assert_eq!((pool.warm_hits(), pool.cold_hits()), (2, 4));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -9,6 +9,29 @@ use schemars::JsonSchema;
 use serde_json::Value;
 use std::fmt::Display;
 
+/// Author of a prior message of a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+	User,
+	Assistant,
+}
+
+impl Display for Role {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Role::User => write!(f, "user"),
+			Role::Assistant => write!(f, "assistant"),
+		}
+	}
+}
+
+/// A prior message of a conversation, for the model to answer in context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextMessage {
+	pub role: Role,
+	pub content: String,
+}
+
 pub fn user_msg(content: impl Into<String>) -> Result<ChatCompletionRequestMessage> {
 	let msg = ChatCompletionRequestUserMessageArgs::default()
 		.content(content.into())
@@ -16,6 +39,20 @@ pub fn user_msg(content: impl Into<String>) -> Result<ChatCompletionRequestMessa
 	Ok(msg.into())
 }
 
+pub fn assistant_msg(content: impl Into<String>) -> Result<ChatCompletionRequestMessage> {
+	let msg = ChatCompletionRequestAssistantMessageArgs::default()
+		.content(content.into())
+		.build()?;
+	Ok(msg.into())
+}
+
+pub fn context_msg(message: &ContextMessage) -> Result<ChatCompletionRequestMessage> {
+	match message.role {
+		Role::User => user_msg(message.content.clone()),
+		Role::Assistant => assistant_msg(message.content.clone()),
+	}
+}
+
 pub fn tool_response_msg(
 	tool_call_id: String,
 	content: impl Display,
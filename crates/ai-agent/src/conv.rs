@@ -1,7 +1,7 @@
+use crate::chat::{self, ContextMessage};
 use crate::error::Error;
 use crate::oa_client::OaClient;
 use crate::tools::AiTools;
-use crate::{chat, gpts};
 use async_openai::types::{ChatCompletionToolChoiceOption, CreateChatCompletionRequest};
 use serde_json::Value;
 use tokio::task::JoinSet;
@@ -9,13 +9,25 @@ use tokio::task::JoinSet;
 pub async fn send_user_msg(
 	oa_client: OaClient,
 	ai_tools: AiTools,
+	model: &str,
+	question: &str,
+) -> Result<String, Error> {
+	send_conversation(oa_client, ai_tools, model, &[], question).await
+}
+
+/// Send the question after the prior messages of the conversation, oldest first.
+pub async fn send_conversation(
+	oa_client: OaClient,
+	ai_tools: AiTools,
+	model: &str,
+	context: &[ContextMessage],
 	question: &str,
 ) -> Result<String, Error> {
 	let chat_client = oa_client.chat();
-	let model = gpts::MODEL;
 
 	// -- Build messages
-	let messages = vec![chat::user_msg(question)?];
+	let mut messages = context.iter().map(chat::context_msg).collect::<Result<Vec<_>, _>>()?;
+	messages.push(chat::user_msg(question)?);
 
 	// -- Extract tools and rpc_router
 	let rpc_router = ai_tools.router().clone();
@@ -44,22 +56,31 @@ pub async fn send_user_msg(
 		response: Value,
 	}
 	let mut tool_responses: Vec<ToolResponse> = Vec::new();
-	let mut join_set: JoinSet<(String, Result<rpc_router::CallResponse, rpc_router::CallError>)> =
-		JoinSet::new();
+	let mut join_set: JoinSet<(String, Result<Value, rpc_router::CallError>)> = JoinSet::new();
 
-	// For each tool_call, rpc_router call
+	// For each tool_call, rpc_router call, unless its result is cached
 	let tool_calls = first_choice.message.tool_calls;
 	for tool_call in tool_calls.iter().flatten() {
 		let tool_call_id = tool_call.id.clone();
 		let fn_name = tool_call.function.name.clone();
 		let params: Value = serde_json::from_str(&tool_call.function.arguments)?;
 		let rpc_router = rpc_router.clone();
+		let ai_tools = ai_tools.clone();
 
 		join_set.spawn(async move {
-			let call_result: Result<rpc_router::CallResponse, rpc_router::CallError> =
-				rpc_router.call_route(None, fn_name, Some(params)).await;
-
-			(tool_call_id, call_result)
+			let cache = ai_tools.cache();
+			cache.called(&fn_name);
+			if let Some(value) = cache.get(&fn_name, &params) {
+				return (tool_call_id, Ok(value));
+			}
+
+			let call_result =
+				rpc_router.call_route(None, fn_name.clone(), Some(params.clone())).await;
+			if let Ok(response) = &call_result {
+				cache.insert(&fn_name, &params, response.value.clone());
+			}
+
+			(tool_call_id, call_result.map(|response| response.value))
 		});
 
 		// Execute with rpc_router
@@ -75,7 +96,7 @@ pub async fn send_user_msg(
 		let (tool_call_id, response_res) = join_result.map_err(|e| format!("Join error: {}", e))?;
 
 		let response = match response_res {
-			Ok(response) => response.value,
+			Ok(value) => value,
 			Err(rpc_router::CallError { error, id: _, method: _ }) => {
 				return Err(format!("RPC Error: {}", error).into())
 			},
@@ -84,9 +105,6 @@ pub async fn send_user_msg(
 		tool_responses.push(ToolResponse { tool_call_id, response });
 	}
 
-	// -- Make messages mutable for follow-up
-	let mut messages = messages;
-
 	// -- Append the tool calls (send from AI Model)
 	if let Some(tool_calls) = tool_calls {
 		messages.push(chat::tool_calls_msg(tool_calls)?);
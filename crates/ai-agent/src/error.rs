@@ -9,13 +9,13 @@ pub enum Error {
 
 	// -- Externals
 	#[from]
-	OpenAi(async_openai::error::OpenAIError),
+	OpenAi(Box<async_openai::error::OpenAIError>),
 
 	#[from]
 	Json(serde_json::Error),
 
 	#[from]
-	RpcCall(rpc_router::CallError),
+	RpcCall(Box<rpc_router::CallError>),
 }
 
 // region:    --- Froms
@@ -26,6 +26,18 @@ impl From<&str> for Error {
 	}
 }
 
+impl From<async_openai::error::OpenAIError> for Error {
+	fn from(val: async_openai::error::OpenAIError) -> Self {
+		Self::OpenAi(Box::new(val))
+	}
+}
+
+impl From<rpc_router::CallError> for Error {
+	fn from(val: rpc_router::CallError) -> Self {
+		Self::RpcCall(Box::new(val))
+	}
+}
+
 // endregion: --- Froms
 
 // region:    --- Error Boilerplate
@@ -2,6 +2,7 @@ mod error;
 
 pub use error::{Error, Result};
 
+pub mod backend;
 pub mod chat;
 pub mod conv;
 pub mod gpts;
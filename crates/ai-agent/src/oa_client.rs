@@ -5,6 +5,13 @@ use std::sync::Arc;
 
 pub type OaClient = Arc<Client<OpenAIConfig>>;
 
+/// Environment variable overriding the base URL of the OpenAI API, e.g. for a compatible server.
+pub const OPENAI_BASE_URL_ENV: &str = "OPENAI_BASE_URL";
+
 pub fn new_oa_client() -> Result<OaClient> {
-	Ok(Client::new().into())
+	let config = match std::env::var(OPENAI_BASE_URL_ENV) {
+		Ok(base_url) => OpenAIConfig::new().with_api_base(base_url),
+		Err(_) => OpenAIConfig::new(),
+	};
+	Ok(Client::with_config(config).into())
 }
@@ -1,3 +1,4 @@
+use super::ToolCache;
 use async_openai::types::ChatCompletionTool;
 use rpc_router::Router;
 use std::sync::Arc;
@@ -6,11 +7,17 @@ use std::sync::Arc;
 pub struct AiTools {
 	router: Router,
 	chat_tools: Arc<Vec<ChatCompletionTool>>,
+	cache: Arc<ToolCache>,
 }
 
 impl AiTools {
 	pub fn new(router: Router, chat_tools: Vec<ChatCompletionTool>) -> Self {
-		AiTools { router, chat_tools: Arc::new(chat_tools) }
+		AiTools { router, chat_tools: Arc::new(chat_tools), cache: Default::default() }
+	}
+
+	pub fn with_cache(mut self, cache: ToolCache) -> Self {
+		self.cache = Arc::new(cache);
+		self
 	}
 }
 
@@ -19,6 +26,11 @@ impl AiTools {
 		&self.router
 	}
 
+	/// Results of the deterministic tools, shared by the clones of the tools.
+	pub fn cache(&self) -> &ToolCache {
+		&self.cache
+	}
+
 	pub fn chat_tools_clone(&self) -> Vec<ChatCompletionTool> {
 		self.chat_tools.as_ref().clone()
 	}
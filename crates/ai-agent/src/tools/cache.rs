@@ -0,0 +1,166 @@
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Hits and misses of the tool cache since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolCacheStats {
+	pub hits: u64,
+	pub misses: u64,
+	/// Results currently cached, expired ones included until they are looked up.
+	pub entries: usize,
+}
+
+#[derive(Debug)]
+struct CachedResult {
+	value: Value,
+	expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+	results: HashMap<(String, u64), CachedResult>,
+	stats: ToolCacheStats,
+}
+
+/// Results of the deterministic tools, keyed by tool name and arguments. Only the tools given a
+/// TTL are cached, and their results are dropped once another tool they depend on is called.
+#[derive(Debug, Default)]
+pub struct ToolCache {
+	ttls: HashMap<String, Duration>,
+	/// Tools whose results are invalidated by each tool call.
+	hooks: HashMap<String, HashSet<String>>,
+	state: Mutex<CacheState>,
+}
+
+impl ToolCache {
+	/// Cache the results of the given tool for `ttl`.
+	pub fn with_ttl(mut self, tool: impl Into<String>, ttl: Duration) -> Self {
+		self.ttls.insert(tool.into(), ttl);
+		self
+	}
+
+	/// Drop the cached results of `tool` whenever `trigger` is called, e.g. a write of what `tool`
+	/// reads.
+	pub fn invalidated_by(mut self, tool: impl Into<String>, trigger: impl Into<String>) -> Self {
+		self.hooks.entry(trigger.into()).or_default().insert(tool.into());
+		self
+	}
+
+	/// The cached result of the call, if still fresh.
+	pub fn get(&self, tool: &str, params: &Value) -> Option<Value> {
+		if !self.ttls.contains_key(tool) {
+			return None;
+		}
+
+		let mut state = self.state.lock().expect("Tool cache not to be poisoned.");
+		let key = (tool.to_string(), params_hash(params));
+		let value = match state.results.get(&key) {
+			Some(result) if result.expires_at > Instant::now() => Some(result.value.clone()),
+			Some(_) => {
+				state.results.remove(&key);
+				None
+			},
+			None => None,
+		};
+		match value {
+			Some(_) => state.stats.hits += 1,
+			None => state.stats.misses += 1,
+		}
+		value
+	}
+
+	/// Keep the result of the call, if its tool is cached.
+	pub fn insert(&self, tool: &str, params: &Value, value: Value) {
+		let Some(ttl) = self.ttls.get(tool) else {
+			return;
+		};
+
+		let mut state = self.state.lock().expect("Tool cache not to be poisoned.");
+		let result = CachedResult { value, expires_at: Instant::now() + *ttl };
+		state.results.insert((tool.to_string(), params_hash(params)), result);
+	}
+
+	/// Run the invalidation hooks of the given tool, before calling it.
+	pub fn called(&self, tool: &str) {
+		for invalidated in self.hooks.get(tool).into_iter().flatten() {
+			self.invalidate(invalidated);
+		}
+	}
+
+	/// Drop the cached results of the given tool.
+	pub fn invalidate(&self, tool: &str) {
+		let mut state = self.state.lock().expect("Tool cache not to be poisoned.");
+		state.results.retain(|(cached, _), _| cached != tool);
+	}
+
+	pub fn invalidate_all(&self) {
+		self.state.lock().expect("Tool cache not to be poisoned.").results.clear();
+	}
+
+	pub fn stats(&self) -> ToolCacheStats {
+		let state = self.state.lock().expect("Tool cache not to be poisoned.");
+		ToolCacheStats { entries: state.results.len(), ..state.stats }
+	}
+}
+
+/// Hash of the arguments of a call. Objects keep their keys sorted, so the order the model wrote
+/// them in does not matter.
+fn params_hash(params: &Value) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	params.to_string().hash(&mut hasher);
+	hasher.finish()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn test_cache_hits_until_expired() {
+		let cache = ToolCache::default()
+			.with_ttl("get_weather", Duration::from_secs(60))
+			.with_ttl("get_time", Duration::ZERO);
+		let params = json!({"location": "Paris", "country": "France"});
+		let reordered = json!({"country": "France", "location": "Paris"});
+
+		assert_eq!(cache.get("get_weather", &params), None);
+		cache.insert("get_weather", &params, json!(30));
+		cache.insert("get_time", &params, json!("noon"));
+		cache.insert("send_email", &params, json!("sent"));
+
+		assert_eq!(cache.get("get_weather", &reordered), Some(json!(30)));
+		assert_eq!(cache.get("get_weather", &json!({"location": "Rome"})), None);
+		assert_eq!(cache.get("get_time", &params), None);
+		// Tools without a TTL are never cached.
+		assert_eq!(cache.get("send_email", &params), None);
+		assert_eq!(cache.stats(), ToolCacheStats { hits: 1, misses: 3, entries: 1 });
+	}
+
+	#[test]
+	fn test_cache_invalidation() {
+		let cache = ToolCache::default()
+			.with_ttl("read_file", Duration::from_secs(60))
+			.invalidated_by("read_file", "write_file");
+		let params = json!({"path": "notes.txt"});
+
+		cache.insert("read_file", &params, json!("v1"));
+		cache.called("read_file");
+		assert_eq!(cache.get("read_file", &params), Some(json!("v1")));
+
+		cache.called("write_file");
+		assert_eq!(cache.get("read_file", &params), None);
+
+		cache.insert("read_file", &params, json!("v2"));
+		cache.invalidate_all();
+		assert_eq!(cache.stats().entries, 0);
+	}
+}
+
+// endregion: --- Tests
@@ -1,11 +1,13 @@
 // region:    --- Modules
 
 mod ai_tools;
+mod cache;
 mod spec;
 mod weather;
 
 // -- Flatten
 pub use ai_tools::*;
+pub use cache::*;
 pub use spec::*;
 
 use crate::Result;
@@ -22,5 +24,7 @@ pub fn new_ai_tools(resources: Option<ResourcesBuilder>) -> Result<AiTools> {
 	let mut chat_tools = Vec::new();
 	chat_tools.extend(weather::chat_tools()?);
 
-	Ok(AiTools::new(router, chat_tools))
+	let cache = ToolCache::default().with_ttl("get_weather", weather::CACHE_TTL);
+
+	Ok(AiTools::new(router, chat_tools).with_cache(cache))
 }
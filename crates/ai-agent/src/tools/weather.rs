@@ -3,6 +3,10 @@ use crate::model::ModelManager;
 use async_openai::types::ChatCompletionTool;
 use rpc_router::{router_builder, RouterBuilder, RpcParams};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How long a weather lookup is reused for the same location.
+pub(super) const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
 
 pub(super) fn router_builder() -> RouterBuilder {
 	router_builder![get_weather]
@@ -0,0 +1,163 @@
+use libp2p::{identity, PeerId};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+	signed::{SignatureError, SignedPayload},
+	types::{deserialize_message, serialize_message, ProtocolError, TaskType},
+};
+
+const SIGNING_DOMAIN: &[u8] = b"dasn-agent-card:";
+
+/// What an agent offers to the network. Each provider signs its card and stores it in the DHT under
+/// its own [`provider_key`], so requesters can check the provider announced the agent before
+/// calling it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentCard {
+	pub name: String,
+	pub version: String,
+	pub capabilities: Vec<String>,
+	/// Price of a single request.
+	pub pricing: f64,
+	pub task_types: Vec<TaskType>,
+	/// Requests with a longer message are only accepted from requesters that answered a
+	/// challenge of the provider, `Some(0)` challenging every request.
+	#[serde(default)]
+	pub challenge_above: Option<usize>,
+	/// x25519 key of the provider the requests may be encrypted to, set by the event loop when
+	/// publishing the card with end-to-end encryption enabled.
+	#[serde(default)]
+	pub encryption_key: Option<[u8; 32]>,
+	/// Languages the agent answers in, e.g. `en`. Any language when empty.
+	#[serde(default)]
+	pub languages: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum AgentCardError {
+	#[error("Invalid agent card: {0}")]
+	Signature(#[from] SignatureError),
+	#[error("Malformed agent card: {0}")]
+	Protocol(#[from] ProtocolError),
+	#[error("Agent card of {found} stored under the key of {expected}")]
+	NameMismatch { expected: String, found: String },
+	#[error("Agent card signed by {0}, not by its provider")]
+	SignerMismatch(PeerId),
+}
+
+impl AgentCard {
+	pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+		Self {
+			name: name.into(),
+			version: version.into(),
+			capabilities: Vec::new(),
+			pricing: 0.0,
+			task_types: Vec::new(),
+			challenge_above: None,
+			encryption_key: None,
+			languages: Vec::new(),
+		}
+	}
+
+	/// Whether the requester must answer a challenge before sending a message of `message_len`
+	/// bytes, its context included.
+	pub fn requires_challenge(&self, message_len: usize) -> bool {
+		self.challenge_above.is_some_and(|limit| message_len > limit)
+	}
+
+	/// Whether the agent advertises answering in `language`.
+	pub fn speaks(&self, language: &str) -> bool {
+		self.languages.iter().any(|spoken| spoken.eq_ignore_ascii_case(language))
+	}
+
+	pub fn sign(&self, key: &identity::Keypair) -> Result<SignedPayload, SignatureError> {
+		SignedPayload::sign(key, SIGNING_DOMAIN, self)
+	}
+
+	/// Sign the card and encode it as the value of its DHT record.
+	pub fn encode_signed(&self, key: &identity::Keypair) -> Result<Vec<u8>, AgentCardError> {
+		Ok(serialize_message(&self.sign(key)?)?)
+	}
+
+	/// Decode and verify the card stored under the [`provider_key`] of `name` and `provider`.
+	pub fn verify(name: &str, provider: &PeerId, data: &[u8]) -> Result<AgentCard, AgentCardError> {
+		let signed: SignedPayload = deserialize_message(data)?;
+		let (signer, card): (PeerId, AgentCard) = signed.verify(SIGNING_DOMAIN)?;
+		if signer != *provider {
+			return Err(AgentCardError::SignerMismatch(signer));
+		}
+		if card.name != name {
+			return Err(AgentCardError::NameMismatch {
+				expected: name.to_string(),
+				found: card.name,
+			});
+		}
+
+		Ok(card)
+	}
+}
+
+/// DHT key the providers of an agent are stored under.
+pub fn agent_key(name: &str) -> String {
+	format!("/asn/agent/{name}")
+}
+
+/// DHT key the card of an agent is stored under by one of its providers.
+pub fn provider_key(name: &str, provider: &PeerId) -> String {
+	format!("{}/{provider}", agent_key(name))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	fn card() -> AgentCard {
+		AgentCard {
+			capabilities: vec!["summarize".to_string()],
+			pricing: 0.5,
+			task_types: vec![TaskType::WebResearch],
+			..AgentCard::new("researcher", "1.2.0")
+		}
+	}
+
+	#[test]
+	fn test_agent_card_round_trip() -> Result<()> {
+		let key = identity::Keypair::generate_ed25519();
+		let data = card().encode_signed(&key)?;
+
+		let verified = AgentCard::verify("researcher", &key.public().to_peer_id(), &data)?;
+
+		assert_eq!(verified, card());
+		Ok(())
+	}
+
+	#[test]
+	fn test_agent_card_signed_by_another_peer() -> Result<()> {
+		let data = card().encode_signed(&identity::Keypair::generate_ed25519())?;
+
+		assert!(matches!(
+			AgentCard::verify("researcher", &PeerId::random(), &data),
+			Err(AgentCardError::SignerMismatch(_))
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn test_agent_card_under_another_name() -> Result<()> {
+		let key = identity::Keypair::generate_ed25519();
+		let data = card().encode_signed(&key)?;
+
+		assert!(matches!(
+			AgentCard::verify("echo", &key.public().to_peer_id(), &data),
+			Err(AgentCardError::NameMismatch { .. })
+		));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
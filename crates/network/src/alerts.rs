@@ -0,0 +1,285 @@
+use std::{
+	collections::VecDeque,
+	error::Error,
+	path::Path,
+	sync::Mutex,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+use tokio::time::Instant;
+
+use crate::{events::EventObserver, types::Event};
+
+/// How often the rules watching metrics and missing events are evaluated.
+pub(crate) const EVALUATION_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a webhook has to accept an alert, for an unresponsive one not to pile up requests.
+pub(crate) const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A rule raising an alert when its condition holds, at most once per `cooldown`.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertRule {
+	pub name: String,
+	pub condition: AlertCondition,
+	#[serde(default = "default_actions")]
+	pub actions: Vec<AlertAction>,
+	#[serde_as(as = "DurationSeconds<u64>")]
+	#[serde(default = "default_cooldown")]
+	pub cooldown: Duration,
+}
+
+/// Events are named after their [`Event`] variant, in snake case, e.g. `peer_disconnected`.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+	/// A metric of the node above a threshold.
+	Threshold { metric: AlertMetric, above: i64 },
+	/// No event of the kind for `within`, such as the heartbeat of a peer.
+	Absence {
+		event: String,
+		#[serde_as(as = "DurationSeconds<u64>")]
+		within: Duration,
+	},
+	/// `count` events of the kind within `within`, such as repeated connection failures.
+	Repeated {
+		event: String,
+		count: usize,
+		#[serde_as(as = "DurationSeconds<u64>")]
+		within: Duration,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+	ConnectedPeers,
+	DroppedEvents,
+	RejectedCommands,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertAction {
+	/// Log the alert as a warning.
+	Log,
+	/// POST the alert as JSON.
+	Webhook { url: String },
+	/// Publish the alert as JSON on a gossip topic.
+	Gossip { topic: String },
+}
+
+/// An alert raised by a rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+	pub rule: String,
+	pub message: String,
+	/// Unix time the alert was raised at, in seconds.
+	pub raised_at: u64,
+}
+
+fn default_actions() -> Vec<AlertAction> {
+	vec![AlertAction::Log]
+}
+
+fn default_cooldown() -> Duration {
+	Duration::from_secs(5 * 60)
+}
+
+/// Load the alert rules from a YAML list of rules.
+pub fn load_alert_rules(path: &Path) -> Result<Vec<AlertRule>, Box<dyn Error>> {
+	Ok(serde_yaml::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+#[derive(Debug)]
+struct RuleState {
+	rule: AlertRule,
+	/// Times of the recent events of the rule, for repeated events.
+	seen: VecDeque<Instant>,
+	last_seen: Instant,
+	last_raised: Option<Instant>,
+}
+
+impl RuleState {
+	fn raise(&mut self, message: String, now: Instant) -> Option<(Alert, Vec<AlertAction>)> {
+		if self.last_raised.is_some_and(|raised| now < raised + self.rule.cooldown) {
+			return None;
+		}
+
+		self.last_raised = Some(now);
+		let raised_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		let alert = Alert { rule: self.rule.name.clone(), message, raised_at };
+		Some((alert, self.rule.actions.clone()))
+	}
+}
+
+#[derive(Debug, Default)]
+struct State {
+	rules: Vec<RuleState>,
+	/// Alerts raised by the events, waiting for their actions to run.
+	raised: Vec<(Alert, Vec<AlertAction>)>,
+}
+
+/// Evaluates the alert rules, observing the events sent to the application.
+#[derive(Debug, Default)]
+pub struct AlertEngine {
+	state: Mutex<State>,
+}
+
+impl AlertEngine {
+	pub fn new(rules: Vec<AlertRule>) -> Self {
+		let now = Instant::now();
+		let rules = rules
+			.into_iter()
+			.map(|rule| RuleState {
+				rule,
+				seen: VecDeque::new(),
+				last_seen: now,
+				last_raised: None,
+			})
+			.collect();
+		Self { state: Mutex::new(State { rules, raised: Vec::new() }) }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.state.lock().expect("Alert state not to be poisoned.").rules.is_empty()
+	}
+
+	fn observe_at(&self, kind: &str, now: Instant) {
+		let mut state = self.state.lock().expect("Alert state not to be poisoned.");
+		let State { rules, raised } = &mut *state;
+		for rule in rules.iter_mut() {
+			match &rule.rule.condition {
+				AlertCondition::Absence { event, .. } if event == kind => rule.last_seen = now,
+				AlertCondition::Repeated { event, count, within } if event == kind => {
+					let (count, within) = (*count, *within);
+					rule.seen.push_back(now);
+					while rule.seen.front().is_some_and(|seen| now > *seen + within) {
+						rule.seen.pop_front();
+					}
+					if rule.seen.len() >= count {
+						let message = format!("{count} {kind} events within {within:?}");
+						raised.extend(rule.raise(message, now));
+					}
+				},
+				_ => {},
+			}
+		}
+	}
+
+	/// The alerts raised since the last evaluation, with the ones of the metrics and of the
+	/// missing events.
+	pub(crate) fn evaluate(
+		&self,
+		metric: impl Fn(AlertMetric) -> i64,
+		now: Instant,
+	) -> Vec<(Alert, Vec<AlertAction>)> {
+		let mut state = self.state.lock().expect("Alert state not to be poisoned.");
+		let mut raised = std::mem::take(&mut state.raised);
+		for rule in state.rules.iter_mut() {
+			let message = match &rule.rule.condition {
+				AlertCondition::Threshold { metric: name, above } => {
+					let value = metric(*name);
+					(value > *above).then(|| format!("{name:?} is {value}, above {above}"))
+				},
+				AlertCondition::Absence { event, within } => (now > rule.last_seen + *within)
+					.then(|| format!("No {event} event for {:?}", now - rule.last_seen)),
+				AlertCondition::Repeated { .. } => None,
+			};
+			if let Some(message) = message {
+				raised.extend(rule.raise(message, now));
+			}
+		}
+		raised
+	}
+}
+
+impl EventObserver for AlertEngine {
+	fn observe(&self, event: &Event) {
+		self.observe_at(event_kind(event), Instant::now());
+	}
+}
+
+/// Name of the kind of an event in the alert rules.
+pub fn event_kind(event: &Event) -> &'static str {
+	match event {
+		Event::LLMInboundRequest { .. } => "llm_inbound_request",
+		Event::ArtifactRequest { .. } => "artifact_request",
+		Event::LLMStreamRequest { .. } => "llm_stream_request",
+		Event::InboundTaskProposal { .. } => "inbound_task_proposal",
+		Event::InboundBid { .. } => "inbound_bid",
+		Event::Auction { .. } => "auction",
+		Event::TaskAwarded { .. } => "task_awarded",
+		Event::TaskStatusChanged { .. } => "task_status_changed",
+		Event::TaskResult { .. } => "task_result",
+		Event::Feedback { .. } => "feedback",
+		Event::Bulletin { .. } => "bulletin",
+		Event::GossipMessage { .. } => "gossip_message",
+		Event::NatStatusChanged { .. } => "nat_status_changed",
+		Event::ConnectionLimitReached { .. } => "connection_limit_reached",
+		Event::PeerConnected { .. } => "peer_connected",
+		Event::PeerDisconnected { .. } => "peer_disconnected",
+		Event::ConnectionEstablished { .. } => "connection_established",
+		Event::ConnectionClosed { .. } => "connection_closed",
+		Event::PeerDiscovered { .. } => "peer_discovered",
+		Event::PeerExpired { .. } => "peer_expired",
+		Event::Reconnect { .. } => "reconnect",
+		Event::NetworkResumed { .. } => "network_resumed",
+		Event::ListenAddressChanged { .. } => "listen_address_changed",
+		Event::Error { .. } => "error",
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_alert_rules() -> Result<()> {
+		let rules: Vec<AlertRule> = serde_yaml::from_str(
+			"
+- name: flapping
+  condition: { type: repeated, event: peer_disconnected, count: 2, within: 60 }
+  cooldown: 600
+- name: heartbeat
+  condition: { type: absence, event: inbound_task_proposal, within: 30 }
+  actions: [{ type: gossip, topic: alerts }]
+- name: isolated
+  condition: { type: threshold, metric: dropped_events, above: 10 }
+",
+		)?;
+		assert_eq!(rules[0].actions, [AlertAction::Log]);
+		let engine = AlertEngine::new(rules);
+		let start = Instant::now();
+		let metric = |metric| if metric == AlertMetric::DroppedEvents { 11 } else { 0 };
+
+		engine.observe_at("peer_disconnected", start);
+		engine.observe_at("peer_connected", start);
+		engine.observe_at("inbound_task_proposal", start + Duration::from_secs(20));
+		let raised = engine.evaluate(metric, start + Duration::from_secs(40));
+		assert_eq!(raised.len(), 1);
+		assert_eq!(raised[0].0.rule, "isolated");
+
+		engine.observe_at("peer_disconnected", start + Duration::from_secs(50));
+		engine.observe_at("peer_disconnected", start + Duration::from_secs(55));
+		let raised = engine.evaluate(|_| 0, start + Duration::from_secs(60));
+		let rules: Vec<_> = raised.iter().map(|(alert, _)| alert.rule.as_str()).collect();
+		assert_eq!(rules, ["flapping", "heartbeat"]);
+		assert_eq!(raised[1].1, [AlertAction::Gossip { topic: "alerts".to_string() }]);
+
+		// Within their cooldown.
+		engine.observe_at("peer_disconnected", start + Duration::from_secs(56));
+		assert!(engine.evaluate(|_| 0, start + Duration::from_secs(61)).is_empty());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,499 @@
+//! Store of the artifacts a node serves on the data plane, e.g. the files a requester attaches to
+//! its requests. Artifacts are split in chunks stored by their content id, along with a manifest
+//! listing the chunks, so that a large artifact is fetched one chunk at a time rather than in a
+//! single response, see [`crate::Client::fetch_artifact`]. Artifacts are requested by name:
+//! `manifest/<cid>` for the manifest of the artifact `cid` and `chunk/<cid>` for a chunk.
+//!
+//! Artifacts are kept until collected by [`ArtifactStore::gc`], which removes the ones not pinned
+//! past a time to live along with the chunks no manifest refers to anymore, and evicts the oldest
+//! ones not pinned while the store exceeds its quota.
+//!
+//! Providers may mirror the artifacts in demand: an [`ArtifactDemand`] counts the fetches of each
+//! artifact, and the hot ones are advertised on the DHT under their [`artifact_key`] for the
+//! requesters to fetch them from a mirror when their origin is gone, see
+//! [`crate::Client::fetch_artifact_or_mirrors`].
+
+use std::{
+	collections::{BTreeMap, HashMap, HashSet, VecDeque},
+	io,
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{cid::Cid, persist::atomic_write};
+
+/// Size of the chunks of an artifact, the last one excepted.
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Largest artifact fetched from a peer, unless configured otherwise.
+pub const DEFAULT_MAX_ARTIFACT_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum ArtifactError {
+	#[error("Failed to access the artifact store: {0}")]
+	Io(#[from] io::Error),
+	#[error("Malformed artifact manifest: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("Artifact {0} not found")]
+	NotFound(Cid),
+	#[error("Artifact {0} does not match its content id")]
+	Corrupt(Cid),
+	#[error("Invalid artifact name {0:?}")]
+	InvalidName(String),
+	#[error("Artifact of {size} bytes exceeds the quota of {quota} bytes")]
+	QuotaExceeded { size: u64, quota: u64 },
+	#[error(
+		"Artifact {cid} of {size} bytes in {chunks} chunks exceeds the limit of {limit} bytes"
+	)]
+	TooLarge { cid: Cid, size: u64, chunks: usize, limit: u64 },
+}
+
+/// Content of an artifact store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArtifactStats {
+	pub artifacts: usize,
+	pub pinned: usize,
+	pub chunks: usize,
+	/// Size of the chunks, in bytes.
+	pub bytes: u64,
+}
+
+/// What a [`ArtifactStore::gc`] pass removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcReport {
+	pub artifacts: usize,
+	pub chunks: usize,
+	pub bytes: u64,
+}
+
+/// The chunks of an artifact, in order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactManifest {
+	/// Size of the artifact, in bytes.
+	pub size: u64,
+	pub chunks: Vec<Cid>,
+}
+
+/// DHT key the mirrors of the artifact `cid` are advertised under.
+pub fn artifact_key(cid: &Cid) -> String {
+	format!("/asn/artifact/{cid}")
+}
+
+/// Name to request the manifest of the artifact `cid` by.
+pub fn manifest_name(cid: &Cid) -> String {
+	format!("manifest/{cid}")
+}
+
+/// Name to request the chunk `cid` by.
+pub fn chunk_name(cid: &Cid) -> String {
+	format!("chunk/{cid}")
+}
+
+/// Artifacts kept in a directory, a file per chunk and per manifest, along with the pin counts of
+/// the artifacts in `pins.json`.
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+	dir: PathBuf,
+	quota: Option<u64>,
+}
+
+impl ArtifactStore {
+	/// Open the store in `dir`, created if it does not exist yet.
+	pub fn open(dir: impl Into<PathBuf>) -> Result<Self, ArtifactError> {
+		let dir = dir.into();
+		std::fs::create_dir_all(dir.join("chunks"))?;
+		std::fs::create_dir_all(dir.join("manifests"))?;
+		Ok(Self { dir, quota: None })
+	}
+
+	/// Most bytes of chunks kept, the oldest artifacts not pinned being evicted past it.
+	pub fn with_quota(mut self, quota: Option<u64>) -> Self {
+		self.quota = quota;
+		self
+	}
+
+	/// Store an artifact, returning its content id. Chunks already stored are kept as they are.
+	/// Artifacts not pinned are evicted for it to fit in the quota, failing when it does not fit.
+	pub fn put(&self, data: &[u8]) -> Result<Cid, ArtifactError> {
+		if let Some(quota) = self.quota {
+			let size = data.len() as u64;
+			// Nothing is evicted for an artifact that would not fit anyway.
+			if self.pinned_bytes()? + size > quota {
+				return Err(ArtifactError::QuotaExceeded { size, quota });
+			}
+			let (_, stored) = self.evict(quota - size)?;
+			if stored + size > quota {
+				return Err(ArtifactError::QuotaExceeded { size, quota });
+			}
+		}
+		let mut chunks = Vec::new();
+		for chunk in data.chunks(CHUNK_SIZE) {
+			let cid = Cid::of(chunk);
+			let path = self.dir.join("chunks").join(cid.to_string());
+			if !path.exists() {
+				write(&path, chunk)?;
+			}
+			chunks.push(cid);
+		}
+		let cid = Cid::of(data);
+		let manifest = ArtifactManifest { size: data.len() as u64, chunks };
+		write(&self.dir.join("manifests").join(cid.to_string()), &serde_json::to_vec(&manifest)?)?;
+		Ok(cid)
+	}
+
+	/// The manifest of the artifact `cid`, if stored.
+	pub fn manifest(&self, cid: &Cid) -> Result<Option<ArtifactManifest>, ArtifactError> {
+		match read(&self.dir.join("manifests").join(cid.to_string()))? {
+			Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+			None => Ok(None),
+		}
+	}
+
+	/// The content of the artifact `cid`, if stored.
+	pub fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, ArtifactError> {
+		let Some(manifest) = self.manifest(cid)? else {
+			return Ok(None);
+		};
+		let mut data = Vec::with_capacity(manifest.size as usize);
+		for chunk in &manifest.chunks {
+			let path = self.dir.join("chunks").join(chunk.to_string());
+			data.extend(read(&path)?.ok_or(ArtifactError::NotFound(*chunk))?);
+		}
+		if Cid::of(&data) != *cid {
+			return Err(ArtifactError::Corrupt(*cid));
+		}
+		Ok(Some(data))
+	}
+
+	/// Whether the artifact `cid` is stored.
+	pub fn contains(&self, cid: &Cid) -> bool {
+		self.dir.join("manifests").join(cid.to_string()).exists()
+	}
+
+	/// Pin the artifact `cid` for [`Self::gc`] to keep it, once more. Returns its pin count.
+	pub fn pin(&self, cid: &Cid) -> Result<u32, ArtifactError> {
+		if !self.contains(cid) {
+			return Err(ArtifactError::NotFound(*cid));
+		}
+		let mut pins = self.pins()?;
+		let count = pins.entry(*cid).or_default();
+		*count += 1;
+		let count = *count;
+		write(&self.dir.join("pins.json"), &serde_json::to_vec_pretty(&pins)?)?;
+		Ok(count)
+	}
+
+	/// Take back a pin of the artifact `cid`. Returns the pins left, the artifact being collected
+	/// by [`Self::gc`] once none is.
+	pub fn unpin(&self, cid: &Cid) -> Result<u32, ArtifactError> {
+		let mut pins = self.pins()?;
+		let Some(count) = pins.get_mut(cid) else {
+			return Ok(0);
+		};
+		*count -= 1;
+		let count = *count;
+		if count == 0 {
+			pins.remove(cid);
+		}
+		write(&self.dir.join("pins.json"), &serde_json::to_vec_pretty(&pins)?)?;
+		Ok(count)
+	}
+
+	/// Pin counts of the pinned artifacts.
+	pub fn pins(&self) -> Result<BTreeMap<Cid, u32>, ArtifactError> {
+		match read(&self.dir.join("pins.json"))? {
+			Some(data) => Ok(serde_json::from_slice(&data)?),
+			None => Ok(BTreeMap::new()),
+		}
+	}
+
+	pub fn stats(&self) -> Result<ArtifactStats, ArtifactError> {
+		let chunks = self.entries("chunks")?;
+		Ok(ArtifactStats {
+			artifacts: self.entries("manifests")?.len(),
+			pinned: self.pins()?.len(),
+			chunks: chunks.len(),
+			bytes: chunks.iter().map(|entry| entry.size).sum(),
+		})
+	}
+
+	/// Remove the artifacts not pinned and stored longer than `ttl` ago, and the chunks no
+	/// manifest left refers to, then evict the oldest artifacts not pinned while the store
+	/// exceeds its quota.
+	pub fn gc(&self, ttl: Duration) -> Result<GcReport, ArtifactError> {
+		let pins = self.pins()?;
+		let now = SystemTime::now();
+		let mut report = GcReport::default();
+		for entry in self.entries("manifests")? {
+			if !pins.contains_key(&entry.cid) && entry.age(now) >= ttl {
+				std::fs::remove_file(self.dir.join("manifests").join(entry.cid.to_string()))?;
+				report.artifacts += 1;
+			}
+		}
+		// Chunks are written before their manifest, those of an artifact being stored are young.
+		let chunks = self.collect_chunks(|entry| entry.age(now) >= ttl)?;
+		report.chunks += chunks.chunks;
+		report.bytes += chunks.bytes;
+		if let Some(quota) = self.quota {
+			let (evicted, _) = self.evict(quota)?;
+			report.artifacts += evicted.artifacts;
+			report.chunks += evicted.chunks;
+			report.bytes += evicted.bytes;
+		}
+		Ok(report)
+	}
+
+	/// Remove the oldest artifacts not pinned, and their chunks, until at most `max_bytes` of
+	/// chunks are left. Returns what was removed and the bytes left.
+	fn evict(&self, max_bytes: u64) -> Result<(GcReport, u64), ArtifactError> {
+		let mut bytes: u64 = self.entries("chunks")?.iter().map(|entry| entry.size).sum();
+		let mut report = GcReport::default();
+		if bytes <= max_bytes {
+			return Ok((report, bytes));
+		}
+		let pins = self.pins()?;
+		let mut manifests = self.entries("manifests")?;
+		manifests.retain(|entry| !pins.contains_key(&entry.cid));
+		manifests.sort_by_key(|entry| entry.modified);
+		for entry in manifests {
+			std::fs::remove_file(self.dir.join("manifests").join(entry.cid.to_string()))?;
+			report.artifacts += 1;
+			let removed = self.collect_chunks(|_| true)?;
+			report.chunks += removed.chunks;
+			report.bytes += removed.bytes;
+			bytes -= removed.bytes;
+			if bytes <= max_bytes {
+				break;
+			}
+		}
+		Ok((report, bytes))
+	}
+
+	/// Size of the chunks of the pinned artifacts, in bytes.
+	fn pinned_bytes(&self) -> Result<u64, ArtifactError> {
+		let mut chunks = HashSet::new();
+		for cid in self.pins()?.keys() {
+			if let Some(manifest) = self.manifest(cid)? {
+				chunks.extend(manifest.chunks);
+			}
+		}
+		let sizes = self.entries("chunks")?;
+		Ok(sizes
+			.iter()
+			.filter(|entry| chunks.contains(&entry.cid))
+			.map(|entry| entry.size)
+			.sum())
+	}
+
+	/// Remove the chunks no manifest refers to that match `collect`.
+	fn collect_chunks(&self, collect: impl Fn(&Entry) -> bool) -> Result<GcReport, ArtifactError> {
+		let mut referenced = HashSet::new();
+		for entry in self.entries("manifests")? {
+			if let Some(manifest) = self.manifest(&entry.cid)? {
+				referenced.extend(manifest.chunks);
+			}
+		}
+		let mut report = GcReport::default();
+		for entry in self.entries("chunks")? {
+			if !referenced.contains(&entry.cid) && collect(&entry) {
+				std::fs::remove_file(self.dir.join("chunks").join(entry.cid.to_string()))?;
+				report.chunks += 1;
+				report.bytes += entry.size;
+			}
+		}
+		Ok(report)
+	}
+
+	/// The files of the chunks or manifests, skipping those being written.
+	fn entries(&self, kind: &str) -> Result<Vec<Entry>, ArtifactError> {
+		let mut entries = Vec::new();
+		for file in std::fs::read_dir(self.dir.join(kind))? {
+			let file = file?;
+			let Some(cid) = file.file_name().to_str().and_then(|name| name.parse().ok()) else {
+				continue;
+			};
+			let metadata = file.metadata()?;
+			entries.push(Entry { cid, size: metadata.len(), modified: metadata.modified()? });
+		}
+		Ok(entries)
+	}
+
+	/// The content to answer a request for the artifact `name` with, see [`manifest_name`] and
+	/// [`chunk_name`]. None when it is not stored.
+	pub fn serve(&self, name: &str) -> Result<Option<Vec<u8>>, ArtifactError> {
+		let invalid = || ArtifactError::InvalidName(name.to_string());
+		let (kind, cid) = name.split_once('/').ok_or_else(invalid)?;
+		// Parsed rather than joined as is, for a name not to reach outside of the store.
+		let cid: Cid = cid.parse().map_err(|_| invalid())?;
+		match kind {
+			"manifest" => read(&self.dir.join("manifests").join(cid.to_string())),
+			"chunk" => read(&self.dir.join("chunks").join(cid.to_string())),
+			_ => Err(invalid()),
+		}
+	}
+}
+
+/// Fetches of the artifacts over a sliding window, telling which ones to mirror: an artifact is
+/// hot once fetched `threshold` times within the window, and cools down once it no longer is.
+#[derive(Debug)]
+pub struct ArtifactDemand {
+	window: Duration,
+	threshold: usize,
+	fetches: HashMap<Cid, VecDeque<Instant>>,
+	mirrored: HashSet<Cid>,
+}
+
+impl ArtifactDemand {
+	pub fn new(window: Duration, threshold: usize) -> Self {
+		Self { window, threshold, fetches: HashMap::new(), mirrored: HashSet::new() }
+	}
+
+	/// Count a fetch of the artifact `cid`. Returns true when it just became hot, to be mirrored.
+	pub fn record(&mut self, cid: Cid, now: Instant) -> bool {
+		let window = self.window;
+		let fetches = self.fetches.entry(cid).or_default();
+		fetches.retain(|at| now - *at < window);
+		fetches.push_back(now);
+		fetches.len() >= self.threshold && self.mirrored.insert(cid)
+	}
+
+	/// Fetches of the artifact `cid` within the window.
+	pub fn demand(&self, cid: &Cid, now: Instant) -> usize {
+		self.fetches
+			.get(cid)
+			.map_or(0, |fetches| fetches.iter().filter(|at| now - **at < self.window).count())
+	}
+
+	/// The mirrored artifacts no longer hot, which stop being mirrored.
+	pub fn cooled(&mut self, now: Instant) -> Vec<Cid> {
+		let window = self.window;
+		self.fetches.retain(|_, fetches| {
+			fetches.retain(|at| now - *at < window);
+			!fetches.is_empty()
+		});
+		let cooled: Vec<_> = self
+			.mirrored
+			.iter()
+			.filter(|cid| self.fetches.get(cid).is_none_or(|f| f.len() < self.threshold))
+			.copied()
+			.collect();
+		for cid in &cooled {
+			self.mirrored.remove(cid);
+		}
+		cooled
+	}
+}
+
+/// A chunk or manifest file.
+struct Entry {
+	cid: Cid,
+	size: u64,
+	modified: SystemTime,
+}
+
+impl Entry {
+	fn age(&self, now: SystemTime) -> Duration {
+		now.duration_since(self.modified).unwrap_or_default()
+	}
+}
+
+fn read(path: &Path) -> Result<Option<Vec<u8>>, ArtifactError> {
+	match std::fs::read(path) {
+		Ok(data) => Ok(Some(data)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Replace the file at once, for a crash not to leave a truncated chunk.
+fn write(path: &Path, data: &[u8]) -> Result<(), ArtifactError> {
+	Ok(atomic_write(path, data)?)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_artifact_is_stored_and_served_in_chunks() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = ArtifactStore::open(dir.path())?;
+		let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+		let cid = store.put(&data)?;
+		assert_eq!(cid, Cid::of(&data));
+		assert_eq!(store.get(&cid)?, Some(data.clone()));
+
+		let manifest: ArtifactManifest =
+			serde_json::from_slice(&store.serve(&manifest_name(&cid))?.expect("A manifest."))?;
+		assert_eq!((manifest.size, manifest.chunks.len()), (data.len() as u64, 3));
+		let last = store.serve(&chunk_name(&manifest.chunks[2]))?.expect("A chunk.");
+		assert_eq!(last, &data[CHUNK_SIZE * 2..]);
+
+		let missing = Cid::of(b"missing");
+		assert_eq!(store.get(&missing)?, None);
+		assert_eq!(store.serve(&chunk_name(&missing))?, None);
+		assert!(matches!(store.serve("chunk/../secret"), Err(ArtifactError::InvalidName(_))));
+		assert!(matches!(store.serve(&format!("file/{cid}")), Err(ArtifactError::InvalidName(_))));
+		Ok(())
+	}
+
+	#[test]
+	fn test_gc_keeps_pinned_artifacts_within_quota() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = ArtifactStore::open(dir.path())?.with_quota(Some(10));
+		let kept = store.put(b"aaaa")?;
+		let evicted = store.put(b"bbbb")?;
+		assert_eq!(store.pin(&kept)?, 1);
+		assert_eq!(store.pin(&kept)?, 2);
+		assert!(matches!(store.pin(&Cid::of(b"missing")), Err(ArtifactError::NotFound(_))));
+
+		// Fits once the artifact not pinned is evicted.
+		let added = store.put(b"cccccc")?;
+		assert!(!store.contains(&evicted));
+		assert!(matches!(store.put(&[0; 11]), Err(ArtifactError::QuotaExceeded { .. })));
+		assert_eq!(store.stats()?, ArtifactStats { artifacts: 2, pinned: 1, chunks: 2, bytes: 10 });
+
+		// Not yet past the time to live.
+		assert_eq!(store.gc(Duration::from_secs(3600))?, GcReport::default());
+		assert_eq!(store.gc(Duration::ZERO)?, GcReport { artifacts: 1, chunks: 1, bytes: 6 });
+		assert!(!store.contains(&added));
+		assert_eq!(store.unpin(&kept)?, 1);
+		assert_eq!(store.gc(Duration::ZERO)?, GcReport::default());
+		assert_eq!(store.unpin(&kept)?, 0);
+		assert_eq!(store.gc(Duration::ZERO)?, GcReport { artifacts: 1, chunks: 1, bytes: 4 });
+		assert_eq!(store.stats()?, ArtifactStats::default());
+		Ok(())
+	}
+
+	#[test]
+	fn test_hot_artifacts_are_mirrored_until_they_cool_down() {
+		let mut demand = ArtifactDemand::new(Duration::from_secs(60), 2);
+		let (hot, cold) = (Cid::of(b"weights"), Cid::of(b"notes"));
+		let start = Instant::now();
+		assert!(!demand.record(hot, start));
+		assert!(!demand.record(cold, start));
+		assert!(demand.record(hot, start + Duration::from_secs(10)));
+		// Already mirrored.
+		assert!(!demand.record(hot, start + Duration::from_secs(20)));
+		// Fetched too far apart.
+		assert!(!demand.record(cold, start + Duration::from_secs(70)));
+		assert_eq!(demand.demand(&hot, start + Duration::from_secs(30)), 3);
+
+		assert!(demand.cooled(start + Duration::from_secs(65)).is_empty());
+		assert_eq!(demand.cooled(start + Duration::from_secs(71)), [hot]);
+		// Mirrored again once hot again.
+		assert!(!demand.record(hot, start + Duration::from_secs(200)));
+		assert!(demand.record(hot, start + Duration::from_secs(210)));
+	}
+}
+
+// endregion: --- Tests
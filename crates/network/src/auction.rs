@@ -0,0 +1,475 @@
+//! Auctions of tasks: the proposal is gossiped on the tasks topic, the bids sent back within a
+//! window are collected, and the best of them by the policy of the auction wins the task. The
+//! other acceptable bids are kept as backups: a winner that misses the deadline of the task is
+//! penalized in its reputation ledger and the task goes to the next-best bidder, see
+//! [`AuctionConfig::reassignments`].
+
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, VecDeque},
+	error::Error,
+	time::Duration,
+};
+
+use futures::channel::oneshot;
+use libp2p::PeerId;
+
+use crate::{
+	hardware::HardwareProfile,
+	reputation::ReputationLedger,
+	selection::accepts_bid,
+	types::{BidResponse, TaskProposal},
+};
+
+/// Resolves with the value of a DHT record.
+pub(crate) type RecordReceiver = oneshot::Receiver<Result<Vec<u8>, Box<dyn Error + Send>>>;
+
+/// How the winner of an auction is chosen among the acceptable bids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuctionPolicy {
+	/// The cheapest bid, then the most reputable and the fastest bidder.
+	#[default]
+	LowestPrice,
+	/// The most reputable bidder, then the cheapest and the fastest.
+	BestReputation,
+	/// The bidder with the lowest median round-trip time, then the cheapest. Bidders never pinged
+	/// come last.
+	LowestLatency,
+}
+
+/// How long an auction collects bids and how it picks the winner, see
+/// [`crate::Client::run_auction`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionConfig {
+	/// How long bids are collected once the proposal is gossiped.
+	pub window: Duration,
+	pub policy: AuctionPolicy,
+	/// Reputation of the bidders, higher being better, e.g. from the feedback on their past work.
+	/// Bidders missing here are scored by their reputation ledger on the DHT when it is found
+	/// before the window closes, unknown bidders score 0.
+	pub reputation: HashMap<PeerId, f64>,
+	/// How many times a task whose worker missed the deadline is handed to the next-best bidder,
+	/// with as much time as the first worker had, before it times out.
+	pub reassignments: usize,
+}
+
+impl Default for AuctionConfig {
+	fn default() -> Self {
+		Self {
+			window: Duration::from_secs(5),
+			policy: AuctionPolicy::default(),
+			reputation: HashMap::new(),
+			reassignments: 2,
+		}
+	}
+}
+
+/// The bid that won an auction, already notified of its award.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuctionWinner {
+	pub bidder: PeerId,
+	pub bid: BidResponse,
+}
+
+/// Progress of an auction run by the node, reported as [`crate::Event::Auction`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuctionState {
+	/// The proposal was gossiped, bids are collected until the window closes.
+	Open { window: Duration },
+	/// An acceptable bid was received, replacing the previous bid of the same bidder.
+	BidReceived { bidder: PeerId, bid: f64 },
+	/// The window closed and the winner was sent its award.
+	Awarded { winner: PeerId, bid: f64 },
+	/// The window closed without any acceptable bid.
+	NoBids,
+	/// The worker missed the deadline of the task, which was awarded to the next-best bidder.
+	Reassigned { defaulted: PeerId, winner: PeerId, bid: f64 },
+}
+
+/// An auction waiting for its window to close.
+#[derive(Debug)]
+pub(crate) struct Auction {
+	proposal: TaskProposal,
+	config: AuctionConfig,
+	closes_at: tokio::time::Instant,
+	/// Last bid of each bidder, in the order they first bid.
+	bids: Vec<(PeerId, BidResponse)>,
+	/// Lookups of the reputation ledgers of the bidders missing from the config.
+	reputation_lookups: HashMap<PeerId, RecordReceiver>,
+}
+
+impl Auction {
+	pub(crate) fn new(proposal: TaskProposal, config: AuctionConfig) -> Self {
+		let closes_at = tokio::time::Instant::now() + config.window;
+		Self { proposal, config, closes_at, bids: Vec::new(), reputation_lookups: HashMap::new() }
+	}
+
+	pub(crate) fn closes_at(&self) -> tokio::time::Instant {
+		self.closes_at
+	}
+
+	pub(crate) fn proposal(&self) -> &TaskProposal {
+		&self.proposal
+	}
+
+	/// Record the bid of `bidder`, replacing its previous one. Bids that do not answer the proposal
+	/// within its budget and deadline, or from bidders whose announced `hardware` does not meet its
+	/// requirements, are refused. `now` is a unix timestamp in seconds.
+	pub(crate) fn bid(
+		&mut self,
+		bidder: PeerId,
+		bid: BidResponse,
+		hardware: &HardwareProfile,
+		now: u64,
+	) -> bool {
+		if !accepts_bid(&self.proposal, &bid, now)
+			|| !self.proposal.requirements.satisfied_by(hardware)
+		{
+			return false;
+		}
+		match self.bids.iter_mut().find(|(peer, _)| *peer == bidder) {
+			Some((_, previous)) => *previous = bid,
+			None => self.bids.push((bidder, bid)),
+		}
+		true
+	}
+
+	/// Whether the reputation of `bidder` is neither configured nor being looked up.
+	pub(crate) fn needs_reputation(&self, bidder: &PeerId) -> bool {
+		!self.config.reputation.contains_key(bidder)
+			&& !self.reputation_lookups.contains_key(bidder)
+	}
+
+	/// Score `bidder` by the reputation ledger `lookup` resolves with, if it does before the
+	/// window closes.
+	pub(crate) fn await_reputation(&mut self, bidder: PeerId, lookup: RecordReceiver) {
+		self.reputation_lookups.insert(bidder, lookup);
+	}
+
+	/// The acceptable bids of the auction, best first, given the latency of the bidders.
+	pub(crate) fn ranking(
+		&mut self,
+		latency: impl Fn(&PeerId) -> Option<Duration>,
+	) -> Vec<AuctionWinner> {
+		for (bidder, mut lookup) in self.reputation_lookups.drain() {
+			let Ok(Some(Ok(data))) = lookup.try_recv() else {
+				continue;
+			};
+			match ReputationLedger::decode(bidder, &data) {
+				Ok(ledger) => {
+					self.config.reputation.insert(bidder, ledger.score());
+				},
+				Err(e) => tracing::warn!("Ignoring the reputation ledger of {bidder}: {e}"),
+			}
+		}
+		rank_bids(&self.config, &self.bids, latency)
+	}
+
+	/// The assignment of the task to the first of `ranking`, None without any bid. `now` is a unix
+	/// timestamp in seconds.
+	pub(crate) fn assign(self, ranking: Vec<AuctionWinner>, now: u64) -> Option<Assignment> {
+		let mut bidders = VecDeque::from(ranking);
+		let worker = bidders.pop_front()?.bidder;
+		Some(Assignment {
+			allowance: self.proposal.deadline.saturating_sub(now),
+			deadline: self.proposal.deadline,
+			worker,
+			reassignments: self.config.reassignments,
+			backups: bidders,
+			proposal: self.proposal,
+		})
+	}
+}
+
+/// A task awarded by the node, watched until it finishes.
+#[derive(Debug)]
+pub(crate) struct Assignment {
+	proposal: TaskProposal,
+	worker: PeerId,
+	/// Unix timestamp in seconds past which the worker defaulted.
+	deadline: u64,
+	/// Seconds the first worker had from its award to the deadline, given to the next ones too.
+	allowance: u64,
+	reassignments: usize,
+	/// The other acceptable bids, best first.
+	backups: VecDeque<AuctionWinner>,
+}
+
+impl Assignment {
+	pub(crate) fn proposal(&self) -> &TaskProposal {
+		&self.proposal
+	}
+
+	pub(crate) fn worker(&self) -> PeerId {
+		self.worker
+	}
+
+	pub(crate) fn deadline(&self) -> u64 {
+		self.deadline
+	}
+
+	/// Hand the task to the next-best bidder once the worker defaulted at `now`, returning it
+	/// along with its deadline. None when no bidder or reassignment is left.
+	pub(crate) fn reassign(&mut self, now: u64) -> Option<(AuctionWinner, u64)> {
+		if self.reassignments == 0 {
+			return None;
+		}
+		let next = self.backups.pop_front()?;
+		self.reassignments -= 1;
+		self.worker = next.bidder;
+		self.deadline = now + self.allowance;
+		Some((next, self.deadline))
+	}
+}
+
+/// The best of `bids` by the policy of the auction. Ties go to the earliest bidder.
+pub fn select_winner(
+	config: &AuctionConfig,
+	bids: &[(PeerId, BidResponse)],
+	latency: impl Fn(&PeerId) -> Option<Duration>,
+) -> Option<AuctionWinner> {
+	rank_bids(config, bids, latency).into_iter().next()
+}
+
+/// The acceptable `bids` ordered by the policy of the auction, best first. Ties keep the bid order.
+pub fn rank_bids(
+	config: &AuctionConfig,
+	bids: &[(PeerId, BidResponse)],
+	latency: impl Fn(&PeerId) -> Option<Duration>,
+) -> Vec<AuctionWinner> {
+	type Bid = (PeerId, BidResponse);
+	let reputation = |peer: &PeerId| config.reputation.get(peer).copied().unwrap_or_default();
+	let by_price = |a: &Bid, b: &Bid| a.1.bid.total_cmp(&b.1.bid);
+	let by_reputation = |a: &Bid, b: &Bid| reputation(&b.0).total_cmp(&reputation(&a.0));
+	// Unknown latencies sort after the known ones.
+	let by_latency = |a: &Bid, b: &Bid| match (latency(&a.0), latency(&b.0)) {
+		(Some(a), Some(b)) => a.cmp(&b),
+		(Some(_), None) => Ordering::Less,
+		(None, Some(_)) => Ordering::Greater,
+		(None, None) => Ordering::Equal,
+	};
+
+	let mut ranked: Vec<_> = bids.iter().filter(|(_, bid)| bid.bid.is_finite()).collect();
+	ranked.sort_by(|a, b| match config.policy {
+		AuctionPolicy::LowestPrice => {
+			by_price(a, b).then_with(|| by_reputation(a, b)).then_with(|| by_latency(a, b))
+		},
+		AuctionPolicy::BestReputation => {
+			by_reputation(a, b).then_with(|| by_price(a, b)).then_with(|| by_latency(a, b))
+		},
+		AuctionPolicy::LowestLatency => by_latency(a, b).then_with(|| by_price(a, b)),
+	});
+	ranked
+		.into_iter()
+		.map(|(bidder, bid)| AuctionWinner { bidder: *bidder, bid: bid.clone() })
+		.collect()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use libp2p::identity;
+	use proptest::prelude::*;
+
+	use super::*;
+	use crate::{
+		hardware::HardwareRequirements,
+		reputation::{Attestation, Outcome},
+		types::TaskType,
+	};
+
+	fn proposal() -> TaskProposal {
+		TaskProposal {
+			agent_name: "painter".to_string(),
+			task_id: "task".to_string(),
+			task_type: TaskType::ImageGeneration,
+			task_message: "a cat".to_string(),
+			max_bid: 10.0,
+			deadline: 1_000,
+			requirements: Default::default(),
+		}
+	}
+
+	fn bid(price: f64) -> BidResponse {
+		BidResponse {
+			task_id: "task".to_string(),
+			capabilities: Vec::new(),
+			bid: price,
+			payment_address: None,
+		}
+	}
+
+	fn offers() -> impl Strategy<Value = Vec<(BidResponse, u64)>> {
+		let price = prop_oneof![
+			8 => 0.0..20.0f64,
+			1 => Just(f64::NAN),
+			1 => Just(f64::INFINITY),
+			1 => -10.0..0.0f64,
+		];
+		// Bids for the task or another one, sent before or after the deadline of the proposal.
+		prop::collection::vec(
+			(prop_oneof![Just("task"), Just("other")], price, 0u64..2_000).prop_map(
+				|(task_id, price, sent_at)| {
+					(BidResponse { task_id: task_id.to_string(), ..bid(price) }, sent_at)
+				},
+			),
+			0..16,
+		)
+	}
+
+	proptest! {
+		#[test]
+		fn test_ranking_only_keeps_bids_within_budget_and_deadline(
+			offers in offers(),
+			policy in prop_oneof![
+				Just(AuctionPolicy::LowestPrice),
+				Just(AuctionPolicy::BestReputation),
+				Just(AuctionPolicy::LowestLatency),
+			],
+		) {
+			let proposal = proposal();
+			let mut auction = Auction::new(proposal.clone(), AuctionConfig { policy, ..Default::default() });
+			let any = HardwareProfile::default();
+			let mut accepted = 0;
+			for (offer, sent_at) in offers {
+				accepted += usize::from(auction.bid(PeerId::random(), offer, &any, sent_at));
+			}
+
+			let ranking = auction.ranking(|_| None);
+			prop_assert_eq!(ranking.len(), accepted);
+			for winner in &ranking {
+				prop_assert_eq!(&winner.bid.task_id, &proposal.task_id);
+				prop_assert!(winner.bid.bid >= 0.0 && winner.bid.bid <= proposal.max_bid);
+			}
+			if policy == AuctionPolicy::LowestPrice {
+				prop_assert!(ranking.windows(2).all(|pair| pair[0].bid.bid <= pair[1].bid.bid));
+			}
+		}
+	}
+
+	#[test]
+	fn test_auction_keeps_the_last_acceptable_bid_of_each_bidder() {
+		let (a, b) = (PeerId::random(), PeerId::random());
+		let mut auction = Auction::new(proposal(), AuctionConfig::default());
+		let any = HardwareProfile::default();
+
+		assert!(auction.bid(a, bid(5.0), &any, 100));
+		assert!(auction.bid(b, bid(4.0), &any, 100));
+		assert!(auction.bid(a, bid(3.0), &any, 100));
+		// Over budget, for another task, or past the deadline.
+		assert!(!auction.bid(b, bid(11.0), &any, 100));
+		let other_task = BidResponse { task_id: "other".to_string(), ..bid(1.0) };
+		assert!(!auction.bid(b, other_task, &any, 100));
+		assert!(!auction.bid(b, bid(1.0), &any, 1_000));
+
+		assert_eq!(auction.bids, [(a, bid(3.0)), (b, bid(4.0))]);
+		let ranking = auction.ranking(|_| None);
+		assert_eq!(ranking[0], AuctionWinner { bidder: a, bid: bid(3.0) });
+	}
+
+	#[test]
+	fn test_auction_refuses_bidders_without_the_required_hardware() {
+		let (gpu, cpu) = (PeerId::random(), PeerId::random());
+		let requirements = HardwareRequirements { min_vram_mb: Some(16_000), ..Default::default() };
+		let mut auction =
+			Auction::new(TaskProposal { requirements, ..proposal() }, AuctionConfig::default());
+		let a100 = HardwareProfile { vram_mb: Some(40_960), ..Default::default() };
+
+		assert!(!auction.bid(cpu, bid(1.0), &HardwareProfile::default(), 100));
+		assert!(auction.bid(gpu, bid(5.0), &a100, 100));
+		assert_eq!(auction.ranking(|_| None).first().map(|winner| winner.bidder), Some(gpu));
+	}
+
+	#[test]
+	fn test_auction_scores_bidders_by_their_ledger() -> Result<()> {
+		let (cheap, reputable) = (PeerId::random(), PeerId::random());
+		let config = AuctionConfig { policy: AuctionPolicy::BestReputation, ..Default::default() };
+		let mut auction = Auction::new(proposal(), config);
+		let any = HardwareProfile::default();
+		assert!(auction.bid(cheap, bid(1.0), &any, 100));
+		assert!(auction.bid(reputable, bid(5.0), &any, 100));
+
+		let mut ledger = ReputationLedger::new(reputable);
+		let attestation = Attestation {
+			provider: reputable,
+			task_id: "earlier".to_string(),
+			outcome: Outcome::Completed,
+			issued_at: 1,
+		};
+		ledger.add(attestation.sign(&identity::Keypair::generate_ed25519())?)?;
+		for (bidder, ledger) in [(cheap, None), (reputable, Some(ledger))] {
+			assert!(auction.needs_reputation(&bidder));
+			let (sender, receiver) = oneshot::channel();
+			auction.await_reputation(bidder, receiver);
+			if let Some(ledger) = ledger {
+				sender.send(Ok(ledger.encode()?)).expect("Receiver not to be dropped.");
+			}
+		}
+		assert!(!auction.needs_reputation(&cheap));
+
+		let ranking = auction.ranking(|_| None);
+		assert_eq!(ranking.first().map(|winner| winner.bidder), Some(reputable));
+		Ok(())
+	}
+
+	#[test]
+	fn test_select_winner_by_policy() {
+		let (cheap, reputable, fast) = (PeerId::random(), PeerId::random(), PeerId::random());
+		let bids = [(cheap, bid(1.0)), (reputable, bid(3.0)), (fast, bid(2.0))];
+		let latency = |peer: &PeerId| {
+			(*peer != cheap)
+				.then_some(if *peer == fast { 10 } else { 50 })
+				.map(Duration::from_millis)
+		};
+		let winner = |policy| {
+			let config = AuctionConfig {
+				policy,
+				reputation: HashMap::from([(reputable, 0.9), (fast, 0.1)]),
+				..Default::default()
+			};
+			select_winner(&config, &bids, latency).map(|winner| winner.bidder)
+		};
+
+		assert_eq!(winner(AuctionPolicy::LowestPrice), Some(cheap));
+		assert_eq!(winner(AuctionPolicy::BestReputation), Some(reputable));
+		assert_eq!(winner(AuctionPolicy::LowestLatency), Some(fast));
+		assert_eq!(select_winner(&AuctionConfig::default(), &[], latency), None);
+	}
+
+	#[test]
+	fn test_overdue_task_goes_to_the_next_best_bidder() {
+		let (cheap, fair, pricey) = (PeerId::random(), PeerId::random(), PeerId::random());
+		let config = AuctionConfig { reassignments: 1, ..Default::default() };
+		let mut auction = Auction::new(proposal(), config);
+		let any = HardwareProfile::default();
+		for (bidder, price) in [(pricey, 5.0), (cheap, 1.0), (fair, 3.0)] {
+			assert!(auction.bid(bidder, bid(price), &any, 100));
+		}
+		let ranking = auction.ranking(|_| None);
+		let bidders: Vec<_> = ranking.iter().map(|winner| winner.bidder).collect();
+		assert_eq!(bidders, [cheap, fair, pricey]);
+
+		let mut assignment = auction.assign(ranking, 400).expect("An assignment.");
+		assert_eq!((assignment.worker(), assignment.deadline()), (cheap, 1_000));
+		// The next worker gets the 600 seconds the first one had.
+		let (next, deadline) = assignment.reassign(1_000).expect("A reassignment.");
+		assert_eq!((next, deadline), (AuctionWinner { bidder: fair, bid: bid(3.0) }, 1_600));
+		assert_eq!(assignment.worker(), fair);
+		// Out of reassignments, although a bidder is left.
+		assert_eq!(assignment.reassign(1_600), None);
+	}
+
+	#[test]
+	fn test_select_winner_breaks_ties_in_bid_order() {
+		let (first, second) = (PeerId::random(), PeerId::random());
+		let bids = [(first, bid(2.0)), (second, bid(2.0))];
+		let winner = select_winner(&AuctionConfig::default(), &bids, |_| None);
+		assert_eq!(winner.map(|winner| winner.bidder), Some(first));
+	}
+}
+
+// endregion: --- Tests
@@ -1,57 +1,246 @@
-use crate::types::{LLMRequest, LLMResponse};
+use crate::{
+	commitment::{ResultAck, TaskResultMessage},
+	compression::{CompressedCodec, CompressionConfig, CompressionSaved},
+	config::{ConnectionLimitsConfig, KademliaConfig, NetworkConfig},
+	directory::{DirectorySyncRequest, DirectorySyncResponse},
+	discovery::MdnsConfig,
+	encryption::{EncryptedCodec, EncryptionKey},
+	feedback::Feedback,
+	moderation::TopicModeration,
+	protocol::{VersionedCodec, CONTROL_PROTOCOLS},
+	stream::{StreamRequest, StreamResponse},
+	tasks::TaskStatusUpdate,
+	types::{ArtifactRequest, ArtifactResponse, BidResponse, LLMRequest, LLMResponse, TaskAward},
+};
 use libp2p::{
-	autonat, gossipsub, identify, identity, kad,
-	kad::Config as KademliaConfig,
-	mdns, ping, relay, rendezvous,
+	allow_block_list, autonat, connection_limits, gossipsub, identify, identity, kad, mdns, ping,
+	relay, rendezvous,
 	request_response::{self, ProtocolSupport},
-	swarm::NetworkBehaviour,
+	swarm::{behaviour::toggle::Toggle, NetworkBehaviour},
 	upnp, PeerId, StreamProtocol,
 };
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
 	collections::hash_map::DefaultHasher,
 	hash::{Hash, Hasher},
+	sync::Arc,
 	time::Duration,
 };
 
 static PROTOCOL_VERSION: &str = "/asn/1.0.0";
+static DATA_PROTOCOL_VERSION: &str = "/asn/data/1.0.0";
+static STREAM_PROTOCOL_VERSION: &str = "/asn/stream/1.0.0";
+static BID_PROTOCOL_VERSION: &str = "/asn/bid/1.0.0";
+static AWARD_PROTOCOL_VERSION: &str = "/asn/award/1.0.0";
+static FEEDBACK_PROTOCOL_VERSION: &str = "/asn/feedback/1.0.0";
+static TASK_STATUS_PROTOCOL_VERSION: &str = "/asn/task-status/1.0.0";
+static TASK_RESULT_PROTOCOL_VERSION: &str = "/asn/task-result/1.0.0";
+/// Protocol the capability directory is pulled from a peer over, see [`crate::directory`].
+pub static DIRECTORY_SYNC_PROTOCOL_VERSION: &str = "/asn/directory-sync/1.0.0";
 static EVERYONE_TOPIC: &str = "everyone";
-static CAPABILITIES_TOPIC: &str = "capabilities";
+/// Topic the providers gossip their capability announcements on, see [`crate::directory`].
+pub static CAPABILITIES_TOPIC: &str = "capabilities";
+/// Topic the task proposals are gossiped on, the bids being sent back to the proposer directly.
+pub static TASKS_TOPIC: &str = "tasks";
+
+/// The control plane carries the agent requests: small messages that must not wait behind large
+/// transfers, hence its tight size limit.
+const REQUEST_SIZE_MAXIMUM: u64 = 64 * 1024;
+const CONTROL_RESPONSE_SIZE_MAXIMUM: u64 = 1024 * 1024;
+/// The data plane carries the artifacts. Its transfers may be slow, so fewer of them run at once.
+const DATA_RESPONSE_SIZE_MAXIMUM: u64 = 256 * 1024 * 1024;
+const DATA_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+const DATA_MAX_CONCURRENT_STREAMS: usize = 16;
+
+/// Names the codec of a request-response behaviour, libp2p does not export the cbor one.
+pub trait CodecOf {
+	type Codec;
+}
+
+impl<C: request_response::Codec + Clone + Send + 'static> CodecOf
+	for request_response::Behaviour<C>
+{
+	type Codec = C;
+}
+
+pub(crate) type CborCodec<Req, Resp> =
+	<request_response::cbor::Behaviour<Req, Resp> as CodecOf>::Codec;
+
+/// Codec of the agent requests, shimmed for the peers still on an older protocol version.
+pub type ControlCodec =
+	VersionedCodec<CompressedCodec<EncryptedCodec<CborCodec<LLMRequest, LLMResponse>>>>;
+pub type DataCodec = CompressedCodec<CborCodec<ArtifactRequest, ArtifactResponse>>;
+
+/// A cbor codec whose requests are all small, while responses may be up to
+/// `response_size_maximum` bytes.
+fn cbor_codec<Req, Resp>(response_size_maximum: u64) -> CborCodec<Req, Resp>
+where
+	Req: Send + Serialize + DeserializeOwned + 'static,
+	Resp: Send + Serialize + DeserializeOwned + 'static,
+{
+	CborCodec::<Req, Resp>::default()
+		.set_request_size_maximum(REQUEST_SIZE_MAXIMUM)
+		.set_response_size_maximum(response_size_maximum)
+}
+
+/// A codec compressing its payloads on the compressed variants of its protocols.
+fn compressed_codec<C>(
+	inner: C,
+	response_size_maximum: u64,
+	compression: &CompressionConfig,
+	saved: &CompressionSaved,
+) -> CompressedCodec<C> {
+	CompressedCodec::new(
+		inner,
+		compression,
+		REQUEST_SIZE_MAXIMUM,
+		response_size_maximum,
+		saved.clone(),
+	)
+}
+
+fn kademlia_config(config: &KademliaConfig) -> kad::Config {
+	let mut kademlia_config = kad::Config::default();
+	kademlia_config
+		.set_replication_interval(config.replication_interval)
+		.set_publication_interval(config.publication_interval)
+		.set_record_ttl(config.record_ttl)
+		.set_provider_record_ttl(config.provider_record_ttl)
+		.set_provider_publication_interval(config.provider_publication_interval)
+		.set_query_timeout(config.query_timeout)
+		.set_parallelism(config.parallelism);
+	kademlia_config
+}
+
+fn mdns_behaviour(config: &MdnsConfig, peer_id: PeerId) -> Option<mdns::tokio::Behaviour> {
+	if !config.enabled {
+		return None;
+	}
+
+	let mdns_config = mdns::Config {
+		ttl: config.ttl,
+		query_interval: config.query_interval,
+		enable_ipv6: config.ipv6,
+	};
+	mdns::tokio::Behaviour::new(mdns_config, peer_id)
+		.inspect_err(|e| tracing::warn!("mDNS disabled, failed to start: {e}"))
+		.ok()
+}
 
 #[derive(NetworkBehaviour)]
 pub struct AsnBehaviour {
+	pub connection_limits: connection_limits::Behaviour,
+	pub blocked_peers: allow_block_list::Behaviour<allow_block_list::BlockedPeers>,
 	pub identify: identify::Behaviour,
-	pub request_response: request_response::cbor::Behaviour<LLMRequest, LLMResponse>,
+	pub control: request_response::Behaviour<ControlCodec>,
+	pub data: request_response::Behaviour<DataCodec>,
+	pub llm_stream: request_response::cbor::Behaviour<StreamRequest, StreamResponse>,
+	pub bids: request_response::cbor::Behaviour<BidResponse, ()>,
+	pub awards: request_response::cbor::Behaviour<TaskAward, ()>,
+	pub feedback: request_response::cbor::Behaviour<Feedback, ()>,
+	pub task_status: request_response::cbor::Behaviour<TaskStatusUpdate, ()>,
+	pub task_results: request_response::cbor::Behaviour<TaskResultMessage, ResultAck>,
+	pub directory_sync:
+		request_response::cbor::Behaviour<DirectorySyncRequest, DirectorySyncResponse>,
 	pub rendezvous: rendezvous::client::Behaviour,
+	pub rendezvous_server: Toggle<rendezvous::server::Behaviour>,
 	pub relay: relay::Behaviour,
 	pub ping: ping::Behaviour,
 	pub kademlia: kad::Behaviour<kad::store::MemoryStore>,
 	pub auto_nat: autonat::Behaviour,
-	pub mdns: mdns::tokio::Behaviour,
+	pub mdns: Toggle<mdns::tokio::Behaviour>,
 	pub gossipsub: gossipsub::Behaviour,
 	pub upnp: upnp::tokio::Behaviour,
 }
 
 impl AsnBehaviour {
-	pub fn new(key: &identity::Keypair) -> Self {
+	pub fn new(
+		key: &identity::Keypair,
+		config: &NetworkConfig,
+		compression_saved: &CompressionSaved,
+	) -> Self {
 		let peer_id = key.public().to_peer_id();
-		let mut kademlia_config = KademliaConfig::default();
-		kademlia_config.set_provider_publication_interval(Some(Duration::from_secs(60)));
 
 		Self {
-			identify: identify::Behaviour::new(identify::Config::new(
-				PROTOCOL_VERSION.into(),
-				key.public().clone(),
-			)),
+			connection_limits: connection_limits::Behaviour::new(Default::default()),
+			blocked_peers: Default::default(),
+			identify: identify::Behaviour::new(
+				identify::Config::new(PROTOCOL_VERSION.into(), key.public().clone())
+					.with_agent_version(config.capabilities.to_agent_version()),
+			),
 			kademlia: kad::Behaviour::with_config(
 				peer_id,
 				kad::store::MemoryStore::new(peer_id),
-				kademlia_config,
+				kademlia_config(&config.kademlia),
+			),
+			control: request_response::Behaviour::with_codec(
+				VersionedCodec(compressed_codec(
+					EncryptedCodec::new(
+						cbor_codec(CONTROL_RESPONSE_SIZE_MAXIMUM),
+						EncryptionKey::from_identity(key).map(Arc::new),
+					),
+					CONTROL_RESPONSE_SIZE_MAXIMUM,
+					&config.compression,
+					compression_saved,
+				)),
+				// Only the newest version is compressed.
+				config
+					.compression
+					.protocols(CONTROL_PROTOCOLS[0])
+					.into_iter()
+					.chain(
+						CONTROL_PROTOCOLS[1..].iter().map(|protocol| StreamProtocol::new(protocol)),
+					)
+					.map(|protocol| (protocol, ProtocolSupport::Full)),
+				request_response::Config::default()
+					.with_request_timeout(config.request_timeouts.max),
+			),
+			data: request_response::Behaviour::with_codec(
+				compressed_codec(
+					cbor_codec(DATA_RESPONSE_SIZE_MAXIMUM),
+					DATA_RESPONSE_SIZE_MAXIMUM,
+					&config.compression,
+					compression_saved,
+				),
+				config
+					.compression
+					.protocols(DATA_PROTOCOL_VERSION)
+					.into_iter()
+					.map(|protocol| (protocol, ProtocolSupport::Full)),
+				request_response::Config::default()
+					.with_request_timeout(DATA_REQUEST_TIMEOUT)
+					.with_max_concurrent_streams(DATA_MAX_CONCURRENT_STREAMS),
+			),
+			llm_stream: request_response::cbor::Behaviour::new(
+				[(StreamProtocol::new(STREAM_PROTOCOL_VERSION), ProtocolSupport::Full)],
+				request_response::Config::default(),
+			),
+			bids: request_response::cbor::Behaviour::new(
+				[(StreamProtocol::new(BID_PROTOCOL_VERSION), ProtocolSupport::Full)],
+				request_response::Config::default(),
+			),
+			awards: request_response::cbor::Behaviour::new(
+				[(StreamProtocol::new(AWARD_PROTOCOL_VERSION), ProtocolSupport::Full)],
+				request_response::Config::default(),
+			),
+			feedback: request_response::cbor::Behaviour::new(
+				[(StreamProtocol::new(FEEDBACK_PROTOCOL_VERSION), ProtocolSupport::Full)],
+				request_response::Config::default(),
+			),
+			task_status: request_response::cbor::Behaviour::new(
+				[(StreamProtocol::new(TASK_STATUS_PROTOCOL_VERSION), ProtocolSupport::Full)],
+				request_response::Config::default(),
+			),
+			task_results: request_response::cbor::Behaviour::new(
+				[(StreamProtocol::new(TASK_RESULT_PROTOCOL_VERSION), ProtocolSupport::Full)],
+				request_response::Config::default(),
 			),
-			request_response: request_response::cbor::Behaviour::new(
-				[(StreamProtocol::new(PROTOCOL_VERSION), ProtocolSupport::Full)],
+			directory_sync: request_response::cbor::Behaviour::new(
+				[(StreamProtocol::new(DIRECTORY_SYNC_PROTOCOL_VERSION), ProtocolSupport::Full)],
 				request_response::Config::default(),
 			),
 			rendezvous: rendezvous::client::Behaviour::new(key.clone()),
+			rendezvous_server: Toggle::from(None),
 			relay: relay::Behaviour::new(key.public().to_peer_id(), Default::default()),
 			ping: ping::Behaviour::new(
 				ping::Config::new()
@@ -63,13 +252,14 @@ impl AsnBehaviour {
 				key.public().to_peer_id(),
 				autonat::Config { only_global_ips: false, ..Default::default() },
 			),
-			mdns: mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())
-				.unwrap(),
+			mdns: Toggle::from(mdns_behaviour(&config.mdns, peer_id)),
 			gossipsub: gossipsub::Behaviour::new(
 				gossipsub::MessageAuthenticity::Signed(key.clone()),
 				gossipsub::ConfigBuilder::default()
 					.heartbeat_interval(Duration::from_secs(10))
 					.validation_mode(gossipsub::ValidationMode::Permissive)
+					// Messages are only propagated once the event loop validated them.
+					.validate_messages()
 					.allow_self_origin(true)
 					.history_length(10)
 					.history_gossip(10)
@@ -89,10 +279,14 @@ impl AsnBehaviour {
 		}
 	}
 
+	/// Stop serving the DHT and leave every gossip topic.
 	pub fn shutdown(&mut self) {
 		self.kademlia.set_mode(None);
-		self.gossipsub.unsubscribe(&gossipsub::IdentTopic::new(EVERYONE_TOPIC));
-		self.gossipsub.unsubscribe(&gossipsub::IdentTopic::new(CAPABILITIES_TOPIC));
+		let topics: Vec<_> = self.gossipsub.topics().cloned().collect();
+		for topic in topics {
+			tracing::info!("Unsubscribed from topic: {topic}");
+			self.gossipsub.unsubscribe(&gossipsub::IdentTopic::new(topic.into_string()));
+		}
 	}
 
 	pub fn bootstrap(&mut self) {
@@ -110,6 +304,8 @@ impl AsnBehaviour {
 			.subscribe(&gossipsub::IdentTopic::new(CAPABILITIES_TOPIC))
 			.unwrap();
 
+		self.subscribe(TASKS_TOPIC);
+
 		match self.kademlia.bootstrap() {
 			Ok(_) => {
 				tracing::info!("Successfully bootstrapped");
@@ -120,6 +316,39 @@ impl AsnBehaviour {
 		}
 	}
 
+	/// Enable gossipsub peer scoring so that messages rejected on the moderated topics lower the
+	/// score of the peers forwarding them.
+	pub fn enable_topic_moderation(&mut self, moderation: &TopicModeration) {
+		let mut params = gossipsub::PeerScoreParams::default();
+		for topic in moderation.topics() {
+			params.topics.insert(topic.clone(), gossipsub::TopicScoreParams::default());
+		}
+
+		match self
+			.gossipsub
+			.with_peer_score(params, gossipsub::PeerScoreThresholds::default())
+		{
+			Ok(()) => tracing::info!("Enabled gossipsub peer scoring for moderated topics"),
+			Err(e) => tracing::error!("Failed to enable gossipsub peer scoring: {e}"),
+		}
+	}
+
+	/// Serve as a rendezvous point, letting the other nodes register and discover each other
+	/// through this one.
+	pub fn enable_rendezvous_server(&mut self) {
+		tracing::info!("Serving as a rendezvous point");
+		self.rendezvous_server =
+			Toggle::from(Some(rendezvous::server::Behaviour::new(Default::default())));
+	}
+
+	pub fn set_connection_limits(&mut self, config: &ConnectionLimitsConfig) {
+		*self.connection_limits.limits_mut() = connection_limits::ConnectionLimits::default()
+			.with_max_established(config.max_established)
+			.with_max_established_per_peer(config.max_established_per_peer)
+			.with_max_pending_incoming(config.max_pending_incoming)
+			.with_max_pending_outgoing(config.max_pending_outgoing);
+	}
+
 	pub fn subscribe(&mut self, topic: &str) {
 		tracing::info!("Subscribed to topic: {topic}");
 		self.gossipsub.subscribe(&gossipsub::IdentTopic::new(topic)).unwrap();
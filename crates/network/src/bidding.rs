@@ -0,0 +1,352 @@
+//! Bidding of providers on the task proposals, priced by a schedule: a provider may advertise and
+//! bid lower when its energy is cheap, e.g. at night, and higher at peak hours. Schedules come
+//! from a [`ScheduleSource`], a fixed [`PriceSchedule`] or e.g. a feed of energy prices. The
+//! scheduled price is the floor a [`BidStrategy`] bids from, or declines the task, by default
+//! [`CostPlus`].
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+	selection::accepts_bid,
+	types::{BidResponse, TaskProposal, TaskType},
+};
+
+/// Factor applied to the base price of a provider at a given time.
+pub trait ScheduleSource: Send + Sync {
+	/// Price factor at the unix timestamp `now` in seconds, e.g. 0.5 to charge half the base price.
+	fn price_factor(&self, now: u64) -> f64;
+}
+
+/// A window of hours of the day, in UTC, priced with its own factor. Windows may wrap around
+/// midnight, such as `22-6`, and cover the whole day when they start where they end, such as
+/// `0-24`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScheduleWindow {
+	pub start_hour: u8,
+	/// First hour past the window.
+	pub end_hour: u8,
+	pub factor: f64,
+}
+
+impl ScheduleWindow {
+	fn contains(&self, hour: u8) -> bool {
+		if self.start_hour == self.end_hour {
+			true
+		} else if self.start_hour < self.end_hour {
+			(self.start_hour..self.end_hour).contains(&hour)
+		} else {
+			hour >= self.start_hour || hour < self.end_hour
+		}
+	}
+}
+
+/// Price factors by hour of the day, written `22-6=0.5,17-21=1.5`. The first window holding the
+/// hour applies, hours outside any window are priced at the base price.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PriceSchedule {
+	pub windows: Vec<ScheduleWindow>,
+}
+
+impl ScheduleSource for PriceSchedule {
+	fn price_factor(&self, now: u64) -> f64 {
+		let hour = ((now % 86_400) / 3600) as u8;
+		self.windows
+			.iter()
+			.find(|window| window.contains(hour))
+			.map_or(1.0, |window| window.factor)
+	}
+}
+
+impl FromStr for PriceSchedule {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let window = |window: &str| {
+			let invalid = || format!("Invalid schedule window {window}, expected e.g. 22-6=0.5");
+			let (hours, factor) = window.split_once('=').ok_or_else(invalid)?;
+			let (start, end) = hours.split_once('-').ok_or_else(invalid)?;
+			let hour = |hour: &str| hour.trim().parse::<u8>().ok().filter(|hour| *hour <= 24);
+			let window = ScheduleWindow {
+				start_hour: hour(start).ok_or_else(invalid)? % 24,
+				end_hour: hour(end).ok_or_else(invalid)? % 24,
+				factor: factor.trim().parse().map_err(|_| invalid())?,
+			};
+			if !window.factor.is_finite() || window.factor < 0.0 {
+				return Err(invalid());
+			}
+			Ok(window)
+		};
+		let windows = s.split(',').filter(|w| !w.trim().is_empty()).map(window);
+		Ok(Self { windows: windows.collect::<Result<_, _>>()? })
+	}
+}
+
+impl fmt::Display for PriceSchedule {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let windows: Vec<_> = self
+			.windows
+			.iter()
+			.map(|w| format!("{}-{}={}", w.start_hour, w.end_hour, w.factor))
+			.collect();
+		f.write_str(&windows.join(","))
+	}
+}
+
+/// What a [`BidStrategy`] prices a task from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BidInput<'a> {
+	pub task_type: &'a TaskType,
+	/// Size of the task message, in bytes.
+	pub message_size: usize,
+	/// Tasks the provider won and has not finished yet.
+	pub load: usize,
+	/// Scheduled price of the provider, see [`Bidder::price_at`].
+	pub price_floor: f64,
+}
+
+/// How a provider prices the tasks it bids on.
+pub trait BidStrategy: Send + Sync {
+	/// The bid on a task, None to decline it.
+	fn bid(&self, input: &BidInput) -> Option<f64>;
+}
+
+/// The price floor plus the size of the message, with a margin on top, declining the tasks past
+/// a number of unfinished ones. Written `cost-plus:margin=0.2,per-kib=0.01,max-load=4`, each
+/// parameter optional.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CostPlus {
+	/// Share of the cost added to it, e.g. 0.2 for 20%.
+	pub margin: f64,
+	/// Cost of a KiB of message.
+	pub per_kib: f64,
+	pub max_load: Option<usize>,
+}
+
+impl BidStrategy for CostPlus {
+	fn bid(&self, input: &BidInput) -> Option<f64> {
+		if self.max_load.is_some_and(|max_load| input.load >= max_load) {
+			return None;
+		}
+		let cost = input.price_floor + self.per_kib * input.message_size as f64 / 1024.0;
+		Some(cost * (1.0 + self.margin))
+	}
+}
+
+/// The price floor raised by a step for every unfinished task, for a busy provider to win the
+/// tasks that pay for the wait. Written `surge:step=0.25,max-load=8`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surge {
+	/// Share of the price floor added per unfinished task.
+	pub step: f64,
+	pub max_load: Option<usize>,
+}
+
+impl BidStrategy for Surge {
+	fn bid(&self, input: &BidInput) -> Option<f64> {
+		if self.max_load.is_some_and(|max_load| input.load >= max_load) {
+			return None;
+		}
+		Some(input.price_floor * (1.0 + self.step * input.load as f64))
+	}
+}
+
+/// The strategies a provider selects by name, e.g. from the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrategyConfig {
+	CostPlus(CostPlus),
+	Surge(Surge),
+}
+
+impl Default for StrategyConfig {
+	fn default() -> Self {
+		Self::CostPlus(CostPlus::default())
+	}
+}
+
+impl BidStrategy for StrategyConfig {
+	fn bid(&self, input: &BidInput) -> Option<f64> {
+		match self {
+			StrategyConfig::CostPlus(strategy) => strategy.bid(input),
+			StrategyConfig::Surge(strategy) => strategy.bid(input),
+		}
+	}
+}
+
+impl FromStr for StrategyConfig {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (name, params) = s.split_once(':').unwrap_or((s, ""));
+		let mut params: Vec<(&str, &str)> = params
+			.split(',')
+			.filter(|param| !param.trim().is_empty())
+			.map(|param| {
+				param.split_once('=').map(|(key, value)| (key.trim(), value.trim())).ok_or_else(
+					|| format!("Invalid strategy parameter {param}, expected key=value"),
+				)
+			})
+			.collect::<Result<_, _>>()?;
+		let mut take = |key: &str| -> Result<Option<f64>, String> {
+			let Some(i) = params.iter().position(|(k, _)| *k == key) else {
+				return Ok(None);
+			};
+			let (_, value) = params.remove(i);
+			match value.parse::<f64>() {
+				Ok(value) if value.is_finite() && value >= 0.0 => Ok(Some(value)),
+				_ => Err(format!("Invalid {key} {value}, expected a positive number")),
+			}
+		};
+		let strategy = match name.trim() {
+			"cost-plus" => StrategyConfig::CostPlus(CostPlus {
+				margin: take("margin")?.unwrap_or(0.0),
+				per_kib: take("per-kib")?.unwrap_or(0.0),
+				max_load: take("max-load")?.map(|max_load| max_load as usize),
+			}),
+			"surge" => StrategyConfig::Surge(Surge {
+				step: take("step")?.unwrap_or(0.25),
+				max_load: take("max-load")?.map(|max_load| max_load as usize),
+			}),
+			name => {
+				return Err(format!("Unknown bid strategy {name}, expected cost-plus or surge"))
+			},
+		};
+		match params.first() {
+			Some((key, _)) => {
+				Err(format!("Unknown parameter {key} of the {} strategy", name.trim()))
+			},
+			None => Ok(strategy),
+		}
+	}
+}
+
+/// Prices and bids of a provider: its base price scaled by the factor of its schedule, bid on by
+/// its strategy.
+pub struct Bidder {
+	base_price: f64,
+	schedule: Box<dyn ScheduleSource>,
+	strategy: Box<dyn BidStrategy>,
+	capabilities: Vec<String>,
+}
+
+impl Bidder {
+	pub fn new(base_price: f64, schedule: impl ScheduleSource + 'static) -> Self {
+		Self {
+			base_price,
+			schedule: Box::new(schedule),
+			strategy: Box::new(CostPlus::default()),
+			capabilities: Vec::new(),
+		}
+	}
+
+	/// Capabilities listed in the bids.
+	pub fn with_capabilities(mut self, capabilities: Vec<String>) -> Self {
+		self.capabilities = capabilities;
+		self
+	}
+
+	/// Strategy pricing the bids, [`CostPlus`] with no margin by default.
+	pub fn with_strategy(mut self, strategy: impl BidStrategy + 'static) -> Self {
+		self.strategy = Box::new(strategy);
+		self
+	}
+
+	/// The price at the unix timestamp `now`, to advertise in the agent card.
+	pub fn price_at(&self, now: u64) -> f64 {
+		self.base_price * self.schedule.price_factor(now)
+	}
+
+	/// The bid on `proposal` at the unix timestamp `now` with `load` unfinished tasks, None when
+	/// the strategy declines it, the price exceeds the budget of the proposal or its deadline
+	/// passed.
+	pub fn bid(&self, proposal: &TaskProposal, now: u64, load: usize) -> Option<BidResponse> {
+		let input = BidInput {
+			task_type: &proposal.task_type,
+			message_size: proposal.task_message.len(),
+			load,
+			price_floor: self.price_at(now),
+		};
+		let bid = BidResponse {
+			task_id: proposal.task_id.clone(),
+			capabilities: self.capabilities.clone(),
+			bid: self.strategy.bid(&input)?,
+			payment_address: None,
+		};
+		accepts_bid(proposal, &bid, now).then_some(bid)
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::types::TaskType;
+
+	const HOUR: u64 = 3600;
+
+	#[test]
+	fn test_price_schedule_by_hour() -> Result<()> {
+		let schedule: PriceSchedule = "22-6=0.5, 17-21=1.5".parse()?;
+		assert_eq!(schedule.to_string(), "22-6=0.5,17-21=1.5");
+		// Midnight of some day, then 3am, noon, 6pm and 11pm.
+		let midnight = 20_000 * 86_400;
+		let factors: Vec<_> = [0, 3, 12, 18, 23]
+			.map(|hour| schedule.price_factor(midnight + hour * HOUR))
+			.into();
+		assert_eq!(factors, [0.5, 0.5, 1.0, 1.5, 0.5]);
+		let all_day: PriceSchedule = "0-24=2".parse()?;
+		assert_eq!(all_day.price_factor(midnight + 12 * HOUR), 2.0);
+
+		assert!("22-6".parse::<PriceSchedule>().is_err());
+		assert!("25-6=1".parse::<PriceSchedule>().is_err());
+		assert!("1-2=-1".parse::<PriceSchedule>().is_err());
+		assert_eq!("".parse::<PriceSchedule>()?, PriceSchedule::default());
+		Ok(())
+	}
+
+	#[test]
+	fn test_bidder_bids_low_at_night() -> Result<()> {
+		let bidder = Bidder::new(4.0, "0-6=0.5".parse::<PriceSchedule>()?);
+		let night = 20_000 * 86_400 + 2 * HOUR;
+		let proposal = TaskProposal {
+			agent_name: "painter".to_string(),
+			task_id: "task".to_string(),
+			task_type: TaskType::ImageGeneration,
+			task_message: "a cat".to_string(),
+			max_bid: 3.0,
+			deadline: night + 12 * HOUR,
+			requirements: Default::default(),
+		};
+
+		assert_eq!(bidder.bid(&proposal, night, 0).map(|bid| bid.bid), Some(2.0));
+		// Over budget by day.
+		assert_eq!(bidder.bid(&proposal, night + 8 * HOUR, 0), None);
+		Ok(())
+	}
+
+	#[test]
+	fn test_strategies_price_size_and_load() -> Result<()> {
+		let input = BidInput {
+			task_type: &TaskType::DataProcessing,
+			message_size: 2048,
+			load: 2,
+			price_floor: 1.0,
+		};
+		let cost_plus: StrategyConfig = "cost-plus:margin=0.5,per-kib=0.5".parse()?;
+		assert_eq!(cost_plus.bid(&input), Some(3.0));
+		let surge: StrategyConfig = "surge:step=0.5,max-load=3".parse()?;
+		assert_eq!(surge.bid(&input), Some(2.0));
+		assert_eq!(surge.bid(&BidInput { load: 3, ..input }), None);
+		assert_eq!("cost-plus".parse::<StrategyConfig>()?, StrategyConfig::default());
+
+		assert!("cost-plus:margin=-1".parse::<StrategyConfig>().is_err());
+		assert!("cost-plus:step=1".parse::<StrategyConfig>().is_err());
+		assert!("auction".parse::<StrategyConfig>().is_err());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,242 @@
+use std::{
+	collections::{HashMap, HashSet},
+	time::Duration,
+};
+
+use libp2p::{
+	gossipsub::{IdentTopic, TopicHash},
+	identity, Multiaddr, PeerId,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+	signed::{SignatureError, SignedPayload},
+	stream::now_ms,
+	types::ProtocolError,
+	wire::tolerant_enum,
+};
+
+/// Gossip topic the signed bulletins are published on.
+pub static CONTROL_TOPIC: &str = "control";
+
+const SIGNING_DOMAIN: &[u8] = b"dasn-bulletin:";
+
+/// A network-wide configuration notice published by a trusted key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub enum BulletinKind {
+	/// Peers new nodes are recommended to bootstrap from.
+	BootstrapPeers { peers: Vec<Multiaddr> },
+	/// A protocol that will stop being supported.
+	Deprecation { protocol: String, notice: String },
+	/// A topic nodes should stop following, e.g. because it is being flooded.
+	TopicBan { topic: String },
+	/// A notice introduced by a newer node, reported but never applied.
+	#[serde(skip)]
+	Unknown(String, Vec<u8>),
+}
+
+tolerant_enum!(BulletinKind);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bulletin {
+	pub kind: BulletinKind,
+	/// Unix timestamp in milliseconds. Bulletins older than the last one of the same issuer, or
+	/// issued longer ago than [`BulletinConfig::max_age`], are discarded as replays.
+	pub issued_at: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum BulletinError {
+	#[error("Invalid bulletin: {0}")]
+	Signature(#[from] SignatureError),
+	#[error("Malformed bulletin: {0}")]
+	Protocol(#[from] ProtocolError),
+	#[error("Bulletin issued by untrusted key {0}")]
+	UntrustedIssuer(PeerId),
+	#[error("Stale bulletin from {0}")]
+	Stale(PeerId),
+	#[error("Bulletin from {0} issued {1:?} ago")]
+	Expired(PeerId, Duration),
+}
+
+impl Bulletin {
+	pub fn sign(&self, key: &identity::Keypair) -> Result<SignedPayload, SignatureError> {
+		SignedPayload::sign(key, SIGNING_DOMAIN, self)
+	}
+}
+
+/// Which bulletins a node trusts and whether it acts on them.
+#[derive(Debug, Clone)]
+pub struct BulletinConfig {
+	/// Keys allowed to issue bulletins. The control topic is only followed when set.
+	pub trusted_issuers: HashSet<PeerId>,
+	/// Apply verified bulletins automatically instead of only reporting them as events.
+	pub auto_apply: bool,
+	/// Topics the operator keeps following whatever the bulletins say.
+	pub pinned_topics: HashSet<String>,
+	/// Bulletins issued longer ago are discarded, for a restarted node, which forgot the last
+	/// bulletin of each issuer, not to apply an old one replayed to it.
+	pub max_age: Duration,
+}
+
+impl Default for BulletinConfig {
+	fn default() -> Self {
+		Self {
+			trusted_issuers: HashSet::new(),
+			auto_apply: false,
+			pinned_topics: HashSet::new(),
+			max_age: Duration::from_secs(60 * 60),
+		}
+	}
+}
+
+/// Verifies the inbound bulletins and keeps track of the ones applied.
+#[derive(Debug, Default)]
+pub struct BulletinBoard {
+	config: BulletinConfig,
+	latest: HashMap<PeerId, u64>,
+	banned_topics: HashSet<TopicHash>,
+}
+
+impl BulletinBoard {
+	pub fn new(config: BulletinConfig) -> Self {
+		Self { config, ..Default::default() }
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		!self.config.trusted_issuers.is_empty()
+	}
+
+	pub fn auto_apply(&self) -> bool {
+		self.config.auto_apply
+	}
+
+	/// Decode and verify a bulletin received on the control topic.
+	pub fn accept(&mut self, data: &[u8]) -> Result<(PeerId, Bulletin), BulletinError> {
+		let signed: SignedPayload = crate::types::deserialize_message(data)?;
+		let (issuer, bulletin): (PeerId, Bulletin) = signed.verify(SIGNING_DOMAIN)?;
+		if !self.config.trusted_issuers.contains(&issuer) {
+			return Err(BulletinError::UntrustedIssuer(issuer));
+		}
+		if self.latest.get(&issuer).is_some_and(|latest| bulletin.issued_at <= *latest) {
+			return Err(BulletinError::Stale(issuer));
+		}
+		let age = Duration::from_millis(now_ms().saturating_sub(bulletin.issued_at));
+		if age > self.config.max_age {
+			return Err(BulletinError::Expired(issuer, age));
+		}
+
+		self.latest.insert(issuer, bulletin.issued_at);
+		Ok((issuer, bulletin))
+	}
+
+	/// Record a topic ban. Returns `false` when the operator pinned the topic.
+	pub fn ban_topic(&mut self, topic: &str) -> bool {
+		if self.config.pinned_topics.contains(topic) {
+			return false;
+		}
+		self.banned_topics.insert(IdentTopic::new(topic).hash());
+		true
+	}
+
+	pub fn is_banned(&self, topic: &TopicHash) -> bool {
+		self.banned_topics.contains(topic)
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	fn board(issuer: &identity::Keypair) -> BulletinBoard {
+		BulletinBoard::new(BulletinConfig {
+			trusted_issuers: HashSet::from([issuer.public().to_peer_id()]),
+			auto_apply: true,
+			pinned_topics: HashSet::from(["everyone".to_string()]),
+			..Default::default()
+		})
+	}
+
+	/// A ban issued `issued_at` milliseconds into the last minute.
+	fn ban(topic: &str, issued_at: u64) -> Bulletin {
+		let issued_at = now_ms() - 60_000 + issued_at;
+		Bulletin { kind: BulletinKind::TopicBan { topic: topic.to_string() }, issued_at }
+	}
+
+	#[test]
+	fn test_bulletin_from_trusted_issuer() -> Result<()> {
+		let issuer = identity::Keypair::generate_ed25519();
+		let signed = ban("spam", 1).sign(&issuer)?;
+
+		let (peer_id, bulletin) = board(&issuer).accept(&serde_json::to_vec(&signed)?)?;
+
+		assert_eq!(peer_id, issuer.public().to_peer_id());
+		assert_eq!(bulletin.kind, ban("spam", 1).kind);
+		Ok(())
+	}
+
+	#[test]
+	fn test_bulletin_rejects_untrusted_and_tampered() -> Result<()> {
+		let issuer = identity::Keypair::generate_ed25519();
+		let mut board = board(&issuer);
+
+		let forged = ban("spam", 1).sign(&identity::Keypair::generate_ed25519())?;
+		assert!(matches!(
+			board.accept(&serde_json::to_vec(&forged)?),
+			Err(BulletinError::UntrustedIssuer(_))
+		));
+
+		let mut tampered = ban("spam", 1).sign(&issuer)?;
+		tampered.payload = serde_json::to_vec(&ban("capabilities", 1))?;
+		assert!(matches!(
+			board.accept(&serde_json::to_vec(&tampered)?),
+			Err(BulletinError::Signature(SignatureError::InvalidSignature))
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn test_bulletin_rejects_replays() -> Result<()> {
+		let issuer = identity::Keypair::generate_ed25519();
+		let mut board = board(&issuer);
+		let signed = serde_json::to_vec(&ban("spam", 2).sign(&issuer)?)?;
+		let older = serde_json::to_vec(&ban("other", 1).sign(&issuer)?)?;
+
+		board.accept(&signed)?;
+
+		assert!(matches!(board.accept(&signed), Err(BulletinError::Stale(_))));
+		assert!(matches!(board.accept(&older), Err(BulletinError::Stale(_))));
+		Ok(())
+	}
+
+	#[test]
+	fn test_bulletin_rejects_old_ones_after_a_restart() -> Result<()> {
+		let issuer = identity::Keypair::generate_ed25519();
+		let max_age = BulletinConfig::default().max_age.as_millis() as u64;
+		let old = Bulletin { issued_at: now_ms() - max_age - 1, ..ban("spam", 1) };
+		let old = serde_json::to_vec(&old.sign(&issuer)?)?;
+
+		// Nothing known of the issuer, as after a restart.
+		assert!(matches!(board(&issuer).accept(&old), Err(BulletinError::Expired(..))));
+		Ok(())
+	}
+
+	#[test]
+	fn test_bulletin_pinned_topics_are_never_banned() {
+		let mut board = board(&identity::Keypair::generate_ed25519());
+
+		assert!(!board.ban_topic("everyone"));
+		assert!(board.ban_topic("spam"));
+		assert!(board.is_banned(&IdentTopic::new("spam").hash()));
+		assert!(!board.is_banned(&IdentTopic::new("everyone").hash()));
+	}
+}
+
+// endregion: --- Tests
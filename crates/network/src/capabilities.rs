@@ -0,0 +1,157 @@
+use crate::hardware::HardwareProfile;
+
+/// Prefix of the identify agent version of the nodes of the swarm, followed by their version.
+pub const AGENT_VERSION_PREFIX: &str = "dasn/";
+
+/// Protocol features of this build, announced by default.
+pub const FEATURES: [&str; 4] = ["stream", "data", "challenge", "context"];
+
+/// What a node announces about itself through the identify agent version, e.g.
+/// `dasn/0.1.0 role=provider tasks=summarize,translate langs=en,fr features=stream,data cores=8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeCapabilities {
+	/// Role of the node, such as `bootstrap`, `provider` or `client`.
+	pub role: Option<String>,
+	/// Task types of the agents the node provides.
+	pub task_types: Vec<String>,
+	/// Languages the agents of the node answer in, e.g. `en`.
+	pub languages: Vec<String>,
+	/// Protocol features the node supports.
+	pub features: Vec<String>,
+	/// Hardware of the node and its local models, matched against the requirements of the task
+	/// proposals.
+	pub hardware: HardwareProfile,
+}
+
+impl Default for NodeCapabilities {
+	fn default() -> Self {
+		Self {
+			role: None,
+			task_types: Vec::new(),
+			languages: Vec::new(),
+			features: FEATURES.iter().map(ToString::to_string).collect(),
+			hardware: HardwareProfile::default(),
+		}
+	}
+}
+
+impl NodeCapabilities {
+	/// The identify agent version announcing the capabilities. Values that would not parse back,
+	/// holding spaces, commas or `=`, are left out.
+	pub fn to_agent_version(&self) -> String {
+		let mut agent_version = format!("{AGENT_VERSION_PREFIX}{}", env!("CARGO_PKG_VERSION"));
+		if let Some(role) = self.role.as_deref().filter(|role| is_compact(role)) {
+			agent_version.push_str(&format!(" role={role}"));
+		}
+		for (key, values) in
+			[("tasks", &self.task_types), ("langs", &self.languages), ("features", &self.features)]
+		{
+			let values: Vec<_> = values.iter().filter(|value| is_compact(value)).cloned().collect();
+			if !values.is_empty() {
+				agent_version.push_str(&format!(" {key}={}", values.join(",")));
+			}
+		}
+		for field in self.hardware.to_fields() {
+			agent_version.push_str(&format!(" {field}"));
+		}
+		agent_version
+	}
+
+	/// The capabilities announced by a node of the swarm, `None` for other agent versions.
+	/// Unknown keys are skipped, so newer nodes can announce more.
+	pub fn from_agent_version(agent_version: &str) -> Option<Self> {
+		let mut fields = agent_version.strip_prefix(AGENT_VERSION_PREFIX)?.split_whitespace();
+		fields.next()?;
+
+		let mut capabilities = Self {
+			role: None,
+			task_types: Vec::new(),
+			languages: Vec::new(),
+			features: Vec::new(),
+			hardware: HardwareProfile::default(),
+		};
+		for field in fields {
+			match field.split_once('=') {
+				Some(("role", role)) if !role.is_empty() => {
+					capabilities.role = Some(role.to_string())
+				},
+				Some(("tasks", tasks)) => capabilities.task_types = list(tasks),
+				Some(("langs", languages)) => capabilities.languages = list(languages),
+				Some(("features", features)) => capabilities.features = list(features),
+				Some((key, value)) => capabilities.hardware.parse_field(key, value),
+				None => {},
+			}
+		}
+		Some(capabilities)
+	}
+
+	pub fn supports(&self, feature: &str) -> bool {
+		self.features.iter().any(|supported| supported == feature)
+	}
+
+	pub fn speaks(&self, language: &str) -> bool {
+		self.languages.iter().any(|spoken| spoken.eq_ignore_ascii_case(language))
+	}
+}
+
+fn list(values: &str) -> Vec<String> {
+	values.split(',').filter(|value| !value.is_empty()).map(String::from).collect()
+}
+
+fn is_compact(value: &str) -> bool {
+	!value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == ',' || c == '=')
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_capabilities_round_trip_through_the_agent_version() {
+		let capabilities = NodeCapabilities {
+			role: Some("provider".to_string()),
+			task_types: vec!["summarize".to_string(), "not compact".to_string()],
+			languages: vec!["en".to_string(), "fr".to_string()],
+			..Default::default()
+		};
+		let agent_version = capabilities.to_agent_version();
+		assert!(agent_version.ends_with(
+			" role=provider tasks=summarize langs=en,fr features=stream,data,challenge,context"
+		));
+		let hardware = HardwareProfile {
+			cpu_cores: Some(8),
+			gpu: Some("NVIDIA A100".to_string()),
+			vram_mb: Some(40_960),
+			models: vec!["llama3:8b".to_string(), "mistral:7b".to_string()],
+			..Default::default()
+		};
+		let agent_version = NodeCapabilities { hardware, ..capabilities }.to_agent_version();
+		assert!(agent_version
+			.ends_with(" cores=8 vram=40960 gpu=NVIDIA-A100 models=llama3:8b,mistral:7b"));
+
+		let parsed = NodeCapabilities::from_agent_version(&agent_version).expect("A dasn node.");
+		assert_eq!(parsed.task_types, ["summarize"]);
+		assert!(parsed.supports("stream"));
+		assert!(parsed.speaks("FR") && !parsed.speaks("de"));
+		assert_eq!(parsed.hardware.gpu.as_deref(), Some("NVIDIA-A100"));
+		assert_eq!(parsed.hardware.models, ["llama3:8b", "mistral:7b"]);
+
+		let newer = NodeCapabilities::from_agent_version("dasn/9.0.0 region=eu features=")
+			.expect("A dasn node.");
+		assert_eq!(
+			newer,
+			NodeCapabilities {
+				role: None,
+				task_types: vec![],
+				languages: vec![],
+				features: vec![],
+				hardware: HardwareProfile::default(),
+			}
+		);
+		assert_eq!(NodeCapabilities::from_agent_version("rust-libp2p/0.45.0"), None);
+	}
+}
+
+// endregion: --- Tests
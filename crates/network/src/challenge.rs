@@ -0,0 +1,104 @@
+use std::collections::{HashMap, HashSet};
+
+use libp2p::{identity, PeerId};
+use thiserror::Error;
+
+use crate::signed::{SignatureError, SignedPayload};
+
+const SIGNING_DOMAIN: &[u8] = b"dasn-challenge:";
+
+#[derive(Error, Debug)]
+pub enum ChallengeError {
+	#[error("Invalid challenge answer: {0}")]
+	Signature(#[from] SignatureError),
+	#[error("Challenge answered by {0} instead of the requester")]
+	SignerMismatch(PeerId),
+	#[error("No pending challenge matches the answer")]
+	UnknownNonce,
+}
+
+/// Sign the nonce of a challenge with the node key, proving the node holds the key of its peer id.
+pub fn answer(key: &identity::Keypair, nonce: &[u8]) -> Result<SignedPayload, SignatureError> {
+	SignedPayload::sign(key, SIGNING_DOMAIN, &nonce)
+}
+
+/// Challenges sent to the requesters of expensive agents, and the requesters that answered them.
+/// A requester stays verified until it disconnects.
+#[derive(Debug, Default)]
+pub(crate) struct Challenges {
+	pending: HashMap<PeerId, Vec<u8>>,
+	verified: HashSet<PeerId>,
+}
+
+impl Challenges {
+	/// A new nonce for the peer to sign, replacing the one it did not answer yet if any.
+	pub(crate) fn issue(&mut self, peer: PeerId) -> Vec<u8> {
+		let nonce = rand::random::<[u8; 32]>().to_vec();
+		self.pending.insert(peer, nonce.clone());
+		nonce
+	}
+
+	pub(crate) fn verify(
+		&mut self,
+		peer: PeerId,
+		answer: &SignedPayload,
+	) -> Result<(), ChallengeError> {
+		let (signer, nonce): (PeerId, Vec<u8>) = answer.verify(SIGNING_DOMAIN)?;
+		if signer != peer {
+			return Err(ChallengeError::SignerMismatch(signer));
+		}
+		if self.pending.get(&peer) != Some(&nonce) {
+			return Err(ChallengeError::UnknownNonce);
+		}
+
+		self.pending.remove(&peer);
+		self.verified.insert(peer);
+		Ok(())
+	}
+
+	pub(crate) fn is_verified(&self, peer: &PeerId) -> bool {
+		self.verified.contains(peer)
+	}
+
+	pub(crate) fn forget(&mut self, peer: &PeerId) {
+		self.pending.remove(peer);
+		self.verified.remove(peer);
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_challenge_verifies_the_requester() -> Result<()> {
+		let key = identity::Keypair::generate_ed25519();
+		let peer = key.public().to_peer_id();
+		let mut challenges = Challenges::default();
+
+		let nonce = challenges.issue(peer);
+		let stale = answer(&key, &challenges.issue(PeerId::random()))?;
+		assert!(matches!(challenges.verify(peer, &stale), Err(ChallengeError::UnknownNonce)));
+		assert!(matches!(
+			challenges.verify(PeerId::random(), &answer(&key, &nonce)?),
+			Err(ChallengeError::SignerMismatch(_))
+		));
+		assert!(!challenges.is_verified(&peer));
+
+		challenges.verify(peer, &answer(&key, &nonce)?)?;
+
+		assert!(challenges.is_verified(&peer));
+		// Nonces are only answered once.
+		assert!(challenges.verify(peer, &answer(&key, &nonce)?).is_err());
+		challenges.forget(&peer);
+		assert!(!challenges.is_verified(&peer));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
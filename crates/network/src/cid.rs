@@ -0,0 +1,118 @@
+use std::{fmt, str::FromStr};
+
+use libp2p::multihash::Multihash;
+use multibase::Base;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use thiserror::Error;
+
+/// Multicodec code of the blake3 hash function.
+const BLAKE3: u64 = 0x1e;
+
+/// Content id: the blake3 hash of some content, displayed as its multihash in base32 multibase
+/// (`b...`). Contents are referred to by their id wherever they are stored, cached or logged.
+#[derive(
+	Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, SerializeDisplay, DeserializeFromStr,
+)]
+pub struct Cid([u8; 32]);
+
+#[derive(Error, Debug)]
+pub enum CidError {
+	#[error("Invalid multibase encoding: {0}")]
+	Multibase(#[from] multibase::Error),
+	#[error("Invalid multihash: {0}")]
+	Multihash(#[from] libp2p::multihash::Error),
+	#[error("Unsupported hash function {0:#x}, expected blake3")]
+	UnsupportedHash(u64),
+	#[error("Invalid blake3 digest of {0} bytes")]
+	InvalidDigest(usize),
+}
+
+impl Cid {
+	/// The id of the given content.
+	pub fn of(data: impl AsRef<[u8]>) -> Self {
+		Self(*blake3::hash(data.as_ref()).as_bytes())
+	}
+
+	pub fn digest(&self) -> &[u8; 32] {
+		&self.0
+	}
+
+	pub fn to_multihash(&self) -> Multihash<64> {
+		Multihash::wrap(BLAKE3, &self.0).expect("Blake3 digest to fit in a multihash.")
+	}
+}
+
+impl fmt::Display for Cid {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&multibase::encode(Base::Base32Lower, self.to_multihash().to_bytes()))
+	}
+}
+
+impl fmt::Debug for Cid {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Cid({self})")
+	}
+}
+
+impl FromStr for Cid {
+	type Err = CidError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (_, bytes) = multibase::decode(s)?;
+		let multihash = Multihash::<64>::from_bytes(&bytes)?;
+		if multihash.code() != BLAKE3 {
+			return Err(CidError::UnsupportedHash(multihash.code()));
+		}
+
+		let digest = multihash.digest();
+		Ok(Self(digest.try_into().map_err(|_| CidError::InvalidDigest(digest.len()))?))
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_cid_display_round_trip() -> Result<()> {
+		let cid = Cid::of(b"");
+
+		assert_eq!(
+			cid.digest().as_slice(),
+			blake3::hash(b"").as_bytes(),
+			"Expected the blake3 digest of the content"
+		);
+		assert!(cid.to_string().starts_with('b'));
+		assert_eq!(cid.to_string().parse::<Cid>()?, cid);
+		assert_ne!(Cid::of(b"other"), cid);
+		Ok(())
+	}
+
+	#[test]
+	fn test_cid_serde_as_string() -> Result<()> {
+		let cid = Cid::of(b"weights");
+
+		let json = serde_json::to_string(&cid)?;
+
+		assert_eq!(json, format!("\"{cid}\""));
+		assert_eq!(serde_json::from_str::<Cid>(&json)?, cid);
+		Ok(())
+	}
+
+	#[test]
+	fn test_cid_rejects_other_hashes() -> Result<()> {
+		let sha256 = Multihash::<64>::wrap(0x12, &[0; 32])?;
+		let encoded = multibase::encode(Base::Base32Lower, sha256.to_bytes());
+
+		assert!(matches!(encoded.parse::<Cid>(), Err(CidError::UnsupportedHash(0x12))));
+		assert!("not a cid".parse::<Cid>().is_err());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -1,27 +1,94 @@
-use std::{collections::HashSet, error::Error};
+use std::{
+	collections::{HashMap, HashSet},
+	error::Error,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
 use futures::{
 	channel::{mpsc, oneshot},
 	prelude::*,
 };
-use libp2p::{core::Multiaddr, request_response::ResponseChannel, PeerId};
+use libp2p::{
+	autonat::NatStatus, core::Multiaddr, kad::Quorum, metrics::Registry,
+	request_response::ResponseChannel, PeerId,
+};
+use prometheus_client::metrics::counter::Counter;
 
-use crate::types::{Command, LLMResponse};
+use crate::{
+	agent_card::{provider_key, AgentCard},
+	artifacts::{self, ArtifactError, ArtifactManifest},
+	auction::{AuctionConfig, AuctionWinner},
+	behaviour::TASKS_TOPIC,
+	bulletin::BulletinKind,
+	capabilities::NodeCapabilities,
+	cid::Cid,
+	commitment::{Agreement, Redundancy, ResultCommitment, Tally},
+	directory::{AgentFilter, AgentListing},
+	encryption::EncryptionError,
+	feedback::Feedback,
+	hedge::hedge,
+	history::{HistoryPage, HistoryQuery},
+	journal::JournalEntry,
+	labels::{LabelSelector, Labels},
+	latency::PeerLatency,
+	query::QueryHandle,
+	reputation::{self, reputation_key, Attestation, Outcome, ReputationLedger},
+	request::RequestHandle,
+	signed::SignedPayload,
+	stream::{now_ms, AgentStream, StreamError, StreamRequest, StreamResponse},
+	tasks::{TaskRecord, TaskStatus},
+	types::{
+		serialize_message, ArtifactResponse, BidResponse, Command, ConnectedPeer, ContextMessage,
+		KBucketInfo, LLMResponse, NetworkError, TaskProposal,
+	},
+	validation::{ResponseValidators, Violation},
+};
 
 #[derive(Clone)]
 pub struct Client {
 	pub sender: mpsc::Sender<Command>,
+	pub(crate) command_timeout: Duration,
+	pub(crate) max_artifact_size: u64,
+	pub(crate) rejected_commands: Counter,
+	pub(crate) metrics_registry: Arc<Mutex<Registry>>,
 }
 
 impl Client {
+	/// Queue a command for the event loop, failing with [`NetworkError::Overloaded`] when it is not
+	/// accepted within the command timeout and with [`NetworkError::ShuttingDown`] once the event
+	/// loop stopped.
+	async fn send_command(&mut self, command: Command) -> Result<(), Box<dyn Error + Send>> {
+		match tokio::time::timeout(self.command_timeout, self.sender.send(command)).await {
+			Ok(Ok(())) => Ok(()),
+			Ok(Err(_)) => Err(Box::new(NetworkError::ShuttingDown)),
+			Err(_) => {
+				tracing::warn!("Event loop busy, rejecting the command");
+				self.rejected_commands.inc();
+				Err(Box::new(NetworkError::Overloaded))
+			},
+		}
+	}
+
+	/// The Prometheus registry holding the swarm metrics. Applications may register their own
+	/// metrics in it to have them exported alongside.
+	pub fn metrics_registry(&self) -> Arc<Mutex<Registry>> {
+		self.metrics_registry.clone()
+	}
+
+	/// Encode the metrics in the OpenMetrics text format.
+	pub fn encode_metrics(&self) -> Result<String, std::fmt::Error> {
+		let mut encoded = String::new();
+		let registry = self.metrics_registry.lock().expect("Metrics registry not to be poisoned.");
+		prometheus_client::encoding::text::encode(&mut encoded, &registry)?;
+		Ok(encoded)
+	}
+
 	/// Listen for incoming connections on the given address.
 	pub async fn start_listening(&mut self, addr: Multiaddr) -> Result<(), Box<dyn Error + Send>> {
 		tracing::info!("Starting to listen on: {:?}", addr);
 		let (sender, receiver) = oneshot::channel();
-		self.sender
-			.send(Command::StartListening { addr, sender })
-			.await
-			.expect("Command receiver not to be dropped.");
+		self.send_command(Command::StartListening { addr, sender }).await?;
 		receiver.await.expect("Sender not to be dropped.")
 	}
 
@@ -33,48 +100,629 @@ impl Client {
 	) -> Result<(), Box<dyn Error + Send>> {
 		tracing::info!("Dialing peer: {:?}", peer_id);
 		let (sender, receiver) = oneshot::channel();
-		self.sender
-			.send(Command::Dial { peer_id, peer_addr, sender })
-			.await
-			.expect("Command receiver not to be dropped.");
+		self.send_command(Command::Dial { peer_id, peer_addr, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Check the given peer is reachable: open a new connection to it through the addresses it
+	/// advertised and wait for it to answer a ping. Returns the round-trip time of the ping.
+	pub async fn dial_back(&mut self, peer: PeerId) -> Result<Duration, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::DialBack { peer, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Close every connection to the given peer. Bootstrap peers and the rendezvous point are
+	/// redialed as usual, ban them to keep them away.
+	pub async fn disconnect(&mut self, peer: PeerId) -> Result<(), Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::Disconnect { peer, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Close the connections to the given peer and deny any new one, incoming or outgoing, for the
+	/// given duration.
+	pub async fn ban(
+		&mut self,
+		peer: PeerId,
+		duration: Duration,
+	) -> Result<(), Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::Ban { peer, duration, sender }).await?;
 		receiver.await.expect("Sender not to be dropped.")
 	}
 
-	/// Advertise the local node as the provider of the given agent on the DHT.
-	pub async fn start_providing(&mut self, agent_name: String) {
-		tracing::info!("Starting to provide: {:?}", agent_name);
+	/// Publish the signed card of the agent and advertise the local node as its provider on the
+	/// DHT. The returned handle resolves once the provider record is stored.
+	pub async fn start_providing(
+		&mut self,
+		card: AgentCard,
+	) -> Result<QueryHandle<()>, Box<dyn Error + Send>> {
+		tracing::info!("Starting to provide: {:?}", card.name);
 		let (sender, receiver) = oneshot::channel();
-		self.sender
-			.send(Command::StartProviding { agent_name, sender })
+		self.send_command(Command::StartProviding { agent_name: card.name.clone(), sender })
+			.await?;
+		let query = receiver.await.expect("Sender not to be dropped.")?;
+
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::PublishAgentCard { card, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")?;
+		Ok(query)
+	}
+
+	/// Publish a new version of the card of a provided agent, e.g. with its current price.
+	pub async fn update_agent_card(
+		&mut self,
+		card: AgentCard,
+	) -> Result<(), Box<dyn Error + Send>> {
+		tracing::info!("Updating the card of {:?}", card.name);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::PublishAgentCard { card, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Stop advertising the local node as a provider of the agent, and leave its topics. Its
+	/// provider record expires on the peers storing it.
+	pub async fn stop_providing(
+		&mut self,
+		agent_name: String,
+	) -> Result<(), Box<dyn Error + Send>> {
+		tracing::info!("Stopping to provide: {agent_name:?}");
+		self.send_command(Command::StopProviding { agent_name }).await
+	}
+
+	/// Get the card the given provider published for the given agent from the DHT, checking it
+	/// was signed by the provider.
+	pub async fn get_agent_card(
+		&mut self,
+		provider: PeerId,
+		agent_name: String,
+	) -> Result<AgentCard, Box<dyn Error + Send>> {
+		let data = self.get_record(provider_key(&agent_name, &provider), Quorum::One).await?;
+		AgentCard::verify(&agent_name, &provider, &data)
+			.map_err(|e| Box::new(e) as Box<dyn Error + Send>)
+	}
+
+	/// Keep the providers that published a card for the given agent signed with their own key,
+	/// along with their card. Anyone can announce itself as the provider of any agent, the result
+	/// of [`Client::get_providers`] should go through this before being called.
+	pub async fn verified_providers(
+		&mut self,
+		agent_name: &str,
+		providers: HashSet<PeerId>,
+	) -> HashMap<PeerId, AgentCard> {
+		let cards = providers.into_iter().map(|provider| {
+			let mut client = self.clone();
+			let agent_name = agent_name.to_string();
+			async move { (provider, client.get_agent_card(provider, agent_name).await) }
+		});
+
+		future::join_all(cards)
 			.await
-			.expect("Command receiver not to be dropped.");
-		receiver.await.expect("Sender not to be dropped.");
+			.into_iter()
+			.filter_map(|(provider, card)| match card {
+				Ok(card) => Some((provider, card)),
+				Err(e) => {
+					tracing::warn!("Ignoring unverified provider {provider} of {agent_name}: {e}");
+					None
+				},
+			})
+			.collect()
 	}
 
-	/// Find the providers for the given file on the DHT.
-	pub async fn get_providers(&mut self, agent_name: String) -> HashSet<PeerId> {
+	/// Find the providers of the given agent on the DHT. The returned handle reports the progress
+	/// of the lookup until it resolves with the first providers found, which are not verified.
+	pub async fn get_providers(
+		&mut self,
+		agent_name: String,
+	) -> Result<QueryHandle<HashSet<PeerId>>, Box<dyn Error + Send>> {
 		tracing::info!("Getting providers for: {:?}", agent_name);
 		let (sender, receiver) = oneshot::channel();
-		self.sender
-			.send(Command::GetProviders { agent_name, sender })
-			.await
-			.expect("Command receiver not to be dropped.");
+		self.send_command(Command::GetProviders { agent_name, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Find the peers providing the given DHT key, e.g. `/asn/agent/<name>` for the providers of an
+	/// agent, unlike [`Self::get_providers`] taking the key as is.
+	pub async fn get_key_providers(
+		&mut self,
+		key: String,
+	) -> Result<QueryHandle<HashSet<PeerId>>, Box<dyn Error + Send>> {
+		tracing::info!("Getting providers of: {:?}", key);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::GetKeyProviders { key, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Publish a small value (pricing, schema, endpoints...) under the given key on the DHT. The
+	/// record is stored locally and succeeds once `quorum` peers stored it as well.
+	pub async fn put_record(
+		&mut self,
+		key: String,
+		value: Vec<u8>,
+		quorum: Quorum,
+	) -> Result<(), Box<dyn Error + Send>> {
+		tracing::info!("Putting record: {:?}", key);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::PutRecord { key, value, quorum, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Get the value published under the given key on the DHT once `quorum` copies of the record
+	/// were found. `Quorum::Majority` and `Quorum::All` are counted against the replication factor.
+	pub async fn get_record(
+		&mut self,
+		key: String,
+		quorum: Quorum,
+	) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+		tracing::info!("Getting record: {:?}", key);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::GetRecord { key, quorum, sender }).await?;
 		receiver.await.expect("Sender not to be dropped.")
 	}
 
-	/// Request the content of the given file from the given peer.
+	/// Find the peers closest to the given key in the DHT, i.e. the ones a record under that key is
+	/// replicated to.
+	pub async fn get_closest_peers(
+		&mut self,
+		key: String,
+	) -> Result<Vec<PeerId>, Box<dyn Error + Send>> {
+		tracing::info!("Getting closest peers to: {:?}", key);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::GetClosestPeers { key, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Snapshot of the non-empty buckets of the local Kademlia routing table.
+	pub async fn kbuckets(&mut self) -> Result<Vec<KBucketInfo>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::KBuckets { sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Addresses the node listens on, ending with its `/p2p/<peer id>`.
+	pub async fn listen_addresses(&mut self) -> Result<Vec<Multiaddr>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::ListenAddresses { sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Confirmed addresses the node is reachable at from the outside, ending with its
+	/// `/p2p/<peer id>`.
+	pub async fn external_addresses(&mut self) -> Result<Vec<Multiaddr>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::ExternalAddresses { sender }).await?;
+		answer(receiver).await
+	}
+
+	/// The peers the node is connected to, with what they announced through identify.
+	pub async fn connected_peers(&mut self) -> Result<Vec<ConnectedPeer>, Box<dyn Error + Send>> {
+		self.list_peers(LabelSelector::default()).await
+	}
+
+	/// The connected peers whose labels match `selector`, e.g. the GPU nodes of a fleet.
+	pub async fn list_peers(
+		&mut self,
+		selector: LabelSelector,
+	) -> Result<Vec<ConnectedPeer>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::GetConnectedPeers { selector, sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Attach a label to a peer, replacing its previous value. Labels are kept while the node
+	/// runs, whether or not the peer is connected.
+	pub async fn tag_peer(
+		&mut self,
+		peer: PeerId,
+		key: impl Into<String>,
+		value: impl Into<String>,
+	) -> Result<(), Box<dyn Error + Send>> {
+		let (key, value) = (key.into(), value.into());
+		self.send_command(Command::TagPeer { peer, key, value }).await
+	}
+
+	pub async fn untag_peer(
+		&mut self,
+		peer: PeerId,
+		key: impl Into<String>,
+	) -> Result<(), Box<dyn Error + Send>> {
+		self.send_command(Command::UntagPeer { peer, key: key.into() }).await
+	}
+
+	/// Time since the last presence beacon of the peer, unset when none arrived.
+	pub async fn last_seen(
+		&mut self,
+		peer: PeerId,
+	) -> Result<Option<Duration>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::LastSeen { peer, sender }).await?;
+		answer(receiver).await
+	}
+
+	/// The providers of the agent still online, dropping the ones whose presence lapsed before
+	/// their DHT records expire. Providers that never sent a beacon are kept.
+	pub async fn online_providers(
+		&mut self,
+		agent_name: String,
+		providers: HashSet<PeerId>,
+	) -> Result<HashSet<PeerId>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::OnlineProviders { agent_name, providers, sender })
+			.await?;
+		answer(receiver).await
+	}
+
+	/// The agents of the capability directory matching `filter`, cheapest first, as announced by
+	/// their providers on the capabilities topic. No DHT query is made.
+	pub async fn find_agents(
+		&mut self,
+		filter: AgentFilter,
+	) -> Result<Vec<AgentListing>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::FindAgents { filter, sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Pull the capability announcements `peer` knows newer than the ones of the directory,
+	/// returning how many were added. Done with the first peer supporting it on startup.
+	pub async fn sync_directory(&mut self, peer: PeerId) -> Result<usize, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::SyncDirectory { peer, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// A snapshot of the peers connected, the relays among them and the capability directory,
+	/// signed with the key of the node for bootstrap nodes to publish it.
+	pub async fn network_snapshot(&mut self) -> Result<SignedPayload, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::NetworkSnapshot { sender }).await?;
+		receiver
+			.await
+			.expect("Sender not to be dropped.")
+			.map_err(|e| Box::new(e) as Box<dyn Error + Send>)
+	}
+
+	/// Labels attached to a peer.
+	pub async fn peer_labels(&mut self, peer: PeerId) -> Result<Labels, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::PeerLabels { peer, sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Capabilities announced by the connected nodes of the swarm through identify.
+	pub async fn peer_capabilities(
+		&mut self,
+	) -> Result<HashMap<PeerId, NodeCapabilities>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::PeerCapabilities { sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Round-trip times of the last pings of a connected peer, once it answered one.
+	pub async fn peer_latency(
+		&mut self,
+		peer: PeerId,
+	) -> Result<Option<PeerLatency>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::PeerLatency { peer, sender }).await?;
+		answer(receiver).await
+	}
+
+	/// The providers whose circuit lets a request through, skipping the ones that kept failing
+	/// until their cooldown elapsed. The probe of a provider whose cooldown elapsed only starts
+	/// with the next request sent to it, the requests sent to a provider whose circuit is open
+	/// failing with [`NetworkError::CircuitOpen`].
+	pub async fn admissible_providers(
+		&mut self,
+		providers: impl IntoIterator<Item = PeerId>,
+	) -> Result<HashSet<PeerId>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::AdmissibleProviders {
+			providers: providers.into_iter().collect(),
+			sender,
+		})
+		.await?;
+		answer(receiver).await
+	}
+
+	/// A page of the gossip messages accepted lately, oldest first.
+	pub async fn gossip_history(
+		&mut self,
+		query: HistoryQuery,
+	) -> Result<HistoryPage, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::GossipHistory { query, sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Whether the node is reachable from the outside, as last determined by AutoNAT.
+	pub async fn nat_status(&mut self) -> Result<NatStatus, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::NatStatus { sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Request the given agent from the given peer and wait for its response.
 	pub async fn request_agent(
 		&mut self,
 		peer: PeerId,
 		agent_name: String,
 		message: String,
 	) -> Result<Vec<u8>, Box<dyn Error + Send>> {
-		tracing::info!("Requesting agent: {:?} from peer: {:?}", agent_name, peer);
+		self.request_agent_with_context(peer, agent_name, message, Vec::new()).await
+	}
+
+	/// Request the given agent with the prior messages of the conversation, oldest first.
+	pub async fn request_agent_with_context(
+		&mut self,
+		peer: PeerId,
+		agent_name: String,
+		message: String,
+		context: Vec<ContextMessage>,
+	) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+		self.send_agent_request(peer, agent_name, message, context)
+			.await?
+			.response()
+			.await
+	}
+
+	/// Request the given agent from the first of the providers, ordered best first, and send a
+	/// duplicate to the next one whenever no response arrived within `delay`. Returns the first
+	/// response, the request still in flight is cancelled, along with the violations of
+	/// `validators` to warn about. Failed requests, and responses the validators send to another
+	/// provider, fail over right away.
+	#[allow(clippy::too_many_arguments)]
+	pub async fn hedged_request(
+		&mut self,
+		providers: Vec<PeerId>,
+		agent_name: String,
+		message: String,
+		context: Vec<ContextMessage>,
+		language: Option<String>,
+		delay: Duration,
+		validators: &ResponseValidators,
+	) -> Result<(Vec<u8>, Vec<Violation>), Box<dyn Error + Send>> {
+		let call = |peer| {
+			let mut client = self.clone();
+			let (agent_name, message, context, language) =
+				(agent_name.clone(), message.clone(), context.clone(), language.clone());
+			let validators = validators.clone();
+			// Dropping the handle of the losing request cancels it.
+			async move {
+				let response = client
+					.send_agent_request_in(peer, agent_name, message.clone(), context, language)
+					.await?
+					.response()
+					.await?;
+				match validators.check(&message, &response) {
+					Ok(warnings) => Ok(Ok((response, warnings))),
+					Err(invalid) if invalid.retry => {
+						tracing::warn!("Asking another provider than {peer}: {invalid}");
+						Err(Box::new(invalid) as Box<dyn Error + Send>)
+					},
+					Err(invalid) => Ok(Err(invalid)),
+				}
+			}
+		};
+		match hedge(providers, delay, call).await {
+			Some(Ok(Ok(response))) => Ok(response),
+			Some(Ok(Err(invalid))) => Err(Box::new(invalid)),
+			Some(Err(e)) => Err(e),
+			None => Err(Box::new(NetworkError::NoProviders(agent_name))),
+		}
+	}
+
+	/// Send a request of the journal again, to its providers in turn until one answers, with its
+	/// attachments, which this node must serve meanwhile. Returns the provider that answered along
+	/// with its response. A provider that answered an earlier attempt repeats its answer.
+	pub async fn resume_request(
+		&mut self,
+		entry: &JournalEntry,
+	) -> Result<(PeerId, Vec<u8>), Box<dyn Error + Send>> {
+		let mut last_error = None;
+		for &peer in &entry.providers {
+			let response =
+				async { self.send_journaled_request(peer, entry).await?.response().await };
+			match response.await {
+				Ok(response) => return Ok((peer, response)),
+				Err(e) => {
+					tracing::warn!("Provider {peer} failed to answer request {}: {e}", entry.id);
+					last_error = Some(e);
+				},
+			}
+		}
+		Err(last_error
+			.unwrap_or_else(|| Box::new(NetworkError::NoProviders(entry.agent_name.clone()))))
+	}
+
+	/// Send an attempt at the request of the journal `entry`, without waiting for its response.
+	/// The id of the entry goes along as the id of the job, for the provider to answer every
+	/// attempt at the request alike, computing the answer once.
+	pub async fn send_journaled_request(
+		&mut self,
+		peer: PeerId,
+		entry: &JournalEntry,
+	) -> Result<RequestHandle, Box<dyn Error + Send>> {
+		tracing::info!(
+			"Requesting agent: {:?} from peer: {:?}, job {}",
+			entry.agent_name,
+			peer,
+			entry.id
+		);
 		let (sender, receiver) = oneshot::channel();
-		self.sender
-			.send(Command::RequestAgent { agent_name, message, peer, sender })
+		self.send_command(Command::RequestAgent {
+			agent_name: entry.agent_name.clone(),
+			message: entry.message.clone(),
+			context: entry.context.clone(),
+			encrypt_to: None,
+			language: entry.language.clone(),
+			attachments: entry.attachments.clone(),
+			job: Some(entry.id),
+			peer,
+			sender,
+		})
+		.await?;
+		receiver.await.expect("Sender not be dropped.")
+	}
+
+	/// Send a request for the given agent to the given peer without waiting for its response, so
+	/// it can be cancelled.
+	pub async fn send_agent_request(
+		&mut self,
+		peer: PeerId,
+		agent_name: String,
+		message: String,
+		context: Vec<ContextMessage>,
+	) -> Result<RequestHandle, Box<dyn Error + Send>> {
+		self.send_agent_request_in(peer, agent_name, message, context, None).await
+	}
+
+	/// Like [`Self::send_agent_request`], asking for the answer in the given language, e.g. `fr`,
+	/// for providers running a profile per language.
+	pub async fn send_agent_request_in(
+		&mut self,
+		peer: PeerId,
+		agent_name: String,
+		message: String,
+		context: Vec<ContextMessage>,
+		language: Option<String>,
+	) -> Result<RequestHandle, Box<dyn Error + Send>> {
+		self.send_agent_request_attached(peer, agent_name, message, context, language, Vec::new())
 			.await
-			.expect("Command receiver not to be dropped.");
+	}
+
+	/// Like [`Self::send_agent_request_in`], with artifacts attached to the message. The provider
+	/// fetches them from this node, which serves them from its [`crate::ArtifactStore`] meanwhile.
+	pub async fn send_agent_request_attached(
+		&mut self,
+		peer: PeerId,
+		agent_name: String,
+		message: String,
+		context: Vec<ContextMessage>,
+		language: Option<String>,
+		attachments: Vec<Cid>,
+	) -> Result<RequestHandle, Box<dyn Error + Send>> {
+		tracing::info!("Requesting agent: {:?} from peer: {:?}", agent_name, peer);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::RequestAgent {
+			agent_name,
+			message,
+			context,
+			encrypt_to: None,
+			language,
+			attachments,
+			job: None,
+			peer,
+			sender,
+		})
+		.await?;
+		receiver.await.expect("Sender not be dropped.")
+	}
+
+	/// Send a request for the agent of the given card, encrypted end to end to the key the card
+	/// advertises so that the relays in between only see the agent name and the language.
+	pub async fn send_encrypted_agent_request(
+		&mut self,
+		peer: PeerId,
+		card: &AgentCard,
+		message: String,
+		context: Vec<ContextMessage>,
+		language: Option<String>,
+	) -> Result<RequestHandle, Box<dyn Error + Send>> {
+		let Some(key) = card.encryption_key else {
+			return Err(Box::new(EncryptionError::NoKey(card.name.clone())));
+		};
+		tracing::info!("Requesting agent: {:?} from peer: {:?}, encrypted", card.name, peer);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::RequestAgent {
+			agent_name: card.name.clone(),
+			message,
+			context,
+			encrypt_to: Some(key),
+			language,
+			attachments: Vec::new(),
+			job: None,
+			peer,
+			sender,
+		})
+		.await?;
+		receiver.await.expect("Sender not be dropped.")
+	}
+
+	/// Stop waiting for the response of the given request, which is discarded if it still
+	/// arrives. The provider may still compute it.
+	pub async fn cancel_request(&mut self, request: RequestHandle) {
+		let request_id = request.id();
+		// The request is discarded anyway once the network shut down.
+		let _ = self.sender.send(Command::CancelRequest { request_id }).await;
+	}
+
+	/// Open a streamed request to the given peer. The provider generates at most `window` tokens
+	/// ahead of the ones consumed, and stops generating once `timeout` elapsed. The challenge of a
+	/// provider requiring one is answered before opening the stream again.
+	pub async fn request_agent_stream(
+		&mut self,
+		peer: PeerId,
+		agent_name: String,
+		message: String,
+		window: u32,
+		timeout: Duration,
+	) -> Result<AgentStream, Box<dyn Error + Send>> {
+		tracing::info!("Requesting agent stream: {:?} from peer: {:?}", agent_name, peer);
+		let deadline = now_ms() + timeout.as_millis() as u64;
+		let request = StreamRequest::Open { agent_name, message, window, deadline };
+		let mut response = self.stream_request(peer, request.clone()).await?;
+		if let StreamResponse::Challenge { nonce } = response {
+			self.answer_challenge(peer, nonce).await?;
+			response = self.stream_request(peer, request).await?;
+		}
+
+		match response {
+			StreamResponse::Opened { stream_id } => {
+				Ok(AgentStream::new(self.clone(), peer, stream_id, timeout))
+			},
+			StreamResponse::Busy { limit } => Err(Box::new(NetworkError::ProviderBusy { limit })),
+			StreamResponse::Error(e) => Err(Box::new(StreamError::Rejected(e))),
+			response => Err(Box::new(StreamError::UnexpectedResponse(response))),
+		}
+	}
+
+	/// Prove to the given provider that the local node holds the key of its peer id, which the
+	/// agents whose card sets `challenge_above` require before accepting large requests. The
+	/// provider trusts the node until it disconnects.
+	pub async fn authenticate(&mut self, peer: PeerId) -> Result<(), Box<dyn Error + Send>> {
+		match self.stream_request(peer, StreamRequest::Challenge).await? {
+			StreamResponse::Challenge { nonce } => self.answer_challenge(peer, nonce).await,
+			StreamResponse::Error(e) => Err(Box::new(StreamError::Rejected(e))),
+			response => Err(Box::new(StreamError::UnexpectedResponse(response))),
+		}
+	}
+
+	async fn answer_challenge(
+		&mut self,
+		peer: PeerId,
+		nonce: Vec<u8>,
+	) -> Result<(), Box<dyn Error + Send>> {
+		tracing::info!("Answering the challenge of {peer}");
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::AnswerChallenge { nonce, sender }).await?;
+		let answer = receiver.await.expect("Sender not to be dropped.")?;
+
+		match self.stream_request(peer, StreamRequest::Authenticate { answer }).await? {
+			StreamResponse::Authenticated => Ok(()),
+			StreamResponse::Error(e) => Err(Box::new(StreamError::Rejected(e))),
+			response => Err(Box::new(StreamError::UnexpectedResponse(response))),
+		}
+	}
+
+	pub(crate) async fn stream_request(
+		&mut self,
+		peer: PeerId,
+		request: StreamRequest,
+	) -> Result<StreamResponse, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::StreamRequest { peer, request, sender }).await?;
 		receiver.await.expect("Sender not be dropped.")
 	}
 
@@ -85,10 +733,129 @@ impl Client {
 		channel: ResponseChannel<LLMResponse>,
 	) {
 		tracing::info!("Responding with LLM output.");
-		self.sender
-			.send(Command::RespondLLM { llm_output, channel })
-			.await
-			.expect("Command receiver not to be dropped.");
+		// Answers still being computed when the network shut down are dropped.
+		if self.sender.send(Command::RespondLLM { llm_output, channel }).await.is_err() {
+			tracing::warn!("Network shut down, dropping the LLM output.");
+		}
+	}
+
+	/// Request an artifact from the given peer over the data plane, which allows large payloads
+	/// and slow transfers without holding up the agent requests.
+	pub async fn request_artifact(
+		&mut self,
+		peer: PeerId,
+		name: String,
+	) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+		tracing::info!("Requesting artifact: {:?} from peer: {:?}", name, peer);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::RequestArtifact { name, peer, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Fetch the artifact `cid` from the given peer, its manifest first and then its chunks one at
+	/// a time, each checked against its content id. Artifacts whose manifest exceeds the maximum
+	/// artifact size of the configuration are refused before fetching any chunk.
+	pub async fn fetch_artifact(
+		&mut self,
+		peer: PeerId,
+		cid: &Cid,
+	) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+		let manifest = self.request_artifact(peer, artifacts::manifest_name(cid)).await?;
+		if manifest.is_empty() {
+			return Err(Box::new(ArtifactError::NotFound(*cid)));
+		}
+		let manifest: ArtifactManifest = serde_json::from_slice(&manifest)
+			.map_err(|e| Box::new(ArtifactError::from(e)) as Box<dyn Error + Send>)?;
+		let max_chunks = self.max_artifact_size.div_ceil(artifacts::CHUNK_SIZE as u64);
+		if manifest.size > self.max_artifact_size || manifest.chunks.len() as u64 > max_chunks {
+			return Err(Box::new(ArtifactError::TooLarge {
+				cid: *cid,
+				size: manifest.size,
+				chunks: manifest.chunks.len(),
+				limit: self.max_artifact_size,
+			}));
+		}
+		// The manifest is not trusted to size the buffer, only the chunks received are.
+		let mut data = Vec::new();
+		for chunk in &manifest.chunks {
+			let content = self.request_artifact(peer, artifacts::chunk_name(chunk)).await?;
+			if content.is_empty() {
+				return Err(Box::new(ArtifactError::NotFound(*chunk)));
+			}
+			if Cid::of(&content) != *chunk {
+				return Err(Box::new(ArtifactError::Corrupt(*chunk)));
+			}
+			if data.len() + content.len() > manifest.size as usize {
+				return Err(Box::new(ArtifactError::Corrupt(*cid)));
+			}
+			data.extend(content);
+		}
+		if Cid::of(&data) != *cid {
+			return Err(Box::new(ArtifactError::Corrupt(*cid)));
+		}
+		Ok(data)
+	}
+
+	/// Fetch the artifact `cid` from `origin`, or from the mirrors advertising it on the DHT when
+	/// the origin fails, in turn until one serves it.
+	pub async fn fetch_artifact_or_mirrors(
+		&mut self,
+		origin: PeerId,
+		cid: &Cid,
+	) -> Result<Vec<u8>, Box<dyn Error + Send>> {
+		let error = match self.fetch_artifact(origin, cid).await {
+			Ok(data) => return Ok(data),
+			Err(e) => e,
+		};
+		tracing::warn!("Failed to fetch artifact {cid} from {origin}, trying its mirrors: {error}");
+		let mirrors = self.get_artifact_providers(cid).await?.result().await?;
+		for mirror in mirrors.into_iter().filter(|mirror| *mirror != origin) {
+			match self.fetch_artifact(mirror, cid).await {
+				Ok(data) => return Ok(data),
+				Err(e) => {
+					tracing::warn!("Failed to fetch artifact {cid} from mirror {mirror}: {e}")
+				},
+			}
+		}
+		Err(error)
+	}
+
+	/// Advertise the local node on the DHT as a mirror of the artifact `cid`, served from its
+	/// store. The returned handle resolves once the provider record is stored.
+	pub async fn provide_artifact(
+		&mut self,
+		cid: Cid,
+	) -> Result<QueryHandle<()>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::ProvideArtifact { cid, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Stop advertising the local node as a mirror of the artifact `cid`.
+	pub async fn stop_providing_artifact(&mut self, cid: Cid) -> Result<(), Box<dyn Error + Send>> {
+		self.send_command(Command::StopProvidingArtifact { cid }).await
+	}
+
+	/// Find the mirrors of the artifact `cid` on the DHT.
+	pub async fn get_artifact_providers(
+		&mut self,
+		cid: &Cid,
+	) -> Result<QueryHandle<HashSet<PeerId>>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::GetArtifactProviders { cid: *cid, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Respond with the content of an artifact to the given request.
+	pub async fn respond_artifact(
+		&mut self,
+		data: Vec<u8>,
+		channel: ResponseChannel<ArtifactResponse>,
+	) {
+		tracing::info!("Responding with an artifact of {} bytes.", data.len());
+		if self.sender.send(Command::RespondArtifact { data, channel }).await.is_err() {
+			tracing::warn!("Network shut down, dropping the artifact.");
+		}
 	}
 
 	/// Gossip the given message in the given topic.
@@ -98,10 +865,277 @@ impl Client {
 		message: String,
 	) -> Result<(), Box<dyn Error + Send>> {
 		tracing::info!("Gossiping message: [{topic}] {message}");
-		self.sender
-			.send(Command::GossipMessage { topic, message })
-			.await
-			.expect("Command receiver not to be dropped.");
+		self.send_command(Command::GossipMessage { topic, message }).await?;
 		Ok(())
 	}
+
+	/// Subscribe to `topic`, returning whether the node was not subscribed yet. Topics banned by
+	/// a bulletin are refused.
+	pub async fn subscribe(&mut self, topic: String) -> Result<bool, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::Subscribe { topic, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Unsubscribe from `topic`, returning whether the node was subscribed.
+	pub async fn unsubscribe(&mut self, topic: String) -> Result<bool, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::Unsubscribe { topic, sender }).await?;
+		answer(receiver).await
+	}
+
+	/// The gossip topics the node is subscribed to.
+	pub async fn topics(&mut self) -> Result<Vec<String>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::Topics { sender }).await?;
+		answer(receiver).await
+	}
+
+	/// Dial the bootstrap peers and bootstrap the DHT through them again, as done at startup.
+	pub async fn bootstrap(&mut self) -> Result<(), Box<dyn Error + Send>> {
+		self.send_command(Command::Bootstrap).await
+	}
+
+	/// Gossip a task proposal on the tasks topic. The peers bidding on it send their bids to the
+	/// node, delivered as [`crate::Event::InboundBid`].
+	pub async fn propose_task(
+		&mut self,
+		proposal: &TaskProposal,
+	) -> Result<(), Box<dyn Error + Send>> {
+		let message =
+			serialize_message(proposal).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+		let message = String::from_utf8(message).expect("JSON to be valid UTF-8");
+		self.gossip(TASKS_TOPIC.to_string(), message).await
+	}
+
+	/// Send a bid to the proposer of a task, returning once the proposer acknowledged it.
+	pub async fn submit_bid(
+		&mut self,
+		proposer: PeerId,
+		bid: BidResponse,
+	) -> Result<(), Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::SubmitBid { peer: proposer, bid, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Rate an exchange with an agent of `provider`, returning once the provider acknowledged it.
+	pub async fn send_feedback(
+		&mut self,
+		provider: PeerId,
+		feedback: Feedback,
+	) -> Result<(), Box<dyn Error + Send>> {
+		tracing::info!("Rating {} of {provider} {}", feedback.receipt, feedback.rating);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::SendFeedback { peer: provider, feedback, sender })
+			.await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Sign the outcome of a task `provider` worked on for the node and append it to the reputation
+	/// ledger of the provider on the DHT. Concurrent attestations on the same provider may
+	/// overwrite each other, the ledger keeping the last one written.
+	pub async fn attest(
+		&mut self,
+		provider: PeerId,
+		task_id: impl Into<String>,
+		outcome: Outcome,
+	) -> Result<(), Box<dyn Error + Send>> {
+		let attestation =
+			Attestation { provider, task_id: task_id.into(), outcome, issued_at: now_ms() };
+		tracing::info!("Attesting task {} of {provider} {outcome}", attestation.task_id);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::SignAttestation { attestation, sender }).await?;
+		let signed = receiver.await.expect("Sender not to be dropped.")?;
+
+		let mut ledger = self.reputation(provider).await?;
+		ledger.add(signed).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+		let data = ledger.encode().map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+		self.put_record(reputation_key(&provider), data, Quorum::One).await
+	}
+
+	/// The reputation ledger of `provider` on the DHT, empty when none was published.
+	pub async fn reputation(
+		&mut self,
+		provider: PeerId,
+	) -> Result<ReputationLedger, Box<dyn Error + Send>> {
+		match self.get_record(reputation_key(&provider), Quorum::One).await {
+			Ok(data) => ReputationLedger::decode(provider, &data)
+				.map_err(|e| Box::new(e) as Box<dyn Error + Send>),
+			Err(e) if reputation::is_not_found(e.as_ref()) => Ok(ReputationLedger::new(provider)),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// The reputation scores of `providers`, see [`ReputationLedger::score`]. Providers whose
+	/// ledger could not be read are left out.
+	pub async fn reputations(
+		&mut self,
+		providers: impl IntoIterator<Item = PeerId>,
+	) -> HashMap<PeerId, f64> {
+		let ledgers = providers.into_iter().map(|provider| {
+			let mut client = self.clone();
+			async move { (provider, client.reputation(provider).await) }
+		});
+
+		future::join_all(ledgers)
+			.await
+			.into_iter()
+			.filter_map(|(provider, ledger)| match ledger {
+				Ok(ledger) => Some((provider, ledger.score())),
+				Err(e) => {
+					tracing::warn!("Failed to read the reputation of {provider}: {e}");
+					None
+				},
+			})
+			.collect()
+	}
+
+	/// Auction a task: gossip its proposal, collect the bids sent back during the window of the
+	/// auction and award the task to the best of them by its policy. Returns the winner, already
+	/// notified with [`crate::Event::TaskAwarded`], or None when no acceptable bid came in. The
+	/// progress is reported as [`crate::Event::Auction`].
+	pub async fn run_auction(
+		&mut self,
+		proposal: TaskProposal,
+		config: AuctionConfig,
+	) -> Result<Option<AuctionWinner>, Box<dyn Error + Send>> {
+		tracing::info!("Auctioning task {} for {:?}", proposal.task_id, config.window);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::RunAuction { proposal, config, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// The status of a task auctioned or won by the node.
+	pub async fn get_task_status(
+		&mut self,
+		task_id: impl Into<String>,
+	) -> Result<Option<TaskRecord>, Box<dyn Error + Send>> {
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::GetTaskStatus { task_id: task_id.into(), sender })
+			.await?;
+		answer(receiver).await
+	}
+
+	/// Report the progress of a task won by the node to its owner, returning once the owner
+	/// acknowledged it. The owner of a task may update it too, e.g. to fail it.
+	pub async fn update_task_status(
+		&mut self,
+		task_id: impl Into<String>,
+		status: TaskStatus,
+	) -> Result<(), Box<dyn Error + Send>> {
+		let task_id = task_id.into();
+		tracing::info!("Task {task_id} is now {status:?}");
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::UpdateTaskStatus { task_id, status, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Commit to the result of a task won by the node: send the owner of the task the signed hash
+	/// of the result, returning once the owner acknowledged it. The result itself is sent with
+	/// [`Client::reveal_result`].
+	pub async fn commit_result(
+		&mut self,
+		task_id: impl Into<String>,
+		result: &[u8],
+	) -> Result<(), Box<dyn Error + Send>> {
+		let commitment = ResultCommitment::of(task_id, result);
+		tracing::info!("Committing to result {} of task {}", commitment.result, commitment.task_id);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::CommitResult { commitment, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Reveal the result of a task the node committed to. The owner completes the task when the
+	/// result matches the commitment, and fails it otherwise with
+	/// [`NetworkError::ResultRefused`].
+	pub async fn reveal_result(
+		&mut self,
+		task_id: impl Into<String>,
+		result: Vec<u8>,
+	) -> Result<(), Box<dyn Error + Send>> {
+		let task_id = task_id.into();
+		tracing::info!("Revealing the result of task {task_id}");
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::RevealResult { task_id, result, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+
+	/// Request the given agent from the first `redundancy.providers` of the providers at once,
+	/// and return the answer `redundancy.required` of them agree on, whitespace around it aside.
+	/// The requests still in flight are cancelled once they agree. Fails with
+	/// [`NetworkError::NoAgreement`] when too few answers agree.
+	pub async fn redundant_request(
+		&mut self,
+		providers: Vec<PeerId>,
+		agent_name: String,
+		message: String,
+		context: Vec<ContextMessage>,
+		language: Option<String>,
+		redundancy: Redundancy,
+	) -> Result<Agreement, Box<dyn Error + Send>> {
+		if providers.is_empty() {
+			return Err(Box::new(NetworkError::NoProviders(agent_name)));
+		}
+		let mut requests: stream::FuturesUnordered<_> = providers
+			.into_iter()
+			.take(redundancy.providers)
+			.map(|peer| {
+				let mut client = self.clone();
+				let (agent_name, message, context, language) =
+					(agent_name.clone(), message.clone(), context.clone(), language.clone());
+				async move {
+					let response = client
+						.send_agent_request_in(peer, agent_name, message, context, language)
+						.await;
+					(peer, response)
+				}
+			})
+			.collect();
+		let mut handles = Vec::new();
+		while let Some((peer, handle)) = requests.next().await {
+			match handle {
+				Ok(handle) => handles.push(async move { (peer, handle.response().await) }),
+				Err(e) => tracing::warn!("Failed to request {peer}: {e}"),
+			}
+		}
+
+		let mut responses: stream::FuturesUnordered<_> = handles.into_iter().collect();
+		let mut tally = Tally::default();
+		while let Some((peer, response)) = responses.next().await {
+			match response {
+				Ok(response) => {
+					// Dropping the other handles cancels their requests.
+					if let Some(agreement) = tally.add(peer, response, redundancy.required) {
+						return Ok(agreement);
+					}
+				},
+				Err(e) => tracing::warn!("Provider {peer} failed to answer: {e}"),
+			}
+		}
+		Err(Box::new(NetworkError::NoAgreement {
+			required: redundancy.required,
+			agreeing: tally.best(),
+		}))
+	}
+
+	/// Sign a bulletin with the node key and publish it on the control topic. Only nodes trusting
+	/// this key will act on it.
+	pub async fn publish_bulletin(
+		&mut self,
+		kind: BulletinKind,
+	) -> Result<(), Box<dyn Error + Send>> {
+		tracing::info!("Publishing bulletin: {:?}", kind);
+		let (sender, receiver) = oneshot::channel();
+		self.send_command(Command::PublishBulletin { kind, sender }).await?;
+		receiver.await.expect("Sender not to be dropped.")
+	}
+}
+
+/// Wait for the answer to a command, failing with [`NetworkError::ShuttingDown`] when the event
+/// loop dropped the command without answering it, as it does once shutting down.
+async fn answer<T>(receiver: oneshot::Receiver<T>) -> Result<T, Box<dyn Error + Send>> {
+	receiver
+		.await
+		.map_err(|_| Box::new(NetworkError::ShuttingDown) as Box<dyn Error + Send>)
 }
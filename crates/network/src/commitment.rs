@@ -0,0 +1,197 @@
+//! Verification of results. The worker of a task first sends its owner a signed commitment to the
+//! hash of its result, acknowledged by the owner, then reveals the result, which only completes the
+//! task, and so releases its payment, when it matches the commitment. The commitment the owner
+//! keeps is the evidence of a dispute. Requesters may also send the same message to several
+//! providers and only use the answer enough of them agree on, see [`Redundancy`].
+
+use std::{fmt, str::FromStr};
+
+use libp2p::{identity, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	cid::Cid,
+	provenance::Provenance,
+	signed::{SignatureError, SignedPayload},
+};
+
+const SIGNING_DOMAIN: &[u8] = b"dasn-result-commitment:";
+
+/// Commitment of the worker of a task to its result, before revealing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultCommitment {
+	pub task_id: String,
+	/// Content id of the result.
+	pub result: Cid,
+}
+
+impl ResultCommitment {
+	pub fn of(task_id: impl Into<String>, result: &[u8]) -> Self {
+		Self { task_id: task_id.into(), result: Cid::of(result) }
+	}
+
+	pub fn sign(&self, key: &identity::Keypair) -> Result<SignedPayload, SignatureError> {
+		SignedPayload::sign(key, SIGNING_DOMAIN, self)
+	}
+
+	/// Check the signature of a commitment and return its signer along with it.
+	pub fn verify(signed: &SignedPayload) -> Result<(PeerId, Self), SignatureError> {
+		signed.verify(SIGNING_DOMAIN)
+	}
+
+	pub fn matches(&self, result: &[u8]) -> bool {
+		self.result == Cid::of(result)
+	}
+}
+
+/// Sent by the worker of a task to its owner over `/asn/task-result/1.0.0`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskResultMessage {
+	/// A signed [`ResultCommitment`].
+	Commit(SignedPayload),
+	Reveal {
+		task_id: String,
+		result: Vec<u8>,
+	},
+}
+
+/// Answer of the owner of a task to a [`TaskResultMessage`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResultAck {
+	Accepted,
+	Refused(String),
+}
+
+/// Send the same message to `providers` providers and use the answer `required` of them agree on,
+/// written `2-of-3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redundancy {
+	pub required: usize,
+	pub providers: usize,
+}
+
+impl FromStr for Redundancy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("Invalid redundancy {s}, expected e.g. 2-of-3");
+		let (required, providers) = s.split_once("-of-").ok_or_else(invalid)?;
+		let redundancy = Self {
+			required: required.trim().parse().map_err(|_| invalid())?,
+			providers: providers.trim().parse().map_err(|_| invalid())?,
+		};
+		if redundancy.required == 0 || redundancy.required > redundancy.providers {
+			return Err(invalid());
+		}
+		Ok(redundancy)
+	}
+}
+
+impl fmt::Display for Redundancy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}-of-{}", self.required, self.providers)
+	}
+}
+
+/// The answer enough providers agreed on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Agreement {
+	pub response: Vec<u8>,
+	pub agreeing: Vec<PeerId>,
+	/// Providers that answered something else before the agreement was reached.
+	pub dissenting: Vec<PeerId>,
+}
+
+/// Answers grouped by content, whitespace around them and their provenance trailer aside.
+#[derive(Debug, Default)]
+pub(crate) struct Tally {
+	answers: Vec<(Cid, Vec<u8>, Vec<PeerId>)>,
+}
+
+impl Tally {
+	/// Count the answer of `provider`, returning the agreement once `required` providers gave the
+	/// same answer.
+	pub(crate) fn add(
+		&mut self,
+		provider: PeerId,
+		response: Vec<u8>,
+		required: usize,
+	) -> Option<Agreement> {
+		let (content, _) = Provenance::split(&response);
+		let cid = Cid::of(content.trim_ascii());
+		let agreeing = match self.answers.iter_mut().find(|(answer, ..)| *answer == cid) {
+			Some((_, _, providers)) => {
+				providers.push(provider);
+				providers.len()
+			},
+			None => {
+				self.answers.push((cid, response, vec![provider]));
+				1
+			},
+		};
+		if agreeing < required {
+			return None;
+		}
+		let (_, response, agreeing) = self.answers.iter().find(|(answer, ..)| *answer == cid)?;
+		let dissenting = self
+			.answers
+			.iter()
+			.filter(|(answer, ..)| *answer != cid)
+			.flat_map(|(_, _, providers)| providers.iter().copied())
+			.collect();
+		Some(Agreement { response: response.clone(), agreeing: agreeing.clone(), dissenting })
+	}
+
+	/// Most providers that agreed on an answer.
+	pub(crate) fn best(&self) -> usize {
+		self.answers
+			.iter()
+			.map(|(_, _, providers)| providers.len())
+			.max()
+			.unwrap_or_default()
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_commitment_binds_the_result() -> Result<()> {
+		let key = identity::Keypair::generate_ed25519();
+		let commitment = ResultCommitment::of("task", b"42");
+		let (signer, verified) = ResultCommitment::verify(&commitment.sign(&key)?)?;
+
+		assert_eq!(signer, key.public().to_peer_id());
+		assert!(verified.matches(b"42"));
+		assert!(!verified.matches(b"43"));
+		Ok(())
+	}
+
+	#[test]
+	fn test_tally_reaches_agreement() -> Result<()> {
+		let redundancy: Redundancy = "2-of-3".parse()?;
+		assert_eq!(redundancy, Redundancy { required: 2, providers: 3 });
+		assert!("3-of-2".parse::<Redundancy>().is_err());
+		assert!("0-of-2".parse::<Redundancy>().is_err());
+
+		let (a, b, c) = (PeerId::random(), PeerId::random(), PeerId::random());
+		let mut tally = Tally::default();
+		assert_eq!(tally.add(a, b"Paris".to_vec(), 2), None);
+		assert_eq!(tally.add(b, b"Lyon".to_vec(), 2), None);
+		assert_eq!(tally.best(), 1);
+		let agreement = tally.add(c, b"Paris\n".to_vec(), 2).expect("An agreement.");
+		assert_eq!(
+			agreement,
+			Agreement { response: b"Paris".to_vec(), agreeing: vec![a, c], dissenting: vec![b] }
+		);
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,309 @@
+use std::{
+	io::{self, Read, Write},
+	str::FromStr,
+};
+
+use async_trait::async_trait;
+use futures::{io::Cursor, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::{request_response, StreamProtocol};
+use prometheus_client::{
+	encoding::EncodeLabelSet,
+	metrics::{counter::Counter, family::Family},
+};
+
+/// Messages are prefixed with whether they are compressed, as small ones are sent as they are.
+const RAW: u8 = 0;
+const COMPRESSED: u8 = 1;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression of the request-response payloads. Each algorithm is offered as a variant of the
+/// protocols, e.g. `/asn/2.0.0/zstd`, so peers agree on one when opening a stream and fall back to
+/// the uncompressed protocol with the peers lacking it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+	/// Algorithms offered, preferred first. Compression is off when empty.
+	pub algorithms: Vec<Compression>,
+	/// Payloads smaller than this are sent uncompressed, in bytes.
+	pub threshold: usize,
+}
+
+impl Default for CompressionConfig {
+	fn default() -> Self {
+		Self { algorithms: Vec::new(), threshold: 1024 }
+	}
+}
+
+impl CompressionConfig {
+	/// The protocols of `base`, its compressed variants first.
+	pub(crate) fn protocols(&self, base: &'static str) -> Vec<StreamProtocol> {
+		self.algorithms
+			.iter()
+			.filter_map(|algorithm| {
+				StreamProtocol::try_from_owned(format!("{base}/{}", algorithm.name())).ok()
+			})
+			.chain([StreamProtocol::new(base)])
+			.collect()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compression {
+	Zstd,
+	Gzip,
+}
+
+impl Compression {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Compression::Zstd => "zstd",
+			Compression::Gzip => "gzip",
+		}
+	}
+
+	fn of_protocol(protocol: &str) -> Option<Self> {
+		[Compression::Zstd, Compression::Gzip]
+			.into_iter()
+			.find(|algorithm| protocol.ends_with(&format!("/{}", algorithm.name())))
+	}
+
+	fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+		match self {
+			Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL),
+			Compression::Gzip => {
+				let mut encoder =
+					flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+				encoder.write_all(data)?;
+				encoder.finish()
+			},
+		}
+	}
+
+	/// Decompress `data`, failing beyond `limit` bytes rather than inflating a bomb.
+	fn decompress(&self, data: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+		let mut decompressed = Vec::new();
+		match self {
+			Compression::Zstd => zstd::stream::read::Decoder::new(data)?
+				.take(limit + 1)
+				.read_to_end(&mut decompressed)?,
+			Compression::Gzip => flate2::read::GzDecoder::new(data)
+				.take(limit + 1)
+				.read_to_end(&mut decompressed)?,
+		};
+		if decompressed.len() as u64 > limit {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Payload too large"));
+		}
+		Ok(decompressed)
+	}
+}
+
+impl FromStr for Compression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"zstd" => Ok(Compression::Zstd),
+			"gzip" => Ok(Compression::Gzip),
+			_ => Err(format!("Unknown compression {s}, expected zstd or gzip")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct CompressionLabels {
+	algorithm: String,
+}
+
+/// Bytes saved by compressing the payloads, by algorithm.
+pub type CompressionSaved = Family<CompressionLabels, Counter>;
+
+/// Wraps a codec, compressing its payloads on the streams negotiated with a compressed protocol.
+#[derive(Debug, Clone)]
+pub struct CompressedCodec<C> {
+	inner: C,
+	threshold: usize,
+	request_size_maximum: u64,
+	response_size_maximum: u64,
+	saved: CompressionSaved,
+}
+
+impl<C> CompressedCodec<C> {
+	pub(crate) fn new(
+		inner: C,
+		config: &CompressionConfig,
+		request_size_maximum: u64,
+		response_size_maximum: u64,
+		saved: CompressionSaved,
+	) -> Self {
+		Self {
+			inner,
+			threshold: config.threshold,
+			request_size_maximum,
+			response_size_maximum,
+			saved,
+		}
+	}
+
+	fn encode(&self, algorithm: Compression, payload: Vec<u8>) -> io::Result<Vec<u8>> {
+		if payload.len() >= self.threshold {
+			let compressed = algorithm.compress(&payload)?;
+			if compressed.len() < payload.len() {
+				let saved = (payload.len() - compressed.len()) as u64;
+				self.saved
+					.get_or_create(&CompressionLabels { algorithm: algorithm.name().to_string() })
+					.inc_by(saved);
+				return Ok([&[COMPRESSED], compressed.as_slice()].concat());
+			}
+		}
+		Ok([&[RAW], payload.as_slice()].concat())
+	}
+
+	async fn read<T>(algorithm: Compression, io: &mut T, limit: u64) -> io::Result<Cursor<Vec<u8>>>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		let mut message = Vec::new();
+		io.take(limit + 1).read_to_end(&mut message).await?;
+		let payload = match message.split_first() {
+			Some((&RAW, payload)) => payload.to_vec(),
+			Some((&COMPRESSED, compressed)) => algorithm.decompress(compressed, limit)?,
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid frame")),
+		};
+		Ok(Cursor::new(payload))
+	}
+}
+
+#[async_trait]
+impl<C> request_response::Codec for CompressedCodec<C>
+where
+	C: request_response::Codec<Protocol = StreamProtocol> + Send,
+	C::Request: 'static,
+	C::Response: 'static,
+{
+	type Protocol = StreamProtocol;
+	type Request = C::Request;
+	type Response = C::Response;
+
+	async fn read_request<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+	) -> io::Result<C::Request>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		match Compression::of_protocol(protocol.as_ref()) {
+			Some(algorithm) => {
+				let mut payload = Self::read(algorithm, io, self.request_size_maximum).await?;
+				self.inner.read_request(protocol, &mut payload).await
+			},
+			None => self.inner.read_request(protocol, io).await,
+		}
+	}
+
+	async fn read_response<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+	) -> io::Result<C::Response>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		match Compression::of_protocol(protocol.as_ref()) {
+			Some(algorithm) => {
+				let mut payload = Self::read(algorithm, io, self.response_size_maximum).await?;
+				self.inner.read_response(protocol, &mut payload).await
+			},
+			None => self.inner.read_response(protocol, io).await,
+		}
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+		request: C::Request,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		let Some(algorithm) = Compression::of_protocol(protocol.as_ref()) else {
+			return self.inner.write_request(protocol, io, request).await;
+		};
+		let mut payload = Cursor::new(Vec::new());
+		self.inner.write_request(protocol, &mut payload, request).await?;
+		io.write_all(&self.encode(algorithm, payload.into_inner())?).await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+		response: C::Response,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		let Some(algorithm) = Compression::of_protocol(protocol.as_ref()) else {
+			return self.inner.write_response(protocol, io, response).await;
+		};
+		let mut payload = Cursor::new(Vec::new());
+		self.inner.write_response(protocol, &mut payload, response).await?;
+		io.write_all(&self.encode(algorithm, payload.into_inner())?).await
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::{
+		behaviour::CborCodec,
+		types::{ArtifactRequest, ArtifactResponse},
+	};
+	use request_response::Codec;
+
+	#[tokio::test]
+	async fn test_compressed_round_trip() -> Result<()> {
+		let config = CompressionConfig { algorithms: vec![Compression::Zstd], threshold: 64 };
+		assert_eq!(
+			config.protocols("/asn/data/1.0.0"),
+			["/asn/data/1.0.0/zstd", "/asn/data/1.0.0"]
+		);
+
+		let saved = CompressionSaved::default();
+		let mut codec = CompressedCodec::new(
+			CborCodec::<ArtifactRequest, ArtifactResponse>::default(),
+			&config,
+			4096,
+			4096,
+			saved.clone(),
+		);
+		let protocol = StreamProtocol::new("/asn/data/1.0.0/zstd");
+		let response = ArtifactResponse(b"tokens ".repeat(100));
+
+		let mut io = Cursor::new(Vec::new());
+		codec.write_response(&protocol, &mut io, response.clone()).await?;
+		assert!(io.get_ref().len() < 100);
+		io.set_position(0);
+		assert_eq!(codec.read_response(&protocol, &mut io).await?, response);
+
+		let labels = CompressionLabels { algorithm: "zstd".to_string() };
+		assert!(saved.get_or_create(&labels).get() > 500);
+
+		// Under the threshold, sent as it is.
+		let mut io = Cursor::new(Vec::new());
+		codec
+			.write_request(&protocol, &mut io, ArtifactRequest("a".to_string()))
+			.await?;
+		assert_eq!(io.get_ref()[0], RAW);
+		io.set_position(0);
+		assert_eq!(codec.read_request(&protocol, &mut io).await?, ArtifactRequest("a".to_string()));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,323 @@
+use std::{
+	collections::{HashMap, HashSet},
+	error::Error,
+	num::{NonZeroU8, NonZeroUsize},
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use libp2p::{identity, Multiaddr, PeerId};
+
+use crate::{
+	alerts::AlertRule, bulletin::BulletinConfig, capabilities::NodeCapabilities,
+	compression::CompressionConfig, directory::DirectoryConfig, discovery::MdnsConfig,
+	escrow::TaskEscrow, history::HistoryConfig, moderation::GossipValidators,
+	policy::RequestPolicy, presence::PresenceConfig, reconnect::ReconnectConfig,
+	resume::ResumeConfig, selection::CircuitBreakerConfig, snapshot::NetworkSnapshot,
+	timeouts::RequestTimeoutConfig,
+};
+
+#[cfg(feature = "pnet")]
+use libp2p::pnet::PreSharedKey;
+
+/// Environment variable holding the pre-shared key of a private swarm.
+#[cfg(feature = "pnet")]
+pub static PSK_ENV: &str = "DASN_PSK";
+
+/// Settings used by [`crate::new`] to build the swarm and its event loop.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConfig {
+	/// Seed for a deterministic node identity. A random identity is generated when unset.
+	pub secret_key_seed: Option<u8>,
+	/// Identity of the node, e.g. kept with [`load_identity`]. Takes precedence over the seed.
+	pub identity: Option<identity::Keypair>,
+	/// Peers dialed and added to the routing table at startup, and again whenever the routing
+	/// table runs empty. `/dnsaddr` seeds are resolved when dialed.
+	pub bootstrap_peers: Vec<Multiaddr>,
+	/// Gossip topics to subscribe to on top of the default ones.
+	pub additional_topics: Vec<String>,
+	/// Peers allowed to publish on each moderated topic. Messages from any other publisher are
+	/// rejected and penalized in the peer score; topics not listed here stay open to everyone.
+	pub topic_publishers: HashMap<String, HashSet<PeerId>>,
+	/// Validators of the gossip messages received from authorized publishers, per topic.
+	pub gossip_validators: GossipValidators,
+	/// Trusted issuers of the signed configuration bulletins and how to act on them.
+	pub bulletins: BulletinConfig,
+	/// Serve as the rendezvous point of the network.
+	pub rendezvous_server: bool,
+	/// Rendezvous point to register with and discover peers from. Its address must end with the
+	/// `/p2p/<peer id>` of the point.
+	pub rendezvous_point: Option<Multiaddr>,
+	/// Address this node is reachable at, advertised to the rendezvous point. Without it, the
+	/// node registers once AutoNAT or UPnP confirmed an external address.
+	pub external_address: Option<Multiaddr>,
+	/// Backoff of the redials to the dialed peers and the rendezvous point once disconnected.
+	pub reconnect: ReconnectConfig,
+	/// Detection of the suspends and network changes the node recovers from on its own.
+	pub resume: ResumeConfig,
+	/// Role, task types and protocol features announced to the peers through identify.
+	pub capabilities: NodeCapabilities,
+	/// Gossip messages kept per topic for [`crate::Client::gossip_history`].
+	pub history: HistoryConfig,
+	/// File the gossip history is kept in, for a restart not to lose the recent messages.
+	/// Messages only live in memory when unset.
+	pub history_store: Option<PathBuf>,
+	/// Discovery of the peers of the local network.
+	pub mdns: MdnsConfig,
+	/// Replication, expiry and query settings of the DHT.
+	pub kademlia: KademliaConfig,
+	/// Resource limits of the swarm, to be tuned for large deployments.
+	pub connection_limits: ConnectionLimitsConfig,
+	/// Capacities of the channels between the client and the event loop.
+	pub channels: ChannelConfig,
+	/// Gossip topics followed for each provided agent.
+	pub agent_topics: AgentTopicsConfig,
+	/// Agent requests and streams served at once, the ones beyond are answered busy right away so
+	/// their requesters fail over to another provider. Unbounded when unset.
+	pub max_inbound_requests: Option<usize>,
+	/// Authorization policies of the agent requests, by agent name. The requests a policy does
+	/// not allow are answered denied; agents without a policy serve every request.
+	pub request_policies: HashMap<String, RequestPolicy>,
+	/// Presence beacons telling the requesters which providers are still online.
+	pub presence: PresenceConfig,
+	/// Capability announcements of the provided agents, aggregated into a local directory.
+	pub directory: DirectoryConfig,
+	/// Snapshot of the swarm to start from, its peers dialed along with the bootstrap peers and
+	/// its agents added to the directory. See [`crate::snapshot::load_snapshot`].
+	pub snapshot: Option<NetworkSnapshot>,
+	/// Timeouts of the agent requests, adapted to the response times of each provider.
+	pub request_timeouts: RequestTimeoutConfig,
+	/// File the tasks auctioned or won by the node are kept in, for a restart not to lose the
+	/// tasks in flight. Tasks only live in memory when unset.
+	pub task_store: Option<PathBuf>,
+	/// Escrow the tasks auctioned by the node are paid through, their budget locked once assigned
+	/// and settled once finished. See [`crate::escrow::EscrowSettlement`].
+	pub task_escrow: Option<Arc<dyn TaskEscrow>>,
+	/// File the tasks whose budget is locked in the escrow are kept in, for a restart not to lose
+	/// the payments left to settle. Locks only live in memory when unset.
+	pub escrow_store: Option<PathBuf>,
+	/// File the labels of the peers are kept in, for a restart not to lose the grouping of the
+	/// fleet. Labels only live in memory when unset.
+	pub address_book: Option<PathBuf>,
+	/// When to stop sending agent requests to a provider that keeps failing them.
+	pub circuit_breaker: CircuitBreakerConfig,
+	/// File the circuits of the providers are kept in, for a restart not to retry the failing
+	/// providers right away. Circuits only live in memory when unset.
+	pub circuit_store: Option<PathBuf>,
+	/// Rules raising alerts from the events and metrics of the node.
+	pub alert_rules: Vec<AlertRule>,
+	/// Largest artifact fetched from a peer, in bytes, checked against its manifest before any
+	/// chunk is fetched. [`crate::artifacts::DEFAULT_MAX_ARTIFACT_SIZE`] when unset.
+	pub max_artifact_size: Option<u64>,
+	/// Compression of the agent requests and artifacts, negotiated with each peer.
+	pub compression: CompressionConfig,
+	/// Advertise an x25519 key in the published agent cards, for the requesters to encrypt their
+	/// requests end to end rather than trusting the relays in between.
+	pub end_to_end_encryption: bool,
+	/// Pre-shared key of a private swarm. Only nodes holding the same key can connect.
+	#[cfg(feature = "pnet")]
+	pub psk: Option<PreSharedKey>,
+}
+
+/// Connection limits of the swarm. Unset limits are unbounded, connections beyond a limit are
+/// denied and reported as [`crate::Event::ConnectionLimitReached`].
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLimitsConfig {
+	pub max_established: Option<u32>,
+	pub max_established_per_peer: Option<u32>,
+	pub max_pending_incoming: Option<u32>,
+	pub max_pending_outgoing: Option<u32>,
+	/// Addresses of a peer dialed concurrently, libp2p dials 8 at once by default.
+	pub dial_concurrency_factor: Option<NonZeroU8>,
+}
+
+/// Kademlia settings of the node. The intervals and TTLs turn the matching republication or
+/// expiry off when unset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KademliaConfig {
+	/// How often the stored records are replicated to the closest peers.
+	pub replication_interval: Option<Duration>,
+	/// How often the records published by the node are published again.
+	pub publication_interval: Option<Duration>,
+	pub record_ttl: Option<Duration>,
+	/// How long the provider records of the node live on the peers storing them.
+	pub provider_record_ttl: Option<Duration>,
+	/// How often the node announces again the agents it provides.
+	pub provider_publication_interval: Option<Duration>,
+	pub query_timeout: Duration,
+	/// Peers queried at once by each step of a query.
+	pub parallelism: NonZeroUsize,
+}
+
+impl Default for KademliaConfig {
+	fn default() -> Self {
+		Self {
+			replication_interval: Some(Duration::from_secs(60 * 60)),
+			publication_interval: Some(Duration::from_secs(24 * 60 * 60)),
+			record_ttl: Some(Duration::from_secs(36 * 60 * 60)),
+			provider_record_ttl: Some(Duration::from_secs(48 * 60 * 60)),
+			provider_publication_interval: Some(Duration::from_secs(60)),
+			query_timeout: Duration::from_secs(60),
+			parallelism: NonZeroUsize::new(3).expect("Parallelism not to be zero."),
+		}
+	}
+}
+
+/// Capacities of the channels between the application and the event loop, and what happens once
+/// they are full: commands wait up to `command_timeout` before failing with
+/// [`crate::types::NetworkError::Overloaded`], events drop the oldest one not read yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelConfig {
+	pub command_capacity: usize,
+	pub command_timeout: Duration,
+	pub event_capacity: usize,
+}
+
+impl Default for ChannelConfig {
+	fn default() -> Self {
+		Self {
+			command_capacity: 32,
+			command_timeout: Duration::from_secs(10),
+			event_capacity: 1024,
+		}
+	}
+}
+
+/// Gossip topics subscribed to when providing an agent, and left once it is no longer provided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentTopicsConfig {
+	/// Names of the topics, `{agent}` standing for the name of the agent. None when empty.
+	pub templates: Vec<String>,
+}
+
+impl Default for AgentTopicsConfig {
+	fn default() -> Self {
+		Self {
+			templates: vec![
+				"agents/{agent}/requests".to_string(),
+				"agents/{agent}/announcements".to_string(),
+			],
+		}
+	}
+}
+
+impl AgentTopicsConfig {
+	pub fn topics(&self, agent_name: &str) -> Vec<String> {
+		self.templates
+			.iter()
+			.map(|template| template.replace("{agent}", agent_name))
+			.collect()
+	}
+}
+
+/// Parse a bootstrap list: one multiaddr per line, blank lines and `#` comments are ignored.
+pub fn parse_bootstrap_peers(list: &str) -> Result<Vec<Multiaddr>, libp2p::multiaddr::Error> {
+	list.lines()
+		.map(|line| line.split('#').next().unwrap_or_default().trim())
+		.filter(|line| !line.is_empty())
+		.map(str::parse)
+		.collect()
+}
+
+pub fn load_bootstrap_peers(path: &Path) -> Result<Vec<Multiaddr>, Box<dyn Error>> {
+	Ok(parse_bootstrap_peers(&std::fs::read_to_string(path)?)?)
+}
+
+/// Load the identity of the node from `path`, in the protobuf encoding of libp2p, generating an
+/// ed25519 keypair readable only by the user and saving it there on first use.
+pub fn load_identity(path: &Path) -> Result<identity::Keypair, Box<dyn Error>> {
+	match std::fs::read(path) {
+		Ok(encoded) => Ok(identity::Keypair::from_protobuf_encoding(&encoded)?),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			let key = identity::Keypair::generate_ed25519();
+			save_identity(path, &key)?;
+			Ok(key)
+		},
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Save `key` to `path` as [`load_identity`] reads it, readable only by the user. Fails when the
+/// file exists, an identity is never overwritten.
+pub fn save_identity(path: &Path, key: &identity::Keypair) -> Result<(), Box<dyn Error>> {
+	if let Some(dir) = path.parent() {
+		std::fs::create_dir_all(dir)?;
+	}
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+	std::io::Write::write_all(&mut options.open(path)?, &key.to_protobuf_encoding()?)?;
+	Ok(())
+}
+
+/// Load the pre-shared key from `path`, falling back to the [`PSK_ENV`] environment variable.
+///
+/// Both sources use the `/key/swarm/psk/1.0.0/` file format shared with go-ipfs and kubo.
+#[cfg(feature = "pnet")]
+pub fn load_psk(path: Option<&Path>) -> Result<Option<PreSharedKey>, Box<dyn Error>> {
+	let encoded = match path {
+		Some(path) => std::fs::read_to_string(path)?,
+		None => match std::env::var(PSK_ENV) {
+			Ok(encoded) => encoded,
+			Err(std::env::VarError::NotPresent) => return Ok(None),
+			Err(e) => return Err(e.into()),
+		},
+	};
+
+	Ok(Some(encoded.trim().parse::<PreSharedKey>()?))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_parse_bootstrap_peers() -> Result<()> {
+		let list = "
+			# Seeds
+			/dnsaddr/bootstrap.libp2p.io
+			/ip4/10.0.0.1/tcp/4001/p2p/12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X # local
+		";
+
+		let peers = parse_bootstrap_peers(list)?;
+
+		assert_eq!(peers.len(), 2);
+		assert_eq!(peers[0], "/dnsaddr/bootstrap.libp2p.io".parse()?);
+		assert!(parse_bootstrap_peers("not a multiaddr").is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_identity_is_kept_across_loads() -> Result<()> {
+		let path = std::env::temp_dir()
+			.join(format!("identity-{}", rand::random::<u64>()))
+			.join("identity.key");
+
+		let key = load_identity(&path)?;
+
+		assert!(save_identity(&path, &identity::Keypair::generate_ed25519()).is_err());
+		assert_eq!(load_identity(&path)?.public(), key.public());
+		std::fs::write(&path, b"not a key")?;
+		assert!(load_identity(&path).is_err());
+		std::fs::remove_dir_all(path.parent().expect("A parent directory."))?;
+		Ok(())
+	}
+
+	#[test]
+	fn test_agent_topics() {
+		assert_eq!(
+			AgentTopicsConfig::default().topics("foo"),
+			["agents/foo/requests", "agents/foo/announcements"]
+		);
+		assert!(AgentTopicsConfig { templates: Vec::new() }.topics("foo").is_empty());
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,618 @@
+//! Capability directory: providers periodically gossip a signed [`CapabilityAnnouncement`] of the
+//! agents they provide on the capabilities topic, and every node aggregates the announcements it
+//! receives into a local directory, searched with [`crate::Client::find_agents`] without a DHT
+//! query. Announcements expire when their provider stops sending them, and an older announcement
+//! never replaces a newer one of the same provider.
+//!
+//! A node joining the network does not wait for the next announcements to be gossiped: it sends
+//! the timestamp of the announcements it knows of each provider to a peer, which answers with the
+//! signed announcements it knows to be newer, verified as the gossiped ones.
+
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use libp2p::{identity, PeerId};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use thiserror::Error;
+use tokio::time::Instant;
+
+use crate::{
+	agent_card::AgentCard,
+	signed::{SignatureError, SignedPayload},
+	types::{deserialize_message, TaskType},
+};
+
+/// Version of the announcements sent. Bumped for the changes older nodes cannot read, fields
+/// added with a default do not need it.
+pub const ANNOUNCEMENT_VERSION: u32 = 1;
+
+/// Most announcements sent in answer to a directory sync.
+pub const MAX_SYNCED_ANNOUNCEMENTS: usize = 1024;
+
+const SIGNING_DOMAIN: &[u8] = b"asn/capability-announcement";
+
+#[derive(Error, Debug)]
+pub enum AnnouncementError {
+	#[error("Invalid capability announcement: {0}")]
+	Signature(#[from] SignatureError),
+	#[error("Capability announcement signed by {0}, not by its publisher")]
+	SignerMismatch(PeerId),
+	#[error("Capability announcement of version {0}, newer than supported")]
+	UnsupportedVersion(u32),
+}
+
+/// Capability announcements gossiped by the node, and how long they are trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectoryConfig {
+	pub enabled: bool,
+	/// How often a node providing agents announces them.
+	pub interval: Duration,
+	/// Announcements are dropped from the directory once none newer arrived for this long.
+	pub expire_after: Duration,
+}
+
+impl Default for DirectoryConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			interval: Duration::from_secs(60),
+			expire_after: Duration::from_secs(300),
+		}
+	}
+}
+
+/// The agents a provider serves, as of `timestamp`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapabilityAnnouncement {
+	pub version: u32,
+	/// Unix timestamp in milliseconds.
+	pub timestamp: u64,
+	pub agents: Vec<AgentCard>,
+}
+
+impl CapabilityAnnouncement {
+	pub fn sign(&self, key: &identity::Keypair) -> Result<SignedPayload, SignatureError> {
+		SignedPayload::sign(key, SIGNING_DOMAIN, self)
+	}
+
+	/// Decode and verify an announcement gossiped by `publisher`.
+	pub fn verify(publisher: &PeerId, data: &[u8]) -> Result<Self, AnnouncementError> {
+		let signed: SignedPayload = deserialize_message(data).map_err(SignatureError::from)?;
+		let (signer, announcement): (PeerId, Self) = signed.verify(SIGNING_DOMAIN)?;
+		if signer != *publisher {
+			return Err(AnnouncementError::SignerMismatch(signer));
+		}
+		if announcement.version > ANNOUNCEMENT_VERSION {
+			return Err(AnnouncementError::UnsupportedVersion(announcement.version));
+		}
+		Ok(announcement)
+	}
+}
+
+/// The timestamp of the latest announcement known of each provider, for a peer to answer with
+/// the newer ones.
+#[serde_as]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectorySyncRequest {
+	#[serde_as(as = "HashMap<DisplayFromStr, _>")]
+	pub heads: HashMap<PeerId, u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectorySyncResponse {
+	pub announcements: Vec<SyncedAnnouncement>,
+}
+
+/// An announcement as signed by its provider.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncedAnnouncement {
+	#[serde_as(as = "DisplayFromStr")]
+	pub provider: PeerId,
+	pub data: Vec<u8>,
+	/// Milliseconds since the peer received it. Not trusted, the receiver expires the announcement
+	/// by its signed timestamp, sent for the nodes still reading it.
+	pub age_ms: u64,
+}
+
+/// What the agents looked for in the directory must match. Unset fields match any agent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentFilter {
+	pub name: Option<String>,
+	pub capability: Option<String>,
+	pub task_type: Option<TaskType>,
+	pub language: Option<String>,
+	pub max_price: Option<f64>,
+	pub query: Option<AgentQuery>,
+}
+
+impl AgentFilter {
+	pub fn matches(&self, card: &AgentCard) -> bool {
+		self.name.as_ref().is_none_or(|name| card.name == *name)
+			&& self.capability.as_ref().is_none_or(|capability| {
+				card.capabilities.iter().any(|c| c.eq_ignore_ascii_case(capability))
+			}) && self
+			.task_type
+			.as_ref()
+			.is_none_or(|task_type| card.task_types.contains(task_type))
+			&& self.language.as_ref().is_none_or(|language| card.speaks(language))
+			&& self.max_price.is_none_or(|max_price| card.pricing <= max_price)
+			&& self.query.as_ref().is_none_or(|query| query.matches(card))
+	}
+}
+
+/// Conditions on the cards of the agents, written as `price<0.01 AND lang=en AND tools contains
+/// web_fetch`. `OR` separates alternatives of conditions that must all hold, `AND` binding
+/// tighter.
+///
+/// The text fields are `name` (or `model`), `version`, `capabilities` (or `capability`, `tools`),
+/// `task_types` (or `task_type`) and `languages` (or `language`, `lang`), compared regardless of
+/// case with `=`, `!=`, `~` (contains the text) and `contains` (holds the value, for the lists).
+/// A list matches `=` and `~` when one of its values does. `price` is compared with `=`, `!=`,
+/// `<`, `<=`, `>` and `>=`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentQuery {
+	alternatives: Vec<Vec<Condition>>,
+}
+
+impl AgentQuery {
+	pub fn matches(&self, card: &AgentCard) -> bool {
+		self.alternatives
+			.iter()
+			.any(|conditions| conditions.iter().all(|condition| condition.matches(card)))
+	}
+}
+
+impl FromStr for AgentQuery {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut alternatives = vec![Vec::new()];
+		let mut condition = Vec::new();
+		for token in s.split_whitespace().chain([""]) {
+			let or = token.eq_ignore_ascii_case("OR");
+			if !or && !token.eq_ignore_ascii_case("AND") && !token.is_empty() {
+				condition.push(token);
+				continue;
+			}
+			if condition.is_empty() {
+				return Err(format!("Invalid query {s}, expected a condition before {token:?}"));
+			}
+			let conditions = alternatives.last_mut().expect("An alternative.");
+			conditions.push(condition.join(" ").parse()?);
+			condition.clear();
+			if or {
+				alternatives.push(Vec::new());
+			}
+		}
+		Ok(Self { alternatives })
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+	Name,
+	Version,
+	Capabilities,
+	TaskTypes,
+	Languages,
+}
+
+impl TextField {
+	fn values(self, card: &AgentCard) -> Vec<String> {
+		match self {
+			TextField::Name => vec![card.name.clone()],
+			TextField::Version => vec![card.version.clone()],
+			TextField::Capabilities => card.capabilities.clone(),
+			TextField::TaskTypes => card
+				.task_types
+				.iter()
+				.map(|task_type| match task_type {
+					TaskType::Unknown(tag, _) => tag.clone(),
+					known => format!("{known:?}"),
+				})
+				.collect(),
+			TextField::Languages => card.languages.clone(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+	Eq,
+	Ne,
+	Like,
+	Contains,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+	Text { field: TextField, operator: Operator, value: String },
+	Price { operator: Operator, value: f64 },
+}
+
+impl Condition {
+	fn matches(&self, card: &AgentCard) -> bool {
+		match self {
+			Condition::Text { field, operator, value } => {
+				let mut values = field.values(card).into_iter();
+				match operator {
+					Operator::Ne => !values.any(|v| v.eq_ignore_ascii_case(value)),
+					Operator::Like => {
+						values.any(|v| v.to_lowercase().contains(&value.to_lowercase()))
+					},
+					_ => values.any(|v| v.eq_ignore_ascii_case(value)),
+				}
+			},
+			Condition::Price { operator, value } => match operator {
+				Operator::Eq => card.pricing == *value,
+				Operator::Ne => card.pricing != *value,
+				Operator::Lt => card.pricing < *value,
+				Operator::Le => card.pricing <= *value,
+				Operator::Gt => card.pricing > *value,
+				_ => card.pricing >= *value,
+			},
+		}
+	}
+}
+
+/// Parses a single `field operator value` condition.
+impl FromStr for Condition {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (field, operator, value) = match s.split_whitespace().collect::<Vec<_>>()[..] {
+			[field, operator, value] if operator.eq_ignore_ascii_case("contains") => {
+				(field, Operator::Contains, value)
+			},
+			_ => {
+				let Some(start) = s.find(['=', '!', '<', '>', '~']) else {
+					return Err(format!(
+						"Invalid condition {s}, expected field, operator and value"
+					));
+				};
+				let (operator, len) = match &s[start..] {
+					rest if rest.starts_with("!=") => (Operator::Ne, 2),
+					rest if rest.starts_with("<=") => (Operator::Le, 2),
+					rest if rest.starts_with(">=") => (Operator::Ge, 2),
+					rest if rest.starts_with('=') => (Operator::Eq, 1),
+					rest if rest.starts_with('<') => (Operator::Lt, 1),
+					rest if rest.starts_with('>') => (Operator::Gt, 1),
+					rest if rest.starts_with('~') => (Operator::Like, 1),
+					_ => return Err(format!("Invalid operator in condition {s}")),
+				};
+				(s[..start].trim(), operator, s[start + len..].trim())
+			},
+		};
+		let value = value.trim_matches(|c| c == '"' || c == '\'');
+		if value.is_empty() {
+			return Err(format!("Invalid condition {s}, expected a value"));
+		}
+		let field = match field.to_ascii_lowercase().as_str() {
+			"price" => {
+				if matches!(operator, Operator::Like | Operator::Contains) {
+					return Err(format!("Invalid condition {s}, price is compared as a number"));
+				}
+				let value = value.parse().map_err(|_| format!("Invalid price in condition {s}"))?;
+				return Ok(Condition::Price { operator, value });
+			},
+			"name" | "model" => TextField::Name,
+			"version" => TextField::Version,
+			"capabilities" | "capability" | "tools" => TextField::Capabilities,
+			"task_types" | "task_type" => TextField::TaskTypes,
+			"languages" | "language" | "lang" => TextField::Languages,
+			_ => return Err(format!("Unknown field {field} in condition {s}")),
+		};
+		if matches!(operator, Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge) {
+			return Err(format!(
+				"Invalid condition {s}, only price is compared with <, <=, > and >="
+			));
+		}
+		Ok(Condition::Text { field, operator, value: value.to_string() })
+	}
+}
+
+/// An agent found in the directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentListing {
+	pub provider: PeerId,
+	pub card: AgentCard,
+	/// Unix timestamp in milliseconds of the announcement listing the agent.
+	pub announced_at: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+	announcement: CapabilityAnnouncement,
+	/// The announcement as signed by its provider, to pass on to the peers syncing. None for
+	/// the ones taken from a network snapshot.
+	data: Option<Vec<u8>>,
+	received_at: Instant,
+}
+
+/// The latest announcement of each provider.
+#[derive(Debug)]
+pub(crate) struct CapabilityDirectory {
+	providers: HashMap<PeerId, Entry>,
+	expire_after: Duration,
+}
+
+impl CapabilityDirectory {
+	pub(crate) fn new(config: &DirectoryConfig) -> Self {
+		Self { providers: HashMap::new(), expire_after: config.expire_after }
+	}
+
+	/// Keep the announcement of `provider`, signed as `data`, unless one as recent is known.
+	/// Returns whether it was kept.
+	pub(crate) fn record(
+		&mut self,
+		provider: PeerId,
+		announcement: CapabilityAnnouncement,
+		data: Option<Vec<u8>>,
+		now: Instant,
+	) -> bool {
+		if self
+			.providers
+			.get(&provider)
+			.is_some_and(|entry| entry.announcement.timestamp >= announcement.timestamp)
+		{
+			return false;
+		}
+		self.providers.insert(provider, Entry { announcement, data, received_at: now });
+		true
+	}
+
+	/// What to ask a peer for the announcements newer than the ones known.
+	pub(crate) fn sync_request(&self, now: Instant) -> DirectorySyncRequest {
+		let heads = self
+			.providers
+			.iter()
+			.filter(|(_, entry)| now - entry.received_at <= self.expire_after)
+			.map(|(provider, entry)| (*provider, entry.announcement.timestamp))
+			.collect();
+		DirectorySyncRequest { heads }
+	}
+
+	/// The signed announcements not expired newer than the `heads` of the peer syncing.
+	pub(crate) fn sync_response(
+		&self,
+		request: &DirectorySyncRequest,
+		now: Instant,
+	) -> DirectorySyncResponse {
+		let announcements = self
+			.providers
+			.iter()
+			.filter(|(provider, entry)| {
+				now - entry.received_at <= self.expire_after
+					&& request
+						.heads
+						.get(provider)
+						.is_none_or(|head| entry.announcement.timestamp > *head)
+			})
+			.filter_map(|(provider, entry)| {
+				Some(SyncedAnnouncement {
+					provider: *provider,
+					data: entry.data.clone()?,
+					age_ms: (now - entry.received_at).as_millis() as u64,
+				})
+			})
+			.take(MAX_SYNCED_ANNOUNCEMENTS)
+			.collect();
+		DirectorySyncResponse { announcements }
+	}
+
+	/// Keep the announcements a peer answered a sync with that are signed by their provider and
+	/// not expired, their age taken from their signed timestamp against `unix_ms`, the wall clock
+	/// in milliseconds, rather than from the peer. Returns how many were kept.
+	pub(crate) fn merge(
+		&mut self,
+		response: DirectorySyncResponse,
+		now: Instant,
+		unix_ms: u64,
+	) -> usize {
+		let mut kept = 0;
+		for synced in response.announcements {
+			match CapabilityAnnouncement::verify(&synced.provider, &synced.data) {
+				Ok(announcement) => {
+					// Timestamps ahead of the clock count as fresh.
+					let age = Duration::from_millis(unix_ms.saturating_sub(announcement.timestamp));
+					if age > self.expire_after {
+						continue;
+					}
+					let received_at = now.checked_sub(age).unwrap_or(now);
+					if self.record(synced.provider, announcement, Some(synced.data), received_at) {
+						kept += 1;
+					}
+				},
+				Err(e) => tracing::warn!("Ignored synced announcement of {}: {e}", synced.provider),
+			}
+		}
+		kept
+	}
+
+	/// The agents of the announcements not expired matching `filter`, cheapest first.
+	pub(crate) fn find(&self, filter: &AgentFilter, now: Instant) -> Vec<AgentListing> {
+		let mut listings: Vec<_> =
+			self.providers
+				.iter()
+				.filter(|(_, entry)| now - entry.received_at <= self.expire_after)
+				.flat_map(|(provider, entry)| {
+					entry.announcement.agents.iter().filter(|card| filter.matches(card)).map(
+						|card| AgentListing {
+							provider: *provider,
+							card: card.clone(),
+							announced_at: entry.announcement.timestamp,
+						},
+					)
+				})
+				.collect();
+		listings.sort_by(|a, b| a.card.pricing.total_cmp(&b.card.pricing));
+		listings
+	}
+
+	/// Forget the expired announcements.
+	pub(crate) fn prune(&mut self, now: Instant) {
+		let expire_after = self.expire_after;
+		self.providers.retain(|_, entry| now - entry.received_at <= expire_after);
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::types::serialize_message;
+
+	fn announcement(timestamp: u64, pricing: f64) -> CapabilityAnnouncement {
+		CapabilityAnnouncement {
+			version: ANNOUNCEMENT_VERSION,
+			timestamp,
+			agents: vec![AgentCard {
+				capabilities: vec!["summarize".to_string()],
+				pricing,
+				languages: vec!["fr".to_string()],
+				..AgentCard::new("writer", "0.1.0")
+			}],
+		}
+	}
+
+	#[test]
+	fn test_announcement_is_signed_by_its_publisher() -> Result<()> {
+		let key = identity::Keypair::generate_ed25519();
+		let publisher = key.public().to_peer_id();
+		let data = serialize_message(&announcement(1, 1.0).sign(&key)?)?;
+		assert_eq!(CapabilityAnnouncement::verify(&publisher, &data)?, announcement(1, 1.0));
+		assert!(matches!(
+			CapabilityAnnouncement::verify(&PeerId::random(), &data),
+			Err(AnnouncementError::SignerMismatch(_))
+		));
+
+		let newer =
+			CapabilityAnnouncement { version: ANNOUNCEMENT_VERSION + 1, ..announcement(1, 1.0) };
+		let data = serialize_message(&newer.sign(&key)?)?;
+		assert!(matches!(
+			CapabilityAnnouncement::verify(&publisher, &data),
+			Err(AnnouncementError::UnsupportedVersion(_))
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn test_directory_keeps_the_latest_announcements() {
+		let config = DirectoryConfig::default();
+		let mut directory = CapabilityDirectory::new(&config);
+		let (cheap, dear) = (PeerId::random(), PeerId::random());
+		let start = Instant::now();
+		assert!(directory.record(dear, announcement(10, 3.0), None, start));
+		assert!(directory.record(cheap, announcement(10, 1.0), None, start));
+		// A replayed announcement does not replace the newer one.
+		assert!(!directory.record(dear, announcement(5, 0.5), None, start));
+
+		let filter =
+			AgentFilter { capability: Some("Summarize".to_string()), ..Default::default() };
+		let providers: Vec<_> = directory
+			.find(&filter, start)
+			.into_iter()
+			.map(|listing| listing.provider)
+			.collect();
+		assert_eq!(providers, [cheap, dear]);
+		let affordable =
+			AgentFilter { max_price: Some(2.0), language: Some("fr".into()), ..filter };
+		assert_eq!(directory.find(&affordable, start).len(), 1);
+		let unknown = AgentFilter { name: Some("painter".to_string()), ..Default::default() };
+		assert!(directory.find(&unknown, start).is_empty());
+
+		let later = start + config.expire_after;
+		assert!(directory.record(cheap, announcement(20, 1.0), None, later));
+		directory.prune(later + Duration::from_secs(1));
+		let providers: Vec<_> = directory
+			.find(&AgentFilter::default(), later)
+			.into_iter()
+			.map(|listing| listing.provider)
+			.collect();
+		assert_eq!(providers, [cheap]);
+	}
+
+	#[test]
+	fn test_directory_sync_sends_the_newer_signed_announcements() -> Result<()> {
+		let config = DirectoryConfig::default();
+		let now = Instant::now();
+		let (key, other) = (identity::Keypair::generate_ed25519(), PeerId::random());
+		let provider = key.public().to_peer_id();
+		let mut known = CapabilityDirectory::new(&config);
+		let data = serialize_message(&announcement(10, 1.0).sign(&key)?)?;
+		known.record(provider, announcement(10, 1.0), Some(data), now);
+		// Not signed by its provider, so not passed on.
+		known.record(other, announcement(10, 2.0), None, now);
+
+		let mut joining = CapabilityDirectory::new(&config);
+		let response = known.sync_response(&joining.sync_request(now), now);
+		assert_eq!(response.announcements.len(), 1);
+		let unix_ms = 10 + 1000;
+		assert_eq!(joining.merge(response, now, unix_ms), 1);
+		assert_eq!(joining.find(&AgentFilter::default(), now)[0].provider, provider);
+		assert!(known.sync_response(&joining.sync_request(now), now).announcements.is_empty());
+
+		let mut forged = known.sync_response(&DirectorySyncRequest::default(), now);
+		forged.announcements[0].provider = other;
+		assert_eq!(CapabilityDirectory::new(&config).merge(forged, now, unix_ms), 0);
+		// The age the peer claims is ignored, the signed timestamp is not.
+		let mut claimed = known.sync_response(&DirectorySyncRequest::default(), now);
+		claimed.announcements[0].age_ms = u64::MAX;
+		assert_eq!(CapabilityDirectory::new(&config).merge(claimed, now, unix_ms), 1);
+		let mut expired = known.sync_response(&DirectorySyncRequest::default(), now);
+		expired.announcements[0].age_ms = 0;
+		let later = 10 + config.expire_after.as_millis() as u64 + 1;
+		assert_eq!(CapabilityDirectory::new(&config).merge(expired, now, later), 0);
+		Ok(())
+	}
+
+	#[test]
+	fn test_agent_query() -> Result<()> {
+		let card = AgentCard {
+			capabilities: vec!["web_fetch".to_string(), "summarize".to_string()],
+			pricing: 0.005,
+			task_types: vec![TaskType::WebResearch],
+			languages: vec!["en".to_string()],
+			..AgentCard::new("gpt-4o-researcher", "0.2.0")
+		};
+		let matching = [
+			"model~gpt-4 AND price<0.01 AND lang=en AND tools contains web_fetch",
+			"name = GPT-4O-RESEARCHER",
+			"task_type=WebResearch and price >= 0.005",
+			"lang=fr OR capability~summ",
+			"version!=0.1.0",
+		];
+		for query in matching {
+			assert!(query.parse::<AgentQuery>()?.matches(&card), "{query}");
+		}
+		let failing =
+			["price>0.01", "lang=fr AND tools contains web_fetch", "capabilities!=summarize"];
+		for query in failing {
+			assert!(!query.parse::<AgentQuery>()?.matches(&card), "{query}");
+		}
+		let invalid =
+			["", "price~cheap", "name<b", "colour=red", "lang=en AND", "OR lang=en", "lang"];
+		for query in invalid {
+			assert!(query.parse::<AgentQuery>().is_err(), "{query}");
+		}
+
+		let mut directory = CapabilityDirectory::new(&DirectoryConfig::default());
+		let now = Instant::now();
+		directory.record(PeerId::random(), announcement(1, 1.0), None, now);
+		let filter =
+			AgentFilter { query: Some("lang=fr AND price<=1".parse()?), ..Default::default() };
+		assert_eq!(directory.find(&filter, now).len(), 1);
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
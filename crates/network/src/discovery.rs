@@ -0,0 +1,124 @@
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use if_addrs::IfAddr;
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+
+/// Discovery of the peers of the local network over mDNS. Servers and CI runners usually turn it
+/// off, the peers found are reported as [`crate::Event::PeerDiscovered`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsConfig {
+	pub enabled: bool,
+	/// Query over IPv6 instead of IPv4.
+	pub ipv6: bool,
+	/// Names of the network interfaces whose peers are kept, e.g. `eth0`, all of them when empty.
+	pub interfaces: Vec<String>,
+	pub query_interval: Duration,
+	/// How long a discovered peer is kept without hearing from it again.
+	pub ttl: Duration,
+}
+
+impl Default for MdnsConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			ipv6: false,
+			interfaces: Vec::new(),
+			query_interval: Duration::from_secs(5 * 60),
+			ttl: Duration::from_secs(6 * 60),
+		}
+	}
+}
+
+/// Keeps the discovered addresses on the selected network interfaces.
+#[derive(Debug, Default)]
+pub(crate) struct InterfaceFilter {
+	interfaces: Vec<String>,
+}
+
+impl InterfaceFilter {
+	pub(crate) fn new(config: &MdnsConfig) -> Self {
+		Self { interfaces: config.interfaces.clone() }
+	}
+
+	/// The addresses reachable through the selected interfaces, all of them without a selection.
+	/// Interfaces are looked up on each discovery, as they come and go.
+	pub(crate) fn retain(&self, addresses: Vec<Multiaddr>) -> Vec<Multiaddr> {
+		if self.interfaces.is_empty() {
+			return addresses;
+		}
+
+		let networks: Vec<_> = match if_addrs::get_if_addrs() {
+			Ok(interfaces) => interfaces
+				.into_iter()
+				.filter(|interface| self.interfaces.contains(&interface.name))
+				.map(|interface| match interface.addr {
+					IfAddr::V4(addr) => (IpAddr::V4(addr.ip), IpAddr::V4(addr.netmask)),
+					IfAddr::V6(addr) => (IpAddr::V6(addr.ip), IpAddr::V6(addr.netmask)),
+				})
+				.collect(),
+			Err(e) => {
+				tracing::warn!("Failed to list the network interfaces: {e}");
+				Vec::new()
+			},
+		};
+		addresses
+			.into_iter()
+			.filter(|address| {
+				ip(address).is_some_and(|ip| {
+					networks.iter().any(|(network, netmask)| in_network(ip, *network, *netmask))
+				})
+			})
+			.collect()
+	}
+}
+
+/// The addresses of each peer of an mDNS event, which lists them one by one.
+pub(crate) fn by_peer(list: Vec<(PeerId, Multiaddr)>) -> HashMap<PeerId, Vec<Multiaddr>> {
+	list.into_iter().fold(HashMap::new(), |mut peers, (peer_id, address)| {
+		peers.entry(peer_id).or_insert_with(Vec::new).push(address);
+		peers
+	})
+}
+
+fn ip(address: &Multiaddr) -> Option<IpAddr> {
+	address.iter().find_map(|protocol| match protocol {
+		Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+		Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+		_ => None,
+	})
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, netmask: IpAddr) -> bool {
+	match (ip, network, netmask) {
+		(IpAddr::V4(ip), IpAddr::V4(network), IpAddr::V4(netmask)) => {
+			ip.to_bits() & netmask.to_bits() == network.to_bits() & netmask.to_bits()
+		},
+		(IpAddr::V6(ip), IpAddr::V6(network), IpAddr::V6(netmask)) => {
+			ip.to_bits() & netmask.to_bits() == network.to_bits() & netmask.to_bits()
+		},
+		_ => false,
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_in_network() {
+		let network = "192.168.1.7".parse().unwrap();
+		let netmask = "255.255.255.0".parse().unwrap();
+		assert!(in_network("192.168.1.42".parse().unwrap(), network, netmask));
+		assert!(!in_network("192.168.2.42".parse().unwrap(), network, netmask));
+		assert!(!in_network("fe80::1".parse().unwrap(), network, netmask));
+
+		let address: Multiaddr = "/ip4/192.168.1.42/tcp/4001".parse().unwrap();
+		assert_eq!(ip(&address), Some("192.168.1.42".parse().unwrap()));
+		let filter = InterfaceFilter::default();
+		assert_eq!(filter.retain(vec![address.clone()]), [address]);
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,297 @@
+use std::{io, sync::Arc};
+
+use async_trait::async_trait;
+use chacha20poly1305::{
+	aead::{Aead, KeyInit},
+	ChaCha20Poly1305, Key, Nonce,
+};
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::{identity, request_response, StreamProtocol};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::types::{
+	deserialize_message, serialize_message, ContextMessage, LLMRequest, LLMResponse, ProtocolError,
+};
+
+const IDENTITY_CONTEXT: &str = "dasn 2025 end-to-end encryption key";
+const REQUEST_CONTEXT: &str = "dasn 2025 end-to-end request";
+const RESPONSE_CONTEXT: &str = "dasn 2025 end-to-end response";
+/// Each key encrypts a single message, so the nonce never repeats under a key.
+const NONCE: [u8; 12] = [0; 12];
+
+#[derive(Error, Debug)]
+pub enum EncryptionError {
+	#[error("Agent card of {0} advertises no encryption key")]
+	NoKey(String),
+	#[error("Failed to encrypt the payload")]
+	Encryption,
+	#[error("Failed to decrypt the payload")]
+	Decryption,
+	#[error("Malformed sealed payload: {0}")]
+	Protocol(#[from] ProtocolError),
+}
+
+/// The message and context of a request, encrypted to the key advertised in the card of the
+/// provider so that the relays in between only see the agent name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sealed {
+	/// Public half of the key the requester generated for this request alone.
+	pub ephemeral: [u8; 32],
+	pub ciphertext: Vec<u8>,
+}
+
+/// The x25519 key of the node, derived from its ed25519 identity so it survives restarts.
+pub struct EncryptionKey(StaticSecret);
+
+impl std::fmt::Debug for EncryptionKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_tuple("EncryptionKey").field(&self.public()).finish()
+	}
+}
+
+impl EncryptionKey {
+	/// The key of an ed25519 identity, other identities cannot advertise one.
+	pub fn from_identity(key: &identity::Keypair) -> Option<Self> {
+		let key = key.clone().try_into_ed25519().ok()?;
+		let secret = blake3::derive_key(IDENTITY_CONTEXT, key.secret().as_ref());
+		Some(Self(StaticSecret::from(secret)))
+	}
+
+	pub fn public(&self) -> [u8; 32] {
+		PublicKey::from(&self.0).to_bytes()
+	}
+
+	/// Decrypt a request sealed to the key, along with the key of its response.
+	pub(crate) fn open(
+		&self,
+		sealed: &Sealed,
+	) -> Result<(String, Vec<ContextMessage>, SessionKey), EncryptionError> {
+		let shared = self.0.diffie_hellman(&PublicKey::from(sealed.ephemeral));
+		let (request, response) = derive_keys(shared.as_bytes(), &sealed.ephemeral, &self.public());
+		let plaintext = ChaCha20Poly1305::new(&request)
+			.decrypt(Nonce::from_slice(&NONCE), sealed.ciphertext.as_slice())
+			.map_err(|_| EncryptionError::Decryption)?;
+		let (message, context) = deserialize_message(&plaintext)?;
+		Ok((message, context, SessionKey(response)))
+	}
+}
+
+/// Key of the response to a sealed request, known to its requester and provider only.
+#[derive(Clone)]
+pub struct SessionKey(Key);
+
+impl std::fmt::Debug for SessionKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str("SessionKey(..)")
+	}
+}
+
+impl SessionKey {
+	pub(crate) fn seal(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+		ChaCha20Poly1305::new(&self.0)
+			.encrypt(Nonce::from_slice(&NONCE), data)
+			.map_err(|_| EncryptionError::Encryption)
+	}
+
+	pub(crate) fn open(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+		ChaCha20Poly1305::new(&self.0)
+			.decrypt(Nonce::from_slice(&NONCE), data)
+			.map_err(|_| EncryptionError::Decryption)
+	}
+}
+
+fn derive_keys(shared: &[u8; 32], ephemeral: &[u8; 32], recipient: &[u8; 32]) -> (Key, Key) {
+	let material = [shared.as_slice(), ephemeral, recipient].concat();
+	(
+		blake3::derive_key(REQUEST_CONTEXT, &material).into(),
+		blake3::derive_key(RESPONSE_CONTEXT, &material).into(),
+	)
+}
+
+/// Encrypt a message and its context to the key of a provider, along with the key of its response.
+pub(crate) fn seal(
+	recipient: &[u8; 32],
+	message: &str,
+	context: &[ContextMessage],
+) -> Result<(Sealed, SessionKey), EncryptionError> {
+	let secret = EphemeralSecret::random_from_rng(rand::rngs::OsRng);
+	let ephemeral = PublicKey::from(&secret).to_bytes();
+	let shared = secret.diffie_hellman(&PublicKey::from(*recipient));
+	let (request, response) = derive_keys(shared.as_bytes(), &ephemeral, recipient);
+	let ciphertext = ChaCha20Poly1305::new(&request)
+		.encrypt(Nonce::from_slice(&NONCE), serialize_message(&(message, context))?.as_slice())
+		.map_err(|_| EncryptionError::Encryption)?;
+	Ok((Sealed { ephemeral, ciphertext }, SessionKey(response)))
+}
+
+fn invalid_data(error: EncryptionError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Wraps the codec of the agent requests on the provider side, opening the sealed requests and
+/// sealing their responses with the key of the request. The requester seals and opens its own in
+/// the event loop, which knows the card of the provider.
+#[derive(Debug, Clone)]
+pub struct EncryptedCodec<C> {
+	inner: C,
+	key: Option<Arc<EncryptionKey>>,
+	/// Key of the response to the request read from the stream, when it was sealed.
+	session: Option<SessionKey>,
+}
+
+impl<C> EncryptedCodec<C> {
+	pub(crate) fn new(inner: C, key: Option<Arc<EncryptionKey>>) -> Self {
+		Self { inner, key, session: None }
+	}
+}
+
+#[async_trait]
+impl<C> request_response::Codec for EncryptedCodec<C>
+where
+	C: request_response::Codec<
+			Protocol = StreamProtocol,
+			Request = LLMRequest,
+			Response = LLMResponse,
+		> + Send,
+{
+	type Protocol = StreamProtocol;
+	type Request = LLMRequest;
+	type Response = LLMResponse;
+
+	async fn read_request<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+	) -> io::Result<LLMRequest>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		let request = self.inner.read_request(protocol, io).await?;
+		let Some(sealed) = &request.3 else {
+			return Ok(request);
+		};
+		let key = self
+			.key
+			.as_ref()
+			.ok_or_else(|| invalid_data(EncryptionError::NoKey(request.0.clone())))?;
+		let (message, context, session) = key.open(sealed).map_err(invalid_data)?;
+		self.session = Some(session);
+		Ok(LLMRequest(request.0, message, context, None, request.4, request.5, request.6))
+	}
+
+	async fn read_response<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+	) -> io::Result<LLMResponse>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		self.inner.read_response(protocol, io).await
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+		request: LLMRequest,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		self.inner.write_request(protocol, io, request).await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+		response: LLMResponse,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		let response = match (&self.session, response) {
+			(Some(session), LLMResponse::Output(output)) => {
+				LLMResponse::Output(session.seal(&output).map_err(invalid_data)?)
+			},
+			(_, response) => response,
+		};
+		self.inner.write_response(protocol, io, response).await
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::{behaviour::CborCodec, types::MessageRole};
+	use futures::io::Cursor;
+	use request_response::Codec;
+
+	#[tokio::test]
+	async fn test_sealed_round_trip() -> Result<()> {
+		let provider = Arc::new(
+			EncryptionKey::from_identity(&identity::Keypair::generate_ed25519())
+				.ok_or("Ed25519 identity without an encryption key")?,
+		);
+		let context =
+			vec![ContextMessage { role: MessageRole::User, content: "hello".to_string() }];
+		let (sealed, session) = seal(&provider.public(), "secret", &context)?;
+		assert!(!String::from_utf8_lossy(&sealed.ciphertext).contains("secret"));
+
+		let protocol = StreamProtocol::new("/asn/2.0.0");
+		let mut codec = EncryptedCodec::new(
+			CborCodec::<LLMRequest, LLMResponse>::default(),
+			Some(provider.clone()),
+		);
+		let mut io = Cursor::new(Vec::new());
+		let request = LLMRequest(
+			"echo".to_string(),
+			String::new(),
+			Vec::new(),
+			Some(sealed),
+			None,
+			Vec::new(),
+			None,
+		);
+		codec.write_request(&protocol, &mut io, request).await?;
+		io.set_position(0);
+		let opened = codec.read_request(&protocol, &mut io).await?;
+		assert_eq!(
+			opened,
+			LLMRequest(
+				"echo".to_string(),
+				"secret".to_string(),
+				context,
+				None,
+				None,
+				Vec::new(),
+				None
+			)
+		);
+
+		let mut io = Cursor::new(Vec::new());
+		codec
+			.write_response(&protocol, &mut io, LLMResponse::Output(b"answer".to_vec()))
+			.await?;
+		io.set_position(0);
+		let LLMResponse::Output(response) = codec.read_response(&protocol, &mut io).await? else {
+			return Err("Expected the output of the agent".into());
+		};
+		assert_eq!(session.open(&response)?, b"answer");
+
+		// Sealed to another provider.
+		let (sealed, _) = seal(&[9; 32], "secret", &[])?;
+		assert!(matches!(provider.open(&sealed), Err(EncryptionError::Decryption)));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
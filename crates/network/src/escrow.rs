@@ -0,0 +1,256 @@
+//! Payment of the auctioned tasks through an escrow: the budget of a task is locked once it is
+//! assigned, paid to its worker once it completes and returned to its owner once it times out.
+//! Chains plug in by implementing [`TaskEscrow`], set as the [`crate::NetworkConfig::task_escrow`]
+//! of the node.
+
+use std::{
+	collections::HashSet,
+	error::Error,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use async_trait::async_trait;
+use libp2p::PeerId;
+use tokio::sync::mpsc;
+
+use crate::{
+	events::EventObserver,
+	persist::atomic_write,
+	tasks::{TaskRecord, TaskStatus},
+	types::Event,
+};
+
+/// Escrow of the payments of tasks on a chain. Each method returns the id of the transaction it
+/// submitted.
+#[async_trait]
+pub trait TaskEscrow: std::fmt::Debug + Send + Sync {
+	/// Lock the `max_bid` of the task for its worker, paid at its `payment_address`, until the
+	/// deadline of the task.
+	async fn lock(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>>;
+
+	/// Pay the locked funds to the worker of the task.
+	async fn release(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>>;
+
+	/// Return the locked funds to the owner of the task.
+	async fn refund(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl<E: TaskEscrow + ?Sized> TaskEscrow for Arc<E> {
+	async fn lock(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		self.as_ref().lock(task).await
+	}
+
+	async fn release(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		self.as_ref().release(task).await
+	}
+
+	async fn refund(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		self.as_ref().refund(task).await
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscrowAction {
+	Locked,
+	Released,
+	Refunded,
+}
+
+/// A transaction submitted by [`EscrowSettlement`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settlement {
+	pub task_id: String,
+	pub action: EscrowAction,
+	pub transaction: String,
+}
+
+/// Settles the payments of the tasks auctioned by the node as their status changes, fed with the
+/// [`Event::TaskStatusChanged`] of the node. Failed tasks keep their funds locked, for the owner
+/// to dispute them or wait for their deadline to get a refund.
+pub struct EscrowSettlement<E> {
+	escrow: E,
+	local_peer_id: PeerId,
+	/// Tasks whose funds are locked.
+	locked: HashSet<String>,
+	/// File the locked tasks are kept in, for a restart not to lose the funds to settle.
+	store: Option<PathBuf>,
+}
+
+impl<E: TaskEscrow> EscrowSettlement<E> {
+	/// Settle through `escrow`, with the locked tasks loaded from `store` when set.
+	pub fn new(escrow: E, local_peer_id: PeerId, store: Option<PathBuf>) -> Self {
+		let locked = match store.as_deref().filter(|path| path.exists()).map(load) {
+			Some(Ok(locked)) => locked,
+			Some(Err(e)) => {
+				tracing::error!("Failed to load the escrow store: {e}");
+				HashSet::new()
+			},
+			None => HashSet::new(),
+		};
+		Self { escrow, local_peer_id, locked, store }
+	}
+
+	/// Lock, release or refund the payment of the task of the event, if any. Events of other
+	/// kinds, and of the tasks the node works on, are skipped.
+	pub async fn handle(
+		&mut self,
+		event: &Event,
+	) -> Option<Result<Settlement, Box<dyn Error + Send + Sync>>> {
+		let Event::TaskStatusChanged { task } = event else {
+			return None;
+		};
+		if task.owner != self.local_peer_id {
+			return None;
+		}
+		let locked = self.locked.contains(&task.task_id);
+		let (action, result) = match &task.status {
+			TaskStatus::Assigned if !locked => (EscrowAction::Locked, self.escrow.lock(task).await),
+			TaskStatus::Completed if locked => {
+				(EscrowAction::Released, self.escrow.release(task).await)
+			},
+			TaskStatus::TimedOut if locked => {
+				(EscrowAction::Refunded, self.escrow.refund(task).await)
+			},
+			_ => return None,
+		};
+		let transaction = match result {
+			Ok(transaction) => transaction,
+			Err(e) => {
+				tracing::error!("Failed to settle the payment of task {}: {e}", task.task_id);
+				return Some(Err(e));
+			},
+		};
+		match action {
+			EscrowAction::Locked => self.locked.insert(task.task_id.clone()),
+			EscrowAction::Released | EscrowAction::Refunded => self.locked.remove(&task.task_id),
+		};
+		self.persist();
+		tracing::info!("Payment of task {} {action:?} in {transaction}", task.task_id);
+		Some(Ok(Settlement { task_id: task.task_id.clone(), action, transaction }))
+	}
+
+	fn persist(&self) {
+		let Some(store) = &self.store else {
+			return;
+		};
+		if let Err(e) = save(store, &self.locked) {
+			tracing::error!("Failed to write the escrow store: {e}");
+		}
+	}
+}
+
+impl<E: TaskEscrow + 'static> EscrowSettlement<E> {
+	/// Settle the payments in the background, in the order the status changes are seen by the
+	/// returned observer, until the observer is dropped along with the event loop.
+	pub(crate) fn spawn(mut self) -> SettlementFeed {
+		let (sender, mut receiver) = mpsc::unbounded_channel();
+		tokio::spawn(async move {
+			while let Some(event) = receiver.recv().await {
+				self.handle(&event).await;
+			}
+		});
+		SettlementFeed(sender)
+	}
+}
+
+/// Feeds a spawned [`EscrowSettlement`] with the status changes of the tasks.
+#[derive(Debug)]
+pub(crate) struct SettlementFeed(mpsc::UnboundedSender<Event>);
+
+impl EventObserver for SettlementFeed {
+	fn observe(&self, event: &Event) {
+		if let Event::TaskStatusChanged { task } = event {
+			let _ = self.0.send(Event::TaskStatusChanged { task: task.clone() });
+		}
+	}
+}
+
+fn load(store: &Path) -> Result<HashSet<String>, Box<dyn Error>> {
+	Ok(serde_json::from_str(&std::fs::read_to_string(store)?)?)
+}
+
+/// Replace the store with the locked tasks at once, for a crash not to leave a truncated store
+/// behind.
+fn save(store: &Path, locked: &HashSet<String>) -> Result<(), Box<dyn Error>> {
+	let mut locked: Vec<_> = locked.iter().collect();
+	locked.sort_unstable();
+	atomic_write(store, &serde_json::to_vec_pretty(&locked)?)?;
+	Ok(())
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::testing::RecordingEscrow;
+
+	fn changed(task_id: &str, owner: PeerId, status: TaskStatus) -> Event {
+		Event::TaskStatusChanged {
+			task: TaskRecord {
+				task_id: task_id.to_string(),
+				owner,
+				worker: Some(PeerId::random()),
+				status,
+				max_bid: 10.0,
+				payment_address: None,
+				deadline: 100,
+				updated_at: 0,
+				commitment: None,
+			},
+		}
+	}
+
+	#[tokio::test]
+	async fn test_settlement_follows_the_status_of_the_tasks() {
+		let (local, other) = (PeerId::random(), PeerId::random());
+		let escrow = Arc::new(RecordingEscrow::default());
+		let mut settlement = EscrowSettlement::new(escrow.clone(), local, None);
+
+		for event in [
+			changed("done", local, TaskStatus::Assigned),
+			changed("done", local, TaskStatus::InProgress),
+			changed("done", local, TaskStatus::Completed),
+			changed("late", local, TaskStatus::Assigned),
+			changed("late", local, TaskStatus::TimedOut),
+			// Never locked, or worked on by the node.
+			changed("unassigned", local, TaskStatus::TimedOut),
+			changed("theirs", other, TaskStatus::Assigned),
+		] {
+			settlement.handle(&event).await;
+		}
+		let settled = settlement.handle(&changed("done", local, TaskStatus::Completed)).await;
+		assert!(settled.is_none(), "Released twice");
+
+		assert_eq!(
+			*escrow.calls.lock().unwrap(),
+			["lock done 10", "release done 10", "lock late 10", "refund late 10"]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_locked_tasks_survive_a_restart() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = dir.path().join("escrow.json");
+		let local = PeerId::random();
+		let escrow = Arc::new(RecordingEscrow::default());
+
+		let mut settlement = EscrowSettlement::new(escrow.clone(), local, Some(store.clone()));
+		settlement.handle(&changed("task", local, TaskStatus::Assigned)).await;
+		drop(settlement);
+		let mut restarted = EscrowSettlement::new(escrow.clone(), local, Some(store));
+		restarted.handle(&changed("task", local, TaskStatus::Assigned)).await;
+		restarted.handle(&changed("task", local, TaskStatus::Completed)).await;
+
+		assert_eq!(*escrow.calls.lock().unwrap(), ["lock task 10", "release task 10"]);
+		assert!(restarted.locked.is_empty());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -1,85 +1,387 @@
 use std::{
 	collections::{hash_map, HashMap, HashSet},
 	error::Error,
-	time::Duration,
+	path::PathBuf,
+	sync::Arc,
+	time::{Duration, Instant, SystemTime},
 };
 
 use futures::{
 	channel::{mpsc, oneshot},
+	future::BoxFuture,
 	prelude::*,
+	stream::{FusedStream, FuturesUnordered},
 	StreamExt,
 };
 use libp2p::{
-	autonat, gossipsub, identify, kad, mdns,
+	allow_block_list, autonat, connection_limits,
+	core::transport::ListenerId,
+	gossipsub, identify, identity, kad, mdns,
 	multiaddr::Protocol,
 	ping, relay, rendezvous,
-	request_response::{self, OutboundRequestId},
-	swarm::{Swarm, SwarmEvent},
+	request_response::{self, InboundRequestId, OutboundRequestId, ResponseChannel},
+	swarm::{
+		dial_opts::{DialOpts, PeerCondition},
+		ConnectionDenied, ConnectionId, DialError, ListenError, Swarm, SwarmEvent,
+	},
 	upnp, Multiaddr, PeerId,
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::types::{Command, Event, LLMRequest, LLMResponse};
+use crate::types::{
+	ArtifactRequest, ArtifactResponse, Command, ConnectedPeer, DiscoverySource, Event,
+	EventLoopError, KBucketInfo, LLMRequest, LLMResponse, NetworkError, RecordError, RoutingPeer,
+};
 use crate::{
-	behaviour::{AsnBehaviour, AsnBehaviourEvent},
-	types::{deserialize_message, TaskProposal},
+	agent_card::{agent_key, provider_key, AgentCard},
+	alerts::{self, Alert, AlertAction, AlertEngine},
+	artifacts::artifact_key,
+	auction::{Assignment, Auction, AuctionConfig, AuctionState, AuctionWinner, RecordReceiver},
+	behaviour::{
+		AsnBehaviour, AsnBehaviourEvent, CAPABILITIES_TOPIC, DIRECTORY_SYNC_PROTOCOL_VERSION,
+		TASKS_TOPIC,
+	},
+	bulletin::{self, Bulletin, BulletinBoard, BulletinError, BulletinKind},
+	capabilities::NodeCapabilities,
+	challenge::{self, Challenges},
+	cid::Cid,
+	commitment::{ResultAck, ResultCommitment, TaskResultMessage},
+	config::AgentTopicsConfig,
+	directory::{
+		AgentFilter, AnnouncementError, CapabilityAnnouncement, CapabilityDirectory,
+		DirectoryConfig, ANNOUNCEMENT_VERSION,
+	},
+	discovery::{by_peer, InterfaceFilter, MdnsConfig},
+	encryption::{self, SessionKey},
+	events::EventSender,
+	history::{HistoryConfig, MessageHistory},
+	labels::AddressBook,
+	latency::RttHistogram,
+	metrics::NetworkMetrics,
+	moderation::TopicModeration,
+	policy::{RequestAttributes, RequestPolicy},
+	presence::{Beacon, PresenceConfig, PresenceTable, PRESENCE_TOPIC},
+	protocol::ProtocolVersion,
+	query::{PendingQuery, QueryProgress},
+	reconnect::{ReconnectConfig, ReconnectManager, ReconnectState},
+	reputation::{self, reputation_key, Attestation, Outcome, ReputationLedger},
+	request::{RequestHandle, RequestResult},
+	resume::{NetworkMonitor, ResumeConfig},
+	selection::{CircuitBreaker, CircuitBreakerConfig},
+	signed::SignedPayload,
+	snapshot::{
+		NetworkSnapshot, SnapshotAgent, MAX_SNAPSHOT_AGENTS, MAX_SNAPSHOT_PEERS, SNAPSHOT_VERSION,
+	},
+	stream::{now_ms, ProviderStream, StreamRequest, StreamResponse},
+	tasks::{TaskError, TaskRecord, TaskStatus, TaskStatusUpdate, TaskTracker},
+	timeouts::{AdaptiveTimeouts, RequestTimeoutConfig},
+	types::{deserialize_message, serialize_message, TaskAward, TaskProposal},
 };
 
 type PendingDialResult = Result<(), Box<dyn Error + Send>>;
 type PendingDialSender = oneshot::Sender<PendingDialResult>;
 type FileRequestResult = Result<Vec<u8>, Box<dyn Error + Send>>;
 type FileRequestSender = oneshot::Sender<FileRequestResult>;
+type PutRecordSender = oneshot::Sender<Result<(), Box<dyn Error + Send>>>;
+type GetRecordSender = oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>;
+type ClosestPeersSender = oneshot::Sender<Result<Vec<PeerId>, Box<dyn Error + Send>>>;
+type StreamRequestSender = oneshot::Sender<Result<StreamResponse, Box<dyn Error + Send>>>;
+type BidSender = oneshot::Sender<Result<(), Box<dyn Error + Send>>>;
+type FeedbackSender = oneshot::Sender<Result<(), Box<dyn Error + Send>>>;
+type TaskStatusSender = oneshot::Sender<Result<(), Box<dyn Error + Send>>>;
+type TaskResultSender = oneshot::Sender<Result<(), Box<dyn Error + Send>>>;
+type DirectorySyncSender = oneshot::Sender<Result<usize, Box<dyn Error + Send>>>;
+type AuctionSender = oneshot::Sender<Result<Option<AuctionWinner>, Box<dyn Error + Send>>>;
+type DialBackSender = oneshot::Sender<Result<Duration, Box<dyn Error + Send>>>;
+type StreamKey = (PeerId, u64);
+/// An acknowledgement waiting for the next tokens of a stream.
+type StreamPull = BoxFuture<
+	'static,
+	(StreamKey, ResponseChannel<StreamResponse>, Option<ProviderStream>, StreamResponse),
+>;
+
+/// What the node learned about a connected peer.
+struct PeerInfo {
+	connected_since: tokio::time::Instant,
+	agent_version: Option<String>,
+	protocols: Vec<String>,
+	/// Capabilities announced in the agent version of the peer, when it is a node of the swarm.
+	capabilities: Option<NodeCapabilities>,
+	rtts: RttHistogram,
+}
+
+/// A `get_record` query waiting for enough copies of the record to reach its quorum.
+struct PendingGetRecord {
+	sender: GetRecordSender,
+	required: usize,
+	values: Vec<Vec<u8>>,
+}
 
 static NAMESPACE: &str = "dasn";
+const DISCOVER_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the routing table is checked, the bootstrap peers are dialed again once it is empty.
+const REBOOTSTRAP_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a shutting down node keeps driving the swarm to flush its last messages.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+/// How often the agent requests are checked against their adaptive timeout.
+const TIMEOUTS_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct EventLoop {
 	swarm: Swarm<AsnBehaviour>,
+	id_key: identity::Keypair,
 	command_receiver: mpsc::Receiver<Command>,
-	event_sender: mpsc::Sender<Event>,
+	event_sender: EventSender,
+	metrics: NetworkMetrics,
+	moderation: TopicModeration,
+	bulletins: BulletinBoard,
 	agents_providing: Vec<String>,
+	agent_topics: AgentTopicsConfig,
+	/// Cards of the agents provided, by name.
+	provided_cards: HashMap<String, AgentCard>,
+	challenges: Challenges,
+	connected_peers: HashMap<PeerId, PeerInfo>,
+	address_book: AddressBook,
+	/// Banned peers, by the end of their ban.
+	bans: HashMap<PeerId, tokio::time::Instant>,
 	pending_dial: HashMap<PeerId, PendingDialSender>,
-	pending_start_providing: HashMap<kad::QueryId, oneshot::Sender<()>>,
-	pending_get_providers: HashMap<kad::QueryId, oneshot::Sender<HashSet<PeerId>>>,
-	pending_request: HashMap<OutboundRequestId, FileRequestSender>,
+	/// Dial-back checks, by the connection waiting for the first ping of the peer.
+	pending_dial_back: HashMap<ConnectionId, DialBackSender>,
+	pending_start_providing: HashMap<kad::QueryId, PendingQuery<()>>,
+	pending_get_providers: HashMap<kad::QueryId, PendingQuery<HashSet<PeerId>>>,
+	pending_request: HashMap<OutboundRequestId, oneshot::Sender<RequestResult>>,
+	/// Keys of the responses to the requests encrypted end to end.
+	sealed_requests: HashMap<OutboundRequestId, SessionKey>,
+	/// Agent requests delivered to the application and not answered yet.
+	inbound_requests: HashSet<InboundRequestId>,
+	max_inbound_requests: Option<usize>,
+	request_policies: HashMap<String, RequestPolicy>,
+	/// Deadlines of the agent requests, adapted to the response times of their providers.
+	request_timeouts: AdaptiveTimeouts,
+	/// Last presence beacon of each peer.
+	presence: PresenceTable,
+	presence_config: PresenceConfig,
+	/// Latest capability announcement of each provider.
+	directory: CapabilityDirectory,
+	directory_config: DirectoryConfig,
+	pending_artifact_request: HashMap<OutboundRequestId, FileRequestSender>,
+	pending_put_record: HashMap<kad::QueryId, PutRecordSender>,
+	pending_get_record: HashMap<kad::QueryId, PendingGetRecord>,
+	pending_get_closest_peers: HashMap<kad::QueryId, ClosestPeersSender>,
+	pending_stream_request: HashMap<OutboundRequestId, StreamRequestSender>,
+	pending_bid: HashMap<OutboundRequestId, BidSender>,
+	pending_feedback: HashMap<OutboundRequestId, FeedbackSender>,
+	pending_task_status: HashMap<OutboundRequestId, TaskStatusSender>,
+	/// Commitments and reveals sent to the owners of tasks, along with the task a reveal completes
+	/// once accepted.
+	pending_task_results: HashMap<OutboundRequestId, (Option<String>, TaskResultSender)>,
+	/// Directory syncs under way, the one made on startup having no sender.
+	pending_directory_sync: HashMap<OutboundRequestId, Option<DirectorySyncSender>>,
+	/// Whether the directory was pulled from a peer since the node started.
+	directory_synced: bool,
+	/// Auctions collecting bids, by task id.
+	auctions: HashMap<String, (Auction, AuctionSender)>,
+	/// Awarded tasks of the node watched for their deadline, by task id.
+	assignments: HashMap<String, Assignment>,
+	/// Attestations of defaults waiting for the reputation ledger they are added to.
+	pending_attestations: Vec<(PeerId, SignedPayload, RecordReceiver)>,
+	tasks: TaskTracker,
+	/// Circuits of the providers, fed with the outcome of every agent request.
+	circuits: CircuitBreaker,
+	streams: HashMap<StreamKey, ProviderStream>,
+	stream_pulls: FuturesUnordered<StreamPull>,
+	next_stream_id: u64,
 	cookie: Option<rendezvous::Cookie>,
 	namespace: Option<rendezvous::Namespace>,
 	rendezvous_point: Option<PeerId>,
 	rendezvous_point_address: Option<Multiaddr>,
 	external_address: Option<Multiaddr>,
+	reconnect: ReconnectManager,
+	monitor: NetworkMonitor,
+	history: MessageHistory,
+	interface_filter: InterfaceFilter,
+	/// Addresses listened on, by listener, and the ones whose listener closed.
+	listeners: HashMap<ListenerId, Multiaddr>,
+	closed_listeners: Vec<Multiaddr>,
+	bootstrap_peers: Vec<Multiaddr>,
+	nat_status: autonat::NatStatus,
+	alerts: Arc<AlertEngine>,
+	/// Client posting the alerts to their webhooks, sharing its connections across alerts.
+	webhooks: reqwest::Client,
+	/// x25519 key advertised in the published cards, when end-to-end encryption is enabled.
+	encryption_key: Option<[u8; 32]>,
 }
 
 impl EventLoop {
 	#[allow(clippy::too_many_arguments)]
 	pub fn new(
 		swarm: Swarm<AsnBehaviour>,
+		id_key: identity::Keypair,
 		command_receiver: mpsc::Receiver<Command>,
-		event_sender: mpsc::Sender<Event>,
+		event_sender: EventSender,
+		metrics: NetworkMetrics,
+		moderation: TopicModeration,
+		bulletins: BulletinBoard,
 		namespace: Option<rendezvous::Namespace>,
 		rendezvous_point: Option<PeerId>,
 		rendezvous_point_address: Option<Multiaddr>,
 		external_address: Option<Multiaddr>,
+		reconnect: ReconnectConfig,
+		resume: ResumeConfig,
+		history: HistoryConfig,
+		history_store: Option<PathBuf>,
+		mdns: &MdnsConfig,
+		bootstrap_peers: Vec<Multiaddr>,
+		alerts: Arc<AlertEngine>,
+		encryption_key: Option<[u8; 32]>,
+		agent_topics: AgentTopicsConfig,
+		max_inbound_requests: Option<usize>,
+		request_policies: HashMap<String, RequestPolicy>,
+		presence: PresenceConfig,
+		request_timeouts: RequestTimeoutConfig,
+		task_store: Option<PathBuf>,
+		circuit_breaker: CircuitBreakerConfig,
+		circuit_store: Option<PathBuf>,
+		address_book: Option<PathBuf>,
+		directory: DirectoryConfig,
+		snapshot: Option<NetworkSnapshot>,
 	) -> Self {
+		let mut reconnect = ReconnectManager::new(reconnect);
+		if let (Some(point), Some(address)) = (rendezvous_point, &rendezvous_point_address) {
+			reconnect.track(point, address.clone());
+		}
+
+		// A snapshot shortcuts the discovery of the peers and agents, relays first.
+		let mut bootstrap_peers = bootstrap_peers;
+		let mut capability_directory = CapabilityDirectory::new(&directory);
+		if let Some(snapshot) = snapshot {
+			let now = tokio::time::Instant::now();
+			for (provider, announcement) in snapshot.announcements() {
+				capability_directory.record(provider, announcement, None, now);
+			}
+			for peer in snapshot.relays.into_iter().chain(snapshot.peers) {
+				if !bootstrap_peers.contains(&peer) {
+					bootstrap_peers.push(peer);
+				}
+			}
+		}
+
 		Self {
 			swarm,
+			id_key,
 			command_receiver,
 			event_sender,
+			metrics,
+			moderation,
+			bulletins,
 			agents_providing: Default::default(),
+			agent_topics,
+			provided_cards: Default::default(),
+			challenges: Default::default(),
+			connected_peers: Default::default(),
+			address_book: AddressBook::open(address_book),
+			bans: Default::default(),
 			pending_dial: Default::default(),
+			pending_dial_back: Default::default(),
 			pending_start_providing: Default::default(),
 			pending_get_providers: Default::default(),
 			pending_request: Default::default(),
+			sealed_requests: Default::default(),
+			inbound_requests: Default::default(),
+			max_inbound_requests,
+			request_policies,
+			request_timeouts: AdaptiveTimeouts::new(request_timeouts),
+			presence: PresenceTable::new(&presence),
+			presence_config: presence,
+			directory: capability_directory,
+			directory_config: directory,
+			pending_artifact_request: Default::default(),
+			pending_put_record: Default::default(),
+			pending_get_record: Default::default(),
+			pending_get_closest_peers: Default::default(),
+			pending_stream_request: Default::default(),
+			pending_bid: Default::default(),
+			pending_feedback: Default::default(),
+			pending_task_status: Default::default(),
+			pending_task_results: Default::default(),
+			pending_directory_sync: Default::default(),
+			directory_synced: false,
+			auctions: Default::default(),
+			assignments: Default::default(),
+			pending_attestations: Default::default(),
+			tasks: TaskTracker::new(task_store),
+			circuits: CircuitBreaker::open(circuit_breaker, circuit_store),
+			streams: Default::default(),
+			stream_pulls: Default::default(),
+			next_stream_id: 0,
 			cookie: None,
 			namespace,
 			rendezvous_point,
 			rendezvous_point_address,
 			external_address,
+			reconnect,
+			monitor: NetworkMonitor::new(resume),
+			history: MessageHistory::open(history, history_store),
+			interface_filter: InterfaceFilter::new(mdns),
+			listeners: Default::default(),
+			closed_listeners: Default::default(),
+			bootstrap_peers,
+			nat_status: autonat::NatStatus::Unknown,
+			alerts,
+			webhooks: reqwest::Client::builder()
+				.timeout(alerts::WEBHOOK_TIMEOUT)
+				.build()
+				.expect("Webhook client to be built."),
+			encryption_key,
+		}
+	}
+
+	/// The view of the swarm of the node: the peers of the routing table it is connected to, the
+	/// relays among them and a sample of its capability directory.
+	fn network_snapshot(&mut self) -> NetworkSnapshot {
+		let (mut peers, mut relays) = (Vec::new(), Vec::new());
+		for bucket in self.swarm.behaviour_mut().kademlia.kbuckets() {
+			for entry in bucket.iter() {
+				if !matches!(entry.status, kad::NodeStatus::Connected) {
+					continue;
+				}
+				let peer_id = *entry.node.key.preimage();
+				let relay = self.connected_peers.get(&peer_id).is_some_and(|peer| {
+					peer.protocols
+						.iter()
+						.any(|protocol| protocol == relay::HOP_PROTOCOL_NAME.as_ref())
+				});
+				for address in entry.node.value.iter() {
+					let address = address.clone().with_p2p(peer_id).unwrap_or_else(|a| a);
+					if relay {
+						relays.push(address.clone());
+					}
+					peers.push(address);
+				}
+			}
 		}
+		peers.truncate(MAX_SNAPSHOT_PEERS);
+		relays.truncate(MAX_SNAPSHOT_PEERS);
+		let mut agents: Vec<_> = self
+			.directory
+			.find(&AgentFilter::default(), tokio::time::Instant::now())
+			.into_iter()
+			.map(|listing| SnapshotAgent {
+				provider: listing.provider,
+				card: listing.card,
+				announced_at: listing.announced_at,
+			})
+			.collect();
+		agents.truncate(MAX_SNAPSHOT_AGENTS);
+		NetworkSnapshot { version: SNAPSHOT_VERSION, timestamp: now_ms(), peers, relays, agents }
 	}
 
 	fn dial_rendezvous_point_address(&mut self) {
 		tracing::info!("Dialing rendezvous point address");
 		if let Some(rendezvous_point_address) = &self.rendezvous_point_address {
-			self.swarm.dial(rendezvous_point_address.clone()).unwrap();
+			if let Err(error) = self.swarm.dial(rendezvous_point_address.clone()) {
+				let address = rendezvous_point_address.clone();
+				self.report(EventLoopError::Dial { address, error });
+			}
 		}
 	}
 
@@ -103,6 +405,35 @@ impl EventLoop {
 		}
 	}
 
+	/// Dial the bootstrap peers, adding the ones with a known peer id to the routing table, and
+	/// bootstrap the DHT through them.
+	fn dial_bootstrap_peers(&mut self) {
+		for address in self.bootstrap_peers.clone() {
+			if let Some(Protocol::P2p(peer_id)) = address.iter().last() {
+				let mut peer_address = address.clone();
+				peer_address.pop();
+				self.swarm.behaviour_mut().kademlia.add_address(&peer_id, peer_address);
+				self.reconnect.track(peer_id, address.clone());
+			}
+			tracing::info!("Dialing bootstrap peer {address}");
+			if let Err(e) = self.swarm.dial(address.clone()) {
+				tracing::warn!("Failed to dial bootstrap peer {address}: {e}");
+			}
+		}
+
+		if let Err(e) = self.swarm.behaviour_mut().kademlia.bootstrap() {
+			tracing::warn!("Failed to bootstrap: {e}");
+		}
+	}
+
+	fn routing_table_is_empty(&mut self) -> bool {
+		self.swarm
+			.behaviour_mut()
+			.kademlia
+			.kbuckets()
+			.all(|bucket| bucket.num_entries() == 0)
+	}
+
 	fn add_external_address(&mut self) {
 		tracing::info!("Adding external address");
 		if let Some(external_address) = &self.external_address {
@@ -110,18 +441,759 @@ impl EventLoop {
 		}
 	}
 
+	async fn report_reconnect(&mut self, peer_id: PeerId, state: ReconnectState) {
+		tracing::info!("Connection to {peer_id}: {state:?}");
+		self.event_sender.send(Event::Reconnect { peer_id, state });
+	}
+
+	async fn report_limit_reached(&mut self, peer_id: Option<PeerId>, cause: &ConnectionDenied) {
+		if let Some(exceeded) = cause.downcast_ref::<connection_limits::Exceeded>() {
+			tracing::warn!("Connection with {peer_id:?} denied: {exceeded}");
+			self.event_sender
+				.send(Event::ConnectionLimitReached { peer_id, exceeded: *exceeded });
+		} else if cause.downcast_ref::<allow_block_list::Blocked>().is_some() {
+			tracing::info!("Connection with banned peer {peer_id:?} denied");
+		}
+	}
+
+	fn lift_expired_bans(&mut self) {
+		let now = tokio::time::Instant::now();
+		self.bans.retain(|peer_id, until| {
+			let expired = *until <= now;
+			if expired {
+				tracing::info!("Ban of {peer_id} lifted");
+				self.swarm.behaviour_mut().blocked_peers.unblock_peer(*peer_id);
+			}
+			!expired
+		});
+	}
+
+	async fn redial_due_peers(&mut self) {
+		for (peer_id, address) in self.reconnect.due(tokio::time::Instant::now()) {
+			tracing::info!("Reconnecting to {peer_id} at {address}");
+			let opts = DialOpts::peer_id(peer_id).addresses(vec![address]).build();
+			if let Err(e) = self.swarm.dial(opts) {
+				tracing::warn!("Failed to reconnect to {peer_id}: {e}");
+				if let Some(state) =
+					self.reconnect.disconnected(&peer_id, tokio::time::Instant::now())
+				{
+					self.report_reconnect(peer_id, state).await;
+				}
+			}
+		}
+	}
+
+	/// Recover from a suspend or a network change: listen again where listeners closed, redial
+	/// the tracked peers right away, bootstrap the DHT, register with the rendezvous point again
+	/// and renew the gossip subscriptions.
+	async fn resume(&mut self, suspended: Option<Duration>) {
+		match suspended {
+			Some(suspended) => tracing::info!("Resuming after a suspend of {suspended:?}"),
+			None => tracing::info!("Resuming after a network change"),
+		}
+
+		for address in std::mem::take(&mut self.closed_listeners) {
+			match self.swarm.listen_on(address.clone()) {
+				Ok(listener_id) => {
+					self.listeners.insert(listener_id, address);
+				},
+				Err(e) => tracing::warn!("Failed to listen on {address} again: {e}"),
+			}
+		}
+		for (peer_id, address) in self.reconnect.reset() {
+			if self.swarm.is_connected(&peer_id) {
+				continue;
+			}
+			let opts = DialOpts::peer_id(peer_id).addresses(vec![address]).build();
+			if let Err(e) = self.swarm.dial(opts) {
+				tracing::warn!("Failed to redial {peer_id}: {e}");
+			}
+		}
+		if let Err(e) = self.swarm.behaviour_mut().kademlia.bootstrap() {
+			tracing::warn!("Failed to bootstrap: {e}");
+		}
+		self.register_rendezvous_point();
+		// Subscribing anew announces the topics again and rejoins their meshes.
+		let topics: Vec<_> = self.swarm.behaviour().gossipsub.topics().cloned().collect();
+		for topic in topics {
+			let gossipsub = &mut self.swarm.behaviour_mut().gossipsub;
+			gossipsub.unsubscribe(&gossipsub::IdentTopic::new(topic.as_str()));
+			if let Err(e) = gossipsub.subscribe(&gossipsub::IdentTopic::new(topic.as_str())) {
+				tracing::warn!("Failed to subscribe to {topic} again: {e}");
+			}
+		}
+
+		self.event_sender.send(Event::NetworkResumed { suspended });
+	}
+
+	fn update_gossipsub_metrics(&mut self) {
+		let gossipsub = &self.swarm.behaviour().gossipsub;
+		for topic in gossipsub.topics() {
+			self.metrics
+				.set_gossipsub_mesh_peers(topic.as_str(), gossipsub.mesh_peers(topic).count());
+		}
+	}
+
+	/// The pending query a started providing completes, reporting the completions of the queries
+	/// no longer pending, e.g. failed at shutdown.
+	fn take_start_providing(&mut self, id: kad::QueryId) -> Option<PendingQuery<()>> {
+		let query = self.pending_start_providing.remove(&id);
+		if query.is_none() {
+			self.report(EventLoopError::UnknownQuery(id));
+		}
+		query
+	}
+
+	/// Report a failure the event loop recovered from.
+	fn report(&mut self, error: EventLoopError) {
+		tracing::error!("{error}");
+		self.event_sender.send(Event::Error { error });
+	}
+
+	/// Fail the agent requests whose provider did not answer within its adaptive timeout.
+	fn expire_requests(&mut self) {
+		for (request_id, peer, timeout) in self.request_timeouts.expired(Instant::now()) {
+			self.sealed_requests.remove(&request_id);
+			if let Some(sender) = self.pending_request.remove(&request_id) {
+				tracing::warn!("Request {request_id} timed out after {timeout:?}");
+				self.circuits.record_failure(peer, now_ms());
+				let _ = sender.send(Err(Box::new(NetworkError::Timeout(timeout))));
+			}
+		}
+	}
+
+	/// Gossip the proposal of an auction and collect the bids sent back until its window closes.
+	fn open_auction(
+		&mut self,
+		proposal: TaskProposal,
+		config: AuctionConfig,
+		sender: AuctionSender,
+	) {
+		let task_id = proposal.task_id.clone();
+		if self.auctions.contains_key(&task_id) {
+			let _ = sender.send(Err(Box::new(NetworkError::AuctionRunning(task_id))));
+			return;
+		}
+		let data = match serialize_message(&proposal) {
+			Ok(data) => data,
+			Err(e) => {
+				let _ = sender.send(Err(Box::new(e)));
+				return;
+			},
+		};
+		let topic = gossipsub::IdentTopic::new(TASKS_TOPIC);
+		if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
+			let _ = sender.send(Err(Box::new(NetworkError::Gossip(e.to_string()))));
+			return;
+		}
+
+		tracing::info!("Auctioning task {task_id}");
+		let window = config.window;
+		self.track_task(TaskRecord {
+			task_id: task_id.clone(),
+			owner: *self.swarm.local_peer_id(),
+			worker: None,
+			status: TaskStatus::Proposed,
+			max_bid: proposal.max_bid,
+			payment_address: None,
+			deadline: proposal.deadline,
+			updated_at: now_ms() / 1000,
+			commitment: None,
+		});
+		self.auctions.insert(task_id.clone(), (Auction::new(proposal, config), sender));
+		self.event_sender
+			.send(Event::Auction { task_id, state: AuctionState::Open { window } });
+	}
+
+	/// Look up the record under `key` on the DHT until `quorum` copies of it are found.
+	fn get_record(&mut self, key: String, quorum: kad::Quorum, sender: GetRecordSender) {
+		tracing::info!("Getting record {key}");
+		let query_id = self.swarm.behaviour_mut().kademlia.get_record(kad::RecordKey::new(&key));
+		let required = match quorum {
+			kad::Quorum::One => 1,
+			kad::Quorum::Majority => kad::K_VALUE.get() / 2 + 1,
+			kad::Quorum::All => kad::K_VALUE.get(),
+			kad::Quorum::N(n) => n.get(),
+		};
+		self.pending_get_record
+			.insert(query_id, PendingGetRecord { sender, required, values: Vec::new() });
+	}
+
+	/// Pick the winners of the auctions whose window closed and send them their award.
+	fn close_auctions(&mut self) {
+		let now = tokio::time::Instant::now();
+		let closed: Vec<_> = self
+			.auctions
+			.iter()
+			.filter(|(_, (auction, _))| auction.closes_at() <= now)
+			.map(|(task_id, _)| task_id.clone())
+			.collect();
+		for task_id in closed {
+			let Some((mut auction, sender)) = self.auctions.remove(&task_id) else {
+				continue;
+			};
+			let connected_peers = &self.connected_peers;
+			let ranking = auction.ranking(|peer| {
+				connected_peers.get(peer).and_then(|p| p.rtts.latency()).map(|l| l.median)
+			});
+			let winner = ranking.first().cloned();
+			let state = match &winner {
+				Some(AuctionWinner { bidder, bid }) => {
+					tracing::info!("Task {task_id} awarded to {bidder} for {}", bid.bid);
+					let award = TaskAward {
+						task_id: task_id.clone(),
+						bid: bid.bid,
+						max_bid: auction.proposal().max_bid,
+						deadline: auction.proposal().deadline,
+					};
+					self.swarm.behaviour_mut().awards.send_request(bidder, award);
+					let payment_address = bid.payment_address.clone();
+					match self.tasks.assign(&task_id, *bidder, payment_address, now_ms() / 1000) {
+						Ok(task) => self.event_sender.send(Event::TaskStatusChanged { task }),
+						Err(e) => tracing::warn!("Failed to assign task {task_id}: {e}"),
+					}
+					let state = AuctionState::Awarded { winner: *bidder, bid: bid.bid };
+					if let Some(assignment) = auction.assign(ranking, now_ms() / 1000) {
+						self.assignments.insert(task_id.clone(), assignment);
+					}
+					state
+				},
+				None => {
+					tracing::info!("No acceptable bid for task {task_id}");
+					let failed = TaskStatus::Failed { reason: "No acceptable bid".to_string() };
+					if let Err(e) = self.transition_task(&task_id, failed) {
+						tracing::warn!("Failed to fail task {task_id}: {e}");
+					}
+					AuctionState::NoBids
+				},
+			};
+			self.event_sender.send(Event::Auction { task_id, state });
+			let _ = sender.send(Ok(winner));
+		}
+	}
+
+	/// Start tracking a task of the node.
+	fn track_task(&mut self, task: TaskRecord) {
+		self.tasks.insert(task.clone());
+		self.event_sender.send(Event::TaskStatusChanged { task });
+	}
+
+	/// Move a task of the node to `status`.
+	fn transition_task(&mut self, task_id: &str, status: TaskStatus) -> Result<(), TaskError> {
+		let task = self.tasks.transition(task_id, status, now_ms() / 1000)?;
+		self.event_sender.send(Event::TaskStatusChanged { task });
+		Ok(())
+	}
+
+	/// Hand the awarded tasks whose worker missed the deadline to the next-best bidder of their
+	/// auction, attesting the default in the reputation ledger of the worker. Tasks without a
+	/// bidder left time out, as does the task of the worker that defaulted on its side.
+	fn reassign_overdue(&mut self) {
+		let now = now_ms() / 1000;
+		let tasks = &self.tasks;
+		self.assignments
+			.retain(|task_id, _| tasks.get(task_id).is_some_and(|task| !task.status.is_terminal()));
+		let overdue: Vec<_> = self
+			.assignments
+			.iter()
+			.filter(|(_, assignment)| assignment.deadline() <= now)
+			.map(|(task_id, _)| task_id.clone())
+			.collect();
+		for task_id in overdue {
+			let Some(mut assignment) = self.assignments.remove(&task_id) else {
+				continue;
+			};
+			let defaulted = assignment.worker();
+			tracing::warn!("Worker {defaulted} missed the deadline of task {task_id}");
+			self.attest_default(defaulted, &task_id);
+			let Some((AuctionWinner { bidder, bid }, deadline)) = assignment.reassign(now) else {
+				continue;
+			};
+			tracing::info!("Task {task_id} reassigned to {bidder} for {}", bid.bid);
+			let award = TaskAward {
+				task_id: task_id.clone(),
+				bid: bid.bid,
+				max_bid: assignment.proposal().max_bid,
+				deadline,
+			};
+			self.swarm.behaviour_mut().awards.send_request(&bidder, award);
+			match self.tasks.reassign(&task_id, bidder, bid.payment_address, deadline, now) {
+				Ok(task) => self.event_sender.send(Event::TaskStatusChanged { task }),
+				Err(e) => {
+					tracing::warn!("Failed to reassign task {task_id}: {e}");
+					continue;
+				},
+			}
+			let state = AuctionState::Reassigned { defaulted, winner: bidder, bid: bid.bid };
+			self.event_sender.send(Event::Auction { task_id: task_id.clone(), state });
+			self.assignments.insert(task_id, assignment);
+		}
+	}
+
+	/// Attest that `provider` defaulted on `task_id`, once its reputation ledger is read.
+	fn attest_default(&mut self, provider: PeerId, task_id: &str) {
+		let attestation = Attestation {
+			provider,
+			task_id: task_id.to_string(),
+			outcome: Outcome::Failed,
+			issued_at: now_ms(),
+		};
+		let signed = match attestation.sign(&self.id_key) {
+			Ok(signed) => signed,
+			Err(e) => {
+				tracing::error!("Failed to sign the default of {provider}: {e}");
+				return;
+			},
+		};
+		let (sender, receiver) = oneshot::channel();
+		self.get_record(reputation_key(&provider), kad::Quorum::One, sender);
+		self.pending_attestations.push((provider, signed, receiver));
+	}
+
+	/// Add the pending attestations whose ledger was read to it and store it back on the DHT.
+	fn publish_attestations(&mut self) {
+		for (provider, signed, mut lookup) in std::mem::take(&mut self.pending_attestations) {
+			let ledger = match lookup.try_recv() {
+				Ok(None) => {
+					self.pending_attestations.push((provider, signed, lookup));
+					continue;
+				},
+				Ok(Some(Ok(data))) => ReputationLedger::decode(provider, &data),
+				Ok(Some(Err(e))) if reputation::is_not_found(e.as_ref()) => {
+					Ok(ReputationLedger::new(provider))
+				},
+				Ok(Some(Err(e))) => {
+					tracing::warn!("Failed to read the reputation ledger of {provider}: {e}");
+					continue;
+				},
+				Err(oneshot::Canceled) => continue,
+			};
+			let data = ledger.and_then(|mut ledger| {
+				ledger.add(signed)?;
+				Ok(ledger.encode()?)
+			});
+			let data = match data {
+				Ok(data) => data,
+				Err(e) => {
+					tracing::error!("Failed to update the reputation ledger of {provider}: {e}");
+					continue;
+				},
+			};
+			let record = kad::Record::new(kad::RecordKey::new(&reputation_key(&provider)), data);
+			if let Err(e) = self.swarm.behaviour_mut().kademlia.put_record(record, kad::Quorum::One)
+			{
+				tracing::error!("Failed to store the reputation ledger of {provider}: {e}");
+			}
+		}
+	}
+
+	/// Time out the unfinished tasks of the node whose deadline passed.
+	fn expire_tasks(&mut self) {
+		for task in self.tasks.expire(now_ms() / 1000) {
+			tracing::warn!("Task {} timed out", task.task_id);
+			self.event_sender.send(Event::TaskStatusChanged { task });
+		}
+	}
+
+	/// Update the status of a task of the node, reporting it to the owner of the task unless the
+	/// node owns it.
+	fn update_task_status(
+		&mut self,
+		task_id: String,
+		status: TaskStatus,
+		sender: TaskStatusSender,
+	) {
+		let Some(owner) = self.tasks.get(&task_id).map(|task| task.owner) else {
+			let _ = sender.send(Err(Box::new(NetworkError::from(TaskError::Unknown(task_id)))));
+			return;
+		};
+		if let Err(e) = self.transition_task(&task_id, status.clone()) {
+			let _ = sender.send(Err(Box::new(NetworkError::from(e))));
+			return;
+		}
+		if owner == *self.swarm.local_peer_id() {
+			let _ = sender.send(Ok(()));
+			return;
+		}
+		let update = TaskStatusUpdate { task_id, status };
+		let request_id = self.swarm.behaviour_mut().task_status.send_request(&owner, update);
+		self.pending_task_status.insert(request_id, sender);
+	}
+
+	/// Apply the status update of a task owned by the node, sent by the worker of the task.
+	fn handle_task_status_update(&mut self, peer: PeerId, update: TaskStatusUpdate) {
+		let TaskStatusUpdate { task_id, status } = update;
+		let local_peer_id = *self.swarm.local_peer_id();
+		match self.tasks.get(&task_id) {
+			Some(task) if task.owner == local_peer_id && task.worker == Some(peer) => {
+				if let Err(e) = self.transition_task(&task_id, status) {
+					tracing::warn!("Refused the status update of {peer}: {e}");
+				}
+			},
+			_ => tracing::warn!("Refused the status update of {peer} for task {task_id}"),
+		}
+	}
+
+	/// Send a commitment to, or the reveal of, the result of a task won by the node to the owner
+	/// of the task.
+	fn send_task_result(
+		&mut self,
+		task_id: String,
+		message: TaskResultMessage,
+		sender: TaskResultSender,
+	) {
+		let local_peer_id = *self.swarm.local_peer_id();
+		let owner = match self.tasks.get(&task_id) {
+			Some(task) if task.worker == Some(local_peer_id) => task.owner,
+			_ => {
+				let _ = sender.send(Err(Box::new(NetworkError::from(TaskError::Unknown(task_id)))));
+				return;
+			},
+		};
+		let completes = matches!(message, TaskResultMessage::Reveal { .. }).then_some(task_id);
+		let request_id = self.swarm.behaviour_mut().task_results.send_request(&owner, message);
+		self.pending_task_results.insert(request_id, (completes, sender));
+	}
+
+	/// Ask `peer` for the capability announcements newer than the ones of the directory.
+	fn sync_directory(&mut self, peer: PeerId, sender: Option<DirectorySyncSender>) {
+		tracing::info!("Syncing the capability directory from {peer}");
+		let request = self.directory.sync_request(tokio::time::Instant::now());
+		let request_id = self.swarm.behaviour_mut().directory_sync.send_request(&peer, request);
+		self.pending_directory_sync.insert(request_id, sender);
+	}
+
+	/// Record the commitment or check the reveal of the result of a task owned by the node, sent
+	/// by `peer`.
+	fn handle_task_result(&mut self, peer: PeerId, message: TaskResultMessage) -> ResultAck {
+		let task_id = match &message {
+			TaskResultMessage::Commit(signed) => match ResultCommitment::verify(signed) {
+				Ok((signer, commitment)) if signer == peer => commitment.task_id,
+				Ok(_) => return ResultAck::Refused("Commitment signed by another peer".into()),
+				Err(e) => return ResultAck::Refused(e.to_string()),
+			},
+			TaskResultMessage::Reveal { task_id, .. } => task_id.clone(),
+		};
+		let local_peer_id = *self.swarm.local_peer_id();
+		let commitment = match self.tasks.get(&task_id) {
+			Some(task) if task.owner == local_peer_id && task.worker == Some(peer) => {
+				task.commitment.clone()
+			},
+			_ => return ResultAck::Refused(format!("Not the worker of task {task_id}")),
+		};
+
+		match message {
+			TaskResultMessage::Commit(signed) => match self.tasks.commit(&task_id, signed) {
+				Ok(_) => {
+					tracing::info!("Worker {peer} committed to the result of task {task_id}");
+					ResultAck::Accepted
+				},
+				Err(e) => ResultAck::Refused(e.to_string()),
+			},
+			TaskResultMessage::Reveal { result, .. } => {
+				let committed = commitment
+					.as_ref()
+					.and_then(|signed| ResultCommitment::verify(signed).ok())
+					.map(|(_, commitment)| commitment);
+				let Some(committed) = committed else {
+					return ResultAck::Refused(format!("No commitment to the result of {task_id}"));
+				};
+				if !committed.matches(&result) {
+					tracing::warn!(
+						"Result of task {task_id} revealed by {peer} is not the one committed"
+					);
+					let reason = "Result does not match its commitment".to_string();
+					let failed = TaskStatus::Failed { reason: reason.clone() };
+					if let Err(e) = self.transition_task(&task_id, failed) {
+						tracing::warn!("Failed to fail task {task_id}: {e}");
+					}
+					return ResultAck::Refused(reason);
+				}
+				if let Err(e) = self.transition_task(&task_id, TaskStatus::Completed) {
+					return ResultAck::Refused(e.to_string());
+				}
+				self.event_sender.send(Event::TaskResult { task_id, worker: peer, result });
+				ResultAck::Accepted
+			},
+		}
+	}
+
+	/// Announce the node and the agents it provides on the presence topic.
+	fn send_beacon(&mut self) {
+		self.presence.prune(tokio::time::Instant::now());
+		let beacon = Beacon { agents: self.agents_providing.clone() };
+		let data = match serialize_message(&beacon) {
+			Ok(data) => data,
+			Err(e) => {
+				tracing::error!("Failed to encode presence beacon: {e}");
+				return;
+			},
+		};
+		let topic = gossipsub::IdentTopic::new(PRESENCE_TOPIC);
+		if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
+			// Expected while no peer of the mesh subscribed to the topic.
+			tracing::debug!("Failed to send presence beacon: {e}");
+		}
+	}
+
+	/// Announce the cards of the provided agents on the capabilities topic, signed.
+	fn announce_capabilities(&mut self) {
+		self.directory.prune(tokio::time::Instant::now());
+		if self.provided_cards.is_empty() {
+			return;
+		}
+		let announcement = CapabilityAnnouncement {
+			version: ANNOUNCEMENT_VERSION,
+			timestamp: now_ms(),
+			agents: self.provided_cards.values().cloned().collect(),
+		};
+		let data = match announcement.sign(&self.id_key).map(|signed| serialize_message(&signed)) {
+			Ok(Ok(data)) => data,
+			Ok(Err(e)) => {
+				tracing::error!("Failed to encode capability announcement: {e}");
+				return;
+			},
+			Err(e) => {
+				tracing::error!("Failed to sign capability announcement: {e}");
+				return;
+			},
+		};
+		let topic = gossipsub::IdentTopic::new(CAPABILITIES_TOPIC);
+		if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
+			// Expected while no peer of the mesh subscribed to the topic.
+			tracing::debug!("Failed to send capability announcement: {e}");
+		}
+	}
+
+	/// Run the actions of the alerts raised since the last evaluation.
+	fn raise_alerts(&mut self) {
+		let now = tokio::time::Instant::now();
+		let raised = self.alerts.evaluate(|metric| self.metrics.alert_metric(metric), now);
+		for (alert, actions) in raised {
+			for action in actions {
+				self.run_alert_action(&alert, action);
+			}
+		}
+	}
+
+	fn run_alert_action(&mut self, alert: &Alert, action: AlertAction) {
+		match action {
+			AlertAction::Log => tracing::warn!("Alert {}: {}", alert.rule, alert.message),
+			AlertAction::Webhook { url } => {
+				let alert = alert.clone();
+				let webhooks = self.webhooks.clone();
+				tokio::spawn(async move {
+					let sent = webhooks
+						.post(&url)
+						.json(&alert)
+						.send()
+						.await
+						.and_then(|response| response.error_for_status());
+					if let Err(e) = sent {
+						tracing::error!("Failed to send alert {} to {url}: {e}", alert.rule);
+					}
+				});
+			},
+			AlertAction::Gossip { topic } => {
+				let message = match serialize_message(alert) {
+					Ok(message) => message,
+					Err(e) => {
+						self.report(e.into());
+						return;
+					},
+				};
+				let topic = gossipsub::IdentTopic::new(topic);
+				if let Err(e) = self.swarm.behaviour_mut().gossipsub.publish(topic, message) {
+					tracing::error!("Failed to gossip alert {}: {e}", alert.rule);
+				}
+			},
+		}
+	}
+
+	fn apply_bulletin(&mut self, bulletin: &Bulletin) {
+		match &bulletin.kind {
+			BulletinKind::BootstrapPeers { peers } => {
+				for address in peers {
+					let Some(Protocol::P2p(peer_id)) = address.iter().last() else {
+						tracing::warn!("Ignoring bootstrap peer without peer id: {address}");
+						continue;
+					};
+					self.swarm.behaviour_mut().kademlia.add_address(&peer_id, address.clone());
+					if let Err(e) = self.swarm.dial(address.clone()) {
+						tracing::warn!("Failed to dial recommended bootstrap peer {address}: {e}");
+					}
+				}
+			},
+			BulletinKind::Deprecation { protocol, notice } => {
+				tracing::warn!("Protocol {protocol} is deprecated: {notice}");
+			},
+			BulletinKind::TopicBan { topic } => {
+				if self.bulletins.ban_topic(topic) {
+					tracing::warn!("Unsubscribing from banned topic {topic}");
+					self.swarm
+						.behaviour_mut()
+						.gossipsub
+						.unsubscribe(&gossipsub::IdentTopic::new(topic));
+				} else {
+					tracing::warn!("Ignoring ban of pinned topic {topic}");
+				}
+			},
+			BulletinKind::Unknown(tag, _) => {
+				tracing::info!("Ignoring bulletin of unknown kind {tag}");
+			},
+		}
+	}
+
+	fn send_stream_response(
+		&mut self,
+		channel: ResponseChannel<StreamResponse>,
+		response: StreamResponse,
+	) {
+		if self.swarm.behaviour_mut().llm_stream.send_response(channel, response).is_err() {
+			tracing::warn!("Failed to send stream response, the requester went away");
+		}
+	}
+
+	async fn handle_stream_request(
+		&mut self,
+		peer: PeerId,
+		request: StreamRequest,
+		channel: ResponseChannel<StreamResponse>,
+	) {
+		match request {
+			StreamRequest::Open { ref agent_name, ref message, .. }
+				if self.requires_challenge(&peer, agent_name, message.len()) =>
+			{
+				let nonce = self.challenges.issue(peer);
+				self.send_stream_response(channel, StreamResponse::Challenge { nonce });
+			},
+			StreamRequest::Open { ref agent_name, ref message, .. }
+				if !self.is_allowed(peer, agent_name, None, message.len()) =>
+			{
+				tracing::warn!("Policy of {agent_name} denies the stream of {peer}");
+				let reason = format!("Policy of agent {agent_name} denies the request");
+				self.send_stream_response(channel, StreamResponse::Error(reason));
+			},
+			StreamRequest::Open { agent_name, message, window, deadline } => {
+				// Streams abandoned by their requester are dropped once expired, which also stops
+				// their generation.
+				self.streams.retain(|_, stream| !stream.is_expired());
+				if let Some(limit) = self.busy_limit() {
+					tracing::warn!("Busy, rejecting stream for {agent_name} from {peer}");
+					self.send_stream_response(channel, StreamResponse::Busy { limit });
+					return;
+				}
+
+				let key = (peer, self.next_stream_id);
+				self.next_stream_id += 1;
+				let (stream, tokens) = ProviderStream::new(window, deadline);
+				self.streams.insert(key, stream);
+				self.send_stream_response(channel, StreamResponse::Opened { stream_id: key.1 });
+
+				self.event_sender.send(Event::LLMStreamRequest { agent_name, message, tokens });
+			},
+			StreamRequest::Ack { stream_id } => match self.streams.remove(&(peer, stream_id)) {
+				Some(stream) => self.stream_pulls.push(
+					async move {
+						let (stream, response) = stream.next_tokens().await;
+						((peer, stream_id), channel, stream, response)
+					}
+					.boxed(),
+				),
+				None => self.send_stream_response(
+					channel,
+					StreamResponse::Error(format!("Unknown stream {stream_id}")),
+				),
+			},
+			StreamRequest::Challenge => {
+				let nonce = self.challenges.issue(peer);
+				self.send_stream_response(channel, StreamResponse::Challenge { nonce });
+			},
+			StreamRequest::Authenticate { answer } => {
+				let response = match self.challenges.verify(peer, &answer) {
+					Ok(()) => {
+						tracing::info!("Requester {peer} answered its challenge");
+						StreamResponse::Authenticated
+					},
+					Err(e) => {
+						tracing::warn!("Requester {peer} failed its challenge: {e}");
+						StreamResponse::Error(e.to_string())
+					},
+				};
+				self.send_stream_response(channel, response);
+			},
+			StreamRequest::Unknown(tag, _) => self.send_stream_response(
+				channel,
+				StreamResponse::Error(format!("Unsupported stream request {tag}")),
+			),
+		}
+	}
+
+	/// The given addresses of the node, ending with its `/p2p/<peer id>`.
+	fn with_peer_id<'a>(&self, addresses: impl Iterator<Item = &'a Multiaddr>) -> Vec<Multiaddr> {
+		let peer_id = *self.swarm.local_peer_id();
+		addresses
+			.map(|address| address.clone().with_p2p(peer_id).unwrap_or_else(|address| address))
+			.collect()
+	}
+
+	/// Whether a request from the given peer must wait for it to answer a challenge, as required
+	/// by the card of the agent.
+	fn requires_challenge(&self, peer: &PeerId, agent_name: &str, message_len: usize) -> bool {
+		self.provided_cards
+			.get(agent_name)
+			.is_some_and(|card| card.requires_challenge(message_len))
+			&& !self.challenges.is_verified(peer)
+	}
+
+	/// The limit of concurrent agent requests when reached. Open streams count as requests, they
+	/// are generating their answers too.
+	fn busy_limit(&self) -> Option<usize> {
+		let served = self.inbound_requests.len() + self.streams.len() + self.stream_pulls.len();
+		self.max_inbound_requests.filter(|limit| served >= *limit)
+	}
+
+	/// Whether the policy of the agent, if any, allows a request of the given peer, streamed or
+	/// not, of `size` bytes.
+	fn is_allowed(&self, peer: PeerId, agent: &str, language: Option<String>, size: usize) -> bool {
+		let Some(policy) = self.request_policies.get(agent) else {
+			return true;
+		};
+		let seconds_of_day = now_ms() / 1000 % (24 * 60 * 60);
+		policy.allows(&RequestAttributes {
+			peer,
+			agent: agent.to_string(),
+			language,
+			size,
+			hour: (seconds_of_day / 3600) as u8,
+		})
+	}
+
 	pub async fn run(mut self, cancellation_token: CancellationToken) {
-		let mut discover_tick = tokio::time::interval(Duration::from_secs(60));
+		// The first discovery happens once connected to the rendezvous point.
+		let mut discover_tick = tokio::time::interval_at(
+			tokio::time::Instant::now() + DISCOVER_INTERVAL,
+			DISCOVER_INTERVAL,
+		);
+		let mut metrics_tick = tokio::time::interval(Duration::from_secs(10));
+		let mut alerts_tick = tokio::time::interval(alerts::EVALUATION_INTERVAL);
+		let mut presence_tick = tokio::time::interval(self.presence_config.interval);
+		let mut announce_tick = tokio::time::interval(self.directory_config.interval);
+		let mut timeouts_tick = tokio::time::interval(TIMEOUTS_INTERVAL);
+		let mut resume_tick = tokio::time::interval(self.monitor.check_interval());
+		let mut rebootstrap_tick = tokio::time::interval_at(
+			tokio::time::Instant::now() + REBOOTSTRAP_INTERVAL,
+			REBOOTSTRAP_INTERVAL,
+		);
 
 		self.add_external_address();
 		self.dial_rendezvous_point_address();
 		self.register_rendezvous_point();
+		self.dial_bootstrap_peers();
 
 		loop {
 			tokio::select! {
-				_ = cancellation_token.cancelled() => {
-					self.swarm.behaviour_mut().shutdown()
-				},
+				_ = cancellation_token.cancelled() => return self.shutdown().await,
 				event = self.swarm.select_next_some() => {
 					self.handle_event(event).await
 				},
@@ -129,34 +1201,224 @@ impl EventLoop {
 					Some(c) => self.handle_command(c).await,
 					None=>  return,
 				},
-				_ = discover_tick.tick(), if self.rendezvous_point.is_some() => {
+				_ = discover_tick.tick() => if let Some(rendezvous_point) = self.rendezvous_point {
 					self.swarm.behaviour_mut().rendezvous.discover(
 						self.namespace.clone(),
 						self.cookie.clone(),
 						None,
-						self.rendezvous_point.unwrap(),
+						rendezvous_point,
 					)
 				},
+				_ = metrics_tick.tick() => self.update_gossipsub_metrics(),
+				_ = alerts_tick.tick(), if !self.alerts.is_empty() => self.raise_alerts(),
+				_ = presence_tick.tick(), if self.presence_config.enabled => self.send_beacon(),
+				_ = announce_tick.tick(), if self.directory_config.enabled => {
+					self.announce_capabilities()
+				},
+				_ = timeouts_tick.tick() => {
+					self.expire_requests();
+					self.reassign_overdue();
+					self.publish_attestations();
+					self.expire_tasks();
+					self.circuits.flush();
+				},
+				_ = resume_tick.tick(), if self.monitor.is_enabled() => {
+					let now = tokio::time::Instant::now();
+					if let Some(suspended) = self.monitor.check(SystemTime::now(), now) {
+						self.resume(Some(suspended)).await;
+					}
+				},
+				_ = rebootstrap_tick.tick(), if !self.bootstrap_peers.is_empty() => {
+					if self.routing_table_is_empty() {
+						tracing::info!("Routing table empty, dialing the bootstrap peers again");
+						self.dial_bootstrap_peers();
+					}
+				},
+				_ = sleep_until(self.reconnect.next_dial()) => self.redial_due_peers().await,
+				_ = sleep_until(self.bans.values().min().copied()) => self.lift_expired_bans(),
+				_ = sleep_until(self.auctions.values().map(|(a, _)| a.closes_at()).min()) => {
+					self.close_auctions()
+				},
+				Some((key, channel, stream, response)) = self.stream_pulls.next(),
+					if !self.stream_pulls.is_empty() =>
+				{
+					if let Some(stream) = stream {
+						self.streams.insert(key, stream);
+					}
+					self.send_stream_response(channel, response);
+				},
 			}
 		}
 	}
 
+	/// Leave the network: withdraw from the DHT, the rendezvous point and the gossip topics, fail
+	/// everything still pending, then keep driving the swarm for a moment so the last responses
+	/// and unsubscriptions reach the peers before closing it.
+	async fn shutdown(mut self) {
+		tracing::info!("Shutting down");
+		for agent_name in std::mem::take(&mut self.agents_providing) {
+			let key = kad::RecordKey::new(&agent_key(&agent_name));
+			self.swarm.behaviour_mut().kademlia.stop_providing(&key);
+		}
+		if let Some(rendezvous_point) = self.rendezvous_point {
+			self.swarm
+				.behaviour_mut()
+				.rendezvous
+				.unregister(rendezvous::Namespace::from_static(NAMESPACE), rendezvous_point);
+		}
+		self.swarm.behaviour_mut().shutdown();
+		self.fail_pending();
+		self.circuits.flush();
+		// Dropping the streams stops their generation.
+		self.streams.clear();
+
+		let grace = tokio::time::sleep(SHUTDOWN_GRACE);
+		tokio::pin!(grace);
+		loop {
+			tokio::select! {
+				_ = &mut grace => break,
+				// Inbound requests are no longer served, their requesters get an error.
+				event = self.swarm.select_next_some() => self.metrics.record(&event),
+				command = self.command_receiver.next(), if !self.command_receiver.is_terminated() => {
+					match command {
+						Some(
+							c @ (Command::RespondLLM { .. }
+							| Command::RespondArtifact { .. }
+							| Command::KBuckets { .. }
+							| Command::NatStatus { .. }
+							| Command::GossipHistory { .. }
+							| Command::GetConnectedPeers { .. }
+							| Command::TagPeer { .. }
+							| Command::UntagPeer { .. }
+							| Command::PeerLabels { .. }
+							| Command::LastSeen { .. }
+							| Command::OnlineProviders { .. }
+							| Command::FindAgents { .. }
+							| Command::Unsubscribe { .. }
+							| Command::Topics { .. }
+							| Command::NetworkSnapshot { .. }
+							| Command::PeerLatency { .. }
+							| Command::AdmissibleProviders { .. }
+							| Command::PeerCapabilities { .. }
+							| Command::GetTaskStatus { .. }
+							| Command::ListenAddresses { .. }
+							| Command::ExternalAddresses { .. }),
+						) => {
+							self.handle_command(c).await
+						},
+						Some(c) => reject_command(c),
+						None => {},
+					}
+				},
+				Some((_, channel, _, response)) = self.stream_pulls.next(),
+					if !self.stream_pulls.is_empty() =>
+				{
+					self.send_stream_response(channel, response);
+				},
+			}
+		}
+
+		let peers: Vec<_> = self.swarm.connected_peers().copied().collect();
+		for peer in peers {
+			let _ = self.swarm.disconnect_peer_id(peer);
+		}
+		tracing::info!("Shut down");
+	}
+
+	/// Stop tracking the agent requests whose handle was dropped without waiting for a response.
+	fn forget_dropped_requests(&mut self) {
+		self.pending_request.retain(|request_id, sender| {
+			if sender.is_canceled() {
+				tracing::info!("Request {request_id} dropped by its requester");
+			}
+			!sender.is_canceled()
+		});
+		let pending_request = &self.pending_request;
+		self.sealed_requests
+			.retain(|request_id, _| pending_request.contains_key(request_id));
+		self.request_timeouts
+			.retain(|request_id| pending_request.contains_key(request_id));
+	}
+
+	/// Resolve every pending query and request with [`NetworkError::ShuttingDown`].
+	fn fail_pending(&mut self) {
+		for (_, sender) in self.pending_dial.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_dial_back.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, query) in self.pending_start_providing.drain() {
+			query.finish(Err(shutting_down()));
+		}
+		for (_, query) in self.pending_get_providers.drain() {
+			query.finish(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_request.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		self.sealed_requests.clear();
+		self.request_timeouts.clear();
+		for (_, sender) in self.pending_artifact_request.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_put_record.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, pending) in self.pending_get_record.drain() {
+			let _ = pending.sender.send(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_get_closest_peers.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_stream_request.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_bid.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_feedback.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, sender) in self.pending_task_status.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, (_, sender)) in self.pending_task_results.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for sender in self.pending_directory_sync.drain().filter_map(|(_, sender)| sender) {
+			let _ = sender.send(Err(shutting_down()));
+		}
+		for (_, (_, sender)) in self.auctions.drain() {
+			let _ = sender.send(Err(shutting_down()));
+		}
+	}
+
 	async fn handle_event(&mut self, event: SwarmEvent<AsnBehaviourEvent>) {
+		self.metrics.record(&event);
+		if matches!(
+			event,
+			SwarmEvent::ConnectionEstablished { .. } | SwarmEvent::ConnectionClosed { .. }
+		) {
+			self.metrics.set_connected_peers(self.swarm.connected_peers().count());
+		}
+
 		match event {
 			// -- Kademlia events
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
 				kad::Event::OutboundQueryProgressed {
 					id,
 					result: kad::QueryResult::StartProviding(_),
-					..
+					stats,
+					step,
 				},
 			)) => {
 				tracing::info!("Started providing");
-				let sender: oneshot::Sender<()> = self
-					.pending_start_providing
-					.remove(&id)
-					.expect("Completed query to be previously pending.");
-				let _ = sender.send(());
+				let Some(query) = self.take_start_providing(id) else {
+					return;
+				};
+				query.progress(QueryProgress::new(&step, &stats, Vec::new()));
+				query.finish(Ok(()));
 				tracing::info!("Successfully started providing");
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
@@ -167,17 +1429,21 @@ impl EventLoop {
 							providers,
 							..
 						})),
-					..
+					stats,
+					step,
 				},
 			)) => {
 				tracing::info!("Found providers for query {id}");
-				if let Some(sender) = self.pending_get_providers.remove(&id) {
+				if let Some(query) = self.pending_get_providers.remove(&id) {
 					providers.clone().iter().for_each(|p| {
 						tracing::info!("Found provider: {p}");
 					});
-					sender.send(providers).expect("Receiver not to be dropped");
+					query.progress(QueryProgress::new(&step, &stats, Vec::new()));
+					query.finish(Ok(providers));
 					// Finish the query. We are only interested in the first result.
-					self.swarm.behaviour_mut().kademlia.query_mut(&id).unwrap().finish();
+					if let Some(mut query) = self.swarm.behaviour_mut().kademlia.query_mut(&id) {
+						query.finish();
+					}
 				}
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
@@ -185,12 +1451,115 @@ impl EventLoop {
 					id,
 					result:
 						kad::QueryResult::GetProviders(Ok(
-							kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. },
+							kad::GetProvidersOk::FinishedWithNoAdditionalRecord { closest_peers },
 						)),
-					..
+					stats,
+					step,
 				},
 			)) => {
 				tracing::info!("No providers found for query {id}");
+				if let Some(query) = self.pending_get_providers.remove(&id) {
+					query.progress(QueryProgress::new(&step, &stats, closest_peers));
+					query.finish(Ok(HashSet::new()));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
+				kad::Event::OutboundQueryProgressed {
+					id,
+					result: kad::QueryResult::GetProviders(Err(e)),
+					stats,
+					step,
+				},
+			)) => {
+				tracing::error!("Failed to get providers: {e}");
+				if let Some(query) = self.pending_get_providers.remove(&id) {
+					query.progress(QueryProgress::new(&step, &stats, Vec::new()));
+					query.finish(Err(Box::new(e)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
+				kad::Event::OutboundQueryProgressed {
+					id,
+					result: kad::QueryResult::PutRecord(result),
+					..
+				},
+			)) => {
+				// Records are also republished periodically, only answer the queries we started.
+				if let Some(sender) = self.pending_put_record.remove(&id) {
+					let _ = match result {
+						Ok(kad::PutRecordOk { key }) => {
+							tracing::info!(
+								"Put record {:?}",
+								String::from_utf8_lossy(key.as_ref())
+							);
+							sender.send(Ok(()))
+						},
+						Err(e) => sender.send(Err(Box::new(e))),
+					};
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
+				kad::Event::OutboundQueryProgressed {
+					id,
+					result:
+						kad::QueryResult::GetRecord(Ok(kad::GetRecordOk::FoundRecord(
+							kad::PeerRecord { peer, record },
+						))),
+					..
+				},
+			)) => {
+				tracing::info!("Found record for query {id} from {peer:?}");
+				if let Some(pending) = self.pending_get_record.get_mut(&id) {
+					pending.values.push(record.value);
+					if pending.values.len() >= pending.required {
+						let value = pending.values.swap_remove(0);
+						if let Some(pending) = self.pending_get_record.remove(&id) {
+							let _ = pending.sender.send(Ok(value));
+						}
+						if let Some(mut query) = self.swarm.behaviour_mut().kademlia.query_mut(&id)
+						{
+							query.finish();
+						}
+					}
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
+				kad::Event::OutboundQueryProgressed {
+					id,
+					result: kad::QueryResult::GetRecord(result),
+					step,
+					..
+				},
+			)) if step.last => {
+				if let Some(pending) = self.pending_get_record.remove(&id) {
+					let error: Box<dyn Error + Send> = match result {
+						Err(e) if pending.values.is_empty() => Box::new(e),
+						_ if pending.values.is_empty() => Box::new(RecordError::NotFound),
+						_ => Box::new(RecordError::QuorumFailed {
+							found: pending.values.len(),
+							required: pending.required,
+						}),
+					};
+					tracing::info!("Get record query {id} failed: {error}");
+					let _ = pending.sender.send(Err(error));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
+				kad::Event::OutboundQueryProgressed {
+					id,
+					result: kad::QueryResult::GetClosestPeers(result),
+					..
+				},
+			)) => {
+				if let Some(sender) = self.pending_get_closest_peers.remove(&id) {
+					let _ = match result {
+						Ok(kad::GetClosestPeersOk { peers, .. }) => {
+							tracing::info!("Found {} closest peers for query {id}", peers.len());
+							sender.send(Ok(peers.into_iter().map(|p| p.peer_id).collect()))
+						},
+						Err(e) => sender.send(Err(Box::new(e))),
+					};
+				}
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(
 				kad::Event::OutboundQueryProgressed {
@@ -244,6 +1613,13 @@ impl EventLoop {
 				let addr_len = addresses.len();
 				let old_peer_or_empty = old_peer.map(|p| p.to_string()).unwrap_or_default();
 				tracing::info!("Routing updated for {peer} with {addr_len} addresses. Old peer: {old_peer_or_empty}. Is new peer: {is_new_peer}");
+				if is_new_peer {
+					self.event_sender.send(Event::PeerDiscovered {
+						peer_id: peer,
+						addresses: addresses.into_vec(),
+						source: DiscoverySource::Kademlia,
+					});
+				}
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(kad::Event::InboundRequest {
 				request: kad::InboundRequest::FindNode { num_closer_peers, .. },
@@ -269,10 +1645,10 @@ impl EventLoop {
 						record: Some(kad::Record { key, value, publisher, .. }),
 					},
 			})) => {
-				let key_hash = sha256::digest(key.to_vec());
-				let value_hash = sha256::digest(value.to_vec());
+				let key_cid = Cid::of(&key);
+				let value_cid = Cid::of(&value);
 				let publisher_or_empty = publisher.map(|p| p.to_string()).unwrap_or_default();
-				tracing::info!("Received PutRecord request from {source} on connection {connection} with record (key_hash = {key_hash}, value_hash = {value_hash}, publisher = {publisher_or_empty})");
+				tracing::info!("Received PutRecord request from {source} on connection {connection} with record (key = {key_cid}, value = {value_cid}, publisher = {publisher_or_empty})");
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(kad::Event::InboundRequest {
 				request:
@@ -282,8 +1658,8 @@ impl EventLoop {
 					},
 			})) => {
 				let addr_len = addresses.len();
-				let key_hash = sha256::digest(key.to_vec());
-				tracing::info!("Received AddProvider request for {key_hash} from {provider} with {addr_len} addresses");
+				let key_cid = Cid::of(&key);
+				tracing::info!("Received AddProvider request for {key_cid} from {provider} with {addr_len} addresses");
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(kad::Event::InboundRequest {
 				request: kad::InboundRequest::AddProvider { record: None, .. },
@@ -520,61 +1896,438 @@ impl EventLoop {
 				new,
 			})) => {
 				tracing::info!("Status changed from {old:?} to {new:?}");
+				self.nat_status = new.clone();
+				self.event_sender.send(Event::NatStatusChanged { old, new });
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::AutoNat(event)) => {
 				tracing::info!("Unhandled AutoNat event: {:?}", event);
 			},
 
 			// -- Request-Response events
-			SwarmEvent::Behaviour(AsnBehaviourEvent::RequestResponse(
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Control(
 				request_response::Event::Message {
-					message: request_response::Message::Request { request, channel, .. },
+					peer,
+					message: request_response::Message::Request { request_id, request, channel },
 					..
 				},
 			)) => {
-				self.event_sender
-					.send(Event::LLMInboundRequest {
-						agent_name: request.0,
-						message: request.1,
-						channel,
-					})
-					.await
-					.expect("Event receiver not to be dropped.");
-			},
-			SwarmEvent::Behaviour(AsnBehaviourEvent::RequestResponse(
+				if self.requires_challenge(&peer, &request.0, request.message_len()) {
+					// Dropping the channel fails the request, the requester authenticates first.
+					tracing::warn!("Rejecting request for {} from unverified {peer}", request.0);
+					return;
+				}
+				if !self.is_allowed(peer, &request.0, request.4.clone(), request.message_len()) {
+					tracing::warn!("Policy of {} denies the request of {peer}", request.0);
+					let reason = format!("Policy of agent {} denies the request", request.0);
+					let denied = LLMResponse::Denied { reason };
+					if let Err(e) =
+						self.swarm.behaviour_mut().control.send_response(channel, denied)
+					{
+						tracing::error!("Failed to send denied response: {:?}", e);
+					}
+					return;
+				}
+				if let Some(limit) = self.busy_limit() {
+					tracing::warn!("Busy, rejecting request for {} from {peer}", request.0);
+					let busy = LLMResponse::Busy { limit };
+					if let Err(e) = self.swarm.behaviour_mut().control.send_response(channel, busy)
+					{
+						tracing::error!("Failed to send busy response: {:?}", e);
+					}
+					return;
+				}
+				self.inbound_requests.insert(request_id);
+				self.event_sender.send(Event::LLMInboundRequest {
+					agent_name: request.0,
+					message: request.1,
+					context: request.2,
+					language: request.4,
+					requester: peer,
+					attachments: request.5,
+					job: request.6,
+					channel,
+				});
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Control(
 				request_response::Event::Message {
+					peer,
 					message: request_response::Message::Response { request_id, response },
 					..
 				},
 			)) => {
-				let _ = self
-					.pending_request
-					.remove(&request_id)
-					.expect("Request to still be pending.")
-					.send(Ok(response.0));
+				let session = self.sealed_requests.remove(&request_id);
+				match response {
+					LLMResponse::Output(_) => {
+						self.request_timeouts.finish(&request_id, Instant::now());
+						self.circuits.record_success(&peer);
+					},
+					LLMResponse::Busy { .. } | LLMResponse::Denied { .. } => {
+						self.request_timeouts.forget(&request_id)
+					},
+				}
+				match self.pending_request.remove(&request_id) {
+					Some(sender) => {
+						let response = match (response, session) {
+							(LLMResponse::Output(output), Some(session)) => session
+								.open(&output)
+								.map_err(|e| Box::new(e) as Box<dyn Error + Send>),
+							(LLMResponse::Output(output), None) => Ok(output),
+							(LLMResponse::Busy { limit }, _) => {
+								Err(Box::new(NetworkError::ProviderBusy { limit }) as _)
+							},
+							(LLMResponse::Denied { reason }, _) => {
+								Err(Box::new(NetworkError::Denied(reason)) as _)
+							},
+						};
+						let _ = sender.send(response);
+					},
+					None => {
+						tracing::info!("Discarding the response to cancelled request {request_id}")
+					},
+				}
 			},
-			SwarmEvent::Behaviour(AsnBehaviourEvent::RequestResponse(
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Control(
 				request_response::Event::InboundFailure { request_id, connection_id, peer, error },
 			)) => {
+				self.inbound_requests.remove(&request_id);
 				tracing::error!("Inbound request failed for {peer}: {error} (request_id: {request_id}, connection_id: {connection_id})");
 			},
-			SwarmEvent::Behaviour(AsnBehaviourEvent::RequestResponse(
-				request_response::Event::OutboundFailure { request_id, error, .. },
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Control(
+				request_response::Event::OutboundFailure { peer, request_id, error, .. },
 			)) => {
-				let _ = self
-					.pending_request
-					.remove(&request_id)
-					.expect("Request to still be pending.")
-					.send(Err(Box::new(error)));
+				self.sealed_requests.remove(&request_id);
+				self.request_timeouts.forget(&request_id);
+				// A cancelled request says nothing about its provider.
+				if let Some(sender) = self.pending_request.remove(&request_id) {
+					self.circuits.record_failure(peer, now_ms());
+					let _ = sender.send(Err(Box::new(error)));
+				}
 			},
-			SwarmEvent::Behaviour(AsnBehaviourEvent::RequestResponse(
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Control(
 				request_response::Event::ResponseSent { request_id, connection_id, peer },
 			)) => {
+				self.inbound_requests.remove(&request_id);
 				tracing::info!(
 					"Response sent for request {request_id} on connection {connection_id} to {peer}"
 				);
 			},
 
+			// -- Data plane events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Data(request_response::Event::Message {
+				message: request_response::Message::Request { request, channel, .. },
+				..
+			})) => {
+				self.event_sender.send(Event::ArtifactRequest { name: request.0, channel });
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Data(request_response::Event::Message {
+				message: request_response::Message::Response { request_id, response },
+				..
+			})) => {
+				if let Some(sender) = self.pending_artifact_request.remove(&request_id) {
+					let _ = sender.send(Ok(response.0));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Data(
+				request_response::Event::OutboundFailure { request_id, error, .. },
+			)) => {
+				if let Some(sender) = self.pending_artifact_request.remove(&request_id) {
+					let _ = sender.send(Err(Box::new(error)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Data(
+				request_response::Event::InboundFailure { peer, error, .. },
+			)) => {
+				tracing::error!("Inbound artifact request failed for {peer}: {error}");
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Data(event)) => {
+				tracing::debug!("Data plane event: {event:?}");
+			},
+
+			// -- Stream events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::LlmStream(
+				request_response::Event::Message {
+					peer,
+					message: request_response::Message::Request { request, channel, .. },
+					..
+				},
+			)) => self.handle_stream_request(peer, request, channel).await,
+			SwarmEvent::Behaviour(AsnBehaviourEvent::LlmStream(
+				request_response::Event::Message {
+					message: request_response::Message::Response { request_id, response },
+					..
+				},
+			)) => {
+				if let Some(sender) = self.pending_stream_request.remove(&request_id) {
+					let _ = sender.send(Ok(response));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::LlmStream(
+				request_response::Event::OutboundFailure { request_id, error, .. },
+			)) => {
+				if let Some(sender) = self.pending_stream_request.remove(&request_id) {
+					let _ = sender.send(Err(Box::new(error)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::LlmStream(
+				request_response::Event::InboundFailure { peer, error, .. },
+			)) => {
+				tracing::error!("Inbound stream request failed for {peer}: {error}");
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::LlmStream(event)) => {
+				tracing::debug!("Stream event: {event:?}");
+			},
+
+			// -- Bid events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Bids(request_response::Event::Message {
+				peer,
+				message: request_response::Message::Request { request, channel, .. },
+				..
+			})) => {
+				if self.swarm.behaviour_mut().bids.send_response(channel, ()).is_err() {
+					tracing::warn!("Failed to acknowledge the bid of {peer}, it went away");
+				}
+				match self.auctions.get_mut(&request.task_id) {
+					Some((auction, _)) => {
+						let (task_id, bid) = (request.task_id.clone(), request.bid);
+						// Bidders that did not announce their hardware only meet empty requirements.
+						let hardware = self
+							.connected_peers
+							.get(&peer)
+							.and_then(|peer| peer.capabilities.as_ref())
+							.map(|capabilities| capabilities.hardware.clone())
+							.unwrap_or_default();
+						if auction.bid(peer, request, &hardware, now_ms() / 1000) {
+							if auction.needs_reputation(&peer) {
+								let (sender, receiver) = oneshot::channel();
+								auction.await_reputation(peer, receiver);
+								self.get_record(reputation_key(&peer), kad::Quorum::One, sender);
+							}
+							let state = AuctionState::BidReceived { bidder: peer, bid };
+							self.event_sender.send(Event::Auction { task_id, state });
+						} else {
+							tracing::info!("Refused bid of {peer} for task {task_id}");
+						}
+					},
+					None => {
+						self.event_sender.send(Event::InboundBid { bidder: peer, bid: request })
+					},
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Bids(request_response::Event::Message {
+				message: request_response::Message::Response { request_id, .. },
+				..
+			})) => {
+				if let Some(sender) = self.pending_bid.remove(&request_id) {
+					let _ = sender.send(Ok(()));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Bids(
+				request_response::Event::OutboundFailure { request_id, error, .. },
+			)) => {
+				if let Some(sender) = self.pending_bid.remove(&request_id) {
+					let _ = sender.send(Err(Box::new(error)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Bids(event)) => {
+				tracing::debug!("Bid event: {event:?}");
+			},
+
+			// -- Award events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Awards(
+				request_response::Event::Message {
+					peer,
+					message: request_response::Message::Request { request, channel, .. },
+					..
+				},
+			)) => {
+				if self.swarm.behaviour_mut().awards.send_response(channel, ()).is_err() {
+					tracing::warn!("Failed to acknowledge the award of {peer}, it went away");
+				}
+				self.track_task(TaskRecord {
+					task_id: request.task_id.clone(),
+					owner: peer,
+					worker: Some(*self.swarm.local_peer_id()),
+					status: TaskStatus::Assigned,
+					max_bid: request.max_bid,
+					payment_address: None,
+					deadline: request.deadline,
+					updated_at: now_ms() / 1000,
+					commitment: None,
+				});
+				self.event_sender.send(Event::TaskAwarded { proposer: peer, award: request });
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Awards(
+				request_response::Event::OutboundFailure { peer, error, .. },
+			)) => {
+				tracing::warn!("Failed to send the award to {peer}: {error}");
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Awards(event)) => {
+				tracing::debug!("Award event: {event:?}");
+			},
+
+			// -- Feedback events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Feedback(
+				request_response::Event::Message {
+					peer,
+					message: request_response::Message::Request { request, channel, .. },
+					..
+				},
+			)) => {
+				if self.swarm.behaviour_mut().feedback.send_response(channel, ()).is_err() {
+					tracing::warn!("Failed to acknowledge the feedback of {peer}, it went away");
+				}
+				self.event_sender.send(Event::Feedback { requester: peer, feedback: request });
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Feedback(
+				request_response::Event::Message {
+					message: request_response::Message::Response { request_id, .. },
+					..
+				},
+			)) => {
+				if let Some(sender) = self.pending_feedback.remove(&request_id) {
+					let _ = sender.send(Ok(()));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Feedback(
+				request_response::Event::OutboundFailure { request_id, error, .. },
+			)) => {
+				if let Some(sender) = self.pending_feedback.remove(&request_id) {
+					let _ = sender.send(Err(Box::new(error)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Feedback(event)) => {
+				tracing::debug!("Feedback event: {event:?}");
+			},
+
+			// -- Task status events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskStatus(
+				request_response::Event::Message {
+					peer,
+					message: request_response::Message::Request { request, channel, .. },
+					..
+				},
+			)) => {
+				if self.swarm.behaviour_mut().task_status.send_response(channel, ()).is_err() {
+					tracing::warn!(
+						"Failed to acknowledge the status update of {peer}, it went away"
+					);
+				}
+				self.handle_task_status_update(peer, request);
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskStatus(
+				request_response::Event::Message {
+					message: request_response::Message::Response { request_id, .. },
+					..
+				},
+			)) => {
+				if let Some(sender) = self.pending_task_status.remove(&request_id) {
+					let _ = sender.send(Ok(()));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskStatus(
+				request_response::Event::OutboundFailure { request_id, error, .. },
+			)) => {
+				if let Some(sender) = self.pending_task_status.remove(&request_id) {
+					let _ = sender.send(Err(Box::new(error)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskStatus(event)) => {
+				tracing::debug!("Task status event: {event:?}");
+			},
+
+			// -- Task result events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskResults(
+				request_response::Event::Message {
+					peer,
+					message: request_response::Message::Request { request, channel, .. },
+					..
+				},
+			)) => {
+				let ack = self.handle_task_result(peer, request);
+				if self.swarm.behaviour_mut().task_results.send_response(channel, ack).is_err() {
+					tracing::warn!("Failed to answer the task result of {peer}, it went away");
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskResults(
+				request_response::Event::Message {
+					message: request_response::Message::Response { request_id, response },
+					..
+				},
+			)) => {
+				if let Some((completes, sender)) = self.pending_task_results.remove(&request_id) {
+					let result = match response {
+						ResultAck::Accepted => {
+							if let Some(task_id) = completes {
+								if let Err(e) =
+									self.transition_task(&task_id, TaskStatus::Completed)
+								{
+									tracing::warn!("Failed to complete task {task_id}: {e}");
+								}
+							}
+							Ok(())
+						},
+						ResultAck::Refused(reason) => {
+							Err(Box::new(NetworkError::ResultRefused(reason))
+								as Box<dyn Error + Send>)
+						},
+					};
+					let _ = sender.send(result);
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskResults(
+				request_response::Event::OutboundFailure { request_id, error, .. },
+			)) => {
+				if let Some((_, sender)) = self.pending_task_results.remove(&request_id) {
+					let _ = sender.send(Err(Box::new(error)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::TaskResults(event)) => {
+				tracing::debug!("Task result event: {event:?}");
+			},
+
+			// -- Directory sync events
+			SwarmEvent::Behaviour(AsnBehaviourEvent::DirectorySync(
+				request_response::Event::Message {
+					peer,
+					message: request_response::Message::Request { request, channel, .. },
+					..
+				},
+			)) => {
+				let response = match self.directory_config.enabled {
+					true => self.directory.sync_response(&request, tokio::time::Instant::now()),
+					false => Default::default(),
+				};
+				let behaviour = self.swarm.behaviour_mut();
+				if behaviour.directory_sync.send_response(channel, response).is_err() {
+					tracing::warn!("Failed to answer the directory sync of {peer}, it went away");
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::DirectorySync(
+				request_response::Event::Message {
+					peer,
+					message: request_response::Message::Response { request_id, response },
+					..
+				},
+			)) => {
+				let kept = self.directory.merge(response, tokio::time::Instant::now(), now_ms());
+				tracing::info!("Synced {kept} capability announcements from {peer}");
+				self.directory_synced = true;
+				if let Some(Some(sender)) = self.pending_directory_sync.remove(&request_id) {
+					let _ = sender.send(Ok(kept));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::DirectorySync(
+				request_response::Event::OutboundFailure { peer, request_id, error, .. },
+			)) => {
+				tracing::debug!("Failed to sync the capability directory from {peer}: {error}");
+				if let Some(Some(sender)) = self.pending_directory_sync.remove(&request_id) {
+					let _ = sender.send(Err(Box::new(error)));
+				}
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::DirectorySync(event)) => {
+				tracing::debug!("Directory sync event: {event:?}");
+			},
+
 			// -- Swarm events
 			SwarmEvent::NewListenAddr { address, .. } => {
 				let local_peer_id = *self.swarm.local_peer_id();
@@ -586,18 +2339,55 @@ impl EventLoop {
 					"Local node is listening on {:?}",
 					address.clone().with(Protocol::P2p(local_peer_id))
 				);
+				self.event_sender.send(Event::ListenAddressChanged { address, expired: false });
+				if self.monitor.listen_address_added() {
+					self.resume(None).await;
+				}
 			},
 			SwarmEvent::IncomingConnection { local_addr, send_back_addr, connection_id } => {
 				tracing::info!(
 					"Incoming connection from {send_back_addr} to {local_addr} with connection_id {connection_id}"
 				);
 			},
-			SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+			SwarmEvent::ConnectionEstablished {
+				peer_id,
+				connection_id,
+				endpoint,
+				num_established,
+				..
+			} => {
+				self.event_sender.send(Event::ConnectionEstablished {
+					peer_id,
+					connection_id,
+					endpoint: endpoint.clone(),
+					num_established: num_established.get(),
+				});
+				if num_established.get() == 1 {
+					self.connected_peers.insert(
+						peer_id,
+						PeerInfo {
+							connected_since: tokio::time::Instant::now(),
+							agent_version: None,
+							protocols: Vec::new(),
+							capabilities: None,
+							rtts: RttHistogram::default(),
+						},
+					);
+					self.event_sender
+						.send(Event::PeerConnected { peer_id, endpoint: endpoint.clone() });
+				}
+				if let Some(state) = self.reconnect.connected(&peer_id) {
+					self.report_reconnect(peer_id, state).await;
+				}
 				if endpoint.is_dialer() {
 					if let Some(sender) = self.pending_dial.remove(&peer_id) {
 						let _ = sender.send(Ok(()));
 					}
 				}
+				// Without a configured rendezvous point, any peer may be one.
+				if self.rendezvous_point.is_some_and(|point| point != peer_id) {
+					return;
+				}
 				if let Err(error) = self.swarm.behaviour_mut().rendezvous.register(
 					rendezvous::Namespace::from_static(NAMESPACE),
 					peer_id,
@@ -606,16 +2396,65 @@ impl EventLoop {
 					tracing::error!("Failed to register: {error}");
 					return;
 				}
-				tracing::info!("Connection established with rendezvous point {}", peer_id);
+				tracing::info!("Connection established with rendezvous point {}", peer_id);
+
+				if self.rendezvous_point == Some(peer_id) {
+					self.swarm.behaviour_mut().rendezvous.discover(
+						self.namespace.clone(),
+						self.cookie.clone(),
+						None,
+						peer_id,
+					);
+				}
 			},
-			SwarmEvent::ConnectionClosed { peer_id, cause: Some(error), .. } => {
-				tracing::info!("Lost connection with {} : {}", peer_id.to_base58(), error);
+			SwarmEvent::ConnectionClosed {
+				peer_id,
+				connection_id,
+				endpoint,
+				cause,
+				num_established,
+			} => {
+				self.event_sender.send(Event::ConnectionClosed {
+					peer_id,
+					connection_id,
+					endpoint,
+					num_established,
+					cause: cause.as_ref().map(|error| error.to_string()),
+				});
+				if let Some(sender) = self.pending_dial_back.remove(&connection_id) {
+					let _ = sender.send(Err(Box::new(NetworkError::ConnectionClosed)));
+				}
+				if let Some(error) = &cause {
+					tracing::info!("Lost connection with {} : {}", peer_id.to_base58(), error);
+				}
+				if num_established == 0 {
+					self.connected_peers.remove(&peer_id);
+					self.challenges.forget(&peer_id);
+					self.event_sender.send(Event::PeerDisconnected { peer_id, cause });
+					let now = tokio::time::Instant::now();
+					if let Some(state) = self.reconnect.disconnected(&peer_id, now) {
+						self.report_reconnect(peer_id, state).await;
+					}
+				}
 			},
-			SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+			SwarmEvent::OutgoingConnectionError { peer_id, connection_id, error } => {
+				if let DialError::Denied { cause } = &error {
+					self.report_limit_reached(peer_id, cause).await;
+				}
+				if let Some(sender) = self.pending_dial_back.remove(&connection_id) {
+					let _ =
+						sender.send(Err(Box::new(NetworkError::Unreachable(error.to_string()))));
+				}
 				if let Some(peer_id) = peer_id {
 					if let Some(sender) = self.pending_dial.remove(&peer_id) {
 						let _ = sender.send(Err(Box::new(error)));
 					}
+					if !self.swarm.is_connected(&peer_id) {
+						let now = tokio::time::Instant::now();
+						if let Some(state) = self.reconnect.disconnected(&peer_id, now) {
+							self.report_reconnect(peer_id, state).await;
+						}
+					}
 				}
 			},
 			SwarmEvent::IncomingConnectionError {
@@ -627,14 +2466,22 @@ impl EventLoop {
 				tracing::error!(
 					"Incoming connection error from {send_back_addr} to {local_addr} with connection_id {connection_id}: {error}"
 				);
+				if let ListenError::Denied { cause } = &error {
+					self.report_limit_reached(None, cause).await;
+				}
 			},
 			SwarmEvent::ExpiredListenAddr { listener_id, address } => {
 				tracing::warn!("Expired listen address {address} with listener_id {listener_id}");
+				self.event_sender.send(Event::ListenAddressChanged { address, expired: true });
+				self.monitor.listen_address_expired();
 			},
 			SwarmEvent::ListenerError { listener_id, error } => {
 				tracing::warn!("Listener error with listener_id {listener_id}: {error}");
 			},
 			SwarmEvent::ListenerClosed { listener_id, addresses, .. } => {
+				if let Some(address) = self.listeners.remove(&listener_id) {
+					self.closed_listeners.push(address);
+				}
 				let addresses_in_string =
 					addresses.iter().map(|a| a.to_string()).collect::<Vec<String>>().join(", ");
 				tracing::info!(
@@ -650,6 +2497,10 @@ impl EventLoop {
 			},
 			SwarmEvent::ExternalAddrConfirmed { address } => {
 				tracing::info!("External address confirmed: {address}");
+				// Registrations carry our external addresses, so none could be made before.
+				if self.rendezvous_point.is_some() {
+					self.register_rendezvous_point();
+				}
 			},
 			SwarmEvent::ExternalAddrExpired { address } => {
 				tracing::info!("External address expired: {address}");
@@ -666,10 +2517,31 @@ impl EventLoop {
 				tracing::info!("Sent identify info to {peer_id:?}");
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Identify(identify::Event::Received {
-				info: identify::Info { observed_addr, .. },
+				peer_id,
+				info: identify::Info { observed_addr, agent_version, protocols, .. },
 				..
 			})) => {
 				self.swarm.add_external_address(observed_addr.clone());
+				if let Some(peer) = self.connected_peers.get_mut(&peer_id) {
+					peer.capabilities = NodeCapabilities::from_agent_version(&agent_version);
+					peer.agent_version = Some(agent_version);
+					peer.protocols = protocols.iter().map(ToString::to_string).collect();
+				}
+				// A node joining pulls the directory once, from the first peer serving it.
+				if self.directory_config.enabled
+					&& !self.directory_synced
+					&& self.pending_directory_sync.is_empty()
+					&& protocols.iter().any(|p| p.as_ref() == DIRECTORY_SYNC_PROTOCOL_VERSION)
+				{
+					self.sync_directory(peer_id, None);
+				}
+				let version = ProtocolVersion::negotiate(protocols.iter().map(AsRef::as_ref));
+				if version == Some(ProtocolVersion::V1) {
+					tracing::info!(
+						"{peer_id} only supports {}, requests to it carry their context in the message",
+						ProtocolVersion::V1.protocol()
+					);
+				}
 
 				tracing::info!("Received identify message from {observed_addr:?}");
 			},
@@ -698,6 +2570,11 @@ impl EventLoop {
 				self.cookie.replace(new_cookie);
 
 				for registration in registrations {
+					self.event_sender.send(Event::PeerDiscovered {
+						peer_id: registration.record.peer_id(),
+						addresses: registration.record.addresses().to_vec(),
+						source: DiscoverySource::Rendezvous,
+					});
 					for address in registration.record.addresses() {
 						let peer = registration.record.peer_id();
 						tracing::info!(%peer, %address, "Discovered peer");
@@ -710,7 +2587,10 @@ impl EventLoop {
 								address.clone()
 							};
 
-						self.swarm.dial(address_with_p2p).unwrap();
+						// Fails on the registration of the node itself, or once out of connections.
+						if let Err(error) = self.swarm.dial(address_with_p2p.clone()) {
+							self.report(EventLoopError::Dial { address: address_with_p2p, error });
+						}
 					}
 				}
 			},
@@ -745,18 +2625,49 @@ impl EventLoop {
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Rendezvous(event)) => {
 				tracing::info!("Unhandled Rendezvous event: {:?}", event);
 			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::RendezvousServer(
+				rendezvous::server::Event::PeerRegistered { peer, registration },
+			)) => {
+				tracing::info!(
+					"Peer {peer} registered for namespace '{}' for {} seconds",
+					registration.namespace,
+					registration.ttl
+				);
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::RendezvousServer(
+				rendezvous::server::Event::DiscoverServed { enquirer, registrations },
+			)) => {
+				tracing::info!("Served {} registrations to {enquirer}", registrations.len());
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::RendezvousServer(event)) => {
+				tracing::info!("Unhandled Rendezvous server event: {:?}", event);
+			},
 
 			// -- mDNS events
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
-				for (peer_id, _multiaddr) in list {
+				for (peer_id, addresses) in by_peer(list) {
+					let addresses = self.interface_filter.retain(addresses);
+					if addresses.is_empty() {
+						continue;
+					}
 					tracing::info!("mDNS discovered a new peer: {peer_id}");
 					self.swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+					self.event_sender.send(Event::PeerDiscovered {
+						peer_id,
+						addresses,
+						source: DiscoverySource::Mdns,
+					});
 				}
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Mdns(mdns::Event::Expired(list))) => {
-				for (peer_id, _multiaddr) in list {
+				for (peer_id, addresses) in by_peer(list) {
+					let addresses = self.interface_filter.retain(addresses);
+					if addresses.is_empty() {
+						continue;
+					}
 					tracing::info!("mDNS discover peer has expired: {peer_id}");
 					self.swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer_id);
+					self.event_sender.send(Event::PeerExpired { peer_id, addresses });
 				}
 			},
 
@@ -766,6 +2677,101 @@ impl EventLoop {
 				message_id: id,
 				message,
 			})) => {
+				let mut acceptance = self.moderation.validate(&message);
+				let mut bulletin = None;
+				let mut beacon = None;
+				let mut announcement = None;
+				if matches!(acceptance, gossipsub::MessageAcceptance::Reject) {
+					tracing::warn!(
+						"Rejected message {id} on moderated topic {} from unauthorized publisher {:?} via {peer_id}",
+						message.topic,
+						message.source,
+					);
+				} else if self.bulletins.is_banned(&message.topic) {
+					acceptance = gossipsub::MessageAcceptance::Ignore;
+				} else if message.topic
+					== gossipsub::IdentTopic::new(bulletin::CONTROL_TOPIC).hash()
+				{
+					match self.bulletins.accept(&message.data) {
+						Ok(accepted) => bulletin = Some(accepted),
+						Err(e @ BulletinError::Stale(_)) => {
+							tracing::info!("Ignored bulletin {id} via {peer_id}: {e}");
+							acceptance = gossipsub::MessageAcceptance::Ignore;
+						},
+						Err(e) => {
+							tracing::warn!("Rejected bulletin {id} via {peer_id}: {e}");
+							acceptance = gossipsub::MessageAcceptance::Reject;
+						},
+					}
+				} else if message.topic == gossipsub::IdentTopic::new(PRESENCE_TOPIC).hash() {
+					match (message.source, deserialize_message::<Beacon>(&message.data)) {
+						(Some(source), Ok(received)) => beacon = Some((source, received)),
+						_ => {
+							tracing::warn!("Rejected malformed presence beacon {id} via {peer_id}");
+							acceptance = gossipsub::MessageAcceptance::Reject;
+						},
+					}
+				} else if message.topic == gossipsub::IdentTopic::new(CAPABILITIES_TOPIC).hash() {
+					let source = message.source.unwrap_or(peer_id);
+					match CapabilityAnnouncement::verify(&source, &message.data) {
+						Ok(verified) => announcement = Some((source, verified)),
+						// Left to the nodes that understand it.
+						Err(e @ AnnouncementError::UnsupportedVersion(_)) => {
+							tracing::info!(
+								"Ignored capability announcement {id} via {peer_id}: {e}"
+							);
+							acceptance = gossipsub::MessageAcceptance::Ignore;
+						},
+						Err(e) => {
+							tracing::warn!(
+								"Rejected capability announcement {id} via {peer_id}: {e}"
+							);
+							acceptance = gossipsub::MessageAcceptance::Reject;
+						},
+					}
+				} else {
+					acceptance = self.moderation.validate_content(&message);
+					if !matches!(acceptance, gossipsub::MessageAcceptance::Accept) {
+						tracing::info!(
+							"Validator of topic {} returned {acceptance:?} for message {id} via {peer_id}",
+							message.topic,
+						);
+					}
+				}
+
+				let accepted = matches!(acceptance, gossipsub::MessageAcceptance::Accept);
+				self.swarm
+					.behaviour_mut()
+					.gossipsub
+					.report_message_validation_result(&id, &peer_id, acceptance);
+				if !accepted {
+					return;
+				}
+				if let Some((source, beacon)) = beacon {
+					self.presence.record(source, beacon, tokio::time::Instant::now());
+					return;
+				}
+				if let Some((source, announcement)) = announcement {
+					let now = tokio::time::Instant::now();
+					self.directory.record(source, announcement, Some(message.data), now);
+					return;
+				}
+
+				self.history.record(
+					message.topic.to_string(),
+					message.source,
+					message.data.clone(),
+				);
+
+				if let Some((issuer, bulletin)) = bulletin {
+					tracing::info!("Received bulletin from {issuer}: {:?}", bulletin.kind);
+					if self.bulletins.auto_apply() {
+						self.apply_bulletin(&bulletin);
+					}
+					self.event_sender.send(Event::Bulletin { issuer, bulletin });
+					return;
+				}
+
 				tracing::info!(
 					"Got message: '{}' with id: {id} from peer: {peer_id}",
 					String::from_utf8_lossy(&message.data),
@@ -775,12 +2781,19 @@ impl EventLoop {
 					String::from_utf8_lossy(&message.data),
 				);
 
-				if let Ok(proposal) = deserialize_message::<TaskProposal>(&message.data) {
+				if let (Some(proposer), Ok(proposal)) =
+					(message.source, deserialize_message::<TaskProposal>(&message.data))
+				{
 					self.event_sender
-						.send(Event::InboundTaskProposal { task_proposal: proposal })
-						.await
-						.expect("Event receiver not to be dropped.");
+						.send(Event::InboundTaskProposal { proposer, task_proposal: proposal });
 				}
+				self.event_sender.send(Event::GossipMessage {
+					topic: message.topic.to_string(),
+					source: message.source,
+					propagation_source: peer_id,
+					message_id: id,
+					data: message.data,
+				});
 			},
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed {
 				peer_id,
@@ -812,10 +2825,18 @@ impl EventLoop {
 			// -- Ping events
 			SwarmEvent::Behaviour(AsnBehaviourEvent::Ping(ping::Event {
 				peer,
-				result: Ok(rtt),
-				..
+				connection,
+				result,
 			})) => {
-				tracing::trace!(%peer, "Ping is {}ms", rtt.as_millis())
+				if let Ok(rtt) = &result {
+					tracing::trace!(%peer, "Ping is {}ms", rtt.as_millis());
+					if let Some(info) = self.connected_peers.get_mut(&peer) {
+						info.rtts.record(*rtt);
+					}
+				}
+				if let Some(sender) = self.pending_dial_back.remove(&connection) {
+					let _ = sender.send(result.map_err(|e| Box::new(e) as Box<dyn Error + Send>));
+				}
 			},
 
 			// -- Unhandled events
@@ -830,8 +2851,11 @@ impl EventLoop {
 		match command {
 			Command::StartListening { addr, sender } => {
 				tracing::info!("Listening on {addr}");
-				let _ = match self.swarm.listen_on(addr) {
-					Ok(_) => sender.send(Ok(())),
+				let _ = match self.swarm.listen_on(addr.clone()) {
+					Ok(listener_id) => {
+						self.listeners.insert(listener_id, addr);
+						sender.send(Ok(()))
+					},
 					Err(e) => sender.send(Err(Box::new(e))),
 				};
 			},
@@ -839,16 +2863,46 @@ impl EventLoop {
 				tracing::info!("Dialing {peer_id} at {peer_addr}");
 				if let hash_map::Entry::Vacant(e) = self.pending_dial.entry(peer_id) {
 					self.swarm.behaviour_mut().kademlia.add_address(&peer_id, peer_addr.clone());
-					match self.swarm.dial(peer_addr.with(Protocol::P2p(peer_id))) {
+					match self.swarm.dial(peer_addr.clone().with(Protocol::P2p(peer_id))) {
 						Ok(()) => {
 							e.insert(sender);
+							self.reconnect.track(peer_id, peer_addr);
 						},
 						Err(e) => {
 							let _ = sender.send(Err(Box::new(e)));
 						},
 					}
 				} else {
-					todo!("Already dialing peer.");
+					let _ = sender.send(Err(Box::new(NetworkError::AlreadyDialing)));
+					self.report(EventLoopError::AlreadyDialing(peer_id));
+				}
+			},
+			Command::Disconnect { peer, sender } => {
+				let result = self
+					.swarm
+					.disconnect_peer_id(peer)
+					.map_err(|()| Box::new(NetworkError::NotConnected) as Box<dyn Error + Send>);
+				let _ = sender.send(result);
+			},
+			Command::Ban { peer, duration, sender } => {
+				tracing::warn!("Banning {peer} for {duration:?}");
+				// Blocking the peer also closes its connections.
+				self.swarm.behaviour_mut().blocked_peers.block_peer(peer);
+				self.bans.insert(peer, tokio::time::Instant::now() + duration);
+				let _ = sender.send(Ok(()));
+			},
+			Command::DialBack { peer, sender } => {
+				// A new connection, so the check does not pass over one the peer dialed itself.
+				let opts = DialOpts::peer_id(peer).condition(PeerCondition::Always).build();
+				let connection = opts.connection_id();
+				match self.swarm.dial(opts) {
+					Ok(()) => {
+						tracing::info!("Dialing back {peer}");
+						self.pending_dial_back.insert(connection, sender);
+					},
+					Err(e) => {
+						let _ = sender.send(Err(Box::new(e)));
+					},
 				}
 			},
 			Command::StartProviding { agent_name, sender } => {
@@ -857,35 +2911,272 @@ impl EventLoop {
 					.swarm
 					.behaviour_mut()
 					.kademlia
-					.start_providing(agent_name.into_bytes().into())
+					.start_providing(kad::RecordKey::new(&agent_key(&agent_name)))
 				{
 					Ok(query_id) => {
 						tracing::info!("Started providing");
-						self.pending_start_providing.insert(query_id, sender);
-						self.agents_providing.push(agent_name_to_push);
+						let (query, handle) = PendingQuery::new(query_id);
+						self.pending_start_providing.insert(query_id, query);
+						if !self.agents_providing.contains(&agent_name_to_push) {
+							for topic in self.agent_topics.topics(&agent_name_to_push) {
+								self.swarm.behaviour_mut().subscribe(&topic);
+							}
+							self.agents_providing.push(agent_name_to_push);
+						}
+						let _ = sender.send(Ok(handle));
 					},
 					Err(e) => {
 						tracing::error!("Failed to start providing: {:?}", e);
+						let _ = sender.send(Err(Box::new(e)));
 					},
 				}
 			},
+			Command::StopProviding { agent_name } => {
+				let Some(index) = self.agents_providing.iter().position(|name| *name == agent_name)
+				else {
+					tracing::warn!("Not providing {agent_name}");
+					return;
+				};
+				self.agents_providing.remove(index);
+				self.provided_cards.remove(&agent_name);
+				let key = kad::RecordKey::new(&agent_key(&agent_name));
+				self.swarm.behaviour_mut().kademlia.stop_providing(&key);
+				for topic in self.agent_topics.topics(&agent_name) {
+					tracing::info!("Unsubscribing from topic: {topic}");
+					self.swarm
+						.behaviour_mut()
+						.gossipsub
+						.unsubscribe(&gossipsub::IdentTopic::new(topic));
+				}
+			},
 			Command::GetProviders { agent_name, sender } => {
 				tracing::info!("Getting providers");
 				let query_id = self
 					.swarm
 					.behaviour_mut()
 					.kademlia
-					.get_providers(agent_name.into_bytes().into());
-				self.pending_get_providers.insert(query_id, sender);
+					.get_providers(kad::RecordKey::new(&agent_key(&agent_name)));
+				let (query, handle) = PendingQuery::new(query_id);
+				self.pending_get_providers.insert(query_id, query);
+				let _ = sender.send(Ok(handle));
+			},
+			Command::ProvideArtifact { cid, sender } => {
+				let key = kad::RecordKey::new(&artifact_key(&cid));
+				match self.swarm.behaviour_mut().kademlia.start_providing(key) {
+					Ok(query_id) => {
+						tracing::info!("Mirroring artifact {cid}");
+						let (query, handle) = PendingQuery::new(query_id);
+						self.pending_start_providing.insert(query_id, query);
+						let _ = sender.send(Ok(handle));
+					},
+					Err(e) => {
+						tracing::error!("Failed to provide artifact {cid}: {e:?}");
+						let _ = sender.send(Err(Box::new(e)));
+					},
+				}
 			},
-			Command::RequestAgent { agent_name, message, peer, sender } => {
-				tracing::info!("Requesting agent {agent_name} from {peer}");
-				let request_id = self
+			Command::StopProvidingArtifact { cid } => {
+				let key = kad::RecordKey::new(&artifact_key(&cid));
+				self.swarm.behaviour_mut().kademlia.stop_providing(&key);
+			},
+			Command::GetArtifactProviders { cid, sender } => {
+				let query_id = self
+					.swarm
+					.behaviour_mut()
+					.kademlia
+					.get_providers(kad::RecordKey::new(&artifact_key(&cid)));
+				let (query, handle) = PendingQuery::new(query_id);
+				self.pending_get_providers.insert(query_id, query);
+				let _ = sender.send(Ok(handle));
+			},
+			Command::GetKeyProviders { key, sender } => {
+				let query_id =
+					self.swarm.behaviour_mut().kademlia.get_providers(kad::RecordKey::new(&key));
+				let (query, handle) = PendingQuery::new(query_id);
+				self.pending_get_providers.insert(query_id, query);
+				let _ = sender.send(Ok(handle));
+			},
+			Command::GetClosestPeers { key, sender } => {
+				let query_id =
+					self.swarm.behaviour_mut().kademlia.get_closest_peers(key.into_bytes());
+				self.pending_get_closest_peers.insert(query_id, sender);
+			},
+			Command::KBuckets { sender } => {
+				let kbuckets = self
 					.swarm
 					.behaviour_mut()
-					.request_response
-					.send_request(&peer, LLMRequest(agent_name, message));
-				self.pending_request.insert(request_id, sender);
+					.kademlia
+					.kbuckets()
+					.map(|bucket| KBucketInfo {
+						index: bucket.range().0.ilog2().unwrap_or_default(),
+						peers: bucket
+							.iter()
+							.map(|entry| RoutingPeer {
+								peer_id: *entry.node.key.preimage(),
+								addresses: entry.node.value.iter().cloned().collect(),
+								connected: matches!(entry.status, kad::NodeStatus::Connected),
+							})
+							.collect(),
+					})
+					.collect();
+				let _ = sender.send(kbuckets);
+			},
+			Command::GossipHistory { query, sender } => {
+				let _ = sender.send(self.history.page(&query));
+			},
+			Command::NatStatus { sender } => {
+				let _ = sender.send(self.nat_status.clone());
+			},
+			Command::ListenAddresses { sender } => {
+				let _ = sender.send(self.with_peer_id(self.swarm.listeners()));
+			},
+			Command::ExternalAddresses { sender } => {
+				let _ = sender.send(self.with_peer_id(self.swarm.external_addresses()));
+			},
+			Command::GetConnectedPeers { selector, sender } => {
+				let peers = self
+					.connected_peers
+					.iter()
+					.map(|(peer_id, peer)| ConnectedPeer {
+						peer_id: *peer_id,
+						agent_version: peer.agent_version.clone(),
+						protocols: peer.protocols.clone(),
+						connected_for: peer.connected_since.elapsed(),
+						labels: self.address_book.labels(peer_id),
+					})
+					.filter(|peer| selector.matches(&peer.labels))
+					.collect();
+				let _ = sender.send(peers);
+			},
+			Command::TagPeer { peer, key, value } => self.address_book.tag(peer, key, value),
+			Command::UntagPeer { peer, key } => self.address_book.untag(&peer, &key),
+			Command::LastSeen { peer, sender } => {
+				let _ = sender.send(self.presence.last_seen(&peer, tokio::time::Instant::now()));
+			},
+			Command::OnlineProviders { agent_name, providers, sender } => {
+				let now = tokio::time::Instant::now();
+				let _ = sender.send(self.presence.online(&agent_name, providers, now));
+			},
+			Command::SyncDirectory { peer, sender } => self.sync_directory(peer, Some(sender)),
+			Command::FindAgents { filter, sender } => {
+				let _ = sender.send(self.directory.find(&filter, tokio::time::Instant::now()));
+			},
+			Command::NetworkSnapshot { sender } => {
+				let _ = sender.send(self.network_snapshot().sign(&self.id_key));
+			},
+			Command::PeerLabels { peer, sender } => {
+				let _ = sender.send(self.address_book.labels(&peer));
+			},
+			Command::PeerCapabilities { sender } => {
+				let capabilities = self
+					.connected_peers
+					.iter()
+					.filter_map(|(peer_id, peer)| Some((*peer_id, peer.capabilities.clone()?)))
+					.collect();
+				let _ = sender.send(capabilities);
+			},
+			Command::PeerLatency { peer, sender } => {
+				let _ = sender.send(self.connected_peers.get(&peer).and_then(|p| p.rtts.latency()));
+			},
+			Command::AdmissibleProviders { providers, sender } => {
+				let now = now_ms();
+				let admissible = providers
+					.into_iter()
+					.filter(|peer| !self.circuits.is_open(peer, now))
+					.collect();
+				let _ = sender.send(admissible);
+			},
+			Command::RequestAgent {
+				agent_name,
+				message,
+				context,
+				encrypt_to,
+				language,
+				attachments,
+				job,
+				peer,
+				sender,
+			} => {
+				tracing::info!("Requesting agent {agent_name} from {peer}");
+				self.forget_dropped_requests();
+				// Admitted as the request is sent, for a probe to only start with the request.
+				if !self.circuits.admit(peer, now_ms()) {
+					let _ = sender.send(Err(Box::new(NetworkError::CircuitOpen)));
+					return;
+				}
+				let (request, session) = match encrypt_to {
+					Some(key) => match encryption::seal(&key, &message, &context) {
+						Ok((sealed, session)) => (
+							LLMRequest(
+								agent_name,
+								String::new(),
+								Vec::new(),
+								Some(sealed),
+								language,
+								attachments,
+								job,
+							),
+							Some(session),
+						),
+						Err(e) => {
+							let _ = sender.send(Err(Box::new(e)));
+							return;
+						},
+					},
+					None => (
+						LLMRequest(agent_name, message, context, None, language, attachments, job),
+						None,
+					),
+				};
+				let request_id = self.swarm.behaviour_mut().control.send_request(&peer, request);
+				let (response_sender, handle) = RequestHandle::new(request_id);
+				self.pending_request.insert(request_id, response_sender);
+				self.request_timeouts.start(request_id, peer, Instant::now());
+				if let Some(session) = session {
+					self.sealed_requests.insert(request_id, session);
+				}
+				let _ = sender.send(Ok(handle));
+			},
+			Command::CancelRequest { request_id } => {
+				// The request cannot be withdrawn from the provider, its response is discarded.
+				self.sealed_requests.remove(&request_id);
+				self.request_timeouts.forget(&request_id);
+				if self.pending_request.remove(&request_id).is_some() {
+					tracing::info!("Cancelled request {request_id}");
+				}
+			},
+			Command::RequestArtifact { name, peer, sender } => {
+				let request_id =
+					self.swarm.behaviour_mut().data.send_request(&peer, ArtifactRequest(name));
+				self.pending_artifact_request.insert(request_id, sender);
+			},
+			Command::RespondArtifact { data, channel } => {
+				if let Err(e) =
+					self.swarm.behaviour_mut().data.send_response(channel, ArtifactResponse(data))
+				{
+					tracing::error!("Failed to send artifact: {:?}", e);
+				}
+			},
+			Command::StreamRequest { peer, request, sender } => {
+				let request_id = self.swarm.behaviour_mut().llm_stream.send_request(&peer, request);
+				self.pending_stream_request.insert(request_id, sender);
+			},
+			Command::SubmitBid { peer, bid, sender } => {
+				let request_id = self.swarm.behaviour_mut().bids.send_request(&peer, bid);
+				self.pending_bid.insert(request_id, sender);
+			},
+			Command::SendFeedback { peer, feedback, sender } => {
+				let request_id = self.swarm.behaviour_mut().feedback.send_request(&peer, feedback);
+				self.pending_feedback.insert(request_id, sender);
+			},
+			Command::RunAuction { proposal, config, sender } => {
+				self.open_auction(proposal, config, sender)
+			},
+			Command::GetTaskStatus { task_id, sender } => {
+				let _ = sender.send(self.tasks.get(&task_id).cloned());
+			},
+			Command::UpdateTaskStatus { task_id, status, sender } => {
+				self.update_task_status(task_id, status, sender)
 			},
 			Command::RespondLLM { llm_output: output, channel } => {
 				let output_to_string = String::from_utf8_lossy(&output);
@@ -893,8 +3184,8 @@ impl EventLoop {
 				match self
 					.swarm
 					.behaviour_mut()
-					.request_response
-					.send_response(channel, LLMResponse(output))
+					.control
+					.send_response(channel, LLMResponse::Output(output))
 				{
 					Ok(()) => {},
 					Err(e) => {
@@ -902,9 +3193,98 @@ impl EventLoop {
 					},
 				}
 			},
+			Command::PutRecord { key, value, quorum, sender } => {
+				let record = kad::Record::new(kad::RecordKey::new(&key), value);
+				match self.swarm.behaviour_mut().kademlia.put_record(record, quorum) {
+					Ok(query_id) => {
+						self.pending_put_record.insert(query_id, sender);
+					},
+					Err(e) => {
+						tracing::error!("Failed to store record {key}: {e}");
+						let _ = sender.send(Err(Box::new(e)));
+					},
+				}
+			},
+			Command::GetRecord { key, quorum, sender } => self.get_record(key, quorum, sender),
+			Command::CommitResult { commitment, sender } => {
+				let task_id = commitment.task_id.clone();
+				match commitment.sign(&self.id_key) {
+					Ok(signed) => {
+						self.send_task_result(task_id, TaskResultMessage::Commit(signed), sender)
+					},
+					Err(e) => {
+						let _ = sender.send(Err(Box::new(e)));
+					},
+				}
+			},
+			Command::RevealResult { task_id, result, sender } => {
+				let message = TaskResultMessage::Reveal { task_id: task_id.clone(), result };
+				self.send_task_result(task_id, message, sender)
+			},
+			Command::SignAttestation { attestation, sender } => {
+				let _ = sender.send(
+					attestation
+						.sign(&self.id_key)
+						.map_err(|e| Box::new(e) as Box<dyn Error + Send>),
+				);
+			},
+			Command::PublishAgentCard { mut card, sender } => {
+				if self.encryption_key.is_some() {
+					card.encryption_key = self.encryption_key;
+				}
+				let key = provider_key(&card.name, self.swarm.local_peer_id());
+				let record = match card.encode_signed(&self.id_key) {
+					Ok(data) => kad::Record::new(kad::RecordKey::new(&key), data),
+					Err(e) => {
+						let _ = sender.send(Err(Box::new(e)));
+						return;
+					},
+				};
+				match self.swarm.behaviour_mut().kademlia.put_record(record, kad::Quorum::One) {
+					Ok(query_id) => {
+						tracing::info!("Publishing agent card of {}", card.name);
+						self.pending_put_record.insert(query_id, sender);
+						self.provided_cards.insert(card.name.clone(), card);
+					},
+					Err(e) => {
+						tracing::error!("Failed to store agent card of {}: {e}", card.name);
+						let _ = sender.send(Err(Box::new(e)));
+					},
+				}
+			},
+			Command::AnswerChallenge { nonce, sender } => {
+				let _ = sender.send(
+					challenge::answer(&self.id_key, &nonce)
+						.map_err(|e| Box::new(e) as Box<dyn Error + Send>),
+				);
+			},
+			Command::PublishBulletin { kind, sender } => {
+				let issued_at = now_ms();
+				let result = Bulletin { kind, issued_at }
+					.sign(&self.id_key)
+					.and_then(|signed| Ok(crate::types::serialize_message(&signed)?))
+					.map_err(|e| Box::new(e) as Box<dyn Error + Send>)
+					.and_then(|data| {
+						self.swarm
+							.behaviour_mut()
+							.gossipsub
+							.publish(gossipsub::IdentTopic::new(bulletin::CONTROL_TOPIC), data)
+							.map_err(|e| Box::new(e) as Box<dyn Error + Send>)
+					});
+				match &result {
+					Ok(message_id) => tracing::info!("Published bulletin {message_id}"),
+					Err(e) => tracing::error!("Failed to publish bulletin: {e}"),
+				}
+				let _ = sender.send(result.map(|_| ()));
+			},
 			Command::GossipMessage { topic, message } => {
 				tracing::info!("About to Gossip at {topic}: {message}");
 				let topic = gossipsub::IdentTopic::new(topic);
+				let local_peer_id = *self.swarm.local_peer_id();
+				if !self.moderation.is_authorized(&topic.hash(), Some(&local_peer_id)) {
+					tracing::error!("Not authorized to publish on moderated topic {topic}");
+					return;
+				}
 				match self.swarm.behaviour_mut().gossipsub.publish(topic, message.into_bytes()) {
 					Ok(message_id) => {
 						tracing::info!("Gossip done with message id: {message_id}");
@@ -914,6 +3294,722 @@ impl EventLoop {
 					},
 				}
 			},
+			Command::Subscribe { topic, sender } => {
+				let topic = gossipsub::IdentTopic::new(topic);
+				let result = match self.bulletins.is_banned(&topic.hash()) {
+					true => Err(NetworkError::Gossip(format!("topic {topic} is banned"))),
+					false => self
+						.swarm
+						.behaviour_mut()
+						.gossipsub
+						.subscribe(&topic)
+						.map_err(|e| NetworkError::Gossip(e.to_string())),
+				};
+				match &result {
+					Ok(_) => tracing::info!("Subscribed to topic: {topic}"),
+					Err(e) => tracing::warn!("Failed to subscribe to {topic}: {e}"),
+				}
+				let _ = sender.send(result.map_err(|e| Box::new(e) as Box<dyn Error + Send>));
+			},
+			Command::Unsubscribe { topic, sender } => {
+				tracing::info!("Unsubscribing from topic: {topic}");
+				let topic = gossipsub::IdentTopic::new(topic);
+				let _ = sender.send(self.swarm.behaviour_mut().gossipsub.unsubscribe(&topic));
+			},
+			Command::Topics { sender } => {
+				let topics = self.swarm.behaviour().gossipsub.topics().map(|t| t.to_string());
+				let _ = sender.send(topics.collect());
+			},
+			Command::Bootstrap => self.dial_bootstrap_peers(),
+		}
+	}
+}
+
+/// Sleep until the given instant, forever when there is none.
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+	match deadline {
+		Some(deadline) => tokio::time::sleep_until(deadline).await,
+		None => future::pending().await,
+	}
+}
+
+fn shutting_down() -> Box<dyn Error + Send> {
+	Box::new(NetworkError::ShuttingDown)
+}
+
+/// Answer a command received while shutting down with [`NetworkError::ShuttingDown`].
+fn reject_command(command: Command) {
+	match command {
+		Command::StartListening { sender, .. }
+		| Command::Dial { sender, .. }
+		| Command::PutRecord { sender, .. }
+		| Command::PublishAgentCard { sender, .. }
+		| Command::PublishBulletin { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::StartProviding { sender, .. } | Command::ProvideArtifact { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::GetProviders { sender, .. }
+		| Command::GetArtifactProviders { sender, .. }
+		| Command::GetKeyProviders { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::RequestAgent { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::RequestArtifact { sender, .. } | Command::GetRecord { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::GetClosestPeers { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::StreamRequest { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::SubmitBid { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::SendFeedback { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::RunAuction { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::UpdateTaskStatus { sender, .. }
+		| Command::CommitResult { sender, .. }
+		| Command::RevealResult { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::DialBack { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::Disconnect { sender, .. } | Command::Ban { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::AnswerChallenge { sender, .. } | Command::SignAttestation { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::GossipMessage { topic, .. } => {
+			tracing::warn!("Dropping gossip message to {topic}, shutting down");
+		},
+		Command::Subscribe { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::SyncDirectory { sender, .. } => {
+			let _ = sender.send(Err(shutting_down()));
+		},
+		Command::RespondLLM { .. }
+		| Command::RespondArtifact { .. }
+		| Command::CancelRequest { .. }
+		| Command::StopProviding { .. }
+		| Command::StopProvidingArtifact { .. }
+		| Command::KBuckets { .. }
+		| Command::NatStatus { .. }
+		| Command::GossipHistory { .. }
+		| Command::GetConnectedPeers { .. }
+		| Command::TagPeer { .. }
+		| Command::UntagPeer { .. }
+		| Command::PeerLabels { .. }
+		| Command::LastSeen { .. }
+		| Command::OnlineProviders { .. }
+		| Command::FindAgents { .. }
+		| Command::Unsubscribe { .. }
+		| Command::Topics { .. }
+		| Command::Bootstrap
+		| Command::NetworkSnapshot { .. }
+		| Command::PeerLatency { .. }
+		| Command::AdmissibleProviders { .. }
+		| Command::PeerCapabilities { .. }
+		| Command::GetTaskStatus { .. }
+		| Command::ListenAddresses { .. }
+		| Command::ExternalAddresses { .. } => {},
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::{
+		artifacts::ArtifactError,
+		client::Client,
+		config::{ConnectionLimitsConfig, NetworkConfig},
+		feedback::{Feedback, Rating},
+		stream::StreamError,
+		testing::{RecordingEscrow, TestNetwork},
+		types::{BidResponse, TaskType},
+	};
+
+	#[tokio::test]
+	async fn test_query_completed_twice_is_reported() -> Result<()> {
+		let (_, mut events, _, mut event_loop) = crate::new_in_memory(NetworkConfig::default())?;
+		let key = kad::RecordKey::new(&agent_key("echo"));
+		let id = event_loop.swarm.behaviour_mut().kademlia.start_providing(key)?;
+		let (query, _handle) = PendingQuery::new(id);
+		event_loop.pending_start_providing.insert(id, query);
+
+		assert!(event_loop.take_start_providing(id).is_some());
+		assert!(event_loop.take_start_providing(id).is_none());
+		let reported = events.next().await;
+		assert!(matches!(
+			reported,
+			Some(Event::Error { error: EventLoopError::UnknownQuery(unknown) }) if unknown == id
+		));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_second_dial_of_a_peer_fails_while_the_first_is_pending() -> Result<()> {
+		let (_, mut events, _, mut event_loop) = crate::new_in_memory(NetworkConfig::default())?;
+		let peer_id = PeerId::random();
+		let peer_addr = Multiaddr::empty().with(Protocol::Memory(rand::random()));
+
+		let (first, mut first_receiver) = oneshot::channel();
+		let (second, second_receiver) = oneshot::channel();
+		let dial = |sender| Command::Dial { peer_id, peer_addr: peer_addr.clone(), sender };
+		event_loop.handle_command(dial(first)).await;
+		event_loop.handle_command(dial(second)).await;
+
+		let second = second_receiver.await?.expect_err("A second dial to fail.");
+		assert!(matches!(second.downcast_ref(), Some(NetworkError::AlreadyDialing)));
+		assert!(first_receiver.try_recv()?.is_none());
+		let reported = events.next().await;
+		assert!(matches!(
+			reported,
+			Some(Event::Error { error: EventLoopError::AlreadyDialing(dialed) }) if dialed == peer_id
+		));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_denied_dial_keeps_the_loop_running() -> Result<()> {
+		let point = Multiaddr::empty()
+			.with(Protocol::Memory(rand::random()))
+			.with(Protocol::P2p(PeerId::random()));
+		let mut network = TestNetwork::with_config(1, || NetworkConfig {
+			rendezvous_point: Some(point.clone()),
+			connection_limits: ConnectionLimitsConfig {
+				max_pending_outgoing: Some(0),
+				..Default::default()
+			},
+			..Default::default()
+		})
+		.await?;
+		let node = &mut network.nodes[0];
+
+		let error = node
+			.event(|event| match event {
+				Event::Error { error } => Some(error),
+				_ => None,
+			})
+			.await?;
+		assert!(matches!(error, EventLoopError::Dial { address, .. } if address == point));
+		assert!(node.client.connected_peers().await.map_err(|e| e.to_string())?.is_empty());
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_failing_provider_is_no_longer_admitted() -> Result<()> {
+		let mut network = TestNetwork::with_config(1, || NetworkConfig {
+			circuit_breaker: CircuitBreakerConfig { failure_threshold: 1, ..Default::default() },
+			..Default::default()
+		})
+		.await?;
+		let client = &mut network.nodes[0].client;
+		let unreachable = PeerId::random();
+		assert_eq!(
+			client.admissible_providers([unreachable]).await.map_err(|e| e.to_string())?,
+			HashSet::from([unreachable])
+		);
+
+		let request = client
+			.send_agent_request(unreachable, "echo".to_string(), "hello".to_string(), Vec::new())
+			.await
+			.map_err(|e| e.to_string())?;
+		assert!(request.response().await.is_err());
+		assert!(client
+			.admissible_providers([unreachable])
+			.await
+			.map_err(|e| e.to_string())?
+			.is_empty());
+		let error = client
+			.send_agent_request(unreachable, "echo".to_string(), "hello".to_string(), Vec::new())
+			.await
+			.err()
+			.ok_or("Request sent to an open circuit")?;
+		assert!(matches!(error.downcast_ref::<NetworkError>(), Some(NetworkError::CircuitOpen)));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_topics_are_subscribed_while_running() -> Result<()> {
+		let mut network = TestNetwork::new(1).await?;
+		let client = &mut network.nodes[0].client;
+
+		assert!(client.subscribe("ops".to_string()).await.map_err(|e| e.to_string())?);
+		assert!(!client.subscribe("ops".to_string()).await.map_err(|e| e.to_string())?);
+		assert!(client.topics().await.map_err(|e| e.to_string())?.contains(&"ops".to_string()));
+		assert!(client.unsubscribe("ops".to_string()).await.map_err(|e| e.to_string())?);
+		assert!(!client.topics().await.map_err(|e| e.to_string())?.contains(&"ops".to_string()));
+		assert!(!client.unsubscribe("ops".to_string()).await.map_err(|e| e.to_string())?);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_gossip_message_is_delivered_to_the_subscriber() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let publisher_id = network.nodes[0].peer_id;
+		network.nodes[1]
+			.client
+			.subscribe("ops".to_string())
+			.await
+			.map_err(|e| e.to_string())?;
+
+		// The message is only gossiped once the subscription reached the publisher.
+		let mut client = network.nodes[0].client.clone();
+		loop {
+			match client.gossip("ops".to_string(), "hello".to_string()).await {
+				Err(e) if matches!(e.downcast_ref(), Some(NetworkError::Gossip(_))) => {
+					tokio::time::sleep(Duration::from_millis(100)).await
+				},
+				result => break result.map_err(|e| e.to_string())?,
+			}
+		}
+
+		let (topic, source, propagation_source, data) = network.nodes[1]
+			.event(|event| match event {
+				Event::GossipMessage { topic, source, propagation_source, data, .. } => {
+					Some((topic, source, propagation_source, data))
+				},
+				_ => None,
+			})
+			.await?;
+		assert_eq!(topic, "ops");
+		assert_eq!(source, Some(publisher_id));
+		assert_eq!(propagation_source, publisher_id);
+		assert_eq!(data, b"hello");
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_directory_is_synced_from_a_peer() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let first = network.nodes[0].peer_id;
+		let second = network.nodes[1].peer_id;
+
+		let synced = network.nodes[1].client.sync_directory(first).await;
+		assert_eq!(synced.map_err(|e| e.to_string())?, 0);
+		let unknown = network.nodes[0].client.sync_directory(PeerId::random()).await;
+		assert!(unknown.is_err());
+		let synced = network.nodes[0].client.sync_directory(second).await;
+		assert_eq!(synced.map_err(|e| e.to_string())?, 0);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_request_denied_by_the_policy_of_the_agent() -> Result<()> {
+		let config = || NetworkConfig {
+			request_policies: HashMap::from([("echo".to_string(), "size < 5".parse().unwrap())]),
+			..Default::default()
+		};
+		let mut network = TestNetwork::with_config(2, config).await?;
+		let provider_id = network.nodes[1].peer_id;
+
+		let error = network.nodes[0]
+			.client
+			.request_agent(provider_id, "echo".to_string(), "hello".to_string())
+			.await
+			.expect_err("The request to be denied.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::Denied(_))));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_stream_denied_by_the_policy_of_the_agent() -> Result<()> {
+		let config = || NetworkConfig {
+			request_policies: HashMap::from([("echo".to_string(), "size < 5".parse().unwrap())]),
+			..Default::default()
+		};
+		let mut network = TestNetwork::with_config(2, config).await?;
+		let provider_id = network.nodes[1].peer_id;
+
+		let error = network.nodes[0]
+			.client
+			.request_agent_stream(
+				provider_id,
+				"echo".to_string(),
+				"hello".to_string(),
+				8,
+				Duration::from_secs(10),
+			)
+			.await
+			.err()
+			.expect("The stream to be denied.");
+		assert!(
+			matches!(error.downcast_ref(), Some(StreamError::Rejected(reason)) if reason.contains("denies")),
+			"{error}"
+		);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_commands_fail_once_the_event_loop_stopped() -> Result<()> {
+		let (mut client, _events, _, event_loop) = crate::new_in_memory(NetworkConfig::default())?;
+		let cancellation_token = CancellationToken::new();
+		cancellation_token.cancel();
+		event_loop.run(cancellation_token).await;
+
+		let error = client.topics().await.expect_err("The event loop to be gone.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::ShuttingDown)));
+		let error = client.tag_peer(PeerId::random(), "gpu", "true").await.expect_err("Gone.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::ShuttingDown)));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_artifact_larger_than_the_limit_is_refused_before_its_chunks() -> Result<()> {
+		let config = || NetworkConfig { max_artifact_size: Some(1024), ..Default::default() };
+		let mut network = TestNetwork::with_config(2, config).await?;
+		let origin = network.nodes[1].peer_id;
+		let [requester, provider] = &mut network.nodes[..] else {
+			return Err("Expected two nodes".into());
+		};
+
+		let cid = Cid::of(b"artifact");
+		let mut client = requester.client.clone();
+		let fetch = tokio::spawn(async move { client.fetch_artifact(origin, &cid).await });
+		let (name, channel) = provider
+			.event(|event| match event {
+				Event::ArtifactRequest { name, channel } => Some((name, channel)),
+				_ => None,
+			})
+			.await?;
+		assert_eq!(name, crate::artifacts::manifest_name(&cid));
+		// A manifest claiming far more than it will ever send.
+		let manifest = crate::ArtifactManifest { size: u64::MAX, chunks: vec![cid] };
+		provider.client.respond_artifact(serde_json::to_vec(&manifest)?, channel).await;
+
+		let error = fetch.await?.expect_err("The artifact to be refused.");
+		assert!(
+			matches!(error.downcast_ref(), Some(ArtifactError::TooLarge { limit: 1024, .. })),
+			"{error}"
+		);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_open_streams_count_against_the_inbound_limit() -> Result<()> {
+		let config = || NetworkConfig { max_inbound_requests: Some(1), ..Default::default() };
+		let mut network = TestNetwork::with_config(2, config).await?;
+		let provider_id = network.nodes[1].peer_id;
+		let [requester, provider] = &mut network.nodes[..] else {
+			return Err("Expected two nodes".into());
+		};
+
+		let open = |mut client: Client| async move {
+			let message = "hello".to_string();
+			let timeout = Duration::from_secs(10);
+			client
+				.request_agent_stream(provider_id, "echo".to_string(), message, 8, timeout)
+				.await
+		};
+		let _stream = open(requester.client.clone()).await.map_err(|e| e.to_string())?;
+		// Generating, until its tokens are dropped.
+		let _tokens = provider
+			.event(|event| match event {
+				Event::LLMStreamRequest { tokens, .. } => Some(tokens),
+				_ => None,
+			})
+			.await?;
+
+		let error = open(requester.client.clone()).await.err().expect("The stream to be busy.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::ProviderBusy { limit: 1 })));
+		let error = requester
+			.client
+			.request_agent(provider_id, "echo".to_string(), "hello".to_string())
+			.await
+			.expect_err("The request to be busy.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::ProviderBusy { limit: 1 })));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_bid_is_delivered_to_the_proposer() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let (proposer_id, bidder_id) = (network.nodes[0].peer_id, network.nodes[1].peer_id);
+		let bid = BidResponse {
+			task_id: "task".to_string(),
+			capabilities: Vec::new(),
+			bid: 1.5,
+			payment_address: None,
+		};
+
+		network.nodes[1]
+			.client
+			.submit_bid(proposer_id, bid.clone())
+			.await
+			.map_err(|e| e.to_string())?;
+		let (bidder, received) = network.nodes[0]
+			.event(|event| match event {
+				Event::InboundBid { bidder, bid } => Some((bidder, bid)),
+				_ => None,
+			})
+			.await?;
+		assert_eq!(bidder, bidder_id);
+		assert_eq!(received, bid);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_feedback_is_delivered_to_the_provider() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let (provider_id, requester_id) = (network.nodes[0].peer_id, network.nodes[1].peer_id);
+		let feedback = Feedback {
+			receipt: crate::feedback::receipt("echo", "hello", b"hello"),
+			agent_name: "echo".to_string(),
+			rating: Rating::Down,
+			comment: Some("Too short".to_string()),
+		};
+
+		network.nodes[1]
+			.client
+			.send_feedback(provider_id, feedback.clone())
+			.await
+			.map_err(|e| e.to_string())?;
+		let (requester, received) = network.nodes[0]
+			.event(|event| match event {
+				Event::Feedback { requester, feedback } => Some((requester, feedback)),
+				_ => None,
+			})
+			.await?;
+		assert_eq!(requester, requester_id);
+		assert_eq!(received, feedback);
+		Ok(())
+	}
+
+	/// Auction a task of node 0 that node 1 bids on, returning the winner and the award received.
+	async fn auction_task(
+		network: &mut TestNetwork,
+		deadline: u64,
+	) -> Result<(Option<AuctionWinner>, TaskAward)> {
+		let proposer_id = network.nodes[0].peer_id;
+		let proposal = TaskProposal {
+			agent_name: "painter".to_string(),
+			task_id: "task".to_string(),
+			task_type: TaskType::ImageGeneration,
+			task_message: "a cat".to_string(),
+			max_bid: 10.0,
+			deadline,
+			requirements: Default::default(),
+		};
+		let config = AuctionConfig { window: Duration::from_secs(1), ..Default::default() };
+
+		let mut client = network.nodes[0].client.clone();
+		let auction = tokio::spawn(async move {
+			// The proposal is only gossiped once the subscription of the bidder reached the node.
+			loop {
+				match client.run_auction(proposal.clone(), config.clone()).await {
+					Err(e) if matches!(e.downcast_ref(), Some(NetworkError::Gossip(_))) => {
+						tokio::time::sleep(Duration::from_millis(100)).await
+					},
+					result => return result.map_err(|e| e.to_string()),
+				}
+			}
+		});
+
+		let bidder = &mut network.nodes[1];
+		let (proposer, task_id) = bidder
+			.event(|event| match event {
+				Event::InboundTaskProposal { proposer, task_proposal } => {
+					Some((proposer, task_proposal.task_id))
+				},
+				_ => None,
+			})
+			.await?;
+		assert_eq!(proposer, proposer_id);
+		let bid = BidResponse {
+			task_id,
+			capabilities: Vec::new(),
+			bid: 2.5,
+			payment_address: Some("0x52908400098527886E0F7030069857D2E4169EE7".to_string()),
+		};
+		bidder.client.submit_bid(proposer, bid).await.map_err(|e| e.to_string())?;
+
+		let winner = auction.await??;
+		let award = bidder
+			.event(|event| match event {
+				Event::TaskAwarded { proposer, award } => {
+					(proposer == proposer_id).then_some(award)
+				},
+				_ => None,
+			})
+			.await?;
+		Ok((winner, award))
+	}
+
+	#[tokio::test]
+	async fn test_auction_awards_the_task_to_the_bidder() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let bidder_id = network.nodes[1].peer_id;
+		let deadline = now_ms() / 1000 + 60;
+
+		let (winner, award_received) = auction_task(&mut network, deadline).await?;
+		let bid = BidResponse {
+			task_id: "task".to_string(),
+			capabilities: Vec::new(),
+			bid: 2.5,
+			payment_address: Some("0x52908400098527886E0F7030069857D2E4169EE7".to_string()),
+		};
+		assert_eq!(winner, Some(AuctionWinner { bidder: bidder_id, bid }));
+		let award = TaskAward { task_id: "task".to_string(), bid: 2.5, max_bid: 10.0, deadline };
+		assert_eq!(award, award_received);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_worker_reports_the_status_of_the_task_to_its_owner() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let (owner_id, worker_id) = (network.nodes[0].peer_id, network.nodes[1].peer_id);
+		auction_task(&mut network, now_ms() / 1000 + 60).await?;
+
+		let task = network.nodes[1]
+			.client
+			.get_task_status("task")
+			.await
+			.map_err(|e| e.to_string())?
+			.expect("A task.");
+		assert_eq!((task.owner, task.worker), (owner_id, Some(worker_id)));
+		assert_eq!(task.status, TaskStatus::Assigned);
+		for status in [TaskStatus::InProgress, TaskStatus::Completed] {
+			network.nodes[1]
+				.client
+				.update_task_status("task", status)
+				.await
+				.map_err(|e| e.to_string())?;
 		}
+
+		let owner = &mut network.nodes[0];
+		owner
+			.event(|event| match event {
+				Event::TaskStatusChanged { task } => {
+					(task.status == TaskStatus::Completed).then_some(())
+				},
+				_ => None,
+			})
+			.await?;
+		let task = owner
+			.client
+			.get_task_status("task")
+			.await
+			.map_err(|e| e.to_string())?
+			.expect("A task.");
+		assert_eq!(task.worker, Some(worker_id));
+		// Finished tasks cannot be reopened.
+		let error = owner
+			.client
+			.update_task_status("task", TaskStatus::InProgress)
+			.await
+			.expect_err("The transition to be refused.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::Task(_))));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_escrow_settles_the_tasks_auctioned_by_the_node() -> Result<()> {
+		let escrow = Arc::new(RecordingEscrow::default());
+		let task_escrow: Arc<dyn crate::TaskEscrow> = escrow.clone();
+		let mut network = TestNetwork::with_config(2, || NetworkConfig {
+			task_escrow: Some(task_escrow.clone()),
+			..Default::default()
+		})
+		.await?;
+		auction_task(&mut network, now_ms() / 1000 + 60).await?;
+		for status in [TaskStatus::InProgress, TaskStatus::Completed] {
+			network.nodes[1]
+				.client
+				.update_task_status("task", status)
+				.await
+				.map_err(|e| e.to_string())?;
+		}
+
+		// Only the owner of the task pays for it, once its worker completed it.
+		let settled = async {
+			while escrow.calls.lock().unwrap().len() < 2 {
+				tokio::time::sleep(Duration::from_millis(50)).await;
+			}
+		};
+		tokio::time::timeout(Duration::from_secs(10), settled).await?;
+		assert_eq!(*escrow.calls.lock().unwrap(), ["lock task 10", "release task 10"]);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_revealed_result_completes_the_task_when_it_matches_its_commitment() -> Result<()>
+	{
+		let mut network = TestNetwork::new(2).await?;
+		let worker_id = network.nodes[1].peer_id;
+		auction_task(&mut network, now_ms() / 1000 + 60).await?;
+
+		let worker = &mut network.nodes[1].client;
+		let error = worker.reveal_result("task", b"42".to_vec()).await.expect_err("No commitment.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::ResultRefused(_))));
+		worker.commit_result("task", b"42").await.map_err(|e| e.to_string())?;
+		let error = worker.commit_result("task", b"43").await.expect_err("Committed already.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::ResultRefused(_))));
+		worker.reveal_result("task", b"42".to_vec()).await.map_err(|e| e.to_string())?;
+		let task = worker
+			.get_task_status("task")
+			.await
+			.map_err(|e| e.to_string())?
+			.expect("A task.");
+		assert_eq!(task.status, TaskStatus::Completed);
+
+		let owner = &mut network.nodes[0];
+		let (worker, result) = owner
+			.event(|event| match event {
+				Event::TaskResult { worker, result, .. } => Some((worker, result)),
+				_ => None,
+			})
+			.await?;
+		assert_eq!((worker, result), (worker_id, b"42".to_vec()));
+		let task = owner
+			.client
+			.get_task_status("task")
+			.await
+			.map_err(|e| e.to_string())?
+			.expect("A task.");
+		assert_eq!(task.status, TaskStatus::Completed);
+		let commitment = task.commitment.as_ref().expect("The commitment as evidence.");
+		let (signer, commitment) = ResultCommitment::verify(commitment)?;
+		assert_eq!(signer, worker_id);
+		assert!(commitment.matches(b"42"));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_result_not_matching_its_commitment_fails_the_task() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		auction_task(&mut network, now_ms() / 1000 + 60).await?;
+
+		let worker = &mut network.nodes[1].client;
+		worker.commit_result("task", b"42").await.map_err(|e| e.to_string())?;
+		let error = worker.reveal_result("task", b"43".to_vec()).await.expect_err("A mismatch.");
+		assert!(matches!(error.downcast_ref(), Some(NetworkError::ResultRefused(_))));
+
+		let task = network.nodes[0]
+			.client
+			.get_task_status("task")
+			.await
+			.map_err(|e| e.to_string())?
+			.expect("A task.");
+		assert!(matches!(task.status, TaskStatus::Failed { .. }));
+		Ok(())
 	}
 }
+
+// endregion: --- Tests
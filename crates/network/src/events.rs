@@ -0,0 +1,141 @@
+use std::{
+	collections::VecDeque,
+	pin::Pin,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	task::{Context, Poll},
+};
+
+use futures::{task::AtomicWaker, Stream};
+use prometheus_client::metrics::counter::Counter;
+
+use crate::types::Event;
+
+#[derive(Debug)]
+struct Queue {
+	events: Mutex<VecDeque<Event>>,
+	capacity: usize,
+	waker: AtomicWaker,
+	/// Whether the event loop is gone.
+	closed: AtomicBool,
+}
+
+/// Sees each event as the event loop sends it, whether or not the application reads it.
+pub trait EventObserver: std::fmt::Debug + Send + Sync {
+	fn observe(&self, event: &Event);
+}
+
+/// Event loop side of the event channel. Sending never waits on the application: once `capacity`
+/// events are waiting to be read, the oldest one is dropped to make room.
+#[derive(Debug)]
+pub struct EventSender {
+	queue: Arc<Queue>,
+	dropped: Counter,
+	observers: Vec<Arc<dyn EventObserver>>,
+}
+
+/// The events of the network, as returned by [`crate::new`].
+#[derive(Debug)]
+pub struct EventReceiver {
+	queue: Arc<Queue>,
+}
+
+/// A channel keeping at most `capacity` events, counting the ones dropped in `dropped`.
+pub(crate) fn channel(capacity: usize, dropped: Counter) -> (EventSender, EventReceiver) {
+	let queue = Arc::new(Queue {
+		events: Mutex::new(VecDeque::new()),
+		capacity: capacity.max(1),
+		waker: AtomicWaker::new(),
+		closed: AtomicBool::new(false),
+	});
+	(EventSender { queue: queue.clone(), dropped, observers: Vec::new() }, EventReceiver { queue })
+}
+
+impl EventSender {
+	pub(crate) fn add_observer(&mut self, observer: Arc<dyn EventObserver>) {
+		self.observers.push(observer);
+	}
+
+	pub fn send(&self, event: Event) {
+		for observer in &self.observers {
+			observer.observe(&event);
+		}
+
+		// Nobody reading the events anymore is fine.
+		if Arc::strong_count(&self.queue) == 1 {
+			return;
+		}
+
+		let mut events = self.queue.events.lock().expect("Event queue not to be poisoned.");
+		if events.len() >= self.queue.capacity {
+			if let Some(dropped) = events.pop_front() {
+				tracing::warn!("Event queue full, dropping the oldest event: {dropped:?}");
+				self.dropped.inc();
+			}
+		}
+		events.push_back(event);
+		drop(events);
+		self.queue.waker.wake();
+	}
+}
+
+impl Drop for EventSender {
+	fn drop(&mut self) {
+		// Ends the stream once the events left are read.
+		self.queue.closed.store(true, Ordering::SeqCst);
+		self.queue.waker.wake();
+	}
+}
+
+impl Stream for EventReceiver {
+	type Item = Event;
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+		self.queue.waker.register(cx.waker());
+		let mut events = self.queue.events.lock().expect("Event queue not to be poisoned.");
+		match events.pop_front() {
+			Some(event) => Poll::Ready(Some(event)),
+			None if self.queue.closed.load(Ordering::SeqCst) => Poll::Ready(None),
+			None => Poll::Pending,
+		}
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::StreamExt;
+	use libp2p::PeerId;
+
+	fn event(peer_id: PeerId) -> Event {
+		Event::PeerDisconnected { peer_id, cause: None }
+	}
+
+	#[tokio::test]
+	async fn test_full_event_queue_drops_the_oldest() {
+		let dropped = Counter::default();
+		let (sender, receiver) = channel(2, dropped.clone());
+		let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+
+		for peer in peers {
+			sender.send(event(peer));
+		}
+		drop(sender);
+
+		let received: Vec<_> = receiver
+			.map(|event| match event {
+				Event::PeerDisconnected { peer_id, .. } => peer_id,
+				event => panic!("Unexpected event {event:?}"),
+			})
+			.collect()
+			.await;
+		assert_eq!(received, peers[1..]);
+		assert_eq!(dropped.get(), 1);
+	}
+}
+
+// endregion: --- Tests
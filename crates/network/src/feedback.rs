@@ -0,0 +1,110 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cid::Cid;
+
+/// Receipt of an exchange with an agent: the content id of the agent name, the message and the
+/// response. Requester and provider compute it alike, so the feedback of the requester refers to
+/// an exchange the provider recorded without the provider issuing anything.
+pub fn receipt(agent_name: &str, message: &str, response: &[u8]) -> Cid {
+	let mut data = Vec::with_capacity(24 + agent_name.len() + message.len() + response.len());
+	// Length prefixes, for the fields not to run into each other.
+	for field in [agent_name.as_bytes(), message.as_bytes(), response] {
+		data.extend_from_slice(&(field.len() as u64).to_be_bytes());
+		data.extend_from_slice(field);
+	}
+	Cid::of(data)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+	Up,
+	Down,
+}
+
+impl fmt::Display for Rating {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Rating::Up => "up",
+			Rating::Down => "down",
+		})
+	}
+}
+
+impl FromStr for Rating {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"up" | "+1" => Ok(Rating::Up),
+			"down" | "-1" => Ok(Rating::Down),
+			_ => Err(format!("Unknown rating {s}, expected up or down")),
+		}
+	}
+}
+
+/// Thumbs up or down of a requester on an exchange, sent to the provider that answered it with
+/// [`crate::Client::send_feedback`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Feedback {
+	pub receipt: Cid,
+	pub agent_name: String,
+	pub rating: Rating,
+	pub comment: Option<String>,
+}
+
+/// Ratings received by an agent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgentQuality {
+	pub up: u64,
+	pub down: u64,
+}
+
+impl AgentQuality {
+	pub fn record(&mut self, rating: Rating) {
+		match rating {
+			Rating::Up => self.up += 1,
+			Rating::Down => self.down += 1,
+		}
+	}
+
+	/// Share of thumbs up, from -1 when all are down to 1 when all are up, 0 without any rating.
+	/// Usable as the reputation of a bidder or the score of a provider candidate.
+	pub fn score(&self) -> f64 {
+		let total = self.up + self.down;
+		if total == 0 {
+			return 0.0;
+		}
+		(self.up as f64 - self.down as f64) / total as f64
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_receipt_identifies_the_exchange() {
+		let receipt = receipt("echo", "hello", b"hello");
+		assert_eq!(receipt, super::receipt("echo", "hello", b"hello"));
+		assert_ne!(receipt, super::receipt("echo", "hell", b"ohello"));
+		assert_ne!(receipt, super::receipt("echo", "hello", b"world"));
+	}
+
+	#[test]
+	fn test_agent_quality_score() {
+		let mut quality = AgentQuality::default();
+		assert_eq!(quality.score(), 0.0);
+		for rating in [Rating::Up, Rating::Up, Rating::Up, Rating::Down] {
+			quality.record(rating);
+		}
+		assert_eq!(quality, AgentQuality { up: 3, down: 1 });
+		assert_eq!(quality.score(), 0.5);
+	}
+}
+
+// endregion: --- Tests
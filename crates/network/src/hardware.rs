@@ -0,0 +1,237 @@
+//! Hardware of the node and the requirements of the task proposals on it. The profile is detected
+//! at startup and announced along with the other [`crate::NodeCapabilities`], so that proposers
+//! can tell which bidders are able to run their tasks.
+
+use std::{path::PathBuf, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+/// Hardware of a node. Values that could not be detected are unset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HardwareProfile {
+	pub cpu_cores: Option<u32>,
+	pub ram_mb: Option<u64>,
+	/// Space available in the working directory of the node.
+	pub disk_mb: Option<u64>,
+	/// Model of the first GPU, e.g. `NVIDIA-A100-SXM4-40GB`, spaces replaced by dashes.
+	pub gpu: Option<String>,
+	pub vram_mb: Option<u64>,
+	/// Models installed locally, e.g. `llama3:8b`.
+	pub models: Vec<String>,
+}
+
+impl HardwareProfile {
+	/// Detect the hardware of the host and the models installed with Ollama. GPUs are looked up
+	/// with `nvidia-smi`, so only NVIDIA GPUs are found.
+	pub fn detect() -> Self {
+		let (gpu, vram_mb) = gpu().unzip();
+		Self {
+			cpu_cores: std::thread::available_parallelism().ok().map(|n| n.get() as u32),
+			ram_mb: ram_mb(),
+			disk_mb: disk_mb(),
+			gpu,
+			vram_mb: vram_mb.flatten(),
+			models: ollama_models(),
+		}
+	}
+
+	/// The profile as `key=value` fields of the identify agent version.
+	pub(crate) fn to_fields(&self) -> Vec<String> {
+		let mut fields = Vec::new();
+		for (key, value) in [
+			("cores", self.cpu_cores.map(u64::from)),
+			("ram", self.ram_mb),
+			("disk", self.disk_mb),
+			("vram", self.vram_mb),
+		] {
+			if let Some(value) = value {
+				fields.push(format!("{key}={value}"));
+			}
+		}
+		if let Some(gpu) = &self.gpu {
+			fields.push(format!("gpu={}", compact(gpu)));
+		}
+		let models: Vec<_> = self.models.iter().map(|model| compact(model)).collect();
+		if !models.is_empty() {
+			fields.push(format!("models={}", models.join(",")));
+		}
+		fields
+	}
+
+	/// Read a field of the identify agent version, skipping the fields of other keys.
+	pub(crate) fn parse_field(&mut self, key: &str, value: &str) {
+		match key {
+			"cores" => self.cpu_cores = value.parse().ok(),
+			"ram" => self.ram_mb = value.parse().ok(),
+			"disk" => self.disk_mb = value.parse().ok(),
+			"vram" => self.vram_mb = value.parse().ok(),
+			"gpu" => self.gpu = Some(value.to_string()).filter(|gpu| !gpu.is_empty()),
+			"models" => {
+				self.models =
+					value.split(',').filter(|model| !model.is_empty()).map(String::from).collect()
+			},
+			_ => {},
+		}
+	}
+}
+
+/// Hardware a task proposal needs, matched against the announced profile of its bidders. Unset
+/// requirements match any node, set ones fail on nodes that did not announce the value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareRequirements {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub min_cpu_cores: Option<u32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub min_ram_mb: Option<u64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub min_disk_mb: Option<u64>,
+	/// Whether a GPU is needed at all, implied by `min_vram_mb`.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub gpu: bool,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub min_vram_mb: Option<u64>,
+	/// Models that must be installed on the node.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub models: Vec<String>,
+}
+
+impl HardwareRequirements {
+	pub fn is_empty(&self) -> bool {
+		*self == Self::default()
+	}
+
+	pub fn satisfied_by(&self, profile: &HardwareProfile) -> bool {
+		fn at_least<T: PartialOrd>(min: Option<T>, value: Option<T>) -> bool {
+			min.is_none_or(|min| value.is_some_and(|value| value >= min))
+		}
+
+		at_least(self.min_cpu_cores, profile.cpu_cores)
+			&& at_least(self.min_ram_mb, profile.ram_mb)
+			&& at_least(self.min_disk_mb, profile.disk_mb)
+			&& at_least(self.min_vram_mb, profile.vram_mb)
+			&& (!self.gpu || profile.gpu.is_some())
+			&& self.models.iter().all(|model| profile.models.contains(model))
+	}
+}
+
+fn compact(value: &str) -> String {
+	value.trim().replace(|c: char| c.is_whitespace() || c == ',' || c == '=', "-")
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+	let output = Command::new(program).args(args).output().ok()?;
+	output
+		.status
+		.success()
+		.then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn ram_mb() -> Option<u64> {
+	match std::fs::read_to_string("/proc/meminfo") {
+		Ok(meminfo) => parse_meminfo(&meminfo),
+		// macOS
+		Err(_) => run("sysctl", &["-n", "hw.memsize"])?
+			.trim()
+			.parse::<u64>()
+			.ok()
+			.map(|bytes| bytes / (1024 * 1024)),
+	}
+}
+
+fn disk_mb() -> Option<u64> {
+	parse_df(&run("df", &["-Pk", "."])?)
+}
+
+fn gpu() -> Option<(String, Option<u64>)> {
+	let output =
+		run("nvidia-smi", &["--query-gpu=name,memory.total", "--format=csv,noheader,nounits"])?;
+	parse_nvidia_smi(&output)
+}
+
+/// The models pulled with Ollama, from the manifests of its model directory.
+fn ollama_models() -> Vec<String> {
+	let directory = match std::env::var_os("OLLAMA_MODELS") {
+		Some(directory) => PathBuf::from(directory),
+		None => match std::env::var_os("HOME") {
+			Some(home) => PathBuf::from(home).join(".ollama/models"),
+			None => return Vec::new(),
+		},
+	};
+	let library = directory.join("manifests/registry.ollama.ai/library");
+	let mut models = Vec::new();
+	for model in std::fs::read_dir(library).into_iter().flatten().flatten() {
+		for tag in std::fs::read_dir(model.path()).into_iter().flatten().flatten() {
+			models.push(format!(
+				"{}:{}",
+				model.file_name().to_string_lossy(),
+				tag.file_name().to_string_lossy()
+			));
+		}
+	}
+	models.sort();
+	models
+}
+
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+	let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+	let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+	Some(kb / 1024)
+}
+
+/// The available space, from the POSIX output of `df -Pk`.
+fn parse_df(output: &str) -> Option<u64> {
+	let kb: u64 = output.lines().nth(1)?.split_whitespace().nth(3)?.parse().ok()?;
+	Some(kb / 1024)
+}
+
+/// The name and memory of the first GPU listed by `nvidia-smi`.
+fn parse_nvidia_smi(output: &str) -> Option<(String, Option<u64>)> {
+	let (name, memory) = output.lines().next()?.split_once(',')?;
+	Some((compact(name), memory.trim().parse().ok()))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_detected_hardware() {
+		let meminfo = "MemTotal:       16318496 kB\nMemFree:         1270740 kB\n";
+		assert_eq!(parse_meminfo(meminfo), Some(15936));
+		let df = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+		          /dev/nvme0n1p2   479596200 201034500 254105292      45% /\n";
+		assert_eq!(parse_df(df), Some(248149));
+		let smi = "NVIDIA A100-SXM4-40GB, 40960\nNVIDIA A100-SXM4-40GB, 40960\n";
+		assert_eq!(parse_nvidia_smi(smi), Some(("NVIDIA-A100-SXM4-40GB".to_string(), Some(40960))));
+	}
+
+	#[test]
+	fn test_requirements_match_the_profile() {
+		let profile = HardwareProfile {
+			cpu_cores: Some(8),
+			ram_mb: Some(32_768),
+			gpu: Some("NVIDIA-A100".to_string()),
+			vram_mb: Some(40_960),
+			models: vec!["llama3:8b".to_string()],
+			..Default::default()
+		};
+		let requirements = HardwareRequirements {
+			min_cpu_cores: Some(8),
+			min_vram_mb: Some(24_000),
+			models: vec!["llama3:8b".to_string()],
+			..Default::default()
+		};
+		assert!(HardwareRequirements::default().satisfied_by(&HardwareProfile::default()));
+		assert!(requirements.satisfied_by(&profile));
+		assert!(!requirements.satisfied_by(&HardwareProfile { vram_mb: None, ..profile.clone() }));
+		let unknown_disk = HardwareRequirements { min_disk_mb: Some(1), ..Default::default() };
+		assert!(!unknown_disk.satisfied_by(&profile));
+		let more_models =
+			HardwareRequirements { models: vec!["mistral:7b".to_string()], ..requirements };
+		assert!(!more_models.satisfied_by(&profile));
+	}
+}
+
+// endregion: --- Tests
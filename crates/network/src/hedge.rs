@@ -0,0 +1,96 @@
+use std::{future::Future, time::Duration};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+
+/// Call the first candidate, then the next one whenever no call answered within `delay` or all
+/// the calls in flight failed. At most two calls are in flight at once, bounding the extra cost.
+///
+/// Returns the first success, dropping the call still in flight, or the last error once every
+/// candidate failed. None when there is no candidate.
+pub(crate) async fn hedge<C, T, E, Fut>(
+	candidates: impl IntoIterator<Item = C>,
+	delay: Duration,
+	mut call: impl FnMut(C) -> Fut,
+) -> Option<Result<T, E>>
+where
+	Fut: Future<Output = Result<T, E>>,
+{
+	let mut candidates = candidates.into_iter().peekable();
+	let mut in_flight = FuturesUnordered::new();
+	let mut last_error = None;
+	loop {
+		if in_flight.is_empty() {
+			match candidates.next() {
+				Some(candidate) => in_flight.push(call(candidate)),
+				None => return last_error.map(Err),
+			}
+		}
+
+		let hedging = in_flight.len() < 2 && candidates.peek().is_some();
+		tokio::select! {
+			Some(result) = in_flight.next() => match result {
+				Ok(output) => return Some(Ok(output)),
+				Err(e) => last_error = Some(e),
+			},
+			_ = tokio::time::sleep(delay), if hedging => {
+				if let Some(candidate) = candidates.next() {
+					in_flight.push(call(candidate));
+				}
+			},
+		}
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::{Arc, Mutex};
+
+	/// Candidates answering after the given delay, failing when unset.
+	async fn answer(
+		called: Arc<Mutex<Vec<u32>>>,
+		(id, after): (u32, Option<u64>),
+	) -> Result<u32, u32> {
+		called.lock().unwrap().push(id);
+		match after {
+			Some(after) => {
+				tokio::time::sleep(Duration::from_millis(after)).await;
+				Ok(id)
+			},
+			None => Err(id),
+		}
+	}
+
+	#[tokio::test]
+	async fn test_hedge() {
+		let delay = Duration::from_millis(50);
+
+		// The best answers in time, the next one is never called.
+		let called = Arc::new(Mutex::new(Vec::new()));
+		let candidates = [(1, Some(0)), (2, Some(0))];
+		let result = hedge(candidates, delay, |c| answer(called.clone(), c)).await;
+		assert_eq!(result, Some(Ok(1)));
+		assert_eq!(*called.lock().unwrap(), [1]);
+
+		// The best is slow, the duplicate sent to the next one wins.
+		let called = Arc::new(Mutex::new(Vec::new()));
+		let candidates = [(1, Some(10_000)), (2, Some(0)), (3, Some(0))];
+		let result = hedge(candidates, delay, |c| answer(called.clone(), c)).await;
+		assert_eq!(result, Some(Ok(2)));
+		assert_eq!(*called.lock().unwrap(), [1, 2]);
+
+		// Failures fail over right away.
+		let called = Arc::new(Mutex::new(Vec::new()));
+		let candidates = [(1, None), (2, None)];
+		let result = hedge(candidates, delay, |c| answer(called.clone(), c)).await;
+		assert_eq!(result, Some(Err(2)));
+		assert_eq!(
+			hedge(Vec::<(u32, Option<u64>)>::new(), delay, |c| answer(called.clone(), c)).await,
+			None
+		);
+	}
+}
+
+// endregion: --- Tests
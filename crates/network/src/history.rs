@@ -0,0 +1,292 @@
+//! The last gossip messages accepted on each topic, served by [`crate::Client::gossip_history`].
+//! Given a store, the messages are appended to it as they are accepted and loaded back on start,
+//! so the history survives a restart of the node. The store is compacted to the kept messages
+//! on start and whenever the dropped ones outnumber them.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	error::Error,
+	fs::{File, OpenOptions},
+	io::{BufRead, BufReader, Write},
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::persist::atomic_write;
+
+/// Largest page of messages returned by [`crate::Client::gossip_history`].
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// How many of the gossip messages accepted on each topic the node keeps for
+/// [`crate::Client::gossip_history`], the oldest ones being dropped first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryConfig {
+	pub messages_per_topic: usize,
+}
+
+impl Default for HistoryConfig {
+	fn default() -> Self {
+		Self { messages_per_topic: 256 }
+	}
+}
+
+/// A gossip message accepted by the node.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GossipRecord {
+	/// Position of the message in the history, increasing across all topics.
+	pub cursor: u64,
+	pub topic: String,
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub source: Option<PeerId>,
+	pub received_at: SystemTime,
+	pub data: Vec<u8>,
+}
+
+/// Page of the history to fetch: the messages after `after` on the given topics, or on all of
+/// them when empty, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryQuery {
+	pub topics: Vec<String>,
+	pub after: Option<u64>,
+	/// Capped to [`MAX_PAGE_SIZE`].
+	pub limit: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryPage {
+	pub messages: Vec<GossipRecord>,
+	/// Cursor to pass as `after` for the next page, when more messages are kept.
+	pub next_cursor: Option<u64>,
+}
+
+/// The last gossip messages accepted on each topic.
+#[derive(Debug)]
+pub(crate) struct MessageHistory {
+	config: HistoryConfig,
+	topics: HashMap<String, VecDeque<GossipRecord>>,
+	next_cursor: u64,
+	store: Option<HistoryStore>,
+}
+
+/// File the accepted messages are appended to, one JSON record per line.
+#[derive(Debug)]
+struct HistoryStore {
+	path: PathBuf,
+	file: File,
+	/// Records in the file, the kept messages and the dropped ones not compacted yet.
+	records: usize,
+}
+
+impl MessageHistory {
+	pub(crate) fn new(config: HistoryConfig) -> Self {
+		Self { config, topics: Default::default(), next_cursor: 0, store: None }
+	}
+
+	/// A history keeping its messages in `store`, starting from the ones already there. Messages
+	/// only live in memory when unset.
+	pub(crate) fn open(config: HistoryConfig, store: Option<PathBuf>) -> Self {
+		let mut history = Self::new(config);
+		let Some(path) = store.filter(|_| config.messages_per_topic > 0) else {
+			return history;
+		};
+		if path.exists() {
+			match load(&path) {
+				Ok(records) => records.into_iter().for_each(|record| history.keep(record)),
+				Err(e) => tracing::error!("Failed to load the gossip history: {e}"),
+			}
+		}
+		match history.compact(path) {
+			Ok(store) => history.store = Some(store),
+			Err(e) => tracing::error!("Failed to write the gossip history: {e}"),
+		}
+		history
+	}
+
+	pub(crate) fn record(&mut self, topic: String, source: Option<PeerId>, data: Vec<u8>) {
+		if self.config.messages_per_topic == 0 {
+			return;
+		}
+
+		let record = GossipRecord {
+			cursor: self.next_cursor,
+			topic,
+			source,
+			received_at: SystemTime::now(),
+			data,
+		};
+		let appended = self.append(&record);
+		self.keep(record);
+		if let Err(e) = appended.and_then(|()| self.compact_when_outnumbered()) {
+			tracing::error!("Failed to write the gossip history: {e}");
+		}
+	}
+
+	fn keep(&mut self, record: GossipRecord) {
+		self.next_cursor = self.next_cursor.max(record.cursor + 1);
+		let messages = self.topics.entry(record.topic.clone()).or_default();
+		if messages.len() >= self.config.messages_per_topic {
+			messages.pop_front();
+		}
+		messages.push_back(record);
+	}
+
+	fn append(&mut self, record: &GossipRecord) -> Result<(), Box<dyn Error>> {
+		let Some(store) = &mut self.store else {
+			return Ok(());
+		};
+		let mut line = serde_json::to_vec(record)?;
+		line.push(b'\n');
+		store.file.write_all(&line)?;
+		store.records += 1;
+		Ok(())
+	}
+
+	/// Compact the store once the dropped messages outnumber the kept ones.
+	fn compact_when_outnumbered(&mut self) -> Result<(), Box<dyn Error>> {
+		let Some(store) = &self.store else {
+			return Ok(());
+		};
+		let kept: usize = self.topics.values().map(VecDeque::len).sum();
+		if store.records > 2 * kept {
+			let path = store.path.clone();
+			self.store = Some(self.compact(path)?);
+		}
+		Ok(())
+	}
+
+	/// Rewrite the store with the kept messages only, replaced at once for a crash not to lose the
+	/// history, then append to it.
+	fn compact(&self, path: PathBuf) -> Result<HistoryStore, Box<dyn Error>> {
+		let mut records: Vec<_> = self.topics.values().flatten().collect();
+		records.sort_unstable_by_key(|record| record.cursor);
+		let mut lines = Vec::new();
+		for record in &records {
+			serde_json::to_writer(&mut lines, record)?;
+			lines.push(b'\n');
+		}
+
+		atomic_write(&path, &lines)?;
+		let file = OpenOptions::new().append(true).open(&path)?;
+		Ok(HistoryStore { path, file, records: records.len() })
+	}
+
+	pub(crate) fn page(&self, query: &HistoryQuery) -> HistoryPage {
+		let limit = query.limit.clamp(1, MAX_PAGE_SIZE);
+		let mut messages: Vec<_> = self
+			.topics
+			.iter()
+			.filter(|(topic, _)| query.topics.is_empty() || query.topics.contains(topic))
+			.flat_map(|(_, messages)| messages)
+			.filter(|message| query.after.is_none_or(|after| message.cursor > after))
+			.collect();
+		messages.sort_unstable_by_key(|message| message.cursor);
+
+		let next_cursor = (messages.len() > limit).then(|| messages[limit - 1].cursor);
+		HistoryPage { messages: messages.into_iter().take(limit).cloned().collect(), next_cursor }
+	}
+}
+
+/// The records of the store, skipping the ones a crash left truncated.
+fn load(path: &Path) -> Result<Vec<GossipRecord>, Box<dyn Error>> {
+	let mut records = Vec::new();
+	for line in BufReader::new(File::open(path)?).lines() {
+		match serde_json::from_str(&line?) {
+			Ok(record) => records.push(record),
+			Err(e) => tracing::warn!("Skipping an invalid gossip history record: {e}"),
+		}
+	}
+	Ok(records)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	fn cursors(page: &HistoryPage) -> Vec<u64> {
+		page.messages.iter().map(|message| message.cursor).collect()
+	}
+
+	#[test]
+	fn test_history_pages_through_the_kept_messages() {
+		let mut history = MessageHistory::new(HistoryConfig { messages_per_topic: 2 });
+		for (topic, data) in [("tasks", "a"), ("agents", "b"), ("tasks", "c"), ("tasks", "d")] {
+			history.record(topic.to_string(), None, data.as_bytes().to_vec());
+		}
+
+		// The oldest task was dropped.
+		let first = history.page(&HistoryQuery { limit: 2, ..Default::default() });
+		assert_eq!(cursors(&first), [1, 2]);
+		assert_eq!(first.next_cursor, Some(2));
+
+		let second = history.page(&HistoryQuery {
+			after: first.next_cursor,
+			limit: 2,
+			..Default::default()
+		});
+		assert_eq!(cursors(&second), [3]);
+		assert_eq!(second.next_cursor, None);
+
+		let tasks = history.page(&HistoryQuery {
+			topics: vec!["tasks".to_string()],
+			limit: 10,
+			after: None,
+		});
+		assert_eq!(cursors(&tasks), [2, 3]);
+	}
+
+	#[test]
+	fn test_history_keeps_its_messages_in_the_store() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = dir.path().join("history.jsonl");
+		let config = HistoryConfig { messages_per_topic: 2 };
+		let source = PeerId::random();
+		let mut history = MessageHistory::open(config, Some(store.clone()));
+		for data in ["a", "b", "c", "d", "e"] {
+			history.record("tasks".to_string(), Some(source), data.as_bytes().to_vec());
+		}
+		let page = history.page(&HistoryQuery { limit: 10, ..Default::default() });
+		drop(history);
+
+		// The dropped messages were compacted away.
+		assert!(std::fs::read_to_string(&store)?.lines().count() <= 4);
+		let mut restarted = MessageHistory::open(config, Some(store.clone()));
+		assert_eq!(restarted.page(&HistoryQuery { limit: 10, ..Default::default() }), page);
+		assert_eq!(cursors(&page), [3, 4]);
+		assert_eq!(page.messages[0].source, Some(source));
+
+		// The cursors keep increasing after a restart.
+		restarted.record("tasks".to_string(), None, b"f".to_vec());
+		let page =
+			restarted.page(&HistoryQuery { after: Some(4), limit: 10, ..Default::default() });
+		assert_eq!(cursors(&page), [5]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_history_skips_a_truncated_record() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = dir.path().join("history.jsonl");
+		let mut history = MessageHistory::open(HistoryConfig::default(), Some(store.clone()));
+		history.record("tasks".to_string(), None, b"a".to_vec());
+		drop(history);
+		let mut file = OpenOptions::new().append(true).open(&store)?;
+		file.write_all(br#"{"cursor": 1, "top"#)?;
+
+		let history = MessageHistory::open(HistoryConfig::default(), Some(store.clone()));
+		let page = history.page(&HistoryQuery { limit: 10, ..Default::default() });
+		assert_eq!(cursors(&page), [0]);
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
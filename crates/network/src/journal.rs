@@ -0,0 +1,175 @@
+//! Journal of the agent requests sent by a requester and not answered yet, written to a file on
+//! every change. A requester that crashed or was stopped while waiting on an answer sends the
+//! pending requests again to the providers they were sent to on restart, see
+//! [`crate::Client::resume_request`].
+
+use std::{io, path::PathBuf};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use thiserror::Error;
+
+use crate::{cid::Cid, persist::atomic_write, stream::now_ms, types::ContextMessage};
+
+#[derive(Error, Debug)]
+pub enum JournalError {
+	#[error("Failed to access the request journal: {0}")]
+	Io(#[from] io::Error),
+	#[error("Malformed request journal: {0}")]
+	Json(#[from] serde_json::Error),
+}
+
+/// An agent request waiting for its answer.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+	/// Content id of the request and the time it was submitted.
+	pub id: Cid,
+	/// Providers the request may be sent to, in the order they are tried.
+	#[serde_as(as = "Vec<DisplayFromStr>")]
+	pub providers: Vec<PeerId>,
+	pub agent_name: String,
+	pub message: String,
+	#[serde(default)]
+	pub context: Vec<ContextMessage>,
+	pub language: Option<String>,
+	/// Artifacts attached to the message, served by the requester from its store.
+	#[serde(default)]
+	pub attachments: Vec<Cid>,
+	/// Unix timestamp in milliseconds.
+	pub submitted_at: u64,
+	/// Times the request was sent, the first one included.
+	pub attempts: u32,
+}
+
+/// The pending requests of a requester, kept in a JSON file.
+#[derive(Debug)]
+pub struct RequestJournal {
+	path: PathBuf,
+	entries: Vec<JournalEntry>,
+}
+
+impl RequestJournal {
+	/// Open the journal at `path`, empty when the file does not exist yet.
+	pub fn open(path: impl Into<PathBuf>) -> Result<Self, JournalError> {
+		let path = path.into();
+		let entries = match std::fs::read_to_string(&path) {
+			Ok(data) => serde_json::from_str(&data)?,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+			Err(e) => return Err(e.into()),
+		};
+		Ok(Self { path, entries })
+	}
+
+	/// The pending requests, oldest first.
+	pub fn pending(&self) -> &[JournalEntry] {
+		&self.entries
+	}
+
+	/// Record a request about to be sent to `providers`, returning its id.
+	pub fn record(
+		&mut self,
+		providers: Vec<PeerId>,
+		agent_name: String,
+		message: String,
+		context: Vec<ContextMessage>,
+		language: Option<String>,
+		attachments: Vec<Cid>,
+	) -> Result<Cid, JournalError> {
+		let submitted_at = now_ms();
+		let id = Cid::of(serde_json::to_vec(&(
+			&agent_name,
+			&message,
+			&context,
+			&attachments,
+			submitted_at,
+		))?);
+		self.entries.push(JournalEntry {
+			id,
+			providers,
+			agent_name,
+			message,
+			context,
+			language,
+			attachments,
+			submitted_at,
+			attempts: 1,
+		});
+		self.save()?;
+		Ok(id)
+	}
+
+	/// Count another attempt at the request `id`, returning it. None when it is not pending.
+	pub fn retry(&mut self, id: &Cid) -> Result<Option<JournalEntry>, JournalError> {
+		let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == *id) else {
+			return Ok(None);
+		};
+		entry.attempts += 1;
+		let entry = entry.clone();
+		self.save()?;
+		Ok(Some(entry))
+	}
+
+	/// Drop the request `id` once answered or given up on. Returns whether it was pending.
+	pub fn complete(&mut self, id: &Cid) -> Result<bool, JournalError> {
+		let count = self.entries.len();
+		self.entries.retain(|entry| entry.id != *id);
+		if self.entries.len() == count {
+			return Ok(false);
+		}
+		self.save()?;
+		Ok(true)
+	}
+
+	/// Replace the journal with the entries at once, for a crash not to leave a truncated journal
+	/// behind.
+	fn save(&self) -> Result<(), JournalError> {
+		atomic_write(&self.path, &serde_json::to_vec_pretty(&self.entries)?)?;
+		Ok(())
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_pending_requests_survive_a_restart() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let path = dir.path().join("journal.json");
+		let provider = PeerId::random();
+		let mut journal = RequestJournal::open(&path)?;
+		assert!(journal.pending().is_empty());
+		let attachment = Cid::of(b"attachment");
+		let answered =
+			journal.record(vec![provider], "echo".into(), "hi".into(), vec![], None, vec![])?;
+		let pending = journal.record(
+			vec![provider],
+			"echo".into(),
+			"bye".into(),
+			vec![],
+			None,
+			vec![attachment],
+		)?;
+		assert!(journal.complete(&answered)?);
+
+		let mut restarted = RequestJournal::open(&path)?;
+		let entry = restarted.retry(&pending)?.expect("A pending request.");
+		assert_eq!(
+			(entry.message.as_str(), entry.providers, entry.attachments, entry.attempts),
+			("bye", vec![provider], vec![attachment], 2)
+		);
+		assert!(restarted.complete(&pending)?);
+		assert!(!restarted.complete(&pending)?);
+		assert!(RequestJournal::open(&path)?.pending().is_empty());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
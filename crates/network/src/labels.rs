@@ -0,0 +1,171 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	error::Error,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::persist::atomic_write;
+
+/// Labels the application attached to a peer, such as `gpu=true` or `region=eu`.
+pub type Labels = BTreeMap<String, String>;
+
+/// Matches the peers carrying all of its labels, any peer when empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelSelector(Labels);
+
+impl LabelSelector {
+	pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+		self.0.insert(key.into(), value.into());
+		self
+	}
+
+	pub fn matches(&self, labels: &Labels) -> bool {
+		self.0.iter().all(|(key, value)| labels.get(key) == Some(value))
+	}
+}
+
+/// Parses comma-separated `key=value` pairs, e.g. `gpu=true,region=eu`.
+impl FromStr for LabelSelector {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		s.split(',').filter(|pair| !pair.trim().is_empty()).try_fold(
+			LabelSelector::default(),
+			|selector, pair| match pair.split_once('=') {
+				Some((key, value)) if !key.trim().is_empty() => {
+					Ok(selector.with(key.trim(), value.trim()))
+				},
+				_ => Err(format!("Invalid label {pair}, expected key=value")),
+			},
+		)
+	}
+}
+
+/// The labels of the peers, kept when they disconnect so a fleet keeps its grouping across
+/// reconnects, and in a store across restarts when given one.
+#[derive(Debug, Default)]
+pub struct AddressBook {
+	labels: HashMap<PeerId, Labels>,
+	store: Option<PathBuf>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct StoredLabels(#[serde_as(as = "HashMap<DisplayFromStr, _>")] HashMap<PeerId, Labels>);
+
+impl AddressBook {
+	/// An address book keeping its labels in `store`, starting from the ones already there.
+	/// Labels only live in memory when unset.
+	pub fn open(store: Option<PathBuf>) -> Self {
+		let labels = match store.as_deref().filter(|path| path.exists()).map(load) {
+			Some(Ok(labels)) => labels,
+			Some(Err(e)) => {
+				tracing::error!("Failed to load the address book: {e}");
+				HashMap::new()
+			},
+			None => HashMap::new(),
+		};
+		Self { labels, store }
+	}
+
+	pub fn tag(&mut self, peer: PeerId, key: String, value: String) {
+		self.labels.entry(peer).or_default().insert(key, value);
+		self.persist();
+	}
+
+	pub fn untag(&mut self, peer: &PeerId, key: &str) {
+		if let Some(labels) = self.labels.get_mut(peer) {
+			labels.remove(key);
+			if labels.is_empty() {
+				self.labels.remove(peer);
+			}
+			self.persist();
+		}
+	}
+
+	pub fn labels(&self, peer: &PeerId) -> Labels {
+		self.labels.get(peer).cloned().unwrap_or_default()
+	}
+
+	/// The labelled peers matching `selector`.
+	pub fn find(&self, selector: &LabelSelector) -> Vec<(PeerId, Labels)> {
+		self.labels
+			.iter()
+			.filter(|(_, labels)| selector.matches(labels))
+			.map(|(peer, labels)| (*peer, labels.clone()))
+			.collect()
+	}
+
+	fn persist(&self) {
+		let Some(store) = &self.store else {
+			return;
+		};
+		if let Err(e) = save(store, &self.labels) {
+			tracing::error!("Failed to write the address book: {e}");
+		}
+	}
+}
+
+fn load(store: &Path) -> Result<HashMap<PeerId, Labels>, Box<dyn Error>> {
+	let StoredLabels(labels) = serde_json::from_str(&std::fs::read_to_string(store)?)?;
+	Ok(labels)
+}
+
+/// Replace the store with the labels at once, for a crash not to leave a truncated store behind.
+fn save(store: &Path, labels: &HashMap<PeerId, Labels>) -> Result<(), Box<dyn Error>> {
+	atomic_write(store, &serde_json::to_vec_pretty(&StoredLabels(labels.clone()))?)?;
+	Ok(())
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_label_selector() -> Result<()> {
+		let peer = PeerId::random();
+		let mut book = AddressBook::default();
+		book.tag(peer, "gpu".to_string(), "true".to_string());
+		book.tag(peer, "region".to_string(), "eu".to_string());
+
+		let selector: LabelSelector = "gpu=true, region=eu".parse()?;
+		assert!(selector.matches(&book.labels(&peer)));
+		assert!(LabelSelector::default().matches(&book.labels(&PeerId::random())));
+		assert!(!selector.matches(&book.labels(&PeerId::random())));
+
+		book.untag(&peer, "region");
+		assert!(!selector.matches(&book.labels(&peer)));
+		assert!("gpu".parse::<LabelSelector>().is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_address_book_keeps_its_labels_in_the_store() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = dir.path().join("labels.json");
+		let (gpu, cpu) = (PeerId::random(), PeerId::random());
+		let mut book = AddressBook::open(Some(store.clone()));
+		book.tag(gpu, "gpu".to_string(), "true".to_string());
+		book.tag(cpu, "gpu".to_string(), "false".to_string());
+		book.untag(&cpu, "gpu");
+
+		let reopened = AddressBook::open(Some(store.clone()));
+		assert_eq!(reopened.labels(&gpu), book.labels(&gpu));
+		assert!(reopened.labels(&cpu).is_empty());
+		let found = reopened.find(&"gpu=true".parse()?);
+		assert_eq!(found, vec![(gpu, book.labels(&gpu))]);
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,79 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// How many of the last pings of a peer its latency is computed from.
+const WINDOW: usize = 64;
+
+/// Round-trip times of the last pings of a peer, see [`crate::Client::peer_latency`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerLatency {
+	/// Pings the figures are computed from, at most the last 64.
+	pub samples: usize,
+	pub last: Duration,
+	pub min: Duration,
+	pub mean: Duration,
+	pub median: Duration,
+	pub p90: Duration,
+	pub max: Duration,
+}
+
+/// Rolling window of the round-trip times of the pings of a peer.
+#[derive(Debug, Default)]
+pub(crate) struct RttHistogram {
+	rtts: VecDeque<Duration>,
+}
+
+impl RttHistogram {
+	pub(crate) fn record(&mut self, rtt: Duration) {
+		if self.rtts.len() == WINDOW {
+			self.rtts.pop_front();
+		}
+		self.rtts.push_back(rtt);
+	}
+
+	/// The latency of the peer, once it answered a ping.
+	pub(crate) fn latency(&self) -> Option<PeerLatency> {
+		let last = *self.rtts.back()?;
+		let mut sorted: Vec<_> = self.rtts.iter().copied().collect();
+		sorted.sort_unstable();
+		let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+
+		Some(PeerLatency {
+			samples: sorted.len(),
+			last,
+			min: sorted[0],
+			mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+			median: percentile(50),
+			p90: percentile(90),
+			max: sorted[sorted.len() - 1],
+		})
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_latency_of_the_last_pings() {
+		let mut histogram = RttHistogram::default();
+		assert_eq!(histogram.latency(), None);
+
+		// The first ten pings fall out of the window.
+		for ms in (1..=10).chain((1..=WINDOW as u64).rev()) {
+			histogram.record(Duration::from_millis(ms * 10));
+		}
+
+		let latency = histogram.latency().expect("Pings to be recorded.");
+		assert_eq!(latency.samples, WINDOW);
+		assert_eq!(latency.last, Duration::from_millis(10));
+		assert_eq!(latency.min, Duration::from_millis(10));
+		assert_eq!(latency.max, Duration::from_millis(640));
+		assert_eq!(latency.mean, Duration::from_millis(325));
+		assert_eq!(latency.median, Duration::from_millis(320));
+		assert_eq!(latency.p90, Duration::from_millis(570));
+	}
+}
+
+// endregion: --- Tests
@@ -1,61 +1,268 @@
+pub mod agent_card;
+pub mod alerts;
+pub mod artifacts;
+pub mod auction;
 pub mod behaviour;
+pub mod bidding;
+pub mod bulletin;
+pub mod capabilities;
+pub mod challenge;
+pub mod cid;
 pub mod client;
+pub mod commitment;
+pub mod compression;
+pub mod config;
+pub mod directory;
+pub mod discovery;
+pub mod encryption;
+pub mod escrow;
 pub mod eventloop;
+pub mod events;
+pub mod feedback;
+pub mod hardware;
+mod hedge;
+pub mod history;
+pub mod journal;
+pub mod labels;
+pub mod latency;
+pub mod metrics;
+pub mod moderation;
+pub mod persist;
+pub mod pii;
+pub mod policy;
+pub mod presence;
+pub mod protocol;
+pub mod provenance;
+pub mod query;
+pub mod queue;
+pub mod reconnect;
+pub mod reputation;
+pub mod request;
+pub mod resume;
+pub mod selection;
+pub mod signed;
+pub mod snapshot;
+pub mod stream;
+pub mod tasks;
+#[cfg(test)]
+mod testing;
+pub mod timeouts;
+mod transport;
 pub mod types;
+pub mod validation;
+mod wire;
 
-use std::{error::Error, time::Duration};
+use std::{
+	error::Error,
+	sync::{Arc, Mutex},
+};
 
+use alerts::AlertEngine;
+use bulletin::BulletinBoard;
+use encryption::EncryptionKey;
+use events::EventReceiver;
 use futures::{channel::mpsc, prelude::*};
-use libp2p::{identity, noise, tcp, tls, yamux};
+use libp2p::{metrics::Registry, Swarm};
 
+pub use crate::agent_card::AgentCard;
+pub use crate::alerts::{Alert, AlertAction, AlertCondition, AlertMetric, AlertRule};
+pub use crate::artifacts::{ArtifactDemand, ArtifactManifest, ArtifactStore};
+pub use crate::auction::{AuctionConfig, AuctionPolicy, AuctionState, AuctionWinner};
 pub use crate::behaviour::AsnBehaviour;
+pub use crate::bidding::{
+	BidInput, BidStrategy, Bidder, CostPlus, PriceSchedule, ScheduleSource, StrategyConfig, Surge,
+};
+pub use crate::bulletin::{Bulletin, BulletinConfig, BulletinKind};
+pub use crate::capabilities::NodeCapabilities;
+pub use crate::cid::Cid;
 pub use crate::client::Client;
+pub use crate::commitment::{Agreement, Redundancy, ResultCommitment};
+pub use crate::compression::{Compression, CompressionConfig};
+pub use crate::config::{AgentTopicsConfig, ChannelConfig, KademliaConfig, NetworkConfig};
+pub use crate::directory::{
+	AgentFilter, AgentListing, AgentQuery, CapabilityAnnouncement, DirectoryConfig,
+};
+pub use crate::discovery::MdnsConfig;
+pub use crate::escrow::{EscrowSettlement, TaskEscrow};
 pub use crate::eventloop::EventLoop;
-pub use crate::types::Event;
+pub use crate::feedback::{AgentQuality, Feedback, Rating};
+pub use crate::hardware::{HardwareProfile, HardwareRequirements};
+pub use crate::history::{GossipRecord, HistoryConfig, HistoryPage, HistoryQuery};
+pub use crate::journal::{JournalEntry, RequestJournal};
+pub use crate::labels::{AddressBook, LabelSelector, Labels};
+pub use crate::latency::PeerLatency;
+pub use crate::metrics::NetworkMetrics;
+pub use crate::moderation::{GossipValidators, TopicModeration};
+pub use crate::policy::{RequestAttributes, RequestPolicy};
+pub use crate::presence::PresenceConfig;
+pub use crate::protocol::ProtocolVersion;
+pub use crate::provenance::Provenance;
+pub use crate::query::{QueryHandle, QueryProgress};
+pub use crate::queue::{QueuedTask, TaskQueue};
+pub use crate::reputation::{Attestation, CooldownConfig, Cooldowns, Outcome, ReputationLedger};
+pub use crate::request::RequestHandle;
+pub use crate::resume::ResumeConfig;
+pub use crate::snapshot::{NetworkSnapshot, SnapshotAgent};
+pub use crate::stream::AgentStream;
+pub use crate::tasks::{TaskRecord, TaskStatus};
+pub use crate::timeouts::RequestTimeoutConfig;
+pub use crate::types::{DiscoverySource, Event};
+pub use crate::validation::{OnInvalid, ResponseValidator, ResponseValidators};
 
+pub use libp2p::autonat::NatStatus;
+pub use libp2p::gossipsub::{Message as GossipMessage, MessageAcceptance};
+pub use libp2p::identity;
+pub use libp2p::kad::Quorum;
 pub use libp2p::multiaddr::Protocol;
+#[cfg(feature = "pnet")]
+pub use libp2p::pnet::PreSharedKey;
 pub use libp2p::Multiaddr;
+pub use libp2p::PeerId;
 
 pub async fn new(
-	secret_key_seed: Option<u8>,
-	additional_topics: Vec<String>,
+	config: NetworkConfig,
 ) -> Result<(Client, impl Stream<Item = Event>, libp2p::PeerId, EventLoop), Box<dyn Error>> {
-	// Create a public/private key pair, either random or based on a seed.
-	let id_key = match secret_key_seed {
+	let id_key = identity_key(&config);
+	let mut registry = Registry::default();
+	let metrics = NetworkMetrics::new(&mut registry);
+	let swarm = transport::build_swarm(id_key.clone(), &config, &metrics, &mut registry).await?;
+
+	assemble(config, id_key, swarm, registry, metrics)
+}
+
+/// Like [`new`], over the in-memory transport so that the nodes of a test run in one process.
+#[cfg(test)]
+pub(crate) fn new_in_memory(
+	config: NetworkConfig,
+) -> Result<(Client, EventReceiver, libp2p::PeerId, EventLoop), Box<dyn Error>> {
+	let id_key = identity_key(&config);
+	let mut registry = Registry::default();
+	let metrics = NetworkMetrics::new(&mut registry);
+	let swarm = transport::build_memory_swarm(id_key.clone(), &config, &metrics)?;
+
+	assemble(config, id_key, swarm, registry, metrics)
+}
+
+/// Create a public/private key pair, either the configured one, random or based on a seed.
+fn identity_key(config: &NetworkConfig) -> identity::Keypair {
+	if let Some(key) = &config.identity {
+		return key.clone();
+	}
+	match config.secret_key_seed {
 		Some(seed) => {
 			let mut bytes = [0u8; 32];
 			bytes[0] = seed;
 			identity::Keypair::ed25519_from_bytes(bytes).unwrap()
 		},
 		None => identity::Keypair::generate_ed25519(),
-	};
-	let peer_id = id_key.public().to_peer_id();
-
-	let (command_sender, command_receiver) = mpsc::channel(0);
-	let (event_sender, event_receiver) = mpsc::channel(0);
+	}
+}
 
-	let mut swarm = libp2p::SwarmBuilder::with_existing_identity(id_key)
-		.with_tokio()
-		.with_tcp(tcp::Config::default().nodelay(true), noise::Config::new, yamux::Config::default)?
-		.with_quic()
-		.with_dns()?
-		.with_websocket((tls::Config::new, noise::Config::new), yamux::Config::default)
-		.await?
-		.with_behaviour(AsnBehaviour::new)?
-		.with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
-		.build();
+/// Set up the swarm and the channels between the client and the event loop.
+fn assemble(
+	config: NetworkConfig,
+	id_key: identity::Keypair,
+	mut swarm: Swarm<AsnBehaviour>,
+	registry: Registry,
+	metrics: NetworkMetrics,
+) -> Result<(Client, EventReceiver, libp2p::PeerId, EventLoop), Box<dyn Error>> {
+	let peer_id = id_key.public().to_peer_id();
+	let (command_sender, command_receiver) = mpsc::channel(config.channels.command_capacity);
+	let (mut event_sender, event_receiver) =
+		events::channel(config.channels.event_capacity, metrics.dropped_events());
+	let alerts = Arc::new(AlertEngine::new(config.alert_rules));
+	if !alerts.is_empty() {
+		event_sender.add_observer(alerts.clone());
+	}
+	if let Some(escrow) = config.task_escrow {
+		let settlement = EscrowSettlement::new(escrow, peer_id, config.escrow_store);
+		event_sender.add_observer(Arc::new(settlement.spawn()));
+	}
 
+	swarm.behaviour_mut().set_connection_limits(&config.connection_limits);
 	swarm.behaviour_mut().bootstrap();
 
-	for topic in additional_topics {
-		tracing::info!("Subscribed to topic: {topic}");
+	let moderation =
+		TopicModeration::new(&config.topic_publishers).with_validators(config.gossip_validators);
+	if !config.topic_publishers.is_empty() {
+		swarm.behaviour_mut().enable_topic_moderation(&moderation);
+	}
+
+	// Moderated topics are followed too, their allowlist is only enforced on received messages.
+	for topic in config.additional_topics.iter().chain(config.topic_publishers.keys()) {
 		swarm.behaviour_mut().subscribe(topic.as_str());
 	}
 
+	if config.rendezvous_server {
+		swarm.behaviour_mut().enable_rendezvous_server();
+	}
+
+	let rendezvous_point = match &config.rendezvous_point {
+		Some(address) => match address.iter().last() {
+			Some(Protocol::P2p(peer_id)) => Some(peer_id),
+			_ => return Err("Expect rendezvous point multiaddr to contain peer ID.".into()),
+		},
+		None => None,
+	};
+
+	let encryption_key = config
+		.end_to_end_encryption
+		.then(|| EncryptionKey::from_identity(&id_key))
+		.flatten()
+		.map(|key| key.public());
+
+	if config.presence.enabled {
+		swarm.behaviour_mut().subscribe(presence::PRESENCE_TOPIC);
+	}
+
+	let bulletins = BulletinBoard::new(config.bulletins);
+	if bulletins.is_enabled() {
+		swarm.behaviour_mut().subscribe(bulletin::CONTROL_TOPIC);
+	}
+
 	Ok((
-		Client { sender: command_sender },
+		Client {
+			sender: command_sender,
+			command_timeout: config.channels.command_timeout,
+			max_artifact_size: config
+				.max_artifact_size
+				.unwrap_or(artifacts::DEFAULT_MAX_ARTIFACT_SIZE),
+			rejected_commands: metrics.rejected_commands(),
+			metrics_registry: Arc::new(Mutex::new(registry)),
+		},
 		event_receiver,
 		peer_id,
-		EventLoop::new(swarm, command_receiver, event_sender, None, None, None, None),
+		EventLoop::new(
+			swarm,
+			id_key,
+			command_receiver,
+			event_sender,
+			metrics,
+			moderation,
+			bulletins,
+			None,
+			rendezvous_point,
+			config.rendezvous_point,
+			config.external_address,
+			config.reconnect,
+			config.resume,
+			config.history,
+			config.history_store,
+			&config.mdns,
+			config.bootstrap_peers,
+			alerts,
+			encryption_key,
+			config.agent_topics,
+			config.max_inbound_requests,
+			config.request_policies,
+			config.presence,
+			config.request_timeouts,
+			config.task_store,
+			config.circuit_breaker,
+			config.circuit_store,
+			config.address_book,
+			config.directory,
+			config.snapshot,
+		),
 	))
 }
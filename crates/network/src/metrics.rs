@@ -0,0 +1,252 @@
+use libp2p::{
+	metrics::{Metrics, Recorder, Registry},
+	request_response,
+	swarm::SwarmEvent,
+};
+use prometheus_client::{
+	encoding::EncodeLabelSet,
+	metrics::{counter::Counter, family::Family, gauge::Gauge},
+};
+
+use crate::{alerts::AlertMetric, behaviour::AsnBehaviourEvent, compression::CompressionSaved};
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TopicLabels {
+	topic: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct FailureLabels {
+	protocol: String,
+	direction: String,
+	error: String,
+}
+
+/// Prometheus metrics of the swarm: the libp2p protocol metrics plus the ones specific to the
+/// agent network.
+pub struct NetworkMetrics {
+	libp2p: Metrics,
+	connected_peers: Gauge,
+	gossipsub_mesh_peers: Family<TopicLabels, Gauge>,
+	request_response_failures: Family<FailureLabels, Counter>,
+	dropped_events: Counter,
+	rejected_commands: Counter,
+	compression_saved: CompressionSaved,
+}
+
+impl NetworkMetrics {
+	pub fn new(registry: &mut Registry) -> Self {
+		let libp2p = Metrics::new(registry);
+		let registry = registry.sub_registry_with_prefix("asn");
+
+		let connected_peers = Gauge::default();
+		registry.register("connected_peers", "Number of connected peers", connected_peers.clone());
+
+		let gossipsub_mesh_peers = Family::default();
+		registry.register(
+			"gossipsub_mesh_peers",
+			"Number of peers in the gossipsub mesh of each subscribed topic",
+			gossipsub_mesh_peers.clone(),
+		);
+
+		let request_response_failures = Family::default();
+		registry.register(
+			"request_response_failures",
+			"Number of failed requests, by protocol, direction and error",
+			request_response_failures.clone(),
+		);
+
+		let dropped_events = Counter::default();
+		registry.register(
+			"dropped_events",
+			"Number of events dropped because the application did not read them in time",
+			dropped_events.clone(),
+		);
+
+		let rejected_commands = Counter::default();
+		registry.register(
+			"rejected_commands",
+			"Number of commands rejected because the event loop did not accept them in time",
+			rejected_commands.clone(),
+		);
+
+		let compression_saved = CompressionSaved::default();
+		registry.register(
+			"compression_saved_bytes",
+			"Number of bytes saved by compressing the request-response payloads, by algorithm",
+			compression_saved.clone(),
+		);
+
+		Self {
+			libp2p,
+			connected_peers,
+			gossipsub_mesh_peers,
+			request_response_failures,
+			dropped_events,
+			rejected_commands,
+			compression_saved,
+		}
+	}
+
+	/// Record a swarm event, forwarding protocol events to their libp2p recorders.
+	pub fn record(&self, event: &SwarmEvent<AsnBehaviourEvent>) {
+		self.libp2p.record(event);
+
+		match event {
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Kademlia(event)) => self.libp2p.record(event),
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Gossipsub(event)) => self.libp2p.record(event),
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Identify(event)) => self.libp2p.record(event),
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Ping(event)) => self.libp2p.record(event),
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Relay(event)) => self.libp2p.record(event),
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Control(event)) => {
+				self.record_request_event("control", event)
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::Data(event)) => {
+				self.record_request_event("data", event)
+			},
+			SwarmEvent::Behaviour(AsnBehaviourEvent::LlmStream(event)) => {
+				self.record_request_event("stream", event)
+			},
+			_ => {},
+		}
+	}
+
+	pub(crate) fn dropped_events(&self) -> Counter {
+		self.dropped_events.clone()
+	}
+
+	pub(crate) fn rejected_commands(&self) -> Counter {
+		self.rejected_commands.clone()
+	}
+
+	pub(crate) fn compression_saved(&self) -> CompressionSaved {
+		self.compression_saved.clone()
+	}
+
+	/// Current value of a metric watched by the alert rules.
+	pub(crate) fn alert_metric(&self, metric: AlertMetric) -> i64 {
+		match metric {
+			AlertMetric::ConnectedPeers => self.connected_peers.get(),
+			AlertMetric::DroppedEvents => self.dropped_events.get() as i64,
+			AlertMetric::RejectedCommands => self.rejected_commands.get() as i64,
+		}
+	}
+
+	pub fn set_connected_peers(&self, count: usize) {
+		self.connected_peers.set(count as i64);
+	}
+
+	pub fn set_gossipsub_mesh_peers(&self, topic: &str, count: usize) {
+		self.gossipsub_mesh_peers
+			.get_or_create(&TopicLabels { topic: topic.to_string() })
+			.set(count as i64);
+	}
+
+	fn record_request_event<Req, Resp>(
+		&self,
+		protocol: &str,
+		event: &request_response::Event<Req, Resp>,
+	) {
+		let (direction, error) = match event {
+			request_response::Event::InboundFailure { error, .. } => ("inbound", error.to_string()),
+			request_response::Event::OutboundFailure { error, .. } => {
+				("outbound", error.to_string())
+			},
+			_ => return,
+		};
+		self.request_response_failures
+			.get_or_create(&FailureLabels {
+				protocol: protocol.to_string(),
+				direction: direction.to_string(),
+				error,
+			})
+			.inc();
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use std::time::Duration;
+
+	use libp2p::{core::transport::ListenerId, Multiaddr};
+
+	use super::*;
+	use crate::{testing::TestNetwork, types::Event};
+
+	fn encode(registry: &Registry) -> Result<String> {
+		let mut encoded = String::new();
+		prometheus_client::encoding::text::encode(&mut encoded, registry)?;
+		Ok(encoded)
+	}
+
+	#[test]
+	fn test_metrics_are_encoded() -> Result<()> {
+		let mut registry = Registry::default();
+		let metrics = NetworkMetrics::new(&mut registry);
+
+		metrics.set_connected_peers(3);
+		metrics.set_gossipsub_mesh_peers("agents", 2);
+		metrics.dropped_events().inc();
+		metrics.rejected_commands().inc_by(2);
+		metrics.record(&SwarmEvent::NewListenAddr {
+			listener_id: ListenerId::next(),
+			address: "/ip4/127.0.0.1/tcp/4001".parse::<Multiaddr>()?,
+		});
+
+		let encoded = encode(&registry)?;
+		for line in [
+			"asn_connected_peers 3",
+			"asn_gossipsub_mesh_peers{topic=\"agents\"} 2",
+			"asn_dropped_events_total 1",
+			"asn_rejected_commands_total 2",
+			"libp2p_swarm_new_listen_addr_total{protocols=\"/ip4/tcp\"} 1",
+		] {
+			assert!(encoded.lines().any(|encoded| encoded == line), "{line} in {encoded}");
+		}
+		assert!(encoded.ends_with("# EOF\n"));
+		assert_eq!(metrics.alert_metric(AlertMetric::ConnectedPeers), 3);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_request_failures_are_counted() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let provider_id = network.nodes[1].peer_id;
+		let [requester, provider] = &mut network.nodes[..] else {
+			return Err("Expected two nodes".into());
+		};
+
+		let mut client = requester.client.clone();
+		let request = tokio::spawn(async move {
+			client.request_agent(provider_id, "echo".to_string(), "hello".to_string()).await
+		});
+		// Dropping the channel leaves the request unanswered.
+		provider
+			.event(|event| match event {
+				Event::LLMInboundRequest { channel, .. } => {
+					drop(channel);
+					Some(())
+				},
+				_ => None,
+			})
+			.await?;
+		assert!(request.await?.is_err());
+
+		let failure =
+			"asn_request_response_failures_total{protocol=\"control\",direction=\"inbound\"";
+		for _ in 0..50 {
+			if provider.client.encode_metrics()?.contains(failure) {
+				return Ok(());
+			}
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+		Err(format!("No {failure} in {}", provider.client.encode_metrics()?).into())
+	}
+}
+
+// endregion: --- Tests
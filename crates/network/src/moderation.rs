@@ -0,0 +1,188 @@
+use std::{
+	collections::{HashMap, HashSet},
+	fmt,
+	sync::Arc,
+};
+
+use libp2p::{
+	gossipsub::{self, IdentTopic, MessageAcceptance, TopicHash},
+	PeerId,
+};
+
+/// Publisher allowlists of the moderated gossip topics, e.g. an official capability registry only
+/// the registry maintainers may publish to. Topics without an allowlist accept any publisher.
+#[derive(Debug, Clone, Default)]
+pub struct TopicModeration {
+	publishers: HashMap<TopicHash, HashSet<PeerId>>,
+	validators: GossipValidators,
+}
+
+impl TopicModeration {
+	pub fn new(publishers: &HashMap<String, HashSet<PeerId>>) -> Self {
+		Self {
+			publishers: publishers
+				.iter()
+				.map(|(topic, peers)| (IdentTopic::new(topic).hash(), peers.clone()))
+				.collect(),
+			validators: GossipValidators::default(),
+		}
+	}
+
+	pub fn with_validators(mut self, validators: GossipValidators) -> Self {
+		self.validators = validators;
+		self
+	}
+
+	pub fn topics(&self) -> impl Iterator<Item = &TopicHash> {
+		self.publishers.keys()
+	}
+
+	pub fn is_moderated(&self, topic: &TopicHash) -> bool {
+		self.publishers.contains_key(topic)
+	}
+
+	/// Whether `publisher` may publish in `topic`. Anonymous messages are never authorized on a
+	/// moderated topic.
+	pub fn is_authorized(&self, topic: &TopicHash, publisher: Option<&PeerId>) -> bool {
+		match self.publishers.get(topic) {
+			Some(allowed) => publisher.is_some_and(|p| allowed.contains(p)),
+			None => true,
+		}
+	}
+
+	/// Rejected messages are not propagated and count as invalid deliveries in the peer score of
+	/// the peer that forwarded them.
+	pub fn validate(&self, message: &gossipsub::Message) -> MessageAcceptance {
+		if self.is_authorized(&message.topic, message.source.as_ref()) {
+			MessageAcceptance::Accept
+		} else {
+			MessageAcceptance::Reject
+		}
+	}
+
+	/// Run the validator of the message topic, if any, on a message from an authorized publisher.
+	pub fn validate_content(&self, message: &gossipsub::Message) -> MessageAcceptance {
+		self.validators.validate(message)
+	}
+}
+
+/// Decides whether a gossip message propagates: `Accept` forwards it, `Ignore` drops it and
+/// `Reject` drops it and penalizes the peer that forwarded it.
+pub type GossipValidator = Arc<dyn Fn(&gossipsub::Message) -> MessageAcceptance + Send + Sync>;
+
+/// Validators of the gossip topics, e.g. rejecting malformed capability announcements or oversized
+/// task proposals before they propagate. Topics without a validator accept any message.
+#[derive(Clone, Default)]
+pub struct GossipValidators {
+	validators: HashMap<TopicHash, GossipValidator>,
+}
+
+impl GossipValidators {
+	/// Validate the messages of `topic` with `validator`, replacing its previous validator.
+	pub fn with(
+		mut self,
+		topic: &str,
+		validator: impl Fn(&gossipsub::Message) -> MessageAcceptance + Send + Sync + 'static,
+	) -> Self {
+		self.validators.insert(IdentTopic::new(topic).hash(), Arc::new(validator));
+		self
+	}
+
+	pub fn validate(&self, message: &gossipsub::Message) -> MessageAcceptance {
+		match self.validators.get(&message.topic) {
+			Some(validator) => validator(message),
+			None => MessageAcceptance::Accept,
+		}
+	}
+}
+
+impl fmt::Debug for GossipValidators {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_set().entries(self.validators.keys()).finish()
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn message(topic: &str, source: Option<PeerId>) -> gossipsub::Message {
+		gossipsub::Message {
+			source,
+			data: b"registry update".to_vec(),
+			sequence_number: None,
+			topic: IdentTopic::new(topic).hash(),
+		}
+	}
+
+	#[test]
+	fn test_moderation_rejects_unauthorized_publishers() {
+		let maintainer = PeerId::random();
+		let moderation = TopicModeration::new(&HashMap::from([(
+			"registry".to_string(),
+			HashSet::from([maintainer]),
+		)]));
+
+		assert!(matches!(
+			moderation.validate(&message("registry", Some(maintainer))),
+			MessageAcceptance::Accept
+		));
+		assert!(matches!(
+			moderation.validate(&message("registry", Some(PeerId::random()))),
+			MessageAcceptance::Reject
+		));
+		assert!(matches!(
+			moderation.validate(&message("registry", None)),
+			MessageAcceptance::Reject
+		));
+	}
+
+	#[test]
+	fn test_moderation_accepts_any_publisher_on_open_topics() {
+		let moderation = TopicModeration::new(&HashMap::from([(
+			"registry".to_string(),
+			HashSet::from([PeerId::random()]),
+		)]));
+
+		assert!(matches!(
+			moderation.validate(&message("everyone", Some(PeerId::random()))),
+			MessageAcceptance::Accept
+		));
+		assert!(matches!(
+			moderation.validate(&message("everyone", None)),
+			MessageAcceptance::Accept
+		));
+	}
+
+	#[test]
+	fn test_validators_only_apply_to_their_topic() {
+		let validators = GossipValidators::default().with("proposals", |message| {
+			if message.data.len() > 8 {
+				MessageAcceptance::Reject
+			} else {
+				MessageAcceptance::Accept
+			}
+		});
+		let moderation = TopicModeration::default().with_validators(validators);
+
+		assert!(matches!(
+			moderation.validate_content(&message("proposals", None)),
+			MessageAcceptance::Reject
+		));
+		assert!(matches!(
+			moderation.validate_content(&gossipsub::Message {
+				data: b"small".to_vec(),
+				..message("proposals", None)
+			}),
+			MessageAcceptance::Accept
+		));
+		assert!(matches!(
+			moderation.validate_content(&message("everyone", None)),
+			MessageAcceptance::Accept
+		));
+	}
+}
+
+// endregion: --- Tests
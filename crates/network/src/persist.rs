@@ -0,0 +1,58 @@
+//! Writing of the files the stores of a node keep their state in, replaced at once so that a
+//! crash never leaves a truncated store behind.
+
+use std::{
+	fs::File,
+	io::{self, Write},
+	path::Path,
+};
+
+/// Write `data` next to `path` and flush it to disk before renaming it over `path`, then flush the
+/// directory holding it, for a crash to leave either the previous or the new file behind, never a
+/// truncated one or a lost rename.
+pub fn atomic_write(path: &Path, data: &[u8]) -> io::Result<()> {
+	let staged = path.with_extension("tmp");
+	let mut file = File::create(&staged)?;
+	file.write_all(data)?;
+	file.sync_all()?;
+	std::fs::rename(staged, path)?;
+	sync_parent(path)
+}
+
+/// Flush the directory entries of the parent directory of `path`, which records a rename.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+	let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty());
+	File::open(parent.unwrap_or(Path::new(".")))?.sync_all()
+}
+
+/// Directories cannot be opened to be flushed on other platforms.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+	Ok(())
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_atomic_write_replaces_the_file() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let path = dir.path().join("store.json");
+
+		atomic_write(&path, b"first")?;
+		atomic_write(&path, b"second")?;
+
+		assert_eq!(std::fs::read(&path)?, b"second");
+		assert!(!path.with_extension("tmp").exists());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,153 @@
+//! Scrubbing of the personal data found in text, the email addresses and phone numbers, before it
+//! is exported or stored.
+
+use std::ops::RangeInclusive;
+
+const REDACTED: &str = "[redacted]";
+
+/// Digits of a phone number written without a country code.
+const NATIONAL_DIGITS: RangeInclusive<usize> = 10..=15;
+/// Digits of a phone number following a `+` and its country code.
+const INTERNATIONAL_DIGITS: RangeInclusive<usize> = 7..=15;
+
+/// Replace the email addresses and phone numbers found in the text with `[redacted]`, leaving the
+/// punctuation around them.
+pub fn redact(text: &str) -> String {
+	redact_phone_numbers(&redact_emails(text))
+}
+
+fn redact_emails(text: &str) -> String {
+	text.split_inclusive(char::is_whitespace)
+		.map(|word| {
+			let token = word
+				.trim_end()
+				.trim_start_matches(|c: char| !c.is_alphanumeric())
+				.trim_end_matches(|c: char| c.is_ascii_punctuation());
+			if is_email(token) {
+				word.replacen(token, REDACTED, 1)
+			} else {
+				word.to_string()
+			}
+		})
+		.collect()
+}
+
+fn is_email(token: &str) -> bool {
+	match token.split_once('@') {
+		Some((user, domain)) => {
+			!user.is_empty() && domain.contains('.') && !domain.split('.').any(str::is_empty)
+		},
+		None => false,
+	}
+}
+
+fn redact_phone_numbers(text: &str) -> String {
+	let mut redacted = String::with_capacity(text.len());
+	let mut rest = text;
+	let mut previous = None;
+	while let Some(c) = rest.chars().next() {
+		let phone = match previous {
+			Some(previous) if char::is_alphanumeric(previous) => None,
+			_ => phone_number(rest),
+		};
+		let len = match phone {
+			Some(len) => {
+				redacted.push_str(REDACTED);
+				len
+			},
+			None => {
+				redacted.push(c);
+				c.len_utf8()
+			},
+		};
+		previous = rest[..len].chars().last();
+		rest = &rest[len..];
+	}
+	redacted
+}
+
+/// Length of the phone number the text starts with, e.g. `+44 20 7946 0958` or `(555) 010-4477`,
+/// its groups of digits separated by spaces, dashes, dots or parentheses.
+fn phone_number(text: &str) -> Option<usize> {
+	let international = text.starts_with('+');
+	let number = &text[usize::from(international)..];
+	if !number.starts_with(|c: char| c.is_ascii_digit() || c == '(') {
+		return None;
+	}
+
+	// The count of digits of each group and where it ends, up to the first character that is not
+	// part of a phone number.
+	let mut groups: Vec<(usize, usize)> = Vec::new();
+	let mut separators = 0;
+	for (i, c) in number.char_indices() {
+		match c {
+			'0'..='9' => {
+				match groups.last_mut() {
+					Some((digits, end)) if separators == 0 => {
+						*digits += 1;
+						*end = i + 1;
+					},
+					_ => groups.push((1, i + 1)),
+				}
+				separators = 0;
+			},
+			' ' | '-' | '.' | '(' | ')' if separators < 2 => separators += 1,
+			_ => break,
+		}
+	}
+
+	// A lone digit after the first group is rather the next number of a list, e.g. a version.
+	let mut groups = groups.into_iter();
+	let first = groups.next()?;
+	let (digits, end) = groups
+		.take_while(|(digits, _)| *digits > 1)
+		.fold(first, |(total, _), (digits, end)| (total + digits, end));
+	let len = usize::from(international) + end;
+	let expected = if international { INTERNATIONAL_DIGITS } else { NATIONAL_DIGITS };
+	// Neither followed by more of a word nor by the minutes of a time, e.g. after a date.
+	let rest = &text[len..];
+	let bounded = !rest.starts_with(char::is_alphanumeric)
+		&& !rest
+			.strip_prefix(':')
+			.is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+	(expected.contains(&digits) && bounded).then_some(len)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_redact_emails_before_punctuation() {
+		assert_eq!(
+			redact("Mail bob@example.com, (jane@example.org) or @team, not a@b."),
+			"Mail [redacted], ([redacted]) or @team, not a@b."
+		);
+		assert_eq!(redact("write to <jane@example.com>!"), "write to <[redacted]>!");
+	}
+
+	#[test]
+	fn test_redact_phone_numbers() {
+		assert_eq!(redact("Call +44 20 7946 0958 today"), "Call [redacted] today");
+		assert_eq!(redact("or (555) 010-4477."), "or [redacted].");
+		assert_eq!(redact("tel:5550104477"), "tel:[redacted]");
+		assert_eq!(redact("+1-555-010-4477 and 2"), "[redacted] and 2");
+	}
+
+	#[test]
+	fn test_redact_keeps_other_numbers() {
+		for text in [
+			"released 2024-01-15 10:30",
+			"listening on 192.168.1.100:8080",
+			"order 12345 of 3 items",
+			"id ab5550104477",
+			"v1.2.3 4 5 6 7 8 9 10",
+		] {
+			assert_eq!(redact(text), text);
+		}
+	}
+}
+
+// endregion: --- Tests
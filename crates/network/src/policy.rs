@@ -0,0 +1,431 @@
+//! Authorization policies of the agent requests: boolean expressions over the attributes of a
+//! request, e.g. `size < 4096 && (hour >= 8 && hour < 18 || peer in ["12D3KooW..."])`, configured
+//! per agent so that authorization rules change without code changes.
+//!
+//! Attributes: `peer` and `agent` (strings), `language` (string, `null` when unset), `size` (bytes
+//! of the message and its context), `tokens` (estimate of the tokens of the request) and `hour`
+//! (of the day, UTC). Operators: `||`, `&&`, `!`, `==`, `!=`, `<`, `<=`, `>`, `>=` and `in` a
+//! list, along with integer, string, boolean and `null` literals.
+
+use std::{fmt, iter::Peekable, str::Chars, str::FromStr};
+
+use libp2p::PeerId;
+use thiserror::Error;
+
+/// Rough number of bytes per token, for the token estimate of a request.
+const BYTES_PER_TOKEN: usize = 4;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+	#[error("Unexpected character {0:?}")]
+	UnexpectedChar(char),
+	#[error("Unterminated string")]
+	UnterminatedString,
+	#[error("Unknown attribute {0}")]
+	UnknownAttribute(String),
+	#[error("Unexpected {0}")]
+	UnexpectedToken(String),
+	#[error("Cannot compare {0} with {1}")]
+	TypeMismatch(&'static str, &'static str),
+}
+
+/// The attributes of an agent request a policy is evaluated on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestAttributes {
+	pub peer: PeerId,
+	pub agent: String,
+	pub language: Option<String>,
+	pub size: usize,
+	pub hour: u8,
+}
+
+impl RequestAttributes {
+	pub fn tokens(&self) -> usize {
+		self.size.div_ceil(BYTES_PER_TOKEN)
+	}
+}
+
+/// A parsed policy, allowing the requests its expression holds for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestPolicy {
+	source: String,
+	expr: Expr,
+}
+
+impl RequestPolicy {
+	/// Whether the policy allows the request. Requests the expression fails on, e.g. comparing a
+	/// string with an integer, are denied.
+	pub fn allows(&self, request: &RequestAttributes) -> bool {
+		match self.expr.eval(request) {
+			Ok(Value::Bool(allowed)) => allowed,
+			Ok(value) => {
+				tracing::warn!("Policy {self} evaluated to {} instead of a boolean", value.kind());
+				false
+			},
+			Err(e) => {
+				tracing::warn!("Policy {self} failed: {e}");
+				false
+			},
+		}
+	}
+}
+
+impl FromStr for RequestPolicy {
+	type Err = PolicyError;
+
+	fn from_str(source: &str) -> Result<Self, Self::Err> {
+		let mut parser = Parser { tokens: tokenize(source)?.into_iter().peekable() };
+		let expr = parser.or()?;
+		match parser.tokens.next() {
+			Some(token) => Err(PolicyError::UnexpectedToken(token.to_string())),
+			None => Ok(Self { source: source.to_string(), expr }),
+		}
+	}
+}
+
+impl fmt::Display for RequestPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.source)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Attribute {
+	Peer,
+	Agent,
+	Language,
+	Size,
+	Tokens,
+	Hour,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+	Null,
+	Bool(bool),
+	Int(i64),
+	Str(String),
+	List(Vec<Value>),
+}
+
+impl Value {
+	fn kind(&self) -> &'static str {
+		match self {
+			Value::Null => "null",
+			Value::Bool(_) => "a boolean",
+			Value::Int(_) => "an integer",
+			Value::Str(_) => "a string",
+			Value::List(_) => "a list",
+		}
+	}
+
+	fn as_bool(&self) -> Result<bool, PolicyError> {
+		match self {
+			Value::Bool(value) => Ok(*value),
+			value => Err(PolicyError::TypeMismatch(value.kind(), "a boolean")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+	In,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+	Literal(Value),
+	Attribute(Attribute),
+	List(Vec<Expr>),
+	Not(Box<Expr>),
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+	Compare(Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+	fn eval(&self, request: &RequestAttributes) -> Result<Value, PolicyError> {
+		Ok(match self {
+			Expr::Literal(value) => value.clone(),
+			Expr::Attribute(attribute) => match attribute {
+				Attribute::Peer => Value::Str(request.peer.to_string()),
+				Attribute::Agent => Value::Str(request.agent.clone()),
+				Attribute::Language => request.language.clone().map_or(Value::Null, Value::Str),
+				Attribute::Size => Value::Int(request.size as i64),
+				Attribute::Tokens => Value::Int(request.tokens() as i64),
+				Attribute::Hour => Value::Int(request.hour.into()),
+			},
+			Expr::List(items) => {
+				Value::List(items.iter().map(|item| item.eval(request)).collect::<Result<_, _>>()?)
+			},
+			Expr::Not(expr) => Value::Bool(!expr.eval(request)?.as_bool()?),
+			// Short-circuiting, for a guard to protect the rest of the expression.
+			Expr::And(a, b) => {
+				Value::Bool(a.eval(request)?.as_bool()? && b.eval(request)?.as_bool()?)
+			},
+			Expr::Or(a, b) => {
+				Value::Bool(a.eval(request)?.as_bool()? || b.eval(request)?.as_bool()?)
+			},
+			Expr::Compare(op, a, b) => {
+				Value::Bool(compare(*op, a.eval(request)?, b.eval(request)?)?)
+			},
+		})
+	}
+}
+
+fn compare(op: Op, a: Value, b: Value) -> Result<bool, PolicyError> {
+	match (op, a, b) {
+		(Op::In, a, Value::List(items)) => Ok(items.contains(&a)),
+		(Op::In, _, b) => Err(PolicyError::TypeMismatch(b.kind(), "a list")),
+		(Op::Eq, a, b) => Ok(a == b),
+		(Op::Ne, a, b) => Ok(a != b),
+		(op, Value::Int(a), Value::Int(b)) => Ok(match op {
+			Op::Lt => a < b,
+			Op::Le => a <= b,
+			Op::Gt => a > b,
+			_ => a >= b,
+		}),
+		(_, a, b) => Err(PolicyError::TypeMismatch(a.kind(), b.kind())),
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+	Ident(String),
+	Int(i64),
+	Str(String),
+	Op(Op),
+	And,
+	Or,
+	Not,
+	Open,
+	Close,
+	OpenList,
+	CloseList,
+	Comma,
+}
+
+impl fmt::Display for Token {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Token::Ident(ident) => write!(f, "{ident}"),
+			Token::Int(int) => write!(f, "{int}"),
+			Token::Str(string) => write!(f, "{string:?}"),
+			Token::Op(op) => f.write_str(match op {
+				Op::Eq => "==",
+				Op::Ne => "!=",
+				Op::Lt => "<",
+				Op::Le => "<=",
+				Op::Gt => ">",
+				Op::Ge => ">=",
+				Op::In => "in",
+			}),
+			Token::And => f.write_str("&&"),
+			Token::Or => f.write_str("||"),
+			Token::Not => f.write_str("!"),
+			Token::Open => f.write_str("("),
+			Token::Close => f.write_str(")"),
+			Token::OpenList => f.write_str("["),
+			Token::CloseList => f.write_str("]"),
+			Token::Comma => f.write_str(","),
+		}
+	}
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, PolicyError> {
+	let mut tokens = Vec::new();
+	let mut chars = source.chars().peekable();
+	while let Some(c) = chars.next() {
+		let token = match c {
+			c if c.is_whitespace() => continue,
+			'(' => Token::Open,
+			')' => Token::Close,
+			'[' => Token::OpenList,
+			']' => Token::CloseList,
+			',' => Token::Comma,
+			'&' if chars.next_if_eq(&'&').is_some() => Token::And,
+			'|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+			'=' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Eq),
+			'!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+			'!' => Token::Not,
+			'<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+			'<' => Token::Op(Op::Lt),
+			'>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+			'>' => Token::Op(Op::Gt),
+			'"' | '\'' => Token::Str(string(&mut chars, c)?),
+			c if c.is_ascii_digit() => {
+				let mut digits = c.to_string();
+				while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+					digits.push(digit);
+				}
+				Token::Int(digits.parse().map_err(|_| PolicyError::UnexpectedToken(digits))?)
+			},
+			c if c.is_alphabetic() || c == '_' => {
+				let mut ident = c.to_string();
+				while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+					ident.push(c);
+				}
+				match ident.as_str() {
+					"in" => Token::Op(Op::In),
+					_ => Token::Ident(ident),
+				}
+			},
+			c => return Err(PolicyError::UnexpectedChar(c)),
+		};
+		tokens.push(token);
+	}
+	Ok(tokens)
+}
+
+/// The rest of a string literal opened by `quote`.
+fn string(chars: &mut Peekable<Chars>, quote: char) -> Result<String, PolicyError> {
+	let mut string = String::new();
+	loop {
+		match chars.next() {
+			Some(c) if c == quote => return Ok(string),
+			Some('\\') => string.push(chars.next().ok_or(PolicyError::UnterminatedString)?),
+			Some(c) => string.push(c),
+			None => return Err(PolicyError::UnterminatedString),
+		}
+	}
+}
+
+/// Recursive descent parser, `||` binding looser than `&&`, itself looser than `!` and the
+/// comparisons.
+struct Parser {
+	tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+	fn or(&mut self) -> Result<Expr, PolicyError> {
+		let mut expr = self.and()?;
+		while self.tokens.next_if_eq(&Token::Or).is_some() {
+			expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+		}
+		Ok(expr)
+	}
+
+	fn and(&mut self) -> Result<Expr, PolicyError> {
+		let mut expr = self.not()?;
+		while self.tokens.next_if_eq(&Token::And).is_some() {
+			expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+		}
+		Ok(expr)
+	}
+
+	fn not(&mut self) -> Result<Expr, PolicyError> {
+		if self.tokens.next_if_eq(&Token::Not).is_some() {
+			return Ok(Expr::Not(Box::new(self.not()?)));
+		}
+		let expr = self.primary()?;
+		match self.tokens.next_if(|token| matches!(token, Token::Op(_))) {
+			Some(Token::Op(op)) => Ok(Expr::Compare(op, Box::new(expr), Box::new(self.primary()?))),
+			_ => Ok(expr),
+		}
+	}
+
+	fn primary(&mut self) -> Result<Expr, PolicyError> {
+		let token = self.tokens.next().ok_or(PolicyError::UnexpectedToken("end".to_string()))?;
+		Ok(match token {
+			Token::Int(int) => Expr::Literal(Value::Int(int)),
+			Token::Str(string) => Expr::Literal(Value::Str(string)),
+			Token::Ident(ident) => match ident.as_str() {
+				"true" => Expr::Literal(Value::Bool(true)),
+				"false" => Expr::Literal(Value::Bool(false)),
+				"null" => Expr::Literal(Value::Null),
+				"peer" => Expr::Attribute(Attribute::Peer),
+				"agent" => Expr::Attribute(Attribute::Agent),
+				"language" => Expr::Attribute(Attribute::Language),
+				"size" => Expr::Attribute(Attribute::Size),
+				"tokens" => Expr::Attribute(Attribute::Tokens),
+				"hour" => Expr::Attribute(Attribute::Hour),
+				_ => return Err(PolicyError::UnknownAttribute(ident)),
+			},
+			Token::Open => {
+				let expr = self.or()?;
+				self.expect(Token::Close)?;
+				expr
+			},
+			Token::OpenList => {
+				let mut items = Vec::new();
+				while self.tokens.next_if_eq(&Token::CloseList).is_none() {
+					if !items.is_empty() {
+						self.expect(Token::Comma)?;
+					}
+					items.push(self.primary()?);
+				}
+				Expr::List(items)
+			},
+			token => return Err(PolicyError::UnexpectedToken(token.to_string())),
+		})
+	}
+
+	fn expect(&mut self, expected: Token) -> Result<(), PolicyError> {
+		match self.tokens.next() {
+			Some(token) if token == expected => Ok(()),
+			Some(token) => Err(PolicyError::UnexpectedToken(token.to_string())),
+			None => Err(PolicyError::UnexpectedToken("end".to_string())),
+		}
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	fn request(peer: PeerId, size: usize, hour: u8) -> RequestAttributes {
+		RequestAttributes { peer, agent: "echo".to_string(), language: None, size, hour }
+	}
+
+	#[test]
+	fn test_policy_evaluates_the_request_attributes() -> Result<()> {
+		let (trusted, other) = (PeerId::random(), PeerId::random());
+		let policy: RequestPolicy = format!(
+			"agent == 'echo' && (tokens <= 1000 && hour >= 8 && hour < 18 || peer in [\"{trusted}\"])"
+		)
+		.parse()?;
+
+		assert!(policy.allows(&request(other, 4000, 9)));
+		assert!(!policy.allows(&request(other, 4001, 9)));
+		assert!(!policy.allows(&request(other, 10, 20)));
+		assert!(policy.allows(&request(trusted, 1 << 20, 20)));
+		Ok(())
+	}
+
+	#[test]
+	fn test_policy_denies_on_evaluation_errors() -> Result<()> {
+		let peer = PeerId::random();
+		let policy: RequestPolicy = "!(language == null) && language in ['en', 'fr']".parse()?;
+		assert!(!policy.allows(&request(peer, 0, 0)));
+		let french = RequestAttributes { language: Some("fr".to_string()), ..request(peer, 0, 0) };
+		assert!(policy.allows(&french));
+
+		// Comparing a string with an integer, or a policy that is no boolean.
+		assert!(!"agent < 3".parse::<RequestPolicy>()?.allows(&request(peer, 0, 0)));
+		assert!(!"size".parse::<RequestPolicy>()?.allows(&request(peer, 0, 0)));
+		Ok(())
+	}
+
+	#[test]
+	fn test_policy_parse_errors() {
+		let error = |source: &str| source.parse::<RequestPolicy>().unwrap_err();
+		assert_eq!(error("user == 'a'"), PolicyError::UnknownAttribute("user".to_string()));
+		assert_eq!(error("agent == 'a"), PolicyError::UnterminatedString);
+		assert_eq!(error("size < 3 &"), PolicyError::UnexpectedChar('&'));
+		assert_eq!(error("(size < 3"), PolicyError::UnexpectedToken("end".to_string()));
+		assert_eq!(error("size < 3 size"), PolicyError::UnexpectedToken("size".to_string()));
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,121 @@
+use std::{
+	collections::{HashMap, HashSet},
+	time::Duration,
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Gossip topic the nodes announce their presence on.
+pub(crate) const PRESENCE_TOPIC: &str = "presence";
+
+/// Presence beacons sent by the node on the gossip network, telling the requesters which
+/// providers are still online before their DHT records expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresenceConfig {
+	pub enabled: bool,
+	/// How often the node sends a beacon.
+	pub interval: Duration,
+	/// A peer is offline once none of its beacons arrived for this long.
+	pub offline_after: Duration,
+}
+
+impl Default for PresenceConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			interval: Duration::from_secs(30),
+			offline_after: Duration::from_secs(90),
+		}
+	}
+}
+
+/// A presence beacon, listing the agents its node provides.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Beacon {
+	pub agents: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Presence {
+	seen_at: Instant,
+	agents: Vec<String>,
+}
+
+/// When the beacon of each peer was last received.
+#[derive(Debug)]
+pub(crate) struct PresenceTable {
+	peers: HashMap<PeerId, Presence>,
+	offline_after: Duration,
+}
+
+impl PresenceTable {
+	pub(crate) fn new(config: &PresenceConfig) -> Self {
+		Self { peers: HashMap::new(), offline_after: config.offline_after }
+	}
+
+	pub(crate) fn record(&mut self, peer: PeerId, beacon: Beacon, now: Instant) {
+		self.peers.insert(peer, Presence { seen_at: now, agents: beacon.agents });
+	}
+
+	/// Time since the last beacon of the peer, if any arrived.
+	pub(crate) fn last_seen(&self, peer: &PeerId, now: Instant) -> Option<Duration> {
+		self.peers.get(peer).map(|presence| now - presence.seen_at)
+	}
+
+	/// Drop the providers whose beacons lapsed. The ones never heard of are kept, as older nodes
+	/// send no beacon. A provider no longer listing the agent is dropped too.
+	pub(crate) fn online(
+		&self,
+		agent_name: &str,
+		providers: HashSet<PeerId>,
+		now: Instant,
+	) -> HashSet<PeerId> {
+		providers
+			.into_iter()
+			.filter(|peer| match self.peers.get(peer) {
+				Some(presence) => {
+					now - presence.seen_at <= self.offline_after
+						&& presence.agents.iter().any(|agent| agent == agent_name)
+				},
+				None => true,
+			})
+			.collect()
+	}
+
+	/// Forget the peers offline for long, to bound the table.
+	pub(crate) fn prune(&mut self, now: Instant) {
+		let forget_after = self.offline_after * 10;
+		self.peers.retain(|_, presence| now - presence.seen_at <= forget_after);
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_lapsed_providers_are_offline() {
+		let mut table = PresenceTable::new(&PresenceConfig::default());
+		let (fresh, lapsed, unknown) = (PeerId::random(), PeerId::random(), PeerId::random());
+		let start = Instant::now();
+		let beacon = Beacon { agents: vec!["echo".to_string()] };
+		table.record(lapsed, beacon.clone(), start);
+		table.record(fresh, beacon, start + Duration::from_secs(60));
+
+		let now = start + Duration::from_secs(100);
+		let providers = HashSet::from([fresh, lapsed, unknown]);
+		assert_eq!(table.online("echo", providers.clone(), now), HashSet::from([fresh, unknown]));
+		assert_eq!(table.online("other", providers, now), HashSet::from([unknown]));
+		assert_eq!(table.last_seen(&fresh, now), Some(Duration::from_secs(40)));
+
+		table.prune(start + Duration::from_secs(950));
+		assert_eq!(table.last_seen(&lapsed, now), None);
+		assert!(table.last_seen(&fresh, now).is_some());
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,181 @@
+use std::io;
+
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::{request_response, StreamProtocol};
+
+use crate::types::{ContextMessage, LLMRequest, MessageRole};
+
+/// Versions of the agent request protocol, newest first. Peers negotiate the newest one they both
+/// support when opening a request stream, so nodes of either version interoperate during upgrades.
+pub const CONTROL_PROTOCOLS: [&str; 2] =
+	[ProtocolVersion::V2.protocol(), ProtocolVersion::V1.protocol()];
+
+/// A version of the agent request protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+	/// Requests of an agent name and a message.
+	V1,
+	/// Requests may also carry the prior messages of the conversation.
+	V2,
+}
+
+impl ProtocolVersion {
+	pub const fn protocol(&self) -> &'static str {
+		match self {
+			ProtocolVersion::V1 => "/asn/1.0.0",
+			ProtocolVersion::V2 => "/asn/2.0.0",
+		}
+	}
+
+	pub fn from_protocol(protocol: &str) -> Option<Self> {
+		[ProtocolVersion::V1, ProtocolVersion::V2]
+			.into_iter()
+			.find(|version| version.protocol() == protocol)
+	}
+
+	/// The newest version among the protocols a peer supports, as listed by identify.
+	pub fn negotiate<'a>(protocols: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+		protocols.into_iter().filter_map(Self::from_protocol).max()
+	}
+}
+
+/// Wraps the codec of the agent requests with a compatibility shim for the streams negotiated as
+/// [`ProtocolVersion::V1`], whose nodes fail to decode a request with a context.
+#[derive(Debug, Clone, Default)]
+pub struct VersionedCodec<C>(pub C);
+
+#[async_trait]
+impl<C> request_response::Codec for VersionedCodec<C>
+where
+	C: request_response::Codec<Protocol = StreamProtocol, Request = LLMRequest> + Send,
+{
+	type Protocol = StreamProtocol;
+	type Request = LLMRequest;
+	type Response = C::Response;
+
+	async fn read_request<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+	) -> io::Result<LLMRequest>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		self.0.read_request(protocol, io).await
+	}
+
+	async fn read_response<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+	) -> io::Result<C::Response>
+	where
+		T: AsyncRead + Unpin + Send,
+	{
+		self.0.read_response(protocol, io).await
+	}
+
+	async fn write_request<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+		request: LLMRequest,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		let request = match ProtocolVersion::from_protocol(protocol.as_ref()) {
+			Some(ProtocolVersion::V1) => downgrade(request),
+			_ => request,
+		};
+		self.0.write_request(protocol, io, request).await
+	}
+
+	async fn write_response<T>(
+		&mut self,
+		protocol: &StreamProtocol,
+		io: &mut T,
+		response: C::Response,
+	) -> io::Result<()>
+	where
+		T: AsyncWrite + Unpin + Send,
+	{
+		self.0.write_response(protocol, io, response).await
+	}
+}
+
+/// A request a [`ProtocolVersion::V1`] node understands: its context is folded into the message,
+/// as a transcript preceding it, and its language, attachments and job id left out.
+fn downgrade(request: LLMRequest) -> LLMRequest {
+	// Sealed requests are only sent to the providers advertising a key, which all speak V2.
+	let plain =
+		request.2.is_empty() && request.4.is_none() && request.5.is_empty() && request.6.is_none();
+	if plain || request.3.is_some() {
+		return request;
+	}
+	let LLMRequest(agent_name, message, context, ..) = request;
+	if context.is_empty() {
+		return LLMRequest(agent_name, message, Vec::new(), None, None, Vec::new(), None);
+	}
+
+	let transcript = context
+		.iter()
+		.map(|ContextMessage { role, content }| match role {
+			MessageRole::User => format!("User: {content}"),
+			MessageRole::Assistant => format!("Assistant: {content}"),
+		})
+		.collect::<Vec<_>>()
+		.join("\n");
+	LLMRequest(
+		agent_name,
+		format!("{transcript}\nUser: {message}"),
+		Vec::new(),
+		None,
+		None,
+		Vec::new(),
+		None,
+	)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_negotiate_and_downgrade() {
+		let protocols = ["/ipfs/id/1.0.0", "/asn/1.0.0", "/asn/2.0.0"];
+		assert_eq!(ProtocolVersion::negotiate(protocols), Some(ProtocolVersion::V2));
+		assert_eq!(ProtocolVersion::negotiate(["/asn/1.0.0"]), Some(ProtocolVersion::V1));
+		assert_eq!(ProtocolVersion::negotiate(["/ipfs/id/1.0.0"]), None);
+
+		let request = LLMRequest(
+			"agent".to_string(),
+			"And now?".to_string(),
+			vec![
+				ContextMessage { role: MessageRole::User, content: "Hi".to_string() },
+				ContextMessage { role: MessageRole::Assistant, content: "Hello".to_string() },
+			],
+			None,
+			None,
+			Vec::new(),
+			None,
+		);
+		assert_eq!(
+			downgrade(request),
+			LLMRequest(
+				"agent".to_string(),
+				"User: Hi\nAssistant: Hello\nUser: And now?".to_string(),
+				Vec::new(),
+				None,
+				None,
+				Vec::new(),
+				None
+			)
+		);
+	}
+}
+
+// endregion: --- Tests
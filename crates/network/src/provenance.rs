@@ -0,0 +1,148 @@
+//! Provenance of the responses of the providers. Providers end every response with a trailer
+//! naming the provider, the model, the time of the answer and the hash of the agent card it was
+//! answered under, for downstream consumers to trace generated content back to where it came from.
+//! Providers may also hide a [`watermark`] in the text itself, which survives the trailer being
+//! cut off.
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::{
+	agent_card::AgentCard,
+	cid::Cid,
+	types::{serialize_message, ProtocolError},
+};
+
+/// Starts the trailer, followed by the provenance as a line of JSON.
+pub const TRAILER_PREFIX: &str = "\n\n[provenance] ";
+
+/// Starts a watermark, followed by its bits, see [`watermark`].
+const WATERMARK_START: char = '\u{2060}';
+const ZERO: char = '\u{200B}';
+const ONE: char = '\u{200C}';
+
+/// Where a response came from.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+	#[serde_as(as = "DisplayFromStr")]
+	pub provider: PeerId,
+	/// Model that generated the response, e.g. `gpt-4o-mini`.
+	pub model: String,
+	/// Unix timestamp in milliseconds.
+	pub timestamp: u64,
+	/// Content id of the agent card the response was answered under, see [`manifest_of`].
+	pub manifest: Cid,
+}
+
+impl Provenance {
+	/// Append the trailer of the provenance to `response`.
+	pub fn append_to(&self, response: &mut Vec<u8>) -> Result<(), serde_json::Error> {
+		response.extend_from_slice(TRAILER_PREFIX.as_bytes());
+		serde_json::to_writer(&mut *response, self)
+	}
+
+	/// Split a response into its content and the provenance of its trailer, if any. Responses
+	/// whose trailer does not parse are left whole.
+	pub fn split(response: &[u8]) -> (&[u8], Option<Self>) {
+		let prefix = TRAILER_PREFIX.as_bytes();
+		let Some(start) = response.windows(prefix.len()).rposition(|window| window == prefix)
+		else {
+			return (response, None);
+		};
+		match serde_json::from_slice(response[start + prefix.len()..].trim_ascii_end()) {
+			Ok(provenance) => (&response[..start], Some(provenance)),
+			Err(_) => (response, None),
+		}
+	}
+}
+
+/// Content id of the agent card of a provider, naming the agent, its version and terms.
+pub fn manifest_of(card: &AgentCard) -> Result<Cid, ProtocolError> {
+	Ok(Cid::of(serialize_message(card)?))
+}
+
+/// Tag of a provider hidden in its watermarks.
+pub fn watermark_tag(provider: &PeerId) -> [u8; 4] {
+	let digest = Cid::of(provider.to_bytes());
+	let mut tag = [0; 4];
+	tag.copy_from_slice(&digest.digest()[..4]);
+	tag
+}
+
+/// Hide the tag of `provider` in `text` as invisible characters after its first word, or at its
+/// end when it has a single word.
+pub fn watermark(text: &str, provider: &PeerId) -> String {
+	let mut mark = String::from(WATERMARK_START);
+	for byte in watermark_tag(provider) {
+		mark.extend((0..8).rev().map(|bit| if byte >> bit & 1 == 1 { ONE } else { ZERO }));
+	}
+	let at = text.find(char::is_whitespace).unwrap_or(text.len());
+	let mut marked = String::with_capacity(text.len() + mark.len());
+	marked.push_str(&text[..at]);
+	marked.push_str(&mark);
+	marked.push_str(&text[at..]);
+	marked
+}
+
+/// The tag of the watermark of `text`, to compare with the [`watermark_tag`] of a provider.
+pub fn watermark_of(text: &str) -> Option<[u8; 4]> {
+	let (_, mark) = text.split_once(WATERMARK_START)?;
+	let mut tag = [0; 4];
+	let mut bits = mark.chars();
+	for byte in &mut tag {
+		for _ in 0..8 {
+			let bit = match bits.next()? {
+				ZERO => 0,
+				ONE => 1,
+				_ => return None,
+			};
+			*byte = *byte << 1 | bit;
+		}
+	}
+	Some(tag)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_trailer_round_trip() -> Result<()> {
+		let provenance = Provenance {
+			provider: PeerId::random(),
+			model: "gpt-4o-mini".to_string(),
+			timestamp: 1_700_000_000_000,
+			manifest: manifest_of(&AgentCard::new("painter", "0.1.0"))?,
+		};
+		let mut response = b"A cat.".to_vec();
+		provenance.append_to(&mut response)?;
+
+		assert_eq!(Provenance::split(&response), (&b"A cat."[..], Some(provenance)));
+		assert_eq!(Provenance::split(b"A cat."), (&b"A cat."[..], None));
+		let forged = b"A cat.\n\n[provenance] {\"model\": 1}";
+		assert_eq!(Provenance::split(forged), (&forged[..], None));
+		Ok(())
+	}
+
+	#[test]
+	fn test_watermark_names_the_provider() {
+		let (provider, other) = (PeerId::random(), PeerId::random());
+		let marked = watermark("Hello world", &provider);
+
+		assert!(marked.starts_with("Hello\u{2060}"));
+		assert!(marked.ends_with(" world"));
+		assert_eq!(watermark_of(&marked), Some(watermark_tag(&provider)));
+		assert_ne!(watermark_of(&marked), Some(watermark_tag(&other)));
+		assert_eq!(watermark_of("Hello world"), None);
+		assert_eq!(watermark_of(&watermark("Hi", &provider)), Some(watermark_tag(&provider)));
+	}
+}
+
+// endregion: --- Tests
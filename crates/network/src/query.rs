@@ -0,0 +1,131 @@
+use std::{error::Error, time::Duration};
+
+use futures::{
+	channel::{mpsc, oneshot},
+	StreamExt,
+};
+use libp2p::{kad, PeerId};
+
+type QueryResult<T> = Result<T, Box<dyn Error + Send>>;
+
+/// Progress of a DHT query, reported at each of its steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryProgress {
+	/// Results reported so far, starting at 1.
+	pub step: usize,
+	/// Peers the query sent a request to.
+	pub peers_contacted: u32,
+	pub successes: u32,
+	pub failures: u32,
+	/// Peers closest to the key, only known once a lookup finished without finding more results.
+	pub closest_peers: Vec<PeerId>,
+	/// Time since the query started, once it finished.
+	pub elapsed: Option<Duration>,
+}
+
+impl QueryProgress {
+	pub(crate) fn new(
+		step: &kad::ProgressStep,
+		stats: &kad::QueryStats,
+		closest_peers: Vec<PeerId>,
+	) -> Self {
+		Self {
+			step: step.count.get(),
+			peers_contacted: stats.num_requests(),
+			successes: stats.num_successes(),
+			failures: stats.num_failures(),
+			closest_peers,
+			elapsed: stats.duration(),
+		}
+	}
+}
+
+/// A DHT query running in the event loop. Its progress can be followed while waiting for the
+/// result, which makes slow lookups debuggable.
+#[derive(Debug)]
+pub struct QueryHandle<T> {
+	id: kad::QueryId,
+	progress: mpsc::UnboundedReceiver<QueryProgress>,
+	result: oneshot::Receiver<QueryResult<T>>,
+}
+
+impl<T> QueryHandle<T> {
+	pub fn id(&self) -> kad::QueryId {
+		self.id
+	}
+
+	/// Wait for the next progress update. Returns `None` once the query finished.
+	pub async fn next_progress(&mut self) -> Option<QueryProgress> {
+		self.progress.next().await
+	}
+
+	/// Wait for the query to finish, whether its progress was followed or not.
+	pub async fn result(self) -> QueryResult<T> {
+		self.result.await.expect("Sender not to be dropped.")
+	}
+}
+
+/// Event loop side of a [`QueryHandle`].
+#[derive(Debug)]
+pub(crate) struct PendingQuery<T> {
+	progress: mpsc::UnboundedSender<QueryProgress>,
+	result: oneshot::Sender<QueryResult<T>>,
+}
+
+impl<T> PendingQuery<T> {
+	pub(crate) fn new(id: kad::QueryId) -> (Self, QueryHandle<T>) {
+		let (progress_sender, progress) = mpsc::unbounded();
+		let (result_sender, result) = oneshot::channel();
+		(
+			Self { progress: progress_sender, result: result_sender },
+			QueryHandle { id, progress, result },
+		)
+	}
+
+	pub(crate) fn progress(&self, progress: QueryProgress) {
+		// Nobody following the progress is fine.
+		let _ = self.progress.unbounded_send(progress);
+	}
+
+	/// Resolve the query, which also ends its progress updates.
+	pub(crate) fn finish(self, result: QueryResult<T>) {
+		let _ = self.result.send(result);
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_query_progress_ends_with_the_query() -> Result<()> {
+		let mut behaviour =
+			kad::Behaviour::new(PeerId::random(), kad::store::MemoryStore::new(PeerId::random()));
+		let id = behaviour.get_closest_peers(PeerId::random());
+		let (pending, mut handle) = PendingQuery::<u32>::new(id);
+
+		pending.progress(QueryProgress {
+			step: 1,
+			peers_contacted: 3,
+			successes: 2,
+			failures: 1,
+			closest_peers: Vec::new(),
+			elapsed: None,
+		});
+		pending.finish(Ok(7));
+
+		assert_eq!(handle.id(), id);
+		let progress = handle.next_progress().await.ok_or("Expected a progress update")?;
+		assert_eq!((progress.step, progress.peers_contacted), (1, 3));
+		assert!(handle.next_progress().await.is_none());
+		assert_eq!(handle.result().await.map_err(|e| e.to_string())?, 7);
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,271 @@
+//! Queue of the tasks a provider won and has not finished yet, kept in a sled database flushed
+//! on every change so that the tasks survive a restart of the provider. Tasks are queued once by
+//! id, a duplicate award of a queued or recently finished task is ignored, and their result is
+//! kept once computed for a resumed task to send the same result again.
+
+use std::{
+	collections::VecDeque,
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use sled::{
+	transaction::{ConflictableTransactionError, TransactionError},
+	Transactional,
+};
+use thiserror::Error;
+
+use crate::{stream::now_ms, types::TaskProposal};
+
+/// Finished tasks remembered to ignore their duplicate awards, the oldest forgotten past it.
+const MAX_FINISHED: usize = 1024;
+
+/// How long to wait for the lock of a database closed a moment ago, which the I/O threads of sled
+/// only release once done with its last writes.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Error, Debug)]
+pub enum QueueError {
+	#[error("Failed to access the task queue: {0}")]
+	Store(#[from] sled::Error),
+	#[error("Malformed task queue: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("Task {0} is not queued")]
+	Unknown(String),
+}
+
+/// A task won by the provider.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedTask {
+	/// Node that proposed the task.
+	#[serde_as(as = "DisplayFromStr")]
+	pub owner: PeerId,
+	pub proposal: TaskProposal,
+	/// Unix timestamp in milliseconds.
+	pub queued_at: u64,
+	/// Result of the task once computed, not sent to the owner yet.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub result: Option<Vec<u8>>,
+}
+
+/// The database of a queue, with the queued tasks and the ids of the finished ones each in a
+/// tree keyed by the big-endian order they were added in.
+#[derive(Debug)]
+struct Store {
+	db: sled::Db,
+	tasks: sled::Tree,
+	finished: sled::Tree,
+}
+
+/// The queued tasks of a provider, kept in a sled database when a path is set.
+#[derive(Debug, Default)]
+pub struct TaskQueue {
+	store: Option<Store>,
+	/// Oldest first, along with their key.
+	tasks: Vec<(u64, QueuedTask)>,
+	finished: VecDeque<(u64, String)>,
+	next_key: u64,
+}
+
+impl TaskQueue {
+	/// Open the queue in the database at `path`, created empty when it does not exist yet, or an
+	/// in-memory queue.
+	pub fn open(path: Option<PathBuf>) -> Result<Self, QueueError> {
+		let Some(path) = path else {
+			return Ok(Self::default());
+		};
+		let db = open_db(&path)?;
+		let store =
+			Store { tasks: db.open_tree("tasks")?, finished: db.open_tree("finished")?, db };
+		let tasks = store
+			.tasks
+			.iter()
+			.map(|entry| {
+				let (key, value) = entry?;
+				Ok((decode_key(&key), serde_json::from_slice(&value)?))
+			})
+			.collect::<Result<Vec<_>, QueueError>>()?;
+		let finished = store
+			.finished
+			.iter()
+			.map(|entry| {
+				let (key, value) = entry?;
+				Ok((decode_key(&key), String::from_utf8_lossy(&value).into_owned()))
+			})
+			.collect::<Result<VecDeque<_>, QueueError>>()?;
+		let next_key = tasks
+			.iter()
+			.map(|(key, _)| key + 1)
+			.chain(finished.iter().map(|(key, _)| key + 1))
+			.max()
+			.unwrap_or(0);
+		Ok(Self { store: Some(store), tasks, finished, next_key })
+	}
+
+	/// Queue a task won from `owner`. Returns false when it is already queued or finished.
+	pub fn enqueue(&mut self, owner: PeerId, proposal: TaskProposal) -> Result<bool, QueueError> {
+		let task_id = &proposal.task_id;
+		if self.get(task_id).is_some() || self.finished.iter().any(|(_, id)| id == task_id) {
+			return Ok(false);
+		}
+		let key = self.next_key;
+		let task = QueuedTask { owner, proposal, queued_at: now_ms(), result: None };
+		if let Some(store) = &self.store {
+			store.tasks.insert(key.to_be_bytes(), serde_json::to_vec(&task)?)?;
+			store.db.flush()?;
+		}
+		self.next_key += 1;
+		self.tasks.push((key, task));
+		Ok(true)
+	}
+
+	pub fn get(&self, task_id: &str) -> Option<&QueuedTask> {
+		self.tasks
+			.iter()
+			.map(|(_, task)| task)
+			.find(|task| task.proposal.task_id == task_id)
+	}
+
+	/// The oldest queued task.
+	pub fn next(&self) -> Option<&QueuedTask> {
+		self.tasks.first().map(|(_, task)| task)
+	}
+
+	pub fn len(&self) -> usize {
+		self.tasks.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.tasks.is_empty()
+	}
+
+	/// Keep the result of a queued task until it is sent.
+	pub fn set_result(&mut self, task_id: &str, result: Vec<u8>) -> Result<(), QueueError> {
+		let (key, task) = self
+			.tasks
+			.iter_mut()
+			.find(|(_, task)| task.proposal.task_id == task_id)
+			.ok_or_else(|| QueueError::Unknown(task_id.to_string()))?;
+		let updated = QueuedTask { result: Some(result), ..task.clone() };
+		if let Some(store) = &self.store {
+			store.tasks.insert(key.to_be_bytes(), serde_json::to_vec(&updated)?)?;
+			store.db.flush()?;
+		}
+		*task = updated;
+		Ok(())
+	}
+
+	/// Drop a task once finished, successfully or not. Returns whether it was queued.
+	pub fn finish(&mut self, task_id: &str) -> Result<bool, QueueError> {
+		let Some(i) = self.tasks.iter().position(|(_, task)| task.proposal.task_id == task_id)
+		else {
+			return Ok(false);
+		};
+		let key = self.next_key;
+		let forgotten = (self.finished.len() >= MAX_FINISHED).then(|| self.finished[0].0);
+		if let Some(store) = &self.store {
+			// The task leaves the queue and joins the finished ones at once, for a crash not to let
+			// a duplicate award queue it again.
+			(&store.tasks, &store.finished)
+				.transaction(|(tasks, finished)| {
+					tasks.remove(&self.tasks[i].0.to_be_bytes())?;
+					finished.insert(&key.to_be_bytes(), task_id.as_bytes())?;
+					if let Some(forgotten) = forgotten {
+						finished.remove(&forgotten.to_be_bytes())?;
+					}
+					Ok::<_, ConflictableTransactionError<()>>(())
+				})
+				.map_err(|e| match e {
+					TransactionError::Abort(()) => unreachable!("The transaction never aborts."),
+					TransactionError::Storage(e) => e,
+				})?;
+			store.db.flush()?;
+		}
+		self.next_key += 1;
+		self.tasks.remove(i);
+		self.finished.push_back((key, task_id.to_string()));
+		if forgotten.is_some() {
+			self.finished.pop_front();
+		}
+		Ok(true)
+	}
+}
+
+/// Open the database at `path`, retrying for [`LOCK_TIMEOUT`] while the database it replaces
+/// in this process still holds its lock.
+fn open_db(path: &Path) -> sled::Result<sled::Db> {
+	let deadline = Instant::now() + LOCK_TIMEOUT;
+	loop {
+		// Every change is flushed right away, no need for the periodic flushes of a background
+		// thread.
+		match sled::Config::new().path(path).flush_every_ms(None).open() {
+			Err(sled::Error::Io(e))
+				if e.to_string().starts_with("could not acquire lock")
+					&& Instant::now() < deadline =>
+			{
+				std::thread::sleep(Duration::from_millis(10))
+			},
+			db => return db,
+		}
+	}
+}
+
+fn decode_key(key: &[u8]) -> u64 {
+	key.try_into().map(u64::from_be_bytes).unwrap_or_default()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::types::TaskType;
+
+	fn proposal(task_id: &str) -> TaskProposal {
+		TaskProposal {
+			agent_name: "painter".to_string(),
+			task_id: task_id.to_string(),
+			task_type: TaskType::ImageGeneration,
+			task_message: "a cat".to_string(),
+			max_bid: 10.0,
+			deadline: 1_000,
+			requirements: Default::default(),
+		}
+	}
+
+	#[test]
+	fn test_queue_survives_a_restart_and_ignores_duplicates() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let path = dir.path().join("queue");
+		let owner = PeerId::random();
+		let mut queue = TaskQueue::open(Some(path.clone()))?;
+		assert!(queue.enqueue(owner, proposal("a"))?);
+		assert!(queue.enqueue(owner, proposal("b"))?);
+		assert!(!queue.enqueue(owner, proposal("a"))?);
+		queue.set_result("a", b"done".to_vec())?;
+		drop(queue);
+
+		let mut restarted = TaskQueue::open(Some(path.clone()))?;
+		let next = restarted.next().expect("A queued task.");
+		assert_eq!((next.owner, next.result.as_deref()), (owner, Some(&b"done"[..])));
+		assert!(restarted.finish("a")?);
+		assert!(!restarted.enqueue(owner, proposal("a"))?);
+		assert_eq!(restarted.next().map(|task| task.proposal.task_id.as_str()), Some("b"));
+		assert!(matches!(restarted.set_result("a", Vec::new()), Err(QueueError::Unknown(_))));
+		drop(restarted);
+
+		let reopened = TaskQueue::open(Some(path))?;
+		assert_eq!(reopened.len(), 1);
+		assert!(reopened.finished.iter().any(|(_, id)| id == "a"));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,184 @@
+use std::{collections::HashMap, time::Duration};
+
+use libp2p::{Multiaddr, PeerId};
+use tokio::time::Instant;
+
+/// Backoff between the attempts to reconnect to a peer the node depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectConfig {
+	/// Delay before the first attempt, doubled after each failed one.
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+}
+
+impl Default for ReconnectConfig {
+	fn default() -> Self {
+		Self { initial_backoff: Duration::from_secs(1), max_backoff: Duration::from_secs(300) }
+	}
+}
+
+/// Connection state of a bootstrap peer or rendezvous point, reported as it changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconnectState {
+	/// The connection dropped or a reconnection attempt failed, `attempt` is redialed in
+	/// `retry_in`.
+	Disconnected {
+		attempt: u32,
+		retry_in: Duration,
+	},
+	Reconnected,
+}
+
+#[derive(Debug)]
+struct TrackedPeer {
+	address: Multiaddr,
+	/// Failed attempts since the connection dropped.
+	attempt: u32,
+	/// When the next attempt is due, unset while connected or dialing.
+	next_dial: Option<Instant>,
+}
+
+/// Keeps track of the peers the node cannot lose, i.e. the bootstrap peers and the rendezvous
+/// point, and schedules their redials with an exponential backoff and jitter.
+#[derive(Debug, Default)]
+pub(crate) struct ReconnectManager {
+	config: ReconnectConfig,
+	peers: HashMap<PeerId, TrackedPeer>,
+}
+
+impl ReconnectManager {
+	pub(crate) fn new(config: ReconnectConfig) -> Self {
+		Self { config, peers: HashMap::new() }
+	}
+
+	pub(crate) fn track(&mut self, peer_id: PeerId, address: Multiaddr) {
+		self.peers.insert(peer_id, TrackedPeer { address, attempt: 0, next_dial: None });
+	}
+
+	/// Backoff of the given attempt. `jitter` in `[0, 1]` picks a delay between half and the
+	/// whole backoff, so that peers losing the same node do not all redial it at once.
+	fn backoff(&self, attempt: u32, jitter: f64) -> Duration {
+		let backoff = self
+			.config
+			.initial_backoff
+			.saturating_mul(2u32.saturating_pow(attempt))
+			.min(self.config.max_backoff);
+		backoff.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+	}
+
+	/// Schedule the next attempt to reach a tracked peer that is not connected anymore. Nothing
+	/// changes while an attempt is already scheduled.
+	pub(crate) fn disconnected(
+		&mut self,
+		peer_id: &PeerId,
+		now: Instant,
+	) -> Option<ReconnectState> {
+		let peer = self.peers.get(peer_id).filter(|peer| peer.next_dial.is_none())?;
+		let retry_in = self.backoff(peer.attempt, rand::random());
+		let peer = self.peers.get_mut(peer_id)?;
+		peer.attempt += 1;
+		peer.next_dial = Some(now + retry_in);
+		Some(ReconnectState::Disconnected { attempt: peer.attempt, retry_in })
+	}
+
+	/// Reset the backoff of a tracked peer, reporting it when it was being reconnected.
+	pub(crate) fn connected(&mut self, peer_id: &PeerId) -> Option<ReconnectState> {
+		let peer = self.peers.get_mut(peer_id)?;
+		let reconnected = peer.attempt > 0;
+		peer.attempt = 0;
+		peer.next_dial = None;
+		reconnected.then_some(ReconnectState::Reconnected)
+	}
+
+	pub(crate) fn next_dial(&self) -> Option<Instant> {
+		self.peers.values().filter_map(|peer| peer.next_dial).min()
+	}
+
+	/// Forget the backoff of every tracked peer, returning them all to be redialed now.
+	pub(crate) fn reset(&mut self) -> Vec<(PeerId, Multiaddr)> {
+		self.peers
+			.iter_mut()
+			.map(|(peer_id, peer)| {
+				peer.attempt = 0;
+				peer.next_dial = None;
+				(*peer_id, peer.address.clone())
+			})
+			.collect()
+	}
+
+	/// The peers to redial now. They are not due again until their attempt fails.
+	pub(crate) fn due(&mut self, now: Instant) -> Vec<(PeerId, Multiaddr)> {
+		self.peers
+			.iter_mut()
+			.filter(|(_, peer)| peer.next_dial.is_some_and(|next_dial| next_dial <= now))
+			.map(|(peer_id, peer)| {
+				peer.next_dial = None;
+				(*peer_id, peer.address.clone())
+			})
+			.collect()
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn manager() -> ReconnectManager {
+		ReconnectManager::new(ReconnectConfig {
+			initial_backoff: Duration::from_secs(1),
+			max_backoff: Duration::from_secs(10),
+		})
+	}
+
+	#[test]
+	fn test_backoff_doubles_up_to_the_max() {
+		let manager = manager();
+
+		let backoffs: Vec<_> =
+			(0..6).map(|attempt| manager.backoff(attempt, 1.0).as_secs()).collect();
+
+		assert_eq!(backoffs, [1, 2, 4, 8, 10, 10]);
+		assert_eq!(manager.backoff(2, 0.0), Duration::from_secs(2));
+		assert_eq!(manager.backoff(40, 1.0), Duration::from_secs(10));
+	}
+
+	#[test]
+	fn test_reconnect_schedules_tracked_peers_only() {
+		let mut manager = manager();
+		let (tracked, other) = (PeerId::random(), PeerId::random());
+		manager.track(tracked, "/ip4/127.0.0.1/tcp/4001".parse().unwrap());
+		let now = Instant::now();
+
+		assert!(manager.disconnected(&other, now).is_none());
+		let Some(ReconnectState::Disconnected { attempt: 1, retry_in }) =
+			manager.disconnected(&tracked, now)
+		else {
+			panic!("Expected the tracked peer to be scheduled.");
+		};
+
+		assert!(manager.due(now).is_empty());
+		assert!(manager.disconnected(&tracked, now).is_none());
+		assert_eq!(manager.next_dial(), Some(now + retry_in));
+		assert_eq!(manager.due(now + retry_in).len(), 1);
+		// Dialing: not due again until the attempt failed.
+		assert!(manager.due(now + retry_in).is_empty());
+
+		assert!(matches!(
+			manager.disconnected(&tracked, now),
+			Some(ReconnectState::Disconnected { attempt: 2, .. })
+		));
+		assert_eq!(manager.reset().len(), 1);
+		assert_eq!(manager.next_dial(), None);
+		assert!(matches!(
+			manager.disconnected(&tracked, now),
+			Some(ReconnectState::Disconnected { attempt: 1, .. })
+		));
+		assert_eq!(manager.connected(&tracked), Some(ReconnectState::Reconnected));
+		assert_eq!(manager.connected(&tracked), None);
+		assert_eq!(manager.next_dial(), None);
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,439 @@
+//! Reputation of the providers, from the outcomes of the tasks they worked on. The owner of a task
+//! signs an [`Attestation`] of its outcome and appends it to the ledger of the provider on the DHT,
+//! see [`crate::Client::attest`]. Ledgers aggregate into a score, used to pick the providers of a
+//! request and the winners of auctions. A requester demotes the providers whose score dropped for a
+//! cooldown, see [`Cooldowns`].
+
+use std::{
+	collections::{hash_map, HashMap},
+	error::Error,
+	fmt,
+	str::FromStr,
+	time::Duration,
+};
+
+use libp2p::{identity, kad, PeerId};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use thiserror::Error;
+
+use crate::{
+	signed::{SignatureError, SignedPayload},
+	tasks::TaskStatus,
+	types::{deserialize_message, serialize_message, ProtocolError, RecordError},
+};
+
+const SIGNING_DOMAIN: &[u8] = b"dasn-attestation:";
+
+/// Attestations kept in a ledger, the oldest being dropped past it for the record to stay within
+/// the size the DHT stores.
+pub const MAX_ATTESTATIONS: usize = 50;
+
+/// DHT key the reputation ledger of a provider is stored under.
+pub fn reputation_key(provider: &PeerId) -> String {
+	format!("/asn/reputation/{provider}")
+}
+
+/// Whether the lookup of a ledger failed because none was published yet.
+pub(crate) fn is_not_found(error: &(dyn Error + Send + 'static)) -> bool {
+	matches!(error.downcast_ref(), Some(RecordError::NotFound))
+		|| matches!(error.downcast_ref(), Some(kad::GetRecordError::NotFound { .. }))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+	Completed,
+	Failed,
+	/// The provider reported the task completed, its owner contests the result.
+	Disputed,
+}
+
+impl Outcome {
+	/// The outcome of a finished task, None while it runs.
+	pub fn of(status: &TaskStatus) -> Option<Self> {
+		match status {
+			TaskStatus::Completed => Some(Outcome::Completed),
+			TaskStatus::Failed { .. } | TaskStatus::TimedOut => Some(Outcome::Failed),
+			TaskStatus::Proposed | TaskStatus::Assigned | TaskStatus::InProgress => None,
+		}
+	}
+
+	fn value(self) -> f64 {
+		match self {
+			Outcome::Completed => 1.0,
+			Outcome::Failed | Outcome::Disputed => -1.0,
+		}
+	}
+}
+
+impl fmt::Display for Outcome {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Outcome::Completed => "completed",
+			Outcome::Failed => "failed",
+			Outcome::Disputed => "disputed",
+		})
+	}
+}
+
+impl FromStr for Outcome {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"completed" => Ok(Outcome::Completed),
+			"failed" => Ok(Outcome::Failed),
+			"disputed" => Ok(Outcome::Disputed),
+			_ => Err(format!("Unknown outcome {s}, expected completed, failed or disputed")),
+		}
+	}
+}
+
+/// Outcome of a task worked on by `provider`, signed by the owner of the task.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+	#[serde_as(as = "DisplayFromStr")]
+	pub provider: PeerId,
+	pub task_id: String,
+	pub outcome: Outcome,
+	/// Unix timestamp in milliseconds. A later attestation of the same task by the same owner
+	/// replaces the earlier one.
+	pub issued_at: u64,
+}
+
+impl Attestation {
+	pub fn sign(&self, key: &identity::Keypair) -> Result<SignedPayload, SignatureError> {
+		SignedPayload::sign(key, SIGNING_DOMAIN, self)
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum ReputationError {
+	#[error("Invalid attestation: {0}")]
+	Signature(#[from] SignatureError),
+	#[error("Malformed ledger: {0}")]
+	Protocol(#[from] ProtocolError),
+	#[error("Attestation on another provider, {0}")]
+	ProviderMismatch(PeerId),
+	#[error("Provider {0} attested its own task")]
+	SelfAttested(PeerId),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+	attester: PeerId,
+	attestation: Attestation,
+	signed: SignedPayload,
+}
+
+/// The verified attestations on a provider, as stored on the DHT.
+#[derive(Debug, Clone)]
+pub struct ReputationLedger {
+	provider: PeerId,
+	/// Oldest first.
+	entries: Vec<Entry>,
+}
+
+impl ReputationLedger {
+	pub fn new(provider: PeerId) -> Self {
+		Self { provider, entries: Vec::new() }
+	}
+
+	/// Read the ledger of `provider` from its DHT record, dropping the attestations that do not
+	/// verify or are not about the provider.
+	pub fn decode(provider: PeerId, data: &[u8]) -> Result<Self, ReputationError> {
+		let mut ledger = Self::new(provider);
+		for signed in deserialize_message::<Vec<SignedPayload>>(data)? {
+			if let Err(e) = ledger.add(signed) {
+				tracing::warn!("Dropping attestation from the ledger of {provider}: {e}");
+			}
+		}
+		Ok(ledger)
+	}
+
+	pub fn encode(&self) -> Result<Vec<u8>, ProtocolError> {
+		let signed: Vec<_> = self.entries.iter().map(|entry| &entry.signed).collect();
+		serialize_message(&signed)
+	}
+
+	pub fn provider(&self) -> PeerId {
+		self.provider
+	}
+
+	/// The attestations of the ledger along with their signer, oldest first.
+	pub fn attestations(&self) -> impl Iterator<Item = (&PeerId, &Attestation)> {
+		self.entries.iter().map(|entry| (&entry.attester, &entry.attestation))
+	}
+
+	/// Verify an attestation and add it to the ledger, replacing any earlier attestation of the
+	/// same task by the same owner.
+	pub fn add(&mut self, signed: SignedPayload) -> Result<(), ReputationError> {
+		let (attester, attestation) = signed.verify::<Attestation>(SIGNING_DOMAIN)?;
+		if attestation.provider != self.provider {
+			return Err(ReputationError::ProviderMismatch(attestation.provider));
+		}
+		if attester == self.provider {
+			return Err(ReputationError::SelfAttested(attester));
+		}
+		let same_task = |entry: &Entry| {
+			entry.attester == attester && entry.attestation.task_id == attestation.task_id
+		};
+		match self.entries.iter().position(same_task) {
+			Some(i) if self.entries[i].attestation.issued_at >= attestation.issued_at => {
+				return Ok(())
+			},
+			Some(i) => {
+				self.entries.remove(i);
+			},
+			None => {},
+		}
+		let at = self
+			.entries
+			.partition_point(|entry| entry.attestation.issued_at <= attestation.issued_at);
+		self.entries.insert(at, Entry { attester, attestation, signed });
+		if self.entries.len() > MAX_ATTESTATIONS {
+			self.entries.drain(..self.entries.len() - MAX_ATTESTATIONS);
+		}
+		Ok(())
+	}
+
+	/// Share of completed tasks, from -1 when all failed or were disputed to 1 when all completed,
+	/// 0 without any attestation. Every owner weighs the same whatever the number of tasks it
+	/// attested, for a single owner not to make or break a provider. Comparable with
+	/// [`crate::AgentQuality::score`].
+	pub fn score(&self) -> f64 {
+		let mut attesters: Vec<(PeerId, f64, u32)> = Vec::new();
+		for entry in &self.entries {
+			let value = entry.attestation.outcome.value();
+			match attesters.iter_mut().find(|(attester, ..)| *attester == entry.attester) {
+				Some((_, sum, count)) => {
+					*sum += value;
+					*count += 1;
+				},
+				None => attesters.push((entry.attester, value, 1)),
+			}
+		}
+		if attesters.is_empty() {
+			return 0.0;
+		}
+		let total: f64 = attesters.iter().map(|(_, sum, count)| sum / f64::from(*count)).sum();
+		total / attesters.len() as f64
+	}
+}
+
+/// When a requester demotes a provider for its score, and how it gives it another chance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CooldownConfig {
+	/// Score below which a provider is demoted.
+	pub demote_below: f64,
+	/// Score a demoted provider is restored at. Above `demote_below`, for a provider whose score
+	/// hovers around the threshold not to flap between demoted and restored.
+	pub restore_at: f64,
+	/// How long a demoted provider is only called when no other one is left.
+	pub period: Duration,
+	/// Share of the requests a demoted provider is called for again once its period elapsed, to
+	/// probe whether it recovered.
+	pub probe_share: f64,
+}
+
+impl Default for CooldownConfig {
+	fn default() -> Self {
+		Self {
+			demote_below: 0.0,
+			restore_at: 0.25,
+			period: Duration::from_secs(600),
+			probe_share: 0.1,
+		}
+	}
+}
+
+/// Parse `below=-0.2,restore=0.2,period=600,probe=0.1`, the period in seconds. Parameters left out
+/// keep their default.
+impl FromStr for CooldownConfig {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut config = CooldownConfig::default();
+		for param in s.split(',').filter(|param| !param.trim().is_empty()) {
+			let (key, value) = param
+				.split_once('=')
+				.map(|(key, value)| (key.trim(), value.trim()))
+				.ok_or_else(|| format!("Invalid cooldown parameter {param}, expected key=value"))?;
+			let value = value
+				.parse::<f64>()
+				.ok()
+				.filter(|value| value.is_finite())
+				.ok_or_else(|| format!("Invalid {key} {value}, expected a number"))?;
+			match key {
+				"below" => config.demote_below = value,
+				"restore" => config.restore_at = value,
+				"period" if value >= 0.0 => config.period = Duration::from_secs_f64(value),
+				"probe" if (0.0..=1.0).contains(&value) => config.probe_share = value,
+				"period" | "probe" => return Err(format!("Invalid {key} {value}, out of range")),
+				key => return Err(format!("Unknown cooldown parameter {key}")),
+			}
+		}
+		if config.restore_at < config.demote_below {
+			return Err(format!(
+				"The restore score {} is below the demotion one {}",
+				config.restore_at, config.demote_below
+			));
+		}
+		Ok(config)
+	}
+}
+
+/// The providers a requester demoted for their score, kept between requests for the cooldowns to
+/// elapse.
+#[serde_as]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cooldowns {
+	/// Unix timestamp in milliseconds each provider was demoted at.
+	#[serde_as(as = "HashMap<DisplayFromStr, _>")]
+	demoted: HashMap<PeerId, u64>,
+}
+
+impl Cooldowns {
+	/// Demote `provider` when its `score` dropped below the threshold, restore it once the score
+	/// reached the restore one again. Returns whether the provider is demoted.
+	pub fn update(
+		&mut self,
+		config: &CooldownConfig,
+		provider: PeerId,
+		score: f64,
+		now: u64,
+	) -> bool {
+		match self.demoted.entry(provider) {
+			hash_map::Entry::Occupied(demoted) if score >= config.restore_at => {
+				demoted.remove();
+				tracing::info!("Provider {provider} restored with a score of {score:.2}");
+				false
+			},
+			hash_map::Entry::Occupied(_) => true,
+			hash_map::Entry::Vacant(healthy) if score < config.demote_below => {
+				healthy.insert(now);
+				tracing::info!("Provider {provider} demoted with a score of {score:.2}");
+				true
+			},
+			hash_map::Entry::Vacant(_) => false,
+		}
+	}
+
+	pub fn is_demoted(&self, provider: &PeerId) -> bool {
+		self.demoted.contains_key(provider)
+	}
+
+	/// Whether a request may be sent to `provider`: always unless demoted, never during its
+	/// cooldown and for a share of the requests once the cooldown elapsed.
+	pub fn admits(&self, config: &CooldownConfig, provider: &PeerId, now: u64) -> bool {
+		self.admits_with(config, provider, now, rand::random())
+	}
+
+	/// [`Self::admits`], probing when `roll`, in [0, 1), falls within the share of the probes.
+	fn admits_with(&self, config: &CooldownConfig, provider: &PeerId, now: u64, roll: f64) -> bool {
+		match self.demoted.get(provider) {
+			None => true,
+			Some(since) if now.saturating_sub(*since) < config.period.as_millis() as u64 => false,
+			Some(_) => roll < config.probe_share,
+		}
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	fn attest(
+		owner: &identity::Keypair,
+		provider: PeerId,
+		task_id: &str,
+		outcome: Outcome,
+		issued_at: u64,
+	) -> Result<SignedPayload> {
+		let attestation =
+			Attestation { provider, task_id: task_id.to_string(), outcome, issued_at };
+		Ok(attestation.sign(owner)?)
+	}
+
+	#[test]
+	fn test_ledger_round_trip_and_score() -> Result<()> {
+		let provider = PeerId::random();
+		let (busy, other) =
+			(identity::Keypair::generate_ed25519(), identity::Keypair::generate_ed25519());
+		let mut ledger = ReputationLedger::new(provider);
+		assert_eq!(ledger.score(), 0.0);
+
+		for (i, task_id) in ["a", "b", "c"].into_iter().enumerate() {
+			ledger.add(attest(&busy, provider, task_id, Outcome::Completed, i as u64)?)?;
+		}
+		ledger.add(attest(&other, provider, "d", Outcome::Disputed, 10)?)?;
+		// Both owners weigh the same.
+		assert_eq!(ledger.score(), 0.0);
+		// A later attestation of a task replaces the earlier one, an older one is ignored.
+		ledger.add(attest(&other, provider, "d", Outcome::Completed, 11)?)?;
+		ledger.add(attest(&other, provider, "d", Outcome::Failed, 9)?)?;
+		assert_eq!(ledger.score(), 1.0);
+
+		let decoded = ReputationLedger::decode(provider, &ledger.encode()?)?;
+		let outcomes: Vec<_> = decoded.attestations().map(|(_, a)| a.outcome).collect();
+		assert_eq!(outcomes, [Outcome::Completed; 4]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_ledger_refuses_foreign_and_self_attestations() -> Result<()> {
+		let key = identity::Keypair::generate_ed25519();
+		let provider = key.public().to_peer_id();
+		let owner = identity::Keypair::generate_ed25519();
+		let mut ledger = ReputationLedger::new(provider);
+
+		let own = attest(&key, provider, "task", Outcome::Completed, 1)?;
+		assert!(matches!(ledger.add(own), Err(ReputationError::SelfAttested(_))));
+		let foreign = attest(&owner, PeerId::random(), "task", Outcome::Completed, 1)?;
+		assert!(matches!(ledger.add(foreign), Err(ReputationError::ProviderMismatch(_))));
+		let challenge = SignedPayload::sign(&owner, b"dasn-challenge:", &"nonce")?;
+		assert!(ledger.add(challenge).is_err());
+
+		for i in 0..MAX_ATTESTATIONS as u64 + 5 {
+			ledger.add(attest(&owner, provider, &i.to_string(), Outcome::Failed, i)?)?;
+		}
+		assert_eq!(ledger.attestations().count(), MAX_ATTESTATIONS);
+		assert_eq!(ledger.attestations().next().map(|(_, a)| a.issued_at), Some(5));
+		Ok(())
+	}
+
+	#[test]
+	fn test_cooldown_with_hysteresis() -> Result<()> {
+		let config: CooldownConfig = "below=-0.2,restore=0.2,period=60,probe=0.25".parse()?;
+		let provider = PeerId::random();
+		let mut cooldowns = Cooldowns::default();
+
+		assert!(!cooldowns.update(&config, provider, -0.1, 0));
+		assert!(cooldowns.update(&config, provider, -0.5, 1_000));
+		// Between both thresholds, a demoted provider stays demoted.
+		assert!(cooldowns.update(&config, provider, 0.1, 2_000));
+		assert!(!cooldowns.admits_with(&config, &provider, 60_999, 0.0));
+		// Once the cooldown elapsed, a share of the requests probes the provider.
+		assert!(cooldowns.admits_with(&config, &provider, 61_000, 0.2));
+		assert!(!cooldowns.admits_with(&config, &provider, 61_000, 0.3));
+
+		let decoded: Cooldowns = serde_json::from_str(&serde_json::to_string(&cooldowns)?)?;
+		assert_eq!(decoded, cooldowns);
+		assert!(!cooldowns.update(&config, provider, 0.2, 62_000));
+		assert!(cooldowns.admits_with(&config, &provider, 62_000, 0.9));
+
+		assert!("below=0.5,restore=0.1".parse::<CooldownConfig>().is_err());
+		assert!("probe=2".parse::<CooldownConfig>().is_err());
+		assert!("wait=1".parse::<CooldownConfig>().is_err());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,71 @@
+use std::error::Error;
+
+use futures::channel::oneshot;
+use libp2p::request_response::OutboundRequestId;
+
+pub(crate) type RequestResult = Result<Vec<u8>, Box<dyn Error + Send>>;
+
+/// An agent request on its way to a provider. Dropping the handle, or passing it to
+/// [`crate::Client::cancel_request`], stops waiting for the response, which is discarded if it
+/// still arrives.
+#[derive(Debug)]
+pub struct RequestHandle {
+	id: OutboundRequestId,
+	response: oneshot::Receiver<RequestResult>,
+}
+
+impl RequestHandle {
+	pub(crate) fn new(id: OutboundRequestId) -> (oneshot::Sender<RequestResult>, Self) {
+		let (sender, response) = oneshot::channel();
+		(sender, Self { id, response })
+	}
+
+	pub fn id(&self) -> OutboundRequestId {
+		self.id
+	}
+
+	/// Wait for the response of the provider.
+	pub async fn response(self) -> RequestResult {
+		self.response.await.expect("Sender not to be dropped.")
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::types::{LLMRequest, LLMResponse};
+	use libp2p::{request_response, PeerId, StreamProtocol};
+
+	fn request_id() -> OutboundRequestId {
+		let mut behaviour = request_response::cbor::Behaviour::<LLMRequest, LLMResponse>::new(
+			[(StreamProtocol::new("/test"), request_response::ProtocolSupport::Full)],
+			Default::default(),
+		);
+		behaviour.send_request(
+			&PeerId::random(),
+			LLMRequest(String::new(), String::new(), Vec::new(), None, None, Vec::new(), None),
+		)
+	}
+
+	#[tokio::test]
+	async fn test_dropped_request_handle_is_detected() -> Result<()> {
+		let (sender, handle) = RequestHandle::new(request_id());
+		let (dropped_sender, dropped) = RequestHandle::new(request_id());
+
+		drop(dropped);
+		sender
+			.send(Ok(b"answer".to_vec()))
+			.map_err(|_| "Expected the handle to be alive")?;
+
+		assert!(dropped_sender.is_canceled());
+		assert_eq!(handle.response().await.map_err(|e| e.to_string())?, b"answer");
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
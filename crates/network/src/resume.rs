@@ -0,0 +1,111 @@
+use std::time::{Duration, SystemTime};
+
+use tokio::time::Instant;
+
+/// How the node notices it comes back from a suspend or a change of its network interfaces, at
+/// which point it listens, redials, registers and subscribes again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResumeConfig {
+	pub enabled: bool,
+	/// How often the clocks are compared to detect a suspend.
+	pub check_interval: Duration,
+	/// Shortest suspend the node resumes from, shorter pauses are left to the reconnections.
+	pub suspend_threshold: Duration,
+}
+
+impl Default for ResumeConfig {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			check_interval: Duration::from_secs(5),
+			suspend_threshold: Duration::from_secs(30),
+		}
+	}
+}
+
+/// Detects the suspends of the host, during which the wall clock keeps going while the monotonic
+/// one stops, and the listen addresses coming back after some went away.
+#[derive(Debug)]
+pub(crate) struct NetworkMonitor {
+	config: ResumeConfig,
+	last_check: Option<(SystemTime, Instant)>,
+	/// Listen addresses expired since the last resume.
+	expired_addresses: usize,
+}
+
+impl NetworkMonitor {
+	pub(crate) fn new(config: ResumeConfig) -> Self {
+		Self { config, last_check: None, expired_addresses: 0 }
+	}
+
+	pub(crate) fn check_interval(&self) -> Duration {
+		self.config.check_interval
+	}
+
+	pub(crate) fn is_enabled(&self) -> bool {
+		self.config.enabled
+	}
+
+	/// How long the host was suspended since the last check, once above the threshold.
+	pub(crate) fn check(&mut self, wall: SystemTime, now: Instant) -> Option<Duration> {
+		let (last_wall, last_now) = self.last_check.replace((wall, now))?;
+		let suspended = wall
+			.duration_since(last_wall)
+			.unwrap_or_default()
+			.saturating_sub(now.duration_since(last_now));
+		(self.config.enabled && suspended >= self.config.suspend_threshold).then(|| {
+			self.expired_addresses = 0;
+			suspended
+		})
+	}
+
+	pub(crate) fn listen_address_expired(&mut self) {
+		self.expired_addresses += 1;
+	}
+
+	/// Whether a new listen address replaces expired ones, i.e. the network interfaces changed.
+	pub(crate) fn listen_address_added(&mut self) -> bool {
+		let changed = self.config.enabled && self.expired_addresses > 0;
+		if changed {
+			self.expired_addresses = 0;
+		}
+		changed
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_suspend_detected_from_the_clocks() {
+		let mut monitor = NetworkMonitor::new(ResumeConfig::default());
+		let (wall, now) = (SystemTime::now(), Instant::now());
+		let interval = Duration::from_secs(5);
+
+		assert_eq!(monitor.check(wall, now), None);
+		assert_eq!(monitor.check(wall + interval, now + interval), None);
+		// A late check is not a suspend, both clocks moved on.
+		assert_eq!(monitor.check(wall + 20 * interval, now + 20 * interval), None);
+		assert_eq!(monitor.check(wall + 60 * interval, now + 21 * interval), Some(39 * interval));
+	}
+
+	#[test]
+	fn test_interface_change_needs_an_expired_address() {
+		let mut monitor = NetworkMonitor::new(ResumeConfig::default());
+		let mut disabled =
+			NetworkMonitor::new(ResumeConfig { enabled: false, ..ResumeConfig::default() });
+
+		assert!(!monitor.listen_address_added());
+		monitor.listen_address_expired();
+		disabled.listen_address_expired();
+
+		assert!(monitor.listen_address_added());
+		assert!(!monitor.listen_address_added());
+		assert!(!disabled.listen_address_added());
+	}
+}
+
+// endregion: --- Tests
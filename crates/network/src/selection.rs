@@ -0,0 +1,605 @@
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+	error::Error,
+	path::{Path, PathBuf},
+	str::FromStr,
+	time::Duration,
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+use crate::{
+	labels::{LabelSelector, Labels},
+	persist::atomic_write,
+	types::{BidResponse, TaskProposal},
+};
+
+/// A peer providing an agent, as seen by a requester choosing whom to call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderCandidate {
+	pub peer_id: PeerId,
+	pub latency: Duration,
+	pub price: f64,
+	pub score: f64,
+	/// Labels the application attached to the peer, see [`crate::Client::peer_labels`].
+	pub labels: Labels,
+	/// Languages the provider advertises, any language when empty.
+	pub languages: Vec<String>,
+}
+
+impl ProviderCandidate {
+	fn is_eligible(&self, blocked: &HashSet<PeerId>, budget: f64) -> bool {
+		!blocked.contains(&self.peer_id)
+			&& self.price.is_finite()
+			&& self.price >= 0.0
+			&& self.price <= budget
+			&& !self.score.is_nan()
+	}
+
+	/// Higher score first, then the cheaper, then the faster provider.
+	fn rank(&self, other: &Self) -> Ordering {
+		self.score
+			.total_cmp(&other.score)
+			.then_with(|| other.price.total_cmp(&self.price))
+			.then_with(|| other.latency.cmp(&self.latency))
+	}
+}
+
+/// The providers that are not blocked and fit in the budget, best first. Ties keep the order of
+/// `candidates`.
+pub fn rank_providers<'a>(
+	candidates: &'a [ProviderCandidate],
+	blocked: &HashSet<PeerId>,
+	budget: f64,
+) -> Vec<&'a ProviderCandidate> {
+	let mut ranked: Vec<_> = candidates.iter().filter(|c| c.is_eligible(blocked, budget)).collect();
+	ranked.sort_by(|a, b| b.rank(a));
+	ranked
+}
+
+/// Pick the best provider that is not blocked and fits in the budget.
+pub fn select_provider<'a>(
+	candidates: &'a [ProviderCandidate],
+	blocked: &HashSet<PeerId>,
+	budget: f64,
+) -> Option<&'a ProviderCandidate> {
+	rank_providers(candidates, blocked, budget).into_iter().next()
+}
+
+/// The candidates whose labels match `selector`, to select a provider within a group of the fleet.
+pub fn with_labels(
+	candidates: &[ProviderCandidate],
+	selector: &LabelSelector,
+) -> Vec<ProviderCandidate> {
+	candidates.iter().filter(|c| selector.matches(&c.labels)).cloned().collect()
+}
+
+/// The candidates advertising `language`, or all of them when none does, so a request in a
+/// language no provider advertises still finds one.
+pub fn prefer_language(candidates: &[ProviderCandidate], language: &str) -> Vec<ProviderCandidate> {
+	let speaking: Vec<_> = candidates
+		.iter()
+		.filter(|c| c.languages.iter().any(|spoken| spoken.eq_ignore_ascii_case(language)))
+		.cloned()
+		.collect();
+	if speaking.is_empty() {
+		candidates.to_vec()
+	} else {
+		speaking
+	}
+}
+
+/// When to stop calling a provider that keeps failing, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+	/// Consecutive failures opening the circuit of a provider.
+	pub failure_threshold: u32,
+	/// How long an open circuit skips its provider before letting a probe request through.
+	pub cooldown: Duration,
+	/// How long the outcome of a probe is awaited. A probe never answered, e.g. cancelled once
+	/// another provider answered, counts as failed and opens the circuit again.
+	pub probe_timeout: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+	fn default() -> Self {
+		Self {
+			failure_threshold: 3,
+			cooldown: Duration::from_secs(30),
+			probe_timeout: Duration::from_secs(60),
+		}
+	}
+}
+
+/// Parses comma-separated settings, the durations in seconds, e.g.
+/// `failures=3,cooldown=30,probe=60`. Settings left out keep their default.
+impl FromStr for CircuitBreakerConfig {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let seconds = |value: &str| {
+			value
+				.parse()
+				.map(Duration::from_secs)
+				.map_err(|e| format!("Invalid seconds {value}: {e}"))
+		};
+		s.split(',').filter(|setting| !setting.trim().is_empty()).try_fold(
+			Self::default(),
+			|mut config, setting| {
+				match setting.trim().split_once('=') {
+					Some(("failures", value)) => {
+						config.failure_threshold =
+							value.parse().map_err(|e| format!("Invalid failures {value}: {e}"))?;
+					},
+					Some(("cooldown", value)) => config.cooldown = seconds(value)?,
+					Some(("probe", value)) => config.probe_timeout = seconds(value)?,
+					_ => {
+						return Err(format!(
+							"Invalid setting {setting}, expected failures=, cooldown= or probe="
+						))
+					},
+				}
+				Ok(config)
+			},
+		)
+	}
+}
+
+/// The state of the circuit of a provider, with unix timestamps in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Circuit {
+	Closed {
+		failures: u32,
+	},
+	Open {
+		until: u64,
+	},
+	/// The cooldown elapsed and a single probe request is in flight until `until`.
+	HalfOpen {
+		until: u64,
+	},
+}
+
+/// Tracks the consecutive failures of each provider so that a known-bad peer is skipped for a
+/// cooldown instead of having every request time out against it. Once the cooldown elapsed, a
+/// single probe is let through: its success closes the circuit, its failure or its timeout opens
+/// it again. Times are unix timestamps in milliseconds, for the circuits to be kept in a store
+/// across restarts, written by [`CircuitBreaker::flush`] rather than on every change.
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+	config: CircuitBreakerConfig,
+	circuits: HashMap<PeerId, Circuit>,
+	store: Option<PathBuf>,
+	/// Whether the circuits changed since they were last written to the store.
+	dirty: bool,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct StoredCircuits(#[serde_as(as = "HashMap<DisplayFromStr, _>")] HashMap<PeerId, Circuit>);
+
+impl CircuitBreaker {
+	pub fn new(config: CircuitBreakerConfig) -> Self {
+		Self { config, ..Default::default() }
+	}
+
+	/// A breaker keeping its circuits in `store`, starting from the ones already there. Circuits
+	/// only live in memory when unset.
+	pub fn open(config: CircuitBreakerConfig, store: Option<PathBuf>) -> Self {
+		let circuits = match store.as_deref().filter(|path| path.exists()).map(load) {
+			Some(Ok(circuits)) => circuits,
+			Some(Err(e)) => {
+				tracing::error!("Failed to load the circuit store: {e}");
+				HashMap::new()
+			},
+			None => HashMap::new(),
+		};
+		Self { config, circuits, store, dirty: false }
+	}
+
+	/// Whether requests to `peer` are currently skipped.
+	pub fn is_open(&self, peer: &PeerId, now: u64) -> bool {
+		let cooldown = self.config.cooldown.as_millis() as u64;
+		match self.circuits.get(peer) {
+			Some(Circuit::Open { until }) => now < *until,
+			// A probe that timed out failed.
+			Some(Circuit::HalfOpen { until }) => now < until + cooldown,
+			Some(Circuit::Closed { .. }) | None => false,
+		}
+	}
+
+	/// Whether a request may be sent to `peer`, starting its probe when its cooldown elapsed.
+	pub fn admit(&mut self, peer: PeerId, now: u64) -> bool {
+		if self.is_open(&peer, now) {
+			return false;
+		}
+		let probe = Circuit::HalfOpen { until: now + self.config.probe_timeout.as_millis() as u64 };
+		match self.circuits.get_mut(&peer) {
+			Some(circuit @ (Circuit::Open { .. } | Circuit::HalfOpen { .. })) => {
+				*circuit = probe;
+				self.dirty = true;
+			},
+			Some(Circuit::Closed { .. }) | None => {},
+		}
+		true
+	}
+
+	/// Pick the best provider like [`select_provider`], skipping the ones whose circuit is open.
+	/// Selecting a provider whose cooldown elapsed starts its probe.
+	pub fn select<'a>(
+		&mut self,
+		candidates: &'a [ProviderCandidate],
+		blocked: &HashSet<PeerId>,
+		budget: f64,
+		now: u64,
+	) -> Option<&'a ProviderCandidate> {
+		let mut skipped = blocked.clone();
+		skipped.extend(candidates.iter().map(|c| c.peer_id).filter(|p| self.is_open(p, now)));
+
+		let selected = select_provider(candidates, &skipped, budget)?;
+		self.admit(selected.peer_id, now);
+		Some(selected)
+	}
+
+	pub fn record_success(&mut self, peer: &PeerId) {
+		if self.circuits.remove(peer).is_some() {
+			self.dirty = true;
+		}
+	}
+
+	pub fn record_failure(&mut self, peer: PeerId, now: u64) {
+		let open = Circuit::Open { until: now + self.config.cooldown.as_millis() as u64 };
+		let circuit = self.circuits.entry(peer).or_insert(Circuit::Closed { failures: 0 });
+		*circuit = match *circuit {
+			Circuit::Closed { failures } if failures + 1 < self.config.failure_threshold => {
+				Circuit::Closed { failures: failures + 1 }
+			},
+			_ => open,
+		};
+		self.dirty = true;
+	}
+
+	/// Write the circuits to the store if they changed since they were last written.
+	pub fn flush(&mut self) {
+		let Some(store) = self.store.as_ref().filter(|_| self.dirty) else {
+			return;
+		};
+		match save(store, &self.circuits) {
+			Ok(()) => self.dirty = false,
+			Err(e) => tracing::error!("Failed to write the circuit store: {e}"),
+		}
+	}
+}
+
+fn load(store: &Path) -> Result<HashMap<PeerId, Circuit>, Box<dyn Error>> {
+	let StoredCircuits(circuits) = serde_json::from_str(&std::fs::read_to_string(store)?)?;
+	Ok(circuits)
+}
+
+/// Replace the store with the circuits at once, for a crash not to leave a truncated store
+/// behind.
+fn save(store: &Path, circuits: &HashMap<PeerId, Circuit>) -> Result<(), Box<dyn Error>> {
+	atomic_write(store, &serde_json::to_vec_pretty(&StoredCircuits(circuits.clone()))?)?;
+	Ok(())
+}
+
+/// Whether `bid` answers `proposal` within its budget, before its deadline. `now` and the deadline
+/// are both unix timestamps in seconds.
+pub fn accepts_bid(proposal: &TaskProposal, bid: &BidResponse, now: u64) -> bool {
+	bid.task_id == proposal.task_id
+		&& bid.bid.is_finite()
+		&& bid.bid >= 0.0
+		&& bid.bid <= proposal.max_bid
+		&& now < proposal.deadline
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::types::TaskType;
+	use proptest::prelude::*;
+
+	fn peer_ids() -> impl Strategy<Value = PeerId> {
+		any::<[u8; 32]>().prop_map(|mut bytes| {
+			// Ed25519 secret keys are any 32 bytes, so every array maps to a valid peer.
+			let key = libp2p::identity::Keypair::ed25519_from_bytes(&mut bytes).unwrap();
+			key.public().to_peer_id()
+		})
+	}
+
+	fn prices() -> impl Strategy<Value = f64> {
+		prop_oneof![
+			8 => 0.0..1_000.0f64,
+			1 => Just(f64::NAN),
+			1 => Just(f64::INFINITY),
+			1 => -10.0..0.0f64,
+		]
+	}
+
+	fn candidates() -> impl Strategy<Value = Vec<ProviderCandidate>> {
+		prop::collection::vec(
+			(peer_ids(), 0u64..5_000, prices(), -1.0..1.0f64).prop_map(
+				|(peer_id, latency_ms, price, score)| ProviderCandidate {
+					peer_id,
+					latency: Duration::from_millis(latency_ms),
+					price,
+					score,
+					labels: Labels::new(),
+					languages: Vec::new(),
+				},
+			),
+			0..16,
+		)
+	}
+
+	fn proposal() -> impl Strategy<Value = TaskProposal> {
+		("[a-c]", 0.0..1_000.0f64, 0u64..1_000).prop_map(|(task_id, max_bid, deadline)| {
+			TaskProposal {
+				agent_name: "agent".to_string(),
+				task_id,
+				task_type: TaskType::DataProcessing,
+				task_message: "task".to_string(),
+				max_bid,
+				deadline,
+				requirements: Default::default(),
+			}
+		})
+	}
+
+	fn bids() -> impl Strategy<Value = Vec<BidResponse>> {
+		prop::collection::vec(
+			("[a-c]", prices()).prop_map(|(task_id, bid)| BidResponse {
+				task_id,
+				capabilities: vec![],
+				bid,
+				payment_address: None,
+			}),
+			0..16,
+		)
+	}
+
+	proptest! {
+		#[test]
+		fn test_selection_never_picks_blocked_peers(
+			candidates in candidates(),
+			blocked_mask in prop::collection::vec(any::<bool>(), 16),
+			budget in 0.0..1_000.0f64,
+		) {
+			let blocked: HashSet<PeerId> = candidates
+				.iter()
+				.zip(&blocked_mask)
+				.filter(|(_, blocked)| **blocked)
+				.map(|(c, _)| c.peer_id)
+				.collect();
+
+			if let Some(selected) = select_provider(&candidates, &blocked, budget) {
+				prop_assert!(!blocked.contains(&selected.peer_id));
+			}
+		}
+
+		#[test]
+		fn test_selection_respects_budget(candidates in candidates(), budget in 0.0..1_000.0f64) {
+			let selected = select_provider(&candidates, &HashSet::new(), budget);
+
+			match selected {
+				Some(selected) => {
+					prop_assert!(selected.price.is_finite());
+					prop_assert!(selected.price >= 0.0 && selected.price <= budget);
+				},
+				None => prop_assert!(candidates
+					.iter()
+					.all(|c| !(c.price.is_finite() && c.price >= 0.0 && c.price <= budget))),
+			}
+		}
+
+		#[test]
+		fn test_selection_picks_the_best_score(candidates in candidates(), budget in 0.0..1_000.0f64) {
+			let blocked = HashSet::new();
+			if let Some(selected) = select_provider(&candidates, &blocked, budget) {
+				let best = candidates
+					.iter()
+					.filter(|c| c.is_eligible(&blocked, budget))
+					.all(|c| c.score <= selected.score);
+				prop_assert!(best);
+			}
+		}
+
+		#[test]
+		fn test_breaker_never_picks_open_circuits(
+			candidates in candidates(),
+			failures in prop::collection::vec(0u32..5, 16),
+			budget in 0.0..1_000.0f64,
+		) {
+			let now = 1_000_000;
+			let mut breaker = CircuitBreaker::default();
+			for (candidate, failures) in candidates.iter().zip(&failures) {
+				for _ in 0..*failures {
+					breaker.record_failure(candidate.peer_id, now);
+				}
+			}
+
+			let open: HashSet<PeerId> =
+				candidates.iter().map(|c| c.peer_id).filter(|p| breaker.is_open(p, now)).collect();
+			let selected = breaker.select(&candidates, &HashSet::new(), budget, now);
+			if let Some(selected) = selected {
+				prop_assert!(!open.contains(&selected.peer_id));
+			}
+			prop_assert_eq!(selected, select_provider(&candidates, &open, budget));
+		}
+
+		#[test]
+		fn test_ranking_orders_the_eligible_providers(
+			candidates in candidates(),
+			budget in 0.0..1_000.0f64,
+		) {
+			let blocked = HashSet::new();
+			let ranked = rank_providers(&candidates, &blocked, budget);
+
+			let eligible = candidates.iter().filter(|c| c.is_eligible(&blocked, budget)).count();
+			prop_assert_eq!(ranked.len(), eligible);
+			for pair in ranked.windows(2) {
+				prop_assert!(pair[0].rank(pair[1]) != Ordering::Less);
+			}
+			prop_assert_eq!(ranked.first().copied(), select_provider(&candidates, &blocked, budget));
+		}
+
+		#[test]
+		fn test_bid_deadline_monotonicity(
+			proposal in proposal(),
+			bids in bids(),
+			now in 0u64..1_000,
+			earlier in 0u64..1_000,
+			extension in 0u64..1_000,
+		) {
+			let earlier = earlier.min(now);
+			for bid in bids.iter().filter(|b| accepts_bid(&proposal, b, now)) {
+				// Accepted now, accepted at any earlier time...
+				prop_assert!(accepts_bid(&proposal, bid, earlier));
+				// ...and still accepted when the deadline is pushed back.
+				let extended = TaskProposal { deadline: proposal.deadline + extension, ..proposal.clone() };
+				prop_assert!(accepts_bid(&extended, bid, now));
+			}
+
+			if now >= proposal.deadline {
+				prop_assert!(bids.iter().all(|bid| !accepts_bid(&proposal, bid, now)));
+			}
+		}
+	}
+
+	fn candidate(peer_id: PeerId, score: f64) -> ProviderCandidate {
+		ProviderCandidate {
+			peer_id,
+			latency: Duration::from_millis(10),
+			price: 1.0,
+			score,
+			labels: Labels::new(),
+			languages: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn test_select_within_labels() {
+		let (cpu, gpu) = (PeerId::random(), PeerId::random());
+		let mut candidates = [candidate(cpu, 1.0), candidate(gpu, 0.5)];
+		candidates[1].labels.insert("gpu".to_string(), "true".to_string());
+
+		let gpus = with_labels(&candidates, &LabelSelector::default().with("gpu", "true"));
+		let selected = select_provider(&gpus, &HashSet::new(), 10.0);
+		assert_eq!(selected.map(|c| c.peer_id), Some(gpu));
+		assert_eq!(with_labels(&candidates, &LabelSelector::default()).len(), 2);
+	}
+
+	#[test]
+	fn test_prefer_language() {
+		let (english, french) = (PeerId::random(), PeerId::random());
+		let mut candidates = [candidate(english, 1.0), candidate(french, 0.5)];
+		candidates[0].languages.push("en".to_string());
+		candidates[1].languages.push("fr".to_string());
+
+		let french_speaking = prefer_language(&candidates, "FR");
+		let selected = select_provider(&french_speaking, &HashSet::new(), 10.0);
+		assert_eq!(selected.map(|c| c.peer_id), Some(french));
+		assert_eq!(prefer_language(&candidates, "de").len(), 2);
+	}
+
+	#[test]
+	fn test_breaker_probes_after_cooldown() {
+		let (bad, good) = (PeerId::random(), PeerId::random());
+		let candidates = [candidate(bad, 1.0), candidate(good, 0.5)];
+		let config = CircuitBreakerConfig {
+			failure_threshold: 2,
+			cooldown: Duration::from_secs(10),
+			..Default::default()
+		};
+		let cooldown = config.cooldown.as_millis() as u64;
+		let mut breaker = CircuitBreaker::new(config);
+		let now = 1_000_000;
+
+		breaker.record_failure(bad, now);
+		assert_eq!(breaker.select(&candidates, &HashSet::new(), 10.0, now).unwrap().peer_id, bad);
+		breaker.record_failure(bad, now);
+		assert_eq!(breaker.select(&candidates, &HashSet::new(), 10.0, now).unwrap().peer_id, good);
+
+		// Half-open: a single probe goes to the bad peer, the next requests go elsewhere.
+		let later = now + cooldown;
+		assert_eq!(breaker.select(&candidates, &HashSet::new(), 10.0, later).unwrap().peer_id, bad);
+		assert_eq!(
+			breaker.select(&candidates, &HashSet::new(), 10.0, later).unwrap().peer_id,
+			good
+		);
+
+		// A failed probe opens the circuit again, a successful one closes it.
+		breaker.record_failure(bad, later);
+		assert!(breaker.is_open(&bad, later + cooldown / 2));
+		let retry = later + cooldown;
+		assert_eq!(breaker.select(&candidates, &HashSet::new(), 10.0, retry).unwrap().peer_id, bad);
+		breaker.record_success(&bad);
+		assert!(!breaker.is_open(&bad, retry));
+	}
+
+	#[test]
+	fn test_breaker_reopens_when_the_probe_times_out() {
+		let bad = PeerId::random();
+		let config = CircuitBreakerConfig {
+			failure_threshold: 1,
+			cooldown: Duration::from_secs(10),
+			probe_timeout: Duration::from_secs(5),
+		};
+		let (cooldown, probe_timeout) = (10_000, 5_000);
+		let mut breaker = CircuitBreaker::new(config);
+		let now = 1_000_000;
+
+		breaker.record_failure(bad, now);
+		assert!(!breaker.admit(bad, now));
+		let probe = now + cooldown;
+		assert!(breaker.admit(bad, probe));
+		assert!(!breaker.admit(bad, probe + 1));
+
+		// The probe was never answered: the circuit is open for another cooldown, then probed again.
+		let timed_out = probe + probe_timeout;
+		assert!(breaker.is_open(&bad, timed_out));
+		assert!(!breaker.admit(bad, timed_out + cooldown - 1));
+		assert!(breaker.admit(bad, timed_out + cooldown));
+	}
+
+	#[test]
+	fn test_breaker_keeps_its_circuits_in_the_store() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = dir.path().join("circuits.json");
+		let config = CircuitBreakerConfig { failure_threshold: 1, ..Default::default() };
+		let (bad, good) = (PeerId::random(), PeerId::random());
+		let now = 1_000_000;
+
+		let mut breaker = CircuitBreaker::open(config, Some(store.clone()));
+		breaker.record_failure(bad, now);
+		breaker.record_failure(good, now);
+		breaker.record_success(&good);
+		assert!(!store.exists(), "Written on flush only");
+		breaker.flush();
+
+		let reopened = CircuitBreaker::open(config, Some(store.clone()));
+		assert!(reopened.is_open(&bad, now));
+		assert!(!reopened.is_open(&good, now));
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_circuit_breaker_config() {
+		let config: CircuitBreakerConfig = "failures=5, probe=20".parse().unwrap();
+		assert_eq!(config.failure_threshold, 5);
+		assert_eq!(config.cooldown, CircuitBreakerConfig::default().cooldown);
+		assert_eq!(config.probe_timeout, Duration::from_secs(20));
+		assert!("failures=many".parse::<CircuitBreakerConfig>().is_err());
+		assert!("timeout=3".parse::<CircuitBreakerConfig>().is_err());
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,52 @@
+use libp2p::{identity, PeerId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::types::{deserialize_message, serialize_message, ProtocolError};
+
+/// A serialized value signed by a node key. The domain is prepended to the signed bytes so a
+/// signature made for one kind of payload cannot be replayed as another.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedPayload {
+	pub(crate) payload: Vec<u8>,
+	public_key: Vec<u8>,
+	signature: Vec<u8>,
+}
+
+#[derive(Error, Debug)]
+pub enum SignatureError {
+	#[error("Malformed payload: {0}")]
+	Protocol(#[from] ProtocolError),
+	#[error("Invalid signer key: {0}")]
+	InvalidKey(#[from] identity::DecodingError),
+	#[error("Failed to sign payload: {0}")]
+	Signing(#[from] identity::SigningError),
+	#[error("Invalid signature")]
+	InvalidSignature,
+}
+
+impl SignedPayload {
+	pub fn sign<T: Serialize>(
+		key: &identity::Keypair,
+		domain: &[u8],
+		value: &T,
+	) -> Result<Self, SignatureError> {
+		let payload = serialize_message(value)?;
+		let signature = key.sign(&[domain, &payload].concat())?;
+
+		Ok(Self { payload, public_key: key.public().encode_protobuf(), signature })
+	}
+
+	/// Check the signature and return the signer along with the value.
+	pub fn verify<T: DeserializeOwned>(
+		&self,
+		domain: &[u8],
+	) -> Result<(PeerId, T), SignatureError> {
+		let public_key = identity::PublicKey::try_decode_protobuf(&self.public_key)?;
+		if !public_key.verify(&[domain, &self.payload].concat(), &self.signature) {
+			return Err(SignatureError::InvalidSignature);
+		}
+
+		Ok((public_key.to_peer_id(), deserialize_message(&self.payload)?))
+	}
+}
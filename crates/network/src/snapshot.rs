@@ -0,0 +1,182 @@
+//! Network snapshots: bootstrap nodes periodically publish a signed [`NetworkSnapshot`] of the
+//! peers they are connected to, the relays among them and a sample of their capability directory,
+//! e.g. to a file served over HTTP. A node starting from a snapshot signed by a key it trusts
+//! dials those peers along with its bootstrap peers and starts with the sample in its directory,
+//! instead of discovering both from scratch.
+
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	time::Duration,
+};
+
+use libp2p::{identity, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use thiserror::Error;
+
+use crate::{
+	agent_card::AgentCard,
+	directory::{CapabilityAnnouncement, ANNOUNCEMENT_VERSION},
+	signed::{SignatureError, SignedPayload},
+	stream::now_ms,
+	types::deserialize_message,
+};
+
+/// Version of the snapshots published. Bumped for the changes older nodes cannot read.
+pub const SNAPSHOT_VERSION: u32 = 1;
+/// Most peers listed in a snapshot, relays included.
+pub const MAX_SNAPSHOT_PEERS: usize = 64;
+/// Most agents of the directory sample of a snapshot.
+pub const MAX_SNAPSHOT_AGENTS: usize = 256;
+
+const SIGNING_DOMAIN: &[u8] = b"asn/network-snapshot";
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+	#[error("Failed to read the network snapshot: {0}")]
+	Io(#[from] io::Error),
+	#[error("Failed to download the network snapshot: {0}")]
+	Http(#[from] reqwest::Error),
+	#[error("Invalid network snapshot: {0}")]
+	Signature(#[from] SignatureError),
+	#[error("Network snapshot signed by untrusted key {0}")]
+	UntrustedSigner(PeerId),
+	#[error("Network snapshot of version {0}, newer than supported")]
+	UnsupportedVersion(u32),
+	#[error("Network snapshot published {0:?} ago, past its maximum age")]
+	Stale(Duration),
+}
+
+/// An agent of the directory sample of a snapshot.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotAgent {
+	#[serde_as(as = "DisplayFromStr")]
+	pub provider: PeerId,
+	pub card: AgentCard,
+	/// Unix timestamp in milliseconds of the announcement listing the agent.
+	pub announced_at: u64,
+}
+
+/// The view of the swarm of a bootstrap node, as of `timestamp`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkSnapshot {
+	pub version: u32,
+	/// Unix timestamp in milliseconds.
+	pub timestamp: u64,
+	/// Addresses of the peers connected, ending with their `/p2p/<peer id>`.
+	pub peers: Vec<Multiaddr>,
+	/// Addresses of the peers among them running a circuit relay.
+	pub relays: Vec<Multiaddr>,
+	#[serde(default)]
+	pub agents: Vec<SnapshotAgent>,
+}
+
+impl NetworkSnapshot {
+	pub fn sign(&self, key: &identity::Keypair) -> Result<SignedPayload, SignatureError> {
+		SignedPayload::sign(key, SIGNING_DOMAIN, self)
+	}
+
+	/// Decode and verify a snapshot signed by one of `signers` at most `max_age` ago.
+	pub fn verify(
+		data: &[u8],
+		signers: &HashSet<PeerId>,
+		max_age: Duration,
+	) -> Result<Self, SnapshotError> {
+		let signed: SignedPayload = deserialize_message(data).map_err(SignatureError::from)?;
+		let (signer, snapshot): (PeerId, Self) = signed.verify(SIGNING_DOMAIN)?;
+		if !signers.contains(&signer) {
+			return Err(SnapshotError::UntrustedSigner(signer));
+		}
+		if snapshot.version > SNAPSHOT_VERSION {
+			return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+		}
+		let age = Duration::from_millis(now_ms().saturating_sub(snapshot.timestamp));
+		if age > max_age {
+			return Err(SnapshotError::Stale(age));
+		}
+		Ok(snapshot)
+	}
+
+	/// The directory sample as the announcement of each provider, for the directory to start from.
+	pub(crate) fn announcements(&self) -> HashMap<PeerId, CapabilityAnnouncement> {
+		let mut announcements = HashMap::<_, CapabilityAnnouncement>::new();
+		for agent in &self.agents {
+			let announcement =
+				announcements.entry(agent.provider).or_insert_with(|| CapabilityAnnouncement {
+					version: ANNOUNCEMENT_VERSION,
+					timestamp: agent.announced_at,
+					agents: Vec::new(),
+				});
+			announcement.timestamp = announcement.timestamp.min(agent.announced_at);
+			announcement.agents.push(agent.card.clone());
+		}
+		announcements
+	}
+}
+
+/// Load and verify the snapshot at `source`, an `http://` or `https://` URL or a file path.
+pub async fn load_snapshot(
+	source: &str,
+	signers: &HashSet<PeerId>,
+	max_age: Duration,
+) -> Result<NetworkSnapshot, SnapshotError> {
+	let data = match source.starts_with("http://") || source.starts_with("https://") {
+		true => reqwest::get(source).await?.error_for_status()?.bytes().await?.to_vec(),
+		false => tokio::fs::read(source).await?,
+	};
+	NetworkSnapshot::verify(&data, signers, max_age)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::types::serialize_message;
+
+	#[test]
+	fn test_snapshot_is_only_trusted_from_its_signers_while_fresh() -> Result<()> {
+		let key = identity::Keypair::generate_ed25519();
+		let signers = HashSet::from([key.public().to_peer_id()]);
+		let provider = PeerId::random();
+		let snapshot = NetworkSnapshot {
+			version: SNAPSHOT_VERSION,
+			timestamp: now_ms(),
+			peers: vec![format!("/ip4/10.0.0.1/tcp/4001/p2p/{provider}").parse()?],
+			relays: Vec::new(),
+			agents: ["writer", "painter"]
+				.map(|name| SnapshotAgent {
+					provider,
+					card: AgentCard::new(name, "0.1.0"),
+					announced_at: 1,
+				})
+				.to_vec(),
+		};
+		let data = serialize_message(&snapshot.sign(&key)?)?;
+		let day = Duration::from_secs(24 * 60 * 60);
+
+		assert_eq!(NetworkSnapshot::verify(&data, &signers, day)?, snapshot);
+		assert!(matches!(
+			NetworkSnapshot::verify(&data, &HashSet::from([PeerId::random()]), day),
+			Err(SnapshotError::UntrustedSigner(_))
+		));
+		let old = NetworkSnapshot { timestamp: now_ms() - 2 * 1000 * day.as_secs(), ..snapshot };
+		let data = serialize_message(&old.sign(&key)?)?;
+		assert!(matches!(
+			NetworkSnapshot::verify(&data, &signers, day),
+			Err(SnapshotError::Stale(_))
+		));
+
+		let announcements = old.announcements();
+		assert_eq!(announcements.len(), 1);
+		assert_eq!(announcements[&provider].agents.len(), 2);
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,297 @@
+use std::{
+	error::Error,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+	sync::mpsc::{self, error::TryRecvError},
+	time::Instant,
+};
+
+use crate::{client::Client, signed::SignedPayload, wire::tolerant_enum};
+
+/// Longest an acknowledgement waits for new tokens before the provider answers with an empty
+/// batch, keeping each exchange well within the request-response timeout.
+const MAX_ACK_WAIT: Duration = Duration::from_secs(5);
+
+/// Streamed responses are pulled: the requester opens a stream, then acknowledges each batch of
+/// tokens to ask for the next one. The provider buffers at most `window` tokens ahead of the last
+/// acknowledgement, so token generation pauses while the requester is not consuming.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub enum StreamRequest {
+	Open {
+		agent_name: String,
+		message: String,
+		window: u32,
+		/// Unix timestamp in milliseconds after which the provider stops generating.
+		deadline: u64,
+	},
+	Ack {
+		stream_id: u64,
+	},
+	/// Ask for a nonce to sign, for the provider to accept the requests of expensive agents.
+	Challenge,
+	Authenticate {
+		answer: SignedPayload,
+	},
+	/// A request introduced by a newer node, answered with an error.
+	#[serde(skip)]
+	Unknown(String, Vec<u8>),
+}
+
+tolerant_enum!(StreamRequest);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub enum StreamResponse {
+	Opened {
+		stream_id: u64,
+	},
+	Tokens {
+		tokens: Vec<String>,
+	},
+	/// Last batch of the stream. `truncated` is set when the deadline stopped the generation, the
+	/// tokens delivered until then are still counted in the usage.
+	Done {
+		tokens: Vec<String>,
+		truncated: bool,
+		usage: StreamUsage,
+	},
+	Error(String),
+	/// The provider serves its limit of concurrent requests and streams, the requester should
+	/// fail over to another provider.
+	Busy {
+		limit: usize,
+	},
+	/// Nonce the requester signs with [`crate::challenge::answer`] before opening the stream
+	/// again. Also the answer to an opening request of an unverified requester when the agent
+	/// requires it.
+	Challenge {
+		nonce: Vec<u8>,
+	},
+	Authenticated,
+	/// A response introduced by a newer node.
+	#[serde(skip)]
+	Unknown(String, Vec<u8>),
+}
+
+tolerant_enum!(StreamResponse);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamUsage {
+	/// Tokens delivered to the requester.
+	pub tokens: u64,
+}
+
+/// A whole streamed answer, possibly cut short by its deadline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamedAnswer {
+	pub output: String,
+	pub truncated: bool,
+	pub usage: StreamUsage,
+}
+
+#[derive(Error, Debug)]
+pub enum StreamError {
+	#[error("Stream rejected by the provider: {0}")]
+	Rejected(String),
+	#[error("Unexpected stream response: {0:?}")]
+	UnexpectedResponse(StreamResponse),
+	#[error("Stream deadline exceeded")]
+	DeadlineExceeded,
+}
+
+/// Provider side of an open stream. Dropping it stops the generation, as the producer can no
+/// longer send its tokens.
+#[derive(Debug)]
+pub(crate) struct ProviderStream {
+	tokens: mpsc::Receiver<String>,
+	window: usize,
+	deadline: Instant,
+	delivered: u64,
+}
+
+impl ProviderStream {
+	/// Create a stream expiring at `deadline` (Unix milliseconds), along with the sender the
+	/// tokens are produced into.
+	pub(crate) fn new(window: u32, deadline: u64) -> (Self, mpsc::Sender<String>) {
+		let window = window.max(1) as usize;
+		let (sender, tokens) = mpsc::channel(window);
+		let deadline = Instant::now() + Duration::from_millis(deadline.saturating_sub(now_ms()));
+		(Self { tokens, window, deadline, delivered: 0 }, sender)
+	}
+
+	pub(crate) fn is_expired(&self) -> bool {
+		Instant::now() >= self.deadline
+	}
+
+	/// Wait for the next batch of at most `window` tokens. The stream is handed back unless it
+	/// is finished, either by the producer or by the deadline.
+	pub(crate) async fn next_tokens(mut self) -> (Option<Self>, StreamResponse) {
+		let wait_until = self.deadline.min(Instant::now() + MAX_ACK_WAIT);
+		let first = match tokio::time::timeout_at(wait_until, self.tokens.recv()).await {
+			Ok(Some(token)) => token,
+			Ok(None) => return (None, self.done(Vec::new(), false)),
+			Err(_) if self.is_expired() => return (None, self.done(Vec::new(), true)),
+			Err(_) => return (Some(self), StreamResponse::Tokens { tokens: Vec::new() }),
+		};
+
+		let mut tokens = vec![first];
+		while tokens.len() < self.window {
+			match self.tokens.try_recv() {
+				Ok(token) => tokens.push(token),
+				Err(TryRecvError::Empty) => break,
+				Err(TryRecvError::Disconnected) => return (None, self.done(tokens, false)),
+			}
+		}
+
+		if self.is_expired() {
+			return (None, self.done(tokens, true));
+		}
+		self.delivered += tokens.len() as u64;
+		(Some(self), StreamResponse::Tokens { tokens })
+	}
+
+	fn done(&self, tokens: Vec<String>, truncated: bool) -> StreamResponse {
+		let usage = StreamUsage { tokens: self.delivered + tokens.len() as u64 };
+		StreamResponse::Done { tokens, truncated, usage }
+	}
+}
+
+/// Requester side of a stream opened with [`Client::request_agent_stream`]. Tokens are only
+/// requested from the provider when [`AgentStream::next_tokens`] is called.
+pub struct AgentStream {
+	client: Client,
+	peer: PeerId,
+	stream_id: u64,
+	deadline: Instant,
+	/// Whether the answer was truncated and its usage, once the provider finished.
+	done: Option<(bool, StreamUsage)>,
+}
+
+impl AgentStream {
+	pub(crate) fn new(client: Client, peer: PeerId, stream_id: u64, timeout: Duration) -> Self {
+		Self { client, peer, stream_id, deadline: Instant::now() + timeout, done: None }
+	}
+
+	/// Acknowledge the tokens received so far and wait for the next batch. Returns `None` once
+	/// the provider finished.
+	pub async fn next_tokens(&mut self) -> Result<Option<Vec<String>>, Box<dyn Error + Send>> {
+		while self.done.is_none() {
+			// The provider ends the stream at the deadline, only give up when it did not.
+			if Instant::now() >= self.deadline + MAX_ACK_WAIT {
+				return Err(Box::new(StreamError::DeadlineExceeded));
+			}
+
+			let request = StreamRequest::Ack { stream_id: self.stream_id };
+			let tokens = match self.client.stream_request(self.peer, request).await? {
+				StreamResponse::Tokens { tokens } => tokens,
+				StreamResponse::Done { tokens, truncated, usage } => {
+					self.done = Some((truncated, usage));
+					tokens
+				},
+				StreamResponse::Error(e) => return Err(Box::new(StreamError::Rejected(e))),
+				response => return Err(Box::new(StreamError::UnexpectedResponse(response))),
+			};
+			if !tokens.is_empty() {
+				return Ok(Some(tokens));
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Whether the deadline cut the answer short. Only known once the stream finished.
+	pub fn is_truncated(&self) -> bool {
+		self.done.is_some_and(|(truncated, _)| truncated)
+	}
+
+	/// Tokens delivered by the provider. Only known once the stream finished.
+	pub fn usage(&self) -> Option<StreamUsage> {
+		self.done.map(|(_, usage)| usage)
+	}
+
+	/// Consume the whole stream and concatenate its tokens.
+	pub async fn collect(mut self) -> Result<StreamedAnswer, Box<dyn Error + Send>> {
+		let mut output = String::new();
+		while let Some(tokens) = self.next_tokens().await? {
+			output.extend(tokens);
+		}
+		Ok(StreamedAnswer {
+			output,
+			truncated: self.is_truncated(),
+			usage: self.usage().unwrap_or_default(),
+		})
+	}
+}
+
+/// Unix timestamp in milliseconds, as used for stream deadlines.
+pub fn now_ms() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn deadline_in(duration: Duration) -> u64 {
+		now_ms() + duration.as_millis() as u64
+	}
+
+	#[tokio::test]
+	async fn test_stream_batches_at_most_one_window() {
+		let (stream, sender) = ProviderStream::new(2, deadline_in(Duration::from_secs(5)));
+		for token in ["a", "b"] {
+			sender.send(token.to_string()).await.unwrap();
+		}
+
+		// The window is full: the producer is paused until the requester acknowledges.
+		assert!(sender.try_send("c".to_string()).is_err());
+
+		let (stream, response) = stream.next_tokens().await;
+		assert_eq!(response, StreamResponse::Tokens { tokens: vec!["a".into(), "b".into()] });
+		sender.send("c".to_string()).await.unwrap();
+		drop(sender);
+
+		let (stream, response) = stream.unwrap().next_tokens().await;
+		assert_eq!(
+			response,
+			StreamResponse::Done {
+				tokens: vec!["c".into()],
+				truncated: false,
+				usage: StreamUsage { tokens: 3 },
+			}
+		);
+		assert!(stream.is_none());
+	}
+
+	#[tokio::test]
+	async fn test_stream_truncated_at_deadline() {
+		let (stream, sender) = ProviderStream::new(4, deadline_in(Duration::from_millis(50)));
+		sender.send("partial".to_string()).await.unwrap();
+
+		let (stream, response) = stream.next_tokens().await;
+		assert_eq!(response, StreamResponse::Tokens { tokens: vec!["partial".into()] });
+
+		let (stream, response) = stream.unwrap().next_tokens().await;
+		assert!(stream.is_none());
+		assert_eq!(
+			response,
+			StreamResponse::Done {
+				tokens: Vec::new(),
+				truncated: true,
+				usage: StreamUsage { tokens: 1 },
+			}
+		);
+		// The generation stops with the stream.
+		assert!(sender.send("late".to_string()).await.is_err());
+	}
+}
+
+// endregion: --- Tests
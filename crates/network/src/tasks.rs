@@ -0,0 +1,359 @@
+//! Lifecycle of the tasks auctioned or won by the node. The owner of a task, the node that
+//! proposed it, tracks its status as its worker reports progress, see
+//! [`crate::Client::update_task_status`] and [`crate::Client::get_task_status`].
+
+use std::{
+	collections::HashMap,
+	error::Error,
+	path::{Path, PathBuf},
+};
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use thiserror::Error;
+
+use crate::{persist::atomic_write, signed::SignedPayload};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TaskStatus {
+	/// The proposal was gossiped, bids are collected.
+	Proposed,
+	/// A worker won the task.
+	Assigned,
+	InProgress,
+	Completed,
+	Failed {
+		reason: String,
+	},
+	/// The deadline passed before the task completed.
+	TimedOut,
+}
+
+impl TaskStatus {
+	pub fn is_terminal(&self) -> bool {
+		matches!(self, TaskStatus::Completed | TaskStatus::Failed { .. } | TaskStatus::TimedOut)
+	}
+
+	/// Whether a task may go from this status to `next`. Unfinished tasks may fail or time out at
+	/// any point, and an assigned task may complete without reporting its progress.
+	pub fn can_become(&self, next: &TaskStatus) -> bool {
+		match (self, next) {
+			(TaskStatus::Proposed, TaskStatus::Assigned)
+			| (TaskStatus::Assigned, TaskStatus::InProgress)
+			| (TaskStatus::Assigned | TaskStatus::InProgress, TaskStatus::Completed) => true,
+			(status, TaskStatus::Failed { .. } | TaskStatus::TimedOut) => !status.is_terminal(),
+			_ => false,
+		}
+	}
+}
+
+/// A task tracked by the node, as its owner or its worker.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskRecord {
+	pub task_id: String,
+	/// Node that proposed the task and tracks its progress.
+	#[serde_as(as = "DisplayFromStr")]
+	pub owner: PeerId,
+	/// Node the task was assigned to.
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub worker: Option<PeerId>,
+	pub status: TaskStatus,
+	/// Budget of the task, the most its owner pays for it.
+	pub max_bid: f64,
+	/// Address the worker is paid at, from its bid.
+	pub payment_address: Option<String>,
+	/// Unix timestamp, in seconds, past which an unfinished task times out.
+	pub deadline: u64,
+	/// Unix timestamp of the last status change, in seconds.
+	pub updated_at: u64,
+	/// Signed [`crate::commitment::ResultCommitment`] of the worker, kept by the owner as the
+	/// evidence of a dispute.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub commitment: Option<SignedPayload>,
+}
+
+/// Status update sent by the worker of a task to its owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskStatusUpdate {
+	pub task_id: String,
+	pub status: TaskStatus,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TaskError {
+	#[error("Unknown task {0}")]
+	Unknown(String),
+	#[error("Task {task_id} cannot go from {from:?} to {to:?}")]
+	InvalidTransition { task_id: String, from: TaskStatus, to: TaskStatus },
+	#[error("The worker of task {0} already committed to its result")]
+	AlreadyCommitted(String),
+}
+
+/// The tasks of the node by id, written to the task store on every change when one is set so
+/// that a restart does not lose the tasks in flight.
+#[derive(Debug, Default)]
+pub(crate) struct TaskTracker {
+	tasks: HashMap<String, TaskRecord>,
+	store: Option<PathBuf>,
+}
+
+impl TaskTracker {
+	/// A tracker starting with the tasks of the store, if any.
+	pub(crate) fn new(store: Option<PathBuf>) -> Self {
+		let tasks = match store.as_deref().filter(|path| path.exists()).map(load) {
+			Some(Ok(tasks)) => tasks,
+			Some(Err(e)) => {
+				tracing::error!("Failed to load the task store: {e}");
+				Vec::new()
+			},
+			None => Vec::new(),
+		};
+		let tasks = tasks.into_iter().map(|task| (task.task_id.clone(), task)).collect();
+		Self { tasks, store }
+	}
+
+	pub(crate) fn get(&self, task_id: &str) -> Option<&TaskRecord> {
+		self.tasks.get(task_id)
+	}
+
+	pub(crate) fn insert(&mut self, task: TaskRecord) {
+		self.tasks.insert(task.task_id.clone(), task);
+		self.persist();
+	}
+
+	/// Assign an auctioned task to the winner of its auction, paid at `payment_address`.
+	pub(crate) fn assign(
+		&mut self,
+		task_id: &str,
+		worker: PeerId,
+		payment_address: Option<String>,
+		now: u64,
+	) -> Result<TaskRecord, TaskError> {
+		self.apply(task_id, TaskStatus::Assigned, Some((worker, payment_address)), now)
+	}
+
+	/// Hand an unfinished task whose worker defaulted to another worker, due by `deadline`. The
+	/// commitment of the previous worker is dropped.
+	pub(crate) fn reassign(
+		&mut self,
+		task_id: &str,
+		worker: PeerId,
+		payment_address: Option<String>,
+		deadline: u64,
+		now: u64,
+	) -> Result<TaskRecord, TaskError> {
+		let task = self.tasks.get_mut(task_id).ok_or(TaskError::Unknown(task_id.to_string()))?;
+		if task.status.is_terminal() {
+			return Err(TaskError::InvalidTransition {
+				task_id: task_id.to_string(),
+				from: task.status.clone(),
+				to: TaskStatus::Assigned,
+			});
+		}
+		task.status = TaskStatus::Assigned;
+		task.worker = Some(worker);
+		task.payment_address = payment_address;
+		task.deadline = deadline;
+		task.commitment = None;
+		task.updated_at = now;
+		let task = task.clone();
+		self.persist();
+		Ok(task)
+	}
+
+	/// Move a task to `status`, at the unix timestamp `now` in seconds.
+	pub(crate) fn transition(
+		&mut self,
+		task_id: &str,
+		status: TaskStatus,
+		now: u64,
+	) -> Result<TaskRecord, TaskError> {
+		self.apply(task_id, status, None, now)
+	}
+
+	fn apply(
+		&mut self,
+		task_id: &str,
+		status: TaskStatus,
+		worker: Option<(PeerId, Option<String>)>,
+		now: u64,
+	) -> Result<TaskRecord, TaskError> {
+		let task = self.tasks.get_mut(task_id).ok_or(TaskError::Unknown(task_id.to_string()))?;
+		if !task.status.can_become(&status) {
+			return Err(TaskError::InvalidTransition {
+				task_id: task_id.to_string(),
+				from: task.status.clone(),
+				to: status,
+			});
+		}
+		task.status = status;
+		if let Some((worker, payment_address)) = worker {
+			task.worker = Some(worker);
+			task.payment_address = payment_address;
+		}
+		task.updated_at = now;
+		let task = task.clone();
+		self.persist();
+		Ok(task)
+	}
+
+	/// Record the commitment of the worker of an unfinished task to its result. Commitments are
+	/// final.
+	pub(crate) fn commit(
+		&mut self,
+		task_id: &str,
+		commitment: SignedPayload,
+	) -> Result<TaskRecord, TaskError> {
+		let task = self.tasks.get_mut(task_id).ok_or(TaskError::Unknown(task_id.to_string()))?;
+		if task.commitment.is_some() {
+			return Err(TaskError::AlreadyCommitted(task_id.to_string()));
+		}
+		if task.status.is_terminal() {
+			return Err(TaskError::InvalidTransition {
+				task_id: task_id.to_string(),
+				from: task.status.clone(),
+				to: TaskStatus::Completed,
+			});
+		}
+		task.commitment = Some(commitment);
+		let task = task.clone();
+		self.persist();
+		Ok(task)
+	}
+
+	/// Time out the unfinished tasks whose deadline passed, returning them.
+	pub(crate) fn expire(&mut self, now: u64) -> Vec<TaskRecord> {
+		let mut expired = Vec::new();
+		for task in self.tasks.values_mut() {
+			if !task.status.is_terminal() && task.deadline <= now {
+				task.status = TaskStatus::TimedOut;
+				task.updated_at = now;
+				expired.push(task.clone());
+			}
+		}
+		if !expired.is_empty() {
+			self.persist();
+		}
+		expired
+	}
+
+	fn persist(&self) {
+		let Some(store) = &self.store else {
+			return;
+		};
+		if let Err(e) = save(store, self.tasks.values()) {
+			tracing::error!("Failed to write the task store: {e}");
+		}
+	}
+}
+
+fn load(store: &Path) -> Result<Vec<TaskRecord>, Box<dyn Error>> {
+	Ok(serde_json::from_str(&std::fs::read_to_string(store)?)?)
+}
+
+/// Replace the store with the tasks at once, for a crash not to leave a truncated store behind.
+fn save<'a>(
+	store: &Path,
+	tasks: impl Iterator<Item = &'a TaskRecord>,
+) -> Result<(), Box<dyn Error>> {
+	let tasks: Vec<_> = tasks.collect();
+	atomic_write(store, &serde_json::to_vec_pretty(&tasks)?)?;
+	Ok(())
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	fn task(deadline: u64) -> TaskRecord {
+		TaskRecord {
+			task_id: "task".to_string(),
+			owner: PeerId::random(),
+			worker: Some(PeerId::random()),
+			status: TaskStatus::Proposed,
+			max_bid: 10.0,
+			payment_address: None,
+			deadline,
+			updated_at: 0,
+			commitment: None,
+		}
+	}
+
+	#[test]
+	fn test_task_transitions() -> Result<()> {
+		let mut tracker = TaskTracker::default();
+		tracker.insert(task(100));
+
+		tracker.transition("task", TaskStatus::Assigned, 1)?;
+		let error = tracker.transition("task", TaskStatus::Proposed, 2).unwrap_err();
+		assert!(matches!(error, TaskError::InvalidTransition { .. }));
+		tracker.transition("task", TaskStatus::InProgress, 3)?;
+		let completed = tracker.transition("task", TaskStatus::Completed, 4)?;
+		assert_eq!((completed.status, completed.updated_at), (TaskStatus::Completed, 4));
+
+		// Finished tasks stay finished.
+		let failed = TaskStatus::Failed { reason: "crash".to_string() };
+		assert!(tracker.transition("task", failed, 5).is_err());
+		assert_eq!(tracker.expire(1_000), []);
+		assert_eq!(
+			tracker.transition("other", TaskStatus::Completed, 5),
+			Err(TaskError::Unknown("other".to_string()))
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_unfinished_tasks_time_out() {
+		let mut tracker = TaskTracker::default();
+		tracker.insert(task(100));
+
+		assert_eq!(tracker.expire(99), []);
+		let expired = tracker.expire(100);
+		assert_eq!(expired.len(), 1);
+		assert_eq!(tracker.get("task").map(|task| &task.status), Some(&TaskStatus::TimedOut));
+	}
+
+	#[test]
+	fn test_reassigned_task_gets_a_new_worker_and_deadline() -> Result<()> {
+		let mut tracker = TaskTracker::default();
+		tracker.insert(task(100));
+		tracker.transition("task", TaskStatus::Assigned, 1)?;
+		tracker.transition("task", TaskStatus::InProgress, 2)?;
+
+		let backup = PeerId::random();
+		let task = tracker.reassign("task", backup, Some("0xabc".to_string()), 200, 100)?;
+		assert_eq!(
+			(task.status, task.worker, task.deadline),
+			(TaskStatus::Assigned, Some(backup), 200)
+		);
+		assert_eq!(tracker.expire(199), []);
+		tracker.transition("task", TaskStatus::Completed, 150)?;
+		assert!(tracker.reassign("task", PeerId::random(), None, 300, 160).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_tasks_survive_a_restart() -> Result<()> {
+		let dir = tempfile::tempdir()?;
+		let store = dir.path().join("tasks.json");
+		let mut tracker = TaskTracker::new(Some(store.clone()));
+		let task = task(100);
+		tracker.insert(task.clone());
+		tracker.transition("task", TaskStatus::Assigned, 1)?;
+
+		let restarted = TaskTracker::new(Some(store));
+		let expected = TaskRecord { status: TaskStatus::Assigned, updated_at: 1, ..task };
+		assert_eq!(restarted.get("task"), Some(&expected));
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,171 @@
+//! Networks of nodes running in the test process, wired together over the in-memory transport so
+//! the event loop can be exercised without sockets.
+
+use std::{error::Error, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+	client::Client, config::NetworkConfig, discovery::MdnsConfig, escrow::TaskEscrow,
+	events::EventReceiver, tasks::TaskRecord, types::Event,
+};
+
+/// How long a test waits for an event before failing.
+const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A node of a [`TestNetwork`], along with the events of its event loop.
+pub(crate) struct TestNode {
+	pub(crate) client: Client,
+	pub(crate) events: EventReceiver,
+	pub(crate) peer_id: PeerId,
+	pub(crate) address: Multiaddr,
+}
+
+impl TestNode {
+	/// Wait for the first event matching `f`, skipping the others.
+	pub(crate) async fn event<T>(
+		&mut self,
+		mut f: impl FnMut(Event) -> Option<T>,
+	) -> Result<T, Box<dyn Error>> {
+		let wait = async {
+			while let Some(event) = self.events.next().await {
+				if let Some(found) = f(event) {
+					return Ok(found);
+				}
+			}
+			Err("Event loop stopped".into())
+		};
+		tokio::time::timeout(EVENT_TIMEOUT, wait)
+			.await
+			.map_err(|_| "No matching event")?
+	}
+}
+
+/// In-process nodes, each connected to the first one. Their event loops stop once dropped.
+pub(crate) struct TestNetwork {
+	pub(crate) nodes: Vec<TestNode>,
+	cancellation_token: CancellationToken,
+}
+
+impl TestNetwork {
+	pub(crate) async fn new(size: usize) -> Result<Self, Box<dyn Error>> {
+		Self::with_config(size, NetworkConfig::default).await
+	}
+
+	/// Spin up `size` nodes with the configuration returned by `config`. mDNS is turned off, it
+	/// needs sockets.
+	pub(crate) async fn with_config(
+		size: usize,
+		config: impl Fn() -> NetworkConfig,
+	) -> Result<Self, Box<dyn Error>> {
+		let cancellation_token = CancellationToken::new();
+		let mut nodes: Vec<TestNode> = Vec::with_capacity(size);
+		for _ in 0..size {
+			let config = NetworkConfig {
+				mdns: MdnsConfig { enabled: false, ..Default::default() },
+				..config()
+			};
+			let (mut client, events, peer_id, event_loop) = crate::new_in_memory(config)?;
+			tokio::spawn(event_loop.run(cancellation_token.clone()));
+
+			let address = Multiaddr::empty().with(Protocol::Memory(rand::random()));
+			client.start_listening(address.clone()).await.map_err(|e| e.to_string())?;
+			if let Some(first) = nodes.first() {
+				client
+					.dial(first.peer_id, first.address.clone())
+					.await
+					.map_err(|e| e.to_string())?;
+			}
+			nodes.push(TestNode { client, events, peer_id, address });
+		}
+		Ok(Self { nodes, cancellation_token })
+	}
+}
+
+impl Drop for TestNetwork {
+	fn drop(&mut self) {
+		self.cancellation_token.cancel();
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_agent_request_round_trip() -> Result<()> {
+		let mut network = TestNetwork::new(2).await?;
+		let provider_id = network.nodes[1].peer_id;
+		let [requester, provider] = &mut network.nodes[..] else {
+			return Err("Expected two nodes".into());
+		};
+
+		let mut client = requester.client.clone();
+		let request = tokio::spawn(async move {
+			client.request_agent(provider_id, "echo".to_string(), "hello".to_string()).await
+		});
+		let (message, channel) = provider
+			.event(|event| match event {
+				Event::LLMInboundRequest { agent_name, message, channel, .. } => {
+					(agent_name == "echo").then_some((message, channel))
+				},
+				_ => None,
+			})
+			.await?;
+		provider.client.respond_llm(message.into_bytes(), channel).await;
+
+		let response = request.await?.map_err(|e| e.to_string())?;
+		assert_eq!(response, b"hello");
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_nodes_connect_to_the_first() -> Result<()> {
+		let mut network = TestNetwork::new(3).await?;
+		let peers = network.nodes[0].client.connected_peers().await.map_err(|e| e.to_string())?;
+		assert_eq!(peers.len(), 2);
+		for node in &network.nodes[1..] {
+			assert!(peers.iter().any(|peer| peer.peer_id == node.peer_id));
+		}
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
+
+/// Escrow recording the calls it receives.
+#[derive(Debug, Default)]
+pub(crate) struct RecordingEscrow {
+	pub(crate) calls: Mutex<Vec<String>>,
+}
+
+impl RecordingEscrow {
+	fn call(&self, method: &str, task: &TaskRecord) -> String {
+		let call = format!("{method} {} {}", task.task_id, task.max_bid);
+		self.calls.lock().unwrap().push(call.clone());
+		call
+	}
+}
+
+#[async_trait]
+impl TaskEscrow for RecordingEscrow {
+	async fn lock(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		Ok(self.call("lock", task))
+	}
+
+	async fn release(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		Ok(self.call("release", task))
+	}
+
+	async fn refund(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		Ok(self.call("refund", task))
+	}
+}
@@ -0,0 +1,145 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	time::{Duration, Instant},
+};
+
+use libp2p::{request_response::OutboundRequestId, PeerId};
+
+/// How many of the last responses of a provider its timeout is estimated from.
+const WINDOW: usize = 32;
+/// Responses of a provider needed before its own timeout replaces the default one.
+const MIN_SAMPLES: usize = 5;
+
+/// Timeouts of the agent requests, estimated per provider as the 95th percentile of its last
+/// response times plus a margin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestTimeoutConfig {
+	/// Timeout of the providers without enough history yet.
+	pub default: Duration,
+	pub margin: Duration,
+	pub min: Duration,
+	/// Upper bound of any estimate, also enforced by the request-response protocol.
+	pub max: Duration,
+}
+
+impl Default for RequestTimeoutConfig {
+	fn default() -> Self {
+		Self {
+			default: Duration::from_secs(30),
+			margin: Duration::from_secs(5),
+			min: Duration::from_secs(5),
+			max: Duration::from_secs(120),
+		}
+	}
+}
+
+#[derive(Debug)]
+struct InFlight {
+	peer: PeerId,
+	sent_at: Instant,
+	deadline: Instant,
+}
+
+/// Response times of the providers, and the deadlines of the requests waiting for them.
+#[derive(Debug)]
+pub(crate) struct AdaptiveTimeouts {
+	config: RequestTimeoutConfig,
+	latencies: HashMap<PeerId, VecDeque<Duration>>,
+	in_flight: HashMap<OutboundRequestId, InFlight>,
+}
+
+impl AdaptiveTimeouts {
+	pub(crate) fn new(config: RequestTimeoutConfig) -> Self {
+		Self { config, latencies: HashMap::new(), in_flight: HashMap::new() }
+	}
+
+	/// Timeout of the next request to the peer.
+	pub(crate) fn timeout(&self, peer: &PeerId) -> Duration {
+		let Some(latencies) = self.latencies.get(peer).filter(|l| l.len() >= MIN_SAMPLES) else {
+			return self.config.default;
+		};
+		let mut sorted: Vec<_> = latencies.iter().copied().collect();
+		sorted.sort_unstable();
+		let p95 = sorted[(sorted.len() - 1) * 95 / 100];
+		(p95 + self.config.margin).clamp(self.config.min, self.config.max)
+	}
+
+	pub(crate) fn start(&mut self, request_id: OutboundRequestId, peer: PeerId, now: Instant) {
+		let deadline = now + self.timeout(&peer);
+		self.in_flight.insert(request_id, InFlight { peer, sent_at: now, deadline });
+	}
+
+	/// Record the response time of a request answered by its provider.
+	pub(crate) fn finish(&mut self, request_id: &OutboundRequestId, now: Instant) {
+		if let Some(request) = self.in_flight.remove(request_id) {
+			self.record(request.peer, now - request.sent_at);
+		}
+	}
+
+	/// Stop tracking a request that failed or was cancelled, without recording its time.
+	pub(crate) fn forget(&mut self, request_id: &OutboundRequestId) {
+		self.in_flight.remove(request_id);
+	}
+
+	pub(crate) fn retain(&mut self, mut keep: impl FnMut(&OutboundRequestId) -> bool) {
+		self.in_flight.retain(|request_id, _| keep(request_id));
+	}
+
+	pub(crate) fn clear(&mut self) {
+		self.in_flight.clear();
+	}
+
+	/// Take the requests past their deadline, with their provider and timeout. The timeouts are recorded as
+	/// response times, so a provider slowing down gets longer timeouts.
+	pub(crate) fn expired(&mut self, now: Instant) -> Vec<(OutboundRequestId, PeerId, Duration)> {
+		let expired: Vec<_> = self
+			.in_flight
+			.iter()
+			.filter(|(_, request)| request.deadline <= now)
+			.map(|(request_id, _)| *request_id)
+			.collect();
+		expired
+			.into_iter()
+			.filter_map(|request_id| {
+				let request = self.in_flight.remove(&request_id)?;
+				let timeout = request.deadline - request.sent_at;
+				self.record(request.peer, timeout);
+				Some((request_id, request.peer, timeout))
+			})
+			.collect()
+	}
+
+	fn record(&mut self, peer: PeerId, elapsed: Duration) {
+		let latencies = self.latencies.entry(peer).or_default();
+		if latencies.len() == WINDOW {
+			latencies.pop_front();
+		}
+		latencies.push_back(elapsed);
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_adaptive_timeout() {
+		let config = RequestTimeoutConfig::default();
+		let mut timeouts = AdaptiveTimeouts::new(config);
+		let (fast, unknown) = (PeerId::random(), PeerId::random());
+		for secs in [1, 2, 2, 3, 4] {
+			timeouts.record(fast, Duration::from_secs(secs));
+		}
+
+		assert_eq!(timeouts.timeout(&fast), Duration::from_secs(3) + config.margin);
+		assert_eq!(timeouts.timeout(&unknown), config.default);
+
+		timeouts.record(fast, Duration::from_secs(1000));
+		timeouts.record(fast, Duration::from_secs(1000));
+		assert_eq!(timeouts.timeout(&fast), config.max);
+	}
+}
+
+// endregion: --- Tests
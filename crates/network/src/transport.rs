@@ -0,0 +1,117 @@
+use std::{error::Error, time::Duration};
+
+use libp2p::{
+	identity,
+	metrics::Registry,
+	noise,
+	swarm::{self, Swarm},
+	tcp, tls, yamux,
+};
+
+use crate::{behaviour::AsnBehaviour, config::NetworkConfig, metrics::NetworkMetrics};
+
+/// Build the swarm over every supported transport, or over the pnet-protected ones when the
+/// configuration carries a pre-shared key.
+pub(crate) async fn build_swarm(
+	id_key: identity::Keypair,
+	config: &NetworkConfig,
+	metrics: &NetworkMetrics,
+	registry: &mut Registry,
+) -> Result<Swarm<AsnBehaviour>, Box<dyn Error>> {
+	#[cfg(feature = "pnet")]
+	if let Some(psk) = config.psk {
+		return build_private_swarm(id_key, psk, config, metrics, registry);
+	}
+
+	build_public_swarm(id_key, config, metrics, registry).await
+}
+
+fn swarm_config(config: &NetworkConfig) -> impl FnOnce(swarm::Config) -> swarm::Config {
+	let dial_concurrency_factor = config.connection_limits.dial_concurrency_factor;
+	move |c| {
+		let c = c.with_idle_connection_timeout(Duration::from_secs(60));
+		match dial_concurrency_factor {
+			Some(factor) => c.with_dial_concurrency_factor(factor),
+			None => c,
+		}
+	}
+}
+
+async fn build_public_swarm(
+	id_key: identity::Keypair,
+	config: &NetworkConfig,
+	metrics: &NetworkMetrics,
+	registry: &mut Registry,
+) -> Result<Swarm<AsnBehaviour>, Box<dyn Error>> {
+	Ok(libp2p::SwarmBuilder::with_existing_identity(id_key)
+		.with_tokio()
+		.with_tcp(tcp::Config::default().nodelay(true), noise::Config::new, yamux::Config::default)?
+		.with_quic()
+		.with_dns()?
+		.with_websocket((tls::Config::new, noise::Config::new), yamux::Config::default)
+		.await?
+		.with_bandwidth_metrics(registry)
+		.with_behaviour(|key| AsnBehaviour::new(key, config, &metrics.compression_saved()))?
+		.with_swarm_config(swarm_config(config))
+		.build())
+}
+
+/// Swarm over the in-memory transport, for the nodes of a test to run in one process without
+/// sockets. Its peers listen on and dial `/memory/<port>` addresses.
+#[cfg(test)]
+pub(crate) fn build_memory_swarm(
+	id_key: identity::Keypair,
+	config: &NetworkConfig,
+	metrics: &NetworkMetrics,
+) -> Result<Swarm<AsnBehaviour>, Box<dyn Error>> {
+	use libp2p::{
+		core::{transport::MemoryTransport, upgrade::Version},
+		Transport,
+	};
+
+	Ok(libp2p::SwarmBuilder::with_existing_identity(id_key)
+		.with_tokio()
+		.with_other_transport(|key| -> Result<_, Box<dyn Error + Send + Sync>> {
+			Ok(MemoryTransport::default()
+				.upgrade(Version::V1)
+				.authenticate(noise::Config::new(key)?)
+				.multiplex(yamux::Config::default()))
+		})?
+		.with_behaviour(|key| AsnBehaviour::new(key, config, &metrics.compression_saved()))?
+		.with_swarm_config(swarm_config(config))
+		.build())
+}
+
+/// Private swarms run over TCP and WebSocket only: QUIC brings its own TLS handshake and cannot be
+/// wrapped by the pnet protector.
+#[cfg(feature = "pnet")]
+fn build_private_swarm(
+	id_key: identity::Keypair,
+	psk: libp2p::pnet::PreSharedKey,
+	config: &NetworkConfig,
+	metrics: &NetworkMetrics,
+	registry: &mut Registry,
+) -> Result<Swarm<AsnBehaviour>, Box<dyn Error>> {
+	use libp2p::{core::upgrade::Version, pnet::PnetConfig, websocket, Transport};
+
+	tracing::info!("Joining private swarm with pre-shared key {}", psk.fingerprint());
+
+	Ok(libp2p::SwarmBuilder::with_existing_identity(id_key)
+		.with_tokio()
+		.with_other_transport(|key| -> Result<_, Box<dyn Error + Send + Sync>> {
+			let tcp_transport = || tcp::tokio::Transport::new(tcp::Config::default().nodelay(true));
+			let base_transport =
+				tcp_transport().or_transport(websocket::Config::new(tcp_transport()));
+
+			Ok(base_transport
+				.and_then(move |socket, _| PnetConfig::new(psk).handshake(socket))
+				.upgrade(Version::V1Lazy)
+				.authenticate(noise::Config::new(key)?)
+				.multiplex(yamux::Config::default()))
+		})?
+		.with_dns()?
+		.with_bandwidth_metrics(registry)
+		.with_behaviour(|key| AsnBehaviour::new(key, config, &metrics.compression_saved()))?
+		.with_swarm_config(swarm_config(config))
+		.build())
+}
@@ -1,9 +1,55 @@
-use std::{collections::HashSet, error::Error};
+use std::{
+	collections::{HashMap, HashSet},
+	error::Error,
+	time::Duration,
+};
 use thiserror::Error;
 
 use futures::channel::oneshot;
-use libp2p::{core::Multiaddr, request_response::ResponseChannel, PeerId};
-use serde::{Deserialize, Serialize};
+use libp2p::{
+	autonat::NatStatus,
+	connection_limits::Exceeded,
+	core::{ConnectedPoint, Multiaddr},
+	gossipsub::MessageId,
+	kad::{self, Quorum},
+	request_response::{OutboundRequestId, ResponseChannel},
+	swarm::{ConnectionError, ConnectionId, DialError},
+	PeerId,
+};
+use serde::{
+	de::{self, MapAccess, SeqAccess, Visitor},
+	ser::{SerializeMap, SerializeSeq},
+	Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+	agent_card::AgentCard,
+	auction::{AuctionConfig, AuctionState, AuctionWinner},
+	bulletin::{Bulletin, BulletinKind},
+	capabilities::NodeCapabilities,
+	cid::Cid,
+	commitment::ResultCommitment,
+	directory::{AgentFilter, AgentListing},
+	encryption::Sealed,
+	feedback::Feedback,
+	hardware::HardwareRequirements,
+	history::{HistoryPage, HistoryQuery},
+	labels::{LabelSelector, Labels},
+	latency::PeerLatency,
+	query::QueryHandle,
+	reconnect::ReconnectState,
+	reputation::Attestation,
+	request::RequestHandle,
+	signed::{SignatureError, SignedPayload},
+	stream::{StreamRequest, StreamResponse},
+	tasks::{TaskError, TaskRecord, TaskStatus},
+	wire::tolerant_enum,
+};
+
+/// Hands the caller the handle of a DHT query once it started.
+type QuerySender<T> = oneshot::Sender<Result<QueryHandle<T>, Box<dyn Error + Send>>>;
+/// Hands the caller the handle of an agent request once it was sent.
+type RequestSender = oneshot::Sender<Result<RequestHandle, Box<dyn Error + Send>>>;
 
 #[derive(Debug)]
 pub enum Command {
@@ -16,49 +62,561 @@ pub enum Command {
 		peer_addr: Multiaddr,
 		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
 	},
+	StopProviding {
+		agent_name: String,
+	},
 	StartProviding {
 		agent_name: String,
-		sender: oneshot::Sender<()>,
+		sender: QuerySender<()>,
 	},
 	GetProviders {
 		agent_name: String,
-		sender: oneshot::Sender<HashSet<PeerId>>,
+		sender: QuerySender<HashSet<PeerId>>,
+	},
+	/// Advertise the local node as a mirror of an artifact, see [`crate::artifacts::artifact_key`].
+	ProvideArtifact {
+		cid: Cid,
+		sender: QuerySender<()>,
+	},
+	StopProvidingArtifact {
+		cid: Cid,
+	},
+	GetArtifactProviders {
+		cid: Cid,
+		sender: QuerySender<HashSet<PeerId>>,
+	},
+	GetKeyProviders {
+		key: String,
+		sender: QuerySender<HashSet<PeerId>>,
 	},
 	RequestAgent {
 		agent_name: String,
 		message: String,
+		context: Vec<ContextMessage>,
+		/// Key of the provider to encrypt the request to, end to end.
+		encrypt_to: Option<[u8; 32]>,
+		language: Option<String>,
+		/// Artifacts attached to the message, fetched by the provider from the requester.
+		attachments: Vec<Cid>,
+		/// Id of the job the request is an attempt at, see [`crate::RequestJournal`].
+		job: Option<Cid>,
 		peer: PeerId,
-		sender: oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>,
+		sender: RequestSender,
+	},
+	CancelRequest {
+		request_id: OutboundRequestId,
 	},
 	RespondLLM {
 		llm_output: Vec<u8>,
 		channel: ResponseChannel<LLMResponse>,
 	},
+	RequestArtifact {
+		name: String,
+		peer: PeerId,
+		sender: oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>,
+	},
+	RespondArtifact {
+		data: Vec<u8>,
+		channel: ResponseChannel<ArtifactResponse>,
+	},
 	GossipMessage {
 		topic: String,
 		message: String,
 	},
+	Subscribe {
+		topic: String,
+		sender: oneshot::Sender<Result<bool, Box<dyn Error + Send>>>,
+	},
+	Unsubscribe {
+		topic: String,
+		sender: oneshot::Sender<bool>,
+	},
+	Topics {
+		sender: oneshot::Sender<Vec<String>>,
+	},
+	Bootstrap,
+	SyncDirectory {
+		peer: PeerId,
+		sender: oneshot::Sender<Result<usize, Box<dyn Error + Send>>>,
+	},
+	PutRecord {
+		key: String,
+		value: Vec<u8>,
+		quorum: Quorum,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	GetRecord {
+		key: String,
+		quorum: Quorum,
+		sender: oneshot::Sender<Result<Vec<u8>, Box<dyn Error + Send>>>,
+	},
+	PublishAgentCard {
+		card: AgentCard,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	PublishBulletin {
+		kind: BulletinKind,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	GetClosestPeers {
+		key: String,
+		sender: oneshot::Sender<Result<Vec<PeerId>, Box<dyn Error + Send>>>,
+	},
+	KBuckets {
+		sender: oneshot::Sender<Vec<KBucketInfo>>,
+	},
+	NatStatus {
+		sender: oneshot::Sender<NatStatus>,
+	},
+	GossipHistory {
+		query: HistoryQuery,
+		sender: oneshot::Sender<HistoryPage>,
+	},
+	GetConnectedPeers {
+		selector: LabelSelector,
+		sender: oneshot::Sender<Vec<ConnectedPeer>>,
+	},
+	TagPeer {
+		peer: PeerId,
+		key: String,
+		value: String,
+	},
+	UntagPeer {
+		peer: PeerId,
+		key: String,
+	},
+	PeerLabels {
+		peer: PeerId,
+		sender: oneshot::Sender<Labels>,
+	},
+	LastSeen {
+		peer: PeerId,
+		sender: oneshot::Sender<Option<Duration>>,
+	},
+	OnlineProviders {
+		agent_name: String,
+		providers: HashSet<PeerId>,
+		sender: oneshot::Sender<HashSet<PeerId>>,
+	},
+	FindAgents {
+		filter: AgentFilter,
+		sender: oneshot::Sender<Vec<AgentListing>>,
+	},
+	NetworkSnapshot {
+		sender: oneshot::Sender<Result<SignedPayload, SignatureError>>,
+	},
+	PeerCapabilities {
+		sender: oneshot::Sender<HashMap<PeerId, NodeCapabilities>>,
+	},
+	PeerLatency {
+		peer: PeerId,
+		sender: oneshot::Sender<Option<PeerLatency>>,
+	},
+	AdmissibleProviders {
+		providers: Vec<PeerId>,
+		sender: oneshot::Sender<HashSet<PeerId>>,
+	},
+	ListenAddresses {
+		sender: oneshot::Sender<Vec<Multiaddr>>,
+	},
+	ExternalAddresses {
+		sender: oneshot::Sender<Vec<Multiaddr>>,
+	},
+	StreamRequest {
+		peer: PeerId,
+		request: StreamRequest,
+		sender: oneshot::Sender<Result<StreamResponse, Box<dyn Error + Send>>>,
+	},
+	SubmitBid {
+		peer: PeerId,
+		bid: BidResponse,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	SendFeedback {
+		peer: PeerId,
+		feedback: Feedback,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	RunAuction {
+		proposal: TaskProposal,
+		config: AuctionConfig,
+		sender: oneshot::Sender<Result<Option<AuctionWinner>, Box<dyn Error + Send>>>,
+	},
+	GetTaskStatus {
+		task_id: String,
+		sender: oneshot::Sender<Option<TaskRecord>>,
+	},
+	UpdateTaskStatus {
+		task_id: String,
+		status: TaskStatus,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	CommitResult {
+		commitment: ResultCommitment,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	RevealResult {
+		task_id: String,
+		result: Vec<u8>,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	AnswerChallenge {
+		nonce: Vec<u8>,
+		sender: oneshot::Sender<Result<SignedPayload, Box<dyn Error + Send>>>,
+	},
+	SignAttestation {
+		attestation: Attestation,
+		sender: oneshot::Sender<Result<SignedPayload, Box<dyn Error + Send>>>,
+	},
+	DialBack {
+		peer: PeerId,
+		sender: oneshot::Sender<Result<Duration, Box<dyn Error + Send>>>,
+	},
+	Disconnect {
+		peer: PeerId,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
+	Ban {
+		peer: PeerId,
+		duration: Duration,
+		sender: oneshot::Sender<Result<(), Box<dyn Error + Send>>>,
+	},
 }
 
 #[derive(Debug)]
 pub enum Event {
-	LLMInboundRequest { agent_name: String, message: String, channel: ResponseChannel<LLMResponse> },
-	InboundTaskProposal { task_proposal: TaskProposal },
+	LLMInboundRequest {
+		agent_name: String,
+		message: String,
+		/// Prior messages of the conversation, oldest first.
+		context: Vec<ContextMessage>,
+		/// Language the requester asked the answer in, e.g. `fr`.
+		language: Option<String>,
+		requester: PeerId,
+		/// Artifacts attached to the message, served by the requester, see [`crate::ArtifactStore`].
+		attachments: Vec<Cid>,
+		/// Id of the job of a request the requester sends again after a restart, the same on every
+		/// attempt, for the provider to answer it once and repeat its answer to the next attempts.
+		job: Option<Cid>,
+		channel: ResponseChannel<LLMResponse>,
+	},
+	/// A request for an artifact, received on the data plane.
+	ArtifactRequest { name: String, channel: ResponseChannel<ArtifactResponse> },
+	/// A streamed request. The tokens sent are delivered as the requester acknowledges them, so
+	/// sending pauses while the requester is slow and fails once the stream is closed.
+	LLMStreamRequest {
+		agent_name: String,
+		message: String,
+		tokens: tokio::sync::mpsc::Sender<String>,
+	},
+	/// A task proposal gossiped on the tasks topic, to be bid on with [`crate::Client::submit_bid`].
+	InboundTaskProposal { proposer: PeerId, task_proposal: TaskProposal },
+	/// A bid sent by a peer for a proposal of the node that is not auctioned by
+	/// [`crate::Client::run_auction`], already acknowledged.
+	InboundBid { bidder: PeerId, bid: BidResponse },
+	/// Progress of an auction run by the node.
+	Auction { task_id: String, state: AuctionState },
+	/// The node won the auction of a task with its bid.
+	TaskAwarded { proposer: PeerId, award: TaskAward },
+	/// A task auctioned or won by the node changed status: decided by its auction, reported by
+	/// its worker or timed out.
+	TaskStatusChanged { task: TaskRecord },
+	/// The worker of a task owned by the node revealed a result matching its commitment, and the
+	/// task completed.
+	TaskResult { task_id: String, worker: PeerId, result: Vec<u8> },
+	/// A requester rated an exchange it had with an agent of the node, already acknowledged.
+	Feedback { requester: PeerId, feedback: Feedback },
+	/// A verified bulletin from a trusted issuer, already applied when auto-apply is enabled.
+	Bulletin { issuer: PeerId, bulletin: Bulletin },
+	/// A message accepted on a topic the node subscribed to, relayed by `propagation_source`.
+	/// `source` is unset for the anonymous messages.
+	GossipMessage {
+		topic: String,
+		source: Option<PeerId>,
+		propagation_source: PeerId,
+		message_id: MessageId,
+		data: Vec<u8>,
+	},
+	/// AutoNAT changed its verdict on whether the node is reachable from the outside. Providers
+	/// found to be private may want to acquire relay reservations.
+	NatStatusChanged { old: NatStatus, new: NatStatus },
+	/// A connection was denied by one of the configured connection limits.
+	ConnectionLimitReached { peer_id: Option<PeerId>, exceeded: Exceeded },
+	/// The first connection to a peer was established.
+	PeerConnected { peer_id: PeerId, endpoint: ConnectedPoint },
+	/// The last connection to a peer closed, `cause` is unset when it was closed on purpose.
+	PeerDisconnected { peer_id: PeerId, cause: Option<ConnectionError> },
+	/// A connection to a peer was established, `num_established` counting the ones now open
+	/// with the peer.
+	ConnectionEstablished {
+		peer_id: PeerId,
+		connection_id: ConnectionId,
+		endpoint: ConnectedPoint,
+		num_established: u32,
+	},
+	/// A connection to a peer closed, `cause` is unset when it was closed on purpose.
+	ConnectionClosed {
+		peer_id: PeerId,
+		connection_id: ConnectionId,
+		endpoint: ConnectedPoint,
+		num_established: u32,
+		cause: Option<String>,
+	},
+	/// A peer was found, over mDNS on the selected interfaces, in the DHT or at the rendezvous
+	/// point.
+	PeerDiscovered { peer_id: PeerId, addresses: Vec<Multiaddr>, source: DiscoverySource },
+	/// Addresses of a peer found over mDNS were not announced again in time.
+	PeerExpired { peer_id: PeerId, addresses: Vec<Multiaddr> },
+	/// The connection to a dialed peer or the rendezvous point dropped, or came back.
+	Reconnect { peer_id: PeerId, state: ReconnectState },
+	/// The node came back from a suspend of the host, or its network interfaces changed when
+	/// `suspended` is unset, and it listened, redialed, registered and subscribed again.
+	NetworkResumed { suspended: Option<Duration> },
+	/// The node started listening on an address, or the address expired, e.g. when its interface
+	/// went down.
+	ListenAddressChanged { address: Multiaddr, expired: bool },
+	/// A failure the event loop recovered from, e.g. a dial denied by the connection limits.
+	Error { error: EventLoopError },
+}
+
+/// How a peer was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiscoverySource {
+	Mdns,
+	Kademlia,
+	Rendezvous,
+}
+
+/// A non-empty bucket of the Kademlia routing table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KBucketInfo {
+	/// The peers of the bucket are at a distance in `[2^index, 2^(index + 1))` of the local key.
+	pub index: u32,
+	pub peers: Vec<RoutingPeer>,
 }
 
+/// A peer the node is connected to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectedPeer {
+	pub peer_id: PeerId,
+	/// Agent version announced by the peer through identify, once received.
+	pub agent_version: Option<String>,
+	/// Protocols supported by the peer, as announced through identify.
+	pub protocols: Vec<String>,
+	/// Time since the first of the open connections to the peer was established.
+	pub connected_for: Duration,
+	/// Labels the application attached to the peer.
+	pub labels: Labels,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingPeer {
+	pub peer_id: PeerId,
+	pub addresses: Vec<Multiaddr>,
+	pub connected: bool,
+}
+
+/// Author of a prior message of the conversation sent along an agent request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageRole {
+	User,
+	Assistant,
+}
+
+/// A prior message of the conversation, for stateless providers to answer in context.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct LLMRequest(pub String, pub String);
+pub struct ContextMessage {
+	pub role: MessageRole,
+	pub content: String,
+}
+
+/// Request for an agent: its name, the message, the prior messages of the conversation, the
+/// language of the answer, the artifacts attached to the message, fetched by the provider from
+/// the requester, and the id of the job of a request sent again, for the provider to answer it
+/// once. The message and context of a request encrypted end to end are left empty, and sealed
+/// instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LLMRequest(
+	pub String,
+	pub String,
+	pub Vec<ContextMessage>,
+	pub Option<Sealed>,
+	pub Option<String>,
+	pub Vec<Cid>,
+	pub Option<Cid>,
+);
+
+impl LLMRequest {
+	/// Size of the message and its context, in bytes.
+	pub fn message_len(&self) -> usize {
+		self.1.len()
+			+ self.2.iter().map(|message| message.content.len()).sum::<usize>()
+			+ self.3.as_ref().map_or(0, |sealed| sealed.ciphertext.len())
+	}
+}
+
+// Encoded as a sequence whose context, sealed payload, language, attachments and job id are left
+// out when empty, so that older providers still understand the requests without them. The derived
+// impls expect an exact number of fields.
+impl Serialize for LLMRequest {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let len = match (&self.2, &self.3, &self.4, &self.5, &self.6) {
+			(_, _, _, _, Some(_)) => 7,
+			(_, _, _, attachments, None) if !attachments.is_empty() => 6,
+			(_, _, Some(_), _, None) => 5,
+			(_, Some(_), None, _, None) => 4,
+			(context, None, None, _, None) if !context.is_empty() => 3,
+			_ => 2,
+		};
+		let mut seq = serializer.serialize_seq(Some(len))?;
+		seq.serialize_element(&self.0)?;
+		seq.serialize_element(&self.1)?;
+		if len > 2 {
+			seq.serialize_element(&self.2)?;
+		}
+		if len > 3 {
+			seq.serialize_element(&self.3)?;
+		}
+		if len > 4 {
+			seq.serialize_element(&self.4)?;
+		}
+		if len > 5 {
+			seq.serialize_element(&self.5)?;
+		}
+		if len > 6 {
+			seq.serialize_element(&self.6)?;
+		}
+		seq.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for LLMRequest {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct RequestVisitor;
+
+		impl<'de> Visitor<'de> for RequestVisitor {
+			type Value = LLMRequest;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				f.write_str("an agent request")
+			}
+
+			fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<LLMRequest, A::Error> {
+				let agent_name =
+					seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+				let message =
+					seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+				let context = seq.next_element()?.unwrap_or_default();
+				let sealed = seq.next_element::<Option<Sealed>>()?.flatten();
+				let language = seq.next_element::<Option<String>>()?.flatten();
+				let attachments = seq.next_element()?.unwrap_or_default();
+				let job = seq.next_element::<Option<Cid>>()?.flatten();
+				Ok(LLMRequest(agent_name, message, context, sealed, language, attachments, job))
+			}
+		}
+
+		deserializer.deserialize_seq(RequestVisitor)
+	}
+}
+/// Response of an agent. A provider at its limit of concurrent requests answers `Busy` at once,
+/// for the requester to fail over to another provider rather than wait, and `Denied` to the
+/// requests the policy of the agent refuses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LLMResponse {
+	Output(Vec<u8>),
+	Busy { limit: usize },
+	Denied { reason: String },
+}
+
+// The output is encoded as the newtype response it used to be, which older requesters expect.
+impl Serialize for LLMResponse {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self {
+			LLMResponse::Output(output) => output.serialize(serializer),
+			LLMResponse::Busy { limit } => {
+				let mut map = serializer.serialize_map(Some(1))?;
+				map.serialize_entry("busy", limit)?;
+				map.end()
+			},
+			LLMResponse::Denied { reason } => {
+				let mut map = serializer.serialize_map(Some(1))?;
+				map.serialize_entry("denied", reason)?;
+				map.end()
+			},
+		}
+	}
+}
+
+impl<'de> Deserialize<'de> for LLMResponse {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct ResponseVisitor;
+
+		impl<'de> Visitor<'de> for ResponseVisitor {
+			type Value = LLMResponse;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				f.write_str("an agent response")
+			}
+
+			fn visit_bytes<E: de::Error>(self, output: &[u8]) -> Result<LLMResponse, E> {
+				Ok(LLMResponse::Output(output.to_vec()))
+			}
+
+			fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<LLMResponse, A::Error> {
+				let mut output = Vec::with_capacity(seq.size_hint().unwrap_or_default().min(4096));
+				while let Some(byte) = seq.next_element()? {
+					output.push(byte);
+				}
+				Ok(LLMResponse::Output(output))
+			}
+
+			fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<LLMResponse, A::Error> {
+				match map.next_key::<String>()?.as_deref() {
+					Some("busy") => Ok(LLMResponse::Busy { limit: map.next_value()? }),
+					Some("denied") => Ok(LLMResponse::Denied { reason: map.next_value()? }),
+					Some(key) => Err(de::Error::unknown_field(key, &["busy", "denied"])),
+					None => Err(de::Error::invalid_length(0, &self)),
+				}
+			}
+		}
+
+		deserializer.deserialize_any(ResponseVisitor)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactRequest(pub String);
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct LLMResponse(pub Vec<u8>);
+pub struct ArtifactResponse(pub Vec<u8>);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum TaskType {
 	ImageGeneration,
 	DataProcessing,
 	WebResearch,
+	/// A task type introduced by a newer node, with its tag and encoded content.
+	#[serde(skip)]
+	Unknown(String, Vec<u8>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+tolerant_enum!(TaskType);
+
+impl std::str::FromStr for TaskType {
+	type Err = ProtocolError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"ImageGeneration" => Ok(TaskType::ImageGeneration),
+			"DataProcessing" => Ok(TaskType::DataProcessing),
+			"WebResearch" => Ok(TaskType::WebResearch),
+			_ => Err(ProtocolError::InvalidFormat),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TaskProposal {
 	pub agent_name: String,
 	pub task_id: String,
@@ -66,13 +624,31 @@ pub struct TaskProposal {
 	pub task_message: String,
 	pub max_bid: f64,
 	pub deadline: u64,
+	/// Hardware the bidders must have announced for their bids to be accepted.
+	#[serde(default, skip_serializing_if = "HardwareRequirements::is_empty")]
+	pub requirements: HardwareRequirements,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BidResponse {
 	pub task_id: String,
 	pub capabilities: Vec<String>,
 	pub bid: f64,
+	/// Address the bidder is paid at when the proposer escrows its payments, see
+	/// [`crate::escrow::TaskEscrow`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub payment_address: Option<String>,
+}
+
+/// Sent by the proposer of a task to the winner of its auction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskAward {
+	pub task_id: String,
+	pub bid: f64,
+	/// Budget of the proposal, escrowed by the proposer when it pays through an escrow.
+	pub max_bid: f64,
+	/// Deadline of the proposal, a unix timestamp in seconds.
+	pub deadline: u64,
 }
 
 #[derive(Error, Debug)]
@@ -83,6 +659,65 @@ pub enum ProtocolError {
 	InvalidFormat,
 }
 
+#[derive(Error, Debug)]
+pub enum RecordError {
+	#[error("Record not found")]
+	NotFound,
+	#[error("Record found on {found} peers, {required} required")]
+	QuorumFailed { found: usize, required: usize },
+}
+
+/// Failures of the event loop that do not stop it, reported as [`Event::Error`].
+#[derive(Error, Debug)]
+pub enum EventLoopError {
+	#[error("Failed to dial {address}: {error}")]
+	Dial { address: Multiaddr, error: DialError },
+	/// A query completed after its requester was answered, e.g. failed at shutdown.
+	#[error("Completed query {0:?} is not pending")]
+	UnknownQuery(kad::QueryId),
+	#[error("Failed to encode message: {0}")]
+	Protocol(#[from] ProtocolError),
+	/// A peer was dialed again while its first dial was pending, the second dial failed.
+	#[error("Already dialing {0}")]
+	AlreadyDialing(PeerId),
+}
+
+#[derive(Error, Debug)]
+pub enum NetworkError {
+	#[error("Network is shutting down")]
+	ShuttingDown,
+	#[error("Peer not reachable at its advertised addresses: {0}")]
+	Unreachable(String),
+	#[error("Connection closed before the peer answered")]
+	ConnectionClosed,
+	#[error("Network too busy to accept the command")]
+	Overloaded,
+	#[error("Not connected to the peer")]
+	NotConnected,
+	#[error("Already dialing the peer")]
+	AlreadyDialing,
+	#[error("Provider busy with its limit of {limit} concurrent requests")]
+	ProviderBusy { limit: usize },
+	#[error("Request denied by the policy of the provider: {0}")]
+	Denied(String),
+	#[error("No provider to request agent {0} from")]
+	NoProviders(String),
+	#[error("Provider did not answer within {0:?}")]
+	Timeout(Duration),
+	#[error("Provider skipped until the cooldown of its failures elapsed")]
+	CircuitOpen,
+	#[error("Failed to gossip: {0}")]
+	Gossip(String),
+	#[error("Task {0} is already auctioned")]
+	AuctionRunning(String),
+	#[error(transparent)]
+	Task(#[from] TaskError),
+	#[error("Result refused by the owner of the task: {0}")]
+	ResultRefused(String),
+	#[error("No answer agreed on by {required} providers, {agreeing} at most")]
+	NoAgreement { required: usize, agreeing: usize },
+}
+
 pub fn serialize_message<T: Serialize>(msg: &T) -> Result<Vec<u8>, ProtocolError> {
 	serde_json::to_vec(msg).map_err(Into::into)
 }
@@ -0,0 +1,327 @@
+//! Checks a requester runs on the responses of the providers before using them, e.g. that they
+//! match a JSON schema or cite their sources. Validators plug in by implementing
+//! [`ResponseValidator`]; what happens to invalid responses is up to [`OnInvalid`]. Validators
+//! see the responses without their provenance trailer.
+
+use std::{collections::HashSet, fmt, str::FromStr, sync::Arc};
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::provenance::Provenance;
+
+/// A check of the responses of the providers.
+pub trait ResponseValidator: Send + Sync {
+	/// Name the violations of the validator are reported under.
+	fn name(&self) -> &str;
+
+	/// Check the `response` to `message`, returning why it is invalid.
+	fn validate(&self, message: &str, response: &[u8]) -> Result<(), String>;
+}
+
+/// What to do with a response failing a validator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnInvalid {
+	/// Fail the request.
+	#[default]
+	Reject,
+	/// Ask another provider, failing once none is left.
+	Retry,
+	/// Return the response along with its violations.
+	Warn,
+}
+
+impl FromStr for OnInvalid {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"reject" => Ok(OnInvalid::Reject),
+			"retry" => Ok(OnInvalid::Retry),
+			"warn" => Ok(OnInvalid::Warn),
+			_ => Err(format!("Unknown behavior {s}, expected reject, retry or warn")),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+	pub validator: String,
+	pub reason: String,
+}
+
+impl fmt::Display for Violation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.validator, self.reason)
+	}
+}
+
+/// A response that failed its validators.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid response: {}", join(violations))]
+pub struct InvalidResponse {
+	pub violations: Vec<Violation>,
+	/// Whether another provider should be asked, see [`OnInvalid::Retry`].
+	pub retry: bool,
+}
+
+fn join(violations: &[Violation]) -> String {
+	violations.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// The validators of a requester, run in the order they were added.
+#[derive(Clone, Default)]
+pub struct ResponseValidators {
+	validators: Vec<Arc<dyn ResponseValidator>>,
+	on_invalid: OnInvalid,
+}
+
+impl ResponseValidators {
+	pub fn with(mut self, validator: impl ResponseValidator + 'static) -> Self {
+		self.validators.push(Arc::new(validator));
+		self
+	}
+
+	pub fn on_invalid(mut self, on_invalid: OnInvalid) -> Self {
+		self.on_invalid = on_invalid;
+		self
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.validators.is_empty()
+	}
+
+	/// Run every validator on the `response` to `message`. Returns the violations to warn about
+	/// when the response may be used, all of them being empty for a valid response.
+	pub fn check(&self, message: &str, response: &[u8]) -> Result<Vec<Violation>, InvalidResponse> {
+		let (response, _) = Provenance::split(response);
+		let violations: Vec<_> = self
+			.validators
+			.iter()
+			.filter_map(|validator| {
+				let reason = validator.validate(message, response).err()?;
+				Some(Violation { validator: validator.name().to_string(), reason })
+			})
+			.collect();
+		match self.on_invalid {
+			_ if violations.is_empty() => Ok(violations),
+			OnInvalid::Warn => Ok(violations),
+			OnInvalid::Reject => Err(InvalidResponse { violations, retry: false }),
+			OnInvalid::Retry => Err(InvalidResponse { violations, retry: true }),
+		}
+	}
+}
+
+impl fmt::Debug for ResponseValidators {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let names: Vec<_> = self.validators.iter().map(|validator| validator.name()).collect();
+		f.debug_struct("ResponseValidators")
+			.field("validators", &names)
+			.field("on_invalid", &self.on_invalid)
+			.finish()
+	}
+}
+
+/// Responses of at most this many characters.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxLength(pub usize);
+
+impl ResponseValidator for MaxLength {
+	fn name(&self) -> &str {
+		"max-length"
+	}
+
+	fn validate(&self, _message: &str, response: &[u8]) -> Result<(), String> {
+		let length = String::from_utf8_lossy(response).chars().count();
+		if length > self.0 {
+			return Err(format!("{length} characters, at most {} allowed", self.0));
+		}
+		Ok(())
+	}
+}
+
+/// JSON responses matching a schema. Only `type`, `enum`, `properties`, `required` and `items`
+/// are checked, the other keywords are ignored.
+#[derive(Debug, Clone)]
+pub struct JsonSchema(pub Value);
+
+impl ResponseValidator for JsonSchema {
+	fn name(&self) -> &str {
+		"json-schema"
+	}
+
+	fn validate(&self, _message: &str, response: &[u8]) -> Result<(), String> {
+		let value: Value =
+			serde_json::from_slice(response).map_err(|e| format!("Not JSON: {e}"))?;
+		check_schema(&self.0, &value, "$")
+	}
+}
+
+fn check_schema(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+	if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+		let matches = match expected {
+			"object" => value.is_object(),
+			"array" => value.is_array(),
+			"string" => value.is_string(),
+			"number" => value.is_number(),
+			"integer" => value.is_i64() || value.is_u64(),
+			"boolean" => value.is_boolean(),
+			"null" => value.is_null(),
+			_ => true,
+		};
+		if !matches {
+			return Err(format!("{path} is not of type {expected}"));
+		}
+	}
+	if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+		if !allowed.contains(value) {
+			return Err(format!("{path} is not one of {}", Value::Array(allowed.clone())));
+		}
+	}
+	if let Some(object) = value.as_object() {
+		let required = schema.get("required").and_then(Value::as_array).into_iter().flatten();
+		for field in required.filter_map(Value::as_str) {
+			if !object.contains_key(field) {
+				return Err(format!("{path}.{field} is missing"));
+			}
+		}
+		if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+			for (field, schema) in properties {
+				if let Some(value) = object.get(field) {
+					check_schema(schema, value, &format!("{path}.{field}"))?;
+				}
+			}
+		}
+	}
+	if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+		for (i, value) in array.iter().enumerate() {
+			check_schema(items, value, &format!("{path}[{i}]"))?;
+		}
+	}
+	Ok(())
+}
+
+/// Responses free of the blocked terms, matched regardless of case.
+#[derive(Debug, Clone)]
+pub struct SafetyFilter {
+	blocked: Vec<String>,
+}
+
+impl SafetyFilter {
+	pub fn new(blocked: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+		Self { blocked: blocked.into_iter().map(|term| term.as_ref().to_lowercase()).collect() }
+	}
+}
+
+impl ResponseValidator for SafetyFilter {
+	fn name(&self) -> &str {
+		"safety"
+	}
+
+	fn validate(&self, _message: &str, response: &[u8]) -> Result<(), String> {
+		let response = String::from_utf8_lossy(response).to_lowercase();
+		match self.blocked.iter().find(|term| response.contains(term.as_str())) {
+			Some(term) => Err(format!("Contains the blocked term {term:?}")),
+			None => Ok(()),
+		}
+	}
+}
+
+/// Responses citing at least this many sources, counted as the distinct URLs and numbered
+/// references such as `[1]` they contain.
+#[derive(Debug, Clone, Copy)]
+pub struct CitationCheck(pub usize);
+
+impl ResponseValidator for CitationCheck {
+	fn name(&self) -> &str {
+		"citations"
+	}
+
+	fn validate(&self, _message: &str, response: &[u8]) -> Result<(), String> {
+		let citations = citations(&String::from_utf8_lossy(response));
+		if citations < self.0 {
+			return Err(format!("{citations} citations, at least {} required", self.0));
+		}
+		Ok(())
+	}
+}
+
+fn citations(text: &str) -> usize {
+	let mut found = HashSet::new();
+	for word in text.split_whitespace() {
+		if let Some(start) = word.find("http://").or_else(|| word.find("https://")) {
+			let url = word[start..].trim_end_matches(|c: char| ".,;:)]>\"'".contains(c));
+			found.insert(url.to_string());
+		}
+	}
+	let mut rest = text;
+	while let Some(start) = rest.find('[') {
+		rest = &rest[start + 1..];
+		if let Some(end) = rest.find(']') {
+			let reference = &rest[..end];
+			if !reference.is_empty() && reference.chars().all(|c| c.is_ascii_digit()) {
+				found.insert(format!("[{reference}]"));
+			}
+		}
+	}
+	found.len()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	#[test]
+	fn test_builtin_validators() {
+		assert!(MaxLength(5).validate("", "héllo".as_bytes()).is_ok());
+		assert!(MaxLength(4).validate("", b"hello").is_err());
+
+		let schema = JsonSchema(json!({
+			"type": "object",
+			"required": ["title"],
+			"properties": {
+				"title": { "type": "string" },
+				"tags": { "type": "array", "items": { "enum": ["a", "b"] } }
+			}
+		}));
+		assert!(schema.validate("", br#"{"title": "t", "tags": ["a"]}"#).is_ok());
+		assert_eq!(schema.validate("", br#"{"tags": []}"#), Err("$.title is missing".into()));
+		assert_eq!(
+			schema.validate("", br#"{"title": "t", "tags": ["c"]}"#),
+			Err(r#"$.tags[0] is not one of ["a","b"]"#.into())
+		);
+		assert!(schema.validate("", b"not json").is_err());
+
+		let safety = SafetyFilter::new(["Password"]);
+		assert!(safety.validate("", b"The PASSWORD is 1234").is_err());
+		assert!(safety.validate("", b"Nothing to see").is_ok());
+
+		let text = "See https://a.org/x, [1] and [2], again https://a.org/x. [draft]";
+		assert_eq!(citations(text), 3);
+		assert!(CitationCheck(3).validate("", text.as_bytes()).is_ok());
+		assert!(CitationCheck(4).validate("", text.as_bytes()).is_err());
+	}
+
+	#[test]
+	fn test_behavior_on_invalid_responses() {
+		let validators = ResponseValidators::default().with(MaxLength(3));
+		assert_eq!(validators.check("", b"abc"), Ok(Vec::new()));
+
+		let invalid = validators.check("", b"abcd").unwrap_err();
+		assert!(!invalid.retry);
+		assert_eq!(
+			invalid.to_string(),
+			"Invalid response: max-length: 4 characters, at most 3 allowed"
+		);
+		let retry = validators.clone().on_invalid(OnInvalid::Retry).check("", b"abcd");
+		assert!(retry.is_err_and(|invalid| invalid.retry));
+		let warnings = validators.on_invalid(OnInvalid::Warn).check("", b"abcd");
+		assert_eq!(warnings.map(|warnings| warnings.len()), Ok(1));
+	}
+}
+
+// endregion: --- Tests
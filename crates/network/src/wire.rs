@@ -0,0 +1,265 @@
+use serde::{
+	de::{self, Visitor},
+	forward_to_deserialize_any,
+	ser::SerializeMap,
+	Deserializer, Serializer,
+};
+use serde_json::Value;
+
+/// Implement `Serialize` and `Deserialize` for a wire enum, decoding the variants introduced by
+/// newer nodes into its `Unknown(tag, payload)` variant instead of failing, so older nodes keep
+/// interoperating. The enum derives its serde impls with `#[serde(remote = "Self")]` and skips
+/// its `Unknown` variant, which is encoded back as received.
+macro_rules! tolerant_enum {
+	($name:ident) => {
+		impl serde::Serialize for $name {
+			fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				match self {
+					Self::Unknown(tag, payload) => {
+						$crate::wire::serialize_unknown(tag, payload, serializer)
+					},
+					known => Self::serialize(known, serializer),
+				}
+			}
+		}
+
+		impl<'de> serde::Deserialize<'de> for $name {
+			fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				let value = <serde_json::Value as serde::Deserialize>::deserialize(deserializer)?;
+				let variants = $crate::wire::variants(Self::deserialize);
+				match $crate::wire::unknown_variant(&value, variants) {
+					Some((tag, payload)) => Ok(Self::Unknown(tag, payload)),
+					None => Self::deserialize(value).map_err(serde::de::Error::custom),
+				}
+			}
+		}
+	};
+}
+
+pub(crate) use tolerant_enum;
+
+/// Encode an unknown variant the way serde tags enums: its tag alone when it has no content,
+/// otherwise a map from its tag to its content.
+pub(crate) fn serialize_unknown<S: Serializer>(
+	tag: &str,
+	payload: &[u8],
+	serializer: S,
+) -> Result<S::Ok, S::Error> {
+	if payload.is_empty() {
+		return serializer.serialize_str(tag);
+	}
+
+	let content: Value = serde_json::from_slice(payload).map_err(serde::ser::Error::custom)?;
+	let mut map = serializer.serialize_map(Some(1))?;
+	map.serialize_entry(tag, &content)?;
+	map.end()
+}
+
+/// The tag and JSON encoded content of a tagged enum value, unless its tag is one of `variants`.
+pub(crate) fn unknown_variant(value: &Value, variants: &[&str]) -> Option<(String, Vec<u8>)> {
+	let (tag, payload) = match value {
+		Value::String(tag) => (tag, Vec::new()),
+		Value::Object(map) if map.len() == 1 => {
+			let (tag, content) = map.iter().next()?;
+			(tag, serde_json::to_vec(content).ok()?)
+		},
+		_ => return None,
+	};
+
+	(!variants.contains(&tag.as_str())).then(|| (tag.clone(), payload))
+}
+
+/// The variants an enum deserializes, as listed by its derived `Deserialize`.
+pub(crate) fn variants<T>(
+	deserialize: impl FnOnce(VariantNames) -> Result<T, Variants>,
+) -> &'static [&'static str] {
+	match deserialize(VariantNames) {
+		Err(Variants(variants)) => variants,
+		Ok(_) => &[],
+	}
+}
+
+/// Deserializer failing on everything, with the variants of the enum asked for if any.
+pub(crate) struct VariantNames;
+
+#[derive(Debug)]
+pub(crate) struct Variants(&'static [&'static str]);
+
+impl std::fmt::Display for Variants {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Variants {:?}", self.0)
+	}
+}
+
+impl std::error::Error for Variants {}
+
+impl de::Error for Variants {
+	fn custom<T: std::fmt::Display>(_msg: T) -> Self {
+		Variants(&[])
+	}
+}
+
+impl<'de> Deserializer<'de> for VariantNames {
+	type Error = Variants;
+
+	fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+		Err(Variants(&[]))
+	}
+
+	fn deserialize_enum<V: Visitor<'de>>(
+		self,
+		_name: &'static str,
+		variants: &'static [&'static str],
+		_visitor: V,
+	) -> Result<V::Value, Self::Error> {
+		Err(Variants(variants))
+	}
+
+	forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+		unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::{
+		bulletin::BulletinKind,
+		cid::Cid,
+		stream::StreamResponse,
+		types::{
+			deserialize_message, serialize_message, ContextMessage, LLMRequest, LLMResponse,
+			MessageRole, TaskType,
+		},
+	};
+
+	#[test]
+	fn test_known_variants_round_trip() -> Result<()> {
+		let task_type: TaskType = deserialize_message(&serialize_message(&TaskType::WebResearch)?)?;
+		let kind = BulletinKind::TopicBan { topic: "spam".to_string() };
+
+		assert_eq!(serialize_message(&TaskType::WebResearch)?, br#""WebResearch""#);
+		assert_eq!(task_type, TaskType::WebResearch);
+		assert_eq!(deserialize_message::<BulletinKind>(&serialize_message(&kind)?)?, kind);
+		Ok(())
+	}
+
+	#[test]
+	fn test_future_variants_decode_as_unknown() -> Result<()> {
+		let task_type: TaskType = deserialize_message(br#""AudioTranscription""#)?;
+		let future_kind = br#"{"Quarantine":{"peers":["a","b"],"until":42}}"#;
+		let kind: BulletinKind = deserialize_message(future_kind)?;
+		let response: StreamResponse = deserialize_message(br#"{"Progress":0.5}"#)?;
+
+		assert_eq!(task_type, TaskType::Unknown("AudioTranscription".to_string(), Vec::new()));
+		assert!(matches!(&kind, BulletinKind::Unknown(tag, _) if tag == "Quarantine"));
+		assert_eq!(response, StreamResponse::Unknown("Progress".to_string(), b"0.5".to_vec()));
+		// Forwarded as received.
+		assert_eq!(serialize_message(&task_type)?, br#""AudioTranscription""#);
+		assert_eq!(serialize_message(&kind)?, future_kind);
+		Ok(())
+	}
+
+	#[test]
+	fn test_request_without_context_keeps_its_encoding() -> Result<()> {
+		let request = LLMRequest(
+			"echo".to_string(),
+			"hi".to_string(),
+			Vec::new(),
+			None,
+			None,
+			Vec::new(),
+			None,
+		);
+		let context =
+			vec![ContextMessage { role: MessageRole::User, content: "hello".to_string() }];
+		let with_context =
+			LLMRequest("echo".to_string(), "hi".to_string(), context, None, None, Vec::new(), None);
+		let in_french = LLMRequest(
+			"echo".to_string(),
+			"salut".to_string(),
+			Vec::new(),
+			None,
+			Some("fr".into()),
+			Vec::new(),
+			None,
+		);
+		let attached = LLMRequest(
+			"echo".to_string(),
+			"summarize".to_string(),
+			Vec::new(),
+			None,
+			None,
+			vec![Cid::of(b"report")],
+			None,
+		);
+
+		assert_eq!(serialize_message(&request)?, br#"["echo","hi"]"#);
+		assert_eq!(deserialize_message::<LLMRequest>(br#"["echo","hi"]"#)?, request);
+		assert_eq!(
+			serialize_message(&with_context)?,
+			br#"["echo","hi",[{"role":"user","content":"hello"}]]"#
+		);
+		assert_eq!(
+			deserialize_message::<LLMRequest>(&serialize_message(&with_context)?)?,
+			with_context
+		);
+		assert_eq!(serialize_message(&in_french)?, br#"["echo","salut",[],null,"fr"]"#);
+		assert_eq!(deserialize_message::<LLMRequest>(&serialize_message(&in_french)?)?, in_french);
+		assert_eq!(
+			serialize_message(&attached)?,
+			format!(r#"["echo","summarize",[],null,null,["{}"]]"#, Cid::of(b"report")).into_bytes()
+		);
+		assert_eq!(deserialize_message::<LLMRequest>(&serialize_message(&attached)?)?, attached);
+		let mut resumed = attached;
+		resumed.6 = Some(Cid::of(b"job"));
+		assert_eq!(
+			serialize_message(&resumed)?,
+			format!(
+				r#"["echo","summarize",[],null,null,["{}"],"{}"]"#,
+				Cid::of(b"report"),
+				Cid::of(b"job")
+			)
+			.into_bytes()
+		);
+		assert_eq!(deserialize_message::<LLMRequest>(&serialize_message(&resumed)?)?, resumed);
+		Ok(())
+	}
+
+	#[test]
+	fn test_response_output_keeps_its_encoding() -> Result<()> {
+		let output = LLMResponse::Output(b"ok".to_vec());
+		let busy = LLMResponse::Busy { limit: 4 };
+		let denied = LLMResponse::Denied { reason: "policy".to_string() };
+
+		assert_eq!(serialize_message(&output)?, b"[111,107]");
+		assert_eq!(deserialize_message::<LLMResponse>(b"[111,107]")?, output);
+		assert_eq!(serialize_message(&busy)?, br#"{"busy":4}"#);
+		assert_eq!(deserialize_message::<LLMResponse>(&serialize_message(&busy)?)?, busy);
+		assert_eq!(serialize_message(&denied)?, br#"{"denied":"policy"}"#);
+		assert_eq!(deserialize_message::<LLMResponse>(&serialize_message(&denied)?)?, denied);
+		Ok(())
+	}
+
+	#[test]
+	fn test_malformed_known_variant_still_fails() {
+		assert!(deserialize_message::<BulletinKind>(br#"{"TopicBan":{"topic":7}}"#).is_err());
+		assert!(deserialize_message::<TaskType>(b"42").is_err());
+	}
+
+	#[test]
+	fn test_variants_listed_without_unknown() {
+		assert_eq!(
+			variants(TaskType::deserialize),
+			["ImageGeneration", "DataProcessing", "WebResearch"]
+		);
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,24 @@
+use std::error::Error;
+
+use thiserror::Error;
+
+pub type Result<T> = core::result::Result<T, SdkError>;
+
+#[derive(Error, Debug)]
+pub enum SdkError {
+	#[error("Invalid peer address {0}: {1}")]
+	InvalidAddress(String, String),
+	#[error("No peer provides agent {0}")]
+	NoProvider(String),
+	#[error("Network error: {0}")]
+	Network(String),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+}
+
+// Errors of the network client are not `Sync`, so they are kept as their message.
+impl From<Box<dyn Error + Send>> for SdkError {
+	fn from(val: Box<dyn Error + Send>) -> Self {
+		Self::Network(val.to_string())
+	}
+}
@@ -0,0 +1,312 @@
+//! A small, stable API over the network crate for application developers: join a swarm, call its
+//! agents and provide new ones, without handling the event loop, its events or multiaddrs.
+//!
+//! ```no_run
+//! # async fn example() -> binary_souls_sdk::Result<()> {
+//! let swarm = binary_souls_sdk::Swarm::connect().await?;
+//! swarm.provide("echo", |message| async move { message }).await?;
+//! let answer = swarm.agent("echo").ask("Hello!").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod error;
+
+pub use error::{Result, SdkError};
+pub use network::{AgentCard, NetworkConfig, PeerId};
+
+use std::{
+	collections::{HashMap, HashSet},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use futures::{future::BoxFuture, prelude::*};
+use network::{
+	selection::{self, ProviderCandidate},
+	types::Event,
+	Client, Multiaddr, Protocol,
+};
+use tokio::task::spawn;
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Version of the agent cards published by [`Swarm::provide`].
+pub const DEFAULT_AGENT_VERSION: &str = "0.1.0";
+
+type AgentFn = Arc<dyn Fn(String) -> BoxFuture<'static, String> + Send + Sync>;
+type Agents = Arc<Mutex<HashMap<String, AgentFn>>>;
+
+/// How to join the swarm.
+#[derive(Debug, Clone, Default)]
+pub struct SwarmOptions {
+	/// TCP port to listen on, any free port when 0.
+	pub port: u16,
+	/// Addresses of the peers to join the swarm through, ending with their `/p2p/<peer id>`, as
+	/// returned by [`Swarm::address`].
+	pub bootstrap_peers: Vec<String>,
+	/// Lower level settings of the node.
+	pub network: NetworkConfig,
+}
+
+/// A node of the swarm running in the background, until dropped.
+pub struct Swarm {
+	client: Client,
+	peer_id: PeerId,
+	address: String,
+	agents: Agents,
+	_shutdown: DropGuard,
+}
+
+impl Swarm {
+	/// Join the swarm with the default options, finding the peers of the local network.
+	pub async fn connect() -> Result<Self> {
+		Self::connect_with(SwarmOptions::default()).await
+	}
+
+	pub async fn connect_with(options: SwarmOptions) -> Result<Self> {
+		let bootstrap_peers = options
+			.bootstrap_peers
+			.iter()
+			.map(|address| bootstrap_peer(address))
+			.collect::<Result<Vec<_>>>()?;
+
+		let (mut client, mut events, peer_id, event_loop) = network::new(options.network)
+			.await
+			.map_err(|e| SdkError::Network(e.to_string()))?;
+		let cancellation_token = CancellationToken::new();
+		spawn(event_loop.run(cancellation_token.clone()));
+
+		let listen_address: Multiaddr = format!("/ip4/0.0.0.0/tcp/{}", options.port)
+			.parse()
+			.expect("Listen address to be a valid multiaddr.");
+		client.start_listening(listen_address).await?;
+		let port = listening_port(&mut events).await?;
+
+		for (peer, address) in bootstrap_peers {
+			client.dial(peer, address).await?;
+		}
+
+		let agents = Agents::default();
+		spawn(serve(client.clone(), events, agents.clone()));
+
+		Ok(Self {
+			client,
+			peer_id,
+			address: format!("/ip4/127.0.0.1/tcp/{port}/p2p/{peer_id}"),
+			agents,
+			_shutdown: cancellation_token.drop_guard(),
+		})
+	}
+
+	pub fn peer_id(&self) -> PeerId {
+		self.peer_id
+	}
+
+	/// Local address of the node, for other nodes of the host to join the swarm through it.
+	pub fn address(&self) -> &str {
+		&self.address
+	}
+
+	/// The client of the underlying network, for what the SDK does not cover.
+	pub fn client(&self) -> Client {
+		self.client.clone()
+	}
+
+	/// An agent of the swarm, provided by any of its peers.
+	pub fn agent(&self, name: impl Into<String>) -> Agent<'_> {
+		Agent { swarm: self, name: name.into() }
+	}
+
+	/// Provide an agent answering each message with the output of `agent`. Fails while no other
+	/// peer of the swarm can store the card of the agent.
+	pub async fn provide<F, Fut>(&self, name: impl Into<String>, agent: F) -> Result<()>
+	where
+		F: Fn(String) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = String> + Send + 'static,
+	{
+		self.provide_card(AgentCard::new(name, DEFAULT_AGENT_VERSION), agent).await
+	}
+
+	/// Provide an agent described by the given card, answering with the output of `agent`.
+	pub async fn provide_card<F, Fut>(&self, card: AgentCard, agent: F) -> Result<()>
+	where
+		F: Fn(String) -> Fut + Send + Sync + 'static,
+		Fut: Future<Output = String> + Send + 'static,
+	{
+		let agent: AgentFn = Arc::new(move |message| agent(message).boxed());
+		self.agents
+			.lock()
+			.expect("Agents not to be poisoned.")
+			.insert(card.name.clone(), agent);
+
+		self.client.clone().start_providing(card).await?.result().await?;
+		Ok(())
+	}
+}
+
+/// An agent of the swarm, see [`Swarm::agent`].
+pub struct Agent<'a> {
+	swarm: &'a Swarm,
+	name: String,
+}
+
+impl Agent<'_> {
+	/// Ask the agent, calling the providers with a valid card in turn, the cheapest then the
+	/// fastest first, until one answers. Fails with the error of the last provider when none does.
+	pub async fn ask(&self, message: impl Into<String>) -> Result<String> {
+		let message = message.into();
+		let mut client = self.swarm.client.clone();
+
+		let providers = client.get_providers(self.name.clone()).await?.result().await?;
+		let cards = client.verified_providers(&self.name, providers).await;
+		let mut candidates = Vec::new();
+		for (&provider, card) in &cards {
+			let latency = client.peer_latency(provider).await?;
+			candidates.push(ProviderCandidate {
+				peer_id: provider,
+				// Providers never pinged come after the ones of the same price that were.
+				latency: latency.map_or(Duration::MAX, |latency| latency.median),
+				price: card.pricing,
+				score: 0.0,
+				labels: Default::default(),
+				languages: card.languages.clone(),
+			});
+		}
+
+		let mut last_error = None;
+		for candidate in selection::rank_providers(&candidates, &HashSet::new(), f64::INFINITY) {
+			let provider = candidate.peer_id;
+			let answer = async {
+				if cards[&provider].requires_challenge(message.len()) {
+					client.authenticate(provider).await?;
+				}
+				client.request_agent(provider, self.name.clone(), message.clone()).await
+			};
+			match answer.await {
+				Ok(answer) => return Ok(String::from_utf8_lossy(&answer).into_owned()),
+				Err(e) => {
+					tracing::warn!(
+						"Provider {provider} of agent {} failed to answer: {e}",
+						self.name
+					);
+					last_error = Some(e);
+				},
+			}
+		}
+		Err(last_error.map_or_else(|| SdkError::NoProvider(self.name.clone()), SdkError::from))
+	}
+}
+
+fn bootstrap_peer(address: &str) -> Result<(PeerId, Multiaddr)> {
+	let invalid = |reason: &str| SdkError::InvalidAddress(address.to_string(), reason.to_string());
+	let multiaddr: Multiaddr = address.parse().map_err(|_| invalid("not a multiaddr"))?;
+	match multiaddr.iter().last() {
+		Some(Protocol::P2p(peer)) => Ok((peer, multiaddr)),
+		_ => Err(invalid("missing the /p2p/<peer id> of the peer")),
+	}
+}
+
+/// The TCP port of the first address the node reports listening on, the one the system picked
+/// when asked for any free port.
+async fn listening_port(events: &mut (impl Stream<Item = Event> + Unpin)) -> Result<u16> {
+	while let Some(event) = events.next().await {
+		let Event::ListenAddressChanged { address, expired: false } = event else {
+			continue;
+		};
+		let port = address.iter().find_map(|protocol| match protocol {
+			Protocol::Tcp(port) => Some(port),
+			_ => None,
+		});
+		if let Some(port) = port {
+			return Ok(port);
+		}
+	}
+	Err(SdkError::Network("The node stopped before listening".to_string()))
+}
+
+/// Answer the requests of the provided agents until the node shuts down.
+async fn serve(client: Client, mut events: impl Stream<Item = Event> + Unpin, agents: Agents) {
+	while let Some(event) = events.next().await {
+		match event {
+			Event::LLMInboundRequest { agent_name, message, channel, .. } => {
+				let agent =
+					agents.lock().expect("Agents not to be poisoned.").get(&agent_name).cloned();
+				let Some(agent) = agent else {
+					tracing::warn!("Dropping request for unknown agent {agent_name}");
+					continue;
+				};
+				let mut client = client.clone();
+				spawn(async move {
+					let answer = agent(message).await;
+					client.respond_llm(answer.into_bytes(), channel).await;
+				});
+			},
+			Event::LLMStreamRequest { agent_name, .. } => {
+				tracing::warn!("Dropping stream request for agent {agent_name}, not supported");
+			},
+			_ => {},
+		}
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn test_provided_agent_answers() -> Result<()> {
+		let requester = Swarm::connect().await?;
+		let provider = Swarm::connect_with(SwarmOptions {
+			bootstrap_peers: vec![requester.address().to_string()],
+			..Default::default()
+		})
+		.await?;
+
+		provider
+			.provide("shout", |message| async move { message.to_uppercase() })
+			.await?;
+
+		assert_eq!(requester.agent("shout").ask("hello").await?, "HELLO");
+		let addresses = provider.client().listen_addresses().await.map_err(|e| e.to_string())?;
+		assert!(!addresses.is_empty());
+		assert!(addresses
+			.iter()
+			.all(|address| address.iter().last() == Some(Protocol::P2p(provider.peer_id()))));
+		assert!(matches!(
+			requester.agent("whisper").ask("hello").await,
+			Err(SdkError::NoProvider(_))
+		));
+		Ok(())
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn test_ask_fails_with_the_error_of_the_provider() -> Result<()> {
+		let requester = Swarm::connect().await?;
+		let provider = Swarm::connect_with(SwarmOptions {
+			bootstrap_peers: vec![requester.address().to_string()],
+			..Default::default()
+		})
+		.await?;
+		assert_ne!(provider.address(), requester.address());
+		provider
+			.provide("shout", |message| async move { message.to_uppercase() })
+			.await?;
+		drop(provider);
+
+		assert!(matches!(requester.agent("shout").ask("hello").await, Err(SdkError::Network(_))));
+		Ok(())
+	}
+
+	#[test]
+	fn test_bootstrap_peer_needs_its_peer_id() {
+		assert!(bootstrap_peer("/ip4/127.0.0.1/tcp/4001").is_err());
+		assert!(bootstrap_peer("not an address").is_err());
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,27 @@
+use thiserror::Error;
+
+pub type Result<T> = core::result::Result<T, WalletError>;
+
+#[derive(Error, Debug)]
+pub enum WalletError {
+	#[error("Invalid keystore: {0}")]
+	InvalidKeystore(String),
+	#[error("Wrong keystore password")]
+	WrongPassword,
+	#[error("Invalid address {0}")]
+	InvalidAddress(String),
+	#[error("Invalid private key")]
+	InvalidKey,
+	#[error("Signing failed: {0}")]
+	Signing(String),
+	#[error("Hardware wallet error: {0}")]
+	Device(String),
+	#[error("Estimated {fee} of {estimated} wei exceeds the cap of {cap} wei")]
+	FeeCapExceeded { fee: String, estimated: u128, cap: u128 },
+	#[error("RPC error: {0}")]
+	Rpc(String),
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+	#[error(transparent)]
+	Json(#[from] serde_json::Error),
+}
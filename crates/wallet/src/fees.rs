@@ -0,0 +1,199 @@
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::{Address, Result, WalletError};
+
+/// Blocks of fee history the priority fee is estimated from.
+pub(crate) const FEE_HISTORY_BLOCKS: u64 = 10;
+
+/// How quickly a transaction should be included, from the priority fees paid in recent blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FeeSpeed {
+	Slow,
+	#[default]
+	Normal,
+	Fast,
+}
+
+impl FeeSpeed {
+	/// Percentile of the priority fees of the recent blocks to pay.
+	pub fn reward_percentile(&self) -> f64 {
+		match self {
+			FeeSpeed::Slow => 10.0,
+			FeeSpeed::Normal => 50.0,
+			FeeSpeed::Fast => 90.0,
+		}
+	}
+
+	/// How many times the next base fee the max fee leaves room for, as it rises by up to 12.5%
+	/// per full block.
+	fn base_fee_multiplier(&self) -> u128 {
+		match self {
+			FeeSpeed::Slow => 1,
+			FeeSpeed::Normal | FeeSpeed::Fast => 2,
+		}
+	}
+}
+
+impl FromStr for FeeSpeed {
+	type Err = String;
+
+	fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+		match s {
+			"slow" => Ok(FeeSpeed::Slow),
+			"normal" => Ok(FeeSpeed::Normal),
+			"fast" => Ok(FeeSpeed::Fast),
+			_ => Err(format!("Unknown fee speed {s}, expected slow, normal or fast")),
+		}
+	}
+}
+
+/// The fees a node is willing to pay, in wei. Transactions whose estimate exceeds a cap are
+/// refused before being signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeePolicy {
+	pub speed: FeeSpeed,
+	pub max_fee_per_gas: Option<u128>,
+	pub max_priority_fee_per_gas: Option<u128>,
+	/// Cap of the whole fee of a transaction, gas limit times max fee per gas.
+	pub max_total_fee: Option<u128>,
+	/// Margin added to the estimated gas, in percent.
+	pub gas_margin_percent: u64,
+}
+
+impl Default for FeePolicy {
+	fn default() -> Self {
+		Self {
+			speed: FeeSpeed::default(),
+			max_fee_per_gas: None,
+			max_priority_fee_per_gas: None,
+			max_total_fee: None,
+			gas_margin_percent: 20,
+		}
+	}
+}
+
+impl FeePolicy {
+	/// The estimate, unless it exceeds one of the caps.
+	pub fn check(&self, estimate: FeeEstimate) -> Result<FeeEstimate> {
+		let caps = [
+			("max fee per gas", estimate.max_fee_per_gas, self.max_fee_per_gas),
+			(
+				"max priority fee per gas",
+				estimate.max_priority_fee_per_gas,
+				self.max_priority_fee_per_gas,
+			),
+			("total fee", estimate.max_total_fee(), self.max_total_fee),
+		];
+		match caps.into_iter().find(|(_, fee, cap)| cap.is_some_and(|cap| *fee > cap)) {
+			Some((fee, estimated, Some(cap))) => {
+				Err(WalletError::FeeCapExceeded { fee: fee.to_string(), estimated, cap })
+			},
+			_ => Ok(estimate),
+		}
+	}
+
+	/// The estimate of a transaction of `gas` units, from the `eth_feeHistory` of the recent
+	/// blocks queried at the percentile of the policy.
+	pub fn estimate(&self, gas: u64, fee_history: &Value) -> Result<FeeEstimate> {
+		let invalid = || WalletError::Rpc(format!("Invalid fee history: {fee_history}"));
+		// The base fees end with the one of the next block.
+		let base_fee_per_gas = fee_history["baseFeePerGas"]
+			.as_array()
+			.and_then(|fees| fees.last())
+			.and_then(quantity)
+			.ok_or_else(invalid)?;
+		let rewards = fee_history["reward"]
+			.as_array()
+			.ok_or_else(invalid)?
+			.iter()
+			.map(|reward| reward.get(0).and_then(quantity).ok_or_else(invalid))
+			.collect::<Result<Vec<_>>>()?;
+		let max_priority_fee_per_gas = match rewards.len() {
+			0 => 0,
+			len => rewards.iter().sum::<u128>() / len as u128,
+		};
+
+		Ok(FeeEstimate {
+			gas_limit: gas + gas * self.gas_margin_percent / 100,
+			base_fee_per_gas,
+			max_priority_fee_per_gas,
+			max_fee_per_gas: base_fee_per_gas * self.speed.base_fee_multiplier()
+				+ max_priority_fee_per_gas,
+		})
+	}
+}
+
+/// Gas and EIP-1559 fees of a transaction, in wei, to show before submitting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeEstimate {
+	pub gas_limit: u64,
+	pub base_fee_per_gas: u128,
+	pub max_priority_fee_per_gas: u128,
+	pub max_fee_per_gas: u128,
+}
+
+impl FeeEstimate {
+	/// Most the transaction can cost in fees.
+	pub fn max_total_fee(&self) -> u128 {
+		self.gas_limit as u128 * self.max_fee_per_gas
+	}
+}
+
+/// A transaction to estimate, sign and submit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionRequest {
+	pub from: Option<Address>,
+	/// Recipient, unset to deploy a contract.
+	pub to: Option<Address>,
+	pub value: u128,
+	pub data: Vec<u8>,
+}
+
+pub(crate) fn quantity(value: &Value) -> Option<u128> {
+	u128::from_str_radix(value.as_str()?.trim_start_matches("0x"), 16).ok()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn test_estimate_within_caps() -> Result<()> {
+		let fee_history = json!({
+			"oldestBlock": "0x10",
+			"baseFeePerGas": ["0x64", "0x6e", "0x78"],
+			"reward": [["0xa"], ["0x14"]],
+		});
+		let policy = FeePolicy { speed: FeeSpeed::Fast, ..Default::default() };
+
+		let estimate = policy.estimate(21_000, &fee_history)?;
+		assert_eq!(
+			estimate,
+			FeeEstimate {
+				gas_limit: 25_200,
+				base_fee_per_gas: 120,
+				max_priority_fee_per_gas: 15,
+				max_fee_per_gas: 255,
+			}
+		);
+		assert_eq!(policy.check(estimate)?, estimate);
+
+		let capped = FeePolicy { max_total_fee: Some(25_200 * 200), ..policy };
+		assert!(matches!(
+			capped.check(estimate),
+			Err(WalletError::FeeCapExceeded { cap: 5_040_000, .. })
+		));
+		assert!(policy.estimate(21_000, &json!({"reward": []})).is_err());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use async_trait::async_trait;
+use k256::ecdsa::SigningKey;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{keccak256, Address, Result, Signature, Signer, WalletError};
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+/// Scrypt cost of the keystores written by [`LocalSigner::save`], as used by geth.
+const SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// A signer holding its private key, stored in a password encrypted keystore (Web3 Secret Storage
+/// v3, as written by geth and most wallets).
+pub struct LocalSigner {
+	key: SigningKey,
+	address: Address,
+}
+
+impl LocalSigner {
+	pub fn random() -> Self {
+		Self::from_key(SigningKey::random(&mut OsRng))
+	}
+
+	pub fn from_private_key(private_key: &[u8]) -> Result<Self> {
+		Ok(Self::from_key(
+			SigningKey::from_slice(private_key).map_err(|_| WalletError::InvalidKey)?,
+		))
+	}
+
+	fn from_key(key: SigningKey) -> Self {
+		let public_key = key.verifying_key().to_encoded_point(false);
+		let public_key =
+			public_key.as_bytes().try_into().expect("Uncompressed key to be 65 bytes.");
+		Self { address: Address::from_public_key(public_key), key }
+	}
+
+	/// Decrypt the keystore at `path`.
+	pub fn load(path: &Path, password: &str) -> Result<Self> {
+		let keystore: Keystore = serde_json::from_slice(&std::fs::read(path)?)?;
+		Self::decrypt(&keystore, password)
+	}
+
+	/// Encrypt the key into a keystore at `path`, only readable by its owner.
+	pub fn save(&self, path: &Path, password: &str) -> Result<()> {
+		let keystore = self.encrypt(password, SCRYPT_LOG_N)?;
+		let mut options = std::fs::OpenOptions::new();
+		options.write(true).create_new(true);
+		#[cfg(unix)]
+		std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+		serde_json::to_writer_pretty(options.open(path)?, &keystore)?;
+		Ok(())
+	}
+
+	fn encrypt(&self, password: &str, log_n: u8) -> Result<Keystore> {
+		let mut salt = [0u8; 32];
+		let mut iv = [0u8; 16];
+		let mut id = [0u8; 16];
+		OsRng.fill_bytes(&mut salt);
+		OsRng.fill_bytes(&mut iv);
+		OsRng.fill_bytes(&mut id);
+
+		let kdfparams = KdfParams { dklen: 32, n: 1 << log_n, r: SCRYPT_R, p: SCRYPT_P, salt };
+		let derived = kdfparams.derive(password)?;
+		let mut ciphertext = self.key.to_bytes().to_vec();
+		Aes128Ctr::new(derived[..16].into(), &iv.into()).apply_keystream(&mut ciphertext);
+
+		Ok(Keystore {
+			version: 3,
+			id: uuid(id),
+			address: hex::encode(self.address.0),
+			crypto: Crypto {
+				cipher: "aes-128-ctr".to_string(),
+				cipherparams: CipherParams { iv },
+				mac: mac(&derived, &ciphertext),
+				ciphertext,
+				kdf: "scrypt".to_string(),
+				kdfparams,
+			},
+		})
+	}
+
+	fn decrypt(keystore: &Keystore, password: &str) -> Result<Self> {
+		let crypto = &keystore.crypto;
+		if keystore.version != 3 || crypto.cipher != "aes-128-ctr" || crypto.kdf != "scrypt" {
+			return Err(WalletError::InvalidKeystore(format!(
+				"Unsupported keystore: version {}, {} cipher, {} kdf",
+				keystore.version, crypto.cipher, crypto.kdf
+			)));
+		}
+
+		let derived = crypto.kdfparams.derive(password)?;
+		if mac(&derived, &crypto.ciphertext) != crypto.mac {
+			return Err(WalletError::WrongPassword);
+		}
+		let mut key = crypto.ciphertext.clone();
+		Aes128Ctr::new(derived[..16].into(), &crypto.cipherparams.iv.into())
+			.apply_keystream(&mut key);
+		Self::from_private_key(&key)
+	}
+}
+
+#[async_trait]
+impl Signer for LocalSigner {
+	fn address(&self) -> Address {
+		self.address
+	}
+
+	async fn sign_hash(&self, hash: [u8; 32]) -> Result<Signature> {
+		let (signature, recovery_id) = self
+			.key
+			.sign_prehash_recoverable(&hash)
+			.map_err(|e| WalletError::Signing(e.to_string()))?;
+		let (r, s) = signature.split_bytes();
+		Ok(Signature { r: r.into(), s: s.into(), v: recovery_id.to_byte() })
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct Keystore {
+	version: u8,
+	id: String,
+	address: String,
+	crypto: Crypto,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Crypto {
+	cipher: String,
+	cipherparams: CipherParams,
+	#[serde(with = "hex::serde")]
+	ciphertext: Vec<u8>,
+	kdf: String,
+	kdfparams: KdfParams,
+	#[serde(with = "hex::serde")]
+	mac: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize)]
+struct CipherParams {
+	#[serde(with = "hex::serde")]
+	iv: [u8; 16],
+}
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+	dklen: usize,
+	n: u64,
+	r: u32,
+	p: u32,
+	#[serde(with = "hex::serde")]
+	salt: [u8; 32],
+}
+
+impl KdfParams {
+	fn derive(&self, password: &str) -> Result<[u8; 32]> {
+		let invalid = || WalletError::InvalidKeystore("Invalid scrypt parameters".to_string());
+		if self.dklen != 32 || !self.n.is_power_of_two() {
+			return Err(invalid());
+		}
+
+		let params = scrypt::Params::new(self.n.ilog2() as u8, self.r, self.p, self.dklen)
+			.map_err(|_| invalid())?;
+		let mut derived = [0u8; 32];
+		scrypt::scrypt(password.as_bytes(), &self.salt, &params, &mut derived)
+			.map_err(|_| invalid())?;
+		Ok(derived)
+	}
+}
+
+fn mac(derived: &[u8; 32], ciphertext: &[u8]) -> [u8; 32] {
+	keccak256(&[&derived[16..], ciphertext].concat())
+}
+
+/// A random (version 4) UUID.
+fn uuid(mut bytes: [u8; 16]) -> String {
+	bytes[6] = (bytes[6] & 0x0f) | 0x40;
+	bytes[8] = (bytes[8] & 0x3f) | 0x80;
+	let hex = hex::encode(bytes);
+	format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_address_of_private_key() -> Result<()> {
+		let mut private_key = [0; 32];
+		private_key[31] = 1;
+		let signer = LocalSigner::from_private_key(&private_key)?;
+		assert_eq!(signer.address().to_string(), "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf");
+		assert!(LocalSigner::from_private_key(&[0; 32]).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_keystore_round_trip() -> Result<()> {
+		let signer = LocalSigner::random();
+		let keystore = signer.encrypt("hunter2", 4)?;
+
+		let json = serde_json::to_string(&keystore)?;
+		let keystore: Keystore = serde_json::from_str(&json)?;
+		assert_eq!(LocalSigner::decrypt(&keystore, "hunter2")?.address(), signer.address());
+		assert!(matches!(
+			LocalSigner::decrypt(&keystore, "hunter3"),
+			Err(WalletError::WrongPassword)
+		));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_signature_recovers_the_address() -> Result<()> {
+		let signer = LocalSigner::random();
+		let hash = keccak256(b"transaction");
+		let signature = signer.sign_hash(hash).await?;
+
+		let recovered = k256::ecdsa::VerifyingKey::recover_from_prehash(
+			&hash,
+			&k256::ecdsa::Signature::from_slice(&signature.to_bytes()[..64])?,
+			k256::ecdsa::RecoveryId::from_byte(signature.v).ok_or("Invalid recovery id")?,
+		)?;
+		let public_key = recovered.to_encoded_point(false);
+		assert_eq!(Address::from_public_key(public_key.as_bytes().try_into()?), signer.address());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
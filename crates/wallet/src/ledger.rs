@@ -0,0 +1,183 @@
+//! Ledger devices running the Ethereum app, over USB HID.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use hidapi::{HidApi, HidDevice};
+
+use crate::{Address, Result, Signature, Signer, WalletError};
+
+const LEDGER_VENDOR_ID: u16 = 0x2c97;
+const LEDGER_USAGE_PAGE: u16 = 0xffa0;
+const CHANNEL: u16 = 0x0101;
+const TAG_APDU: u8 = 0x05;
+const PACKET_SIZE: usize = 64;
+/// How long to wait for the user to confirm on the device.
+const READ_TIMEOUT_MS: i32 = 120_000;
+
+const CLA: u8 = 0xe0;
+const INS_GET_ADDRESS: u8 = 0x02;
+const INS_SIGN_TRANSACTION: u8 = 0x04;
+const STATUS_OK: u16 = 0x9000;
+/// Largest data of a single APDU, longer transactions are sent in chunks.
+const MAX_CHUNK: usize = 255;
+
+/// A signer whose key never leaves a Ledger device, the user confirming each transaction on it.
+pub struct LedgerSigner {
+	device: Arc<Mutex<HidDevice>>,
+	path: Vec<u32>,
+	address: Address,
+}
+
+impl LedgerSigner {
+	/// Connect to the first Ledger plugged in, signing with the account `m/44'/60'/0'/0/<index>`.
+	pub async fn connect(account_index: u32) -> Result<Self> {
+		let api = HidApi::new().map_err(device_error)?;
+		let device = api
+			.device_list()
+			.find(|d| d.vendor_id() == LEDGER_VENDOR_ID && d.usage_page() == LEDGER_USAGE_PAGE)
+			.ok_or_else(|| WalletError::Device("No Ledger found".to_string()))?
+			.open_device(&api)
+			.map_err(device_error)?;
+
+		let device = Arc::new(Mutex::new(device));
+		let path = vec![hardened(44), hardened(60), hardened(0), 0, account_index];
+		let response = exchange(&device, INS_GET_ADDRESS, vec![encode_path(&path)]).await?;
+
+		// Public key length and key, then the address as hex.
+		let public_key_len = *response.first().ok_or_else(short_response)? as usize;
+		let address_len = *response.get(1 + public_key_len).ok_or_else(short_response)? as usize;
+		let address = response
+			.get(2 + public_key_len..2 + public_key_len + address_len)
+			.ok_or_else(short_response)?;
+		let address = std::str::from_utf8(address)
+			.map_err(|_| WalletError::Device("Invalid address".to_string()))?
+			.parse()?;
+
+		Ok(Self { device, path, address })
+	}
+}
+
+#[async_trait]
+impl Signer for LedgerSigner {
+	fn address(&self) -> Address {
+		self.address
+	}
+
+	async fn sign_hash(&self, _hash: [u8; 32]) -> Result<Signature> {
+		Err(WalletError::Signing("Ledger only signs transactions it can display".to_string()))
+	}
+
+	async fn sign_transaction(&self, transaction: &[u8]) -> Result<Signature> {
+		let data = [encode_path(&self.path), transaction.to_vec()].concat();
+		let chunks = data.chunks(MAX_CHUNK).map(<[u8]>::to_vec).collect();
+		let response = exchange(&self.device, INS_SIGN_TRANSACTION, chunks).await?;
+		if response.len() < 65 {
+			return Err(short_response());
+		}
+
+		// Legacy transactions get an EIP-155 `v`, truncated to a byte, typed ones their parity.
+		let v = match response[0] {
+			v @ (0 | 1) => v,
+			v @ (27 | 28) => v - 27,
+			v => v.wrapping_sub(35) & 1,
+		};
+		Ok(Signature {
+			r: response[1..33].try_into().expect("Slice to be 32 bytes."),
+			s: response[33..65].try_into().expect("Slice to be 32 bytes."),
+			v,
+		})
+	}
+}
+
+/// Send the chunks of a command, the first one with `P1 = 0x00` and the next ones with `0x80`,
+/// returning the response to the last one.
+async fn exchange(
+	device: &Arc<Mutex<HidDevice>>,
+	ins: u8,
+	chunks: Vec<Vec<u8>>,
+) -> Result<Vec<u8>> {
+	let device = device.clone();
+	tokio::task::spawn_blocking(move || {
+		let device = device.lock().expect("Ledger device not to be poisoned.");
+		let mut response = Vec::new();
+		for (i, chunk) in chunks.iter().enumerate() {
+			let p1 = if i == 0 { 0x00 } else { 0x80 };
+			let apdu = [&[CLA, ins, p1, 0x00, chunk.len() as u8], chunk.as_slice()].concat();
+			write_apdu(&device, &apdu)?;
+			response = read_apdu(&device)?;
+		}
+		Ok(response)
+	})
+	.await
+	.map_err(|e| WalletError::Device(e.to_string()))?
+}
+
+fn write_apdu(device: &HidDevice, apdu: &[u8]) -> Result<()> {
+	let mut message = (apdu.len() as u16).to_be_bytes().to_vec();
+	message.extend_from_slice(apdu);
+	for (sequence, chunk) in message.chunks(PACKET_SIZE - 5).enumerate() {
+		// HID report id, then the packet header.
+		let mut packet = vec![0x00];
+		packet.extend_from_slice(&CHANNEL.to_be_bytes());
+		packet.push(TAG_APDU);
+		packet.extend_from_slice(&(sequence as u16).to_be_bytes());
+		packet.extend_from_slice(chunk);
+		packet.resize(PACKET_SIZE + 1, 0);
+		device.write(&packet).map_err(device_error)?;
+	}
+	Ok(())
+}
+
+fn read_apdu(device: &HidDevice) -> Result<Vec<u8>> {
+	let mut message = Vec::new();
+	let mut expected = None;
+	while expected.is_none_or(|len| message.len() < len) {
+		let mut packet = [0u8; PACKET_SIZE];
+		let read = device.read_timeout(&mut packet, READ_TIMEOUT_MS).map_err(device_error)?;
+		if read < 7 || packet[..3] != [0x01, 0x01, TAG_APDU] {
+			return Err(WalletError::Device("Unexpected packet".to_string()));
+		}
+
+		let payload = match expected {
+			None => {
+				expected = Some(u16::from_be_bytes([packet[5], packet[6]]) as usize);
+				&packet[7..read]
+			},
+			Some(_) => &packet[5..read],
+		};
+		message.extend_from_slice(payload);
+	}
+
+	message.truncate(expected.unwrap_or_default());
+	let status = message
+		.len()
+		.checked_sub(2)
+		.map(|end| u16::from_be_bytes([message[end], message[end + 1]]))
+		.ok_or_else(short_response)?;
+	if status != STATUS_OK {
+		return Err(WalletError::Device(format!("Ledger refused with status {status:#06x}")));
+	}
+	message.truncate(message.len() - 2);
+	Ok(message)
+}
+
+fn encode_path(path: &[u32]) -> Vec<u8> {
+	let mut encoded = vec![path.len() as u8];
+	for index in path {
+		encoded.extend_from_slice(&index.to_be_bytes());
+	}
+	encoded
+}
+
+fn hardened(index: u32) -> u32 {
+	index | 0x8000_0000
+}
+
+fn device_error(e: hidapi::HidError) -> WalletError {
+	WalletError::Device(e.to_string())
+}
+
+fn short_response() -> WalletError {
+	WalletError::Device("Response too short".to_string())
+}
@@ -0,0 +1,121 @@
+//! Signers of the transactions of a node: a local keystore, or a hardware wallet behind the
+//! `ledger` feature. Addresses and signatures follow Ethereum's secp256k1 conventions.
+
+mod error;
+mod fees;
+mod keystore;
+#[cfg(feature = "ledger")]
+pub mod ledger;
+pub mod rpc;
+#[cfg(feature = "chain-tests")]
+pub mod testing;
+mod transaction;
+
+pub use error::{Result, WalletError};
+pub use fees::{FeeEstimate, FeePolicy, FeeSpeed, TransactionRequest};
+pub use keystore::LocalSigner;
+pub use transaction::Eip1559Transaction;
+
+use std::{fmt, str::FromStr};
+
+use async_trait::async_trait;
+use sha3::{Digest, Keccak256};
+
+/// Signs transactions on behalf of an address.
+#[async_trait]
+pub trait Signer: Send + Sync {
+	fn address(&self) -> Address;
+
+	/// Sign a 32 bytes digest.
+	async fn sign_hash(&self, hash: [u8; 32]) -> Result<Signature>;
+
+	/// Sign an encoded transaction. Hardware wallets sign the transaction itself, to show it on
+	/// their screen, others sign its Keccak-256 digest.
+	async fn sign_transaction(&self, transaction: &[u8]) -> Result<Signature> {
+		self.sign_hash(keccak256(transaction)).await
+	}
+}
+
+/// A 20 bytes account address, displayed with its EIP-55 checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address(pub [u8; 20]);
+
+impl Address {
+	/// The address of an uncompressed SEC1 public key, `0x04 || x || y`.
+	pub fn from_public_key(public_key: &[u8; 65]) -> Self {
+		let hash = keccak256(&public_key[1..]);
+		Self(hash[12..].try_into().expect("Digest to end with 20 bytes."))
+	}
+}
+
+impl fmt::Display for Address {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let hex = hex::encode(self.0);
+		let hash = keccak256(hex.as_bytes());
+		let checksummed: String = hex
+			.chars()
+			.enumerate()
+			.map(|(i, c)| {
+				let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+				if nibble >= 8 {
+					c.to_ascii_uppercase()
+				} else {
+					c
+				}
+			})
+			.collect();
+		write!(f, "0x{checksummed}")
+	}
+}
+
+impl FromStr for Address {
+	type Err = WalletError;
+
+	fn from_str(s: &str) -> Result<Self> {
+		let bytes = hex::decode(s.trim_start_matches("0x"))
+			.map_err(|_| WalletError::InvalidAddress(s.to_string()))?;
+		Ok(Self(bytes.try_into().map_err(|_| WalletError::InvalidAddress(s.to_string()))?))
+	}
+}
+
+/// A recoverable secp256k1 signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+	pub r: [u8; 32],
+	pub s: [u8; 32],
+	/// Recovery id, 0 or 1.
+	pub v: u8,
+}
+
+impl Signature {
+	/// `r || s || v`, the 65 bytes encoding of the signature.
+	pub fn to_bytes(&self) -> [u8; 65] {
+		let mut bytes = [0; 65];
+		bytes[..32].copy_from_slice(&self.r);
+		bytes[32..64].copy_from_slice(&self.s);
+		bytes[64] = self.v;
+		bytes
+	}
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+	Keccak256::digest(data).into()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_address_checksum() -> Result<()> {
+		// From the EIP-55 test vectors.
+		let address: Address = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse()?;
+		assert_eq!(address.to_string(), "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+		assert!("0x5aae".parse::<Address>().is_err());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
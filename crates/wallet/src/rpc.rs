@@ -0,0 +1,128 @@
+use serde_json::{json, Value};
+
+use crate::{
+	fees::{quantity, FEE_HISTORY_BLOCKS},
+	Address, FeeEstimate, FeePolicy, Result, TransactionRequest, WalletError,
+};
+
+/// Balance of the address in wei, as of the latest block of the chain behind `rpc_url`.
+pub async fn balance(rpc_url: &str, address: Address) -> Result<u128> {
+	let balance =
+		request(rpc_url, "eth_getBalance", json!([address.to_string(), "latest"])).await?;
+	quantity(&balance).ok_or_else(|| WalletError::Rpc(format!("Invalid balance {balance}")))
+}
+
+/// Estimate the gas and fees of a transaction at the speed of the policy, refusing it when the
+/// estimate exceeds one of the policy caps.
+pub async fn estimate_fees(
+	rpc_url: &str,
+	transaction: &TransactionRequest,
+	policy: &FeePolicy,
+) -> Result<FeeEstimate> {
+	let mut call = json!({
+		"value": format!("{:#x}", transaction.value),
+		"data": format!("0x{}", hex::encode(&transaction.data)),
+	});
+	if let Some(from) = transaction.from {
+		call["from"] = json!(from.to_string());
+	}
+	if let Some(to) = transaction.to {
+		call["to"] = json!(to.to_string());
+	}
+	let gas = request(rpc_url, "eth_estimateGas", json!([call])).await?;
+	let gas = quantity(&gas)
+		.and_then(|gas| u64::try_from(gas).ok())
+		.ok_or_else(|| WalletError::Rpc(format!("Invalid gas estimate {gas}")))?;
+
+	let fee_history = request(
+		rpc_url,
+		"eth_feeHistory",
+		json!([format!("{FEE_HISTORY_BLOCKS:#x}"), "latest", [policy.speed.reward_percentile()]]),
+	)
+	.await?;
+	policy.check(policy.estimate(gas, &fee_history)?)
+}
+
+/// Id of the chain behind `rpc_url`, which its transactions are signed for.
+pub async fn chain_id(rpc_url: &str) -> Result<u64> {
+	let chain_id = request(rpc_url, "eth_chainId", json!([])).await?;
+	quantity(&chain_id)
+		.and_then(|chain_id| u64::try_from(chain_id).ok())
+		.ok_or_else(|| WalletError::Rpc(format!("Invalid chain id {chain_id}")))
+}
+
+/// Nonce of the next transaction of the address, after its pending ones.
+pub async fn nonce(rpc_url: &str, address: Address) -> Result<u64> {
+	let nonce =
+		request(rpc_url, "eth_getTransactionCount", json!([address.to_string(), "pending"]))
+			.await?;
+	quantity(&nonce)
+		.and_then(|nonce| u64::try_from(nonce).ok())
+		.ok_or_else(|| WalletError::Rpc(format!("Invalid nonce {nonce}")))
+}
+
+/// Submit a raw signed transaction, returning its hash.
+pub async fn send_raw_transaction(rpc_url: &str, transaction: &[u8]) -> Result<String> {
+	let hash = request(
+		rpc_url,
+		"eth_sendRawTransaction",
+		json!([format!("0x{}", hex::encode(transaction))]),
+	)
+	.await?;
+	hash.as_str()
+		.map(str::to_string)
+		.ok_or_else(|| WalletError::Rpc(format!("Invalid transaction hash {hash}")))
+}
+
+/// Call a JSON-RPC method, returning its result.
+pub(crate) async fn request(rpc_url: &str, method: &str, params: Value) -> Result<Value> {
+	let request = json!({
+		"jsonrpc": "2.0",
+		"id": 1,
+		"method": method,
+		"params": params,
+	});
+	let mut response: Value = reqwest::Client::new()
+		.post(rpc_url)
+		.json(&request)
+		.send()
+		.await
+		.map_err(|e| WalletError::Rpc(e.to_string()))?
+		.json()
+		.await
+		.map_err(|e| WalletError::Rpc(e.to_string()))?;
+
+	if let Some(error) = response.get("error") {
+		return Err(WalletError::Rpc(error.to_string()));
+	}
+	match response.get_mut("result") {
+		Some(result) => Ok(result.take()),
+		None => Err(WalletError::Rpc(format!("Unexpected response: {response}"))),
+	}
+}
+
+/// Format an amount of wei in ether, without trailing zeros.
+pub fn format_ether(wei: u128) -> String {
+	const WEI_PER_ETHER: u128 = 1_000_000_000_000_000_000;
+	let fraction = format!("{:018}", wei % WEI_PER_ETHER);
+	match fraction.trim_end_matches('0') {
+		"" => format!("{}", wei / WEI_PER_ETHER),
+		fraction => format!("{}.{fraction}", wei / WEI_PER_ETHER),
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_format_ether() {
+		assert_eq!(format_ether(0), "0");
+		assert_eq!(format_ether(2_500_000_000_000_000_000), "2.5");
+		assert_eq!(format_ether(1), "0.000000000000000001");
+	}
+}
+
+// endregion: --- Tests
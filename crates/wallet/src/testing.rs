@@ -0,0 +1,132 @@
+//! A local chain for the integration tests of the code paying and settling on-chain, behind the
+//! `chain-tests` feature. It runs anvil, from Foundry, which must be on the `PATH` or at
+//! `$ANVIL_BIN`.
+
+use std::{
+	net::TcpListener,
+	process::Stdio,
+	time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use tokio::process::{Child, Command};
+
+use crate::{rpc, Address, LocalSigner, Result, Signer, WalletError};
+
+/// Environment variable overriding the path of the anvil binary.
+pub const ANVIL_BIN_ENV: &str = "ANVIL_BIN";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An anvil chain on a free local port, mining each transaction as it is submitted. It is killed
+/// when dropped.
+pub struct TestChain {
+	child: Child,
+	rpc_url: String,
+}
+
+impl TestChain {
+	pub async fn spawn() -> Result<Self> {
+		let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+		let anvil = std::env::var(ANVIL_BIN_ENV).unwrap_or_else(|_| "anvil".to_string());
+		let child = Command::new(anvil)
+			.args(["--port", &port.to_string(), "--silent"])
+			.stdout(Stdio::null())
+			.kill_on_drop(true)
+			.spawn()?;
+
+		let chain = Self { child, rpc_url: format!("http://127.0.0.1:{port}") };
+		let started = Instant::now();
+		while let Err(e) = rpc::request(&chain.rpc_url, "eth_chainId", json!([])).await {
+			if started.elapsed() > STARTUP_TIMEOUT {
+				return Err(WalletError::Rpc(format!("anvil did not start: {e}")));
+			}
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		}
+		Ok(chain)
+	}
+
+	pub fn rpc_url(&self) -> &str {
+		&self.rpc_url
+	}
+
+	/// A new account holding `wei`.
+	pub async fn funded_account(&self, wei: u128) -> Result<LocalSigner> {
+		let signer = LocalSigner::random();
+		self.set_balance(signer.address(), wei).await?;
+		Ok(signer)
+	}
+
+	pub async fn set_balance(&self, address: Address, wei: u128) -> Result<()> {
+		self.request("anvil_setBalance", json!([address.to_string(), format!("{wei:#x}")]))
+			.await?;
+		Ok(())
+	}
+
+	/// Deploy a contract from its creation bytecode, returning its address. The deployment is
+	/// sent from one of the accounts anvil unlocks, so it needs no signer.
+	pub async fn deploy(&self, bytecode: &[u8]) -> Result<Address> {
+		let accounts = self.request("eth_accounts", json!([])).await?;
+		let from = accounts.get(0).cloned().ok_or_else(|| unexpected(&accounts))?;
+		let tx = json!({"from": from, "data": format!("0x{}", hex::encode(bytecode))});
+		let hash = self.request("eth_sendTransaction", json!([tx])).await?;
+
+		let receipt = self.request("eth_getTransactionReceipt", json!([hash])).await?;
+		if receipt["status"] != "0x1" {
+			return Err(WalletError::Rpc(format!("Deployment failed: {receipt}")));
+		}
+		receipt["contractAddress"].as_str().ok_or_else(|| unexpected(&receipt))?.parse()
+	}
+
+	/// Move the time of the chain forward and mine a block, to reach deadlines.
+	pub async fn advance_time(&self, by: Duration) -> Result<()> {
+		self.request("evm_increaseTime", json!([by.as_secs()])).await?;
+		self.request("evm_mine", json!([])).await?;
+		Ok(())
+	}
+
+	pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+		rpc::request(&self.rpc_url, method, params).await
+	}
+
+	/// Stop the chain, waiting for anvil to exit.
+	pub async fn stop(mut self) -> Result<()> {
+		self.child.kill().await?;
+		Ok(())
+	}
+}
+
+fn unexpected(response: &Value) -> WalletError {
+	WalletError::Rpc(format!("Unexpected response: {response}"))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	/// Creation code of a contract whose runtime code is a single `STOP`.
+	const STOP_CONTRACT: [u8; 13] =
+		[0x60, 0x01, 0x60, 0x0c, 0x60, 0x00, 0x39, 0x60, 0x01, 0x60, 0x00, 0xf3, 0x00];
+
+	#[tokio::test]
+	async fn test_chain_fixtures() -> Result<()> {
+		let chain = TestChain::spawn().await?;
+
+		let account = chain.funded_account(1_000_000).await?;
+		assert_eq!(rpc::balance(chain.rpc_url(), account.address()).await?, 1_000_000);
+
+		let contract = chain.deploy(&STOP_CONTRACT).await?;
+		let code = chain.request("eth_getCode", json!([contract.to_string(), "latest"])).await?;
+		assert_eq!(code, "0x00");
+
+		chain.advance_time(Duration::from_secs(3600)).await?;
+		chain.stop().await?;
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,184 @@
+//! EIP-1559 transactions, the typed envelope paying a base fee and a priority fee per gas, RLP
+//! encoded to be signed and submitted to the chain.
+
+use crate::{Address, FeeEstimate, Result, Signature, Signer, TransactionRequest};
+
+/// Type of the EIP-1559 transactions, prefixed to their RLP encoding.
+const EIP1559_TYPE: u8 = 0x02;
+
+/// A transaction paying the fees of a [`FeeEstimate`], with an empty access list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eip1559Transaction {
+	pub chain_id: u64,
+	pub nonce: u64,
+	pub max_priority_fee_per_gas: u128,
+	pub max_fee_per_gas: u128,
+	pub gas_limit: u64,
+	/// Recipient, unset to deploy a contract.
+	pub to: Option<Address>,
+	pub value: u128,
+	pub data: Vec<u8>,
+}
+
+impl Eip1559Transaction {
+	/// The `request` paying the estimated `fees`, as transaction `nonce` of its sender on the chain
+	/// `chain_id`.
+	pub fn new(request: TransactionRequest, fees: &FeeEstimate, chain_id: u64, nonce: u64) -> Self {
+		Self {
+			chain_id,
+			nonce,
+			max_priority_fee_per_gas: fees.max_priority_fee_per_gas,
+			max_fee_per_gas: fees.max_fee_per_gas,
+			gas_limit: fees.gas_limit,
+			to: request.to,
+			value: request.value,
+			data: request.data,
+		}
+	}
+
+	/// `0x02 || rlp([chain_id, nonce, ..., access_list])`, what the sender signs.
+	pub fn signing_payload(&self) -> Vec<u8> {
+		self.encode(None)
+	}
+
+	/// `0x02 || rlp([chain_id, nonce, ..., access_list, y_parity, r, s])`, the raw transaction
+	/// submitted to the chain.
+	pub fn encode_signed(&self, signature: &Signature) -> Vec<u8> {
+		self.encode(Some(signature))
+	}
+
+	/// Sign the transaction with `signer`, returning the raw signed transaction.
+	pub async fn sign(&self, signer: &dyn Signer) -> Result<Vec<u8>> {
+		let signature = signer.sign_transaction(&self.signing_payload()).await?;
+		Ok(self.encode_signed(&signature))
+	}
+
+	fn encode(&self, signature: Option<&Signature>) -> Vec<u8> {
+		let mut fields = Vec::new();
+		encode_uint(self.chain_id.into(), &mut fields);
+		encode_uint(self.nonce.into(), &mut fields);
+		encode_uint(self.max_priority_fee_per_gas, &mut fields);
+		encode_uint(self.max_fee_per_gas, &mut fields);
+		encode_uint(self.gas_limit.into(), &mut fields);
+		encode_bytes(self.to.as_ref().map_or(&[], |to| &to.0[..]), &mut fields);
+		encode_uint(self.value, &mut fields);
+		encode_bytes(&self.data, &mut fields);
+		encode_list(&[], &mut fields);
+		if let Some(signature) = signature {
+			encode_uint(signature.v.into(), &mut fields);
+			encode_bytes(strip_zeros(&signature.r), &mut fields);
+			encode_bytes(strip_zeros(&signature.s), &mut fields);
+		}
+
+		let mut transaction = vec![EIP1559_TYPE];
+		encode_list(&fields, &mut transaction);
+		transaction
+	}
+}
+
+/// RLP encoding of a string of bytes.
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+	match bytes {
+		[byte] if *byte < 0x80 => out.push(*byte),
+		_ => {
+			encode_length(0x80, bytes.len(), out);
+			out.extend_from_slice(bytes);
+		},
+	}
+}
+
+/// RLP encoding of an integer, as its big-endian bytes without leading zeros.
+fn encode_uint(value: u128, out: &mut Vec<u8>) {
+	encode_bytes(strip_zeros(&value.to_be_bytes()), out)
+}
+
+/// RLP encoding of a list, from the concatenated encodings of its items.
+fn encode_list(items: &[u8], out: &mut Vec<u8>) {
+	encode_length(0xc0, items.len(), out);
+	out.extend_from_slice(items);
+}
+
+/// The prefix of a string, at `offset` 0x80, or of a list, at 0xc0, of `len` bytes.
+fn encode_length(offset: u8, len: usize, out: &mut Vec<u8>) {
+	if len < 56 {
+		out.push(offset + len as u8);
+	} else {
+		let len = len.to_be_bytes();
+		let len = strip_zeros(&len);
+		out.push(offset + 55 + len.len() as u8);
+		out.extend_from_slice(len);
+	}
+}
+
+fn strip_zeros(bytes: &[u8]) -> &[u8] {
+	let start = bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len());
+	&bytes[start..]
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::{keccak256, LocalSigner};
+
+	#[test]
+	fn test_rlp_encoding() {
+		let encoded = |encode: &dyn Fn(&mut Vec<u8>)| {
+			let mut out = Vec::new();
+			encode(&mut out);
+			hex::encode(out)
+		};
+		// From the examples of the RLP specification.
+		assert_eq!(encoded(&|out| encode_bytes(b"dog", out)), "83646f67");
+		assert_eq!(encoded(&|out| encode_bytes(b"", out)), "80");
+		assert_eq!(encoded(&|out| encode_bytes(&[0x0f], out)), "0f");
+		assert_eq!(encoded(&|out| encode_uint(0, out)), "80");
+		assert_eq!(encoded(&|out| encode_uint(1024, out)), "820400");
+		assert_eq!(encoded(&|out| encode_list(&[], out)), "c0");
+		let items = [&b"\x83cat"[..], &b"\x83dog"[..]].concat();
+		assert_eq!(encoded(&|out| encode_list(&items, out)), "c88363617483646f67");
+		let lorem = b"Lorem ipsum dolor sit amet, consectetur adipisicing elit";
+		assert_eq!(encoded(&|out| encode_bytes(lorem, out)), format!("b838{}", hex::encode(lorem)));
+	}
+
+	#[tokio::test]
+	async fn test_signed_transaction_recovers_the_sender() -> Result<()> {
+		let to: Address = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse()?;
+		let request = TransactionRequest { to: Some(to), ..Default::default() };
+		let fees = FeeEstimate {
+			gas_limit: 21_000,
+			base_fee_per_gas: 1,
+			max_priority_fee_per_gas: 1,
+			max_fee_per_gas: 2,
+		};
+		let transaction = Eip1559Transaction::new(request, &fees, 1, 0);
+
+		let payload = transaction.signing_payload();
+		assert_eq!(
+			hex::encode(&payload),
+			format!("02df0180010282520894{}8080c0", hex::encode(to.0))
+		);
+
+		let signer = LocalSigner::random();
+		let signed = transaction.sign(&signer).await?;
+		let signature = signer.sign_transaction(&payload).await?;
+		assert_eq!(signed, transaction.encode_signed(&signature));
+		// The signature ends the fields of the transaction.
+		assert_eq!(signed[0], EIP1559_TYPE);
+		assert!(signed.ends_with(strip_zeros(&signature.s)));
+		let recovered = k256::ecdsa::VerifyingKey::recover_from_prehash(
+			&keccak256(&payload),
+			&k256::ecdsa::Signature::from_slice(&signature.to_bytes()[..64])?,
+			k256::ecdsa::RecoveryId::from_byte(signature.v).ok_or("Invalid recovery id")?,
+		)?;
+		let public_key = recovered.to_encoded_point(false);
+		assert_eq!(Address::from_public_key(public_key.as_bytes().try_into()?), signer.address());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,338 @@
+//! A three-node swarm launched in a single process: a bootstrap node, a provider answering with the
+//! mock LLM backend and a requester that discovers the provider, its agent card and pricing through
+//! the DHT and calls it, cancels a request and auctions a task the provider bids on.
+
+use std::{
+	error::Error,
+	net::TcpListener,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ai_agent::backend::{LlmBackend, MockBackend};
+use futures::{prelude::*, StreamExt};
+use network::{
+	types::{BidResponse, Event, NetworkError, TaskProposal, TaskType},
+	AgentCard, AuctionConfig, Client, Multiaddr, NetworkConfig, Protocol, Quorum,
+};
+use tokio::task::spawn;
+use tokio_util::sync::CancellationToken;
+
+pub const AGENT_NAME: &str = "echo";
+pub const PRICING_KEY: &str = "echo/pricing";
+pub const PRICING: &str = "0.01";
+pub const ARTIFACT_NAME: &str = "echo/weights";
+/// Larger than any response of the control plane, so the artifact can only go over the data plane.
+pub const ARTIFACT_SIZE: usize = 4 * 1024 * 1024;
+/// Message of the request the requester cancels before its answer arrives.
+pub const CANCELLED_MESSAGE: &str = "Never mind";
+/// What the provider bids on the tasks proposed for its agent.
+pub const BID: f64 = 0.005;
+pub const MAX_BID: f64 = 0.02;
+/// How long the mock backend takes to answer, for the cancelled request to still be in flight.
+const BACKEND_LATENCY: Duration = Duration::from_millis(200);
+const AUCTION_WINDOW: Duration = Duration::from_secs(1);
+const REQUEST_ATTEMPTS: usize = 5;
+const STREAM_WINDOW: u32 = 2;
+const STREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A node running in the background of the current process.
+pub struct DemoNode {
+	pub client: Client,
+	pub peer_id: network::PeerId,
+	pub address: Multiaddr,
+}
+
+/// What the requester observed while running the demo.
+#[derive(Debug)]
+pub struct DemoReport {
+	pub bootstrap: network::PeerId,
+	pub provider: network::PeerId,
+	pub providers_found: usize,
+	pub card: AgentCard,
+	pub pricing: String,
+	/// Peers the pricing record is replicated to.
+	pub closest_peers: Vec<network::PeerId>,
+	/// Peers in the routing table of the requester.
+	pub routing_peers: usize,
+	pub response: String,
+	/// The same message answered through a stream.
+	pub streamed: String,
+	/// Bytes of the artifact fetched from the provider.
+	pub artifact_size: usize,
+	/// Round-trip time of the ping answered over a new connection to the provider.
+	pub dial_back_rtt: Duration,
+	/// Whether the provider still received the cancelled request, its answer being discarded.
+	pub cancelled_served: bool,
+	/// Answer to the request sent right after the cancelled one.
+	pub after_cancel: String,
+	/// Bidder that won the auction of the task, and its bid.
+	pub winner: network::PeerId,
+	pub winning_bid: f64,
+}
+
+/// Start a node listening on a free local port, optionally dialing `bootstrap` once it is up.
+pub async fn spawn_node(
+	seed: u8,
+	bootstrap: Option<&DemoNode>,
+	cancellation_token: CancellationToken,
+) -> Result<(DemoNode, impl Stream<Item = Event>), Box<dyn Error>> {
+	let (mut client, events, peer_id, event_loop) =
+		network::new(NetworkConfig { secret_key_seed: Some(seed), ..Default::default() }).await?;
+	spawn(event_loop.run(cancellation_token));
+
+	// Listen on every interface: peers may learn our address through mDNS rather than loopback.
+	let port = free_port()?;
+	let listen_address: Multiaddr = format!("/ip4/0.0.0.0/tcp/{port}").parse()?;
+	client.start_listening(listen_address).await.map_err(|e| e.to_string())?;
+	let address: Multiaddr = format!("/ip4/127.0.0.1/tcp/{port}").parse()?;
+
+	if let Some(bootstrap) = bootstrap {
+		client
+			.dial(bootstrap.peer_id, bootstrap.address.clone())
+			.await
+			.map_err(|e| e.to_string())?;
+	}
+
+	let address = address.with(Protocol::P2p(peer_id));
+	tracing::info!("Node {seed} listening on {address}");
+
+	Ok((DemoNode { client, peer_id, address }, events))
+}
+
+/// Run the whole demo. The nodes shut down once it is over, whether it succeeded or not.
+pub async fn run(message: &str) -> Result<DemoReport, Box<dyn Error>> {
+	let cancellation_token = CancellationToken::new();
+	let _shutdown = cancellation_token.clone().drop_guard();
+
+	// -- Bootstrap node
+	// Only the provider reads its events, the ones of the other nodes are dropped.
+	let (bootstrap, _) = spawn_node(1, None, cancellation_token.clone()).await?;
+
+	// -- Provider node
+	let (mut provider, provider_events) =
+		spawn_node(2, Some(&bootstrap), cancellation_token.clone()).await?;
+	let card = AgentCard {
+		capabilities: vec!["echo".to_string()],
+		pricing: PRICING.parse()?,
+		task_types: vec![TaskType::DataProcessing],
+		// Every request waits for the requester to answer a challenge.
+		challenge_above: Some(0),
+		..AgentCard::new(AGENT_NAME, env!("CARGO_PKG_VERSION"))
+	};
+	let providing = provider.client.start_providing(card).await.map_err(|e| e.to_string())?;
+	providing.result().await.map_err(|e| e.to_string())?;
+	provider
+		.client
+		.put_record(PRICING_KEY.to_string(), PRICING.as_bytes().to_vec(), Quorum::One)
+		.await
+		.map_err(|e| e.to_string())?;
+	let received = Arc::new(Mutex::new(Vec::new()));
+	let backend = Arc::new(MockBackend::echo().with_latency(BACKEND_LATENCY));
+	spawn(serve(provider.client.clone(), provider_events, backend, received.clone()));
+
+	// -- Requester node
+	let (mut requester, _) = spawn_node(3, Some(&bootstrap), cancellation_token.clone()).await?;
+
+	let providers = requester
+		.client
+		.get_providers(AGENT_NAME.to_string())
+		.await
+		.map_err(|e| e.to_string())?
+		.result()
+		.await
+		.map_err(|e| e.to_string())?;
+	tracing::info!("Discovered providers for {AGENT_NAME}: {providers:?}");
+	let providers_found = providers.len();
+	let mut cards = requester.client.verified_providers(AGENT_NAME, providers).await;
+	let Some(card) = cards.remove(&provider.peer_id) else {
+		return Err(
+			format!("Provider {} was not discovered and verified.", provider.peer_id).into()
+		);
+	};
+	let provider_id = provider.peer_id;
+
+	let pricing = requester
+		.client
+		.get_record(PRICING_KEY.to_string(), Quorum::One)
+		.await
+		.map_err(|e| e.to_string())?;
+	let closest_peers = requester
+		.client
+		.get_closest_peers(PRICING_KEY.to_string())
+		.await
+		.map_err(|e| e.to_string())?;
+	let routing_peers = requester
+		.client
+		.kbuckets()
+		.await
+		.map_err(|e| e.to_string())?
+		.iter()
+		.map(|bucket| bucket.peers.len())
+		.sum();
+
+	// Freshly discovered peers may only be known through observed (ephemeral) addresses until mDNS
+	// or identify catch up, so give the dial a few chances.
+	let mut attempt = 1;
+	let response = loop {
+		let mut client = requester.client.clone();
+		let request = async {
+			client.authenticate(provider_id).await?;
+			client
+				.request_agent(provider_id, AGENT_NAME.to_string(), message.to_string())
+				.await
+		};
+		match request.await {
+			Ok(response) => break response,
+			Err(e) if attempt < REQUEST_ATTEMPTS => {
+				tracing::warn!("Request attempt {attempt} failed: {e}");
+				attempt += 1;
+				tokio::time::sleep(Duration::from_millis(500)).await;
+			},
+			Err(e) => return Err(e.to_string().into()),
+		}
+	};
+
+	let dial_back_rtt = requester.client.dial_back(provider_id).await.map_err(|e| e.to_string())?;
+
+	let streamed = requester
+		.client
+		.request_agent_stream(
+			provider_id,
+			AGENT_NAME.to_string(),
+			message.to_string(),
+			STREAM_WINDOW,
+			STREAM_TIMEOUT,
+		)
+		.await
+		.map_err(|e| e.to_string())?
+		.collect()
+		.await
+		.map_err(|e| e.to_string())?
+		.output;
+
+	let artifact = requester
+		.client
+		.request_artifact(provider_id, ARTIFACT_NAME.to_string())
+		.await
+		.map_err(|e| e.to_string())?;
+
+	// -- Cancellation
+	// The provider answers the requests one after the other, so the cancelled one is answered
+	// first and its answer must be discarded rather than taken for the next one.
+	let cancelled = requester
+		.client
+		.send_agent_request(
+			provider_id,
+			AGENT_NAME.to_string(),
+			CANCELLED_MESSAGE.to_string(),
+			Vec::new(),
+		)
+		.await
+		.map_err(|e| e.to_string())?;
+	requester.client.cancel_request(cancelled).await;
+	let after_cancel = requester
+		.client
+		.request_agent(provider_id, AGENT_NAME.to_string(), message.to_string())
+		.await
+		.map_err(|e| e.to_string())?;
+	let cancelled_served = received
+		.lock()
+		.expect("Lock not to be poisoned.")
+		.iter()
+		.any(|m| m == CANCELLED_MESSAGE);
+
+	// -- Bidding
+	let proposal = TaskProposal {
+		agent_name: AGENT_NAME.to_string(),
+		task_id: format!("{AGENT_NAME}-task"),
+		task_type: TaskType::DataProcessing,
+		task_message: message.to_string(),
+		max_bid: MAX_BID,
+		deadline: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + 60,
+		requirements: Default::default(),
+	};
+	let config = AuctionConfig { window: AUCTION_WINDOW, ..Default::default() };
+	// The proposal is only gossiped once the subscription of the provider reached the requester.
+	let winner = loop {
+		match requester.client.run_auction(proposal.clone(), config.clone()).await {
+			Err(e) if matches!(e.downcast_ref(), Some(NetworkError::Gossip(_))) => {
+				tokio::time::sleep(Duration::from_millis(100)).await
+			},
+			result => break result.map_err(|e| e.to_string())?,
+		}
+	};
+	let Some(winner) = winner else {
+		return Err(format!("No bid was received for task {}.", proposal.task_id).into());
+	};
+
+	Ok(DemoReport {
+		bootstrap: bootstrap.peer_id,
+		provider: provider_id,
+		providers_found,
+		card,
+		pricing: String::from_utf8(pricing)?,
+		closest_peers,
+		routing_peers,
+		response: String::from_utf8(response)?,
+		streamed,
+		artifact_size: artifact.len(),
+		dial_back_rtt,
+		cancelled_served,
+		after_cancel: String::from_utf8(after_cancel)?,
+		winner: winner.bidder,
+		winning_bid: winner.bid.bid,
+	})
+}
+
+async fn serve(
+	mut client: Client,
+	mut events: impl Stream<Item = Event> + Unpin,
+	backend: Arc<dyn LlmBackend>,
+	received: Arc<Mutex<Vec<String>>>,
+) {
+	while let Some(event) = events.next().await {
+		match event {
+			Event::LLMInboundRequest { agent_name, message, channel, .. } => {
+				tracing::info!("Provider received request for agent: {agent_name}");
+				received.lock().expect("Lock not to be poisoned.").push(message.clone());
+				match backend.complete(&message).await {
+					Ok(output) => client.respond_llm(output.into_bytes(), channel).await,
+					Err(e) => tracing::error!("Backend {} failed: {e}", backend.name()),
+				}
+			},
+			Event::LLMStreamRequest { agent_name, message, tokens } => {
+				tracing::info!("Provider received stream request for agent: {agent_name}");
+				let backend = backend.clone();
+				spawn(async move {
+					if let Err(e) = backend.complete_stream(&message, tokens).await {
+						tracing::error!("Backend {} failed: {e}", backend.name());
+					}
+				});
+			},
+			Event::InboundTaskProposal { proposer, task_proposal }
+				if task_proposal.agent_name == AGENT_NAME =>
+			{
+				tracing::info!("Provider bidding {BID} on task {}", task_proposal.task_id);
+				let bid = BidResponse {
+					task_id: task_proposal.task_id,
+					capabilities: vec!["echo".to_string()],
+					bid: BID,
+					payment_address: None,
+				};
+				if let Err(e) = client.submit_bid(proposer, bid).await {
+					tracing::error!("Failed to bid: {e}");
+				}
+			},
+			Event::ArtifactRequest { name, channel } => {
+				tracing::info!("Provider received request for artifact: {name}");
+				client.respond_artifact(vec![0; ARTIFACT_SIZE], channel).await;
+			},
+			_ => {},
+		}
+	}
+}
+
+fn free_port() -> std::io::Result<u16> {
+	Ok(TcpListener::bind("0.0.0.0:0")?.local_addr()?.port())
+}
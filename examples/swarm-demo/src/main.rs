@@ -0,0 +1,32 @@
+use std::error::Error;
+
+use tracing_subscriber::EnvFilter;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+	let _ = tracing_subscriber::fmt()
+		.with_level(true)
+		.with_line_number(true)
+		.with_env_filter(EnvFilter::from_env("RUST_LOG"))
+		.try_init();
+
+	let report = swarm_demo::run("Hello from the requester!").await?;
+
+	println!("Bootstrap node: {}", report.bootstrap);
+	println!("Provider node:  {} ({} provider(s) found)", report.provider, report.providers_found);
+	println!("Agent card:     {:?}", report.card);
+	println!("Pricing:        {}", report.pricing);
+	println!("Replicated to:  {:?}", report.closest_peers);
+	println!("Routing table:  {} peer(s)", report.routing_peers);
+	println!("Response:       {}", report.response);
+	println!("Streamed:       {}", report.streamed);
+	println!("Artifact:       {} bytes", report.artifact_size);
+	println!("Dial-back:      {:?}", report.dial_back_rtt);
+	println!(
+		"Cancelled:      served {}, next answer {}",
+		report.cancelled_served, report.after_cancel
+	);
+	println!("Auction:        won by {} bidding {}", report.winner, report.winning_bid);
+
+	Ok(())
+}
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_three_node_swarm_round_trip() {
+	let message = "ping over a stream";
+	let report = tokio::time::timeout(Duration::from_secs(60), swarm_demo::run(message))
+		.await
+		.expect("Demo not to time out.")
+		.expect("Demo to succeed.");
+
+	assert!(report.providers_found >= 1);
+	assert_ne!(report.provider, report.bootstrap);
+	assert_eq!(report.card.name, swarm_demo::AGENT_NAME);
+	assert_eq!(report.pricing, swarm_demo::PRICING);
+	assert!(report.closest_peers.contains(&report.provider));
+	assert!(report.routing_peers >= 2);
+	assert_eq!(report.response, message);
+	assert_eq!(report.streamed, message);
+	assert_eq!(report.artifact_size, swarm_demo::ARTIFACT_SIZE);
+	assert!(report.cancelled_served);
+	assert_eq!(report.after_cancel, message);
+	assert_eq!(report.winner, report.provider);
+	assert_eq!(report.winning_bid, swarm_demo::BID);
+}
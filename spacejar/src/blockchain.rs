@@ -1,6 +1,12 @@
 use async_trait::async_trait;
+use futures::{stream, SinkExt, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use serde_json::{json, Value};
+use std::{fmt, pin::Pin, time::Duration};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use wallet::{rpc, Eip1559Transaction, FeeEstimate, FeePolicy, Signer, TransactionRequest};
 
 use crate::error::RuntimeError;
 
@@ -31,9 +37,597 @@ pub trait BlockchainManager: Send + Sync {
 	/// Submit a transaction to the blockchain
 	async fn submit_transaction(&self, tx_data: Vec<u8>) -> Result<String, RuntimeError>;
 
+	/// Estimate the gas and fees of a transaction at the speed of the policy, to show them before
+	/// submitting it. Estimates above the caps of the policy are refused.
+	async fn estimate_fees(
+		&self,
+		tx: &TransactionRequest,
+		policy: &FeePolicy,
+	) -> Result<FeeEstimate, RuntimeError>;
+
+	/// Sign a transaction paying the estimated fees with the given signer, then submit it. The
+	/// manager knows how the chain encodes the fees and the signature into the transaction.
+	async fn sign_and_submit(
+		&self,
+		tx: TransactionRequest,
+		fees: FeeEstimate,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError>;
+
+	/// Estimate the fees of a transaction, then sign and submit it unless they exceed the caps of
+	/// the policy
+	async fn submit_with_policy(
+		&self,
+		tx: TransactionRequest,
+		policy: &FeePolicy,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError> {
+		let fees = self.estimate_fees(&tx, policy).await?;
+		self.sign_and_submit(tx, fees, signer).await
+	}
+
 	/// Get the current state of a transaction
 	async fn get_transaction_state(&self, tx_id: &str) -> Result<TransactionState, RuntimeError>;
 
 	/// Verify a transaction proof
 	async fn verify_proof(&self, proof: &[u8]) -> Result<bool, RuntimeError>;
+
+	/// Subscribe to the on-chain events matching the filter, such as agent registry updates or
+	/// escrow settlements
+	async fn subscribe_events(&self, filter: EventFilter)
+		-> Result<ChainEventStream, RuntimeError>;
+}
+
+/// Stream of the on-chain events of a subscription
+pub type ChainEventStream = Pin<Box<dyn Stream<Item = Result<ChainEvent, RuntimeError>> + Send>>;
+
+/// Which on-chain events to subscribe to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventFilter {
+	/// Contracts emitting the events, any contract when empty
+	pub addresses: Vec<String>,
+	/// Topics by position, `None` matching any topic at that position
+	pub topics: Vec<Option<String>>,
+	/// First block to report events from, the latest block when unset
+	pub from_block: Option<u64>,
+}
+
+/// An event emitted on-chain by a contract
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainEvent {
+	pub address: String,
+	pub topics: Vec<String>,
+	pub data: Vec<u8>,
+	pub block_number: Option<u64>,
+	pub transaction_hash: Option<String>,
+	pub log_index: Option<u64>,
+}
+
+/// [`BlockchainManager`] of an EVM chain through the JSON-RPC endpoint of one of its nodes. The
+/// transactions are signed as EIP-1559 transactions for the chain of the node, and the events are
+/// subscribed to through an [`EvmLogSubscriber`] of the node.
+pub struct EvmBlockchainManager {
+	rpc_url: String,
+	subscriber: EvmLogSubscriber,
+}
+
+impl EvmBlockchainManager {
+	/// A manager of the chain of the node at the HTTP endpoint `rpc_url`.
+	pub fn new(rpc_url: impl Into<String>) -> Self {
+		let rpc_url = rpc_url.into();
+		Self { subscriber: EvmLogSubscriber::new(rpc_url.clone()), rpc_url }
+	}
+
+	/// Subscribe to the events over this WebSocket endpoint of the node, see
+	/// [`EvmLogSubscriber::with_ws_url`].
+	pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+		self.subscriber = self.subscriber.with_ws_url(ws_url);
+		self
+	}
+}
+
+#[async_trait]
+impl BlockchainManager for EvmBlockchainManager {
+	/// Submit a raw signed transaction
+	async fn submit_transaction(&self, tx_data: Vec<u8>) -> Result<String, RuntimeError> {
+		Ok(rpc::send_raw_transaction(&self.rpc_url, &tx_data).await?)
+	}
+
+	async fn estimate_fees(
+		&self,
+		tx: &TransactionRequest,
+		policy: &FeePolicy,
+	) -> Result<FeeEstimate, RuntimeError> {
+		Ok(rpc::estimate_fees(&self.rpc_url, tx, policy).await?)
+	}
+
+	/// Sign the transaction as the next one of the signer on the chain of the node
+	async fn sign_and_submit(
+		&self,
+		tx: TransactionRequest,
+		fees: FeeEstimate,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError> {
+		let chain_id = rpc::chain_id(&self.rpc_url).await?;
+		let nonce = rpc::nonce(&self.rpc_url, signer.address()).await?;
+		let transaction = Eip1559Transaction::new(tx, &fees, chain_id, nonce);
+		self.submit_transaction(transaction.sign(signer).await?).await
+	}
+
+	async fn get_transaction_state(&self, tx_id: &str) -> Result<TransactionState, RuntimeError> {
+		let receipt = self.subscriber.call("eth_getTransactionReceipt", json!([tx_id])).await?;
+		if receipt.is_null() {
+			let transaction =
+				self.subscriber.call("eth_getTransactionByHash", json!([tx_id])).await?;
+			return Ok(match transaction.is_null() {
+				true => TransactionState::Unknown,
+				false => TransactionState::Submitted,
+			});
+		}
+		let block = receipt["blockNumber"]
+			.as_str()
+			.and_then(|block| u64::from_str_radix(block.trim_start_matches("0x"), 16).ok());
+		Ok(match (receipt["status"].as_str(), block) {
+			(Some("0x1"), Some(block)) => TransactionState::Confirmed(block),
+			(Some(_), _) => TransactionState::Failed("Reverted".into()),
+			(None, _) => TransactionState::Pending,
+		})
+	}
+
+	/// A proof is the 32 bytes hash of a transaction, valid once the transaction is confirmed
+	async fn verify_proof(&self, proof: &[u8]) -> Result<bool, RuntimeError> {
+		if proof.len() != 32 {
+			return Err(RuntimeError::Blockchain(format!(
+				"Expected a transaction hash, got {} bytes",
+				proof.len()
+			)));
+		}
+		let state = self.get_transaction_state(&format!("0x{}", hex::encode(proof))).await?;
+		Ok(matches!(state, TransactionState::Confirmed(_)))
+	}
+
+	async fn subscribe_events(
+		&self,
+		filter: EventFilter,
+	) -> Result<ChainEventStream, RuntimeError> {
+		self.subscriber.subscribe_events(filter).await
+	}
+}
+
+/// Longest delay between two polls of a filter the node keeps failing to return.
+const MAX_POLL_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Log subscription of an EVM chain through its JSON-RPC endpoints. Over a WebSocket endpoint the
+/// node pushes the logs of an `eth_subscribe("logs")` subscription as they are mined. HTTP
+/// endpoints cannot push logs, so without a WebSocket endpoint, or when it cannot be reached, a
+/// filter installed on the node is drained every `poll_interval` instead. Either way the runtime
+/// only sees the resulting stream, and dropping it ends the subscription on the node.
+#[derive(Clone)]
+pub struct EvmLogSubscriber {
+	rpc_url: String,
+	ws_url: Option<String>,
+	client: reqwest::Client,
+	poll_interval: Duration,
+}
+
+impl EvmLogSubscriber {
+	/// A subscriber of the node at `rpc_url`, which pushes the logs when it is a `ws://` or
+	/// `wss://` URL and is polled otherwise.
+	pub fn new(rpc_url: impl Into<String>) -> Self {
+		let rpc_url = rpc_url.into();
+		Self {
+			ws_url: is_websocket(&rpc_url).then(|| rpc_url.clone()),
+			rpc_url,
+			client: reqwest::Client::new(),
+			poll_interval: Duration::from_secs(2),
+		}
+	}
+
+	/// Subscribe over this WebSocket endpoint of the node, its HTTP endpoint only being polled
+	/// when the subscription fails.
+	pub fn with_ws_url(mut self, ws_url: impl Into<String>) -> Self {
+		self.ws_url = Some(ws_url.into());
+		self
+	}
+
+	pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+		self.poll_interval = poll_interval;
+		self
+	}
+
+	/// Stream the logs matching the filter as they are mined, after the ones mined since its
+	/// `from_block`
+	pub async fn subscribe_events(
+		&self,
+		filter: EventFilter,
+	) -> Result<ChainEventStream, RuntimeError> {
+		if let Some(ws_url) = &self.ws_url {
+			match subscribe_logs(ws_url, &filter).await {
+				Ok(events) => return Ok(events),
+				Err(e) if !is_websocket(&self.rpc_url) => {
+					tracing::warn!("Polling the logs of {}: {}", self.rpc_url, e);
+				},
+				Err(e) => return Err(e),
+			}
+		}
+		self.poll_events(filter).await
+	}
+
+	/// Install a log filter on the node and drain it every `poll_interval`, less and less often
+	/// while the node fails to answer
+	async fn poll_events(&self, filter: EventFilter) -> Result<ChainEventStream, RuntimeError> {
+		let filter_id = self.call("eth_newFilter", json!([log_filter(&filter)])).await?;
+		let filter = InstalledFilter {
+			subscriber: self.clone(),
+			id: filter_id
+				.as_str()
+				.ok_or_else(|| RuntimeError::Blockchain("Invalid filter id".into()))?
+				.to_string(),
+		};
+
+		let events = stream::unfold(
+			(filter, Vec::<ChainEvent>::new().into_iter(), self.poll_interval),
+			|(filter, mut pending, mut delay)| async move {
+				loop {
+					if let Some(event) = pending.next() {
+						return Some((Ok(event), (filter, pending, delay)));
+					}
+
+					tokio::time::sleep(delay).await;
+					let changes =
+						filter.subscriber.call("eth_getFilterChanges", json!([filter.id])).await;
+					match changes.and_then(|logs| parse_logs(&logs)) {
+						Ok(events) => {
+							pending = events.into_iter();
+							delay = filter.subscriber.poll_interval;
+						},
+						// Reported without ending the subscription, the node may only be
+						// unreachable for a while. Dropping the stream ends it.
+						Err(e) => {
+							delay = backoff(delay);
+							return Some((Err(e), (filter, pending, delay)));
+						},
+					}
+				}
+			},
+		);
+
+		Ok(Box::pin(events))
+	}
+
+	async fn call(&self, method: &str, params: Value) -> Result<Value, RuntimeError> {
+		let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+		let response: Value = self
+			.client
+			.post(&self.rpc_url)
+			.json(&request)
+			.send()
+			.await
+			.map_err(|e| RuntimeError::Blockchain(e.to_string()))?
+			.json()
+			.await
+			.map_err(|e| RuntimeError::Blockchain(e.to_string()))?;
+		rpc_result(method, response)
+	}
+}
+
+/// A log filter installed on the node, uninstalled once the stream polling it is dropped
+struct InstalledFilter {
+	subscriber: EvmLogSubscriber,
+	id: String,
+}
+
+impl Drop for InstalledFilter {
+	fn drop(&mut self) {
+		// Outside of a runtime, the node expires the filter once it is no longer polled.
+		let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+			return;
+		};
+		let (subscriber, id) = (self.subscriber.clone(), std::mem::take(&mut self.id));
+		runtime.spawn(async move {
+			if let Err(e) = subscriber.call("eth_uninstallFilter", json!([id])).await {
+				tracing::warn!("Failed to uninstall log filter {}: {}", id, e);
+			}
+		});
+	}
+}
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Subscribe to the logs over a WebSocket, after catching up with the ones mined since the
+/// `from_block` of the filter, which subscriptions do not replay. The stream reports the loss of
+/// the connection, then ends.
+async fn subscribe_logs(
+	ws_url: &str,
+	filter: &EventFilter,
+) -> Result<ChainEventStream, RuntimeError> {
+	let (mut socket, _) = tokio_tungstenite::connect_async(ws_url)
+		.await
+		.map_err(|e| RuntimeError::Blockchain(format!("Failed to connect to {}: {}", ws_url, e)))?;
+
+	let past = match filter.from_block {
+		Some(_) => {
+			parse_logs(&ws_call(&mut socket, 1, "eth_getLogs", json!([log_filter(filter)])).await?)?
+		},
+		None => Vec::new(),
+	};
+	let mut live = log_filter(filter);
+	if let Some(live) = live.as_object_mut() {
+		live.remove("fromBlock");
+	}
+	let subscription = ws_call(&mut socket, 2, "eth_subscribe", json!(["logs", live])).await?;
+	let subscription = subscription
+		.as_str()
+		.ok_or_else(|| RuntimeError::Blockchain("Invalid subscription id".into()))?
+		.to_string();
+
+	let live = stream::unfold(Some(socket), move |socket| {
+		let subscription = subscription.clone();
+		async move {
+			let mut socket = socket?;
+			loop {
+				match socket.next().await {
+					Some(Ok(Message::Text(text))) => match parse_notification(&text, &subscription)
+					{
+						Ok(Some(log)) => return Some((parse_log(&log), Some(socket))),
+						Ok(None) => continue,
+						Err(e) => return Some((Err(e), Some(socket))),
+					},
+					Some(Ok(Message::Close(_))) | None => {
+						let closed = RuntimeError::Blockchain("Log subscription closed".into());
+						return Some((Err(closed), None));
+					},
+					Some(Ok(_)) => continue,
+					Some(Err(e)) => {
+						return Some((Err(RuntimeError::Blockchain(e.to_string())), None))
+					},
+				}
+			}
+		}
+	});
+
+	Ok(Box::pin(stream::iter(past.into_iter().map(Ok)).chain(live)))
+}
+
+/// Send a JSON-RPC request over the WebSocket and wait for its response
+async fn ws_call(
+	socket: &mut WebSocket,
+	id: u64,
+	method: &str,
+	params: Value,
+) -> Result<Value, RuntimeError> {
+	let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+	socket
+		.send(Message::text(request.to_string()))
+		.await
+		.map_err(|e| RuntimeError::Blockchain(e.to_string()))?;
+	while let Some(message) = socket.next().await {
+		let Message::Text(text) = message.map_err(|e| RuntimeError::Blockchain(e.to_string()))?
+		else {
+			continue;
+		};
+		let response: Value = serde_json::from_str(&text)
+			.map_err(|e| RuntimeError::Blockchain(format!("Invalid response: {}", e)))?;
+		if response["id"] == id {
+			return rpc_result(method, response);
+		}
+	}
+	Err(RuntimeError::Blockchain(format!("{} failed: connection closed", method)))
+}
+
+fn rpc_result(method: &str, mut response: Value) -> Result<Value, RuntimeError> {
+	if let Some(error) = response.get("error") {
+		return Err(RuntimeError::Blockchain(format!("{} failed: {}", method, error)));
+	}
+	Ok(response["result"].take())
+}
+
+/// The log of a notification of the subscription, `None` for other messages
+fn parse_notification(text: &str, subscription: &str) -> Result<Option<Value>, RuntimeError> {
+	let mut message: Value = serde_json::from_str(text)
+		.map_err(|e| RuntimeError::Blockchain(format!("Invalid notification: {}", e)))?;
+	let notifies = message["method"] == "eth_subscription"
+		&& message["params"]["subscription"] == subscription;
+	Ok(notifies.then(|| message["params"]["result"].take()))
+}
+
+fn is_websocket(url: &str) -> bool {
+	url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// The delay before polling again after a failed poll, doubling up to [`MAX_POLL_BACKOFF`]
+fn backoff(delay: Duration) -> Duration {
+	(delay * 2).min(MAX_POLL_BACKOFF.max(delay))
+}
+
+fn log_filter(filter: &EventFilter) -> Value {
+	let from_block = match filter.from_block {
+		Some(block) => format!("{:#x}", block),
+		None => "latest".to_string(),
+	};
+	json!({
+		"address": filter.addresses,
+		"topics": filter.topics,
+		"fromBlock": from_block,
+	})
+}
+
+fn parse_logs(logs: &Value) -> Result<Vec<ChainEvent>, RuntimeError> {
+	let logs = logs
+		.as_array()
+		.ok_or_else(|| RuntimeError::Blockchain("Expected an array of logs".into()))?;
+	logs.iter().map(parse_log).collect()
+}
+
+fn parse_log(log: &Value) -> Result<ChainEvent, RuntimeError> {
+	let string = |field: &str| log[field].as_str().map(str::to_string);
+	let number = |field: &str| {
+		log[field]
+			.as_str()
+			.and_then(|n| u64::from_str_radix(n.trim_start_matches("0x"), 16).ok())
+	};
+	Ok(ChainEvent {
+		address: string("address")
+			.ok_or_else(|| RuntimeError::Blockchain("Log without an address".into()))?,
+		topics: log["topics"]
+			.as_array()
+			.map(|topics| topics.iter().filter_map(|t| t.as_str().map(str::to_string)).collect())
+			.unwrap_or_default(),
+		data: decode_hex(log["data"].as_str().unwrap_or("0x"))?,
+		block_number: number("blockNumber"),
+		transaction_hash: string("transactionHash"),
+		log_index: number("logIndex"),
+	})
+}
+
+fn decode_hex(data: &str) -> Result<Vec<u8>, RuntimeError> {
+	hex::decode(data.trim_start_matches("0x"))
+		.map_err(|e| RuntimeError::Blockchain(format!("Invalid hex data {}: {}", data, e)))
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	const REGISTRY: &str = "0x5fbdb2315678afecb367f032d93f642f64180aa3";
+	const TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+	fn log(block: u64, data: &str) -> Value {
+		json!({
+			"address": REGISTRY,
+			"topics": [TOPIC],
+			"data": data,
+			"blockNumber": format!("{:#x}", block),
+			"transactionHash": "0xabc",
+			"logIndex": "0x1",
+		})
+	}
+
+	#[test]
+	fn test_log_filter() {
+		let filter = EventFilter {
+			addresses: vec![REGISTRY.to_string()],
+			topics: vec![Some(TOPIC.to_string()), None],
+			from_block: Some(255),
+		};
+		assert_eq!(
+			log_filter(&filter),
+			json!({ "address": [REGISTRY], "topics": [TOPIC, null], "fromBlock": "0xff" })
+		);
+		assert_eq!(log_filter(&EventFilter::default())["fromBlock"], "latest");
+	}
+
+	#[test]
+	fn test_parse_logs() -> Result<()> {
+		let events = parse_logs(&json!([log(16, "0x0102"), { "address": REGISTRY }]))?;
+		assert_eq!(
+			events[0],
+			ChainEvent {
+				address: REGISTRY.to_string(),
+				topics: vec![TOPIC.to_string()],
+				data: vec![1, 2],
+				block_number: Some(16),
+				transaction_hash: Some("0xabc".to_string()),
+				log_index: Some(1),
+			}
+		);
+		// Pending logs have no block yet.
+		assert_eq!(events[1].block_number, None);
+		assert!(events[1].topics.is_empty() && events[1].data.is_empty());
+
+		assert!(parse_logs(&json!({ "address": REGISTRY })).is_err());
+		assert!(parse_logs(&json!([{ "topics": [TOPIC] }])).is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_decode_hex() -> Result<()> {
+		assert_eq!(decode_hex("0x0aFf")?, vec![0x0a, 0xff]);
+		assert_eq!(decode_hex("0aff")?, vec![0x0a, 0xff]);
+		assert!(decode_hex("0x")?.is_empty());
+		assert!(decode_hex("0x123").is_err());
+		assert!(decode_hex("0xzz").is_err());
+		assert!(decode_hex("0xé1").is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_notification() -> Result<()> {
+		let notification = |subscription: &str| {
+			json!({
+				"jsonrpc": "2.0",
+				"method": "eth_subscription",
+				"params": { "subscription": subscription, "result": log(1, "0x") },
+			})
+			.to_string()
+		};
+		assert_eq!(parse_notification(&notification("0x1"), "0x1")?, Some(log(1, "0x")));
+		assert_eq!(parse_notification(&notification("0x2"), "0x1")?, None);
+		assert_eq!(parse_notification(r#"{"jsonrpc":"2.0","id":3,"result":true}"#, "0x1")?, None);
+		assert!(parse_notification("not json", "0x1").is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_backoff() {
+		let second = Duration::from_secs(1);
+		assert_eq!(backoff(second), 2 * second);
+		assert_eq!(backoff(40 * second), MAX_POLL_BACKOFF);
+		// Polls slower than the longest backoff keep their interval.
+		assert_eq!(backoff(2 * MAX_POLL_BACKOFF), 2 * MAX_POLL_BACKOFF);
+	}
+
+	#[tokio::test]
+	async fn test_subscription_catches_up_then_pushes_the_logs() -> Result<()> {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+		let url = format!("ws://{}", listener.local_addr()?);
+		let node = tokio::spawn(async move {
+			let (stream, _) = listener.accept().await?;
+			let mut socket = tokio_tungstenite::accept_async(stream).await?;
+			let mut methods = Vec::new();
+			for result in [json!([log(1, "0x01")]), json!("0xsub")] {
+				let Some(Ok(Message::Text(request))) = socket.next().await else {
+					return Err("Expected a request".into());
+				};
+				let request: Value = serde_json::from_str(&request)?;
+				methods.push((request["method"].clone(), request["params"].clone()));
+				let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+				socket.send(Message::text(response.to_string())).await?;
+			}
+			for subscription in ["0xother", "0xsub"] {
+				let notification = json!({
+					"jsonrpc": "2.0",
+					"method": "eth_subscription",
+					"params": { "subscription": subscription, "result": log(2, "0x02") },
+				});
+				socket.send(Message::text(notification.to_string())).await?;
+			}
+			socket.close(None).await?;
+			Ok::<_, Box<dyn std::error::Error + Send + Sync>>(methods)
+		});
+
+		let filter = EventFilter { from_block: Some(1), ..Default::default() };
+		let events: Vec<_> =
+			EvmLogSubscriber::new(url).subscribe_events(filter).await?.collect().await;
+
+		let blocks: Vec<_> = events.iter().flatten().map(|event| event.block_number).collect();
+		assert_eq!(blocks, [Some(1), Some(2)]);
+		// The loss of the connection is reported before the stream ends.
+		assert_eq!(events.len(), 3);
+		assert!(events[2].is_err());
+		let methods = node.await.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+		assert_eq!(methods[0].0, "eth_getLogs");
+		assert_eq!(
+			methods[1],
+			(json!("eth_subscribe"), json!(["logs", { "address": [], "topics": [] }]))
+		);
+		Ok(())
+	}
 }
+
+// endregion: --- Tests
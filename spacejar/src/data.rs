@@ -1,4 +1,7 @@
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
 
 use crate::error::RuntimeError;
 
@@ -15,3 +18,327 @@ pub trait DataManager: Send + Sync {
 	/// Delete data from storage
 	async fn delete_data(&self, key: &str) -> Result<(), RuntimeError>;
 }
+
+/// A stage data goes through before storage
+pub trait DataTransform: Send + Sync {
+	/// Name of the stage, recorded in the pipeline metadata
+	fn name(&self) -> &str;
+
+	/// Transform or validate the data before it is stored
+	fn apply(&self, data: Vec<u8>) -> Result<Vec<u8>, RuntimeError>;
+
+	/// Undo the stage on retrieval, stages that only validate or scrub keep the data as is
+	fn revert(&self, data: Vec<u8>) -> Result<Vec<u8>, RuntimeError> {
+		Ok(data)
+	}
+}
+
+/// Compress data with zstd
+pub struct Compression {
+	pub level: i32,
+}
+
+impl DataTransform for Compression {
+	fn name(&self) -> &str {
+		"zstd"
+	}
+
+	fn apply(&self, data: Vec<u8>) -> Result<Vec<u8>, RuntimeError> {
+		zstd::encode_all(data.as_slice(), self.level).map_err(|e| RuntimeError::Data(e.to_string()))
+	}
+
+	fn revert(&self, data: Vec<u8>) -> Result<Vec<u8>, RuntimeError> {
+		zstd::decode_all(data.as_slice()).map_err(|e| RuntimeError::Data(e.to_string()))
+	}
+}
+
+/// Reject data that is not a JSON object holding the required fields
+pub struct SchemaValidation {
+	pub required_fields: Vec<String>,
+}
+
+impl DataTransform for SchemaValidation {
+	fn name(&self) -> &str {
+		"schema"
+	}
+
+	fn apply(&self, data: Vec<u8>) -> Result<Vec<u8>, RuntimeError> {
+		let value: serde_json::Value = serde_json::from_slice(&data)
+			.map_err(|e| RuntimeError::Data(format!("Invalid JSON: {}", e)))?;
+		let object = value
+			.as_object()
+			.ok_or_else(|| RuntimeError::Data("Expected a JSON object".into()))?;
+		if let Some(missing) = self.required_fields.iter().find(|f| !object.contains_key(*f)) {
+			return Err(RuntimeError::Data(format!("Missing required field {}", missing)));
+		}
+
+		Ok(data)
+	}
+}
+
+/// Replace the email addresses and phone numbers found in text data, see [`network::pii::redact`]
+pub struct PiiScrubber;
+
+impl DataTransform for PiiScrubber {
+	fn name(&self) -> &str {
+		"pii-scrub"
+	}
+
+	fn apply(&self, data: Vec<u8>) -> Result<Vec<u8>, RuntimeError> {
+		let text = String::from_utf8(data).map_err(|e| RuntimeError::Data(e.to_string()))?;
+		Ok(network::pii::redact(&text).into_bytes())
+	}
+}
+
+/// The stages stored data went through, kept next to it to verify it on retrieval
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineMetadata {
+	/// Names of the stages, in the order they were applied
+	pub stages: Vec<String>,
+	/// SHA-256 of the data as stored, hex encoded
+	pub digest: String,
+}
+
+/// Ordered stages stored data goes through
+#[derive(Clone, Default)]
+pub struct DataPipeline {
+	stages: Vec<Arc<dyn DataTransform>>,
+}
+
+impl DataPipeline {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Register a stage, run after the ones already registered
+	pub fn with_stage(mut self, stage: impl DataTransform + 'static) -> Self {
+		self.stages.push(Arc::new(stage));
+		self
+	}
+
+	/// Run the data through every stage
+	pub fn apply(&self, data: Vec<u8>) -> Result<(Vec<u8>, PipelineMetadata), RuntimeError> {
+		let data = self.stages.iter().try_fold(data, |data, stage| stage.apply(data))?;
+		let metadata = PipelineMetadata {
+			stages: self.stages.iter().map(|stage| stage.name().to_string()).collect(),
+			digest: digest(&data),
+		};
+
+		Ok((data, metadata))
+	}
+
+	/// Check stored data against its metadata and undo the stages, last one first
+	pub fn revert(
+		&self,
+		data: Vec<u8>,
+		metadata: &PipelineMetadata,
+	) -> Result<Vec<u8>, RuntimeError> {
+		let stages: Vec<_> = self.stages.iter().map(|stage| stage.name()).collect();
+		if stages != metadata.stages {
+			return Err(RuntimeError::Data(format!(
+				"Data went through {:?}, the pipeline runs {:?}",
+				metadata.stages, stages
+			)));
+		}
+		if digest(&data) != metadata.digest {
+			return Err(RuntimeError::Data("Stored data does not match its digest".into()));
+		}
+
+		self.stages.iter().rev().try_fold(data, |data, stage| stage.revert(data))
+	}
+}
+
+/// A data manager running the data through a pipeline before storage. The metadata of the
+/// pipeline is stored under `<key>.pipeline` and verified on retrieval.
+pub struct PipelineDataManager {
+	inner: Arc<dyn DataManager>,
+	pipeline: DataPipeline,
+}
+
+impl PipelineDataManager {
+	pub fn new(inner: Arc<dyn DataManager>, pipeline: DataPipeline) -> Self {
+		Self { inner, pipeline }
+	}
+
+	/// Metadata of the pipeline the data stored under `key` went through
+	pub async fn pipeline_metadata(&self, key: &str) -> Result<PipelineMetadata, RuntimeError> {
+		let metadata = self.inner.retrieve_data(&metadata_key(key)).await?;
+		serde_json::from_slice(&metadata).map_err(|e| RuntimeError::Data(e.to_string()))
+	}
+}
+
+#[async_trait]
+impl DataManager for PipelineDataManager {
+	async fn store_data(
+		&self,
+		key: &str,
+		data: Vec<u8>,
+		encrypt: bool,
+	) -> Result<(), RuntimeError> {
+		let (data, metadata) = self.pipeline.apply(data)?;
+		let metadata =
+			serde_json::to_vec(&metadata).map_err(|e| RuntimeError::Data(e.to_string()))?;
+
+		self.inner.store_data(key, data, encrypt).await?;
+		self.inner.store_data(&metadata_key(key), metadata, false).await
+	}
+
+	async fn retrieve_data(&self, key: &str) -> Result<Vec<u8>, RuntimeError> {
+		let metadata = self.pipeline_metadata(key).await?;
+		let data = self.inner.retrieve_data(key).await?;
+		self.pipeline.revert(data, &metadata)
+	}
+
+	async fn delete_data(&self, key: &str) -> Result<(), RuntimeError> {
+		self.inner.delete_data(key).await?;
+		self.inner.delete_data(&metadata_key(key)).await
+	}
+}
+
+fn metadata_key(key: &str) -> String {
+	format!("{}.pipeline", key)
+}
+
+fn digest(data: &[u8]) -> String {
+	Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use std::collections::HashMap;
+	use std::sync::Mutex;
+
+	const RECORD: &str = r#"{"prompt": "Write to alice@example.com about the release"}"#;
+
+	#[tokio::test]
+	async fn test_store_and_retrieve_round_trip() -> Result<()> {
+		let (storage, manager) = pipeline_manager(compressing_pipeline());
+
+		manager.store_data("record", RECORD.as_bytes().to_vec(), false).await?;
+
+		// Stored compressed, along with the stages it went through
+		assert_ne!(storage.get("record").ok_or("Expected the record")?, RECORD.as_bytes());
+		let metadata = manager.pipeline_metadata("record").await?;
+		assert_eq!(metadata.stages, ["schema", "zstd"]);
+		assert_eq!(metadata.digest, digest(&storage.get("record").ok_or("Expected the record")?));
+		assert_eq!(manager.retrieve_data("record").await?, RECORD.as_bytes());
+
+		manager.delete_data("record").await?;
+		assert!(storage.is_empty());
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_retrieve_rejects_data_not_matching_its_digest() -> Result<()> {
+		let (storage, manager) = pipeline_manager(compressing_pipeline());
+		manager.store_data("record", RECORD.as_bytes().to_vec(), false).await?;
+
+		let tampered = zstd::encode_all(r#"{"prompt": "Tampered"}"#.as_bytes(), 3)?;
+		storage.store_data("record", tampered, false).await?;
+
+		let result = manager.retrieve_data("record").await;
+		assert!(
+			matches!(&result, Err(RuntimeError::Data(e)) if e.contains("digest")),
+			"{:?}",
+			result
+		);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_retrieve_rejects_data_of_another_pipeline() -> Result<()> {
+		let storage = Arc::new(MemoryDataManager::default());
+		PipelineDataManager::new(storage.clone(), compressing_pipeline())
+			.store_data("record", RECORD.as_bytes().to_vec(), false)
+			.await?;
+
+		let manager = PipelineDataManager::new(storage, DataPipeline::new());
+		let result = manager.retrieve_data("record").await;
+		assert!(
+			matches!(&result, Err(RuntimeError::Data(e)) if e.contains("went through")),
+			"{:?}",
+			result
+		);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_store_rejects_invalid_data() {
+		let (storage, manager) = pipeline_manager(compressing_pipeline());
+
+		let missing_field = manager.store_data("record", br#"{"answer": 42}"#.to_vec(), false);
+		assert!(matches!(missing_field.await, Err(RuntimeError::Data(e)) if e.contains("prompt")));
+		let not_json = manager.store_data("record", b"prompt".to_vec(), false);
+		assert!(not_json.await.is_err());
+		assert!(storage.is_empty());
+	}
+
+	#[test]
+	fn test_pii_scrubber() -> Result<()> {
+		let scrubbed =
+			PiiScrubber.apply(b"Mail bob@example.com, call +1 555 010 4477 or @team".to_vec())?;
+		assert_eq!(String::from_utf8(scrubbed)?, "Mail [redacted], call [redacted] or @team");
+		Ok(())
+	}
+
+	// region:    --- Support
+
+	fn compressing_pipeline() -> DataPipeline {
+		DataPipeline::new()
+			.with_stage(SchemaValidation { required_fields: vec!["prompt".into()] })
+			.with_stage(Compression { level: 3 })
+	}
+
+	fn pipeline_manager(pipeline: DataPipeline) -> (Arc<MemoryDataManager>, PipelineDataManager) {
+		let storage = Arc::new(MemoryDataManager::default());
+		(storage.clone(), PipelineDataManager::new(storage, pipeline))
+	}
+
+	#[derive(Default)]
+	struct MemoryDataManager {
+		data: Mutex<HashMap<String, Vec<u8>>>,
+	}
+
+	impl MemoryDataManager {
+		fn get(&self, key: &str) -> Option<Vec<u8>> {
+			self.data.lock().unwrap().get(key).cloned()
+		}
+
+		fn is_empty(&self) -> bool {
+			self.data.lock().unwrap().is_empty()
+		}
+	}
+
+	#[async_trait]
+	impl DataManager for MemoryDataManager {
+		async fn store_data(
+			&self,
+			key: &str,
+			data: Vec<u8>,
+			_encrypt: bool,
+		) -> core::result::Result<(), RuntimeError> {
+			self.data.lock().unwrap().insert(key.to_string(), data);
+			Ok(())
+		}
+
+		async fn retrieve_data(&self, key: &str) -> core::result::Result<Vec<u8>, RuntimeError> {
+			self.get(key)
+				.ok_or_else(|| RuntimeError::Data(format!("No data under {}", key)))
+		}
+
+		async fn delete_data(&self, key: &str) -> core::result::Result<(), RuntimeError> {
+			self.data.lock().unwrap().remove(key);
+			Ok(())
+		}
+	}
+
+	// endregion: --- Support
+}
+
+// endregion: --- Tests
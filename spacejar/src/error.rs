@@ -1,3 +1,6 @@
+use crate::escrow::EscrowError;
+use crate::model::ModelTransitionError;
+
 /// Core error types for the ML runtime system
 #[derive(Debug, thiserror::Error)]
 pub enum RuntimeError {
@@ -9,4 +12,10 @@ pub enum RuntimeError {
 	Data(String),
 	#[error("System error: {0}")]
 	System(String),
+	#[error(transparent)]
+	ModelTransition(#[from] ModelTransitionError),
+	#[error(transparent)]
+	Escrow(#[from] EscrowError),
+	#[error(transparent)]
+	Wallet(#[from] wallet::WalletError),
 }
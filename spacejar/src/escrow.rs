@@ -0,0 +1,618 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use network::{TaskEscrow, TaskRecord};
+use serde::{Deserialize, Serialize};
+use wallet::{keccak256, Address, FeePolicy, Signer, TransactionRequest};
+
+use crate::blockchain::{BlockchainManager, ChainEvent, EventFilter};
+use crate::error::RuntimeError;
+
+/// Errors of the escrow lifecycle, caught before a transaction is submitted
+#[derive(Debug, thiserror::Error)]
+pub enum EscrowError {
+	#[error("Escrow {0} cannot be refunded before its deadline")]
+	NotExpired(String),
+	#[error("Escrow {0} cannot be disputed after its deadline")]
+	Expired(String),
+	#[error("Invalid escrow event: {0}")]
+	InvalidEvent(String),
+	#[error("Task {0} has no payment address to escrow its payment for")]
+	NoPaymentAddress(String),
+}
+
+/// An escrow of the payment of a task, identified on-chain by the task id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escrow {
+	pub id: [u8; 32],
+	pub payee: Address,
+	/// Unix time after which the payer can be refunded, and disputes are no longer accepted
+	pub deadline: u64,
+}
+
+impl Escrow {
+	fn is_expired(&self) -> bool {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		now >= self.deadline
+	}
+
+	fn id_hex(&self) -> String {
+		format!("0x{}", hex::encode(self.id))
+	}
+}
+
+/// Lifecycle events emitted by the escrow contract
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EscrowEvent {
+	Created { id: [u8; 32], payer: String, payee: String, deadline: u64 },
+	Funded { id: [u8; 32], amount: u128 },
+	Released { id: [u8; 32] },
+	Refunded { id: [u8; 32] },
+	Disputed { id: [u8; 32], by: String },
+}
+
+/// Signatures of the events, whose first indexed argument is the escrow id
+const EVENTS: [&str; 5] = [
+	"Created(bytes32,address,address,uint64)",
+	"Funded(bytes32,uint256)",
+	"Released(bytes32)",
+	"Refunded(bytes32)",
+	"Disputed(bytes32,address)",
+];
+
+/// Typed client of the escrow contract holding task payments:
+///
+/// ```solidity
+/// function create(bytes32 id, address payee, uint64 deadline);
+/// function fund(bytes32 id) payable;
+/// function release(bytes32 id);      // payer, pays the payee
+/// function refund(bytes32 id);       // payer, once the deadline passed without dispute
+/// function dispute(bytes32 id);      // payer or payee, before the deadline
+/// ```
+///
+/// Transactions go through the [`BlockchainManager`], which estimates their fees against the
+/// policy of the client before signing them.
+pub struct EscrowClient {
+	manager: Arc<dyn BlockchainManager>,
+	contract: Address,
+	policy: FeePolicy,
+}
+
+impl EscrowClient {
+	pub fn new(manager: Arc<dyn BlockchainManager>, contract: Address) -> Self {
+		Self { manager, contract, policy: FeePolicy::default() }
+	}
+
+	pub fn with_fee_policy(mut self, policy: FeePolicy) -> Self {
+		self.policy = policy;
+		self
+	}
+
+	/// Open an escrow for the task, paying `payee` and refundable after `deadline`
+	pub async fn create(
+		&self,
+		task_id: [u8; 32],
+		payee: Address,
+		deadline: u64,
+		signer: &dyn Signer,
+	) -> Result<(Escrow, String), RuntimeError> {
+		let data = call(
+			"create(bytes32,address,uint64)",
+			&[task_id, address_word(&payee), uint_word(deadline as u128)],
+		);
+		let tx_id = self.submit(data, 0, signer).await?;
+		Ok((Escrow { id: task_id, payee, deadline }, tx_id))
+	}
+
+	/// Deposit `amount` wei into the escrow
+	pub async fn fund(
+		&self,
+		escrow: &Escrow,
+		amount: u128,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError> {
+		self.submit(call("fund(bytes32)", &[escrow.id]), amount, signer).await
+	}
+
+	/// Pay the escrowed funds to the payee, once the task is accepted
+	pub async fn release(
+		&self,
+		escrow: &Escrow,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError> {
+		self.submit(call("release(bytes32)", &[escrow.id]), 0, signer).await
+	}
+
+	/// Return the escrowed funds to the payer, which the contract only allows after the deadline
+	pub async fn refund(
+		&self,
+		escrow: &Escrow,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError> {
+		if !escrow.is_expired() {
+			return Err(EscrowError::NotExpired(escrow.id_hex()).into());
+		}
+		self.submit(call("refund(bytes32)", &[escrow.id]), 0, signer).await
+	}
+
+	/// Freeze the escrow for arbitration, which the contract only allows before the deadline
+	pub async fn dispute(
+		&self,
+		escrow: &Escrow,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError> {
+		if escrow.is_expired() {
+			return Err(EscrowError::Expired(escrow.id_hex()).into());
+		}
+		self.submit(call("dispute(bytes32)", &[escrow.id]), 0, signer).await
+	}
+
+	/// Stream the lifecycle events of an escrow, from `from_block` or the latest block
+	pub async fn events(
+		&self,
+		escrow: &Escrow,
+		from_block: Option<u64>,
+	) -> Result<impl futures::Stream<Item = Result<EscrowEvent, RuntimeError>>, RuntimeError> {
+		let filter = EventFilter {
+			addresses: vec![self.contract.to_string()],
+			topics: vec![None, Some(escrow.id_hex())],
+			from_block,
+		};
+		let events = self.manager.subscribe_events(filter).await?;
+		Ok(events.map(|event| event.and_then(|event| decode_event(&event))))
+	}
+
+	async fn submit(
+		&self,
+		data: Vec<u8>,
+		value: u128,
+		signer: &dyn Signer,
+	) -> Result<String, RuntimeError> {
+		let tx = TransactionRequest {
+			from: Some(signer.address()),
+			to: Some(self.contract),
+			value,
+			data,
+		};
+		self.manager.submit_with_policy(tx, &self.policy, signer).await
+	}
+}
+
+/// Escrow of the task marketplace of the network over the escrow contract, so that
+/// [`network::EscrowSettlement`] locks the budget of the auctioned tasks on-chain. The escrow of a
+/// task is identified by the keccak256 hash of the task id, and its budget is converted to wei at
+/// `wei_per_unit`.
+pub struct ContractTaskEscrow {
+	client: EscrowClient,
+	signer: Arc<dyn Signer>,
+	wei_per_unit: u128,
+}
+
+impl std::fmt::Debug for ContractTaskEscrow {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ContractTaskEscrow")
+			.field("signer", &self.signer.address())
+			.field("wei_per_unit", &self.wei_per_unit)
+			.finish_non_exhaustive()
+	}
+}
+
+impl ContractTaskEscrow {
+	pub fn new(client: EscrowClient, signer: Arc<dyn Signer>, wei_per_unit: u128) -> Self {
+		Self { client, signer, wei_per_unit }
+	}
+
+	fn escrow(task: &TaskRecord) -> Result<Escrow, RuntimeError> {
+		let payee = task
+			.payment_address
+			.as_deref()
+			.ok_or_else(|| EscrowError::NoPaymentAddress(task.task_id.clone()))?
+			.parse()?;
+		Ok(Escrow { id: keccak256(task.task_id.as_bytes()), payee, deadline: task.deadline })
+	}
+}
+
+#[async_trait]
+impl TaskEscrow for ContractTaskEscrow {
+	/// Open the escrow of the task, then fund it with the budget of the task
+	async fn lock(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		let escrow = Self::escrow(task)?;
+		let signer = self.signer.as_ref();
+		let (escrow, _) =
+			self.client.create(escrow.id, escrow.payee, escrow.deadline, signer).await?;
+		let amount = (task.max_bid * self.wei_per_unit as f64) as u128;
+		Ok(self.client.fund(&escrow, amount, signer).await?)
+	}
+
+	async fn release(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		Ok(self.client.release(&Self::escrow(task)?, self.signer.as_ref()).await?)
+	}
+
+	async fn refund(&self, task: &TaskRecord) -> Result<String, Box<dyn Error + Send + Sync>> {
+		Ok(self.client.refund(&Self::escrow(task)?, self.signer.as_ref()).await?)
+	}
+}
+
+/// ABI encoding of a call: the 4 byte selector of the function, then its static arguments
+fn call(signature: &str, args: &[[u8; 32]]) -> Vec<u8> {
+	let mut data = keccak256(signature.as_bytes())[..4].to_vec();
+	for arg in args {
+		data.extend_from_slice(arg);
+	}
+	data
+}
+
+fn address_word(address: &Address) -> [u8; 32] {
+	let mut word = [0u8; 32];
+	word[12..].copy_from_slice(&address.0);
+	word
+}
+
+fn uint_word(value: u128) -> [u8; 32] {
+	let mut word = [0u8; 32];
+	word[16..].copy_from_slice(&value.to_be_bytes());
+	word
+}
+
+fn decode_event(event: &ChainEvent) -> Result<EscrowEvent, RuntimeError> {
+	let invalid = || EscrowError::InvalidEvent(format!("{:?}", event));
+	let topic = |index: usize| -> Result<[u8; 32], EscrowError> {
+		let topic = event.topics.get(index).ok_or_else(invalid)?;
+		let bytes = hex::decode(topic.trim_start_matches("0x")).map_err(|_| invalid())?;
+		bytes.try_into().map_err(|_| invalid())
+	};
+	// Non-indexed arguments, one 32 byte word each
+	let word = |index: usize| -> Result<&[u8], EscrowError> {
+		event.data.get(index * 32..(index + 1) * 32).ok_or_else(invalid)
+	};
+	let address = |word: &[u8]| format!("0x{}", hex::encode(&word[12..]));
+	let uint = |word: &[u8]| -> Result<u128, EscrowError> {
+		if word[..16].iter().any(|b| *b != 0) {
+			return Err(invalid());
+		}
+		Ok(u128::from_be_bytes(word[16..].try_into().expect("Word to be 32 bytes.")))
+	};
+
+	let id = topic(1)?;
+	let signature = topic(0)?;
+	let name = EVENTS
+		.iter()
+		.find(|event| keccak256(event.as_bytes()) == signature)
+		.and_then(|event| event.split('(').next())
+		.ok_or_else(invalid)?;
+	let event = match name {
+		"Created" => EscrowEvent::Created {
+			id,
+			payer: address(word(0)?),
+			payee: address(word(1)?),
+			deadline: uint(word(2)?)? as u64,
+		},
+		"Funded" => EscrowEvent::Funded { id, amount: uint(word(0)?)? },
+		"Released" => EscrowEvent::Released { id },
+		"Refunded" => EscrowEvent::Refunded { id },
+		"Disputed" => EscrowEvent::Disputed { id, by: address(word(0)?) },
+		_ => return Err(invalid().into()),
+	};
+	Ok(event)
+}
+
+// region:    --- Tests
+
+#[cfg(all(test, feature = "chain-tests"))]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+	use crate::blockchain::EvmBlockchainManager;
+	use std::collections::HashMap;
+	use std::time::Duration;
+	use wallet::testing::TestChain;
+	use wallet::{rpc, LocalSigner};
+
+	const ETHER: u128 = 1_000_000_000_000_000_000;
+	const PAYMENT: u128 = ETHER / 10;
+	const HOUR: u64 = 3600;
+
+	#[tokio::test]
+	async fn test_escrow_released_to_the_payee() -> Result<()> {
+		let chain = TestChain::spawn().await?;
+		let client = escrow_client(&chain).await?;
+		let payer = chain.funded_account(ETHER).await?;
+		let payee = LocalSigner::random().address();
+
+		let (escrow, _) = client.create([1; 32], payee, now() + HOUR, &payer).await?;
+		client.fund(&escrow, PAYMENT, &payer).await?;
+		assert_eq!(rpc::balance(chain.rpc_url(), client.contract).await?, PAYMENT);
+		client.release(&escrow, &payer).await?;
+
+		assert_eq!(rpc::balance(chain.rpc_url(), payee).await?, PAYMENT);
+		assert_eq!(rpc::balance(chain.rpc_url(), client.contract).await?, 0);
+		let events = escrow_events(&client, &escrow, 3).await?;
+		assert_eq!(
+			events,
+			vec![
+				EscrowEvent::Created {
+					id: escrow.id,
+					payer: payer.address().to_string().to_lowercase(),
+					payee: payee.to_string().to_lowercase(),
+					deadline: escrow.deadline,
+				},
+				EscrowEvent::Funded { id: escrow.id, amount: PAYMENT },
+				EscrowEvent::Released { id: escrow.id },
+			]
+		);
+		chain.stop().await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_escrow_refunded_after_its_deadline() -> Result<()> {
+		let chain = TestChain::spawn().await?;
+		let client = escrow_client(&chain).await?;
+		let payer = chain.funded_account(ETHER).await?;
+		let payee = chain.funded_account(ETHER).await?;
+
+		let (escrow, _) = client.create([2; 32], payee.address(), now(), &payer).await?;
+		client.fund(&escrow, PAYMENT, &payer).await?;
+		assert!(matches!(
+			client.dispute(&escrow, &payee).await,
+			Err(RuntimeError::Escrow(EscrowError::Expired(_)))
+		));
+		chain.advance_time(Duration::from_secs(60)).await?;
+		let balance = rpc::balance(chain.rpc_url(), payer.address()).await?;
+		client.refund(&escrow, &payer).await?;
+
+		assert_eq!(rpc::balance(chain.rpc_url(), client.contract).await?, 0);
+		// The refund, less the fees of the refund transaction
+		assert!(rpc::balance(chain.rpc_url(), payer.address()).await? > balance + PAYMENT / 2);
+		assert_eq!(rpc::balance(chain.rpc_url(), payee.address()).await?, ETHER);
+		let events = escrow_events(&client, &escrow, 3).await?;
+		assert_eq!(events[2], EscrowEvent::Refunded { id: escrow.id });
+		chain.stop().await?;
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_disputed_escrow_is_frozen() -> Result<()> {
+		let chain = TestChain::spawn().await?;
+		let client = escrow_client(&chain).await?;
+		let payer = chain.funded_account(ETHER).await?;
+		let payee = chain.funded_account(ETHER).await?;
+
+		let (escrow, _) = client.create([3; 32], payee.address(), now() + HOUR, &payer).await?;
+		client.fund(&escrow, PAYMENT, &payer).await?;
+		assert!(matches!(
+			client.refund(&escrow, &payer).await,
+			Err(RuntimeError::Escrow(EscrowError::NotExpired(_)))
+		));
+		client.dispute(&escrow, &payee).await?;
+
+		// The contract refuses to pay out a disputed escrow, even after its deadline
+		assert!(client.release(&escrow, &payer).await.is_err());
+		chain.advance_time(Duration::from_secs(2 * HOUR)).await?;
+		let expired = Escrow { deadline: now(), ..escrow.clone() };
+		assert!(client.refund(&expired, &payer).await.is_err());
+		assert_eq!(rpc::balance(chain.rpc_url(), client.contract).await?, PAYMENT);
+		let events = escrow_events(&client, &escrow, 3).await?;
+		assert_eq!(
+			events[2],
+			EscrowEvent::Disputed { id: escrow.id, by: payee.address().to_string().to_lowercase() }
+		);
+		chain.stop().await?;
+		Ok(())
+	}
+
+	// region:    --- Support
+
+	fn now() -> u64 {
+		SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+	}
+
+	async fn escrow_client(chain: &TestChain) -> Result<EscrowClient> {
+		let contract = chain.deploy(&escrow_contract()).await?;
+		let manager = EvmBlockchainManager::new(chain.rpc_url())
+			.with_ws_url(chain.rpc_url().replacen("http", "ws", 1));
+		Ok(EscrowClient::new(Arc::new(manager), contract))
+	}
+
+	/// The first `count` events of the escrow, from the genesis block
+	async fn escrow_events(
+		client: &EscrowClient,
+		escrow: &Escrow,
+		count: usize,
+	) -> Result<Vec<EscrowEvent>> {
+		let events = client.events(escrow, Some(0)).await?.take(count).collect::<Vec<_>>();
+		let events = tokio::time::timeout(Duration::from_secs(10), events).await?;
+		Ok(events.into_iter().collect::<core::result::Result<_, _>>()?)
+	}
+
+	/// Creation code of an escrow contract implementing the interface of [`EscrowClient`]. The
+	/// fields of an escrow are stored at consecutive slots from its id: payer, payee, deadline,
+	/// amount and whether it is disputed.
+	fn escrow_contract() -> Vec<u8> {
+		let mut runtime = Assembler::default();
+		// Dispatch on the selector, left on the stack
+		runtime.push(&[0]).op(&[CALLDATALOAD]).push(&[0xe0]).op(&[SHR]);
+		let functions = [
+			("create", "create(bytes32,address,uint64)"),
+			("fund", "fund(bytes32)"),
+			("release", "release(bytes32)"),
+			("refund", "refund(bytes32)"),
+			("dispute", "dispute(bytes32)"),
+		];
+		for (function, signature) in functions {
+			runtime.op(&[DUP1]).push(&keccak256(signature.as_bytes())[..4]).op(&[EQ]);
+			runtime.jump_if(function);
+		}
+		runtime.label("revert").push(&[0]).op(&[DUP1, REVERT]);
+
+		runtime.label("create");
+		runtime.field(PAYER).op(&[SLOAD]).jump_if("revert");
+		runtime.op(&[CALLER]).field(PAYER).op(&[SSTORE]);
+		runtime.arg(1).field(PAYEE).op(&[SSTORE]);
+		runtime.arg(2).field(DEADLINE).op(&[SSTORE]);
+		runtime.op(&[CALLER]).push(&[0]).op(&[MSTORE]);
+		runtime.arg(1).push(&[0x20]).op(&[MSTORE]);
+		runtime.arg(2).push(&[0x40]).op(&[MSTORE]);
+		runtime.emit("Created(bytes32,address,address,uint64)", 0x60);
+
+		runtime.label("fund").only_payer();
+		runtime
+			.op(&[CALLVALUE])
+			.field(AMOUNT)
+			.op(&[SLOAD, ADD])
+			.field(AMOUNT)
+			.op(&[SSTORE]);
+		runtime.op(&[CALLVALUE]).push(&[0]).op(&[MSTORE]);
+		runtime.emit("Funded(bytes32,uint256)", 0x20);
+
+		runtime.label("release").only_payer().not_disputed();
+		runtime.field(PAYEE).op(&[SLOAD]).pay_out();
+		runtime.emit("Released(bytes32)", 0);
+
+		// Once the deadline passed
+		runtime.label("refund").only_payer().not_disputed();
+		runtime.field(DEADLINE).op(&[SLOAD, TIMESTAMP, LT]).jump_if("revert");
+		runtime.op(&[CALLER]).pay_out();
+		runtime.emit("Refunded(bytes32)", 0);
+
+		// By the payer or the payee, before the deadline
+		runtime.label("dispute");
+		runtime
+			.field(PAYER)
+			.op(&[SLOAD, CALLER, EQ])
+			.field(PAYEE)
+			.op(&[SLOAD, CALLER, EQ, OR]);
+		runtime.op(&[ISZERO]).jump_if("revert");
+		runtime.field(DEADLINE).op(&[SLOAD, TIMESTAMP, LT, ISZERO]).jump_if("revert");
+		runtime.push(&[1]).field(DISPUTED).op(&[SSTORE]);
+		runtime.op(&[CALLER]).push(&[0]).op(&[MSTORE]);
+		runtime.emit("Disputed(bytes32,address)", 0x20);
+		let runtime = runtime.assemble();
+
+		// Copy the runtime code, which follows these 13 bytes, to memory and return it
+		let mut creation = Assembler::default();
+		creation.push(&(runtime.len() as u16).to_be_bytes()).op(&[DUP1]);
+		creation.push(&13u16.to_be_bytes()).push(&[0]).op(&[CODECOPY]);
+		creation.push(&[0]).op(&[RETURN]);
+		let mut creation = creation.assemble();
+		creation.extend(runtime);
+		creation
+	}
+
+	const STOP: u8 = 0x00;
+	const ADD: u8 = 0x01;
+	const LT: u8 = 0x10;
+	const EQ: u8 = 0x14;
+	const ISZERO: u8 = 0x15;
+	const OR: u8 = 0x17;
+	const SHR: u8 = 0x1c;
+	const CALLER: u8 = 0x33;
+	const CALLVALUE: u8 = 0x34;
+	const CALLDATALOAD: u8 = 0x35;
+	const CODECOPY: u8 = 0x39;
+	const TIMESTAMP: u8 = 0x42;
+	const MSTORE: u8 = 0x52;
+	const SLOAD: u8 = 0x54;
+	const SSTORE: u8 = 0x55;
+	const JUMPI: u8 = 0x57;
+	const GAS: u8 = 0x5a;
+	const JUMPDEST: u8 = 0x5b;
+	const PUSH1: u8 = 0x60;
+	const PUSH2: u8 = 0x61;
+	const DUP1: u8 = 0x80;
+	const DUP6: u8 = 0x85;
+	const LOG2: u8 = 0xa2;
+	const CALL: u8 = 0xf1;
+	const RETURN: u8 = 0xf3;
+	const REVERT: u8 = 0xfd;
+
+	/// Offsets of the fields of an escrow from its storage slot
+	const PAYER: u8 = 0;
+	const PAYEE: u8 = 1;
+	const DEADLINE: u8 = 2;
+	const AMOUNT: u8 = 3;
+	const DISPUTED: u8 = 4;
+
+	/// Assembler of EVM bytecode, resolving the destinations of the jumps once assembled
+	#[derive(Default)]
+	struct Assembler {
+		code: Vec<u8>,
+		labels: HashMap<&'static str, u16>,
+		jumps: Vec<(usize, &'static str)>,
+	}
+
+	impl Assembler {
+		fn op(&mut self, ops: &[u8]) -> &mut Self {
+			self.code.extend_from_slice(ops);
+			self
+		}
+
+		fn push(&mut self, value: &[u8]) -> &mut Self {
+			self.code.push(PUSH1 + value.len() as u8 - 1);
+			self.op(value)
+		}
+
+		fn label(&mut self, label: &'static str) -> &mut Self {
+			self.labels.insert(label, self.code.len() as u16);
+			self.op(&[JUMPDEST])
+		}
+
+		/// Jump to the label when the top of the stack is not zero
+		fn jump_if(&mut self, label: &'static str) -> &mut Self {
+			self.code.push(PUSH2);
+			self.jumps.push((self.code.len(), label));
+			self.op(&[0, 0, JUMPI])
+		}
+
+		/// The argument of the call at `index`, after the selector
+		fn arg(&mut self, index: u8) -> &mut Self {
+			self.push(&[4 + 32 * index]).op(&[CALLDATALOAD])
+		}
+
+		/// The storage slot of a field of the escrow identified by the first argument
+		fn field(&mut self, offset: u8) -> &mut Self {
+			self.arg(0).push(&[offset]).op(&[ADD])
+		}
+
+		fn only_payer(&mut self) -> &mut Self {
+			self.field(PAYER).op(&[SLOAD, CALLER, EQ, ISZERO]).jump_if("revert")
+		}
+
+		fn not_disputed(&mut self) -> &mut Self {
+			self.field(DISPUTED).op(&[SLOAD]).jump_if("revert")
+		}
+
+		/// Send the escrowed amount to the address on the stack, emptying the escrow
+		fn pay_out(&mut self) -> &mut Self {
+			// No return data, no call data, then the amount
+			self.push(&[0]).op(&[DUP1, DUP1, DUP1]).field(AMOUNT).op(&[SLOAD]);
+			self.push(&[0]).field(AMOUNT).op(&[SSTORE]);
+			// The address below them, as the recipient
+			self.op(&[DUP6, GAS, CALL, ISZERO]).jump_if("revert")
+		}
+
+		/// Log the event with the escrow id as its indexed argument and the first `size` bytes
+		/// of memory as its data, then stop
+		fn emit(&mut self, event: &str, size: u8) -> &mut Self {
+			self.arg(0).push(&keccak256(event.as_bytes())).push(&[size]).push(&[0]);
+			self.op(&[LOG2, STOP])
+		}
+
+		fn assemble(&mut self) -> Vec<u8> {
+			for (at, label) in &self.jumps {
+				let destination = self.labels[label].to_be_bytes();
+				self.code[*at..*at + 2].copy_from_slice(&destination);
+			}
+			std::mem::take(&mut self.code)
+		}
+	}
+
+	// endregion: --- Support
+}
+
+// endregion: --- Tests
@@ -6,6 +6,7 @@ pub mod bindings;
 pub mod blockchain;
 pub mod data;
 pub mod error;
+pub mod escrow;
 pub mod model;
 pub mod runtime;
 mod submodule;
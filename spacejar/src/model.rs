@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt, sync::Arc};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
 use crate::error::RuntimeError;
 
@@ -26,6 +26,42 @@ pub enum ModelState {
 	Ready,
 	/// Model failed to load
 	Failed { error: String },
+	/// Model is being released from memory
+	Unloading,
+}
+
+impl ModelState {
+	/// Whether a model may go from this state to `next`: Registered -> Loading -> Ready or
+	/// Failed, and Ready -> Unloading -> Registered.
+	pub fn can_transition_to(&self, next: &ModelState) -> bool {
+		matches!(
+			(self, next),
+			(ModelState::Registered, ModelState::Loading)
+				| (ModelState::Loading, ModelState::Ready)
+				| (ModelState::Loading, ModelState::Failed { .. })
+				| (ModelState::Ready, ModelState::Unloading)
+				| (ModelState::Unloading, ModelState::Registered)
+		)
+	}
+}
+
+/// Errors of the write path of the model registry
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ModelTransitionError {
+	#[error("Model {0} not found")]
+	NotFound(ModelId),
+	#[error("Model {0} already registered")]
+	AlreadyRegistered(ModelId),
+	#[error("Model {id} cannot go from {from:?} to {to:?}")]
+	Invalid { id: ModelId, from: ModelState, to: ModelState },
+}
+
+/// A change of state of a model, broadcast to the subscribers of the registry
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelTransition {
+	pub id: ModelId,
+	pub from: ModelState,
+	pub to: ModelState,
 }
 
 /// Statistics for a model instance
@@ -82,6 +118,8 @@ pub trait ModelManager: Send + Sync {
 /// Implementation of a thread-safe model registry
 pub struct ModelRegistry {
 	models: Arc<RwLock<HashMap<ModelId, ModelState>>>,
+	/// Transition event channel
+	transitions: broadcast::Sender<ModelTransition>,
 }
 
 impl Default for ModelRegistry {
@@ -93,17 +131,53 @@ impl Default for ModelRegistry {
 impl ModelRegistry {
 	/// Create a new model registry with the specified resource configuration
 	pub fn new() -> Self {
-		Self { models: Arc::new(RwLock::new(HashMap::new())) }
+		let (transitions, _) = broadcast::channel(100);
+		Self { models: Arc::new(RwLock::new(HashMap::new())), transitions }
 	}
 
 	/// Add a new model to the registry
 	pub async fn add_model(&self, id: ModelId) -> Result<(), RuntimeError> {
 		let mut models = self.models.write().await;
+		if models.contains_key(&id) {
+			return Err(ModelTransitionError::AlreadyRegistered(id).into());
+		}
 
 		models.insert(id, ModelState::Registered);
 		Ok(())
 	}
 
+	/// Move a model to the given state, rejecting the transitions its current state does not
+	/// allow. The check and the update happen under the same lock, so concurrent writers cannot
+	/// both move a model out of the same state.
+	pub async fn transition(&self, id: &ModelId, to: ModelState) -> Result<(), RuntimeError> {
+		let mut models = self.models.write().await;
+		let state = models.get_mut(id).ok_or_else(|| ModelTransitionError::NotFound(id.clone()))?;
+		if !state.can_transition_to(&to) {
+			return Err(
+				ModelTransitionError::Invalid { id: id.clone(), from: state.clone(), to }.into()
+			);
+		}
+
+		let from = std::mem::replace(state, to.clone());
+		// Nobody listening to the transitions is fine.
+		let _ = self.transitions.send(ModelTransition { id: id.clone(), from, to });
+		Ok(())
+	}
+
+	/// Subscribe to the state transitions of the models
+	pub fn subscribe_transitions(&self) -> broadcast::Receiver<ModelTransition> {
+		self.transitions.subscribe()
+	}
+
+	/// Get the current state of a model
+	pub async fn get_model_state(&self, id: &ModelId) -> Result<ModelState, RuntimeError> {
+		let models = self.models.read().await;
+		Ok(models
+			.get(id)
+			.cloned()
+			.ok_or_else(|| ModelTransitionError::NotFound(id.clone()))?)
+	}
+
 	/// List all models in the registry
 	pub async fn list_models(&self) -> Result<HashMap<ModelId, ModelState>, RuntimeError> {
 		let models = self.models.read().await;
@@ -126,3 +200,150 @@ impl ModelRegistry {
 		}
 	}
 }
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	fn failed() -> ModelState {
+		ModelState::Failed { error: "Out of memory".into() }
+	}
+
+	#[test]
+	fn test_allowed_transitions() {
+		use ModelState::*;
+
+		let allowed = [
+			(Registered, Loading),
+			(Loading, Ready),
+			(Loading, failed()),
+			(Ready, Unloading),
+			(Unloading, Registered),
+		];
+		for (from, to) in &allowed {
+			assert!(from.can_transition_to(to), "{:?} -> {:?}", from, to);
+		}
+
+		let states = [Registered, Loading, Ready, failed(), Unloading];
+		for from in &states {
+			for to in &states {
+				let expected = allowed.iter().any(|(a, b)| {
+					std::mem::discriminant(a) == std::mem::discriminant(from)
+						&& std::mem::discriminant(b) == std::mem::discriminant(to)
+				});
+				assert_eq!(from.can_transition_to(to), expected, "{:?} -> {:?}", from, to);
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn test_transition_through_the_lifecycle() -> Result<()> {
+		let registry = ModelRegistry::new();
+		let id = ModelId("llama".into());
+		registry.add_model(id.clone()).await?;
+
+		for state in [ModelState::Loading, ModelState::Ready, ModelState::Unloading] {
+			registry.transition(&id, state).await?;
+		}
+		registry.transition(&id, ModelState::Registered).await?;
+		assert!(matches!(registry.get_model_state(&id).await?, ModelState::Registered));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_rejected_transition_keeps_the_state() -> Result<()> {
+		let registry = ModelRegistry::new();
+		let id = ModelId("llama".into());
+		registry.add_model(id.clone()).await?;
+
+		let result = registry.transition(&id, ModelState::Ready).await;
+		assert!(matches!(
+			result,
+			Err(RuntimeError::ModelTransition(ModelTransitionError::Invalid {
+				from: ModelState::Registered,
+				to: ModelState::Ready,
+				..
+			}))
+		));
+		assert!(matches!(registry.get_model_state(&id).await?, ModelState::Registered));
+
+		registry.transition(&id, ModelState::Loading).await?;
+		registry.transition(&id, failed()).await?;
+		let result = registry.transition(&id, ModelState::Ready).await;
+		assert!(matches!(
+			result,
+			Err(RuntimeError::ModelTransition(ModelTransitionError::Invalid {
+				from: ModelState::Failed { .. },
+				..
+			}))
+		));
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_transition_of_unknown_model() {
+		let registry = ModelRegistry::new();
+		let id = ModelId("llama".into());
+
+		let result = registry.transition(&id, ModelState::Loading).await;
+		assert!(matches!(
+			result,
+			Err(RuntimeError::ModelTransition(ModelTransitionError::NotFound(missing))) if missing == id
+		));
+	}
+
+	#[tokio::test]
+	async fn test_add_model_twice() -> Result<()> {
+		let registry = ModelRegistry::new();
+		let id = ModelId("llama".into());
+		registry.add_model(id.clone()).await?;
+		registry.transition(&id, ModelState::Loading).await?;
+
+		let result = registry.add_model(id.clone()).await;
+		assert!(matches!(
+			result,
+			Err(RuntimeError::ModelTransition(ModelTransitionError::AlreadyRegistered(duplicate))) if duplicate == id
+		));
+		// The registered model is left as it was
+		assert!(matches!(registry.get_model_state(&id).await?, ModelState::Loading));
+		assert_eq!(registry.list_models().await?.len(), 1);
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn test_transitions_are_broadcast() -> Result<()> {
+		let registry = ModelRegistry::new();
+		let id = ModelId("llama".into());
+		registry.add_model(id.clone()).await?;
+		let mut first = registry.subscribe_transitions();
+		let mut second = registry.subscribe_transitions();
+
+		registry.transition(&id, ModelState::Loading).await?;
+		// Rejected transitions are not broadcast
+		assert!(registry.transition(&id, ModelState::Unloading).await.is_err());
+		registry.transition(&id, ModelState::Ready).await?;
+
+		for subscriber in [&mut first, &mut second] {
+			let transition = subscriber.recv().await?;
+			assert_eq!(transition.id, id);
+			assert!(matches!(
+				(transition.from, transition.to),
+				(ModelState::Registered, ModelState::Loading)
+			));
+			let transition = subscriber.recv().await?;
+			assert!(matches!(
+				(transition.from, transition.to),
+				(ModelState::Loading, ModelState::Ready)
+			));
+			assert!(subscriber.try_recv().is_err());
+		}
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,383 @@
+use std::{error::Error, io::Write, net::SocketAddr, path::Path};
+
+use network::{AgentCard, Client, PeerId};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+	sync::watch,
+	task::spawn,
+};
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::{
+	cli::CtlCommand,
+	metrics::{http_response, read_request, RequestHead},
+	output::Output,
+};
+
+/// What the control API of a node reports on and acts upon.
+#[derive(Clone)]
+pub struct Admin {
+	pub peer_id: PeerId,
+	pub client: Client,
+	/// Cards of the agents served, as last advertised.
+	pub cards: watch::Receiver<Vec<AgentCard>>,
+	/// Stops the node gracefully once cancelled.
+	pub shutdown: CancellationToken,
+	/// Filter of the logs, replaced on `POST /log-level`.
+	pub log_filter: reload::Handle<EnvFilter, Registry>,
+	/// Token the requests must carry, see [`write_token`].
+	pub token: String,
+}
+
+/// Write a new random token of the control API to `path`, readable by its owner only, for
+/// `dasn ctl` to read it. Returns the token.
+pub fn write_token(path: &Path) -> std::io::Result<String> {
+	let token = hex::encode(rand::random::<[u8; 32]>());
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+	let mut file = options.open(path)?;
+	// The mode only applies to new files.
+	#[cfg(unix)]
+	file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+	file.write_all(token.as_bytes())?;
+	Ok(token)
+}
+
+/// Serve the control API of a running node in JSON:
+///
+/// - `GET /status`, `/peers`, `/agents`, `/topics` and `/routing` report on the node.
+/// - `POST /topics/subscribe?topic=...` and `/topics/unsubscribe?topic=...` change the topics.
+/// - `POST /bootstrap` dials the bootstrap peers again.
+/// - `POST /log-level?filter=...` replaces the filter of the logs, e.g. `info,network=debug`.
+/// - `POST /shutdown` stops the node gracefully.
+///
+/// Only meant to be reachable from the host. Requests must carry the token of the node in an
+/// `Authorization: Bearer` header, and the ones sent by web pages, with an `Origin` header, are
+/// refused.
+pub async fn serve(address: SocketAddr, admin: Admin) -> std::io::Result<()> {
+	if !address.ip().is_loopback() {
+		tracing::warn!("Control API served on {address}, reachable from other hosts");
+	}
+	let listener = TcpListener::bind(address).await?;
+	tracing::info!("Serving the control API on http://{}", listener.local_addr()?);
+	accept(listener, admin).await
+}
+
+async fn accept(listener: TcpListener, admin: Admin) -> std::io::Result<()> {
+	loop {
+		let (stream, remote) = listener.accept().await?;
+		let admin = admin.clone();
+		spawn(async move {
+			if let Err(e) = handle_connection(stream, admin).await {
+				tracing::debug!("Control request from {remote} failed: {e}");
+			}
+		});
+	}
+}
+
+async fn handle_connection(mut stream: TcpStream, mut admin: Admin) -> std::io::Result<()> {
+	let Some(request) = read_request(&mut stream).await? else {
+		return Ok(());
+	};
+	// Web pages the user visits may send requests to the host, with their origin.
+	let refused = if request.header("Origin").is_some() {
+		Some(("403 Forbidden", "Cross-origin requests are refused"))
+	} else if !authorized(&request, &admin.token) {
+		Some(("401 Unauthorized", "Missing or wrong token of the control API"))
+	} else {
+		None
+	};
+	if let Some((status, message)) = refused {
+		stream
+			.write_all(http_response(status, "text/plain", message).as_bytes())
+			.await?;
+		return stream.shutdown().await;
+	}
+
+	let RequestHead { method, target, .. } = request;
+	let (path, params) = target.split_once('?').unwrap_or((&target, ""));
+	let param = |name: &str| {
+		form_urlencoded::parse(params.as_bytes())
+			.find(|(key, value)| key == name && !value.is_empty())
+			.map(|(_, value)| value.into_owned())
+	};
+	let result = match (method.as_str(), path) {
+		("GET", "/status") => status(&mut admin).await.map_err(unavailable),
+		("GET", "/peers") => peers(&mut admin).await.map_err(unavailable),
+		("GET", "/agents") => Ok(serde_json::json!(*admin.cards.borrow())),
+		("GET", "/topics") => {
+			admin.client.topics().await.map(|t| serde_json::json!(t)).map_err(unavailable)
+		},
+		("GET", "/routing") => routing(&mut admin).await.map_err(unavailable),
+		("POST", "/topics/subscribe") => match param("topic") {
+			Some(topic) => match admin.client.subscribe(topic).await {
+				Ok(subscribed) => Ok(serde_json::json!({ "subscribed": subscribed })),
+				Err(e) => Err(("409 Conflict", e.to_string())),
+			},
+			None => Err(("400 Bad Request", "Missing parameter: topic".to_string())),
+		},
+		("POST", "/topics/unsubscribe") => match param("topic") {
+			Some(topic) => match admin.client.unsubscribe(topic).await {
+				Ok(unsubscribed) => Ok(serde_json::json!({ "unsubscribed": unsubscribed })),
+				Err(e) => Err(("503 Service Unavailable", e.to_string())),
+			},
+			None => Err(("400 Bad Request", "Missing parameter: topic".to_string())),
+		},
+		("POST", "/bootstrap") => match admin.client.bootstrap().await {
+			Ok(()) => Ok(serde_json::json!({ "bootstrapping": true })),
+			Err(e) => Err(("503 Service Unavailable", e.to_string())),
+		},
+		("POST", "/log-level") => match param("filter").map(|filter| EnvFilter::try_new(&filter)) {
+			Some(Ok(filter)) => {
+				let applied = filter.to_string();
+				match admin.log_filter.reload(filter) {
+					Ok(()) => {
+						tracing::info!("Log filter set to {applied} over the control API");
+						Ok(serde_json::json!({ "filter": applied }))
+					},
+					Err(e) => Err(("500 Internal Server Error", e.to_string())),
+				}
+			},
+			Some(Err(e)) => Err(("400 Bad Request", format!("Invalid log filter: {e}"))),
+			None => Err(("400 Bad Request", "Missing parameter: filter".to_string())),
+		},
+		("POST", "/shutdown") => {
+			tracing::info!("Shutdown requested over the control API");
+			admin.shutdown.cancel();
+			Ok(serde_json::json!({ "stopping": true }))
+		},
+		_ => Err(("404 Not Found", "Not Found".to_string())),
+	};
+	let response = match result {
+		Ok(body) => http_response("200 OK", "application/json", &body.to_string()),
+		Err((status, message)) => http_response(status, "text/plain", &message),
+	};
+
+	stream.write_all(response.as_bytes()).await?;
+	stream.shutdown().await
+}
+
+/// Whether the request carries the token of the node, compared in constant time not to leak it
+/// through the time taken.
+fn authorized(request: &RequestHead, token: &str) -> bool {
+	let Some(given) = request.header("Authorization").and_then(|v| v.strip_prefix("Bearer "))
+	else {
+		return false;
+	};
+	given.len() == token.len()
+		&& given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The answer to a request the node failed to serve, shutting down.
+fn unavailable(e: Box<dyn Error + Send>) -> (&'static str, String) {
+	("503 Service Unavailable", e.to_string())
+}
+
+async fn status(admin: &mut Admin) -> Result<serde_json::Value, Box<dyn Error + Send>> {
+	let agents: Vec<_> = admin
+		.cards
+		.borrow()
+		.iter()
+		.map(|card| {
+			serde_json::json!({
+				"name": card.name,
+				"version": card.version,
+				"pricing": card.pricing,
+			})
+		})
+		.collect();
+	let listen_addresses = admin.client.listen_addresses().await?;
+	let external_addresses = admin.client.external_addresses().await?;
+	let connected_peers = admin.client.connected_peers().await?.len();
+	let nat_status = admin.client.nat_status().await?;
+	Ok(serde_json::json!({
+		"peer_id": admin.peer_id.to_string(),
+		"agents": agents,
+		"listen_addresses": listen_addresses.iter().map(ToString::to_string).collect::<Vec<_>>(),
+		"external_addresses": external_addresses.iter().map(ToString::to_string).collect::<Vec<_>>(),
+		"connected_peers": connected_peers,
+		"nat_status": format!("{nat_status:?}"),
+	}))
+}
+
+async fn peers(admin: &mut Admin) -> Result<serde_json::Value, Box<dyn Error + Send>> {
+	let peers: Vec<_> = admin
+		.client
+		.connected_peers()
+		.await?
+		.into_iter()
+		.map(|peer| {
+			serde_json::json!({
+				"peer_id": peer.peer_id.to_string(),
+				"agent_version": peer.agent_version,
+				"connected_for": peer.connected_for.as_secs(),
+				"labels": peer.labels,
+			})
+		})
+		.collect();
+	Ok(serde_json::json!(peers))
+}
+
+async fn routing(admin: &mut Admin) -> Result<serde_json::Value, Box<dyn Error + Send>> {
+	let buckets: Vec<_> = admin
+		.client
+		.kbuckets()
+		.await?
+		.into_iter()
+		.map(|bucket| {
+			let peers: Vec<_> = bucket
+				.peers
+				.iter()
+				.map(|peer| {
+					serde_json::json!({
+						"peer_id": peer.peer_id.to_string(),
+						"addresses": peer.addresses.iter().map(ToString::to_string).collect::<Vec<_>>(),
+						"connected": peer.connected,
+					})
+				})
+				.collect();
+			serde_json::json!({ "index": bucket.index, "peers": peers })
+		})
+		.collect();
+	Ok(serde_json::json!(buckets))
+}
+
+/// Send `command` to the control API of the node running at `address`, with the token the node
+/// wrote to `token_file`, printing its answer.
+pub async fn ctl(
+	address: Option<SocketAddr>,
+	token_file: Option<&Path>,
+	command: CtlCommand,
+	output: &Output,
+) -> Result<(), Box<dyn Error>> {
+	let address = address.ok_or("No --admin-address of the node to control.")?;
+	let token_file = token_file.ok_or("No --admin-token file of the node to control.")?;
+	let token = std::fs::read_to_string(token_file)
+		.map_err(|e| format!("Failed to read the token {}: {e}", token_file.display()))?;
+	let query = |key, value: String| {
+		form_urlencoded::Serializer::new(String::new())
+			.append_pair(key, &value)
+			.finish()
+	};
+	let (method, target) = match command {
+		CtlCommand::Status => ("GET", "/status".to_string()),
+		CtlCommand::Peers => ("GET", "/peers".to_string()),
+		CtlCommand::Agents => ("GET", "/agents".to_string()),
+		CtlCommand::Topics => ("GET", "/topics".to_string()),
+		CtlCommand::Subscribe { topic } => {
+			("POST", format!("/topics/subscribe?{}", query("topic", topic)))
+		},
+		CtlCommand::Unsubscribe { topic } => {
+			("POST", format!("/topics/unsubscribe?{}", query("topic", topic)))
+		},
+		CtlCommand::Bootstrap => ("POST", "/bootstrap".to_string()),
+		CtlCommand::Routing => ("GET", "/routing".to_string()),
+		CtlCommand::LogLevel { filter } => {
+			("POST", format!("/log-level?{}", query("filter", filter)))
+		},
+		CtlCommand::Shutdown => ("POST", "/shutdown".to_string()),
+	};
+
+	let mut stream = TcpStream::connect(address).await?;
+	let request = format!(
+		"{method} {target} HTTP/1.1\r\nHost: {address}\r\nAuthorization: Bearer {}\r\n\
+		 Connection: close\r\n\r\n",
+		token.trim()
+	);
+	stream.write_all(request.as_bytes()).await?;
+	let mut response = String::new();
+	stream.read_to_string(&mut response).await?;
+
+	let (head, body) = response.split_once("\r\n\r\n").ok_or("Malformed control API response.")?;
+	let status = head.lines().next().and_then(|line| line.split_once(' ')).map(|(_, s)| s);
+	match (status, serde_json::from_str::<serde_json::Value>(body)) {
+		(Some("200 OK"), Ok(body)) => {
+			let text = serde_json::to_string_pretty(&body)?;
+			output.result(body, || println!("{text}"));
+		},
+		(status, _) => return Err(format!("{}: {body}", status.unwrap_or("No status")).into()),
+	}
+	Ok(())
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use network::{MdnsConfig, NetworkConfig};
+
+	use super::*;
+
+	/// Send a request with the given extra headers to the server and read its whole response.
+	async fn request(address: SocketAddr, request_line: &str, headers: &str) -> Result<String> {
+		let mut stream = TcpStream::connect(address).await?;
+		stream
+			.write_all(format!("{request_line}\r\nHost: localhost\r\n{headers}\r\n").as_bytes())
+			.await?;
+		let mut response = String::new();
+		stream.read_to_string(&mut response).await?;
+		Ok(response)
+	}
+
+	#[tokio::test]
+	async fn test_requests_need_the_token_and_no_origin() -> Result<()> {
+		let config = NetworkConfig {
+			mdns: MdnsConfig { enabled: false, ..Default::default() },
+			..Default::default()
+		};
+		let (client, _events, peer_id, event_loop) = network::new(config).await?;
+		let cancellation_token = CancellationToken::new();
+		spawn(event_loop.run(cancellation_token.clone()));
+		let dir = tempfile::tempdir()?;
+		let token = write_token(&dir.path().join("admin.token"))?;
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			let mode = std::fs::metadata(dir.path().join("admin.token"))?.permissions().mode();
+			assert_eq!(mode & 0o777, 0o600);
+		}
+		let (_, log_filter) = reload::Layer::new(EnvFilter::new("info"));
+		let admin = Admin {
+			peer_id,
+			client,
+			cards: watch::channel(Vec::new()).1,
+			shutdown: CancellationToken::new(),
+			log_filter,
+			token: token.clone(),
+		};
+		let listener = TcpListener::bind("127.0.0.1:0").await?;
+		let address = listener.local_addr()?;
+		spawn(accept(listener, admin));
+
+		let bearer = format!("Authorization: Bearer {token}\r\n");
+		for (headers, status) in [
+			(String::new(), "401 Unauthorized"),
+			("Authorization: Bearer wrong\r\n".to_string(), "401 Unauthorized"),
+			(format!("{bearer}Origin: http://example.com\r\n"), "403 Forbidden"),
+			(bearer.clone(), "200 OK"),
+		] {
+			let response =
+				request(address, "POST /topics/subscribe?topic=a HTTP/1.1", &headers).await?;
+			assert!(response.starts_with(&format!("HTTP/1.1 {status}\r\n")), "{headers}");
+		}
+		let encoded = "POST /topics/subscribe?topic=agents%2Fecho%26x HTTP/1.1";
+		let response = request(address, encoded, &bearer).await?;
+		assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+		let response = request(address, "GET /topics HTTP/1.1", &bearer).await?;
+		let (_, body) = response.split_once("\r\n\r\n").ok_or("Expected a body")?;
+		let topics: Vec<String> = serde_json::from_str(body)?;
+		assert!(topics.contains(&"agents/echo&x".to_string()), "{topics:?}");
+
+		cancellation_token.cancel();
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
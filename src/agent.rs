@@ -1,17 +1,57 @@
-use ai_agent::conv;
-use ai_agent::model::ModelManager;
-use ai_agent::oa_client::new_oa_client;
-use ai_agent::tools::new_ai_tools;
-use rpc_router::resources_builder;
+use std::sync::Arc;
+
+use ai_agent::{
+	backend::{LlmBackend, ShadowBackend},
+	chat::{ContextMessage, Role},
+};
+use network::{types::MessageRole, Cid};
 use tokio::task::JoinSet;
 
-pub async fn respond_llm(message: String) -> Result<String, Box<dyn std::error::Error>> {
-	let mut output: Vec<String> = vec![];
-	let oa_client = new_oa_client()?;
+/// Build the backend of an agent, selected by `AGENT_BACKEND`, mirroring `shadow_percent`% of its
+/// requests to the `shadow` backend profile when set.
+pub fn new_backend(
+	shadow: Option<&str>,
+	shadow_percent: u8,
+) -> ai_agent::Result<Arc<dyn LlmBackend>> {
+	let backend = ai_agent::backend::new_backend_from_env()?;
+	let Some(profile) = shadow else {
+		return Ok(backend);
+	};
+
+	let shadow = ai_agent::backend::new_backend(profile)?;
+	tracing::info!("Mirroring {shadow_percent}% of the requests to the {profile} backend");
+	Ok(Arc::new(ShadowBackend::new(backend, shadow, shadow_percent)))
+}
+
+/// The prior messages of a request, as given to the backends.
+pub fn context_messages(context: Vec<network::types::ContextMessage>) -> Vec<ContextMessage> {
+	context
+		.into_iter()
+		.map(|message| ContextMessage {
+			role: match message.role {
+				MessageRole::User => Role::User,
+				MessageRole::Assistant => Role::Assistant,
+			},
+			content: message.content,
+		})
+		.collect()
+}
 
-	let mm = ModelManager::default();
-	let ai_tools = new_ai_tools(Some(resources_builder![mm]))?;
-	let message = message.clone();
+/// The message of a request preceded by its attachments, as text.
+pub fn with_attachments(message: String, attachments: Vec<(Cid, Vec<u8>)>) -> String {
+	let mut attached = String::new();
+	for (cid, data) in attachments {
+		attached.push_str(&format!("== Attachment {cid}:\n{}\n\n", String::from_utf8_lossy(&data)));
+	}
+	attached + &message
+}
+
+pub async fn respond_llm(
+	backend: Arc<dyn LlmBackend>,
+	context: Vec<ContextMessage>,
+	message: String,
+) -> Result<String, Box<dyn std::error::Error>> {
+	let mut output: Vec<String> = vec![];
 
 	// -- User questions
 	let formatted_question = format!(
@@ -25,11 +65,11 @@ pub async fn respond_llm(message: String) -> Result<String, Box<dyn std::error::
 	let mut join_set: JoinSet<(String, Result<String, ai_agent::Error>)> = JoinSet::new();
 
 	for question in questions {
-		let oa_client = oa_client.clone();
-		let ai_tools = ai_tools.clone();
+		let backend = backend.clone();
+		let context = context.clone();
 		join_set.spawn(async move {
 			// Execute user question.
-			let result = conv::send_user_msg(oa_client, ai_tools, &question).await;
+			let result = backend.complete_with_context(&context, &question).await;
 
 			(question.to_string(), result)
 		});
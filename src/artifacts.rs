@@ -0,0 +1,54 @@
+use std::{error::Error, time::Duration};
+
+use network::ArtifactStore;
+use serde_json::json;
+
+use crate::{cli::ArtifactsCommand, output::Output};
+
+/// Run a `dasn artifacts` command, which needs no network.
+pub fn run(command: ArtifactsCommand, output: &Output) -> Result<(), Box<dyn Error>> {
+	match command {
+		ArtifactsCommand::Pin { store, cid } => {
+			let count = ArtifactStore::open(store)?.pin(&cid)?;
+			output.result(json!({ "cid": cid.to_string(), "pins": count }), || {
+				println!("Pinned {cid} ({count} pins)")
+			});
+		},
+		ArtifactsCommand::Unpin { store, cid } => {
+			let count = ArtifactStore::open(store)?.unpin(&cid)?;
+			output.result(json!({ "cid": cid.to_string(), "pins": count }), || {
+				println!("Unpinned {cid} ({count} pins left)")
+			});
+		},
+		ArtifactsCommand::Gc { store, ttl, quota } => {
+			let store = ArtifactStore::open(store)?.with_quota(quota);
+			let report = store.gc(Duration::from_secs(ttl))?;
+			let result = json!({
+				"artifacts": report.artifacts,
+				"chunks": report.chunks,
+				"bytes": report.bytes,
+			});
+			output.result(result, || {
+				println!(
+					"Removed {} artifacts and {} chunks, {} bytes",
+					report.artifacts, report.chunks, report.bytes
+				)
+			});
+		},
+		ArtifactsCommand::Stats { store } => {
+			let stats = ArtifactStore::open(store)?.stats()?;
+			let result = json!({
+				"artifacts": stats.artifacts,
+				"pinned": stats.pinned,
+				"chunks": stats.chunks,
+				"bytes": stats.bytes,
+			});
+			output.result(result, || {
+				println!("Artifacts: {} ({} pinned)", stats.artifacts, stats.pinned);
+				println!("Chunks: {}", stats.chunks);
+				println!("Bytes: {}", stats.bytes);
+			});
+		},
+	}
+	Ok(())
+}
@@ -1,5 +1,7 @@
-use clap::{Parser, Subcommand};
-use network::Multiaddr;
+use clap::{Args, Parser, Subcommand};
+use network::{types::TaskType, BulletinKind, Cid, Multiaddr, PeerId, Quorum, Rating};
+
+use crate::sessions::ExportFormat;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -10,11 +12,26 @@ use network::Multiaddr;
     version = env!("CARGO_PKG_VERSION"),
 )]
 pub struct Cli {
+	#[arg(
+		long,
+		value_name = "FILE",
+		help = "TOML file of settings the flags override, ~/.config/dasn/config.toml if it exists"
+	)]
+	pub config: Option<std::path::PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		conflicts_with = "secret_key_seed",
+		help = "File keeping the key of the node, generated on first use"
+	)]
+	pub identity: Option<std::path::PathBuf>,
+
 	#[arg(
 		long,
 		short = 's',
 		value_name = "SECRET_KEY_SEED",
-		help = "Secret key seed for the node"
+		help = "Secret key seed for the node, a key anyone can derive: for tests, use --identity otherwise"
 	)]
 	pub secret_key_seed: Option<u8>,
 
@@ -26,6 +43,58 @@ pub struct Cli {
 	)]
 	pub peer: Vec<Multiaddr>,
 
+	#[arg(
+		long,
+		value_name = "BOOTSTRAP_PEER",
+		help = "Multiaddress or /dnsaddr seed to bootstrap the DHT from (can be multiple)"
+	)]
+	pub bootstrap_peer: Vec<Multiaddr>,
+
+	#[arg(
+		long,
+		value_name = "BOOTSTRAP_FILE",
+		help = "File listing bootstrap multiaddresses, one per line"
+	)]
+	pub bootstrap_file: Option<std::path::PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "SOURCE",
+		requires = "snapshot_signer",
+		help = "URL or file of a signed network snapshot to start from, its peers dialed along \
+		        with the bootstrap peers"
+	)]
+	pub snapshot: Option<String>,
+
+	#[arg(
+		long,
+		value_name = "PEER_ID",
+		help = "Trust the network snapshots signed by this peer (can be multiple)"
+	)]
+	pub snapshot_signer: Vec<PeerId>,
+
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		default_value_t = 7 * 24 * 60 * 60,
+		help = "Ignore the network snapshots published longer ago"
+	)]
+	pub snapshot_max_age: u64,
+
+	#[arg(
+		long,
+		value_name = "RENDEZVOUS_POINT",
+		help = "Multiaddress of the rendezvous point to register with and discover peers from"
+	)]
+	pub rendezvous_point: Option<Multiaddr>,
+
+	#[arg(
+		long,
+		value_name = "EXTERNAL_ADDRESS",
+		help = "Multiaddress this node is reachable at, advertised to the rendezvous point"
+	)]
+	pub external_address: Option<Multiaddr>,
+
 	#[arg(
 		long,
 		short = 'l',
@@ -34,18 +103,358 @@ pub struct Cli {
 	)]
 	pub listen_address: Vec<Multiaddr>,
 
+	#[arg(
+		long,
+		value_name = "TOPIC=PEER_ID",
+		value_parser = parse_topic_publisher,
+		help = "Only accept messages on TOPIC published by PEER_ID (can be multiple)"
+	)]
+	pub topic_publisher: Vec<(String, PeerId)>,
+
+	#[arg(
+		long,
+		value_name = "PEER_ID",
+		help = "Trust configuration bulletins signed by this peer (can be multiple)"
+	)]
+	pub bulletin_issuer: Vec<PeerId>,
+
+	#[arg(long, help = "Apply trusted bulletins automatically instead of only reporting them")]
+	pub apply_bulletins: bool,
+
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		default_value_t = 60 * 60,
+		help = "Ignore the bulletins issued longer ago"
+	)]
+	pub bulletin_max_age: u64,
+
+	#[arg(
+		long,
+		value_name = "TOPIC",
+		help = "Keep following this topic even if a bulletin bans it (can be multiple)"
+	)]
+	pub pin_topic: Vec<String>,
+
+	#[arg(
+		long,
+		value_name = "METRICS_ADDRESS",
+		help = "Socket address to serve Prometheus metrics and the gossip history on, e.g. 127.0.0.1:9090"
+	)]
+	pub metrics_address: Option<std::net::SocketAddr>,
+
+	#[arg(
+		long,
+		global = true,
+		value_name = "ADMIN_ADDRESS",
+		help = "Socket address of the control API the node serves and ctl talks to, e.g. 127.0.0.1:9091"
+	)]
+	pub admin_address: Option<std::net::SocketAddr>,
+
+	#[arg(
+		long,
+		global = true,
+		value_name = "FILE",
+		help = "File the node writes the token of its control API to, readable by its owner only, \
+		        and ctl reads it from"
+	)]
+	pub admin_token: Option<std::path::PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		help = "YAML file of alert rules raising alerts from the events and metrics of the node"
+	)]
+	pub alert_rules: Option<std::path::PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		help = "JSON file keeping the labels of the peers across restarts, edited with labels"
+	)]
+	pub address_book: Option<std::path::PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		help = "File keeping the gossip history served with the metrics across restarts"
+	)]
+	pub gossip_history: Option<std::path::PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "ALGORITHM",
+		help = "Compression offered to the peers for requests and artifacts, zstd or gzip, \
+		        preferred first (can be multiple)"
+	)]
+	pub compression: Vec<network::Compression>,
+
+	#[arg(
+		long,
+		value_name = "BYTES",
+		default_value_t = 1024,
+		help = "Payloads smaller than this are sent uncompressed"
+	)]
+	pub compression_threshold: usize,
+
+	#[arg(
+		long,
+		help = "Advertise an encryption key in the published agent cards, and encrypt the \
+		        requests end to end to the providers advertising one"
+	)]
+	pub end_to_end_encryption: bool,
+
+	#[arg(
+		long,
+		value_name = "COUNT",
+		help = "Maximum number of agent requests served at once, the ones beyond are answered busy"
+	)]
+	pub max_inbound_requests: Option<usize>,
+
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		help = "Timeout of the agent requests to the providers without enough response history for an estimate"
+	)]
+	pub request_timeout: Option<u64>,
+
+	#[arg(long, value_name = "COUNT", help = "Maximum number of established connections")]
+	pub max_connections: Option<u32>,
+
+	#[arg(long, value_name = "COUNT", help = "Maximum number of established connections per peer")]
+	pub max_connections_per_peer: Option<u32>,
+
+	#[arg(long, value_name = "COUNT", help = "Maximum number of pending incoming connections")]
+	pub max_pending_incoming: Option<u32>,
+
+	#[arg(long, value_name = "COUNT", help = "Maximum number of pending outgoing connections")]
+	pub max_pending_outgoing: Option<u32>,
+
+	#[arg(long, value_name = "COUNT", help = "Number of addresses of a peer dialed concurrently")]
+	pub dial_concurrency: Option<std::num::NonZeroU8>,
+
+	#[arg(long, help = "Do not discover the peers of the local network over mDNS")]
+	pub no_mdns: bool,
+
+	#[arg(
+		long,
+		value_name = "TOPIC",
+		help = "Topic subscribed to for each provided agent, {agent} standing for its name \
+		        (can be multiple, defaults to agents/{agent}/requests and \
+		        agents/{agent}/announcements)"
+	)]
+	pub agent_topic: Vec<String>,
+
+	#[arg(long, help = "Do not subscribe to the topics of the provided agents")]
+	pub no_agent_topics: bool,
+
+	#[arg(long, help = "Do not send nor track the presence beacons of the peers")]
+	pub no_presence: bool,
+
+	#[arg(long, help = "Discover the peers of the local network over IPv6 instead of IPv4")]
+	pub mdns_ipv6: bool,
+
+	#[arg(
+		long,
+		value_name = "INTERFACE",
+		help = "Only keep the peers discovered over mDNS on this network interface (can be multiple)"
+	)]
+	pub mdns_interface: Vec<String>,
+
+	#[arg(long, short = 'v', help = "Print the progress of the DHT queries")]
+	pub verbose: bool,
+
+	#[arg(
+		long,
+		global = true,
+		help = "Print the result, or the error, as a single versioned JSON document on stdout, \
+		        the text and the logs going to stderr"
+	)]
+	pub json: bool,
+
+	#[cfg(feature = "pnet")]
+	#[arg(
+		long,
+		value_name = "PSK_FILE",
+		help = "Pre-shared key file of a private swarm (falls back to the DASN_PSK env variable)"
+	)]
+	pub psk_file: Option<std::path::PathBuf>,
+
 	#[clap(subcommand)]
 	pub command: Commands,
 }
 
+/// Settings shared by the agents a node serves, with `provide` or `daemon`.
+#[derive(Args, Debug)]
+pub struct ProviderArgs {
+	#[arg(
+		long,
+		value_name = "PROFILE",
+		help = "Backend to mirror live requests to for evaluation, e.g. openai:gpt-4o-mini"
+	)]
+	pub shadow: Option<String>,
+	#[arg(
+		long,
+		default_value_t = 10,
+		value_parser = clap::value_parser!(u8).range(0..=100),
+		help = "Share of the live requests mirrored to the shadow backend, in percent"
+	)]
+	pub shadow_percent: u8,
+	#[arg(
+		long,
+		default_value_t = 4,
+		help = "Agents whose backend is kept warm between requests, least recently used evicted"
+	)]
+	pub warm_pool_size: usize,
+	#[arg(
+		long,
+		value_name = "LANGUAGE=PROFILE",
+		value_parser = parse_language_profile,
+		help = "Backend answering the requests in a language, e.g. fr=openai:gpt-4o (can be multiple)"
+	)]
+	pub language_profile: Vec<(String, String)>,
+	#[arg(
+		long,
+		value_name = "FILE",
+		help = "JSON Lines file to record the exchanges served and their ratings in"
+	)]
+	pub session_store: Option<std::path::PathBuf>,
+	#[arg(
+		long,
+		help = "Hide a tag of the node in the text of the answers as invisible characters, on \
+		        top of the provenance trailer ending every answer"
+	)]
+	pub watermark: bool,
+	#[arg(
+		long,
+		value_name = "DIR",
+		help = "Directory keeping the tasks won, and the queue of the ones not finished yet, \
+		        across restarts"
+	)]
+	pub task_dir: Option<std::path::PathBuf>,
+	#[arg(
+		long,
+		value_name = "DIR",
+		help = "Artifact store caching the attachments of the requests, collected hourly"
+	)]
+	pub artifact_store: Option<std::path::PathBuf>,
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		default_value_t = 86_400,
+		requires = "artifact_store",
+		help = "Time to live of the cached attachments"
+	)]
+	pub artifact_ttl: u64,
+	#[arg(
+		long,
+		value_name = "BYTES",
+		requires = "artifact_store",
+		help = "Most bytes of attachments cached, the oldest evicted past it"
+	)]
+	pub artifact_quota: Option<u64>,
+	#[arg(
+		long,
+		value_name = "FETCHES",
+		requires = "artifact_store",
+		help = "Mirror the attachments fetched this many times within ten minutes, serving them \
+		        to the peers that look them up on the DHT"
+	)]
+	pub mirror_threshold: Option<usize>,
+}
+
+fn parse_topic_publisher(value: &str) -> Result<(String, PeerId), String> {
+	let (topic, peer_id) = value
+		.split_once('=')
+		.ok_or_else(|| format!("Expected TOPIC=PEER_ID, got {value}"))?;
+	Ok((topic.to_string(), peer_id.parse().map_err(|e| format!("Invalid peer id: {e}"))?))
+}
+
+fn parse_language_profile(value: &str) -> Result<(String, String), String> {
+	match value.split_once('=') {
+		Some((language, profile)) if !language.is_empty() && !profile.is_empty() => {
+			Ok((language.to_string(), profile.to_string()))
+		},
+		_ => Err(format!("Expected LANGUAGE=PROFILE, got {value}")),
+	}
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
 	#[clap(about = "Run a simple node just to bootstrap the network")]
-	Bootstrap {},
+	Bootstrap {
+		#[arg(long, help = "Also serve as the rendezvous point of the network")]
+		rendezvous: bool,
+		#[arg(
+			long,
+			value_name = "FILE",
+			help = "File to publish a signed snapshot of the network to, e.g. served over HTTP"
+		)]
+		publish_snapshot: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			value_name = "SECONDS",
+			default_value_t = 600,
+			requires = "publish_snapshot",
+			help = "How often the snapshot is published"
+		)]
+		snapshot_interval: u64,
+	},
 	#[clap(about = "Provide a an AI Agent to the network")]
 	Provide {
 		#[arg(long, help = "Name of the Agent to provide")]
 		name: String,
+		#[arg(long, default_value = "0.1.0", help = "Version of the Agent")]
+		version: String,
+		#[arg(long, help = "Capability of the Agent (can be multiple)")]
+		capability: Vec<String>,
+		#[arg(long, default_value_t = 0.0, help = "Price of a single request")]
+		price: f64,
+		#[arg(
+			long,
+			value_name = "SCHEDULE",
+			help = "Price factors by UTC hour, e.g. 22-6=0.5,17-21=1.5, applied to the advertised \
+			        price and to the bids on the task proposals for the Agent"
+		)]
+		price_schedule: Option<network::PriceSchedule>,
+		#[arg(
+			long,
+			value_name = "STRATEGY",
+			help = "How to price the bids from the scheduled price: cost-plus, e.g. \
+			        cost-plus:margin=0.2,per-kib=0.01,max-load=4, or surge, e.g. \
+			        surge:step=0.25,max-load=8"
+		)]
+		bid_strategy: Option<network::StrategyConfig>,
+		#[arg(long, help = "Task type the Agent supports (can be multiple)")]
+		task_type: Vec<TaskType>,
+		#[arg(
+			long,
+			value_name = "BYTES",
+			help = "Only accept longer messages from requesters that answered a signed challenge"
+		)]
+		challenge_above: Option<usize>,
+		#[arg(long, help = "Language the Agent answers in, e.g. en (can be multiple)")]
+		language: Vec<String>,
+		#[arg(
+			long,
+			value_name = "EXPRESSION",
+			help = "Only serve the requests the expression holds for, e.g. \
+			        \"tokens < 2000 && (hour >= 8 && hour < 18 || peer in ['12D3KooW...'])\", \
+			        over peer, agent, language, size, tokens and hour"
+		)]
+		policy: Option<network::RequestPolicy>,
+		#[command(flatten)]
+		provider: ProviderArgs,
+	},
+	#[clap(about = "Run the node long-lived, serving the agents of the config file")]
+	Daemon {
+		#[arg(long, help = "Also serve as the rendezvous point of the network")]
+		rendezvous: bool,
+		#[command(flatten)]
+		provider: ProviderArgs,
+		/// The `[[agents]]` of the config file.
+		#[arg(skip)]
+		agents: Vec<crate::config::AgentConfig>,
 	},
 	#[clap(about = "request LLM content from an agent in the network")]
 	Llm {
@@ -53,6 +462,208 @@ pub enum Commands {
 		name: String,
 		#[arg(long, help = "Message to send to the agent")]
 		message: String,
+		#[arg(long, help = "Stream the answer as it is generated")]
+		stream: bool,
+		#[arg(
+			long,
+			default_value_t = 16,
+			help = "Tokens the provider may generate ahead of the ones printed when streaming"
+		)]
+		window: u32,
+		#[arg(
+			long,
+			help = "Only call the providers answering a ping over a new connection to their advertised addresses"
+		)]
+		dial_back: bool,
+		#[arg(
+			long,
+			value_name = "FILE",
+			conflicts_with = "stream",
+			help = "JSON file with the prior messages of the conversation, e.g. [{\"role\": \"user\", \"content\": \"...\"}]"
+		)]
+		context: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			value_name = "MILLISECONDS",
+			conflicts_with = "stream",
+			help = "Call the fastest provider alone, and the next one too when no answer arrived within this delay"
+		)]
+		hedge_delay: Option<u64>,
+		#[arg(
+			long,
+			value_name = "N-of-M",
+			conflicts_with_all = ["stream", "hedge_delay"],
+			help = "Ask M providers at once and only print the answer N of them agree on, e.g. 2-of-3"
+		)]
+		redundancy: Option<network::Redundancy>,
+		#[arg(
+			long,
+			value_name = "PRICE",
+			help = "Skip the providers pricing the agent above PRICE"
+		)]
+		max_price: Option<f64>,
+		#[arg(
+			long,
+			value_name = "SELECTOR",
+			help = "Only call the providers carrying these labels in the address book, e.g. \
+			        gpu=true,region=eu"
+		)]
+		labels: Option<network::LabelSelector>,
+		#[arg(
+			long,
+			help = "Language to get the answer in, e.g. fr, preferring the providers advertising it"
+		)]
+		language: Option<String>,
+		#[arg(long, value_name = "FILE", help = "JSON Lines file to record the exchange in")]
+		session_store: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			value_name = "CHARACTERS",
+			conflicts_with = "stream",
+			help = "Longest answer accepted"
+		)]
+		max_length: Option<usize>,
+		#[arg(
+			long,
+			value_name = "FILE",
+			conflicts_with = "stream",
+			help = "JSON schema the answer must match"
+		)]
+		json_schema: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			value_name = "TERM",
+			conflicts_with = "stream",
+			help = "Term the answer must not contain, regardless of case"
+		)]
+		block_term: Vec<String>,
+		#[arg(
+			long,
+			value_name = "COUNT",
+			conflicts_with = "stream",
+			help = "Sources the answer must cite, as URLs or numbered references such as [1]"
+		)]
+		min_citations: Option<usize>,
+		#[arg(
+			long,
+			default_value = "reject",
+			help = "What to do with an answer failing the checks: reject, retry another provider or warn"
+		)]
+		on_invalid: network::OnInvalid,
+		#[arg(
+			long,
+			value_name = "FILE",
+			help = "JSON file to journal the request in until it is answered, for resume to send it \
+			        again after a crash"
+		)]
+		journal: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			value_name = "CID",
+			requires = "artifact_store",
+			conflicts_with_all = ["stream", "hedge_delay", "redundancy"],
+			help = "Artifact stored by upload to attach to the message, fetched by the provider \
+			        (can be multiple)"
+		)]
+		attach: Vec<Cid>,
+		#[arg(long, value_name = "DIR", help = "Artifact store to serve the attachments from")]
+		artifact_store: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			value_name = "SETTINGS",
+			default_value = "below=0,restore=0.25,period=600,probe=0.1",
+			help = "Demote the providers whose reputation drops below a score until it reaches the \
+			        restore one, probing them with a share of the requests after the period in seconds"
+		)]
+		cooldown: network::CooldownConfig,
+		#[arg(
+			long,
+			value_name = "FILE",
+			help = "JSON file keeping the demoted providers between requests, for their cooldown to \
+			        elapse"
+		)]
+		cooldown_state: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			value_name = "SETTINGS",
+			default_value = "failures=3,cooldown=30,probe=60",
+			help = "Skip the providers failing that many requests in a row for the cooldown in \
+			        seconds, then let a single probe through, failed when unanswered within its timeout"
+		)]
+		circuit_breaker: network::selection::CircuitBreakerConfig,
+		#[arg(
+			long,
+			value_name = "FILE",
+			help = "JSON file keeping the circuits of the failing providers between requests"
+		)]
+		circuit_state: Option<std::path::PathBuf>,
+		#[arg(
+			long,
+			help = "Print how long each stage took: discovery, dial, selection of the providers, \
+			        request, or first token and the rest of a stream"
+		)]
+		timing: bool,
+	},
+	#[clap(about = "Store a file in the artifact store, printing its content id for llm --attach")]
+	Upload {
+		#[arg(help = "File to store")]
+		file: std::path::PathBuf,
+		#[arg(long, value_name = "DIR", help = "Artifact store to keep the file in")]
+		store: std::path::PathBuf,
+		#[arg(long, help = "Pin the file for the garbage collection of the store to keep it")]
+		pin: bool,
+	},
+	#[clap(about = "Manage the artifact store")]
+	Artifacts {
+		#[clap(subcommand)]
+		command: ArtifactsCommand,
+	},
+	#[clap(about = "Send the requests of a journal left unanswered again to their providers")]
+	Resume {
+		#[arg(long, value_name = "FILE", help = "Journal written by llm --journal")]
+		journal: std::path::PathBuf,
+		#[arg(
+			long,
+			default_value_t = 3,
+			help = "Attempts at a request, the first one included, before it is given up on"
+		)]
+		max_attempts: u32,
+		#[arg(long, value_name = "DIR", help = "Artifact store to serve the attachments from")]
+		artifact_store: Option<std::path::PathBuf>,
+	},
+	#[clap(
+		about = "Send a request recorded by llm --session-store again, diffing the new answer \
+		         against the recorded one"
+	)]
+	Replay {
+		#[arg(help = "Receipt of the exchange, printed by llm")]
+		receipt: Cid,
+		#[arg(long, value_name = "FILE", help = "Session store the exchange was recorded in")]
+		session_store: std::path::PathBuf,
+		#[arg(long, help = "Provider to send the request to, the one that answered by default")]
+		provider: Option<PeerId>,
+	},
+	#[clap(about = "Rate an exchange with an agent, by the receipt printed by llm")]
+	Feedback {
+		#[arg(long, help = "Provider that answered the exchange")]
+		provider: PeerId,
+		#[arg(long, help = "Name of the agent")]
+		name: String,
+		#[arg(long, help = "Receipt of the exchange")]
+		receipt: Cid,
+		#[arg(long, help = "Rating of the exchange: up or down")]
+		rating: Rating,
+		#[arg(long, help = "Comment on the exchange")]
+		comment: Option<String>,
+	},
+	#[clap(about = "Attest the outcome of a task to the reputation ledger of its provider")]
+	Attest {
+		#[arg(long, help = "Provider that worked on the task")]
+		provider: PeerId,
+		#[arg(long, help = "Id of the task")]
+		task: String,
+		#[arg(long, help = "Outcome of the task: completed, failed or disputed")]
+		outcome: network::Outcome,
 	},
 	#[clap(about = "Gossip a message in the network")]
 	Gossip {
@@ -61,4 +672,400 @@ pub enum Commands {
 		#[arg(long, help = "Message to publish")]
 		message: String,
 	},
+	#[clap(
+		about = "Subscribe to gossip topics and print the messages received as JSON Lines until \
+		         interrupted"
+	)]
+	Subscribe {
+		#[arg(
+			long = "topic",
+			required = true,
+			help = "Topic to subscribe to, repeated for several"
+		)]
+		topics: Vec<String>,
+	},
+	#[clap(about = "List the agents of the capability directory matching a query, cheapest first")]
+	Providers {
+		#[arg(
+			value_name = "QUERY",
+			help = "Conditions the agents must match, e.g. \"model~gpt-4 AND price<0.01 AND lang=en \
+			        AND tools contains web_fetch\", any agent without"
+		)]
+		query: Option<network::AgentQuery>,
+		#[arg(
+			long,
+			value_name = "SECONDS",
+			default_value_t = 60,
+			help = "Time to collect the capability announcements of the providers for"
+		)]
+		wait: u64,
+	},
+	#[clap(about = "Print the DHT routing table, and the peers closest to a key")]
+	Routing {
+		#[arg(long, help = "Key to look up the closest peers of")]
+		key: Option<String>,
+	},
+	#[clap(about = "Publish a bulletin signed with the node key on the control topic")]
+	Bulletin {
+		#[clap(subcommand)]
+		kind: BulletinCommand,
+	},
+	#[clap(about = "Manage the wallet signing the transactions of the node")]
+	Wallet {
+		#[clap(subcommand)]
+		command: WalletCommand,
+	},
+	#[clap(about = "Manage the exchanges recorded by llm")]
+	Sessions {
+		#[clap(subcommand)]
+		command: SessionsCommand,
+	},
+	#[clap(about = "Manage the labels of the peers in the address book (--address-book)")]
+	Labels {
+		#[clap(subcommand)]
+		command: LabelsCommand,
+	},
+	#[clap(about = "Manage the identity file of the node (--identity)")]
+	Key {
+		#[clap(subcommand)]
+		command: KeyCommand,
+	},
+	#[clap(about = "Control a running node through its control API (--admin-address)")]
+	Ctl {
+		#[clap(subcommand)]
+		command: CtlCommand,
+	},
+	#[clap(about = "Read and write the DHT by hand, to debug it or publish agent metadata")]
+	Dht {
+		#[clap(subcommand)]
+		command: DhtCommand,
+	},
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DhtCommand {
+	#[clap(about = "Store a value under a key, replicated to the peers closest to it")]
+	Put {
+		#[arg(help = "Key of the record, e.g. /asn/agent/<name>/schema")]
+		key: String,
+		#[arg(help = "Value of the record, read from the standard input without")]
+		value: Option<String>,
+		#[arg(
+			long,
+			default_value = "1",
+			value_parser = parse_quorum,
+			help = "Peers that must store the record as well: a number, majority or all"
+		)]
+		quorum: Quorum,
+	},
+	#[clap(about = "Print the value stored under a key")]
+	Get {
+		#[arg(help = "Key of the record")]
+		key: String,
+		#[arg(
+			long,
+			default_value = "1",
+			value_parser = parse_quorum,
+			help = "Copies of the record to find: a number, majority or all"
+		)]
+		quorum: Quorum,
+	},
+	#[clap(about = "List the peers providing a key, e.g. /asn/agent/<name>")]
+	Providers {
+		#[arg(help = "Key provided")]
+		key: String,
+	},
+}
+
+fn parse_quorum(value: &str) -> Result<Quorum, String> {
+	match value {
+		"majority" => Ok(Quorum::Majority),
+		"all" => Ok(Quorum::All),
+		n => match n.parse::<std::num::NonZeroUsize>() {
+			Ok(n) if n.get() == 1 => Ok(Quorum::One),
+			Ok(n) => Ok(Quorum::N(n)),
+			Err(_) => Err(format!("Invalid quorum {value}, expected a number, majority or all")),
+		},
+	}
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommand {
+	#[clap(about = "Generate a new ed25519 identity, printing its peer id")]
+	Generate {
+		#[arg(long, help = "Replace the existing identity, changing the peer id of the node")]
+		force: bool,
+	},
+	#[clap(about = "Print the peer id and public key of the identity")]
+	Show,
+	#[clap(about = "Print the identity hex encoded, secret key included, to import it elsewhere")]
+	Export,
+	#[clap(
+		about = "Save an identity printed by export, read from the standard input or a file for \
+		         the secret key not to show in the shell history, printing its peer id"
+	)]
+	Import {
+		#[arg(long, help = "File holding the hex encoded identity, instead of the standard input")]
+		file: Option<std::path::PathBuf>,
+		#[arg(long, help = "Replace the existing identity, changing the peer id of the node")]
+		force: bool,
+	},
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LabelsCommand {
+	#[clap(about = "Attach labels to a peer, replacing the values of the keys it already has")]
+	Tag {
+		peer: PeerId,
+		#[arg(required = true, value_parser = parse_label, help = "Labels to attach, e.g. gpu=true")]
+		labels: Vec<(String, String)>,
+	},
+	#[clap(about = "Remove labels from a peer")]
+	Untag {
+		peer: PeerId,
+		#[arg(required = true, help = "Keys of the labels to remove")]
+		keys: Vec<String>,
+	},
+	#[clap(about = "List the labelled peers")]
+	List {
+		#[arg(long, help = "Only list the peers matching these labels, e.g. gpu=true,region=eu")]
+		selector: Option<network::LabelSelector>,
+	},
+}
+
+fn parse_label(value: &str) -> Result<(String, String), String> {
+	match value.split_once('=') {
+		Some((key, value)) if !key.trim().is_empty() => {
+			Ok((key.trim().to_string(), value.trim().to_string()))
+		},
+		_ => Err(format!("Invalid label {value}, expected key=value")),
+	}
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CtlCommand {
+	#[clap(about = "Print the addresses, agents and NAT status of the node")]
+	Status,
+	#[clap(about = "List the peers connected")]
+	Peers,
+	#[clap(about = "List the agents provided")]
+	Agents,
+	#[clap(about = "List the gossip topics subscribed to")]
+	Topics,
+	#[clap(about = "Subscribe to a gossip topic")]
+	Subscribe {
+		#[arg(help = "Topic to subscribe to")]
+		topic: String,
+	},
+	#[clap(about = "Unsubscribe from a gossip topic")]
+	Unsubscribe {
+		#[arg(help = "Topic to unsubscribe from")]
+		topic: String,
+	},
+	#[clap(about = "Dial the bootstrap peers and bootstrap the DHT again")]
+	Bootstrap,
+	#[clap(about = "Print the DHT routing table")]
+	Routing,
+	#[clap(about = "Change the filter of the logs, as RUST_LOG")]
+	LogLevel {
+		#[arg(help = "Log filter, e.g. info,network=debug")]
+		filter: String,
+	},
+	#[clap(about = "Stop the node gracefully")]
+	Shutdown,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ArtifactsCommand {
+	#[clap(about = "Pin an artifact for the garbage collection to keep it")]
+	Pin {
+		#[arg(long, value_name = "DIR", help = "Artifact store holding the artifact")]
+		store: std::path::PathBuf,
+		#[arg(help = "Content id of the artifact")]
+		cid: Cid,
+	},
+	#[clap(about = "Take back a pin of an artifact")]
+	Unpin {
+		#[arg(long, value_name = "DIR", help = "Artifact store holding the artifact")]
+		store: std::path::PathBuf,
+		#[arg(help = "Content id of the artifact")]
+		cid: Cid,
+	},
+	#[clap(about = "Remove the artifacts not pinned past their time to live, and their chunks")]
+	Gc {
+		#[arg(long, value_name = "DIR", help = "Artifact store to collect")]
+		store: std::path::PathBuf,
+		#[arg(
+			long,
+			value_name = "SECONDS",
+			default_value_t = 86_400,
+			help = "Time to live of the artifacts not pinned"
+		)]
+		ttl: u64,
+		#[arg(
+			long,
+			value_name = "BYTES",
+			help = "Size to evict the oldest artifacts not pinned down to"
+		)]
+		quota: Option<u64>,
+	},
+	#[clap(about = "Print the number of artifacts and chunks of the store, and their size")]
+	Stats {
+		#[arg(long, value_name = "DIR", help = "Artifact store to inspect")]
+		store: std::path::PathBuf,
+	},
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionsCommand {
+	#[clap(about = "Print the recorded exchanges as a transcript")]
+	Export {
+		#[arg(long, value_name = "FILE", help = "Session store the exchanges were recorded in")]
+		store: std::path::PathBuf,
+		#[arg(long, default_value = "md", help = "Format of the transcript: md or json")]
+		format: ExportFormat,
+		#[arg(long, help = "Only export the exchanges with this agent")]
+		agent: Option<String>,
+		#[arg(long, help = "Replace the email addresses and phone numbers of the messages")]
+		redact: bool,
+	},
+	#[clap(about = "Print the recorded exchanges as an OpenAI fine-tuning dataset, in JSON Lines")]
+	Dataset {
+		#[arg(long, value_name = "FILE", help = "Session store the exchanges were recorded in")]
+		store: std::path::PathBuf,
+		#[arg(long, help = "Only keep the exchanges with this agent")]
+		agent: Option<String>,
+		#[arg(
+			long,
+			value_name = "TIMESTAMP",
+			help = "Only keep the exchanges since this Unix time"
+		)]
+		since: Option<u64>,
+		#[arg(
+			long,
+			value_name = "TIMESTAMP",
+			help = "Only keep the exchanges before this Unix time"
+		)]
+		until: Option<u64>,
+		#[arg(
+			long,
+			value_name = "SCORE",
+			allow_negative_numbers = true,
+			help = "Only keep the exchanges rated up at least SCORE times more than down, unrated \
+			        ones scoring 0"
+		)]
+		min_rating: Option<i64>,
+	},
+	#[clap(about = "Print the ratings received by each agent and its quality score")]
+	Quality {
+		#[arg(long, value_name = "FILE", help = "Session store the exchanges were recorded in")]
+		store: std::path::PathBuf,
+	},
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WalletCommand {
+	#[clap(about = "Create a keystore with a new key, encrypted with DASN_WALLET_PASSWORD")]
+	New {
+		#[arg(long, help = "Path of the keystore to create")]
+		keystore: std::path::PathBuf,
+	},
+	#[clap(about = "Print the address of the wallet")]
+	Address {
+		#[clap(flatten)]
+		wallet: WalletArgs,
+	},
+	#[clap(about = "Print the balance of the wallet")]
+	Balance {
+		#[clap(flatten)]
+		wallet: WalletArgs,
+		#[arg(long, help = "JSON-RPC endpoint of the chain")]
+		rpc_url: String,
+	},
+	#[clap(about = "Estimate the gas and fees of a transaction from the wallet")]
+	Estimate {
+		#[clap(flatten)]
+		wallet: WalletArgs,
+		#[arg(long, help = "JSON-RPC endpoint of the chain")]
+		rpc_url: String,
+		#[arg(long, help = "Recipient, a contract deployment without it")]
+		to: Option<wallet::Address>,
+		#[arg(long, default_value_t = 0, help = "Value sent, in wei")]
+		value: u128,
+		#[arg(long, default_value = "", help = "Hex encoded call data")]
+		data: String,
+		#[clap(flatten)]
+		fees: FeeArgs,
+	},
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FeeArgs {
+	#[arg(long, default_value = "normal", help = "Inclusion speed: slow, normal or fast")]
+	pub speed: wallet::FeeSpeed,
+	#[arg(long, help = "Refuse a max fee per gas above it, in wei")]
+	pub max_fee_per_gas: Option<u128>,
+	#[arg(long, help = "Refuse a max priority fee per gas above it, in wei")]
+	pub max_priority_fee_per_gas: Option<u128>,
+	#[arg(long, help = "Refuse a transaction whose fees may exceed it, in wei")]
+	pub max_total_fee: Option<u128>,
+}
+
+impl From<&FeeArgs> for wallet::FeePolicy {
+	fn from(fees: &FeeArgs) -> Self {
+		Self {
+			speed: fees.speed,
+			max_fee_per_gas: fees.max_fee_per_gas,
+			max_priority_fee_per_gas: fees.max_priority_fee_per_gas,
+			max_total_fee: fees.max_total_fee,
+			..Default::default()
+		}
+	}
+}
+
+#[derive(clap::Args, Debug)]
+pub struct WalletArgs {
+	#[arg(long, help = "Keystore of the wallet, decrypted with DASN_WALLET_PASSWORD")]
+	pub keystore: Option<std::path::PathBuf>,
+	#[cfg(feature = "ledger")]
+	#[arg(long, conflicts_with = "keystore", help = "Use the Ledger plugged in")]
+	pub ledger: bool,
+	#[cfg(feature = "ledger")]
+	#[arg(long, default_value_t = 0, help = "Account of the Ledger, m/44'/60'/0'/0/<ACCOUNT>")]
+	pub account: u32,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BulletinCommand {
+	#[clap(about = "Recommend bootstrap peers")]
+	BootstrapPeers {
+		#[arg(long, help = "Multiaddress of a recommended peer (can be multiple)")]
+		peer: Vec<Multiaddr>,
+	},
+	#[clap(about = "Announce the deprecation of a protocol")]
+	Deprecate {
+		#[arg(long, help = "Deprecated protocol")]
+		protocol: String,
+		#[arg(long, help = "Notice shown to the operators")]
+		notice: String,
+	},
+	#[clap(about = "Ban a topic in an emergency")]
+	BanTopic {
+		#[arg(long, help = "Topic to stop following")]
+		topic: String,
+	},
+}
+
+impl From<BulletinCommand> for BulletinKind {
+	fn from(command: BulletinCommand) -> Self {
+		match command {
+			BulletinCommand::BootstrapPeers { peer } => {
+				BulletinKind::BootstrapPeers { peers: peer }
+			},
+			BulletinCommand::Deprecate { protocol, notice } => {
+				BulletinKind::Deprecation { protocol, notice }
+			},
+			BulletinCommand::BanTopic { topic } => BulletinKind::TopicBan { topic },
+		}
+	}
 }
@@ -0,0 +1,247 @@
+use std::{
+	collections::HashMap,
+	error::Error,
+	fmt::Display,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use clap::{parser::ValueSource, ArgMatches};
+use network::{types::TaskType, Multiaddr, PeerId, PriceSchedule, RequestPolicy, StrategyConfig};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::cli::{Cli, Commands};
+
+/// Settings of the node read from a TOML file, e.g.
+///
+/// ```toml
+/// listen_addresses = ["/ip4/0.0.0.0/tcp/4001"]
+/// bootstrap_peers = ["/dnsaddr/bootstrap.dasn.dev"]
+/// identity_file = "identity.key"
+/// agent_topics = ["agents/{agent}/requests"]
+///
+/// [topic_publishers]
+/// "control" = ["12D3KooW..."]
+///
+/// [openai]
+/// model = "gpt-4o-mini"
+///
+/// [[agents]]
+/// name = "writer"
+/// capabilities = ["summarize"]
+/// price = 0.5
+/// ```
+///
+/// The flags given on the command line take precedence over the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	#[serde(deserialize_with = "parsed")]
+	pub listen_addresses: Vec<Multiaddr>,
+	#[serde(deserialize_with = "parsed")]
+	pub peers: Vec<Multiaddr>,
+	#[serde(deserialize_with = "parsed")]
+	pub bootstrap_peers: Vec<Multiaddr>,
+	/// URL or file of a network snapshot, trusted when signed by one of `snapshot_signers`.
+	pub snapshot: Option<String>,
+	#[serde(deserialize_with = "parsed")]
+	pub snapshot_signers: Vec<PeerId>,
+	/// Address of the control API, served by the node and used by `dasn ctl`.
+	pub admin_address: Option<SocketAddr>,
+	/// Token of the control API, relative to the directory of the file.
+	pub admin_token_file: Option<PathBuf>,
+	/// Relative to the directory of the file.
+	pub identity_file: Option<PathBuf>,
+	pub agent_topics: Vec<String>,
+	pub pinned_topics: Vec<String>,
+	#[serde(deserialize_with = "topic_publishers")]
+	pub topic_publishers: Vec<(String, PeerId)>,
+	pub openai: OpenAiConfig,
+	/// Agents served by `dasn daemon`, `dasn provide --name` picking the settings of one.
+	pub agents: Vec<AgentConfig>,
+}
+
+/// Settings of the OpenAI backend, the environment variables taking precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenAiConfig {
+	pub api_key: Option<String>,
+	pub base_url: Option<String>,
+	pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentConfig {
+	pub name: String,
+	pub version: Option<String>,
+	#[serde(default)]
+	pub capabilities: Vec<String>,
+	pub price: Option<f64>,
+	#[serde(default, deserialize_with = "parsed")]
+	pub task_types: Vec<TaskType>,
+	#[serde(default)]
+	pub languages: Vec<String>,
+	#[serde(default, deserialize_with = "parsed_option")]
+	pub price_schedule: Option<PriceSchedule>,
+	#[serde(default, deserialize_with = "parsed_option")]
+	pub bid_strategy: Option<StrategyConfig>,
+	#[serde(default, deserialize_with = "parsed_option")]
+	pub policy: Option<RequestPolicy>,
+	pub challenge_above: Option<usize>,
+}
+
+/// `$XDG_CONFIG_HOME/dasn/config.toml`, `~/.config/dasn/config.toml` without it.
+fn default_path() -> Option<PathBuf> {
+	let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) => PathBuf::from(dir),
+		None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+	};
+	Some(dir.join("dasn").join("config.toml"))
+}
+
+/// Read the file given with `--config`, or the default one if it exists.
+pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+	let path = match path {
+		Some(path) => path.to_path_buf(),
+		None => match default_path() {
+			Some(path) if path.exists() => path,
+			_ => return Ok(Config::default()),
+		},
+	};
+	let mut config: Config = toml::from_str(&std::fs::read_to_string(&path)?)
+		.map_err(|e| format!("Invalid config {}: {e}", path.display()))?;
+	for file in [&mut config.identity_file, &mut config.admin_token_file].into_iter().flatten() {
+		if let Some(dir) = path.parent() {
+			*file = dir.join(&*file);
+		}
+	}
+	Ok(config)
+}
+
+impl Config {
+	/// Fill in the settings not given on the command line.
+	pub fn apply(self, cli: &mut Cli, matches: &ArgMatches) {
+		fill(&mut cli.listen_address, self.listen_addresses);
+		fill(&mut cli.peer, self.peers);
+		fill(&mut cli.bootstrap_peer, self.bootstrap_peers);
+		if cli.snapshot.is_none() {
+			cli.snapshot = self.snapshot;
+		}
+		fill(&mut cli.snapshot_signer, self.snapshot_signers);
+		cli.admin_address = cli.admin_address.or(self.admin_address);
+		cli.admin_token = cli.admin_token.take().or(self.admin_token_file);
+		fill(&mut cli.agent_topic, self.agent_topics);
+		fill(&mut cli.pin_topic, self.pinned_topics);
+		fill(&mut cli.topic_publisher, self.topic_publishers);
+		if cli.secret_key_seed.is_none() && cli.identity.is_none() {
+			cli.identity = self.identity_file;
+		}
+		self.openai.export();
+
+		if let Commands::Daemon { agents, .. } = &mut cli.command {
+			*agents = self.agents;
+			return;
+		}
+		let (
+			Commands::Provide {
+				name,
+				version,
+				capability,
+				price,
+				price_schedule,
+				bid_strategy,
+				task_type,
+				challenge_above,
+				language,
+				policy,
+				..
+			},
+			Some(("provide", matches)),
+		) = (&mut cli.command, matches.subcommand())
+		else {
+			return;
+		};
+		let Some(agent) = self.agents.into_iter().find(|agent| agent.name == *name) else {
+			return;
+		};
+		let given = |id| matches.value_source(id) == Some(ValueSource::CommandLine);
+		if let (Some(agent_version), false) = (agent.version, given("version")) {
+			*version = agent_version;
+		}
+		if let (Some(agent_price), false) = (agent.price, given("price")) {
+			*price = agent_price;
+		}
+		fill(capability, agent.capabilities);
+		fill(task_type, agent.task_types);
+		fill(language, agent.languages);
+		*price_schedule = price_schedule.take().or(agent.price_schedule);
+		*bid_strategy = bid_strategy.take().or(agent.bid_strategy);
+		*policy = policy.take().or(agent.policy);
+		*challenge_above = challenge_above.or(agent.challenge_above);
+	}
+}
+
+impl OpenAiConfig {
+	/// Hand the settings to the backends through the environment variables they read, unless
+	/// already set.
+	fn export(self) {
+		let settings = [
+			("OPENAI_API_KEY", self.api_key),
+			(ai_agent::oa_client::OPENAI_BASE_URL_ENV, self.base_url),
+			(ai_agent::backend::BACKEND_ENV, self.model.map(|model| format!("openai:{model}"))),
+		];
+		for (name, value) in settings {
+			if let (Some(value), None) = (value, std::env::var_os(name)) {
+				std::env::set_var(name, value);
+			}
+		}
+	}
+}
+
+/// Take the values of the file for a list left empty on the command line.
+fn fill<T>(flags: &mut Vec<T>, values: Vec<T>) {
+	if flags.is_empty() {
+		*flags = values;
+	}
+}
+
+/// Parse a list of values written as on the command line.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: FromStr,
+	T::Err: Display,
+{
+	Vec::<String>::deserialize(deserializer)?
+		.iter()
+		.map(|value| value.parse().map_err(de::Error::custom))
+		.collect()
+}
+
+/// Parse a value written as on the command line.
+fn parsed_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: FromStr,
+	T::Err: Display,
+{
+	Option::<String>::deserialize(deserializer)?
+		.map(|value| value.parse().map_err(de::Error::custom))
+		.transpose()
+}
+
+/// Parse a table of the peers allowed to publish on each topic.
+fn topic_publishers<'de, D>(deserializer: D) -> Result<Vec<(String, PeerId)>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let mut publishers = Vec::new();
+	for (topic, peer_ids) in HashMap::<String, Vec<String>>::deserialize(deserializer)? {
+		for peer_id in peer_ids {
+			publishers.push((topic.clone(), peer_id.parse().map_err(de::Error::custom)?));
+		}
+	}
+	Ok(publishers)
+}
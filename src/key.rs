@@ -0,0 +1,83 @@
+use std::{error::Error, io::Read, path::Path};
+
+use network::{config, identity};
+use serde_json::json;
+
+use crate::{cli::KeyCommand, output::Output};
+
+/// Run a `dasn key` command on the identity file of the node, which needs no network.
+pub fn run(
+	identity: Option<&Path>,
+	command: KeyCommand,
+	output: &Output,
+) -> Result<(), Box<dyn Error>> {
+	let path = identity.ok_or("Expected the identity file, with --identity or identity_file")?;
+	match command {
+		KeyCommand::Generate { force } => {
+			let key = identity::Keypair::generate_ed25519();
+			save(path, &key, force)?;
+			let peer_id = key.public().to_peer_id();
+			output.result(json!({ "peer_id": peer_id.to_string() }), || println!("{peer_id}"));
+		},
+		KeyCommand::Show => {
+			let key = load(path)?;
+			let peer_id = key.public().to_peer_id();
+			let public_key = hex::encode(key.public().encode_protobuf());
+			let key_type = format!("{:?}", key.key_type());
+			output.result(
+				json!({ "peer_id": peer_id.to_string(), "key_type": key_type, "public_key": public_key }),
+				|| {
+					println!("Peer ID: {peer_id}");
+					println!("Key type: {key_type}");
+					println!("Public key: {public_key}");
+				},
+			);
+		},
+		KeyCommand::Export => {
+			let key = hex::encode(load(path)?.to_protobuf_encoding()?);
+			output.result(json!({ "key": key }), || println!("{key}"));
+		},
+		KeyCommand::Import { file, force } => {
+			let key = match file {
+				Some(file) => std::fs::read_to_string(&file)
+					.map_err(|e| format!("Failed to read the identity {}: {e}", file.display()))?,
+				None => {
+					let mut key = String::new();
+					std::io::stdin().read_to_string(&mut key)?;
+					key
+				},
+			};
+			let key = identity::Keypair::from_protobuf_encoding(&hex::decode(key.trim())?)?;
+			save(path, &key, force)?;
+			let peer_id = key.public().to_peer_id();
+			output.result(json!({ "peer_id": peer_id.to_string() }), || println!("{peer_id}"));
+		},
+	}
+	Ok(())
+}
+
+/// Read the identity without generating one when missing, unlike the node.
+fn load(path: &Path) -> Result<identity::Keypair, Box<dyn Error>> {
+	let encoded = std::fs::read(path)
+		.map_err(|e| format!("Failed to read the identity {}: {e}", path.display()))?;
+	Ok(identity::Keypair::from_protobuf_encoding(&encoded)?)
+}
+
+/// Save the identity, replacing an existing one at once when `force` is given.
+fn save(path: &Path, key: &identity::Keypair, force: bool) -> Result<(), Box<dyn Error>> {
+	if !force {
+		return config::save_identity(path, key).map_err(|e| {
+			format!("Failed to save the identity {}: {e}, --force to replace it", path.display())
+				.into()
+		});
+	}
+	let tmp = path.with_extension("tmp");
+	if let Err(e) = std::fs::remove_file(&tmp) {
+		if e.kind() != std::io::ErrorKind::NotFound {
+			return Err(e.into());
+		}
+	}
+	config::save_identity(&tmp, key)?;
+	std::fs::rename(tmp, path)?;
+	Ok(())
+}
@@ -0,0 +1,50 @@
+use std::{error::Error, path::Path};
+
+use network::{AddressBook, Labels, PeerId};
+use serde_json::json;
+
+use crate::{cli::LabelsCommand, output::Output};
+
+/// Run a `dasn labels` command on the address book of the node, which needs no network.
+pub fn run(
+	address_book: Option<&Path>,
+	command: LabelsCommand,
+	output: &Output,
+) -> Result<(), Box<dyn Error>> {
+	let path = address_book.ok_or("Expected the address book file, with --address-book")?;
+	let mut book = AddressBook::open(Some(path.to_path_buf()));
+	match command {
+		LabelsCommand::Tag { peer, labels } => {
+			for (key, value) in labels {
+				book.tag(peer, key, value);
+			}
+			print_labels(output, &[(peer, book.labels(&peer))]);
+		},
+		LabelsCommand::Untag { peer, keys } => {
+			for key in keys {
+				book.untag(&peer, &key);
+			}
+			print_labels(output, &[(peer, book.labels(&peer))]);
+		},
+		LabelsCommand::List { selector } => {
+			let mut peers = book.find(&selector.unwrap_or_default());
+			peers.sort_by_key(|(peer, _)| peer.to_string());
+			print_labels(output, &peers);
+		},
+	}
+	Ok(())
+}
+
+fn print_labels(output: &Output, peers: &[(PeerId, Labels)]) {
+	let result = peers
+		.iter()
+		.map(|(peer, labels)| json!({ "peer_id": peer.to_string(), "labels": labels }))
+		.collect();
+	output.result(serde_json::Value::Array(result), || {
+		for (peer, labels) in peers {
+			let labels: Vec<_> =
+				labels.iter().map(|(key, value)| format!("{key}={value}")).collect();
+			println!("{peer} {}", labels.join(","));
+		}
+	});
+}
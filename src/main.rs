@@ -7,18 +7,51 @@ use tokio_util::sync::CancellationToken;
 #[cfg(debug_assertions)]
 extern crate better_panic;
 
+mod admin;
 mod agent;
+mod artifacts;
 mod cli;
+mod config;
+mod key;
+mod labels;
+mod metrics;
+mod output;
+mod progress;
+mod provider;
+mod sessions;
+mod wallet;
+mod worker;
 
-use std::{error::Error, io::Write, time::Duration};
+use std::{
+	collections::{HashMap, HashSet},
+	error::Error,
+	io::{Read, Write},
+	path::PathBuf,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
 use futures::{prelude::*, StreamExt};
-use network::Protocol;
-use tokio::task::spawn;
-use tracing_subscriber::EnvFilter;
+use indicatif::ProgressBar;
+use network::selection::{self, ProviderCandidate};
+use network::{
+	config::ConnectionLimitsConfig, types::ContextMessage, types::Event, validation, AgentCard,
+	AgentTopicsConfig, ArtifactStore, Bidder, BulletinConfig, CompressionConfig, HardwareProfile,
+	MdnsConfig, NetworkConfig, NodeCapabilities, OnInvalid, PeerId, PresenceConfig, Protocol,
+	QueryHandle, RequestJournal, RequestTimeoutConfig, ResponseValidators,
+};
+use serde_json::json;
+use tokio::{sync::watch, task::spawn};
+use tracing_subscriber::{
+	fmt::writer::BoxMakeWriter, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter,
+	Registry,
+};
 
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, DhtCommand, ProviderArgs};
+use output::Output;
+
+/// Longest a streamed answer may take before the provider stops generating it.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(120);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -36,25 +69,258 @@ async fn main() -> Result<(), Box<dyn Error>> {
 			.install();
 	}
 
-	let _ = tracing_subscriber::fmt()
-		.with_level(true)
-		.with_line_number(true)
-		.with_env_filter(EnvFilter::from_env("RUST_LOG"))
+	let matches = Cli::command().get_matches();
+	let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+	let output = Output::new(cli.json, &matches);
+
+	// The filter can be changed over the control API while the node runs. Only the JSON document
+	// goes to stdout with --json, and the messages received by subscribe.
+	let (log_filter, log_filter_handle) = reload::Layer::new(EnvFilter::from_env("RUST_LOG"));
+	let writer = match cli.json || matches!(cli.command, Commands::Subscribe { .. }) {
+		true => BoxMakeWriter::new(std::io::stderr),
+		false => BoxMakeWriter::new(std::io::stdout),
+	};
+	let _ = tracing_subscriber::registry()
+		.with(log_filter)
+		.with(
+			tracing_subscriber::fmt::layer()
+				.with_level(true)
+				.with_line_number(true)
+				.with_writer(writer),
+		)
 		.try_init();
 
-	let cli = Cli::parse();
+	let result = run(cli, &matches, &output, log_filter_handle).await;
+	if let Err(e) = &result {
+		output.error(e);
+	}
+	result
+}
+
+async fn run(
+	mut cli: Cli,
+	matches: &ArgMatches,
+	output: &Output,
+	log_filter_handle: reload::Handle<EnvFilter, Registry>,
+) -> Result<(), Box<dyn Error>> {
+	config::load(cli.config.as_deref())?.apply(&mut cli, matches);
+	let verbose = cli.verbose;
+	let end_to_end_encryption = cli.end_to_end_encryption;
+
+	if let Commands::Wallet { command } = cli.command {
+		return wallet::run(command, output).await;
+	}
+	if cli.secret_key_seed.is_some() {
+		tracing::warn!(
+			"The key derived from --secret-key-seed is public, use --identity in production"
+		);
+	}
+	if let Commands::Key { command } = cli.command {
+		return key::run(cli.identity.as_deref(), command, output);
+	}
+	if let Commands::Labels { command } = cli.command {
+		return labels::run(cli.address_book.as_deref(), command, output);
+	}
+	if let Commands::Ctl { command } = cli.command {
+		return admin::ctl(cli.admin_address, cli.admin_token.as_deref(), command, output).await;
+	}
+	if let Commands::Sessions { command } = cli.command {
+		return sessions::run(command, output);
+	}
+	if let Commands::Artifacts { command } = cli.command {
+		return artifacts::run(command, output);
+	}
+	if let Commands::Upload { file, store, pin } = &cli.command {
+		let store = ArtifactStore::open(store)?;
+		let cid = store.put(&std::fs::read(file)?)?;
+		if *pin {
+			store.pin(&cid)?;
+		}
+		output.result(json!({ "cid": cid.to_string() }), || println!("{cid}"));
+		return Ok(());
+	}
 
 	let cancellation_token = CancellationToken::new();
 
+	let mut bootstrap_peers = cli.bootstrap_peer;
+	if let Some(path) = &cli.bootstrap_file {
+		bootstrap_peers.extend(network::config::load_bootstrap_peers(path)?);
+	}
+
+	let task_dir = provider_args(&cli.command).and_then(|args| args.task_dir.clone());
+	if let Some(dir) = &task_dir {
+		std::fs::create_dir_all(dir)?;
+	}
+	// A snapshot only shortcuts the discovery, the node starts from its bootstrap peers without.
+	let snapshot = match &cli.snapshot {
+		Some(_) if cli.snapshot_signer.is_empty() => {
+			return Err("A network snapshot needs a --snapshot-signer to be trusted.".into());
+		},
+		Some(source) => {
+			let signers = cli.snapshot_signer.iter().copied().collect();
+			let max_age = Duration::from_secs(cli.snapshot_max_age);
+			match network::snapshot::load_snapshot(source, &signers, max_age).await {
+				Ok(snapshot) => {
+					tracing::info!(
+						"Starting from the network snapshot {source}: {} peers, {} agents",
+						snapshot.peers.len(),
+						snapshot.agents.len()
+					);
+					Some(snapshot)
+				},
+				Err(e) => {
+					tracing::warn!("Ignoring the network snapshot {source}: {e}");
+					None
+				},
+			}
+		},
+		None => None,
+	};
+	let network_config = NetworkConfig {
+		secret_key_seed: cli.secret_key_seed,
+		identity: cli.identity.as_deref().map(network::config::load_identity).transpose()?,
+		bootstrap_peers,
+		topic_publishers: cli.topic_publisher.iter().fold(
+			HashMap::<String, HashSet<_>>::new(),
+			|mut publishers, (topic, peer_id)| {
+				publishers.entry(topic.clone()).or_default().insert(*peer_id);
+				publishers
+			},
+		),
+		bulletins: BulletinConfig {
+			trusted_issuers: cli.bulletin_issuer.into_iter().collect(),
+			auto_apply: cli.apply_bulletins,
+			pinned_topics: cli.pin_topic.into_iter().collect(),
+			max_age: Duration::from_secs(cli.bulletin_max_age),
+		},
+		rendezvous_server: matches!(
+			cli.command,
+			Commands::Bootstrap { rendezvous: true, .. }
+				| Commands::Daemon { rendezvous: true, .. }
+		),
+		rendezvous_point: cli.rendezvous_point,
+		external_address: cli.external_address,
+		capabilities: node_capabilities(&cli.command),
+		mdns: MdnsConfig {
+			enabled: !cli.no_mdns,
+			ipv6: cli.mdns_ipv6,
+			interfaces: cli.mdns_interface,
+			..Default::default()
+		},
+		connection_limits: ConnectionLimitsConfig {
+			max_established: cli.max_connections,
+			max_established_per_peer: cli.max_connections_per_peer,
+			max_pending_incoming: cli.max_pending_incoming,
+			max_pending_outgoing: cli.max_pending_outgoing,
+			dial_concurrency_factor: cli.dial_concurrency,
+		},
+		compression: CompressionConfig {
+			algorithms: cli.compression,
+			threshold: cli.compression_threshold,
+		},
+		end_to_end_encryption: cli.end_to_end_encryption,
+		max_inbound_requests: cli.max_inbound_requests,
+		request_policies: match &cli.command {
+			Commands::Provide { name, policy: Some(policy), .. } => {
+				HashMap::from([(name.clone(), policy.clone())])
+			},
+			Commands::Daemon { agents, .. } => agents
+				.iter()
+				.filter_map(|agent| Some((agent.name.clone(), agent.policy.clone()?)))
+				.collect(),
+			_ => HashMap::new(),
+		},
+		presence: PresenceConfig { enabled: !cli.no_presence, ..Default::default() },
+		circuit_breaker: match &cli.command {
+			Commands::Llm { circuit_breaker, .. } => *circuit_breaker,
+			_ => Default::default(),
+		},
+		address_book: cli.address_book.clone(),
+		history_store: cli.gossip_history.clone(),
+		circuit_store: match &cli.command {
+			Commands::Llm { circuit_state, .. } => circuit_state.clone(),
+			_ => None,
+		},
+		task_store: task_dir.map(|dir| dir.join("tasks.json")),
+		request_timeouts: match cli.request_timeout {
+			Some(secs) => {
+				let defaults = RequestTimeoutConfig::default();
+				let default = Duration::from_secs(secs);
+				RequestTimeoutConfig { default, max: defaults.max.max(default), ..defaults }
+			},
+			None => RequestTimeoutConfig::default(),
+		},
+		agent_topics: match (cli.no_agent_topics, cli.agent_topic) {
+			(true, _) => AgentTopicsConfig { templates: Vec::new() },
+			(false, templates) if templates.is_empty() => AgentTopicsConfig::default(),
+			(false, templates) => AgentTopicsConfig { templates },
+		},
+		alert_rules: match &cli.alert_rules {
+			Some(path) => network::alerts::load_alert_rules(path)?,
+			None => Vec::new(),
+		},
+		snapshot,
+		#[cfg(feature = "pnet")]
+		psk: network::config::load_psk(cli.psk_file.as_deref())?,
+		..Default::default()
+	};
+
 	let (mut network_client, mut network_events, peer_id, network_event_loop) =
-		network::new(cli.secret_key_seed, vec![]).await?;
+		network::new(network_config).await?;
 
 	tracing::info!("Starting node...");
 	tracing::info!("Node ID: {:?}", peer_id);
+	output.set_peer_id(peer_id);
 
 	// Spawn the network task for it to run in the background.
-	spawn(network_event_loop.run(cancellation_token));
+	let network_task = spawn(network_event_loop.run(cancellation_token.clone()));
+
+	// Providers stop providing their agents before the network is left.
+	let stop_token = match provider_args(&cli.command) {
+		Some(_) => CancellationToken::new(),
+		None => cancellation_token.clone(),
+	};
+	let shutdown_token = stop_token.clone();
+	spawn(async move {
+		if shutdown_signal().await.is_ok() {
+			tracing::info!("Shutting down...");
+			shutdown_token.cancel();
+		}
+	});
 
+	// Cards of the agents provided, for the control API to report them.
+	let (cards, cards_receiver) = watch::channel(Vec::new());
+	if let Some(admin_address) = cli.admin_address {
+		let token_file = cli
+			.admin_token
+			.as_deref()
+			.ok_or("--admin-address needs --admin-token, the file to write its token to.")?;
+		let token = admin::write_token(token_file)?;
+		let admin = admin::Admin {
+			peer_id,
+			client: network_client.clone(),
+			cards: cards_receiver,
+			shutdown: stop_token.clone(),
+			log_filter: log_filter_handle,
+			token,
+		};
+		spawn(async move {
+			if let Err(e) = admin::serve(admin_address, admin).await {
+				tracing::error!("Control API listener failed: {e}");
+			}
+		});
+	}
+
+	if let Some(metrics_address) = cli.metrics_address {
+		let client = network_client.clone();
+		spawn(async move {
+			if let Err(e) = metrics::serve(metrics_address, client).await {
+				tracing::error!("Metrics listener failed: {e}");
+			}
+		});
+	}
+
+	let listen_addresses: Vec<_> = cli.listen_address.iter().map(ToString::to_string).collect();
 	for addr in cli.listen_address {
 		network_client
 			.start_listening(addr.clone())
@@ -71,72 +337,1091 @@ async fn main() -> Result<(), Box<dyn Error>> {
 		tracing::info!("Dialed peer: {:?}", peer_id);
 	}
 
+	// The nodes running until stopped report they started, with the peer id of the document.
+	if matches!(
+		cli.command,
+		Commands::Bootstrap { .. } | Commands::Provide { .. } | Commands::Daemon { .. }
+	) {
+		output.result(json!({ "listen_addresses": listen_addresses }), || {});
+	}
+
 	match cli.command {
-		Commands::Bootstrap {} => {
+		Commands::Bootstrap { publish_snapshot, snapshot_interval, .. } => {
 			let mut discover_tick = tokio::time::interval(Duration::from_secs(30));
+			let mut snapshot_tick =
+				tokio::time::interval(Duration::from_secs(snapshot_interval.max(1)));
 
 			loop {
 				tokio::select! {
 					_ = discover_tick.tick() => {
 					},
+					_ = snapshot_tick.tick(), if publish_snapshot.is_some() => {
+						let path = publish_snapshot.as_ref().expect("A snapshot file.");
+						if let Err(e) = write_snapshot(&mut network_client, path).await {
+							tracing::warn!("Failed to publish the network snapshot: {e}");
+						}
+					},
+					event = network_events.next() => match event {
+						Some(event) => tracing::info!("Network event: {:?}", event),
+						None => break,
+					},
 				}
 			}
 		},
 		Commands::Gossip { topic, message } => {
 			tracing::info!("Gossiping message: [{topic}] {message}");
-			match network_client.gossip(topic, message).await {
+			match network_client.gossip(topic.clone(), message).await {
 				Ok(()) => {
 					tracing::info!("Gossip done.");
+					output.result(json!({ "topic": topic, "published": true }), || {});
+				},
+				Err(e) => {
+					tracing::error!("Failed to gossip message: {:?}", e);
+					output.error(format!("Failed to gossip message: {e}"));
 				},
-				Err(e) => tracing::error!("Failed to gossip message: {:?}", e),
 			}
 		},
-		Commands::Provide { name } => {
-			network_client.start_providing(name.clone()).await;
-
+		Commands::Subscribe { topics } => {
+			for topic in &topics {
+				network_client
+					.subscribe(topic.clone())
+					.await
+					.map_err(|e| format!("Failed to subscribe to {topic}: {e}"))?;
+			}
+			tracing::info!("Subscribed to {topics:?}, waiting for messages...");
+			let mut received = 0u64;
 			loop {
-				match network_events.next().await {
-					Some(network::types::Event::LLMInboundRequest {
-						agent_name,
-						message,
-						channel,
-					}) => {
-						tracing::info!("Received request for agent: {:?}", agent_name);
-						if agent_name == name {
-							let output = crate::agent::respond_llm(message).await?;
-
-							network_client.respond_llm(output.as_bytes().to_vec(), channel).await;
+				let event = tokio::select! {
+					_ = stop_token.cancelled() => break,
+					event = network_events.next() => match event {
+						Some(event) => event,
+						None => break,
+					},
+				};
+				let Event::GossipMessage { topic, source, propagation_source, message_id, data } =
+					event
+				else {
+					continue;
+				};
+				if !topics.contains(&topic) {
+					continue;
+				}
+				let mut message = json!({
+					"topic": topic,
+					"source": source.map(|source| source.to_string()),
+					"propagation_source": propagation_source.to_string(),
+					"message_id": message_id.to_string(),
+				});
+				match String::from_utf8(data) {
+					Ok(data) => message["data"] = json!(data),
+					Err(e) => message["data_hex"] = json!(hex::encode(e.as_bytes())),
+				}
+				println!("{message}");
+				received += 1;
+			}
+			tracing::info!("Received {received} messages.");
+		},
+		Commands::Routing { key } => {
+			let mut result = json!({});
+			if let Some(key) = key {
+				match network_client.get_closest_peers(key.clone()).await {
+					Ok(peers) => {
+						let closest: Vec<_> = peers.iter().map(ToString::to_string).collect();
+						result["closest_peers"] = json!(closest);
+						if !output.is_json() {
+							println!("Closest peers to {key}: {peers:?}");
 						}
 					},
-					e => {
-						tracing::info!("Unhandled event: {:?}", e);
+					Err(e) => {
+						tracing::error!("Failed to get the closest peers to {key}: {e}");
+						output.error(format!("Failed to get the closest peers to {key}: {e}"));
 					},
 				}
 			}
+
+			let buckets = network_client.kbuckets().await.map_err(|e| e.to_string())?;
+			result["buckets"] = json!(buckets
+				.iter()
+				.map(|bucket| {
+					let peers: Vec<_> = bucket
+						.peers
+						.iter()
+						.map(|peer| {
+							json!({
+								"peer_id": peer.peer_id.to_string(),
+								"addresses": peer.addresses.iter().map(ToString::to_string).collect::<Vec<_>>(),
+								"connected": peer.connected,
+							})
+						})
+						.collect();
+					json!({ "index": bucket.index, "peers": peers })
+				})
+				.collect::<Vec<_>>());
+			output.result(result, || {
+				for bucket in buckets {
+					println!("Bucket {}:", bucket.index);
+					for peer in bucket.peers {
+						let status = if peer.connected { "connected" } else { "disconnected" };
+						println!("  {} ({status}) {:?}", peer.peer_id, peer.addresses);
+					}
+				}
+			});
 		},
-		Commands::Llm { name, message } => {
-			let providers = network_client.get_providers(name.clone()).await;
-			if providers.is_empty() {
-				return Err(format!("Could not find provider for agent {name}.").into());
+		Commands::Providers { query, wait } => {
+			// Providers announce their agents periodically, the directory fills as they do.
+			let spinner = progress::spinner(verbose, "Collecting the announcements of the agents");
+			tokio::time::sleep(Duration::from_secs(wait)).await;
+			spinner.finish_and_clear();
+			let filter = network::AgentFilter { query, ..Default::default() };
+			let listings = network_client.find_agents(filter).await.map_err(|e| e.to_string())?;
+			let result: Vec<_> = listings
+				.iter()
+				.map(
+					|listing| json!({ "provider": listing.provider.to_string(), "card": listing.card }),
+				)
+				.collect();
+			output.result(json!(result), || {
+				for listing in listings {
+					let card = listing.card;
+					println!(
+						"{} {} from {}: price {}, capabilities {:?}, task types {:?}, languages {:?}",
+						card.name,
+						card.version,
+						listing.provider,
+						card.pricing,
+						card.capabilities,
+						card.task_types,
+						card.languages
+					);
+				}
+			});
+		},
+		Commands::Feedback { provider, name, receipt, rating, comment } => {
+			let feedback = network::Feedback { receipt, agent_name: name, rating, comment };
+			match network_client.send_feedback(provider, feedback).await {
+				Ok(()) => {
+					tracing::info!("Feedback sent.");
+					output.result(json!({ "provider": provider.to_string(), "sent": true }), || {});
+				},
+				Err(e) => {
+					tracing::error!("Failed to send the feedback: {e}");
+					output.error(format!("Failed to send the feedback: {e}"));
+				},
 			}
+		},
+		Commands::Attest { provider, task, outcome } => {
+			match network_client.attest(provider, task.clone(), outcome).await {
+				Ok(()) => {
+					tracing::info!("Attestation published.");
+					let result = json!({
+						"provider": provider.to_string(),
+						"task": task,
+						"outcome": outcome.to_string(),
+						"published": true,
+					});
+					output.result(result, || {});
+				},
+				Err(e) => {
+					tracing::error!("Failed to publish the attestation: {e}");
+					output.error(format!("Failed to publish the attestation: {e}"));
+				},
+			}
+		},
+		Commands::Resume { journal, max_attempts, artifact_store } => {
+			let mut journal = RequestJournal::open(journal)?;
+			let pending = journal.pending().to_vec();
+			if let Some(dir) = artifact_store {
+				// The providers fetch the attachments from this node again while answering.
+				spawn(serve_artifacts(
+					ArtifactStore::open(dir)?,
+					network_client.clone(),
+					network_events,
+				));
+			} else if pending.iter().any(|pending| !pending.attachments.is_empty()) {
+				return Err(
+					"Requests of the journal have attachments, pass their --artifact-store.".into(),
+				);
+			}
+			let bar = progress::bar(verbose, pending.len() as u64);
+			let mut results = Vec::new();
+			for pending in pending {
+				bar.inc(1);
+				bar.set_message(pending.id.to_string());
+				if pending.attempts >= max_attempts {
+					bar.suspend(|| {
+						eprintln!(
+							"Giving up on request {} after {} attempts",
+							pending.id, pending.attempts
+						)
+					});
+					results.push(json!({
+						"id": pending.id.to_string(),
+						"error": format!("Gave up after {} attempts", pending.attempts),
+					}));
+					journal.complete(&pending.id)?;
+					continue;
+				}
+				let Some(entry) = journal.retry(&pending.id)? else {
+					continue;
+				};
+				// Refresh the addresses of the providers, which may have moved since.
+				match network_client.get_providers(entry.agent_name.clone()).await {
+					Ok(query) => {
+						if let Err(e) = follow_query(query, verbose).await {
+							tracing::warn!(
+								"Failed to look up the providers of {}: {e}",
+								entry.agent_name
+							);
+						}
+					},
+					Err(e) => tracing::warn!(
+						"Failed to look up the providers of {}: {e}",
+						entry.agent_name
+					),
+				}
+				match network_client.resume_request(&entry).await {
+					Ok((provider, response)) => {
+						bar.suspend(|| -> std::io::Result<()> {
+							eprintln!("Request {} answered by {provider}:", entry.id);
+							let mut human = output.human();
+							human.write_all(&response)?;
+							writeln!(human)
+						})?;
+						results.push(json!({
+							"id": entry.id.to_string(),
+							"provider": provider.to_string(),
+							"response": String::from_utf8_lossy(&response),
+						}));
+						journal.complete(&entry.id)?;
+					},
+					Err(e) => {
+						tracing::error!("Failed to resume request {}: {e}", entry.id);
+						results.push(json!({ "id": entry.id.to_string(), "error": e.to_string() }));
+					},
+				}
+			}
+			bar.finish_and_clear();
+			output.result(json!(results), || {});
+		},
+		Commands::Replay { receipt, session_store, provider } => {
+			let original = sessions::load(&session_store)?
+				.into_iter()
+				.find(|exchange| exchange.receipt == Some(receipt))
+				.ok_or_else(|| {
+					format!("No exchange with receipt {receipt} in the session store.")
+				})?;
+			let provider = match (provider, &original.provider) {
+				(Some(provider), _) => provider,
+				(None, Some(provider)) => provider.parse()?,
+				(None, None) => {
+					return Err(
+						"The provider of the exchange is unknown, give one with --provider.".into(),
+					)
+				},
+			};
 
-			tracing::info!("Requesting agent: {:?} from providers: {:?}", name, providers);
+			// Look up the addresses of the provider, and check it still serves the agent.
+			let spinner = progress::spinner(verbose, format!("Looking up provider {provider}"));
+			let query = network_client
+				.get_providers(original.agent.clone())
+				.await
+				.map_err(|e| e.to_string())?;
+			if let Err(e) = follow_query(query, verbose).await {
+				tracing::warn!("Failed to look up the providers of {}: {e}", original.agent);
+			}
+			let card = network_client
+				.verified_providers(&original.agent, HashSet::from([provider]))
+				.await
+				.remove(&provider)
+				.ok_or_else(|| format!("Provider {provider} does not serve {}.", original.agent))?;
+			let reputation = network_client.reputations([provider]).await.remove(&provider);
+			spinner.finish_and_clear();
 
-			let requests = providers.into_iter().map(|p| {
-				let mut network_client = network_client.clone();
-				let name = name.clone();
-				let message = message.clone();
-				async move { network_client.request_agent(p, name, message).await }.boxed()
+			let spinner = progress::spinner(verbose, format!("Waiting for {provider} to answer"));
+			let context_len: usize = original.context.iter().map(|m| m.content.len()).sum();
+			if card.requires_challenge(original.message.len() + context_len) {
+				network_client.authenticate(provider).await.map_err(|e| e.to_string())?;
+			}
+			let request = match card.encryption_key {
+				Some(_) if end_to_end_encryption => {
+					network_client
+						.send_encrypted_agent_request(
+							provider,
+							&card,
+							original.message.clone(),
+							original.context.clone(),
+							original.language.clone(),
+						)
+						.await
+				},
+				_ => {
+					network_client
+						.send_agent_request_in(
+							provider,
+							original.agent.clone(),
+							original.message.clone(),
+							original.context.clone(),
+							original.language.clone(),
+						)
+						.await
+				},
+			};
+			let response = request.map_err(|e| e.to_string())?.response().await;
+			let response = response.map_err(|e| e.to_string())?;
+			spinner.finish_and_clear();
+
+			let mut replayed = original.clone();
+			replayed.provider = Some(provider.to_string());
+			replayed.respond(&response);
+			let identical = replayed.response == original.response;
+			let diff = diffy::create_patch(&original.response, &replayed.response);
+			let result = json!({
+				"receipt": receipt.to_string(),
+				"agent": original.agent,
+				"original_provider": original.provider,
+				"provider": provider.to_string(),
+				"reputation": reputation,
+				"identical": identical,
+				"response": replayed.response,
+				"replay_receipt": replayed.receipt.map(|receipt| receipt.to_string()),
+				"diff": if identical { None } else { Some(diff.to_string()) },
+			});
+			output.result(result, || {
+				if identical {
+					println!("Identical answer from {provider}");
+				} else {
+					print!("{diff}");
+				}
 			});
+		},
+		Commands::Bulletin { kind } => match network_client.publish_bulletin(kind.into()).await {
+			Ok(()) => {
+				tracing::info!("Bulletin published.");
+				output.result(json!({ "published": true }), || {});
+			},
+			Err(e) => {
+				tracing::error!("Failed to publish bulletin: {e}");
+				output.error(format!("Failed to publish bulletin: {e}"));
+			},
+		},
+		Commands::Provide {
+			name,
+			version,
+			capability,
+			price,
+			price_schedule,
+			bid_strategy,
+			task_type,
+			challenge_above,
+			language,
+			provider,
+			..
+		} => {
+			let bidder = Bidder::new(price, price_schedule.unwrap_or_default())
+				.with_capabilities(capability.clone())
+				.with_strategy(bid_strategy.unwrap_or_default());
+			let card = AgentCard {
+				capabilities: capability,
+				task_types: task_type,
+				challenge_above,
+				languages: language,
+				..AgentCard::new(name, version)
+			};
+			provider::run(
+				network_client.clone(),
+				network_events,
+				peer_id,
+				vec![provider::ServedAgent::new(card, bidder)],
+				provider,
+				verbose,
+				stop_token,
+				cards,
+			)
+			.await?;
+		},
+		Commands::Daemon { provider, agents, .. } => {
+			let agents: Vec<_> = agents
+				.into_iter()
+				.map(|agent| {
+					let bidder = Bidder::new(
+						agent.price.unwrap_or_default(),
+						agent.price_schedule.unwrap_or_default(),
+					)
+					.with_capabilities(agent.capabilities.clone())
+					.with_strategy(agent.bid_strategy.unwrap_or_default());
+					let card = AgentCard {
+						capabilities: agent.capabilities,
+						task_types: agent.task_types,
+						challenge_above: agent.challenge_above,
+						languages: agent.languages,
+						..AgentCard::new(
+							agent.name,
+							agent.version.unwrap_or_else(|| "0.1.0".to_string()),
+						)
+					};
+					provider::ServedAgent::new(card, bidder)
+				})
+				.collect();
+			if agents.is_empty() {
+				tracing::info!("No agents in the config, only serving the network");
+			}
+			provider::run(
+				network_client.clone(),
+				network_events,
+				peer_id,
+				agents,
+				provider,
+				verbose,
+				stop_token,
+				cards,
+			)
+			.await?;
+		},
+		Commands::Dht { command } => match command {
+			DhtCommand::Put { key, value, quorum } => {
+				let value = match value {
+					Some(value) => value.into_bytes(),
+					None => {
+						let mut value = Vec::new();
+						std::io::stdin().read_to_end(&mut value)?;
+						value
+					},
+				};
+				let size = value.len();
+				network_client
+					.put_record(key.clone(), value, quorum)
+					.await
+					.map_err(|e| format!("Failed to store {key}: {e}"))?;
+				output.result(json!({ "key": key, "size": size }), || {
+					eprintln!("Stored {size} bytes under {key}")
+				});
+			},
+			DhtCommand::Get { key, quorum } => {
+				let value = network_client
+					.get_record(key.clone(), quorum)
+					.await
+					.map_err(|e| format!("Failed to get {key}: {e}"))?;
+				// Values are free-form, the ones not in UTF-8 are given hex encoded in JSON.
+				let result = match std::str::from_utf8(&value) {
+					Ok(text) => json!({ "key": key, "value": text }),
+					Err(_) => json!({ "key": key, "value_hex": hex::encode(&value) }),
+				};
+				if output.is_json() {
+					output.result(result, || {});
+				} else {
+					std::io::stdout().write_all(&value)?;
+				}
+			},
+			DhtCommand::Providers { key } => {
+				let query = network_client
+					.get_key_providers(key.clone())
+					.await
+					.map_err(|e| format!("Failed to look up the providers of {key}: {e}"))?;
+				let spinner =
+					progress::spinner(verbose, format!("Looking up the providers of {key}"));
+				let providers = follow_query(query, verbose)
+					.await
+					.map_err(|e| format!("Failed to look up the providers of {key}: {e}"))?;
+				spinner.finish_and_clear();
+				let providers: Vec<_> = providers.iter().map(ToString::to_string).collect();
+				output.result(json!({ "key": key, "providers": providers }), || {
+					for provider in &providers {
+						println!("{provider}");
+					}
+				});
+			},
+		},
+		Commands::Wallet { .. } => unreachable!("Wallet commands run without the network."),
+		Commands::Sessions { .. } => unreachable!("Sessions commands run without the network."),
+		Commands::Ctl { .. } => unreachable!("Control commands run without the network."),
+		Commands::Key { .. } => unreachable!("Key commands run without the network."),
+		Commands::Labels { .. } => unreachable!("Labels commands run without the network."),
+		Commands::Upload { .. } => unreachable!("Uploads run without the network."),
+		Commands::Artifacts { .. } => unreachable!("Artifacts commands run without the network."),
+		Commands::Llm {
+			name,
+			message,
+			stream,
+			window,
+			dial_back,
+			context,
+			hedge_delay,
+			redundancy,
+			max_price,
+			labels,
+			language,
+			session_store,
+			max_length,
+			json_schema,
+			block_term,
+			min_citations,
+			on_invalid,
+			journal,
+			attach,
+			artifact_store,
+			cooldown,
+			cooldown_state,
+			timing,
+			..
+		} => {
+			let mut stages = progress::Timing::start();
+			if !attach.is_empty() && end_to_end_encryption {
+				return Err("Attachments are not encrypted end to end.".into());
+			}
+			if let Some(dir) = artifact_store {
+				let store = ArtifactStore::open(dir)?;
+				for cid in &attach {
+					if store.manifest(cid)?.is_none() {
+						return Err(format!(
+							"Artifact {cid} is not in the store, upload it first."
+						)
+						.into());
+					}
+				}
+				// The providers fetch the attachments from this node while answering.
+				spawn(serve_artifacts(store, network_client.clone(), network_events));
+			}
+			let validators = response_validators(
+				max_length,
+				json_schema,
+				block_term,
+				min_citations,
+				on_invalid,
+			)?;
+			let context: Vec<ContextMessage> = match context {
+				Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+				None => Vec::new(),
+			};
 
-			let agent_content = futures::future::select_ok(requests)
+			let spinner = progress::spinner(verbose, format!("Looking up the providers of {name}"));
+			let query =
+				network_client.get_providers(name.clone()).await.map_err(|e| e.to_string())?;
+			let providers = follow_query(query, verbose).await.map_err(|e| e.to_string())?;
+			let providers = network_client
+				.online_providers(name.clone(), providers)
+				.await
+				.map_err(|e| e.to_string())?;
+			spinner.finish_and_clear();
+			stages.stage("discovery");
+
+			let spinner =
+				progress::spinner(verbose, format!("Connecting to {} providers", providers.len()));
+			let mut cards = network_client.verified_providers(&name, providers).await;
+			if dial_back {
+				let checks = cards.keys().map(|&provider| {
+					let mut network_client = network_client.clone();
+					async move { (provider, network_client.dial_back(provider).await) }
+				});
+				for (provider, check) in future::join_all(checks).await {
+					match check {
+						Ok(rtt) => tracing::info!("Provider {provider} answered in {rtt:?}"),
+						Err(e) => {
+							tracing::warn!("Dropping unreachable provider {provider}: {e}");
+							cards.remove(&provider);
+						},
+					}
+				}
+			}
+			spinner.finish_and_clear();
+			stages.stage("dial");
+			if cards.is_empty() {
+				return Err(format!("Could not find a verified provider for agent {name}.").into());
+			}
+			for (provider, card) in &cards {
+				tracing::info!("Agent card signed by {provider}: {card:?}");
+			}
+
+			// Providers whose tasks mostly failed are only called when no other one is left, or
+			// to probe them once their cooldown elapsed.
+			let spinner = progress::spinner(verbose, "Reading the reputation of the providers");
+			let reputation = network_client.reputations(cards.keys().copied()).await;
+			spinner.finish_and_clear();
+			let mut cooldowns: network::Cooldowns = match &cooldown_state {
+				Some(path) if path.exists() => {
+					serde_json::from_str(&std::fs::read_to_string(path)?)?
+				},
+				_ => network::Cooldowns::default(),
+			};
+			let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+			for (provider, score) in &reputation {
+				cooldowns.update(&cooldown, *provider, *score, now);
+			}
+			if let Some(path) = &cooldown_state {
+				network::persist::atomic_write(path, &serde_json::to_vec_pretty(&cooldowns)?)?;
+			}
+			let mut candidates = provider_candidates(&mut network_client, &cards, &reputation)
+				.await
+				.map_err(|e| e.to_string())?;
+			if let Some(selector) = &labels {
+				candidates = selection::with_labels(&candidates, selector);
+				if candidates.is_empty() {
+					return Err(format!("No provider of agent {name} carries the labels.").into());
+				}
+			}
+			if let Some(language) = &language {
+				// Other providers are only called when none advertises the language.
+				candidates = selection::prefer_language(&candidates, language);
+			}
+			let mut blocked: HashSet<PeerId> = candidates
+				.iter()
+				.map(|candidate| candidate.peer_id)
+				.filter(|provider| !cooldowns.admits(&cooldown, provider, now))
+				.collect();
+			if blocked.len() == candidates.len() {
+				blocked.clear();
+			}
+			let budget = max_price.unwrap_or(f64::INFINITY);
+			let mut ranked: Vec<PeerId> = selection::rank_providers(&candidates, &blocked, budget)
+				.into_iter()
+				.map(|candidate| candidate.peer_id)
+				.collect();
+			if ranked.is_empty() {
+				return Err(format!("No provider prices agent {name} within the limit.").into());
+			}
+			// The providers that kept failing are skipped until their cooldown elapsed, each admitted
+			// once a request is sent to it.
+			let admissible = network_client
+				.admissible_providers(ranked.iter().copied())
 				.await
-				.map_err(|_| "None of the providers returned agent.")?
-				.0;
+				.map_err(|e| e.to_string())?;
+			ranked.retain(|provider| admissible.contains(provider));
+			if ranked.is_empty() {
+				return Err(format!(
+					"Every provider of agent {name} kept failing, retry once their cooldown elapsed."
+				)
+				.into());
+			}
+			cards.retain(|provider, _| ranked.contains(provider));
+
+			stages.stage("selection");
+			tracing::info!("Requesting agent: {:?} from providers: {:?}", name, ranked);
+
+			let mut exchange =
+				sessions::Exchange::new(name.clone(), message.clone(), context.clone());
+			exchange.language = language.clone();
+			let signed_cards = cards.clone();
+			let mut journal = journal.map(RequestJournal::open).transpose()?;
+			let journaled = match &mut journal {
+				Some(journal) => {
+					let id = journal.record(
+						ranked.clone(),
+						name.clone(),
+						message.clone(),
+						context.clone(),
+						language.clone(),
+						attach.clone(),
+					)?;
+					journal.pending().iter().find(|entry| entry.id == id).cloned()
+				},
+				None => None,
+			};
 
-			std::io::stdout().write_all(&agent_content)?;
+			let spinner = progress::spinner(
+				verbose,
+				format!("Waiting for {} providers to answer", ranked.len()),
+			);
+			let mut violations = Vec::new();
+			let (provider, response) = if stream {
+				let providers: HashSet<_> = ranked.into_iter().collect();
+				let (provider, response) = stream_llm(
+					network_client,
+					providers,
+					name,
+					message,
+					window,
+					spinner.clone(),
+					&mut stages,
+					output,
+				)
+				.await?;
+				(Some(provider), response.into_bytes())
+			} else if let Some(redundancy) = redundancy {
+				if end_to_end_encryption {
+					return Err("Redundant requests are not encrypted end to end.".into());
+				}
+				let agreement = network_client
+					.redundant_request(ranked, name, message.clone(), context, language, redundancy)
+					.await
+					.map_err(|e| e.to_string())?;
+				spinner.finish_and_clear();
+				for provider in &agreement.dissenting {
+					eprintln!("Warning: {provider} answered otherwise");
+				}
+				let warnings =
+					validators.check(&message, &agreement.response).map_err(|e| e.to_string())?;
+				warn_violations(&warnings);
+				violations.extend(warnings);
+				output.human().write_all(&agreement.response)?;
+				(agreement.agreeing.first().copied(), agreement.response)
+			} else if let Some(delay) = hedge_delay {
+				if end_to_end_encryption {
+					return Err("Hedged requests are not encrypted end to end.".into());
+				}
+				let context_len: usize = context.iter().map(|m| m.content.len()).sum();
+				for provider in &ranked {
+					if cards[provider].requires_challenge(message.len() + context_len) {
+						network_client.authenticate(*provider).await.map_err(|e| e.to_string())?;
+					}
+				}
+				// The fastest first, the best ranked among the equally fast.
+				let latency = |provider: &PeerId| {
+					candidates
+						.iter()
+						.find(|c| c.peer_id == *provider)
+						.map_or(Duration::MAX, |c| c.latency)
+				};
+				let mut providers = ranked;
+				providers.sort_by_key(latency);
+
+				let delay = Duration::from_millis(delay);
+				let (agent_content, warnings) = network_client
+					.hedged_request(providers, name, message, context, language, delay, &validators)
+					.await
+					.map_err(|e| e.to_string())?;
+				spinner.finish_and_clear();
+				warn_violations(&warnings);
+				violations.extend(warnings);
+				output.human().write_all(&agent_content)?;
+				(None, agent_content)
+			} else {
+				let requests = ranked.into_iter().map(|p| {
+					let card = cards[&p].clone();
+					let mut network_client = network_client.clone();
+					let name = name.clone();
+					let message = message.clone();
+					let context = context.clone();
+					let language = language.clone();
+					let attach = attach.clone();
+					let journaled = journaled.clone();
+					let validators = validators.clone();
+					async move {
+						let context_len: usize = context.iter().map(|m| m.content.len()).sum();
+						if card.requires_challenge(message.len() + context_len) {
+							network_client.authenticate(p).await?;
+						}
+						let response = match card.encryption_key {
+							Some(_) if end_to_end_encryption => {
+								network_client
+									.send_encrypted_agent_request(
+										p,
+										&card,
+										message.clone(),
+										context,
+										language,
+									)
+									.await?
+									.response()
+									.await
+							},
+							// Sent as the job of the journal entry, for a resumed request to be
+							// answered alike.
+							_ => match &journaled {
+								Some(entry) => {
+									network_client
+										.send_journaled_request(p, entry)
+										.await?
+										.response()
+										.await
+								},
+								None => {
+									network_client
+										.send_agent_request_attached(
+											p,
+											name,
+											message.clone(),
+											context,
+											language,
+											attach,
+										)
+										.await?
+										.response()
+										.await
+								},
+							},
+						};
+						let response = response?;
+						match validators.check(&message, &response) {
+							Ok(warnings) => Ok((p, Ok((response, warnings)))),
+							// Left to the other providers, racing with this one.
+							Err(invalid) if invalid.retry => {
+								tracing::warn!("Dropping the answer of {p}: {invalid}");
+								Err(Box::new(invalid) as Box<dyn Error + Send>)
+							},
+							Err(invalid) => Ok((p, Err(invalid))),
+						}
+					}
+					.boxed()
+				});
+
+				let (provider, checked) = futures::future::select_ok(requests)
+					.await
+					.map_err(|_| "None of the providers returned agent.")?
+					.0;
+				spinner.finish_and_clear();
+				let (agent_content, warnings) = checked.map_err(|e| e.to_string())?;
+				warn_violations(&warnings);
+				violations.extend(warnings);
+
+				output.human().write_all(&agent_content)?;
+				(Some(provider), agent_content)
+			};
+			spinner.finish_and_clear();
+			stages.stage(if stream { "stream" } else { "request" });
+			if let (Some(journal), Some(entry)) = (&mut journal, journaled) {
+				journal.complete(&entry.id)?;
+			}
+
+			if let Some(store) = session_store {
+				if let Some(card) = provider.and_then(|provider| signed_cards.get(&provider)) {
+					exchange.version = Some(card.version.clone());
+					exchange.pricing = Some(card.pricing);
+				}
+				exchange.provider = provider.map(|provider| provider.to_string());
+				exchange.respond(&response);
+				sessions::record(&store, &exchange)?;
+				if let (Some(provider), Some(receipt)) = (provider, exchange.receipt) {
+					eprintln!("\nReceipt {receipt} from {provider}");
+				}
+			}
+			if timing {
+				stages.print();
+			}
+			let result = json!({
+				"agent": exchange.agent,
+				"provider": provider.map(|provider| provider.to_string()),
+				"response": String::from_utf8_lossy(&response),
+				"receipt": exchange.receipt.map(|receipt| receipt.to_string()),
+				"warnings": violations.iter().map(ToString::to_string).collect::<Vec<_>>(),
+				"timings": stages.to_json(),
+			});
+			output.result(result, || {});
 		},
 	}
 
+	// Leave the network cleanly, e.g. for the gossip message to be flushed.
+	cancellation_token.cancel();
+	network_task.await?;
+
 	Ok(())
 }
+
+/// What the node announces to its peers through identify, from the command it runs.
+fn node_capabilities(command: &Commands) -> NodeCapabilities {
+	let (role, task_types) = match command {
+		Commands::Bootstrap { .. } => ("bootstrap", Vec::new()),
+		Commands::Provide { task_type, .. } => {
+			("provider", task_type.iter().map(|task_type| format!("{task_type:?}")).collect())
+		},
+		Commands::Daemon { agents, .. } => {
+			let mut task_types: Vec<_> = agents
+				.iter()
+				.flat_map(|agent| &agent.task_types)
+				.map(|task_type| format!("{task_type:?}"))
+				.collect();
+			task_types.sort();
+			task_types.dedup();
+			("provider", task_types)
+		},
+		_ => ("client", Vec::new()),
+	};
+	let (languages, hardware) = match command {
+		// Providers announce their hardware for the proposers to match it against their tasks.
+		Commands::Provide { language, .. } => (language.clone(), HardwareProfile::detect()),
+		Commands::Daemon { agents, .. } => {
+			let mut languages: Vec<_> =
+				agents.iter().flat_map(|agent| agent.languages.clone()).collect();
+			languages.sort();
+			languages.dedup();
+			(languages, HardwareProfile::detect())
+		},
+		_ => (Vec::new(), HardwareProfile::default()),
+	};
+	NodeCapabilities {
+		role: Some(role.to_string()),
+		task_types,
+		languages,
+		hardware,
+		..Default::default()
+	}
+}
+
+/// Write a signed snapshot of the network to `path`, replacing the previous one at once for the
+/// readers to never see a partial file.
+async fn write_snapshot(
+	network_client: &mut network::Client,
+	path: &std::path::Path,
+) -> Result<(), Box<dyn Error>> {
+	let signed = network_client.network_snapshot().await.map_err(|e| e.to_string())?;
+	network::persist::atomic_write(path, &network::types::serialize_message(&signed)?)?;
+	Ok(())
+}
+
+/// Settings of the agents served, for the commands serving some.
+fn provider_args(command: &Commands) -> Option<&ProviderArgs> {
+	match command {
+		Commands::Provide { provider, .. } | Commands::Daemon { provider, .. } => Some(provider),
+		_ => None,
+	}
+}
+
+/// Wait for Ctrl-C, or for SIGTERM on Unix, e.g. sent by a service manager.
+async fn shutdown_signal() -> std::io::Result<()> {
+	#[cfg(unix)]
+	{
+		let mut terminate =
+			tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+		tokio::select! {
+			signal = tokio::signal::ctrl_c() => signal,
+			_ = terminate.recv() => Ok(()),
+		}
+	}
+	#[cfg(not(unix))]
+	tokio::signal::ctrl_c().await
+}
+
+/// The checks `llm` runs on the answers of the providers.
+fn response_validators(
+	max_length: Option<usize>,
+	json_schema: Option<PathBuf>,
+	block_term: Vec<String>,
+	min_citations: Option<usize>,
+	on_invalid: OnInvalid,
+) -> Result<ResponseValidators, Box<dyn Error>> {
+	let mut validators = ResponseValidators::default().on_invalid(on_invalid);
+	if let Some(max_length) = max_length {
+		validators = validators.with(validation::MaxLength(max_length));
+	}
+	if let Some(path) = json_schema {
+		let schema = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+		validators = validators.with(validation::JsonSchema(schema));
+	}
+	if !block_term.is_empty() {
+		validators = validators.with(validation::SafetyFilter::new(block_term));
+	}
+	if let Some(min_citations) = min_citations {
+		validators = validators.with(validation::CitationCheck(min_citations));
+	}
+	Ok(validators)
+}
+
+/// Answer the requests for the artifacts of the store, e.g. the attachments of a request.
+async fn serve_artifacts(
+	store: ArtifactStore,
+	mut network_client: network::Client,
+	mut network_events: impl Stream<Item = Event> + Unpin,
+) {
+	while let Some(event) = network_events.next().await {
+		let Event::ArtifactRequest { name, channel } = event else {
+			continue;
+		};
+		// An empty response tells the peer the artifact is not stored here.
+		let data = match store.serve(&name) {
+			Ok(data) => data.unwrap_or_default(),
+			Err(e) => {
+				tracing::warn!("Failed to serve artifact {name}: {e}");
+				Vec::new()
+			},
+		};
+		network_client.respond_artifact(data, channel).await;
+	}
+}
+
+/// Print the checks an answer failed, returned anyway.
+fn warn_violations(violations: &[validation::Violation]) {
+	for violation in violations {
+		eprintln!("Warning: {violation}");
+	}
+}
+
+/// Wait for a DHT query to finish, printing its progress in verbose mode.
+/// The verified providers of an agent as candidates of the selection, scored by their reputation.
+async fn provider_candidates(
+	network_client: &mut network::Client,
+	cards: &HashMap<PeerId, AgentCard>,
+	reputation: &HashMap<PeerId, f64>,
+) -> Result<Vec<ProviderCandidate>, Box<dyn Error + Send>> {
+	let mut candidates = Vec::new();
+	for (&provider, card) in cards {
+		let latency = network_client.peer_latency(provider).await?;
+		candidates.push(ProviderCandidate {
+			peer_id: provider,
+			// Providers never pinged come after the ones of the same rank that were.
+			latency: latency.map_or(Duration::MAX, |latency| latency.median),
+			price: card.pricing,
+			score: reputation.get(&provider).copied().unwrap_or_default(),
+			labels: network_client.peer_labels(provider).await?,
+			languages: card.languages.clone(),
+		});
+	}
+	Ok(candidates)
+}
+
+async fn follow_query<T>(
+	mut query: QueryHandle<T>,
+	verbose: bool,
+) -> Result<T, Box<dyn Error + Send>> {
+	if verbose {
+		while let Some(progress) = query.next_progress().await {
+			eprintln!(
+				"DHT query {:?}, step {}: {} peers contacted ({} succeeded, {} failed) in {:?}, \
+				 closest peers: {:?}",
+				query.id(),
+				progress.step,
+				progress.peers_contacted,
+				progress.successes,
+				progress.failures,
+				progress.elapsed.unwrap_or_default(),
+				progress.closest_peers,
+			);
+		}
+	}
+	query.result().await
+}
+
+/// Print the answer of the first provider accepting the stream as its tokens arrive, and return
+/// it along with the provider. The spinner waiting for the answer is cleared on its first token.
+#[allow(clippy::too_many_arguments)]
+async fn stream_llm(
+	mut network_client: network::Client,
+	providers: HashSet<PeerId>,
+	name: String,
+	message: String,
+	window: u32,
+	spinner: ProgressBar,
+	stages: &mut progress::Timing,
+	output: &Output,
+) -> Result<(PeerId, String), Box<dyn Error>> {
+	for provider in providers {
+		let mut stream = match network_client
+			.request_agent_stream(provider, name.clone(), message.clone(), window, STREAM_TIMEOUT)
+			.await
+		{
+			Ok(stream) => stream,
+			Err(e) => {
+				tracing::warn!("Provider {provider} did not open the stream: {e}");
+				continue;
+			},
+		};
+
+		let mut stdout = output.human();
+		let mut answer = String::new();
+		while let Some(tokens) = stream.next_tokens().await.map_err(|e| e.to_string())? {
+			if !spinner.is_finished() {
+				spinner.finish_and_clear();
+				stages.stage("first token");
+			}
+			for token in tokens {
+				stdout.write_all(token.as_bytes())?;
+				answer.push_str(&token);
+			}
+			stdout.flush()?;
+		}
+		if stream.is_truncated() {
+			let tokens = stream.usage().unwrap_or_default().tokens;
+			tracing::warn!("Answer truncated at the deadline after {tokens} tokens");
+		}
+		return Ok((provider, answer));
+	}
+
+	Err("None of the providers opened the stream.".into())
+}
@@ -0,0 +1,272 @@
+use std::{net::SocketAddr, time::UNIX_EPOCH};
+
+use ai_agent::backend::PoolHit;
+use network::{Client, HistoryPage, HistoryQuery};
+use prometheus_client::{
+	encoding::EncodeLabelSet,
+	metrics::{counter::Counter, family::Family},
+	registry::Registry,
+};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+	task::spawn,
+};
+
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct WarmPoolLabels {
+	hit: String,
+}
+
+/// Warm and cold hits of the provider warm pool, exported alongside the network metrics.
+pub struct WarmPoolMetrics {
+	hits: Family<WarmPoolLabels, Counter>,
+}
+
+impl WarmPoolMetrics {
+	pub fn new(registry: &mut Registry) -> Self {
+		let hits = Family::default();
+		registry.sub_registry_with_prefix("asn").register(
+			"warm_pool_hits",
+			"Number of agent requests, by whether their backend was warm or cold",
+			hits.clone(),
+		);
+		Self { hits }
+	}
+
+	pub fn record(&self, hit: PoolHit) {
+		let hit = match hit {
+			PoolHit::Warm => "warm",
+			PoolHit::Cold => "cold",
+		};
+		self.hits.get_or_create(&WarmPoolLabels { hit: hit.to_string() }).inc();
+	}
+}
+
+/// Serve the network metrics on `GET /metrics` in the OpenMetrics text format, and the gossip
+/// history on `GET /gossip?topic=<topic>&cursor=<cursor>&limit=<count>` in JSON.
+pub async fn serve(address: SocketAddr, client: Client) -> std::io::Result<()> {
+	let listener = TcpListener::bind(address).await?;
+	tracing::info!("Serving metrics on http://{}/metrics", listener.local_addr()?);
+	accept(listener, client).await
+}
+
+async fn accept(listener: TcpListener, client: Client) -> std::io::Result<()> {
+	loop {
+		let (stream, remote) = listener.accept().await?;
+		let client = client.clone();
+		spawn(async move {
+			if let Err(e) = handle_connection(stream, &client).await {
+				tracing::debug!("Metrics request from {remote} failed: {e}");
+			}
+		});
+	}
+}
+
+/// Method, target and headers of an HTTP request.
+pub struct RequestHead {
+	pub method: String,
+	pub target: String,
+	headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+	/// The value of the header `name`, compared case-insensitively.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(key, _)| key.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+}
+
+/// Read the head of an HTTP request. None when the peer went away or sent too long a head.
+pub async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<RequestHead>> {
+	let mut head = Vec::new();
+	let mut buffer = [0; 1024];
+	while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+		let read = stream.read(&mut buffer).await?;
+		if read == 0 || head.len() + read > MAX_REQUEST_HEAD {
+			return Ok(None);
+		}
+		head.extend_from_slice(&buffer[..read]);
+	}
+
+	let head = String::from_utf8_lossy(&head);
+	let mut lines = head.split("\r\n");
+	let mut parts = lines.next().unwrap_or_default().split(' ');
+	let method = parts.next().unwrap_or_default().to_string();
+	let target = parts.next().unwrap_or_default().to_string();
+	let headers = lines
+		.take_while(|line| !line.is_empty())
+		.filter_map(|line| line.split_once(':'))
+		.map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+		.collect();
+	Ok(Some(RequestHead { method, target, headers }))
+}
+
+async fn handle_connection(mut stream: TcpStream, client: &Client) -> std::io::Result<()> {
+	let Some(RequestHead { method, target, .. }) = read_request(&mut stream).await? else {
+		return Ok(());
+	};
+	let response = match (method.as_str(), target.as_str()) {
+		("GET", "/metrics") => match client.encode_metrics() {
+			Ok(body) => http_response("200 OK", CONTENT_TYPE, &body),
+			Err(e) => http_response("500 Internal Server Error", "text/plain", &e.to_string()),
+		},
+		("GET", target) if target.split('?').next() == Some("/gossip") => {
+			match history_query(target) {
+				Ok(query) => match client.clone().gossip_history(query).await {
+					Ok(page) => {
+						http_response("200 OK", "application/json", &history_json(page).to_string())
+					},
+					Err(e) => {
+						http_response("503 Service Unavailable", "text/plain", &e.to_string())
+					},
+				},
+				Err(e) => http_response("400 Bad Request", "text/plain", &e),
+			}
+		},
+		_ => http_response("404 Not Found", "text/plain", "Not Found"),
+	};
+
+	stream.write_all(response.as_bytes()).await?;
+	stream.shutdown().await
+}
+
+/// The history query from the parameters of the request: any number of `topic`, the `cursor`
+/// of the last message seen and the `limit` of messages to return.
+fn history_query(target: &str) -> Result<HistoryQuery, String> {
+	let mut query = HistoryQuery { limit: DEFAULT_PAGE_SIZE, ..Default::default() };
+	let params = target.split_once('?').map(|(_, params)| params).unwrap_or_default();
+	for param in params.split('&').filter(|param| !param.is_empty()) {
+		match param.split_once('=').unwrap_or((param, "")) {
+			("topic", topic) => query.topics.push(topic.to_string()),
+			("cursor", cursor) => {
+				query.after = Some(cursor.parse().map_err(|_| format!("Invalid cursor: {cursor}"))?)
+			},
+			("limit", limit) => {
+				query.limit = limit.parse().map_err(|_| format!("Invalid limit: {limit}"))?
+			},
+			(name, _) => return Err(format!("Unknown parameter: {name}")),
+		}
+	}
+	Ok(query)
+}
+
+fn history_json(page: HistoryPage) -> serde_json::Value {
+	let messages: Vec<_> = page
+		.messages
+		.into_iter()
+		.map(|message| {
+			serde_json::json!({
+				"cursor": message.cursor,
+				"topic": message.topic,
+				"source": message.source.map(|peer| peer.to_string()),
+				"received_at": message
+					.received_at
+					.duration_since(UNIX_EPOCH)
+					.unwrap_or_default()
+					.as_secs(),
+				"data": String::from_utf8_lossy(&message.data),
+			})
+		})
+		.collect();
+	serde_json::json!({ "messages": messages, "next_cursor": page.next_cursor })
+}
+
+pub fn http_response(status: &str, content_type: &str, body: &str) -> String {
+	format!(
+		"HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+		body.len()
+	)
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use network::{MdnsConfig, NetworkConfig};
+	use tokio_util::sync::CancellationToken;
+
+	use super::*;
+
+	/// Send a request to the server and read its whole response.
+	async fn request(address: SocketAddr, request_line: &str) -> Result<String> {
+		let mut stream = TcpStream::connect(address).await?;
+		stream
+			.write_all(format!("{request_line}\r\nHost: localhost\r\n\r\n").as_bytes())
+			.await?;
+		let mut response = String::new();
+		stream.read_to_string(&mut response).await?;
+		Ok(response)
+	}
+
+	#[tokio::test]
+	async fn test_metrics_server_routes_the_requests() -> Result<()> {
+		let config = NetworkConfig {
+			mdns: MdnsConfig { enabled: false, ..Default::default() },
+			..Default::default()
+		};
+		let (client, _events, _, event_loop) = network::new(config).await?;
+		let cancellation_token = CancellationToken::new();
+		spawn(event_loop.run(cancellation_token.clone()));
+		let listener = TcpListener::bind("127.0.0.1:0").await?;
+		let address = listener.local_addr()?;
+		spawn(accept(listener, client));
+
+		let metrics = request(address, "GET /metrics HTTP/1.1").await?;
+		assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"), "{metrics}");
+		assert!(metrics.contains(&format!("Content-Type: {CONTENT_TYPE}\r\n")));
+		let (head, body) = metrics.split_once("\r\n\r\n").ok_or("Expected a body")?;
+		assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+		assert!(body.contains("asn_connected_peers 0"));
+		assert!(body.ends_with("# EOF\n"));
+
+		let gossip = request(address, "GET /gossip?topic=tasks&limit=10 HTTP/1.1").await?;
+		assert!(gossip.starts_with("HTTP/1.1 200 OK\r\n"), "{gossip}");
+		let (_, body) = gossip.split_once("\r\n\r\n").ok_or("Expected a body")?;
+		let page: serde_json::Value = serde_json::from_str(body)?;
+		assert_eq!(page, serde_json::json!({ "messages": [], "next_cursor": null }));
+
+		for (request_line, status) in [
+			("GET /gossip?cursor=last HTTP/1.1", "400 Bad Request"),
+			("GET /gossip?since=0 HTTP/1.1", "400 Bad Request"),
+			("GET /metrics/ HTTP/1.1", "404 Not Found"),
+			("GET /metrics?format=json HTTP/1.1", "404 Not Found"),
+			("GET / HTTP/1.1", "404 Not Found"),
+			("POST /metrics HTTP/1.1", "404 Not Found"),
+		] {
+			let response = request(address, request_line).await?;
+			assert!(response.starts_with(&format!("HTTP/1.1 {status}\r\n")), "{request_line}");
+		}
+
+		cancellation_token.cancel();
+		Ok(())
+	}
+
+	#[test]
+	fn test_history_query() -> Result<()> {
+		let query = history_query("/gossip?topic=tasks&topic=agents&cursor=7&limit=20")?;
+		assert_eq!(
+			query,
+			HistoryQuery {
+				topics: vec!["tasks".to_string(), "agents".to_string()],
+				after: Some(7),
+				limit: 20,
+			}
+		);
+		assert_eq!(history_query("/gossip")?.limit, DEFAULT_PAGE_SIZE);
+		assert!(history_query("/gossip?limit=-1").is_err());
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,96 @@
+use std::{
+	cell::Cell,
+	fmt::Display,
+	io::{self, Write},
+};
+
+use clap::ArgMatches;
+use network::PeerId;
+use serde_json::{json, Value};
+
+/// Version of the documents printed with `--json`, bumped on incompatible changes to them.
+pub const JSON_VERSION: u32 = 1;
+
+/// How a command reports its result: as text on stdout, or with `--json` as a single JSON document
+/// on stdout, the text for people going to stderr:
+///
+/// ```json
+/// { "version": 1, "command": "llm", "peer_id": "12D3KooW...", "result": { ... } }
+/// { "version": 1, "command": "llm", "peer_id": "12D3KooW...", "error": "..." }
+/// ```
+///
+/// `peer_id` is the node running the command, absent for the commands run without the network.
+/// Only the first result or error reported is printed, for the document to stay single.
+#[derive(Debug)]
+pub struct Output {
+	json: bool,
+	command: String,
+	peer_id: Cell<Option<PeerId>>,
+	printed: Cell<bool>,
+}
+
+impl Output {
+	/// The output of the subcommand of `matches`, e.g. `ctl status`.
+	pub fn new(json: bool, matches: &ArgMatches) -> Self {
+		let mut command = Vec::new();
+		let mut matches = matches;
+		while let Some((name, sub)) = matches.subcommand() {
+			command.push(name);
+			matches = sub;
+		}
+		Self {
+			json,
+			command: command.join(" "),
+			peer_id: Cell::default(),
+			printed: Cell::default(),
+		}
+	}
+
+	/// Set the node running the command, once started.
+	pub fn set_peer_id(&self, peer_id: PeerId) {
+		self.peer_id.set(Some(peer_id));
+	}
+
+	pub fn is_json(&self) -> bool {
+		self.json
+	}
+
+	/// Where the text for people goes: stdout, or stderr with `--json`.
+	pub fn human(&self) -> Box<dyn Write> {
+		if self.json {
+			Box::new(io::stderr())
+		} else {
+			Box::new(io::stdout())
+		}
+	}
+
+	/// Report the result of the command: printed by `text`, or as the `result` of the document with
+	/// `--json`.
+	pub fn result(&self, result: Value, text: impl FnOnce()) {
+		if self.json {
+			self.print("result", result);
+		} else {
+			text();
+		}
+	}
+
+	/// Report the command failed. The text is left to the logs, the document carries the error
+	/// with `--json`.
+	pub fn error(&self, error: impl Display) {
+		if self.json {
+			self.print("error", Value::String(error.to_string()));
+		}
+	}
+
+	fn print(&self, key: &str, value: Value) {
+		if self.printed.replace(true) {
+			return;
+		}
+		let mut document = json!({ "version": JSON_VERSION, "command": self.command });
+		if let Some(peer_id) = self.peer_id.get() {
+			document["peer_id"] = json!(peer_id.to_string());
+		}
+		document[key] = value;
+		println!("{document}");
+	}
+}
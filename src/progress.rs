@@ -0,0 +1,82 @@
+use std::{
+	borrow::Cow,
+	time::{Duration, Instant},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+const TICK: Duration = Duration::from_millis(100);
+
+/// A spinner on stderr while `message` runs, hidden in verbose mode for the progress printed then
+/// to stay readable. Not drawn when stderr is not a terminal.
+pub fn spinner(verbose: bool, message: impl Into<Cow<'static, str>>) -> ProgressBar {
+	if verbose {
+		return ProgressBar::hidden();
+	}
+	let spinner = ProgressBar::new_spinner().with_message(message);
+	spinner.set_style(
+		ProgressStyle::with_template("{spinner} {msg} {elapsed}")
+			.unwrap_or_else(|_| ProgressStyle::default_spinner()),
+	);
+	spinner.enable_steady_tick(TICK);
+	spinner
+}
+
+/// A bar on stderr counting the `len` items of a batch as they are done.
+pub fn bar(verbose: bool, len: u64) -> ProgressBar {
+	if verbose {
+		return ProgressBar::hidden();
+	}
+	let bar = ProgressBar::new(len);
+	bar.set_style(
+		ProgressStyle::with_template("[{bar:30}] {pos}/{len} {msg} {elapsed}")
+			.unwrap_or_else(|_| ProgressStyle::default_bar())
+			.progress_chars("=> "),
+	);
+	bar.enable_steady_tick(TICK);
+	bar
+}
+
+/// How long each stage of a command took, printed by `--timing`.
+#[derive(Debug)]
+pub struct Timing {
+	started: Instant,
+	last: Instant,
+	stages: Vec<(&'static str, Duration)>,
+}
+
+impl Timing {
+	pub fn start() -> Self {
+		let now = Instant::now();
+		Self { started: now, last: now, stages: Vec::new() }
+	}
+
+	/// End the stage `name`, started when the previous one ended.
+	pub fn stage(&mut self, name: &'static str) {
+		let now = Instant::now();
+		self.stages.push((name, now - self.last));
+		self.last = now;
+	}
+
+	/// The stages and the total in milliseconds, e.g. `{"discovery": 812, "first_token": 240, ...,
+	/// "total": 1504}`.
+	pub fn to_json(&self) -> serde_json::Value {
+		let millis = |elapsed: Duration| serde_json::Value::from(elapsed.as_millis() as u64);
+		let mut stages: serde_json::Map<_, _> = self
+			.stages
+			.iter()
+			.map(|(name, elapsed)| (name.replace(' ', "_"), millis(*elapsed)))
+			.collect();
+		stages.insert("total".to_string(), millis(self.started.elapsed()));
+		stages.into()
+	}
+
+	/// Print the stages and the total on stderr.
+	pub fn print(&self) {
+		eprintln!();
+		for (name, elapsed) in &self.stages {
+			eprintln!("{name:>12}: {elapsed:>10.1?}");
+		}
+		eprintln!("{:>12}: {:>10.1?}", "total", self.started.elapsed());
+	}
+}
@@ -0,0 +1,498 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	error::Error,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use ai_agent::backend::WarmPool;
+use futures::{Stream, StreamExt};
+use network::{
+	provenance,
+	stream::now_ms,
+	types::{Event, TaskProposal},
+	AgentCard, ArtifactDemand, ArtifactStore, Bidder, Cid, Client, PeerId, Provenance, TaskQueue,
+};
+use tokio::{
+	sync::{watch, Notify},
+	task::spawn,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{cli::ProviderArgs, follow_query, metrics::WarmPoolMetrics, sessions, worker};
+
+/// How often providers check whether their price schedule changed their price.
+const REPRICE_INTERVAL: Duration = Duration::from_secs(60);
+/// How often providers collect the attachments cached past their time to live.
+const ARTIFACT_GC_INTERVAL: Duration = Duration::from_secs(3600);
+/// Window the fetches of an artifact are counted over to decide whether to mirror it.
+const MIRROR_WINDOW: Duration = Duration::from_secs(600);
+/// Most answers kept for the jobs of the requesters, the oldest dropped past it.
+const MAX_ANSWERED_JOBS: usize = 1024;
+
+/// An agent served by the node, and how it bids on the task proposals for it.
+pub struct ServedAgent {
+	pub card: AgentCard,
+	pub bidder: Bidder,
+}
+
+impl ServedAgent {
+	/// The agent of `card`, advertised at the price of the schedule of `bidder`.
+	pub fn new(card: AgentCard, bidder: Bidder) -> Self {
+		let card = AgentCard { pricing: bidder.price_at(now_ms() / 1000), ..card };
+		Self { card, bidder }
+	}
+}
+
+/// The answers given to the jobs of the requesters, repeated to the attempts at a job sent again
+/// instead of answering it anew.
+#[derive(Default)]
+struct AnsweredJobs {
+	answers: HashMap<(PeerId, Cid), Vec<u8>>,
+	order: VecDeque<(PeerId, Cid)>,
+}
+
+impl AnsweredJobs {
+	fn get(&self, requester: PeerId, job: Cid) -> Option<&Vec<u8>> {
+		self.answers.get(&(requester, job))
+	}
+
+	fn insert(&mut self, requester: PeerId, job: Cid, answer: Vec<u8>) {
+		if self.answers.insert((requester, job), answer).is_none() {
+			self.order.push_back((requester, job));
+		}
+		while self.order.len() > MAX_ANSWERED_JOBS {
+			if let Some(oldest) = self.order.pop_front() {
+				self.answers.remove(&oldest);
+			}
+		}
+	}
+}
+
+/// Serve `agents` until `shutdown`, then stop providing them. Their cards are published on
+/// `cards` as their price follows its schedule.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+	mut network_client: Client,
+	mut network_events: impl Stream<Item = Event> + Unpin,
+	peer_id: PeerId,
+	agents: Vec<ServedAgent>,
+	args: ProviderArgs,
+	verbose: bool,
+	shutdown: CancellationToken,
+	cards: watch::Sender<Vec<AgentCard>>,
+) -> Result<(), Box<dyn Error>> {
+	let ProviderArgs {
+		shadow,
+		shadow_percent,
+		warm_pool_size,
+		language_profile,
+		session_store,
+		watermark,
+		task_dir,
+		artifact_store,
+		artifact_ttl,
+		artifact_quota,
+		mirror_threshold,
+	} = args;
+	let pool = WarmPool::new(
+		warm_pool_size,
+		Box::new(move |agent_name| {
+			tracing::info!("Loading the backend of {agent_name}");
+			crate::agent::new_backend(shadow.as_deref(), shadow_percent)
+		}),
+	);
+	let pool_metrics = WarmPoolMetrics::new(
+		&mut network_client
+			.metrics_registry()
+			.lock()
+			.expect("Metrics registry not to be poisoned."),
+	);
+
+	// Warm the provided agents up front, which also fails early on a misconfigured backend.
+	let mut backend = None;
+	for agent in &agents {
+		let (agent_backend, _) = pool.get(&agent.card.name)?;
+		tracing::info!(
+			"Answering requests for {} with the {} backend",
+			agent.card.name,
+			agent_backend.name()
+		);
+		backend.get_or_insert(agent_backend);
+	}
+	let mut profiles = HashMap::new();
+	for (language, profile) in language_profile {
+		tracing::info!("Answering the requests in {language} with the {profile} backend");
+		profiles.insert(language.to_lowercase(), ai_agent::backend::new_backend(&profile)?);
+	}
+
+	// Tasks won are worked on in the background, those left by a previous run first.
+	let queue = TaskQueue::open(task_dir.map(|dir| dir.join("queue")))?;
+	if !queue.is_empty() {
+		tracing::info!("Resuming {} queued tasks", queue.len());
+	}
+	let queue = Arc::new(Mutex::new(queue));
+	let queued = Arc::new(Notify::new());
+	if let Some(backend) = backend {
+		spawn(worker::run(queue.clone(), queued.clone(), network_client.clone(), backend));
+	}
+	// Proposals bid on, by task id, until their deadline.
+	let mut proposals = HashMap::new();
+	// Attachments fetched are cached, and collected past their time to live.
+	let artifact_store = artifact_store
+		.map(|dir| ArtifactStore::open(dir).map(|store| store.with_quota(artifact_quota)))
+		.transpose()?;
+	let mut gc_tick = tokio::time::interval(ARTIFACT_GC_INTERVAL);
+	let mut demand =
+		mirror_threshold.map(|threshold| ArtifactDemand::new(MIRROR_WINDOW, threshold));
+
+	let mut answered = AnsweredJobs::default();
+	let mut agents: HashMap<_, _> =
+		agents.into_iter().map(|agent| (agent.card.name.clone(), agent)).collect();
+	for agent in agents.values() {
+		let providing = match network_client.start_providing(agent.card.clone()).await {
+			Ok(query) => follow_query(query, verbose).await,
+			Err(e) => Err(e),
+		};
+		if let Err(e) = providing {
+			tracing::warn!("Failed to provide the agent {}: {e}", agent.card.name);
+		}
+	}
+	cards.send_replace(agents.values().map(|agent| agent.card.clone()).collect());
+
+	// The advertised prices follow the schedules.
+	let mut reprice_tick = tokio::time::interval(REPRICE_INTERVAL);
+	loop {
+		let event = tokio::select! {
+			event = network_events.next() => event,
+			_ = shutdown.cancelled() => {
+				for name in agents.into_keys() {
+					if let Err(e) = network_client.stop_providing(name.clone()).await {
+						tracing::warn!("Failed to stop providing the agent {name}: {e}");
+					}
+				}
+				return Ok(());
+			},
+			_ = reprice_tick.tick() => {
+				for agent in agents.values_mut() {
+					let pricing = agent.bidder.price_at(now_ms() / 1000);
+					if pricing != agent.card.pricing {
+						tracing::info!("Pricing {} at {pricing}", agent.card.name);
+						agent.card.pricing = pricing;
+						if let Err(e) = network_client.update_agent_card(agent.card.clone()).await {
+							tracing::warn!("Failed to update the card of the agent: {e}");
+						}
+					}
+				}
+				cards.send_replace(agents.values().map(|agent| agent.card.clone()).collect());
+				continue;
+			},
+			_ = gc_tick.tick(), if artifact_store.is_some() => {
+				let store = artifact_store.as_ref().expect("An artifact store.");
+				let now = tokio::time::Instant::now();
+				for cid in demand.as_mut().map(|d| d.cooled(now)).unwrap_or_default() {
+					tracing::info!("No longer mirroring artifact {cid}");
+					if let Err(e) = store.unpin(&cid) {
+						tracing::warn!("Failed to unpin artifact {cid}: {e}");
+					}
+					if let Err(e) = network_client.stop_providing_artifact(cid).await {
+						tracing::warn!("Failed to stop advertising artifact {cid}: {e}");
+					}
+				}
+				match store.gc(Duration::from_secs(artifact_ttl)) {
+					Ok(report) if report.artifacts > 0 => tracing::info!(
+						"Collected {} attachments, {} bytes",
+						report.artifacts,
+						report.bytes
+					),
+					Ok(_) => {},
+					Err(e) => tracing::warn!("Failed to collect the attachments: {e}"),
+				}
+				continue;
+			},
+		};
+		match event {
+			Some(Event::LLMInboundRequest {
+				agent_name,
+				message,
+				context,
+				language,
+				requester,
+				attachments,
+				job,
+				channel,
+			}) => {
+				tracing::info!("Received request for agent: {:?}", agent_name);
+				let Some(agent) = agents.get(&agent_name) else {
+					continue;
+				};
+				if let Some((job, answer)) =
+					job.and_then(|job| Some((job, answered.get(requester, job)?)))
+				{
+					tracing::info!("Repeating the answer to job {job} of {requester}");
+					network_client.respond_llm(answer.clone(), channel).await;
+					continue;
+				}
+				let attached = match fetch_attachments(
+					&mut network_client,
+					artifact_store.as_ref(),
+					requester,
+					attachments,
+				)
+				.await
+				{
+					Ok(attached) => attached,
+					Err(e) => {
+						tracing::error!("Failed to fetch the attachments: {e}");
+						continue;
+					},
+				};
+				if let (Some(demand), Some(store)) = (&mut demand, &artifact_store) {
+					for (cid, _) in &attached {
+						if demand.record(*cid, tokio::time::Instant::now()) {
+							mirror(&mut network_client, store, *cid).await;
+						}
+					}
+				}
+				let profile =
+					language.as_deref().and_then(|language| profiles.get(&language.to_lowercase()));
+				let backend = match profile {
+					Some(backend) => backend.clone(),
+					None => match pool.get(&agent_name) {
+						Ok((backend, hit)) => {
+							pool_metrics.record(hit);
+							backend
+						},
+						Err(e) => {
+							tracing::error!("Failed to load the backend: {e}");
+							continue;
+						},
+					},
+				};
+				let mut exchange =
+					sessions::Exchange::new(agent_name, message.clone(), context.clone());
+				let context = crate::agent::context_messages(context);
+				let message = crate::agent::with_attachments(message, attached);
+				let model = backend.model().to_string();
+				match crate::agent::respond_llm(backend, context, message).await {
+					Ok(output) => {
+						let output = match watermark {
+							true => provenance::watermark(&output, &peer_id),
+							false => output,
+						};
+						if let Some(store) = &session_store {
+							exchange.provider = Some(peer_id.to_string());
+							exchange.version = Some(agent.card.version.clone());
+							exchange.pricing = Some(agent.card.pricing);
+							exchange.language = language;
+							exchange.respond(output.as_bytes());
+							if let Err(e) = sessions::record(store, &exchange) {
+								tracing::error!("Failed to record the exchange: {e}");
+							}
+						}
+						let mut output = output.into_bytes();
+						let appended = provenance::manifest_of(&agent.card)
+							.map_err(|e| e.to_string())
+							.and_then(|manifest| {
+								let provenance = Provenance {
+									provider: peer_id,
+									model,
+									timestamp: now_ms(),
+									manifest,
+								};
+								provenance.append_to(&mut output).map_err(|e| e.to_string())
+							});
+						if let Err(e) = appended {
+							tracing::error!("Failed to append the provenance: {e}");
+						}
+						if let Some(job) = job {
+							answered.insert(requester, job, output.clone());
+						}
+						network_client.respond_llm(output, channel).await;
+					},
+					Err(e) => tracing::error!("Failed to answer request: {e}"),
+				}
+			},
+			Some(Event::ArtifactRequest { name, channel }) => {
+				let data = match &artifact_store {
+					Some(store) => store.serve(&name).unwrap_or_else(|e| {
+						tracing::warn!("Failed to serve artifact {name}: {e}");
+						None
+					}),
+					None => None,
+				};
+				// An artifact is fetched with its manifest first, counted once.
+				let manifest =
+					name.strip_prefix("manifest/").and_then(|cid| cid.parse::<Cid>().ok());
+				if let (Some(demand), Some(store), Some(cid), Some(_)) =
+					(&mut demand, &artifact_store, manifest, &data)
+				{
+					if demand.record(cid, tokio::time::Instant::now()) {
+						mirror(&mut network_client, store, cid).await;
+					}
+				}
+				network_client.respond_artifact(data.unwrap_or_default(), channel).await;
+			},
+			Some(Event::LLMStreamRequest { agent_name, message, tokens }) => {
+				tracing::info!("Received stream request for agent: {:?}", agent_name);
+				if agents.contains_key(&agent_name) {
+					let backend = match pool.get(&agent_name) {
+						Ok((backend, hit)) => {
+							pool_metrics.record(hit);
+							backend
+						},
+						Err(e) => {
+							tracing::error!("Failed to load the backend: {e}");
+							continue;
+						},
+					};
+					spawn(async move {
+						if let Err(e) = backend.complete_stream(&message, tokens).await {
+							tracing::error!("Failed to stream answer: {e}");
+						}
+					});
+				}
+			},
+			Some(Event::InboundTaskProposal { proposer, task_proposal })
+				if agents.contains_key(&task_proposal.agent_name) =>
+			{
+				let bidder = &agents[&task_proposal.agent_name].bidder;
+				let load = queue.lock().expect("Task queue not to be poisoned.").len();
+				let Some(bid) = bidder.bid(&task_proposal, now_ms() / 1000, load) else {
+					tracing::info!("Not bidding on task {}", task_proposal.task_id);
+					continue;
+				};
+				tracing::info!("Bidding {} on task {}", bid.bid, task_proposal.task_id);
+				if let Err(e) = network_client.submit_bid(proposer, bid).await {
+					tracing::warn!("Failed to bid on task {}: {e}", task_proposal.task_id);
+					continue;
+				}
+				let now = now_ms() / 1000;
+				proposals.retain(|_, proposal: &mut TaskProposal| proposal.deadline > now);
+				proposals.insert(task_proposal.task_id.clone(), task_proposal);
+			},
+			Some(Event::TaskAwarded { proposer, award }) => {
+				let Some(mut proposal) = proposals.get(&award.task_id).cloned() else {
+					tracing::warn!("Awarded task {} without bidding on it", award.task_id);
+					continue;
+				};
+				// Reassigned tasks come with a later deadline.
+				proposal.deadline = award.deadline;
+				let queued_task = queue
+					.lock()
+					.expect("Task queue not to be poisoned.")
+					.enqueue(proposer, proposal);
+				match queued_task {
+					Ok(true) => queued.notify_one(),
+					Ok(false) => tracing::info!("Task {} already queued", award.task_id),
+					Err(e) => tracing::error!("Failed to queue task {}: {e}", award.task_id),
+				}
+			},
+			Some(Event::Feedback { requester, feedback }) => {
+				tracing::info!(
+					"Exchange {} rated {} by {requester}",
+					feedback.receipt,
+					feedback.rating
+				);
+				if let Some(store) = &session_store {
+					let record = sessions::FeedbackRecord::new(requester.to_string(), feedback);
+					match sessions::record_feedback(store, record) {
+						Ok(true) => {},
+						Ok(false) => tracing::warn!("Rated exchange unknown, ignored"),
+						Err(e) => tracing::error!("Failed to record the rating: {e}"),
+					}
+				}
+			},
+			Some(Event::PeerConnected { peer_id, endpoint }) => {
+				tracing::info!("Connected to {peer_id} at {}", endpoint.get_remote_address());
+			},
+			Some(Event::PeerDisconnected { peer_id, cause }) => match cause {
+				Some(cause) => tracing::info!("Disconnected from {peer_id}: {cause}"),
+				None => tracing::info!("Disconnected from {peer_id}"),
+			},
+			Some(Event::NetworkResumed { suspended }) => match suspended {
+				Some(suspended) => tracing::info!("Resumed after a suspend of {suspended:?}"),
+				None => tracing::info!("Resumed after a network change"),
+			},
+			Some(Event::NatStatusChanged { new, .. }) => {
+				if matches!(new, network::NatStatus::Private) {
+					tracing::warn!("Not reachable from the outside, requests may not arrive");
+				}
+			},
+			Some(e) => {
+				tracing::info!("Unhandled event: {:?}", e);
+			},
+			None => return Ok(()),
+		}
+	}
+}
+
+/// Fetch the attachments of a request from the requester, unless cached in the store, caching
+/// them there.
+async fn fetch_attachments(
+	network_client: &mut Client,
+	store: Option<&ArtifactStore>,
+	requester: PeerId,
+	attachments: Vec<Cid>,
+) -> Result<Vec<(Cid, Vec<u8>)>, Box<dyn Error + Send>> {
+	let mut attached = Vec::new();
+	for cid in attachments {
+		if let Some(data) = store.and_then(|store| store.get(&cid).ok().flatten()) {
+			attached.push((cid, data));
+			continue;
+		}
+		let data = network_client.fetch_artifact_or_mirrors(requester, &cid).await?;
+		if let Some(Err(e)) = store.map(|store| store.put(&data)) {
+			tracing::warn!("Failed to cache attachment {cid}: {e}");
+		}
+		attached.push((cid, data));
+	}
+	Ok(attached)
+}
+
+/// Keep a hot artifact of the store and advertise it on the DHT, for the peers to fetch it from
+/// this node once its origin is gone.
+async fn mirror(network_client: &mut Client, store: &ArtifactStore, cid: Cid) {
+	tracing::info!("Mirroring artifact {cid}");
+	if let Err(e) = store.pin(&cid) {
+		tracing::warn!("Failed to pin artifact {cid}: {e}");
+		return;
+	}
+	if let Err(e) = network_client.provide_artifact(cid).await {
+		tracing::warn!("Failed to advertise artifact {cid}: {e}");
+	}
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	type Error = Box<dyn std::error::Error>;
+	type Result<T> = core::result::Result<T, Error>; // For tests.
+
+	use super::*;
+
+	#[test]
+	fn test_answered_jobs_keep_the_latest_per_requester() -> Result<()> {
+		// -- Setup & Fixtures
+		let (alice, bob) = (PeerId::random(), PeerId::random());
+		let mut answered = AnsweredJobs::default();
+
+		// -- Exec
+		answered.insert(alice, Cid::of(b"job"), b"answer".to_vec());
+		for i in 0..MAX_ANSWERED_JOBS {
+			answered.insert(bob, Cid::of(i.to_le_bytes()), Vec::new());
+		}
+
+		// -- Check
+		assert_eq!(answered.get(alice, Cid::of(b"job")), None, "the oldest answer is dropped");
+		assert_eq!(answered.get(bob, Cid::of(0usize.to_le_bytes())), Some(&Vec::new()));
+		answered.insert(alice, Cid::of(b"job"), b"answer".to_vec());
+		assert_eq!(answered.get(alice, Cid::of(b"job")), Some(&b"answer".to_vec()));
+		assert_eq!(answered.get(bob, Cid::of(b"job")), None, "jobs are kept per requester");
+
+		Ok(())
+	}
+}
+
+// endregion: --- Tests
@@ -0,0 +1,446 @@
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	error::Error,
+	fs::OpenOptions,
+	io::{BufRead, BufReader, Write},
+	path::Path,
+	str::FromStr,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use network::{
+	pii::redact,
+	types::{ContextMessage, MessageRole},
+	AgentQuality, Cid, Rating,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{cli::SessionsCommand, output::Output};
+
+/// An exchange with an agent, as recorded in the session store by `dasn llm`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+	/// Seconds since the Unix epoch.
+	pub timestamp: u64,
+	pub agent: String,
+	/// Provider that answered, unknown for hedged requests.
+	pub provider: Option<String>,
+	/// Version of the agent, from the card of the provider.
+	pub version: Option<String>,
+	/// Price of the request, from the card of the provider.
+	pub pricing: Option<f64>,
+	pub language: Option<String>,
+	pub context: Vec<ContextMessage>,
+	pub message: String,
+	pub response: String,
+	/// Receipt the requester rates the exchange with, see [`network::feedback::receipt`].
+	#[serde(default)]
+	pub receipt: Option<Cid>,
+	/// Ratings of the exchange, attached when the store is loaded.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub feedback: Vec<FeedbackRecord>,
+}
+
+impl Exchange {
+	pub fn new(agent: String, message: String, context: Vec<ContextMessage>) -> Self {
+		Self {
+			timestamp: now(),
+			agent,
+			provider: None,
+			version: None,
+			pricing: None,
+			language: None,
+			context,
+			message,
+			response: String::new(),
+			receipt: None,
+			feedback: Vec::new(),
+		}
+	}
+
+	/// Set the response, and the receipt of the exchange it completes.
+	pub fn respond(&mut self, response: &[u8]) {
+		self.receipt = Some(network::feedback::receipt(&self.agent, &self.message, response));
+		self.response = String::from_utf8_lossy(response).into_owned();
+	}
+
+	/// How many times the exchange was rated up, minus the times it was rated down.
+	pub fn rating(&self) -> i64 {
+		self.feedback
+			.iter()
+			.map(|record| match record.rating {
+				Rating::Up => 1,
+				Rating::Down => -1,
+			})
+			.sum()
+	}
+
+	/// The exchange with the email addresses and phone numbers of its messages replaced.
+	fn redacted(mut self) -> Self {
+		self.message = redact(&self.message);
+		self.response = redact(&self.response);
+		for message in &mut self.context {
+			message.content = redact(&message.content);
+		}
+		self
+	}
+}
+
+/// A rating of an exchange of the store, received by `dasn provide` from the requester.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRecord {
+	/// Seconds since the Unix epoch.
+	pub timestamp: u64,
+	pub receipt: Cid,
+	pub requester: String,
+	pub rating: Rating,
+	pub comment: Option<String>,
+}
+
+impl FeedbackRecord {
+	pub fn new(requester: String, feedback: network::Feedback) -> Self {
+		Self {
+			timestamp: now(),
+			receipt: feedback.receipt,
+			requester,
+			rating: feedback.rating,
+			comment: feedback.comment,
+		}
+	}
+}
+
+/// A line of the session store.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Record {
+	Exchange(Exchange),
+	Feedback(FeedbackRecord),
+}
+
+/// Append an exchange to the session store, a JSON Lines file created if missing.
+pub fn record(store: &Path, exchange: &Exchange) -> Result<(), Box<dyn Error>> {
+	append(store, &Record::Exchange(exchange.clone()))
+}
+
+/// Append a rating to the session store, unless none of its exchanges has the receipt rated.
+/// Returns whether it was recorded.
+pub fn record_feedback(store: &Path, feedback: FeedbackRecord) -> Result<bool, Box<dyn Error>> {
+	let known = store.exists()
+		&& load(store)?.iter().any(|exchange| exchange.receipt == Some(feedback.receipt));
+	if known {
+		append(store, &Record::Feedback(feedback))?;
+	}
+	Ok(known)
+}
+
+fn append(store: &Path, record: &Record) -> Result<(), Box<dyn Error>> {
+	let mut file = OpenOptions::new().create(true).append(true).open(store)?;
+	writeln!(file, "{}", serde_json::to_string(record)?)?;
+	Ok(())
+}
+
+/// The exchanges of the session store, oldest first, with their ratings.
+pub fn load(store: &Path) -> Result<Vec<Exchange>, Box<dyn Error>> {
+	let mut exchanges: Vec<Exchange> = Vec::new();
+	let mut feedback = Vec::new();
+	for line in BufReader::new(std::fs::File::open(store)?).lines() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		match serde_json::from_str(&line)? {
+			Record::Exchange(exchange) => exchanges.push(exchange),
+			Record::Feedback(record) => feedback.push(record),
+		}
+	}
+
+	let mut by_receipt: HashMap<Cid, Vec<usize>> = HashMap::new();
+	for (index, exchange) in exchanges.iter().enumerate() {
+		if let Some(receipt) = exchange.receipt {
+			by_receipt.entry(receipt).or_default().push(index);
+		}
+	}
+	for record in feedback {
+		for &index in by_receipt.get(&record.receipt).into_iter().flatten() {
+			exchanges[index].feedback.push(record.clone());
+		}
+	}
+	Ok(exchanges)
+}
+
+/// Ratings of the exchanges of each agent.
+pub fn quality(exchanges: &[Exchange]) -> BTreeMap<String, AgentQuality> {
+	let mut quality = BTreeMap::<String, AgentQuality>::new();
+	for exchange in exchanges {
+		let agent = quality.entry(exchange.agent.clone()).or_default();
+		for record in &exchange.feedback {
+			agent.record(record.rating);
+		}
+	}
+	quality
+}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+	Markdown,
+	Json,
+}
+
+impl FromStr for ExportFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+		match s {
+			"md" | "markdown" => Ok(ExportFormat::Markdown),
+			"json" => Ok(ExportFormat::Json),
+			_ => Err(format!("Unknown export format {s}, expected md or json")),
+		}
+	}
+}
+
+/// Run a `dasn sessions` command, which needs no network. With `--json`, the exchanges exported
+/// are the result whatever the format, and the examples of a dataset are in a list.
+pub fn run(command: SessionsCommand, output: &Output) -> Result<(), Box<dyn Error>> {
+	match command {
+		SessionsCommand::Export { store, format, agent, redact } => {
+			let exchanges = load(&store)?
+				.into_iter()
+				.filter(|exchange| agent.as_ref().is_none_or(|agent| &exchange.agent == agent))
+				.map(|exchange| if redact { exchange.redacted() } else { exchange })
+				.collect::<Vec<_>>();
+			if output.is_json() {
+				output.result(serde_json::to_value(&exchanges)?, || {});
+				return Ok(());
+			}
+			match format {
+				ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&exchanges)?),
+				ExportFormat::Markdown => print!("{}", markdown(&exchanges)),
+			}
+		},
+		SessionsCommand::Dataset { store, agent, since, until, min_rating } => {
+			let filter = DatasetFilter { agent, since, until, min_rating };
+			let examples = dataset(load(&store)?, &filter);
+			if !output.is_json() {
+				for example in &examples {
+					println!("{example}");
+				}
+			}
+			output.result(json!(examples), || {});
+		},
+		SessionsCommand::Quality { store } => {
+			let quality = quality(&load(&store)?);
+			let result: Vec<_> = quality
+				.iter()
+				.map(|(agent, quality)| {
+					json!({
+						"agent": agent,
+						"up": quality.up,
+						"down": quality.down,
+						"score": quality.score(),
+					})
+				})
+				.collect();
+			output.result(json!(result), || {
+				for (agent, quality) in &quality {
+					println!(
+						"{agent}: {} up, {} down, score {:.2}",
+						quality.up,
+						quality.down,
+						quality.score()
+					);
+				}
+			});
+		},
+	}
+	Ok(())
+}
+
+/// The exchanges kept in a fine-tuning dataset, all of them when unset.
+#[derive(Debug, Default)]
+struct DatasetFilter {
+	agent: Option<String>,
+	/// Unix time of the first exchange kept.
+	since: Option<u64>,
+	/// Unix time the exchanges kept are before.
+	until: Option<u64>,
+	/// Lowest [`Exchange::rating`] kept.
+	min_rating: Option<i64>,
+}
+
+impl DatasetFilter {
+	fn keeps(&self, exchange: &Exchange) -> bool {
+		self.agent.as_ref().is_none_or(|agent| &exchange.agent == agent)
+			&& self.since.is_none_or(|since| exchange.timestamp >= since)
+			&& self.until.is_none_or(|until| exchange.timestamp < until)
+			&& self.min_rating.is_none_or(|min_rating| exchange.rating() >= min_rating)
+	}
+}
+
+/// The fine-tuning examples of the exchanges the filter keeps, redacted, each conversation kept
+/// once however many times it was recorded.
+fn dataset(exchanges: Vec<Exchange>, filter: &DatasetFilter) -> Vec<serde_json::Value> {
+	let mut seen = HashSet::new();
+	exchanges
+		.into_iter()
+		.filter(|exchange| filter.keeps(exchange))
+		.map(|exchange| fine_tuning_example(exchange.redacted()))
+		.filter(|example| seen.insert(example.to_string()))
+		.collect()
+}
+
+/// The exchange in the chat format of the OpenAI fine-tuning API, the conversation ending with
+/// the answer of the agent.
+fn fine_tuning_example(exchange: Exchange) -> serde_json::Value {
+	let mut messages: Vec<_> = exchange
+		.context
+		.into_iter()
+		.map(|message| serde_json::json!({ "role": message.role, "content": message.content }))
+		.collect();
+	messages.push(serde_json::json!({ "role": "user", "content": exchange.message }));
+	messages.push(serde_json::json!({ "role": "assistant", "content": exchange.response }));
+	serde_json::json!({ "messages": messages })
+}
+
+/// A transcript of the exchanges, a section each.
+fn markdown(exchanges: &[Exchange]) -> String {
+	let mut transcript = String::new();
+	for exchange in exchanges {
+		transcript.push_str(&format!("## {} at {}\n\n", exchange.agent, exchange.timestamp));
+		let metadata = [
+			("Provider", exchange.provider.clone()),
+			("Version", exchange.version.clone()),
+			("Pricing", exchange.pricing.map(|pricing| pricing.to_string())),
+			("Language", exchange.language.clone()),
+			("Receipt", exchange.receipt.map(|receipt| receipt.to_string())),
+		];
+		for (name, value) in metadata {
+			if let Some(value) = value {
+				transcript.push_str(&format!("- {name}: {value}\n"));
+			}
+		}
+		transcript.push('\n');
+		for message in &exchange.context {
+			let role = match message.role {
+				MessageRole::User => "User",
+				MessageRole::Assistant => "Assistant",
+			};
+			transcript.push_str(&format!("**{role}:** {}\n\n", message.content));
+		}
+		transcript.push_str(&format!("**User:** {}\n\n", exchange.message));
+		transcript.push_str(&format!("**Assistant:** {}\n\n", exchange.response));
+		for record in &exchange.feedback {
+			let comment = record.comment.as_deref().map(|c| format!(": {c}")).unwrap_or_default();
+			transcript.push_str(&format!(
+				"*Rated {} by {}{comment}*\n\n",
+				record.rating, record.requester
+			));
+		}
+	}
+	transcript
+}
+
+// region:    --- Tests
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn exchange(agent: &str, timestamp: u64, message: &str, ratings: &[Rating]) -> Exchange {
+		let mut exchange = Exchange::new(agent.to_string(), message.to_string(), Vec::new());
+		exchange.timestamp = timestamp;
+		exchange.respond(b"answer");
+		exchange.feedback = ratings
+			.iter()
+			.map(|rating| FeedbackRecord {
+				timestamp,
+				receipt: exchange.receipt.unwrap(),
+				requester: "requester".to_string(),
+				rating: *rating,
+				comment: None,
+			})
+			.collect();
+		exchange
+	}
+
+	fn messages(examples: &[serde_json::Value]) -> Vec<&str> {
+		examples
+			.iter()
+			.map(|example| example["messages"][0]["content"].as_str().unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn test_dataset_filters_the_exchanges() {
+		let exchanges = vec![
+			exchange("echo", 100, "early", &[]),
+			exchange("echo", 200, "liked", &[Rating::Up, Rating::Up, Rating::Down]),
+			exchange("echo", 300, "disliked", &[Rating::Down]),
+			exchange("other", 200, "elsewhere", &[Rating::Up]),
+		];
+		let all = DatasetFilter::default();
+		assert_eq!(dataset(exchanges.clone(), &all).len(), 4);
+
+		let echo = DatasetFilter { agent: Some("echo".to_string()), ..Default::default() };
+		assert_eq!(messages(&dataset(exchanges.clone(), &echo)), ["early", "liked", "disliked"]);
+		let window = DatasetFilter { since: Some(200), until: Some(300), ..Default::default() };
+		assert_eq!(messages(&dataset(exchanges.clone(), &window)), ["liked", "elsewhere"]);
+		let rated = DatasetFilter { min_rating: Some(1), ..Default::default() };
+		assert_eq!(messages(&dataset(exchanges.clone(), &rated)), ["liked", "elsewhere"]);
+		let unrated = DatasetFilter { min_rating: Some(0), ..Default::default() };
+		assert_eq!(messages(&dataset(exchanges, &unrated)), ["early", "liked", "elsewhere"]);
+	}
+
+	#[test]
+	fn test_dataset_keeps_a_conversation_once() {
+		let exchanges =
+			vec![exchange("echo", 100, "hi", &[]), exchange("echo", 200, "hi", &[Rating::Up])];
+		assert_eq!(dataset(exchanges, &DatasetFilter::default()).len(), 1);
+	}
+
+	#[test]
+	fn test_dataset_redacts_the_email_addresses_and_phone_numbers() {
+		let mut exchange = exchange("echo", 100, "write to jane@example.com please", &[]);
+		exchange.context = vec![ContextMessage {
+			role: MessageRole::User,
+			content: "I am bob@example.org, call +1 555 010 4477.".to_string(),
+		}];
+		exchange.response = "jane@example.com, done".to_string();
+
+		let example = dataset(vec![exchange], &DatasetFilter::default()).remove(0);
+		let text = example.to_string();
+		assert!(!text.contains("example.com") && !text.contains("example.org"));
+		assert_eq!(example["messages"][0]["content"], "I am [redacted], call [redacted].");
+		assert_eq!(example["messages"][1]["content"], "write to [redacted] please");
+	}
+
+	#[test]
+	fn test_fine_tuning_example_ends_with_the_answer() {
+		let mut exchange = exchange("echo", 100, "and now?", &[]);
+		exchange.context = vec![
+			ContextMessage { role: MessageRole::User, content: "hello".to_string() },
+			ContextMessage { role: MessageRole::Assistant, content: "hi".to_string() },
+		];
+
+		assert_eq!(
+			fine_tuning_example(exchange),
+			json!({
+				"messages": [
+					{ "role": "user", "content": "hello" },
+					{ "role": "assistant", "content": "hi" },
+					{ "role": "user", "content": "and now?" },
+					{ "role": "assistant", "content": "answer" },
+				]
+			})
+		);
+	}
+}
+
+// endregion: --- Tests
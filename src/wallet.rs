@@ -0,0 +1,77 @@
+use std::error::Error;
+
+use serde_json::json;
+use wallet::{rpc, FeePolicy, LocalSigner, Signer, TransactionRequest};
+
+use crate::{
+	cli::{WalletArgs, WalletCommand},
+	output::Output,
+};
+
+/// Environment variable holding the password of the keystore.
+pub static PASSWORD_ENV: &str = "DASN_WALLET_PASSWORD";
+
+/// Run a `dasn wallet` command, which needs no network.
+/// Amounts are given in wei as strings in JSON, past the integers JSON parsers keep exact.
+pub async fn run(command: WalletCommand, output: &Output) -> Result<(), Box<dyn Error>> {
+	match command {
+		WalletCommand::New { keystore } => {
+			let signer = LocalSigner::random();
+			signer.save(&keystore, &password()?)?;
+			let address = signer.address();
+			output.result(json!({ "address": address.to_string() }), || println!("{address}"));
+		},
+		WalletCommand::Address { wallet } => {
+			let address = signer(&wallet).await?.address();
+			output.result(json!({ "address": address.to_string() }), || println!("{address}"));
+		},
+		WalletCommand::Balance { wallet, rpc_url } => {
+			let address = signer(&wallet).await?.address();
+			let balance = rpc::balance(&rpc_url, address).await?;
+			output.result(
+				json!({ "address": address.to_string(), "balance_wei": balance.to_string() }),
+				|| println!("{} ETH", rpc::format_ether(balance)),
+			);
+		},
+		WalletCommand::Estimate { wallet, rpc_url, to, value, data, fees } => {
+			let transaction = TransactionRequest {
+				from: Some(signer(&wallet).await?.address()),
+				to,
+				value,
+				data: hex::decode(data.trim_start_matches("0x"))?,
+			};
+			let estimate =
+				rpc::estimate_fees(&rpc_url, &transaction, &FeePolicy::from(&fees)).await?;
+			let result = json!({
+				"gas_limit": estimate.gas_limit,
+				"base_fee_per_gas": estimate.base_fee_per_gas.to_string(),
+				"max_priority_fee_per_gas": estimate.max_priority_fee_per_gas.to_string(),
+				"max_fee_per_gas": estimate.max_fee_per_gas.to_string(),
+				"max_total_fee_wei": estimate.max_total_fee().to_string(),
+			});
+			output.result(result, || {
+				println!("Gas limit: {}", estimate.gas_limit);
+				println!("Base fee: {} wei", estimate.base_fee_per_gas);
+				println!("Max priority fee: {} wei", estimate.max_priority_fee_per_gas);
+				println!("Max fee: {} wei", estimate.max_fee_per_gas);
+				println!("Max total fee: {} ETH", rpc::format_ether(estimate.max_total_fee()));
+			});
+		},
+	}
+	Ok(())
+}
+
+async fn signer(wallet: &WalletArgs) -> Result<Box<dyn Signer>, Box<dyn Error>> {
+	#[cfg(feature = "ledger")]
+	if wallet.ledger {
+		return Ok(Box::new(wallet::ledger::LedgerSigner::connect(wallet.account).await?));
+	}
+
+	let keystore = wallet.keystore.as_deref().ok_or("Expected --keystore")?;
+	Ok(Box::new(LocalSigner::load(keystore, &password()?)?))
+}
+
+fn password() -> Result<String, Box<dyn Error>> {
+	std::env::var(PASSWORD_ENV)
+		.map_err(|_| format!("Expected the keystore password in {PASSWORD_ENV}").into())
+}
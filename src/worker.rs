@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+
+use ai_agent::backend::LlmBackend;
+use network::{stream::now_ms, Client, QueuedTask, TaskQueue, TaskStatus};
+use tokio::sync::Notify;
+
+/// Work on the tasks of the queue one at a time, oldest first, waking up on `queued` when it
+/// runs empty. Tasks left in the queue by a previous run are resumed first.
+pub async fn run(
+	queue: Arc<Mutex<TaskQueue>>,
+	queued: Arc<Notify>,
+	mut client: Client,
+	backend: Arc<dyn LlmBackend>,
+) {
+	loop {
+		let next = lock(&queue).next().cloned();
+		match next {
+			Some(task) => work(&queue, &mut client, backend.as_ref(), task).await,
+			None => queued.notified().await,
+		}
+	}
+}
+
+/// Compute the result of a task, unless it was before a restart, then commit to it and reveal it
+/// to the owner of the task.
+async fn work(
+	queue: &Mutex<TaskQueue>,
+	client: &mut Client,
+	backend: &dyn LlmBackend,
+	task: QueuedTask,
+) {
+	let task_id = task.proposal.task_id;
+	if task.proposal.deadline <= now_ms() / 1000 {
+		tracing::warn!("Dropping task {task_id}, its deadline passed");
+		finish(queue, &task_id);
+		return;
+	}
+	let result = match task.result {
+		Some(result) => {
+			tracing::info!("Resuming task {task_id}");
+			result
+		},
+		None => {
+			tracing::info!("Working on task {task_id}");
+			if let Err(e) = client.update_task_status(&task_id, TaskStatus::InProgress).await {
+				tracing::warn!("Failed to report task {task_id} in progress: {e}");
+			}
+			match backend.complete(&task.proposal.task_message).await {
+				Ok(answer) => {
+					let result = answer.into_bytes();
+					if let Err(e) = lock(queue).set_result(&task_id, result.clone()) {
+						tracing::error!("Failed to keep the result of task {task_id}: {e}");
+					}
+					result
+				},
+				Err(e) => {
+					tracing::error!("Task {task_id} failed: {e}");
+					let failed = TaskStatus::Failed { reason: e.to_string() };
+					if let Err(e) = client.update_task_status(&task_id, failed).await {
+						tracing::warn!("Failed to report task {task_id} failed: {e}");
+					}
+					finish(queue, &task_id);
+					return;
+				},
+			}
+		},
+	};
+
+	// A resumed task may have been committed to already, the owner refusing a second commitment.
+	if let Err(e) = client.commit_result(&task_id, &result).await {
+		tracing::warn!("Failed to commit to the result of task {task_id}: {e}");
+	}
+	match client.reveal_result(&task_id, result).await {
+		Ok(()) => tracing::info!("Task {task_id} completed"),
+		Err(e) => tracing::error!("Failed to reveal the result of task {task_id}: {e}"),
+	}
+	finish(queue, &task_id);
+}
+
+fn finish(queue: &Mutex<TaskQueue>, task_id: &str) {
+	if let Err(e) = lock(queue).finish(task_id) {
+		tracing::error!("Failed to dequeue task {task_id}: {e}");
+	}
+}
+
+fn lock(queue: &Mutex<TaskQueue>) -> std::sync::MutexGuard<'_, TaskQueue> {
+	queue.lock().expect("Task queue not to be poisoned.")
+}